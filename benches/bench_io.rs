@@ -17,8 +17,9 @@ use criterion::*;
 use glob::glob;
 use hifitime::Duration;
 use marlu::{
-    ms::MeasurementSetWriter, mwalib, ndarray::Array3, uvfits::UvfitsWriter, Complex, Jones,
-    MwaObsContext, ObsContext, VisContext, VisSelection, VisWrite,
+    io::GpuboxReader, ms::MeasurementSetWriter, mwalib, ndarray::Array3, uvfits::UvfitsWriter,
+    Complex, Jones, MwaObsContext, ObsContext, Telescope, VisContext, VisRead, VisSelection,
+    VisWrite,
 };
 use mwalib::CorrelatorContext;
 use tempfile::tempdir;
@@ -130,12 +131,10 @@ fn bench_uvfits_init_mwax_half_1247842824(crt: &mut Criterion) {
                 let u = UvfitsWriter::from_marlu(
                     uvfits_path,
                     &vis_ctx,
-                    obs_ctx.array_pos,
+                    &Telescope::new("MWA", obs_ctx.array_pos, vec![], vec![]),
                     obs_ctx.phase_centre,
                     Duration::from_total_nanoseconds(0),
                     obs_ctx.name.as_deref(),
-                    vec![],
-                    vec![],
                     None,
                 )
                 .unwrap();
@@ -266,12 +265,10 @@ fn bench_uvfits_write_mwax_part_1247842824(crt: &mut Criterion) {
                 let mut uvfits_writer = UvfitsWriter::from_marlu(
                     uvfits_path,
                     &vis_ctx,
-                    obs_ctx.array_pos,
+                    &Telescope::new("MWA", obs_ctx.array_pos, obs_ctx.ant_names.clone(), vec![]),
                     obs_ctx.phase_centre,
                     Duration::from_total_nanoseconds(0),
                     obs_ctx.name.as_deref(),
-                    obs_ctx.ant_names.clone(),
-                    vec![],
                     None,
                 )
                 .unwrap();
@@ -284,6 +281,88 @@ fn bench_uvfits_write_mwax_part_1247842824(crt: &mut Criterion) {
     );
 }
 
+fn bench_read_mwalib_mwax_part_1247842824(crt: &mut Criterion) {
+    let corr_ctx = get_context_mwax_half_1247842824();
+
+    let mut vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+    vis_sel.timestep_range = vis_sel.timestep_range.start
+        ..min(
+            vis_sel.timestep_range.start + TIMESTEP_LIMIT + 1,
+            vis_sel.timestep_range.end,
+        );
+
+    let fine_chans_per_coarse = corr_ctx.metafits_context.num_corr_fine_chans_per_coarse;
+    let mut jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+    let mut flag_array = vis_sel.allocate_flags(fine_chans_per_coarse).unwrap();
+
+    crt.bench_function(
+        &format!(
+            "VisSelection::read_mwalib - mwax_half_1247842824 {:?}",
+            jones_array.dim()
+        ),
+        |bch| {
+            bch.iter(|| {
+                vis_sel
+                    .read_mwalib(
+                        &corr_ctx,
+                        jones_array.view_mut(),
+                        flag_array.view_mut(),
+                        false,
+                    )
+                    .unwrap();
+            })
+        },
+    );
+}
+
+/// Read all of the visibilities of a [`VisRead`] source, a timestep at a time.
+fn read_all_timesteps<R: VisRead>(reader: &mut R) {
+    let vis_ctx = reader.vis_ctx().clone();
+    for chunk_ctx in vis_ctx.chunk_time(vis_ctx.num_sel_timesteps) {
+        let mut vis = Array3::zeros(chunk_ctx.sel_dims());
+        let mut weights = Array3::zeros(chunk_ctx.sel_dims());
+        reader
+            .read_vis(vis.view_mut(), weights.view_mut(), &chunk_ctx)
+            .unwrap();
+    }
+}
+
+// A new reader is made for each iteration, so nothing is cached by cfitsio or
+// the memory maps. For cold-cache reads, drop the page cache of the OS before
+// running this benchmark, e.g. `echo 3 | sudo tee /proc/sys/vm/drop_caches`.
+fn bench_gpubox_readers_mwax_part_1247842824(crt: &mut Criterion) {
+    let mut vis_sel = VisSelection::from_mwalib(&get_context_mwax_half_1247842824()).unwrap();
+    vis_sel.timestep_range = vis_sel.timestep_range.start
+        ..min(
+            vis_sel.timestep_range.start + TIMESTEP_LIMIT + 1,
+            vis_sel.timestep_range.end,
+        );
+
+    let mut group = crt.benchmark_group("gpubox readers - mwax_half_1247842824");
+    group.bench_function("GpuboxReader", |bch| {
+        bch.iter_batched(
+            || GpuboxReader::new(get_context_mwax_half_1247842824(), vis_sel.clone()),
+            |mut reader| read_all_timesteps(&mut reader),
+            BatchSize::PerIteration,
+        )
+    });
+    #[cfg(feature = "mmap")]
+    group.bench_function("MmapGpuboxReader", |bch| {
+        bch.iter_batched(
+            || {
+                marlu::io::MmapGpuboxReader::new(
+                    get_context_mwax_half_1247842824(),
+                    vis_sel.clone(),
+                )
+                .unwrap()
+            },
+            |mut reader| read_all_timesteps(&mut reader),
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
 criterion_group!(
     name = io;
     config = Criterion::default().sample_size(60);
@@ -292,6 +371,8 @@ criterion_group!(
         bench_uvfits_init_mwax_half_1247842824,
         bench_ms_write_mwax_part_1247842824,
         bench_uvfits_write_mwax_part_1247842824,
+        bench_read_mwalib_mwax_part_1247842824,
+        bench_gpubox_readers_mwax_part_1247842824,
 );
 
 criterion_main!(io);
@@ -6,8 +6,9 @@
 
 use criterion::*;
 use marlu::{
+    averaging::{average_vis, average_visibilities},
     c64,
-    ndarray::{Array1, Array3},
+    ndarray::{Array1, Array3, Array4},
     pos::xyz,
     HADec, Jones, XyzGeodetic,
 };
@@ -109,6 +110,31 @@ fn misc(c: &mut Criterion) {
                 .collect();
         })
     });
+
+    // Is the parallel average_vis faster than average_visibilities?
+    c.bench_function("average_visibilities", |b| {
+        let shape = (4, 128, 8256);
+        let jones_array = Array3::from_elem(shape, Jones::<f32>::identity());
+        let weight_array = Array4::from_elem((shape.0, shape.1, shape.2, 4), 1.0_f32);
+        let flag_array = Array4::from_elem((shape.0, shape.1, shape.2, 4), false);
+        b.iter(|| {
+            average_visibilities(
+                jones_array.view(),
+                weight_array.view(),
+                flag_array.view(),
+                2,
+                4,
+            )
+            .unwrap()
+        })
+    });
+
+    c.bench_function("average_vis", |b| {
+        let shape = (4, 128, 8256);
+        let jones_array = Array3::from_elem(shape, Jones::<f32>::identity());
+        let weight_array = Array3::from_elem(shape, 1.0_f32);
+        b.iter(|| average_vis(jones_array.view(), weight_array.view(), 2, 4).unwrap())
+    });
 }
 
 criterion_group!(benches, misc);
@@ -0,0 +1,21 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Benchmarks of synthetic workloads, using the `bench` module. Run these with
+//! `cargo bench --features bench --bench bench_synth`.
+
+use criterion::*;
+use marlu::bench::{self, Workload};
+
+fn synth(crt: &mut Criterion) {
+    bench::bench_workload(crt, &Workload::small());
+    bench::bench_workload(crt, &Workload::mwa_coarse_chan());
+}
+
+criterion_group! {
+    name = benches;
+    config = bench::criterion_config();
+    targets = synth
+}
+criterion_main!(benches);
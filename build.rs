@@ -42,5 +42,18 @@ fn main() {
 
         #[cfg(feature = "cuda-static")]
         println!("cargo:rustc-link-lib=static=cudart_static");
+
+        // Compile the CUDA kernels. The compute capability can be specified
+        // with MARLU_CUDA_COMPUTE, e.g. "75".
+        println!("cargo:rerun-if-changed=src/cuda/coords.cu");
+        println!("cargo:rerun-if-env-changed=MARLU_CUDA_COMPUTE");
+        let mut cuda_target = cc::Build::new();
+        cuda_target.cuda(true).cudart("shared");
+        if let Ok(compute) = std::env::var("MARLU_CUDA_COMPUTE") {
+            cuda_target.flag(&format!(
+                "-gencode=arch=compute_{compute},code=sm_{compute}"
+            ));
+        }
+        cuda_target.file("src/cuda/coords.cu").compile("marlu_cuda");
     }
 }
@@ -5,11 +5,13 @@
 //! Spectral and Temporal averaging
 
 use crate::Complex;
+use hifitime::Epoch;
 use itertools::izip;
-use ndarray::prelude::*;
+use ndarray::{prelude::*, Zip};
+use rayon::prelude::*;
 use thiserror::Error;
 
-use crate::Jones;
+use crate::{weights::is_flagged, AveragingPolicy, Jones, JonesArray, PolFrame, VisContext};
 
 #[derive(Error, Debug)]
 pub enum AveragingError {
@@ -37,7 +39,6 @@ pub enum AveragingError {
 /// - `avg_jones` -> [pol]
 /// - `avg_weight_view` -> [pol]
 /// - `avg_flag_view` -> [pol]
-#[macro_export]
 macro_rules! average_chunk_for_pols_f64 {
     (
         // to be averaged
@@ -77,7 +78,7 @@ macro_rules! average_chunk_for_pols_f64 {
                 ) {
                     let weight_f64: f64 = *weight_elem as _;
 
-                    if !flag_elem && *weight_elem >= 0. {
+                    if !flag_elem && !$crate::weights::is_flagged(*weight_elem) {
                         *weighted_vis_sum += jones_elem * weight_f64;
                         *weight_sum += weight_f64;
                         all_flagged = false;
@@ -123,7 +124,7 @@ macro_rules! average_chunk_for_pols_f64 {
 /// - `avg_jones` -> [pol]
 /// - `avg_weight` -> (scalar)
 /// - `avg_flag` -> (scalar)
-#[macro_export]
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
 macro_rules! average_chunk_f64 {
     (
         // to be averaged
@@ -155,7 +156,7 @@ macro_rules! average_chunk_f64 {
             for (jones, weight) in izip!(jones_chunk.iter(), weights_chunk.iter()) {
                 let jones_c64 = Jones::<f64>::from(*jones);
                 jones_sum += jones_c64;
-                if *weight >= 0. && weight.abs() > 0. {
+                if !$crate::weights::is_flagged(*weight) && weight.abs() > 0. {
                     let weight_abs_f64 = (*weight as f64).abs();
                     weight_sum_f64 += weight_abs_f64;
                     $avg_flag = false;
@@ -185,6 +186,8 @@ macro_rules! average_chunk_f64 {
         $avg_weight = weight_sum_f64 as f32;
     };
 }
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
+pub(crate) use average_chunk_f64;
 
 pub type VisData344 = (Array3<Jones<f32>>, Array4<f32>, Array4<bool>);
 pub type VisData33 = (Array3<Jones<f32>>, Array3<f32>);
@@ -322,13 +325,613 @@ pub fn average_visibilities(
     ))
 }
 
+/// Average visibilities in time and frequency (`avg_time`, `avg_freq`), in
+/// parallel over baselines. This is a standalone equivalent of the averaging
+/// done by the writers.
+///
+/// `jones_array` - visibilities with dimensions `[timestep][channel][baseline]`
+///
+/// `weight_array` - weights with dimensions `[timestep][channel][baseline]`.
+///     Negative weights are flagged.
+///
+/// Returns the averaged visibilities and weights, with dimensions
+/// `[timestep / avg_time][channel / avg_freq][baseline]` (rounded up).
+///
+/// # Gorey details
+///
+/// As with [`average_visibilities`], for each `avg_time` * `avg_freq` bin:
+/// - the weight is the sum of the positive weights
+/// - the visibility is the weighted mean of the visibilities with positive
+///   weights
+/// - if no weights are positive, the visibility is the unweighted mean of the
+///   bin, and it is flagged: its weight is the negative of the sum of the
+///   absolute weights.
+///
+/// Sums are accumulated in `f64` over the 8 floats of each [`Jones`] at once,
+/// which the compiler can vectorise.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if the shapes of the arrays
+/// don't match, or an averaging factor is zero.
+pub fn average_vis(
+    jones_array: ArrayView3<Jones<f32>>,
+    weight_array: ArrayView3<f32>,
+    avg_time: usize,
+    avg_freq: usize,
+) -> Result<VisData33, AveragingError> {
+    average_vis_with_policy(
+        jones_array,
+        weight_array,
+        avg_time,
+        avg_freq,
+        AveragingPolicy::UnweightedMean,
+    )
+}
+
+/// Average visibilities like [`average_vis`], but with `policy` deciding the
+/// visibilities of bins in which every visibility is flagged.
+///
+/// # Errors
+///
+/// See [`average_vis`].
+pub fn average_vis_with_policy(
+    jones_array: ArrayView3<Jones<f32>>,
+    weight_array: ArrayView3<f32>,
+    avg_time: usize,
+    avg_freq: usize,
+    policy: AveragingPolicy,
+) -> Result<VisData33, AveragingError> {
+    let jones_dims = jones_array.dim();
+    if weight_array.dim() != jones_dims {
+        return Err(AveragingError::BadArrayShape {
+            argument: "weight_array".to_string(),
+            function: "average_vis_with_policy".to_string(),
+            expected: format!("{:?}", jones_dims),
+            received: format!("{:?}", weight_array.dim()),
+        });
+    }
+    if avg_time == 0 || avg_freq == 0 {
+        return Err(AveragingError::BadArrayShape {
+            argument: if avg_time == 0 {
+                "avg_time"
+            } else {
+                "avg_freq"
+            }
+            .to_string(),
+            function: "average_vis_with_policy".to_string(),
+            expected: "a positive averaging factor".to_string(),
+            received: "0".to_string(),
+        });
+    }
+    let averaged_dims = (
+        (jones_dims.0 + avg_time - 1) / avg_time,
+        (jones_dims.1 + avg_freq - 1) / avg_freq,
+        jones_dims.2,
+    );
+    let mut averaged_jones_array = Array3::<Jones<f32>>::zeros(averaged_dims);
+    let mut averaged_weight_array = Array3::<f32>::zeros(averaged_dims);
+
+    averaged_jones_array
+        .axis_iter_mut(Axis(2))
+        .into_par_iter()
+        .zip(averaged_weight_array.axis_iter_mut(Axis(2)))
+        .zip(jones_array.axis_iter(Axis(2)))
+        .zip(weight_array.axis_iter(Axis(2)))
+        .for_each(
+            |(((mut avg_jones_bl, mut avg_weight_bl), jones_bl), weight_bl)| {
+                // arrays: [timestep][channel]
+                for (mut avg_jones_ts, mut avg_weight_ts, jones_ts, weight_ts) in izip!(
+                    avg_jones_bl.outer_iter_mut(),
+                    avg_weight_bl.outer_iter_mut(),
+                    jones_bl.axis_chunks_iter(Axis(0), avg_time),
+                    weight_bl.axis_chunks_iter(Axis(0), avg_time),
+                ) {
+                    for (avg_jones, avg_weight, jones_bin, weight_bin) in izip!(
+                        avg_jones_ts.iter_mut(),
+                        avg_weight_ts.iter_mut(),
+                        jones_ts.axis_chunks_iter(Axis(1), avg_freq),
+                        weight_ts.axis_chunks_iter(Axis(1), avg_freq),
+                    ) {
+                        (*avg_jones, *avg_weight) = average_bin(jones_bin, weight_bin, policy);
+                    }
+                }
+            },
+        );
+
+    Ok((averaged_jones_array, averaged_weight_array))
+}
+
+/// Average a single bin of visibilities for [`average_vis`].
+#[inline]
+fn average_bin(
+    jones_bin: ArrayView2<Jones<f32>>,
+    weight_bin: ArrayView2<f32>,
+    policy: AveragingPolicy,
+) -> (Jones<f32>, f32) {
+    let mut jones_sum = [0_f64; 8];
+    let mut jones_weighted_sum = [0_f64; 8];
+    let mut weight_sum = 0_f64;
+    let mut abs_weight_sum = 0_f64;
+    for (jones, &weight) in jones_bin.iter().zip(weight_bin.iter()) {
+        let floats = jones.to_float_array();
+        let weight = weight as f64;
+        let unflagged_weight = if weight > 0. { weight } else { 0. };
+        for (sum, weighted_sum, &float) in izip!(
+            jones_sum.iter_mut(),
+            jones_weighted_sum.iter_mut(),
+            floats.iter()
+        ) {
+            *sum += float as f64;
+            *weighted_sum += float as f64 * unflagged_weight;
+        }
+        weight_sum += unflagged_weight;
+        abs_weight_sum += weight.abs();
+    }
+
+    let (sums, divisor, avg_weight) = if weight_sum > 0. {
+        (jones_weighted_sum, weight_sum, weight_sum)
+    } else if policy == AveragingPolicy::Nan {
+        return (Jones::nan(), -abs_weight_sum as f32);
+    } else {
+        (jones_sum, jones_bin.len() as f64, -abs_weight_sum)
+    };
+    let mut avg = [0_f32; 8];
+    for (avg, &sum) in avg.iter_mut().zip(sums.iter()) {
+        *avg = (sum / divisor) as f32;
+    }
+    (Jones::from(avg), avg_weight as f32)
+}
+
+/// Average visibilities like [`average_vis_with_policy`] into pseudo-Stokes I,
+/// (XX + YY) / 2, e.g. for quick-look imaging where the other polarisations
+/// aren't needed.
+///
+/// The weights of XX and YY are the same, and their noise is independent, so
+/// the weight of each Stokes I visibility is twice the averaged weight (see
+/// [`PolFrame::weight_factor`]). Flags are kept in the signs of the weights.
+///
+/// Returns the averaged Stokes I visibilities and weights, with dimensions
+/// `[timestep / avg_time][channel / avg_freq][baseline]` (rounded up).
+///
+/// # Errors
+///
+/// See [`average_vis`].
+pub fn average_stokes_i(
+    jones_array: ArrayView3<Jones<f32>>,
+    weight_array: ArrayView3<f32>,
+    avg_time: usize,
+    avg_freq: usize,
+    policy: AveragingPolicy,
+) -> Result<(Array3<Complex<f32>>, Array3<f32>), AveragingError> {
+    let (averaged_jones_array, mut averaged_weight_array) =
+        average_vis_with_policy(jones_array, weight_array, avg_time, avg_freq, policy)?;
+    let stokes_i_array = averaged_jones_array.mapv(|jones| PolFrame::StokesI.convert(jones)[0]);
+    let weight_factor = PolFrame::StokesI.weight_factor();
+    averaged_weight_array.mapv_inplace(|weight| weight * weight_factor);
+    Ok((stokes_i_array, averaged_weight_array))
+}
+
+/// Average visibilities stored in a [`JonesArray`] in time and frequency
+/// (`avg_time`, `avg_freq`), in parallel over averaged timesteps. This gives
+/// the same results as [`average_vis_with_policy`], but the sums for all
+/// baselines are accumulated at once over each contiguous plane of the
+/// [`JonesArray`], which the compiler can vectorise.
+///
+/// `weight_array` - weights with dimensions `[timestep][channel][baseline]`.
+///     Negative weights are flagged.
+///
+/// Returns the averaged visibilities and weights, with dimensions
+/// `[timestep / avg_time][channel / avg_freq][baseline]` (rounded up).
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if the shapes of the arrays
+/// don't match, or an averaging factor is zero.
+pub fn average_jones_array(
+    jones_array: &JonesArray,
+    weight_array: ArrayView3<f32>,
+    avg_time: usize,
+    avg_freq: usize,
+    policy: AveragingPolicy,
+) -> Result<(JonesArray, Array3<f32>), AveragingError> {
+    let jones_dims = jones_array.dim();
+    if weight_array.dim() != jones_dims {
+        return Err(AveragingError::BadArrayShape {
+            argument: "weight_array".to_string(),
+            function: "average_jones_array".to_string(),
+            expected: format!("{:?}", jones_dims),
+            received: format!("{:?}", weight_array.dim()),
+        });
+    }
+    if avg_time == 0 || avg_freq == 0 {
+        return Err(AveragingError::BadArrayShape {
+            argument: if avg_time == 0 {
+                "avg_time"
+            } else {
+                "avg_freq"
+            }
+            .to_string(),
+            function: "average_jones_array".to_string(),
+            expected: "a positive averaging factor".to_string(),
+            received: "0".to_string(),
+        });
+    }
+    let (num_timesteps, num_chans, num_baselines) = jones_dims;
+    let averaged_dims = (
+        (num_timesteps + avg_time - 1) / avg_time,
+        (num_chans + avg_freq - 1) / avg_freq,
+        num_baselines,
+    );
+    let mut averaged_jones_array = JonesArray::zeros(averaged_dims);
+    let mut averaged_weight_array = Array3::<f32>::zeros(averaged_dims);
+    let (re, im) = (jones_array.re(), jones_array.im());
+    let (mut avg_re, mut avg_im) = averaged_jones_array.planes_mut();
+
+    avg_re
+        .axis_iter_mut(Axis(1))
+        .into_par_iter()
+        .zip(avg_im.axis_iter_mut(Axis(1)))
+        .zip(averaged_weight_array.outer_iter_mut())
+        .enumerate()
+        .for_each(
+            |(i_avg_ts, ((mut avg_re_ts, mut avg_im_ts), mut avg_weight_ts))| {
+                // arrays: [pol][channel][baseline] and [channel][baseline]
+                let timesteps = i_avg_ts * avg_time..((i_avg_ts + 1) * avg_time).min(num_timesteps);
+                // sums: [re/im][pol][baseline]
+                let mut sums = Array3::<f64>::zeros((2, 4, num_baselines));
+                let mut weighted_sums = Array3::<f64>::zeros((2, 4, num_baselines));
+                let mut weight_sums = Array1::<f64>::zeros(num_baselines);
+                let mut abs_weight_sums = Array1::<f64>::zeros(num_baselines);
+
+                for i_avg_chan in 0..averaged_dims.1 {
+                    let chans = i_avg_chan * avg_freq..((i_avg_chan + 1) * avg_freq).min(num_chans);
+                    let bin_size = (timesteps.len() * chans.len()) as f64;
+                    sums.fill(0.0);
+                    weighted_sums.fill(0.0);
+                    weight_sums.fill(0.0);
+                    abs_weight_sums.fill(0.0);
+
+                    for timestep in timesteps.clone() {
+                        for chan in chans.clone() {
+                            let weights = weight_array.slice(s![timestep, chan, ..]);
+                            let unflagged_weights =
+                                weights.mapv(|w| if w > 0. { w as f64 } else { 0. });
+                            weight_sums += &unflagged_weights;
+                            abs_weight_sums
+                                .zip_mut_with(&weights, |sum, &w| *sum += w.abs() as f64);
+
+                            for (part, mut sums, mut weighted_sums) in izip!(
+                                [re, im],
+                                sums.outer_iter_mut(),
+                                weighted_sums.outer_iter_mut()
+                            ) {
+                                for (vis, mut sum, mut weighted_sum) in izip!(
+                                    part.slice(s![.., timestep, chan, ..]).outer_iter(),
+                                    sums.outer_iter_mut(),
+                                    weighted_sums.outer_iter_mut()
+                                ) {
+                                    Zip::from(&mut sum)
+                                        .and(&mut weighted_sum)
+                                        .and(&vis)
+                                        .and(&unflagged_weights)
+                                        .for_each(|sum, weighted_sum, &vis, &weight| {
+                                            *sum += vis as f64;
+                                            *weighted_sum += vis as f64 * weight;
+                                        });
+                                }
+                            }
+                        }
+                    }
+
+                    for (i_bl, (&weight_sum, &abs_weight_sum)) in
+                        weight_sums.iter().zip(abs_weight_sums.iter()).enumerate()
+                    {
+                        let (sums, divisor, avg_weight) = if weight_sum > 0. {
+                            (&weighted_sums, weight_sum, weight_sum)
+                        } else if policy == AveragingPolicy::Nan {
+                            (&sums, f64::NAN, -abs_weight_sum)
+                        } else {
+                            (&sums, bin_size, -abs_weight_sum)
+                        };
+                        for pol in 0..4 {
+                            avg_re_ts[(pol, i_avg_chan, i_bl)] =
+                                (sums[(0, pol, i_bl)] / divisor) as f32;
+                            avg_im_ts[(pol, i_avg_chan, i_bl)] =
+                                (sums[(1, pol, i_bl)] / divisor) as f32;
+                        }
+                        avg_weight_ts[(i_avg_chan, i_bl)] = avg_weight as f32;
+                    }
+                }
+            },
+        );
+
+    Ok((averaged_jones_array, averaged_weight_array))
+}
+
+/// Compute the weighted centroid time and frequency of each averaged bin, i.e.
+/// the weighted mean of the centres of the timesteps and channels of the
+/// visibilities with positive weights. When a bin is partially flagged, its
+/// centroid differs from the centre of the bin given by
+/// [`VisContext::timeseries`] and [`VisContext::avg_frequencies_hz`].
+///
+/// `weight_array` - weights with dimensions `[timestep][channel][baseline]`,
+///     described by `vis_ctx`. Negative weights are flagged.
+///
+/// Returns the centroid times and frequencies \[Hz\], with dimensions
+/// `[averaged timestep][averaged channel][baseline]`. The centroids of bins in
+/// which every visibility is flagged are the centres of the bins, except that
+/// the frequencies are NaN if the [`VisContext::averaging_policy`] is
+/// [`AveragingPolicy::Nan`].
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if the shape of
+/// `weight_array` doesn't match `vis_ctx`.
+pub fn weighted_centroids(
+    weight_array: ArrayView3<f32>,
+    vis_ctx: &VisContext,
+) -> Result<(Array3<Epoch>, Array3<f64>), AveragingError> {
+    let sel_dims = vis_ctx.sel_dims();
+    if weight_array.dim() != sel_dims {
+        return Err(AveragingError::BadArrayShape {
+            argument: "weight_array".to_string(),
+            function: "weighted_centroids".to_string(),
+            expected: format!("{:?}", sel_dims),
+            received: format!("{:?}", weight_array.dim()),
+        });
+    }
+    let avg_time = vis_ctx.avg_time.max(1);
+    let avg_freq = vis_ctx.avg_freq.max(1);
+    let avg_dims = (
+        vis_ctx.num_avg_timesteps(),
+        vis_ctx.num_avg_chans(),
+        sel_dims.2,
+    );
+    let freqs_hz = vis_ctx.frequencies_hz();
+    let mut centroid_times = Array3::from_elem(avg_dims, vis_ctx.start_timestamp);
+    let mut centroid_freqs_hz = Array3::<f64>::zeros(avg_dims);
+
+    for (avg_timestep_idx, weight_ts) in
+        weight_array.axis_chunks_iter(Axis(0), avg_time).enumerate()
+    {
+        for (avg_chan_idx, weight_bin) in weight_ts.axis_chunks_iter(Axis(1), avg_freq).enumerate()
+        {
+            let first_timestep = avg_timestep_idx * avg_time;
+            let first_chan = avg_chan_idx * avg_freq;
+            let bin_freqs_hz = &freqs_hz[first_chan..first_chan + weight_bin.len_of(Axis(1))];
+            for (baseline_idx, weight_bin) in weight_bin.axis_iter(Axis(2)).enumerate() {
+                // The timesteps are in units of the integration time, relative
+                // to the start timestamp.
+                let mut timestep_sum = 0_f64;
+                let mut freq_sum = 0_f64;
+                let mut weight_sum = 0_f64;
+                let mut count = 0_f64;
+                let mut unweighted_timestep_sum = 0_f64;
+                let mut unweighted_freq_sum = 0_f64;
+                for ((timestep_offset, chan_offset), &weight) in weight_bin.indexed_iter() {
+                    let timestep = (first_timestep + timestep_offset) as f64 + 0.5;
+                    let freq_hz = bin_freqs_hz[chan_offset];
+                    unweighted_timestep_sum += timestep;
+                    unweighted_freq_sum += freq_hz;
+                    count += 1.;
+                    if weight > 0. {
+                        let weight = weight as f64;
+                        timestep_sum += timestep * weight;
+                        freq_sum += freq_hz * weight;
+                        weight_sum += weight;
+                    }
+                }
+
+                let (timestep, freq_hz) = if weight_sum > 0. {
+                    (timestep_sum / weight_sum, freq_sum / weight_sum)
+                } else if vis_ctx.averaging_policy == AveragingPolicy::Nan {
+                    (unweighted_timestep_sum / count, f64::NAN)
+                } else {
+                    (unweighted_timestep_sum / count, unweighted_freq_sum / count)
+                };
+                let idx = (avg_timestep_idx, avg_chan_idx, baseline_idx);
+                centroid_times[idx] = vis_ctx.start_timestamp + timestep * vis_ctx.int_time;
+                centroid_freqs_hz[idx] = freq_hz;
+            }
+        }
+    }
+
+    Ok((centroid_times, centroid_freqs_hz))
+}
+
+/// Compute the power spectrum of each antenna from its auto-correlations: the
+/// weighted mean over time of the unflagged XX and YY auto-correlations.
+///
+/// `jones_array` - visibilities with dimensions `[timestep][channel][baseline]`
+///
+/// `weight_array` - weights with dimensions `[timestep][channel][baseline]`.
+///     Negative weights are flagged.
+///
+/// `ant_pairs` - the antenna indices of each baseline. Cross-correlations are
+///     ignored.
+///
+/// Returns the antenna index of each auto-correlation, in the order they
+/// appear in `ant_pairs`, and their spectra with dimensions
+/// `[antenna][channel][pol]`, where the pols are XX and YY. Channels without
+/// any unflagged visibilities are NaN.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if the shapes of the arrays
+/// don't match.
+pub fn auto_power_spectra(
+    jones_array: ArrayView3<Jones<f32>>,
+    weight_array: ArrayView3<f32>,
+    ant_pairs: &[(usize, usize)],
+) -> Result<(Vec<usize>, Array3<f64>), AveragingError> {
+    let jones_dims = jones_array.dim();
+    if weight_array.dim() != jones_dims {
+        return Err(AveragingError::BadArrayShape {
+            argument: "weight_array".to_string(),
+            function: "auto_power_spectra".to_string(),
+            expected: format!("{:?}", jones_dims),
+            received: format!("{:?}", weight_array.dim()),
+        });
+    }
+    if ant_pairs.len() != jones_dims.2 {
+        return Err(AveragingError::BadArrayShape {
+            argument: "ant_pairs".to_string(),
+            function: "auto_power_spectra".to_string(),
+            expected: format!("({},)", jones_dims.2),
+            received: format!("({},)", ant_pairs.len()),
+        });
+    }
+
+    let (auto_idxs, ants): (Vec<usize>, Vec<usize>) = ant_pairs
+        .iter()
+        .enumerate()
+        .filter(|(_, (ant1, ant2))| ant1 == ant2)
+        .map(|(idx, &(ant, _))| (idx, ant))
+        .unzip();
+    let mut spectra = Array3::<f64>::from_elem((ants.len(), jones_dims.1, 2), f64::NAN);
+    for (&auto_idx, mut ant_spectra) in auto_idxs.iter().zip(spectra.outer_iter_mut()) {
+        for (jones_chan, weight_chan, mut chan_spectra) in izip!(
+            jones_array.index_axis(Axis(2), auto_idx).axis_iter(Axis(1)),
+            weight_array
+                .index_axis(Axis(2), auto_idx)
+                .axis_iter(Axis(1)),
+            ant_spectra.outer_iter_mut(),
+        ) {
+            let mut power_sums = [0.0_f64; 2];
+            let mut weight_sum = 0.0_f64;
+            for (jones, &weight) in jones_chan.iter().zip(weight_chan) {
+                if is_flagged(weight) {
+                    continue;
+                }
+                power_sums[0] += jones[0].re as f64 * weight as f64;
+                power_sums[1] += jones[3].re as f64 * weight as f64;
+                weight_sum += weight as f64;
+            }
+            if weight_sum > 0.0 {
+                chan_spectra[0] = power_sums[0] / weight_sum;
+                chan_spectra[1] = power_sums[1] / weight_sum;
+            }
+        }
+    }
+
+    Ok((ants, spectra))
+}
+
+/// The kernel of CASA's `hanningsmooth`, for use with [`smooth_spectra`].
+pub const HANNING_KERNEL: [f32; 3] = [0.25, 0.5, 0.25];
+
+/// Smooth the spectrum of each timestep and baseline in place by convolving it
+/// with `kernel`, e.g. [`HANNING_KERNEL`]. Smoothing before averaging in
+/// frequency suppresses the ringing of sharp features, e.g. RFI.
+///
+/// `jones_array` - visibilities with dimensions `[timestep][channel][baseline]`
+///
+/// `weight_array` - weights with dimensions `[timestep][channel][baseline]`.
+///     Negative weights are flagged.
+///
+/// `kernel` - the convolution kernel, which must have an odd length. It is
+///     centred on each channel.
+///
+/// # Gorey details
+///
+/// Flagged and zero-weighted channels don't contribute to their neighbours,
+/// and nor do channels beyond the edges of the band; the kernel is
+/// renormalised to the channels that do contribute. Flagged channels are left
+/// unchanged.
+///
+/// Weights are inverse variances, and assuming that the noise of each channel
+/// is independent, the weight of a smoothed channel is
+/// `(Σ k_i)² / Σ (k_i² / w_i)`. Note that the noise of neighbouring smoothed
+/// channels is correlated.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if the shapes of the arrays
+/// don't match, or the length of the kernel is not odd.
+pub fn smooth_spectra(
+    mut jones_array: ArrayViewMut3<Jones<f32>>,
+    mut weight_array: ArrayViewMut3<f32>,
+    kernel: &[f32],
+) -> Result<(), AveragingError> {
+    let jones_dims = jones_array.dim();
+    if weight_array.dim() != jones_dims {
+        return Err(AveragingError::BadArrayShape {
+            argument: "weight_array".to_string(),
+            function: "smooth_spectra".to_string(),
+            expected: format!("{:?}", jones_dims),
+            received: format!("{:?}", weight_array.dim()),
+        });
+    }
+    if kernel.len() % 2 == 0 {
+        return Err(AveragingError::BadArrayShape {
+            argument: "kernel".to_string(),
+            function: "smooth_spectra".to_string(),
+            expected: "(2n + 1,)".to_string(),
+            received: format!("({},)", kernel.len()),
+        });
+    }
+    let half_width = kernel.len() / 2;
+    let num_chans = jones_dims.1;
+
+    let mut spectrum = Vec::with_capacity(num_chans);
+    let mut weights = Vec::with_capacity(num_chans);
+    for (mut jones_lane, mut weight_lane) in izip!(
+        jones_array.lanes_mut(Axis(1)),
+        weight_array.lanes_mut(Axis(1)),
+    ) {
+        spectrum.clear();
+        spectrum.extend(jones_lane.iter().map(|&jones| Jones::<f64>::from(jones)));
+        weights.clear();
+        weights.extend(weight_lane.iter().map(|&weight| weight as f64));
+
+        for (chan_idx, (jones, weight)) in
+            izip!(jones_lane.iter_mut(), weight_lane.iter_mut()).enumerate()
+        {
+            if is_flagged(*weight) {
+                continue;
+            }
+            let mut jones_sum = Jones::<f64>::default();
+            let mut kernel_sum = 0.0_f64;
+            let mut variance_sum = 0.0_f64;
+            for (k_idx, &k) in kernel.iter().enumerate() {
+                let neighbour_idx = match (chan_idx + k_idx).checked_sub(half_width) {
+                    Some(idx) if idx < num_chans => idx,
+                    _ => continue,
+                };
+                let neighbour_weight = weights[neighbour_idx];
+                if neighbour_weight <= 0.0 {
+                    continue;
+                }
+                let k = k as f64;
+                jones_sum += spectrum[neighbour_idx] * k;
+                kernel_sum += k;
+                variance_sum += k * k / neighbour_weight;
+            }
+            if kernel_sum.abs() > 0.0 && variance_sum > 0.0 {
+                *jones = Jones::from(jones_sum / kernel_sum);
+                *weight = (kernel_sum * kernel_sum / variance_sum) as f32;
+            }
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tess {
     use crate::Complex;
     use approx::assert_abs_diff_eq;
-    use ndarray::{Array3, Array4};
+    use ndarray::{array, s, Array3, Array4, Axis};
 
-    use super::{average_visibilities, Jones};
+    use hifitime::{Duration, Epoch, Unit};
+
+    use super::{
+        auto_power_spectra, average_jones_array, average_stokes_i, average_vis,
+        average_vis_with_policy, average_visibilities, smooth_spectra, weighted_centroids,
+        AveragingPolicy, Jones, JonesArray, VisContext, HANNING_KERNEL,
+    };
+    use crate::{weights::is_flagged, VisUnit};
 
     fn synthesize_test_data(
         shape: (usize, usize, usize, usize),
@@ -431,5 +1034,306 @@ mod tess {
         assert_abs_diff_eq!(averaged_weight_array[(2, 3, 2, 3)], expected_weight_2_3_2_3);
     }
 
+    #[test]
+    fn test_average_vis_matches_average_visibilities() {
+        let shape = (5, 7, 3, 4);
+        let (vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+        // The weights and flags of all pols of a visibility are the same.
+        let mut flags = flag_array.index_axis(Axis(3), 0).to_owned();
+        // Flag the whole first bin of the first baseline.
+        flags.slice_mut(s![0..2, 0..2, 0]).fill(true);
+        let weights = Array3::from_shape_fn(flags.dim(), |(t, c, b)| {
+            let weight = weight_array[(t, c, b, 0)];
+            if flags[(t, c, b)] {
+                -weight
+            } else {
+                weight
+            }
+        });
+
+        let (averaged_vis_array, averaged_weight_array) =
+            average_vis(vis_array.view(), weights.view(), 2, 2).unwrap();
+        assert_eq!(averaged_vis_array.dim(), (3, 4, 3));
+        assert_eq!(averaged_weight_array.dim(), (3, 4, 3));
+
+        let broadcast_weights = Array4::from_shape_fn(shape, |(t, c, b, _)| weights[(t, c, b)]);
+        let broadcast_flags = Array4::from_shape_fn(shape, |(t, c, b, _)| flags[(t, c, b)]);
+        let (expected_vis_array, expected_weight_array, expected_flag_array) =
+            average_visibilities(
+                vis_array.view(),
+                broadcast_weights.view(),
+                broadcast_flags.view(),
+                2,
+                2,
+            )
+            .unwrap();
+
+        assert_abs_diff_eq!(averaged_vis_array, expected_vis_array, epsilon = 1e-5);
+        for ((idx, &weight), &flag) in averaged_weight_array
+            .indexed_iter()
+            .zip(expected_flag_array.index_axis(Axis(3), 0).iter())
+        {
+            if flag {
+                assert!(is_flagged(weight), "{:?}", idx);
+            } else {
+                let (t, c, b) = idx;
+                assert_abs_diff_eq!(weight, expected_weight_array[(t, c, b, 0)]);
+            }
+        }
+        assert!(is_flagged(averaged_weight_array[(0, 0, 0)]));
+
+        // The flagged bin is the unweighted mean.
+        let expected = vis_array
+            .slice(s![0..2, 0..2, 0])
+            .iter()
+            .fold(Jones::<f64>::default(), |sum, &jones| {
+                sum + Jones::<f64>::from(jones)
+            })
+            / 4.0;
+        assert_abs_diff_eq!(averaged_vis_array[(0, 0, 0)], Jones::<f32>::from(expected));
+
+        assert!(average_vis(vis_array.view(), weights.view(), 0, 1).is_err());
+        assert!(average_vis(vis_array.view(), weights.slice(s![.., 1.., ..]), 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_average_jones_array_matches_average_vis() {
+        let shape = (5, 7, 3, 4);
+        let (vis_array, weight_array, _) = synthesize_test_data(shape);
+        let mut weights = weight_array.index_axis(Axis(3), 0).to_owned();
+        // Flag the whole first bin of the first baseline, and some others.
+        weights.slice_mut(s![0..2, 0..2, 0]).mapv_inplace(|w| -w);
+        weights.slice_mut(s![4, .., 2]).mapv_inplace(|w| -w);
+        weights[(1, 3, 1)] *= -1.0;
+
+        let jones_array = JonesArray::from(vis_array.view());
+        for policy in [AveragingPolicy::UnweightedMean, AveragingPolicy::Nan] {
+            let (expected_vis_array, expected_weight_array) =
+                average_vis_with_policy(vis_array.view(), weights.view(), 2, 3, policy).unwrap();
+            let (averaged_jones_array, averaged_weight_array) =
+                average_jones_array(&jones_array, weights.view(), 2, 3, policy).unwrap();
+            assert_eq!(averaged_jones_array.dim(), (3, 3, 3));
+
+            let averaged_vis_array = averaged_jones_array.to_jones();
+            for (avg, expected) in averaged_vis_array.iter().zip(expected_vis_array.iter()) {
+                if expected.any_nan() {
+                    assert!(avg.iter().all(|c| c.is_nan()));
+                } else {
+                    assert_abs_diff_eq!(avg, expected, epsilon = 1e-5);
+                }
+            }
+            assert_abs_diff_eq!(averaged_weight_array, expected_weight_array);
+        }
+
+        assert!(
+            average_jones_array(&jones_array, weights.view(), 0, 1, AveragingPolicy::Nan).is_err()
+        );
+        assert!(average_jones_array(
+            &jones_array,
+            weights.slice(s![.., 1.., ..]),
+            1,
+            1,
+            AveragingPolicy::Nan,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_average_stokes_i() {
+        // XX = 3 + 2i and YY = 1 in the first timestep, and XX = 5 and YY = 3
+        // in the second, i.e. Stokes I = 2 + i and 4.
+        let mut jones_array = Array3::from_elem((2, 2, 1), Jones::identity());
+        jones_array.slice_mut(s![0, .., ..]).fill(Jones::from([
+            Complex::new(3.0, 2.0),
+            Complex::new(7.0, 0.0),
+            Complex::new(7.0, 0.0),
+            Complex::new(1.0, 0.0),
+        ]));
+        jones_array.slice_mut(s![1, .., ..]).fill(Jones::from([
+            Complex::new(5.0, 0.0),
+            Complex::new(7.0, 0.0),
+            Complex::new(7.0, 0.0),
+            Complex::new(3.0, 0.0),
+        ]));
+        let mut weight_array = Array3::from_elem((2, 2, 1), 1.0);
+        weight_array[(1, 0, 0)] = 3.0;
+        weight_array[(0, 1, 0)] = -1.0;
+        weight_array[(1, 1, 0)] = -1.0;
+
+        let (stokes_i_array, avg_weight_array) = average_stokes_i(
+            jones_array.view(),
+            weight_array.view(),
+            2,
+            1,
+            AveragingPolicy::UnweightedMean,
+        )
+        .unwrap();
+        assert_eq!(stokes_i_array.dim(), (1, 2, 1));
+        assert_abs_diff_eq!(stokes_i_array[(0, 0, 0)], Complex::new(3.5, 0.25));
+        // The second channel is flagged, and is the unweighted mean.
+        assert_abs_diff_eq!(stokes_i_array[(0, 1, 0)], Complex::new(3.0, 0.5));
+        assert_abs_diff_eq!(avg_weight_array, array![[[8.0], [-4.0]]]);
+    }
+
+    #[test]
+    fn test_weighted_centroids() {
+        let mut vis_ctx = VisContext {
+            num_sel_timesteps: 3,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_f64(2., Unit::Second),
+            num_sel_chans: 4,
+            start_freq_hz: 128_000_000.,
+            freq_resolution_hz: 10_000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 2,
+            avg_freq: 2,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::UnweightedMean,
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+        let mut weights = Array3::from_elem(vis_ctx.sel_dims(), 1.0_f32);
+        // Flag the second timestep and the first channel of the first bin.
+        weights[(1, 0, 0)] = -1.0;
+        weights[(1, 1, 0)] = -1.0;
+        weights[(0, 0, 0)] = -1.0;
+        // Flag the whole second bin of the first averaged timestep.
+        weights.slice_mut(s![0..2, 2..4, 0]).fill(-1.0);
+
+        let (times, freqs_hz) = weighted_centroids(weights.view(), &vis_ctx).unwrap();
+        assert_eq!(times.dim(), (2, 2, 1));
+        assert_eq!(freqs_hz.dim(), (2, 2, 1));
+
+        // Only the first timestep and second channel of the first bin are unflagged.
+        assert_abs_diff_eq!(
+            times[(0, 0, 0)].as_gpst_seconds(),
+            1090008641.,
+            epsilon = 1e-6
+        );
+        assert_abs_diff_eq!(freqs_hz[(0, 0, 0)], 128_010_000.);
+        // The whole second bin is flagged, so it has the centre of the bin.
+        assert_abs_diff_eq!(
+            times[(0, 1, 0)].as_gpst_seconds(),
+            1090008642.,
+            epsilon = 1e-6
+        );
+        assert_abs_diff_eq!(freqs_hz[(0, 1, 0)], 128_025_000.);
+        // The last averaged timestep only has one timestep.
+        assert_abs_diff_eq!(
+            times[(1, 0, 0)].as_gpst_seconds(),
+            1090008645.,
+            epsilon = 1e-6
+        );
+        assert_abs_diff_eq!(freqs_hz[(1, 1, 0)], 128_025_000.);
+
+        vis_ctx.averaging_policy = AveragingPolicy::Nan;
+        let (_, freqs_hz) = weighted_centroids(weights.view(), &vis_ctx).unwrap();
+        assert!(freqs_hz[(0, 1, 0)].is_nan());
+        assert_abs_diff_eq!(freqs_hz[(0, 0, 0)], 128_010_000.);
+
+        assert!(weighted_centroids(weights.slice(s![.., 1.., ..]), &vis_ctx).is_err());
+    }
+
+    #[test]
+    fn test_average_vis_nan_policy() {
+        let jones_array = Array3::from_elem((2, 2, 1), Jones::<f32>::identity());
+        let mut weights = Array3::from_elem((2, 2, 1), 1.0_f32);
+        weights.slice_mut(s![.., 0, ..]).fill(-2.0);
+
+        let (avg_jones, avg_weights) = average_vis_with_policy(
+            jones_array.view(),
+            weights.view(),
+            2,
+            1,
+            AveragingPolicy::Nan,
+        )
+        .unwrap();
+        assert!(avg_jones[(0, 0, 0)].any_nan());
+        assert_abs_diff_eq!(avg_weights[(0, 0, 0)], -4.0);
+        assert_abs_diff_eq!(avg_jones[(0, 1, 0)], Jones::identity());
+        assert_abs_diff_eq!(avg_weights[(0, 1, 0)], 2.0);
+
+        let (avg_jones, _) = average_vis(jones_array.view(), weights.view(), 2, 1).unwrap();
+        assert_abs_diff_eq!(avg_jones[(0, 0, 0)], Jones::identity());
+    }
+
     // TODO: test unflagged with zero weight.
+
+    #[test]
+    fn test_auto_power_spectra() {
+        // 2 timesteps, 2 channels, baselines (0, 0), (0, 1), (1, 1)
+        let jones_array = Array3::from_shape_fn((2, 2, 3), |(t, c, b)| {
+            let power = (1 + t + 10 * c + 100 * b) as f32;
+            Jones::from([
+                Complex::new(power, 0.),
+                Complex::new(1., 1.),
+                Complex::new(1., 1.),
+                Complex::new(2. * power, 0.),
+            ])
+        });
+        let mut weight_array = Array3::from_elem((2, 2, 3), 1.0);
+        // flag the second timestep of the second channel of antenna 1.
+        weight_array[(1, 1, 2)] = -1.0;
+        // flag all of the first channel of antenna 0.
+        weight_array[(0, 0, 0)] = -1.0;
+        weight_array[(1, 0, 0)] = -1.0;
+
+        let (ants, spectra) = auto_power_spectra(
+            jones_array.view(),
+            weight_array.view(),
+            &[(0, 0), (0, 1), (1, 1)],
+        )
+        .unwrap();
+        assert_eq!(ants, vec![0, 1]);
+        assert_eq!(spectra.dim(), (2, 2, 2));
+        assert!(spectra[(0, 0, 0)].is_nan());
+        assert_abs_diff_eq!(spectra[(0, 1, 0)], 11.5);
+        assert_abs_diff_eq!(spectra[(0, 1, 1)], 23.0);
+        assert_abs_diff_eq!(spectra[(1, 0, 0)], 201.5);
+        assert_abs_diff_eq!(spectra[(1, 1, 0)], 211.0);
+    }
+
+    #[test]
+    fn test_smooth_spectra() {
+        // 1 timestep, 5 channels, 1 baseline. A spike in the middle channel.
+        let mut jones_array = Array3::from_elem((1, 5, 1), Jones::<f32>::default());
+        jones_array[(0, 2, 0)] = Jones::identity() * 4.0;
+        let mut weight_array = Array3::from_elem((1, 5, 1), 1.0);
+        // Flag the last channel.
+        weight_array[(0, 4, 0)] = -1.0;
+        jones_array[(0, 4, 0)] = Jones::identity() * 100.0;
+
+        smooth_spectra(
+            jones_array.view_mut(),
+            weight_array.view_mut(),
+            &HANNING_KERNEL,
+        )
+        .unwrap();
+
+        assert_abs_diff_eq!(jones_array[(0, 0, 0)], Jones::default());
+        assert_abs_diff_eq!(jones_array[(0, 1, 0)], Jones::identity());
+        assert_abs_diff_eq!(jones_array[(0, 2, 0)], Jones::identity() * 2.0);
+        // The flagged neighbour doesn't contribute, so the kernel is
+        // renormalised.
+        assert_abs_diff_eq!(jones_array[(0, 3, 0)], Jones::identity() * (4.0 / 3.0));
+        // The flagged channel is unchanged.
+        assert_abs_diff_eq!(jones_array[(0, 4, 0)], Jones::identity() * 100.0);
+        assert_abs_diff_eq!(weight_array[(0, 4, 0)], -1.0);
+
+        // Σ k_i = 1, Σ k_i² = 0.375
+        assert_abs_diff_eq!(weight_array[(0, 2, 0)], 1.0 / 0.375);
+        // The band edge: (0.75)² / (0.25² + 0.5²)
+        assert_abs_diff_eq!(weight_array[(0, 0, 0)], 1.8);
+
+        let mut bad_weights = Array3::from_elem((1, 4, 1), 1.0);
+        assert!(smooth_spectra(
+            jones_array.view_mut(),
+            bad_weights.view_mut(),
+            &HANNING_KERNEL
+        )
+        .is_err());
+        assert!(
+            smooth_spectra(jones_array.view_mut(), weight_array.view_mut(), &[0.5, 0.5]).is_err()
+        );
+    }
 }
@@ -0,0 +1,458 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Direction-dependent effects of the primary beam.
+//!
+//! The [`Beam`] trait is the single integration point for beam models; Marlu
+//! doesn't depend on any beam code unless asked to. With the `hyperbeam`
+//! feature, [`FeeBeam`] adapts the MWA FEE beam of `mwa_hyperbeam`.
+//!
+//! Beams can be applied to visibilities with [`apply_beam`], or used to
+//! compute primary beam power maps ([`beam_power_map`]) and corrections for
+//! Stokes I exports ([`stokes_i_attenuation`], [`correct_stokes_i`]).
+//!
+//! Not to be confused with [`crate::context::Beam`], which describes one of
+//! the analogue beams of a multi-beam observation.
+
+use ndarray::{prelude::*, Zip};
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::{AzEl, Jones, VisContext};
+
+#[derive(Error, Debug)]
+pub enum BeamError {
+    #[error("bad array shape supplied to argument {argument} of function {function}. expected {expected}, received {received}")]
+    BadArrayShape {
+        argument: String,
+        function: String,
+        expected: String,
+        received: String,
+    },
+}
+
+/// A model of the direction-dependent response of each tile.
+pub trait Beam {
+    /// The response of `tile` toward `azel` at `freq_hz`, with the
+    /// polarisations ordered like the visibilities (XX, XY, YX, YY). Directions
+    /// that can't be evaluated (e.g. below the horizon) should give
+    /// [`Jones::nan`].
+    fn jones(&self, azel: AzEl, freq_hz: f64, tile: usize) -> Jones<f64>;
+}
+
+/// Apply a [`Beam`] to visibilities of a source, e.g. to corrupt model
+/// visibilities for prediction: each visibility `V` of the baseline between
+/// tiles `p` and `q` becomes `J_p V J_q^H`.
+///
+/// - `jones_array` - visibilities with dimensions [`VisContext::sel_dims`]
+/// - `vis_ctx` - the selected timesteps, channels and baselines of `jones_array`
+/// - `beam` - the beam model, whose tile indices are the antenna indices in
+///   `vis_ctx.sel_baselines`
+/// - `azels` - the direction of the source at each timestep
+///
+/// The beam responses are computed once per tile, timestep and channel.
+/// Baselines are processed in parallel.
+///
+/// # Errors
+///
+/// Will return [`BeamError::BadArrayShape`] if the shape of `jones_array`
+/// doesn't match `vis_ctx`, or there isn't a direction for each timestep.
+pub fn apply_beam<B: Beam + Sync + ?Sized>(
+    mut jones_array: ArrayViewMut3<Jones<f32>>,
+    vis_ctx: &VisContext,
+    beam: &B,
+    azels: &[AzEl],
+) -> Result<(), BeamError> {
+    let sel_dims = vis_ctx.sel_dims();
+    if jones_array.dim() != sel_dims {
+        return Err(BeamError::BadArrayShape {
+            argument: "jones_array".to_string(),
+            function: "apply_beam".to_string(),
+            expected: format!("{:?}", sel_dims),
+            received: format!("{:?}", jones_array.dim()),
+        });
+    }
+    if azels.len() != sel_dims.0 {
+        return Err(BeamError::BadArrayShape {
+            argument: "azels".to_string(),
+            function: "apply_beam".to_string(),
+            expected: format!("({},)", sel_dims.0),
+            received: format!("({},)", azels.len()),
+        });
+    }
+
+    let num_tiles = num_tiles(vis_ctx);
+    let frequencies_hz = vis_ctx.frequencies_hz();
+
+    for (mut jones_2d, &azel) in jones_array.outer_iter_mut().zip(azels) {
+        let tile_jones = tile_jones(beam, azel, &frequencies_hz, num_tiles);
+        jones_2d
+            .axis_iter_mut(Axis(1))
+            .into_par_iter()
+            .zip(&vis_ctx.sel_baselines)
+            .for_each(|(mut bl_jones, &(ant1, ant2))| {
+                for (jones, chan_jones) in bl_jones.iter_mut().zip(tile_jones.outer_iter()) {
+                    let vis = Jones::<f64>::from(*jones);
+                    *jones = Jones::from(Jones::axbh(chan_jones[ant1] * vis, chan_jones[ant2]));
+                }
+            });
+    }
+
+    Ok(())
+}
+
+/// The primary beam power of `tile` for Stokes I toward each of `azels`, a
+/// grid of sky directions, at `freq_hz`. This is `tr(J J^H) / 2`, so an ideal
+/// (identity) response has a power of 1. Directions are evaluated in parallel.
+pub fn beam_power_map<B: Beam + Sync + ?Sized>(
+    beam: &B,
+    azels: ArrayView2<AzEl>,
+    freq_hz: f64,
+    tile: usize,
+) -> Array2<f64> {
+    let mut power = Array2::zeros(azels.dim());
+    Zip::from(&mut power)
+        .and(&azels)
+        .par_for_each(|power, &azel| {
+            let jones = beam.jones(azel, freq_hz, tile);
+            *power = stokes_i_attenuation_of(jones, jones);
+        });
+    power
+}
+
+/// The attenuation of Stokes I of an unpolarised source by the primary beam,
+/// for each visibility. For the baseline between tiles `p` and `q`, this is
+/// `Re(tr(J_p J_q^H)) / 2`.
+///
+/// - `vis_ctx` - the selected timesteps, channels and baselines
+/// - `beam` - the beam model, whose tile indices are the antenna indices in
+///   `vis_ctx.sel_baselines`
+/// - `azels` - the direction of the source at each timestep
+///
+/// Returns the attenuations with dimensions [`VisContext::sel_dims`].
+///
+/// # Errors
+///
+/// Will return [`BeamError::BadArrayShape`] if there isn't a direction for
+/// each timestep.
+pub fn stokes_i_attenuation<B: Beam + Sync + ?Sized>(
+    vis_ctx: &VisContext,
+    beam: &B,
+    azels: &[AzEl],
+) -> Result<Array3<f64>, BeamError> {
+    let sel_dims = vis_ctx.sel_dims();
+    if azels.len() != sel_dims.0 {
+        return Err(BeamError::BadArrayShape {
+            argument: "azels".to_string(),
+            function: "stokes_i_attenuation".to_string(),
+            expected: format!("({},)", sel_dims.0),
+            received: format!("({},)", azels.len()),
+        });
+    }
+
+    let num_tiles = num_tiles(vis_ctx);
+    let frequencies_hz = vis_ctx.frequencies_hz();
+    let mut attenuation = Array3::zeros(sel_dims);
+
+    for (mut attenuation_2d, &azel) in attenuation.outer_iter_mut().zip(azels) {
+        let tile_jones = tile_jones(beam, azel, &frequencies_hz, num_tiles);
+        Zip::from(attenuation_2d.columns_mut())
+            .and(&vis_ctx.sel_baselines)
+            .par_for_each(|mut bl_attenuation, &(ant1, ant2)| {
+                for (attenuation, chan_jones) in
+                    bl_attenuation.iter_mut().zip(tile_jones.outer_iter())
+                {
+                    *attenuation = stokes_i_attenuation_of(chan_jones[ant1], chan_jones[ant2]);
+                }
+            });
+    }
+
+    Ok(attenuation)
+}
+
+/// Correct Stokes I exports for the primary beam, given attenuations from
+/// [`stokes_i_attenuation`]. Each visibility is divided by its attenuation
+/// `A`, and its weight multiplied by `A^2` so that it remains an inverse
+/// variance. Visibilities whose attenuation is zero or not finite are flagged.
+///
+/// # Errors
+///
+/// Will return [`BeamError::BadArrayShape`] if the shapes of the arrays don't
+/// match.
+pub fn correct_stokes_i(
+    mut jones_array: ArrayViewMut3<Jones<f32>>,
+    mut weight_array: ArrayViewMut3<f32>,
+    attenuation: ArrayView3<f64>,
+) -> Result<(), BeamError> {
+    let jones_dims = jones_array.dim();
+    for (argument, dims) in [
+        ("weight_array", weight_array.dim()),
+        ("attenuation", attenuation.dim()),
+    ] {
+        if dims != jones_dims {
+            return Err(BeamError::BadArrayShape {
+                argument: argument.to_string(),
+                function: "correct_stokes_i".to_string(),
+                expected: format!("{:?}", jones_dims),
+                received: format!("{:?}", dims),
+            });
+        }
+    }
+
+    Zip::from(&mut jones_array)
+        .and(&mut weight_array)
+        .and(&attenuation)
+        .par_for_each(|jones, weight, &attenuation| {
+            if attenuation.is_finite() && attenuation != 0.0 {
+                *jones /= attenuation as f32;
+                *weight *= (attenuation * attenuation) as f32;
+            } else {
+                *weight = -weight.abs();
+            }
+        });
+
+    Ok(())
+}
+
+/// `Re(tr(a b^H)) / 2`.
+fn stokes_i_attenuation_of(a: Jones<f64>, b: Jones<f64>) -> f64 {
+    let ab = Jones::axbh(a, b);
+    (ab[0].re + ab[3].re) / 2.0
+}
+
+/// The number of tiles referred to by the baselines of `vis_ctx`.
+fn num_tiles(vis_ctx: &VisContext) -> usize {
+    vis_ctx
+        .sel_baselines
+        .iter()
+        .map(|&(ant1, ant2)| ant1.max(ant2) + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+/// The response of each tile toward `azel`, with dimensions `[channel][tile]`.
+fn tile_jones<B: Beam + Sync + ?Sized>(
+    beam: &B,
+    azel: AzEl,
+    frequencies_hz: &[f64],
+    num_tiles: usize,
+) -> Array2<Jones<f64>> {
+    let mut tile_jones = Array2::<Jones<f64>>::zeros((frequencies_hz.len(), num_tiles));
+    tile_jones
+        .outer_iter_mut()
+        .into_par_iter()
+        .zip(frequencies_hz)
+        .for_each(|(mut chan_jones, &freq_hz)| {
+            for (tile, jones) in chan_jones.iter_mut().enumerate() {
+                *jones = beam.jones(azel, freq_hz, tile);
+            }
+        });
+    tile_jones
+}
+
+#[cfg(feature = "hyperbeam")]
+pub use fee::FeeBeam;
+
+#[cfg(feature = "hyperbeam")]
+mod fee {
+    use ndarray::{Array2, Axis};
+
+    use super::Beam;
+    use crate::{AzEl, Jones, MwaObsContext};
+
+    /// An adaptor for the MWA FEE beam of `mwa_hyperbeam`, with the dipole
+    /// delays and gains of each tile.
+    pub struct FeeBeam {
+        /// The `mwa_hyperbeam` FEE beam.
+        pub beam: mwa_hyperbeam::fee::FEEBeam,
+
+        /// The dipole delays of each tile, with dimensions `[tile][16]`.
+        pub delays: Array2<u32>,
+
+        /// The dipole gains of each tile, with dimensions `[tile][32]`; the
+        /// gains of the X dipoles, then the Y dipoles.
+        pub amps: Array2<f64>,
+
+        /// Whether to normalise the responses to the response at zenith.
+        pub norm_to_zenith: bool,
+
+        /// The latitude of the array \[radians\], to correct for the
+        /// parallactic angle. If `None`, no correction is applied.
+        pub latitude_rad: Option<f64>,
+    }
+
+    impl FeeBeam {
+        /// Adapt `beam` with the dipole delays and gains of each tile in
+        /// `mwa_ctx`. The delays of the X dipoles are used for both
+        /// polarisations.
+        pub fn from_mwa_obs_ctx(
+            beam: mwa_hyperbeam::fee::FEEBeam,
+            mwa_ctx: &MwaObsContext,
+            latitude_rad: Option<f64>,
+        ) -> Self {
+            let delays = mwa_ctx.ant_dipole_delays.index_axis(Axis(1), 0).to_owned();
+            let num_tiles = mwa_ctx.ant_dipole_gains.len_of(Axis(0));
+            let amps = Array2::from_shape_fn((num_tiles, 32), |(tile, dipole)| {
+                mwa_ctx.ant_dipole_gains[(tile, dipole / 16, dipole % 16)]
+            });
+            Self {
+                beam,
+                delays,
+                amps,
+                norm_to_zenith: true,
+                latitude_rad,
+            }
+        }
+    }
+
+    impl Beam for FeeBeam {
+        fn jones(&self, azel: AzEl, freq_hz: f64, tile: usize) -> Jones<f64> {
+            let delays = self.delays.row(tile);
+            let amps = self.amps.row(tile);
+            match self.beam.calc_jones(
+                azel.az,
+                azel.za(),
+                freq_hz.round() as u32,
+                delays.as_slice().unwrap(),
+                amps.as_slice().unwrap(),
+                self.norm_to_zenith,
+                self.latitude_rad,
+                true,
+            ) {
+                Ok(jones) => Jones::from(*jones),
+                Err(_) => Jones::nan(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use hifitime::{Duration, Epoch, Unit};
+
+    use super::*;
+    use crate::{AveragingPolicy, Complex, VisUnit};
+
+    /// A beam with a scalar, frequency-dependent gain for each tile.
+    struct ScalarBeam {
+        gains: Vec<f64>,
+    }
+
+    impl Beam for ScalarBeam {
+        fn jones(&self, azel: AzEl, freq_hz: f64, tile: usize) -> Jones<f64> {
+            if azel.el < 0.0 {
+                return Jones::nan();
+            }
+            Jones::identity() * (self.gains[tile] * freq_hz / 100e6)
+        }
+    }
+
+    #[test]
+    fn test_apply_beam() {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 2,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: 2,
+            start_freq_hz: 100e6,
+            freq_resolution_hz: 100e6,
+            sel_baselines: vec![(0, 1), (1, 2)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+        let beam = ScalarBeam {
+            gains: vec![1.0, 2.0, 3.0],
+        };
+        let mut jones_array = Array3::from_elem(vis_ctx.sel_dims(), Jones::<f32>::identity());
+        jones_array[(0, 0, 0)][1] = Complex::new(0.0, 1.0);
+        let azels = [AzEl::new_degrees(0.0, 90.0), AzEl::new_degrees(0.0, -10.0)];
+        apply_beam(jones_array.view_mut(), &vis_ctx, &beam, &azels).unwrap();
+
+        // baseline (0, 1) at 100 MHz: gains 1 and 2.
+        let expected = Jones::from([
+            Complex::new(2.0, 0.0),
+            Complex::new(0.0, 2.0),
+            Complex::new(0.0, 0.0),
+            Complex::new(2.0, 0.0),
+        ]);
+        assert_abs_diff_eq!(jones_array[(0, 0, 0)], expected);
+        // baseline (1, 2) at 200 MHz: gains 4 and 6.
+        assert_abs_diff_eq!(jones_array[(0, 1, 1)], Jones::identity() * 24.0);
+        // The source is below the horizon for the second timestep.
+        assert!(jones_array[(1, 0, 0)].any_nan());
+
+        assert!(apply_beam(jones_array.view_mut(), &vis_ctx, &beam, &azels[..1]).is_err());
+    }
+
+    #[test]
+    fn test_beam_power_map() {
+        let beam = ScalarBeam {
+            gains: vec![1.0, 2.0],
+        };
+        let azels = Array2::from_shape_fn((2, 3), |(i, j)| {
+            AzEl::new_degrees(j as f64 * 10.0, if i == 0 { 45.0 } else { -45.0 })
+        });
+        let power = beam_power_map(&beam, azels.view(), 150e6, 1);
+        assert_eq!(power.dim(), (2, 3));
+        // (2 * 1.5)^2
+        assert_abs_diff_eq!(power.row(0), Array1::from_elem(3, 9.0));
+        assert!(power.row(1).iter().all(|p| p.is_nan()));
+    }
+
+    #[test]
+    fn test_stokes_i_attenuation_and_correction() {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 1,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: 2,
+            start_freq_hz: 100e6,
+            freq_resolution_hz: 100e6,
+            sel_baselines: vec![(0, 1), (1, 2)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+        let beam = ScalarBeam {
+            gains: vec![1.0, 2.0, 0.0],
+        };
+        let azels = [AzEl::new_degrees(0.0, 90.0)];
+        let attenuation = stokes_i_attenuation(&vis_ctx, &beam, &azels).unwrap();
+        assert_abs_diff_eq!(
+            attenuation,
+            array![[[2.0, 0.0], [8.0, 0.0]]],
+            epsilon = 1e-12
+        );
+
+        // Corrupting a Stokes I source and correcting it gives the source back.
+        let mut jones_array = Array3::from_elem(vis_ctx.sel_dims(), Jones::<f32>::identity() * 5.0);
+        apply_beam(jones_array.view_mut(), &vis_ctx, &beam, &azels).unwrap();
+        let mut weight_array = Array3::from_elem(vis_ctx.sel_dims(), 1.0);
+        weight_array[(0, 1, 0)] = -1.0;
+        correct_stokes_i(
+            jones_array.view_mut(),
+            weight_array.view_mut(),
+            attenuation.view(),
+        )
+        .unwrap();
+        assert_abs_diff_eq!(jones_array[(0, 0, 0)], Jones::identity() * 5.0);
+        assert_abs_diff_eq!(jones_array[(0, 1, 0)], Jones::identity() * 5.0);
+        assert_abs_diff_eq!(weight_array, array![[[4.0, -1.0], [-64.0, -1.0]]]);
+
+        assert!(correct_stokes_i(
+            jones_array.view_mut(),
+            weight_array.view_mut(),
+            attenuation.slice(s![.., ..1, ..]),
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,272 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Criterion benchmarks of representative Marlu workloads.
+//!
+//! Downstream projects can use these to measure how Marlu performs on their
+//! own hardware, e.g. in a bench target like
+//!
+//! ```ignore
+//! use marlu::bench::{self, criterion::*, Workload};
+//!
+//! fn marlu(crt: &mut Criterion) {
+//!     bench::bench_workload(crt, &Workload::mwa_coarse_chan());
+//! }
+//!
+//! criterion_group! {
+//!     name = benches;
+//!     config = bench::criterion_config();
+//!     targets = marlu
+//! }
+//! criterion_main!(benches);
+//! ```
+//!
+//! The visibilities are made with [`crate::synth`], so no data is needed on
+//! disk, except by [`bench_read_mwalib`]. Regressions are detected with
+//! criterion's baselines: save one with
+//! `cargo bench -- --save-baseline <name>`, then compare against it with
+//! `cargo bench -- --baseline <name>`.
+
+use std::path::Path;
+
+pub use criterion;
+use criterion::{BatchSize, Criterion, Throughput};
+use hifitime::Duration;
+
+use crate::{
+    averaging::average_vis,
+    pos::{precession::get_lmst, xyz::xyzs_to_cross_uvws_parallel},
+    synth::{make_obs_ctx, make_vis_ctx, make_vis_cube, make_xyzs},
+    VisContext, VisWriteFormat, VisWriter,
+};
+
+#[cfg(feature = "mwalib")]
+use crate::{mwalib::CorrelatorContext, VisSelection};
+
+/// The dimensions of a synthetic benchmark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Workload {
+    /// The name of the workload, used to name the benchmark group.
+    pub name: &'static str,
+    /// The number of antennas. Every cross-correlation baseline is used.
+    pub num_ants: usize,
+    /// The number of (pre-averaging) timesteps.
+    pub num_timesteps: usize,
+    /// The number of (pre-averaging) channels.
+    pub num_chans: usize,
+    /// The time averaging factor.
+    pub avg_time: usize,
+    /// The frequency averaging factor.
+    pub avg_freq: usize,
+}
+
+impl Workload {
+    /// A small workload that benchmarks quickly, e.g. for CI.
+    pub fn small() -> Self {
+        Self {
+            name: "small",
+            num_ants: 32,
+            num_timesteps: 4,
+            num_chans: 64,
+            avg_time: 2,
+            avg_freq: 4,
+        }
+    }
+
+    /// One 1.28MHz coarse channel (32 40kHz channels) of 8 2s timesteps of a
+    /// 128 tile MWA observation, averaged to 4s and 160kHz.
+    pub fn mwa_coarse_chan() -> Self {
+        Self {
+            name: "mwa_coarse_chan",
+            num_ants: 128,
+            num_timesteps: 8,
+            num_chans: 32,
+            avg_time: 2,
+            avg_freq: 4,
+        }
+    }
+
+    /// The [`VisContext`] of this workload.
+    pub fn vis_ctx(&self) -> VisContext {
+        let mut vis_ctx = make_vis_ctx(self.num_ants, self.num_timesteps, self.num_chans);
+        vis_ctx.avg_time = self.avg_time;
+        vis_ctx.avg_freq = self.avg_freq;
+        vis_ctx
+    }
+
+    /// The number of (pre-averaging) visibilities.
+    pub fn num_vis(&self) -> u64 {
+        let (num_timesteps, num_chans, num_baselines) = self.vis_ctx().sel_dims();
+        (num_timesteps * num_chans * num_baselines) as u64
+    }
+}
+
+/// A [`Criterion`] configured for comparing against saved baselines: more
+/// samples and a stricter significance level than the defaults, and changes
+/// smaller than 3% are treated as noise.
+pub fn criterion_config() -> Criterion {
+    Criterion::default()
+        .sample_size(50)
+        .significance_level(0.01)
+        .noise_threshold(0.03)
+}
+
+/// Benchmark every synthetic benchmark in this module with `workload`.
+pub fn bench_workload(crt: &mut Criterion, workload: &Workload) {
+    bench_averaging(crt, workload);
+    bench_uvws(crt, workload);
+    bench_writing(crt, workload);
+}
+
+/// Benchmark [`average_vis`] on the visibilities of `workload`.
+pub fn bench_averaging(crt: &mut Criterion, workload: &Workload) {
+    let vis_ctx = workload.vis_ctx();
+    let (jones_array, weight_array) = make_vis_cube(&vis_ctx, 0);
+
+    let mut group = crt.benchmark_group(workload.name);
+    group.throughput(Throughput::Elements(workload.num_vis()));
+    group.bench_function("average_vis", |bch| {
+        bch.iter(|| {
+            average_vis(
+                jones_array.view(),
+                weight_array.view(),
+                workload.avg_time,
+                workload.avg_freq,
+            )
+            .unwrap()
+        });
+    });
+    group.finish();
+}
+
+/// Benchmark generating the cross-correlation UVWs of every timestep of
+/// `workload`.
+pub fn bench_uvws(crt: &mut Criterion, workload: &Workload) {
+    let vis_ctx = workload.vis_ctx();
+    let obs_ctx = make_obs_ctx(workload.num_ants);
+    let xyzs = make_xyzs(workload.num_ants);
+    let dut1 = Duration::from_total_nanoseconds(0);
+
+    let mut group = crt.benchmark_group(workload.name);
+    group.throughput(Throughput::Elements(
+        (vis_ctx.num_sel_timesteps * vis_ctx.sel_baselines.len()) as u64,
+    ));
+    group.bench_function("xyzs_to_cross_uvws", |bch| {
+        bch.iter(|| {
+            vis_ctx
+                .timeseries(false, true)
+                .map(|epoch| {
+                    let lst_rad = get_lmst(obs_ctx.array_pos.longitude_rad, epoch, dut1);
+                    let phase_centre = obs_ctx.phase_centre.to_hadec(lst_rad);
+                    xyzs_to_cross_uvws_parallel(&xyzs, phase_centre)
+                })
+                .collect::<Vec<_>>()
+        });
+    });
+    group.finish();
+}
+
+/// Benchmark writing (and averaging) the visibilities of `workload` with each
+/// of the enabled [`VisWriteFormat`]s. The outputs are written to a directory
+/// in [`std::env::temp_dir`], which is removed afterwards.
+pub fn bench_writing(crt: &mut Criterion, workload: &Workload) {
+    let vis_ctx = workload.vis_ctx();
+    let obs_ctx = make_obs_ctx(workload.num_ants);
+    let (jones_array, weight_array) = make_vis_cube(&vis_ctx, 0);
+    let dut1 = Duration::from_total_nanoseconds(0);
+
+    let out_dir = std::env::temp_dir().join(format!("marlu_bench_{}", std::process::id()));
+    std::fs::create_dir_all(&out_dir).unwrap();
+
+    let mut group = crt.benchmark_group(workload.name);
+    group.throughput(Throughput::Elements(workload.num_vis()));
+    for (format, ext) in [
+        (VisWriteFormat::Uvfits, "uvfits"),
+        (VisWriteFormat::MeasurementSet, "ms"),
+        (VisWriteFormat::Parquet, "parquet"),
+        (VisWriteFormat::Zarr, "zarr"),
+    ] {
+        if !format.is_enabled() {
+            continue;
+        }
+        let path = out_dir.join(format!("{}.{}", workload.name, ext));
+        group.bench_function(format!("write {}", format), |bch| {
+            bch.iter_batched(
+                || {
+                    remove_output(&path);
+                    VisWriter::from_path(&path, &vis_ctx, &obs_ctx, dut1, None).unwrap()
+                },
+                |mut writer| {
+                    writer
+                        .write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
+                        .unwrap();
+                    writer.finalise().unwrap();
+                },
+                BatchSize::PerIteration,
+            );
+        });
+    }
+    group.finish();
+
+    std::fs::remove_dir_all(&out_dir).ok();
+}
+
+/// Remove an output from a previous iteration, which may be a file or a
+/// directory.
+fn remove_output(path: &Path) {
+    if path.is_dir() {
+        std::fs::remove_dir_all(path).unwrap();
+    } else if path.exists() {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+/// Benchmark [`VisSelection::read_mwalib`] reading `vis_sel` from real
+/// observation data. The benchmark is named after the metafits file.
+#[cfg(feature = "mwalib")]
+pub fn bench_read_mwalib(
+    crt: &mut Criterion,
+    corr_ctx: &CorrelatorContext,
+    vis_sel: &VisSelection,
+) {
+    let fine_chans_per_coarse = corr_ctx.metafits_context.num_corr_fine_chans_per_coarse;
+    let mut jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+    let mut flag_array = vis_sel.allocate_flags(fine_chans_per_coarse).unwrap();
+    let name = Path::new(&corr_ctx.metafits_context.metafits_filename)
+        .file_stem()
+        .map_or_else(
+            || "mwalib".into(),
+            |stem| stem.to_string_lossy().into_owned(),
+        );
+
+    let mut group = crt.benchmark_group(name);
+    group.throughput(Throughput::Elements(jones_array.len() as u64));
+    group.bench_function("read_mwalib", |bch| {
+        bch.iter(|| {
+            vis_sel
+                .read_mwalib(
+                    corr_ctx,
+                    jones_array.view_mut(),
+                    flag_array.view_mut(),
+                    false,
+                )
+                .unwrap();
+        });
+    });
+    group.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workload_dims() {
+        let workload = Workload::mwa_coarse_chan();
+        let vis_ctx = workload.vis_ctx();
+        assert_eq!(vis_ctx.sel_dims(), (8, 32, 8128));
+        assert_eq!(vis_ctx.avg_dims(), (4, 8, 8128));
+        assert_eq!(workload.num_vis(), 8 * 32 * 8128);
+    }
+}
@@ -0,0 +1,76 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Write a miniature MWAX observation (see `marlu::io::mini`) into a
+//! directory, e.g. for the tests of tools that read MWA data.
+
+use std::{path::PathBuf, process::exit};
+
+use marlu::io::MiniDataset;
+
+const USAGE: &str = "usage: marlu-mini-data [--ants N] [--coarse-chans N] [--fine-chans N] \
+[--timesteps N] [--uvfits] <dir>";
+
+fn main() {
+    let mut builder = MiniDataset::builder();
+    let mut dir = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut number = || -> usize {
+            args.next()
+                .and_then(|value| value.parse().ok())
+                .filter(|&value| value > 0)
+                .unwrap_or_else(|| {
+                    eprintln!("{} needs a positive number\n{}", arg, USAGE);
+                    exit(1);
+                })
+        };
+        builder = match arg.as_str() {
+            "--ants" => builder.num_ants(number()),
+            "--coarse-chans" => builder.coarse_chans((117..).take(number()).collect()),
+            "--fine-chans" => builder.fine_chans_per_coarse(number()),
+            "--timesteps" => builder.num_timesteps(number()),
+            #[cfg(feature = "cfitsio")]
+            "--uvfits" => builder.uvfits(true),
+            #[cfg(not(feature = "cfitsio"))]
+            "--uvfits" => {
+                eprintln!("--uvfits requires Marlu to be built with the cfitsio feature");
+                exit(1);
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if dir.is_none() && !arg.starts_with('-') => {
+                dir = Some(PathBuf::from(&arg));
+                builder
+            }
+            _ => {
+                eprintln!("unexpected argument {}\n{}", arg, USAGE);
+                exit(1);
+            }
+        };
+    }
+    let dir = dir.unwrap_or_else(|| {
+        eprintln!("{}", USAGE);
+        exit(1);
+    });
+
+    let result = std::fs::create_dir_all(&dir)
+        .map_err(Into::into)
+        .and_then(|_| builder.build(&dir));
+    match result {
+        Ok(dataset) => {
+            println!("{}", dataset.metafits.display());
+            for path in dataset.gpubox.iter().chain(&dataset.uvfits) {
+                println!("{}", path.display());
+            }
+        }
+        Err(e) => {
+            eprintln!("couldn't write the dataset into {}: {}", dir.display(), e);
+            exit(1);
+        }
+    }
+}
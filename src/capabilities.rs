@@ -0,0 +1,196 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A runtime description of what this build of Marlu can do.
+//!
+//! Pipelines can use [`capabilities`] to adapt their behaviour to the enabled
+//! features, and include it in provenance records (e.g. with
+//! `write_history_messages`).
+
+use std::fmt::Display;
+
+use crate::built_info;
+
+/// A C library that Marlu is linked against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkedLibrary {
+    /// The name of the library
+    pub name: &'static str,
+
+    /// The version of the library, if it is reported at runtime. The ERFA
+    /// and casacore bindings don't expose their versions.
+    pub version: Option<String>,
+}
+
+/// The compiled features, linked libraries and supported file formats of
+/// this build of Marlu. See [`capabilities`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The version of Marlu
+    pub marlu_version: &'static str,
+
+    /// The enabled cargo features, e.g. `mwalib`, `ms`, `cuda`, `approx`
+    pub features: Vec<&'static str>,
+
+    /// The linked C libraries
+    pub libraries: Vec<LinkedLibrary>,
+
+    /// The formats that visibilities can be read from
+    pub read_formats: Vec<&'static str>,
+
+    /// The formats that visibilities can be written to
+    pub write_formats: Vec<&'static str>,
+}
+
+impl Capabilities {
+    /// Whether a cargo feature is enabled.
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.contains(&feature)
+    }
+}
+
+impl Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "marlu {}", self.marlu_version)?;
+        writeln!(f, "features: {}", self.features.join(", "))?;
+        let libraries: Vec<String> = self
+            .libraries
+            .iter()
+            .map(|lib| match &lib.version {
+                Some(version) => format!("{} {}", lib.name, version),
+                None => lib.name.to_string(),
+            })
+            .collect();
+        writeln!(f, "libraries: {}", libraries.join(", "))?;
+        writeln!(f, "read formats: {}", self.read_formats.join(", "))?;
+        write!(f, "write formats: {}", self.write_formats.join(", "))
+    }
+}
+
+/// Describe the compiled features, linked libraries and supported file
+/// formats of this build of Marlu.
+pub fn capabilities() -> Capabilities {
+    let features = [
+        ("erfa", cfg!(feature = "erfa")),
+        ("mwalib", cfg!(feature = "mwalib")),
+        ("cfitsio", cfg!(feature = "cfitsio")),
+        ("ms", cfg!(feature = "ms")),
+        ("cuda", cfg!(feature = "cuda")),
+        ("approx", cfg!(feature = "approx")),
+        ("serde", cfg!(feature = "serde")),
+        ("python", cfg!(feature = "python")),
+        ("ffi", cfg!(feature = "ffi")),
+        ("parquet", cfg!(feature = "parquet")),
+        ("zarr", cfg!(feature = "zarr")),
+        ("fft", cfg!(feature = "fft")),
+        ("hyperbeam", cfg!(feature = "hyperbeam")),
+        ("mmap", cfg!(feature = "mmap")),
+        ("async", cfg!(feature = "async")),
+        ("bench", cfg!(feature = "bench")),
+        ("mini-data", cfg!(feature = "mini-data")),
+        ("erfa-static", cfg!(feature = "erfa-static")),
+        ("cfitsio-static", cfg!(feature = "cfitsio-static")),
+        ("cuda-static", cfg!(feature = "cuda-static")),
+    ]
+    .into_iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(feature, _)| feature)
+    .collect();
+
+    let libraries = [
+        ("erfa", cfg!(feature = "erfa"), None),
+        ("cfitsio", cfg!(feature = "cfitsio"), cfitsio_version()),
+        ("casacore", cfg!(feature = "ms"), None),
+        ("cudart", cfg!(feature = "cuda"), None),
+    ]
+    .into_iter()
+    .filter(|(_, enabled, _)| *enabled)
+    .map(|(name, _, version)| LinkedLibrary { name, version })
+    .collect();
+
+    let mut read_formats = vec![];
+    if cfg!(feature = "mwalib") {
+        read_formats.push("mwa raw (legacy and MWAX)");
+    }
+    if cfg!(feature = "mmap") {
+        read_formats.push("mwax gpubox (memory-mapped)");
+    }
+    if cfg!(feature = "cfitsio") {
+        read_formats.push("uvfits");
+    }
+    if cfg!(feature = "ms") {
+        read_formats.push("measurement set");
+    }
+    let mut write_formats = vec![];
+    if cfg!(feature = "cfitsio") {
+        write_formats.push("uvfits");
+    }
+    if cfg!(feature = "ms") {
+        write_formats.push("measurement set");
+    }
+    if cfg!(feature = "parquet") {
+        write_formats.push("parquet");
+    }
+    if cfg!(feature = "zarr") {
+        write_formats.push("zarr");
+    }
+
+    Capabilities {
+        marlu_version: built_info::PKG_VERSION,
+        features,
+        libraries,
+        read_formats,
+        write_formats,
+    }
+}
+
+/// Get the version of the linked cfitsio library, e.g. "3.49".
+#[cfg(feature = "cfitsio")]
+fn cfitsio_version() -> Option<String> {
+    let mut version: f32 = 0.0;
+    unsafe {
+        fitsio_sys::ffvers(&mut version);
+    }
+    Some(format!("{:.2}", version))
+}
+
+#[cfg(not(feature = "cfitsio"))]
+fn cfitsio_version() -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let caps = capabilities();
+        assert_eq!(caps.marlu_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(caps.has_feature("approx"), cfg!(feature = "approx"));
+        assert_eq!(caps.has_feature("ms"), cfg!(feature = "ms"));
+        assert_eq!(
+            caps.write_formats.contains(&"uvfits"),
+            cfg!(feature = "cfitsio")
+        );
+        assert_eq!(
+            caps.read_formats.contains(&"uvfits"),
+            cfg!(feature = "cfitsio")
+        );
+        assert_eq!(
+            caps.read_formats.contains(&"measurement set"),
+            cfg!(feature = "ms")
+        );
+        assert_eq!(caps.has_feature("erfa"), cfg!(feature = "erfa"));
+        assert_eq!(
+            caps.libraries.iter().any(|lib| lib.name == "erfa"),
+            cfg!(feature = "erfa")
+        );
+
+        let display = caps.to_string();
+        assert!(display.starts_with(&format!("marlu {}", caps.marlu_version)));
+        assert!(display.contains("read formats: "));
+        assert!(display.contains("write formats: "));
+    }
+}
@@ -0,0 +1,324 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Nearest-neighbour matching between lists of [`RADec`] positions, e.g. to
+//! cross-match source lists.
+//!
+//! Positions are converted to unit vectors and stored in a kd-tree, so that
+//! queries don't suffer from the RA branch cut or the poles. The straight-line
+//! (chord) distance between unit vectors increases monotonically with the
+//! angular separation, so searching by chord distance gives the same matches as
+//! searching by separation.
+
+use std::{cmp::Ordering, f64::consts::PI};
+
+use rayon::prelude::*;
+
+use crate::RADec;
+
+/// A match of a query position to a [`Catalog`] position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CatalogMatch {
+    /// The index of the matching position in the positions used to make the
+    /// [`Catalog`].
+    pub index: usize,
+
+    /// The angular separation between the query position and the matching
+    /// position \[radians\].
+    pub separation: f64,
+}
+
+/// A kd-tree of [`RADec`] positions for fast nearest-neighbour and radius
+/// queries.
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    /// The unit vector of each position, in the order they were given.
+    vectors: Vec<[f64; 3]>,
+
+    /// Indices into `vectors`, arranged as an implicit kd-tree; the median of
+    /// each sub-slice is the splitting node, on the axis given by the depth of
+    /// the sub-slice modulo 3.
+    tree: Vec<usize>,
+}
+
+impl Catalog {
+    /// Build a [`Catalog`] from `radecs`. Match indices refer to the positions
+    /// of `radecs`.
+    pub fn new(radecs: &[RADec]) -> Catalog {
+        let vectors: Vec<[f64; 3]> = radecs.iter().copied().map(unit_vector).collect();
+        let mut tree: Vec<usize> = (0..vectors.len()).collect();
+        build(&mut tree, &vectors, 0);
+        Catalog { vectors, tree }
+    }
+
+    /// The number of positions in the catalog.
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
+    }
+
+    /// Find the catalog position nearest to `radec` that is within `radius`
+    /// \[radians\], or `None` if there isn't one.
+    pub fn nearest(&self, radec: RADec, radius: f64) -> Option<CatalogMatch> {
+        let target = unit_vector(radec);
+        let mut best = None;
+        self.nearest_in(
+            &self.tree,
+            0,
+            &target,
+            radius_to_chord_sq(radius),
+            &mut best,
+        );
+        best.map(|(index, chord_sq)| CatalogMatch {
+            index,
+            separation: chord_sq_to_separation(chord_sq),
+        })
+    }
+
+    /// Find all catalog positions within `radius` \[radians\] of `radec`,
+    /// sorted by separation.
+    pub fn within(&self, radec: RADec, radius: f64) -> Vec<CatalogMatch> {
+        let target = unit_vector(radec);
+        let mut matches = vec![];
+        self.within_in(
+            &self.tree,
+            0,
+            &target,
+            radius_to_chord_sq(radius),
+            &mut matches,
+        );
+        let mut matches: Vec<CatalogMatch> = matches
+            .into_iter()
+            .map(|(index, chord_sq)| CatalogMatch {
+                index,
+                separation: chord_sq_to_separation(chord_sq),
+            })
+            .collect();
+        matches.sort_by(|a, b| {
+            a.separation
+                .partial_cmp(&b.separation)
+                .unwrap_or(Ordering::Equal)
+                .then(a.index.cmp(&b.index))
+        });
+        matches
+    }
+
+    /// Cross-match `radecs` against the catalog in parallel, returning the
+    /// nearest catalog position within `radius` \[radians\] of each of
+    /// `radecs` (see [`Catalog::nearest`]).
+    pub fn cross_match(&self, radecs: &[RADec], radius: f64) -> Vec<Option<CatalogMatch>> {
+        radecs
+            .par_iter()
+            .map(|&radec| self.nearest(radec, radius))
+            .collect()
+    }
+
+    fn nearest_in(
+        &self,
+        tree: &[usize],
+        depth: usize,
+        target: &[f64; 3],
+        max_chord_sq: f64,
+        best: &mut Option<(usize, f64)>,
+    ) {
+        if tree.is_empty() {
+            return;
+        }
+        let axis = depth % 3;
+        let mid = tree.len() / 2;
+        let node = &self.vectors[tree[mid]];
+        let chord_sq = chord_sq(node, target);
+        if chord_sq <= max_chord_sq && best.map_or(true, |(_, best)| chord_sq < best) {
+            *best = Some((tree[mid], chord_sq));
+        }
+
+        let diff = target[axis] - node[axis];
+        let (near, far) = if diff < 0.0 {
+            (&tree[..mid], &tree[mid + 1..])
+        } else {
+            (&tree[mid + 1..], &tree[..mid])
+        };
+        self.nearest_in(near, depth + 1, target, max_chord_sq, best);
+        let bound = best.map_or(max_chord_sq, |(_, best)| best);
+        if diff * diff <= bound {
+            self.nearest_in(far, depth + 1, target, max_chord_sq, best);
+        }
+    }
+
+    fn within_in(
+        &self,
+        tree: &[usize],
+        depth: usize,
+        target: &[f64; 3],
+        max_chord_sq: f64,
+        matches: &mut Vec<(usize, f64)>,
+    ) {
+        if tree.is_empty() {
+            return;
+        }
+        let axis = depth % 3;
+        let mid = tree.len() / 2;
+        let node = &self.vectors[tree[mid]];
+        let chord_sq = chord_sq(node, target);
+        if chord_sq <= max_chord_sq {
+            matches.push((tree[mid], chord_sq));
+        }
+
+        let diff = target[axis] - node[axis];
+        if diff < 0.0 || diff * diff <= max_chord_sq {
+            self.within_in(&tree[..mid], depth + 1, target, max_chord_sq, matches);
+        }
+        if diff >= 0.0 || diff * diff <= max_chord_sq {
+            self.within_in(&tree[mid + 1..], depth + 1, target, max_chord_sq, matches);
+        }
+    }
+}
+
+/// Arrange `tree` (indices into `vectors`) into an implicit kd-tree.
+fn build(tree: &mut [usize], vectors: &[[f64; 3]], depth: usize) {
+    if tree.len() <= 1 {
+        return;
+    }
+    let axis = depth % 3;
+    let mid = tree.len() / 2;
+    tree.select_nth_unstable_by(mid, |&a, &b| {
+        vectors[a][axis]
+            .partial_cmp(&vectors[b][axis])
+            .unwrap_or(Ordering::Equal)
+    });
+    let (left, right) = tree.split_at_mut(mid);
+    build(left, vectors, depth + 1);
+    build(&mut right[1..], vectors, depth + 1);
+}
+
+fn unit_vector(radec: RADec) -> [f64; 3] {
+    let (s_ra, c_ra) = radec.ra.sin_cos();
+    let (s_dec, c_dec) = radec.dec.sin_cos();
+    [c_dec * c_ra, c_dec * s_ra, s_dec]
+}
+
+fn chord_sq(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// The squared chord length between unit vectors separated by `radius`
+/// \[radians\].
+fn radius_to_chord_sq(radius: f64) -> f64 {
+    let chord = 2.0 * (radius.clamp(0.0, PI) / 2.0).sin();
+    chord * chord
+}
+
+/// The angular separation between unit vectors with the squared chord length
+/// `chord_sq` \[radians\].
+fn chord_sq_to_separation(chord_sq: f64) -> f64 {
+    2.0 * (chord_sq.sqrt() / 2.0).min(1.0).asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    /// Pseudo-random positions spread over the whole sky, including both sides
+    /// of the RA branch cut and near the poles.
+    fn get_positions(n: usize, seed: u64) -> Vec<RADec> {
+        let mut state = seed;
+        let mut next = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1_u64 << 53) as f64
+        };
+        (0..n)
+            .map(|_| RADec::new(next() * 2.0 * PI, (2.0 * next() - 1.0).asin()))
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        let catalog_positions = get_positions(2000, 1);
+        let queries = get_positions(200, 2);
+        let radius = 3_f64.to_radians();
+        let catalog = Catalog::new(&catalog_positions);
+        assert_eq!(catalog.len(), 2000);
+
+        let matches = catalog.cross_match(&queries, radius);
+        assert_eq!(matches.len(), queries.len());
+        let mut num_matched = 0;
+        for (query, result) in queries.iter().zip(matches) {
+            let mut expected: Vec<(usize, f64)> = catalog_positions
+                .iter()
+                .map(|pos| query.separation(*pos))
+                .enumerate()
+                .filter(|(_, sep)| *sep <= radius)
+                .collect();
+            expected.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+            match (result, expected.first()) {
+                (Some(result), Some(&(index, separation))) => {
+                    assert_eq!(result.index, index);
+                    assert_abs_diff_eq!(result.separation, separation, epsilon = 1e-10);
+                    num_matched += 1;
+                }
+                (None, None) => (),
+                _ => panic!("nearest mismatch for {query}: {result:?} vs {expected:?}"),
+            }
+
+            let within = catalog.within(*query, radius);
+            assert_eq!(
+                within.iter().map(|m| m.index).collect::<Vec<_>>(),
+                expected.iter().map(|(i, _)| *i).collect::<Vec<_>>()
+            );
+        }
+        // Make sure that the test isn't vacuous.
+        assert!(num_matched > 50);
+    }
+
+    #[test]
+    fn test_branch_cut_and_poles() {
+        let catalog = Catalog::new(&[
+            RADec::new_degrees(359.9, 10.0),
+            RADec::new_degrees(180.0, 89.95),
+            RADec::new_degrees(90.0, 0.0),
+        ]);
+
+        let result = catalog
+            .nearest(RADec::new_degrees(0.1, 10.0), 1_f64.to_radians())
+            .unwrap();
+        assert_eq!(result.index, 0);
+        assert_abs_diff_eq!(
+            result.separation,
+            0.2_f64.to_radians() * 10_f64.to_radians().cos(),
+            epsilon = 1e-6
+        );
+
+        let result = catalog
+            .nearest(RADec::new_degrees(0.0, 89.95), 1_f64.to_radians())
+            .unwrap();
+        assert_eq!(result.index, 1);
+        assert_abs_diff_eq!(result.separation, 0.1_f64.to_radians(), epsilon = 1e-8);
+
+        // Nothing within the radius.
+        assert!(catalog
+            .nearest(RADec::new_degrees(45.0, 0.0), 1_f64.to_radians())
+            .is_none());
+        assert!(catalog
+            .within(RADec::new_degrees(45.0, 0.0), 1_f64.to_radians())
+            .is_empty());
+        // Everything is within pi.
+        assert_eq!(catalog.within(RADec::new_degrees(45.0, 0.0), PI).len(), 3);
+    }
+
+    #[test]
+    fn test_empty() {
+        let catalog = Catalog::new(&[]);
+        assert!(catalog.is_empty());
+        assert!(catalog.nearest(RADec::new(0.0, 0.0), PI).is_none());
+        assert!(catalog.within(RADec::new(0.0, 0.0), PI).is_empty());
+    }
+}
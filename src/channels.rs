@@ -0,0 +1,257 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Convert between MWA receiver coarse channel numbers, sky frequencies and
+//! fine channel indices.
+//!
+//! The centre of the coarse channel with receiver channel number `n` is at
+//! `n` * [`MWA_COARSE_CHAN_WIDTH_HZ`], and the centre fine channel (see
+//! [`ChannelLayout::centre_fine_chan`]) is centred on the coarse channel
+//! centre. The 20 and 40 kHz modes of the legacy MWA correlator are offset
+//! from this by 5 and 15 kHz, respectively (see [`ChannelLayout::offset_hz`]).
+//! This matches the fine channel frequencies of mwalib.
+
+use crate::constants::MWA_COARSE_CHAN_WIDTH_HZ;
+
+/// The layout of fine channels within MWA coarse channels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelLayout {
+    /// The width of each fine channel \[Hz\].
+    pub fine_chan_width_hz: f64,
+
+    /// The number of fine channels in each coarse channel.
+    pub fine_chans_per_coarse: usize,
+
+    /// The offset of every fine channel centre from where it would otherwise
+    /// be \[Hz\]. This is 5 kHz for the 20 kHz mode and 15 kHz for the 40 kHz
+    /// mode of the legacy MWA correlator, and 0 otherwise.
+    pub offset_hz: f64,
+}
+
+impl ChannelLayout {
+    /// The layout of `fine_chans_per_coarse` fine channels of width
+    /// `fine_chan_width_hz` in each coarse channel, without an offset. The
+    /// fine channels don't need to fill the coarse channels.
+    pub fn new(fine_chan_width_hz: f64, fine_chans_per_coarse: usize) -> Self {
+        Self {
+            fine_chan_width_hz,
+            fine_chans_per_coarse,
+            offset_hz: 0.0,
+        }
+    }
+
+    /// The layout of fine channels of width `fine_chan_width_hz` that fill
+    /// each coarse channel.
+    pub fn from_fine_chan_width(fine_chan_width_hz: f64) -> Self {
+        let fine_chans_per_coarse = (MWA_COARSE_CHAN_WIDTH_HZ / fine_chan_width_hz.abs()).round();
+        Self::new(fine_chan_width_hz.abs(), fine_chans_per_coarse as usize)
+    }
+
+    /// The layout of the correlator fine channels of an observation,
+    /// including the offset of the legacy correlator's 20 and 40 kHz modes.
+    #[cfg(feature = "mwalib")]
+    pub fn from_mwalib(meta_ctx: &mwalib::MetafitsContext) -> Self {
+        use mwalib::MWAVersion;
+
+        let mut layout = Self::new(
+            meta_ctx.corr_fine_chan_width_hz as f64,
+            meta_ctx.num_corr_fine_chans_per_coarse,
+        );
+        if matches!(
+            meta_ctx.mwa_version,
+            Some(
+                MWAVersion::CorrLegacy
+                    | MWAVersion::CorrOldLegacy
+                    | MWAVersion::VCSLegacyRecombined
+            )
+        ) {
+            layout.offset_hz = match layout.fine_chans_per_coarse {
+                64 => 5e3,
+                32 => 15e3,
+                _ => 0.0,
+            };
+        }
+        layout
+    }
+
+    /// The centre frequency of the coarse channel with receiver channel
+    /// number `rec_chan` \[Hz\].
+    pub fn coarse_chan_centre_hz(rec_chan: usize) -> f64 {
+        rec_chan as f64 * MWA_COARSE_CHAN_WIDTH_HZ
+    }
+
+    /// The receiver channel number of the coarse channel containing
+    /// `freq_hz`.
+    pub fn rec_chan_at_freq(freq_hz: f64) -> usize {
+        (freq_hz / MWA_COARSE_CHAN_WIDTH_HZ).round() as usize
+    }
+
+    /// The offset of the centre of the fine channel at `fine_chan_idx` from
+    /// the centre of its coarse channel \[Hz\].
+    pub fn fine_chan_offset_hz(&self, fine_chan_idx: usize) -> f64 {
+        (fine_chan_idx as f64 - self.centre_fine_chan() as f64) * self.fine_chan_width_hz
+            + self.offset_hz
+    }
+
+    /// The centre frequency of the fine channel at `fine_chan_idx` of the
+    /// coarse channel with receiver channel number `rec_chan` \[Hz\].
+    pub fn fine_chan_freq_hz(&self, rec_chan: usize, fine_chan_idx: usize) -> f64 {
+        Self::coarse_chan_centre_hz(rec_chan) + self.fine_chan_offset_hz(fine_chan_idx)
+    }
+
+    /// The centre frequencies of all of the fine channels of the coarse
+    /// channels with receiver channel numbers `rec_chans` \[Hz\], e.g. to
+    /// describe the frequencies of a whole observation.
+    pub fn fine_chan_freqs_hz(&self, rec_chans: &[usize]) -> Vec<f64> {
+        rec_chans
+            .iter()
+            .flat_map(|&rec_chan| {
+                (0..self.fine_chans_per_coarse)
+                    .map(move |fine_chan_idx| self.fine_chan_freq_hz(rec_chan, fine_chan_idx))
+            })
+            .collect()
+    }
+
+    /// Find the receiver channel number and fine channel index of the fine
+    /// channel centred on `freq_hz`, or `None` if `freq_hz` isn't within 1% of
+    /// a fine channel width of a fine channel centre.
+    pub fn locate_freq(&self, freq_hz: f64) -> Option<(usize, usize)> {
+        if self.fine_chan_width_hz == 0.0 {
+            return None;
+        }
+        let rec_chan = Self::rec_chan_at_freq(freq_hz);
+        let offset = (freq_hz - self.fine_chan_freq_hz(rec_chan, 0)) / self.fine_chan_width_hz;
+        let fine_chan_idx = offset.round();
+        if (offset - fine_chan_idx).abs() > 0.01
+            || fine_chan_idx < 0.0
+            || fine_chan_idx as usize >= self.fine_chans_per_coarse
+        {
+            return None;
+        }
+        Some((rec_chan, fine_chan_idx as usize))
+    }
+
+    /// The index of the centre fine channel of each coarse channel, i.e. the
+    /// channel centred on the coarse channel centre (before the offset). This is the "DC" channel
+    /// that is usually flagged for the legacy MWA correlator.
+    pub fn centre_fine_chan(&self) -> usize {
+        self.fine_chans_per_coarse / 2
+    }
+
+    /// The indices of the fine channels within `edge_width_hz` of the edges of
+    /// each coarse channel, which are usually flagged because of the shape of
+    /// the polyphase filter bank. A fine channel is included if any of it is
+    /// within `edge_width_hz` of an edge.
+    pub fn edge_fine_chans(&self, edge_width_hz: f64) -> Vec<usize> {
+        let num_edge_chans = ((edge_width_hz / self.fine_chan_width_hz).ceil() as usize)
+            .min(self.fine_chans_per_coarse);
+        (0..self.fine_chans_per_coarse)
+            .filter(|&fine_chan_idx| {
+                fine_chan_idx < num_edge_chans
+                    || fine_chan_idx >= self.fine_chans_per_coarse - num_edge_chans
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_fine_chan_freqs_even() {
+        // 32 legacy MWA 40 kHz channels.
+        let layout = ChannelLayout::from_fine_chan_width(40e3);
+        assert_eq!(layout.fine_chans_per_coarse, 32);
+        assert_eq!(layout.centre_fine_chan(), 16);
+        assert_abs_diff_eq!(ChannelLayout::coarse_chan_centre_hz(143), 183.04e6);
+        // The centre fine channel is centred on the coarse channel centre.
+        assert_abs_diff_eq!(layout.fine_chan_freq_hz(143, 16), 183.04e6);
+        assert_abs_diff_eq!(layout.fine_chan_freq_hz(143, 0), 182.40e6);
+        assert_abs_diff_eq!(layout.fine_chan_freq_hz(143, 31), 183.64e6);
+
+        // The legacy correlator's 40 kHz mode is offset by 15 kHz.
+        let legacy = ChannelLayout {
+            offset_hz: 15e3,
+            ..layout
+        };
+        assert_abs_diff_eq!(legacy.fine_chan_freq_hz(143, 16), 183.055e6);
+        assert_eq!(legacy.locate_freq(183.055e6), Some((143, 16)));
+
+        let freqs = layout.fine_chan_freqs_hz(&[143, 144]);
+        assert_eq!(freqs.len(), 64);
+        // The fine channels of adjacent coarse channels are contiguous.
+        assert_abs_diff_eq!(freqs[32] - freqs[31], 40e3, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_fine_chan_freqs_odd() {
+        // 2 MWAX 640 kHz channels, and odd numbers of channels.
+        let layout = ChannelLayout::from_fine_chan_width(640e3);
+        assert_eq!(layout.fine_chans_per_coarse, 2);
+        assert_abs_diff_eq!(layout.fine_chan_freq_hz(121, 0), 154.24e6);
+        assert_abs_diff_eq!(layout.fine_chan_freq_hz(121, 1), 154.88e6);
+
+        let layout = ChannelLayout::new(1.28e6 / 3.0, 3);
+        assert_eq!(layout.centre_fine_chan(), 1);
+        // The centre fine channel is centred on the coarse channel centre.
+        assert_abs_diff_eq!(layout.fine_chan_freq_hz(100, 1), 128e6);
+        assert_abs_diff_eq!(layout.fine_chan_offset_hz(0), -1.28e6 / 3.0);
+    }
+
+    #[test]
+    fn test_locate_freq() {
+        let layout = ChannelLayout::from_fine_chan_width(10e3);
+        for (rec_chan, fine_chan_idx) in [(57, 0), (57, 64), (57, 127), (169, 3)] {
+            assert_eq!(
+                layout.locate_freq(layout.fine_chan_freq_hz(rec_chan, fine_chan_idx)),
+                Some((rec_chan, fine_chan_idx))
+            );
+        }
+        // Between fine channel centres.
+        assert_eq!(
+            layout.locate_freq(layout.fine_chan_freq_hz(57, 3) + 2.5e3),
+            None
+        );
+        assert_eq!(ChannelLayout::rec_chan_at_freq(183.04e6 + 0.6e6), 143);
+        assert_eq!(ChannelLayout::rec_chan_at_freq(183.04e6 - 0.6e6), 143);
+    }
+
+    #[test]
+    fn test_edge_fine_chans() {
+        let layout = ChannelLayout::from_fine_chan_width(40e3);
+        assert_eq!(layout.edge_fine_chans(80e3), vec![0, 1, 30, 31]);
+        // Partially covered channels are included.
+        assert_eq!(layout.edge_fine_chans(50e3), vec![0, 1, 30, 31]);
+        assert!(layout.edge_fine_chans(0.0).is_empty());
+        assert_eq!(layout.edge_fine_chans(1e9).len(), 32);
+    }
+
+    #[test]
+    #[cfg(feature = "mwalib")]
+    fn test_fine_chan_freqs_match_mwalib() {
+        for metafits in [
+            "tests/data/1254670392_avg/1254670392.metafits",
+            "tests/data/1297526432_mwax/1297526432.metafits",
+        ] {
+            let meta_ctx = mwalib::MetafitsContext::new(&metafits, None).unwrap();
+            let layout = ChannelLayout::from_mwalib(&meta_ctx);
+            let rec_chans: Vec<_> = meta_ctx
+                .metafits_coarse_chans
+                .iter()
+                .map(|c| c.rec_chan_number)
+                .collect();
+            let freqs = layout.fine_chan_freqs_hz(&rec_chans);
+            assert_eq!(freqs.len(), meta_ctx.metafits_fine_chan_freqs_hz.len());
+            for (freq, expected) in freqs
+                .iter()
+                .zip(meta_ctx.metafits_fine_chan_freqs_hz.iter())
+            {
+                assert_abs_diff_eq!(freq, expected, epsilon = 1e-3);
+            }
+        }
+    }
+}
@@ -7,12 +7,12 @@
 use std::f64::consts::PI;
 
 /// Speed of light \[metres/second\]
-pub const VEL_C: f64 = erfa_sys::ERFA_CMPS;
+pub const VEL_C: f64 = 299792458.0;
 
 /// Seconds per day (86400)
-pub const DAYSEC: f64 = erfa_sys::ERFA_DAYSEC;
+pub const DAYSEC: f64 = 86400.0;
 /// Seconds of time to radians (7.272205216643039903848712e-5).
-pub const DS2R: f64 = erfa_sys::ERFA_DS2R;
+pub const DS2R: f64 = 7.27220521664304e-5;
 /// Hour angle to radians (15 / 180 * PI).
 pub const DH2R: f64 = 15.0 / 180.0 * PI;
 /// Ratio of a solar day to a sidereal day (24/23.9344696 = 1.002737909).
@@ -29,6 +29,16 @@ pub const MWA_LONG_DEG: f64 = MWA_LONG_RAD * 180.0 / PI;
 /// MWA height (a.k.a. altitude) \[metres\]
 pub const MWA_HEIGHT_M: f64 = 377.827;
 
+/// The width of an MWA coarse channel \[Hz\]. The centre of a coarse channel is
+/// its receiver channel number multiplied by this width.
+pub const MWA_COARSE_CHAN_WIDTH_HZ: f64 = 1_280_000.0;
+/// The number of coarse channels received by the MWA in an observation.
+pub const MWA_NUM_COARSE_CHANS: usize = 24;
+
+/// The dish diameter written for antennas without one \[metres\]. This is what
+/// cotter writes for MWA tiles.
+pub const DEFAULT_DISH_DIAMETER_M: f64 = 4.0;
+
 /// The weight given to time when calculating a weight factor. When combined
 /// with [`FREQ_WEIGHT_FACTOR`], a visibility weight can be calculated.
 pub const TIME_WEIGHT_FACTOR: f64 = 1.0;
@@ -2,14 +2,19 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use std::ops::Range;
+
 use hifitime::{Duration, Epoch, TimeSeries};
-use ndarray::Array2;
+use ndarray::{Array2, Array3};
+use thiserror::Error;
 
-use crate::{LatLngHeight, RADec, XyzGeocentric, XyzGeodetic, ENH};
+use crate::{
+    channels::ChannelLayout, constants::DEFAULT_DISH_DIAMETER_M, errors::BadArrayShape, AzEl,
+    LatLngHeight, RADec, XyzGeocentric, XyzGeodetic, ENH,
+};
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "mwalib")] {
-        use std::ops::Range;
         use mwalib::{CorrelatorContext, MetafitsContext};
         use hifitime::Unit::Millisecond;
         use itertools::izip;
@@ -47,6 +52,12 @@ pub struct ObsContext {
     /// The Earth position of the instrumental array
     pub array_pos: LatLngHeight,
 
+    /// The name of the telescope (and array), e.g. "MWA".
+    pub telescope_name: String,
+
+    /// The name of the instrument, e.g. "MWA".
+    pub instrument: String,
+
     /// TODO: store in ENH or geodetic?
     /// The geodetic position of each antenna.
     // pub tiles_xyz_geod: Vec<XyzGeodetic>,
@@ -55,6 +66,15 @@ pub struct ObsContext {
 
     /// The name of each antenna / tile.
     pub ant_names: Vec<String>,
+
+    /// Whether each antenna / tile is flagged. Antennas without an entry are
+    /// unflagged, so this may be left empty.
+    pub ant_flags: Vec<bool>,
+
+    /// The simultaneous analogue beams of the observation, e.g. in MWAX
+    /// multi-beam modes. This is empty for single-beam observations, which
+    /// are described by `phase_centre` and `pointing_centre` alone.
+    pub beams: Vec<AnalogueBeam>,
 }
 
 // TODO: impl Default for ObsContext {}
@@ -72,6 +92,7 @@ impl ObsContext {
         let ants = &meta_ctx.antennas;
         let mut ant_positions_enh = Vec::<ENH>::with_capacity(ants.len());
         let mut ant_names = Vec::<String>::with_capacity(ants.len());
+        let mut ant_flags = Vec::<bool>::with_capacity(ants.len());
         for ant in ants {
             ant_positions_enh.push(ENH {
                 e: ant.east_m,
@@ -79,6 +100,7 @@ impl ObsContext {
                 h: ant.height_m,
             });
             ant_names.push(ant.tile_name.clone());
+            ant_flags.push(ant.rfinput_x.flagged || ant.rfinput_y.flagged);
         }
 
         Self {
@@ -93,11 +115,34 @@ impl ObsContext {
             phase_centre: RADec::from_mwalib_phase_or_pointing(meta_ctx),
             pointing_centre: Some(RADec::from_mwalib_tile_pointing(meta_ctx)),
             array_pos: LatLngHeight::new_mwa(),
+            telescope_name: "MWA".into(),
+            instrument: "MWA".into(),
             ant_positions_enh,
             ant_names,
+            ant_flags,
+            beams: vec![],
+        }
+    }
+
+    /// Get the context of a single beam of this observation, e.g. to write each
+    /// beam's data to a separate output. The phase centre, pointing centre and
+    /// field name are those of the beam, and like any single-beam observation,
+    /// `beams` is empty.
+    pub fn for_beam(&self, beam: &AnalogueBeam) -> ObsContext {
+        ObsContext {
+            field_name: beam.name.clone().or_else(|| self.field_name.clone()),
+            phase_centre: beam.phase_centre,
+            pointing_centre: beam.pointing_centre,
+            beams: vec![],
+            ..self.clone()
         }
     }
 
+    /// Find a beam of this observation by its identifier.
+    pub fn beam(&self, id: u32) -> Option<&AnalogueBeam> {
+        self.beams.iter().find(|beam| beam.id == id)
+    }
+
     pub fn ant_positions_geodetic(&self) -> impl Iterator<Item = XyzGeodetic> + '_ {
         self.ant_positions_enh
             .iter()
@@ -115,6 +160,183 @@ impl ObsContext {
     pub fn num_ants(&self) -> usize {
         self.ant_positions_enh.len()
     }
+
+    /// Whether the antenna at `ant_idx` is flagged.
+    pub fn is_ant_flagged(&self, ant_idx: usize) -> bool {
+        self.ant_flags.get(ant_idx).copied().unwrap_or(false)
+    }
+
+    /// The indices of the flagged antennas.
+    pub fn flagged_ants(&self) -> Vec<usize> {
+        (0..self.num_ants())
+            .filter(|&ant_idx| self.is_ant_flagged(ant_idx))
+            .collect()
+    }
+
+    /// Describe the telescope of this observation, e.g. for
+    /// [`crate::UvfitsWriter::from_marlu`].
+    pub fn telescope(&self) -> Telescope {
+        Telescope {
+            name: self.telescope_name.clone(),
+            instrument: self.instrument.clone(),
+            array_pos: self.array_pos,
+            ant_names: self.ant_names.clone(),
+            ant_positions: self.ant_positions_geodetic().collect(),
+            ant_mounts: vec![],
+            ant_dish_diameters_m: vec![],
+            ant_axis_offsets_m: vec![],
+        }
+    }
+}
+
+/// The mount of an antenna, written as `MOUNT` in the measurement set
+/// `ANTENNA` table, and `MNTSTA` in the uvfits `AIPS AN` table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MountType {
+    /// An altitude-azimuth mount. MWA tiles are written as alt-az, like
+    /// cotter.
+    AltAz,
+    /// An equatorial mount, with one axis parallel to the Earth's rotation
+    /// axis.
+    Equatorial,
+    /// An antenna in orbit, e.g. for space VLBI.
+    Orbiting,
+    /// An X-Y mount, with a horizontal axis carrying a perpendicular one.
+    XY,
+    /// An alt-az mount with the receiver at a right-hand Nasmyth focus.
+    NasmythRight,
+    /// An alt-az mount with the receiver at a left-hand Nasmyth focus.
+    NasmythLeft,
+}
+
+impl Default for MountType {
+    fn default() -> Self {
+        Self::AltAz
+    }
+}
+
+impl MountType {
+    /// The measurement set `MOUNT` of this mount type.
+    pub fn ms_mount(self) -> &'static str {
+        match self {
+            Self::AltAz => "ALT-AZ",
+            Self::Equatorial => "EQUATORIAL",
+            Self::Orbiting => "ORBITING",
+            Self::XY => "X-Y",
+            Self::NasmythRight => "ALT-AZ+NASMYTH-R",
+            Self::NasmythLeft => "ALT-AZ+NASMYTH-L",
+        }
+    }
+
+    /// The AIPS `MNTSTA` code of this mount type.
+    pub fn uvfits_mntsta(self) -> i32 {
+        match self {
+            Self::AltAz => 0,
+            Self::Equatorial => 1,
+            Self::Orbiting => 2,
+            Self::XY => 3,
+            Self::NasmythRight => 4,
+            Self::NasmythLeft => 5,
+        }
+    }
+}
+
+/// Describes the telescope that made an observation, so that the writers can
+/// fill in headers (e.g. the `TELESCOP`, `INSTRUME` and `ARRNAM` keys of
+/// uvfits files) for arrays other than the MWA.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Telescope {
+    /// The name of the telescope, which is also used as the name of the
+    /// array, e.g. "MWA".
+    pub name: String,
+
+    /// The name of the instrument, e.g. "MWA".
+    pub instrument: String,
+
+    /// The Earth position of the array.
+    pub array_pos: LatLngHeight,
+
+    /// The name of each antenna / tile.
+    pub ant_names: Vec<String>,
+
+    /// The *unprecessed* geodetic position of each antenna.
+    pub ant_positions: Vec<XyzGeodetic>,
+
+    /// The mount of each antenna. Antennas without a mount (e.g. if this is
+    /// empty) have the default [`MountType::AltAz`].
+    pub ant_mounts: Vec<MountType>,
+
+    /// The dish diameter of each antenna \[metres\]. Antennas without a
+    /// diameter have [`DEFAULT_DISH_DIAMETER_M`].
+    pub ant_dish_diameters_m: Vec<f64>,
+
+    /// The offset between the axes of each antenna's mount \[metres\], written
+    /// as `STAXOF` in uvfits files and the first element of `OFFSET` in
+    /// measurement sets. Antennas without an offset have none.
+    pub ant_axis_offsets_m: Vec<f64>,
+}
+
+impl Telescope {
+    /// A telescope whose instrument has the same name as the telescope.
+    pub fn new(
+        name: &str,
+        array_pos: LatLngHeight,
+        ant_names: Vec<String>,
+        ant_positions: Vec<XyzGeodetic>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            instrument: name.to_string(),
+            array_pos,
+            ant_names,
+            ant_positions,
+            ant_mounts: vec![],
+            ant_dish_diameters_m: vec![],
+            ant_axis_offsets_m: vec![],
+        }
+    }
+
+    /// The MWA, at the position of [`LatLngHeight::new_mwa`].
+    pub fn mwa(ant_names: Vec<String>, ant_positions: Vec<XyzGeodetic>) -> Self {
+        Self::new("MWA", LatLngHeight::new_mwa(), ant_names, ant_positions)
+    }
+
+    /// The mount of the antenna at `ant_idx`.
+    pub fn ant_mount(&self, ant_idx: usize) -> MountType {
+        self.ant_mounts.get(ant_idx).copied().unwrap_or_default()
+    }
+
+    /// The dish diameter of the antenna at `ant_idx` \[metres\].
+    pub fn ant_dish_diameter_m(&self, ant_idx: usize) -> f64 {
+        self.ant_dish_diameters_m
+            .get(ant_idx)
+            .copied()
+            .unwrap_or(DEFAULT_DISH_DIAMETER_M)
+    }
+
+    /// The axis offset of the antenna at `ant_idx` \[metres\].
+    pub fn ant_axis_offset_m(&self, ant_idx: usize) -> f64 {
+        self.ant_axis_offsets_m.get(ant_idx).copied().unwrap_or(0.0)
+    }
+}
+
+/// One of the simultaneous analogue beams of an observation. Each stream of
+/// visibilities from a multi-beam observation is identified by its beam's
+/// `id`. Not to be confused with [`crate::beam::Beam`], which models the
+/// response of a tile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalogueBeam {
+    /// The identifier of the beam, unique within an observation.
+    pub id: u32,
+
+    /// The name of the beam's field, if different to the observation's.
+    pub name: Option<String>,
+
+    /// The phase centre of the beam.
+    pub phase_centre: RADec,
+
+    /// The pointing centre of the beam.
+    pub pointing_centre: Option<RADec>,
 }
 
 /// A container for metadata about how a visibility file was created.
@@ -142,6 +364,80 @@ impl<'a> History<'a> {
     }
 }
 
+/// A structured record of how a visibility file was created: the application
+/// and its command line, the versions of the software used, and a summary of
+/// the parameters. This is written to the measurement set `HISTORY` table
+/// with `MeasurementSetWriter::write_provenance`, or as uvfits `HISTORY`
+/// cards with `UvfitsWriter::write_provenance`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    /// The application (and version) used to create the file
+    pub application: String,
+
+    /// The command line used to create the file
+    pub cmd_line: Option<String>,
+
+    /// The name and version of each piece of software used
+    pub software_versions: Vec<(String, String)>,
+
+    /// The name and value of each parameter, e.g. averaging factors
+    pub parameters: Vec<(String, String)>,
+}
+
+impl Provenance {
+    /// Create a [`Provenance`] for an application, with Marlu's version as the
+    /// first software version.
+    pub fn new(application: &str) -> Self {
+        Self {
+            application: application.to_string(),
+            cmd_line: None,
+            software_versions: vec![(
+                env!("CARGO_PKG_NAME").to_string(),
+                env!("CARGO_PKG_VERSION").to_string(),
+            )],
+            parameters: vec![],
+        }
+    }
+
+    /// The software versions as a single human readable message, e.g.
+    /// "marlu 0.8.0, mwalib 0.15.0".
+    pub fn software_message(&self) -> String {
+        self.software_versions
+            .iter()
+            .map(|(name, version)| format!("{} {}", name, version))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The parameters, formatted as "name=value".
+    pub fn parameter_strings(&self) -> Vec<String> {
+        self.parameters
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect()
+    }
+
+    /// Format the provenance as uvfits `HISTORY` cards, one per line, with a
+    /// key prefix so that the fields can be recovered.
+    pub fn as_history_cards(&self) -> Vec<String> {
+        let mut cards = vec![format!("APPLICATION: {}", self.application)];
+        if let Some(cmd_line) = &self.cmd_line {
+            cards.push(format!("CMDLINE: {}", cmd_line));
+        }
+        cards.extend(
+            self.software_versions
+                .iter()
+                .map(|(name, version)| format!("SOFTWARE: {} {}", name, version)),
+        );
+        cards.extend(
+            self.parameter_strings()
+                .into_iter()
+                .map(|param| format!("PARAM: {}", param)),
+        );
+        cards
+    }
+}
+
 /// An extension of [`ObsContext`] that for MWA-specific metadata that is not
 /// present in some file types like uvfits.
 pub struct MwaObsContext {
@@ -171,6 +467,20 @@ pub struct MwaObsContext {
 
     /// Tile pointing delays
     pub delays: Vec<u32>,
+
+    /// Antenna dipole delays. `[ant_idx][pol][dipole]`
+    pub ant_dipole_delays: Array3<u32>,
+
+    /// Antenna dipole gains, which are 0 for dead dipoles. `[ant_idx][pol][dipole]`
+    pub ant_dipole_gains: Array3<f64>,
+
+    /// Antenna digital gains for each metafits coarse channel.
+    /// `[ant_idx][coarse_chan_idx][pol]`
+    pub ant_digital_gains: Array3<f64>,
+
+    /// The states that the instrument was in over the course of the
+    /// observation, in chronological order.
+    pub instrument_states: Vec<InstrumentState>,
 }
 
 // TODO: impl Default for MwaObsContext {}
@@ -194,6 +504,34 @@ impl MwaObsContext {
             has_calibrator: meta_ctx.calibrator,
             mode: meta_ctx.mode.to_string(),
             delays: meta_ctx.delays.clone(),
+            ant_dipole_delays: Array3::from_shape_fn((ants.len(), 2, 16), |(a, p, d)| {
+                let rf = if p == 0 {
+                    &ants[a].rfinput_x
+                } else {
+                    &ants[a].rfinput_y
+                };
+                rf.dipole_delays[d]
+            }),
+            ant_dipole_gains: Array3::from_shape_fn((ants.len(), 2, 16), |(a, p, d)| {
+                let rf = if p == 0 {
+                    &ants[a].rfinput_x
+                } else {
+                    &ants[a].rfinput_y
+                };
+                rf.dipole_gains[d]
+            }),
+            ant_digital_gains: Array3::from_shape_fn(
+                (ants.len(), meta_ctx.metafits_coarse_chans.len(), 2),
+                |(a, c, p)| {
+                    let rf = if p == 0 {
+                        &ants[a].rfinput_x
+                    } else {
+                        &ants[a].rfinput_y
+                    };
+                    rf.digital_gains[c]
+                },
+            ),
+            instrument_states: InstrumentState::from_mwalib(meta_ctx),
         };
 
         for (ant, mut input, number, receiver, mut slot, mut length) in izip!(
@@ -220,6 +558,109 @@ impl MwaObsContext {
 
         result
     }
+
+    /// The instrument states that overlap with the time range of the
+    /// selection described by the [`VisContext`].
+    pub fn instrument_states_in_selection(&self, vis_ctx: &VisContext) -> Vec<&InstrumentState> {
+        let sel_start = vis_ctx.start_timestamp;
+        let sel_end = sel_start + vis_ctx.int_time * (vis_ctx.num_sel_timesteps as i64);
+        self.instrument_states
+            .iter()
+            .enumerate()
+            .filter(|&(idx, state)| {
+                let state_end = self
+                    .instrument_states
+                    .get(idx + 1)
+                    .map(|next| next.start_timestamp);
+                state.start_timestamp < sel_end && state_end.map_or(true, |end| end > sel_start)
+            })
+            .map(|(_, state)| state)
+            .collect()
+    }
+}
+
+/// The state of the instrument from a point in time until the next state
+/// change, e.g. the tile beamformers settling after slewing to a new pointing.
+///
+/// These are recorded in the history of visibility files so that data products
+/// record what the instrument was doing during the selection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstrumentState {
+    /// When this state came into effect
+    pub start_timestamp: Epoch,
+
+    /// The tile pointing centre
+    pub pointing_centre: RADec,
+
+    /// The tile pointing in horizon coordinates
+    pub pointing_azel: AzEl,
+
+    /// Tile pointing delays
+    pub delays: Vec<u32>,
+
+    /// The global analogue attenuation \[dB\]
+    pub attenuation_db: f64,
+
+    /// Whether the beamformers have settled on the pointing. Data taken while
+    /// unsettled is typically quacked.
+    pub settled: bool,
+}
+
+impl InstrumentState {
+    /// Determine the instrument states from an [`mwalib::MetafitsContext`].
+    ///
+    /// MWA observations have a single pointing, so there is a state from the
+    /// scheduled start time while the beamformers settle, and another from the
+    /// "good time" onwards.
+    #[cfg(feature = "mwalib")]
+    pub fn from_mwalib(meta_ctx: &MetafitsContext) -> Vec<InstrumentState> {
+        let slewing = InstrumentState {
+            start_timestamp: Epoch::from_gpst_seconds(
+                meta_ctx.sched_start_gps_time_ms as f64 / 1e3,
+            ),
+            pointing_centre: RADec::from_mwalib_tile_pointing(meta_ctx),
+            pointing_azel: AzEl::new_degrees(meta_ctx.az_deg, meta_ctx.alt_deg),
+            delays: meta_ctx.delays.clone(),
+            attenuation_db: meta_ctx.global_analogue_attenuation_db,
+            settled: false,
+        };
+        if meta_ctx.good_time_gps_ms <= meta_ctx.sched_start_gps_time_ms {
+            return vec![InstrumentState {
+                settled: true,
+                ..slewing
+            }];
+        }
+        let settled = InstrumentState {
+            start_timestamp: Epoch::from_gpst_seconds(meta_ctx.good_time_gps_ms as f64 / 1e3),
+            settled: true,
+            ..slewing.clone()
+        };
+        vec![slewing, settled]
+    }
+
+    /// Describe this state as a single line suitable for a history entry.
+    pub fn as_comment(&self) -> String {
+        format!(
+            "Instrument state from GPS {:.3}s: pointing {}, az/el {}, delays {:?}, attenuation {} dB, {}",
+            self.start_timestamp.as_gpst_seconds(),
+            self.pointing_centre,
+            self.pointing_azel,
+            self.delays,
+            self.attenuation_db,
+            if self.settled { "settled" } else { "settling" }
+        )
+    }
+}
+
+/// Where a chunk is within a [`VisContext`]. See [`VisContext::locate_chunk`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkLocation {
+    /// The indices of the timesteps of the chunk.
+    pub timesteps: Range<usize>,
+    /// The indices of the channels of the chunk.
+    pub chans: Range<usize>,
+    /// The indices into `sel_baselines` of each baseline of the chunk.
+    pub baseline_idxs: Vec<usize>,
 }
 
 /// A lightweight container for correlator visibility metadata used in Marlu operations.
@@ -233,6 +674,7 @@ impl MwaObsContext {
 ///
 /// A `VisContext` is oblivious to mwalib concepts like coarse channels.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VisContext {
     /// The number of selected timesteps (Axis 0) in the accompanying visibility and weight ndarrays.
     pub num_sel_timesteps: usize,
@@ -254,6 +696,85 @@ pub struct VisContext {
     pub avg_freq: usize,
     /// Number of polarisation combinations in the visibilities e.g. XX,XY,YX,YY == 4
     pub num_vis_pols: usize,
+    /// What averaging does with bins in which every visibility is flagged
+    pub averaging_policy: AveragingPolicy,
+    /// The units of the visibilities, after they have been multiplied by
+    /// `vis_scale`.
+    pub vis_unit: VisUnit,
+    /// The factor that writers multiply visibilities by as they are written,
+    /// e.g. to convert correlator units into `vis_unit`. Weights are not
+    /// scaled.
+    pub vis_scale: f32,
+}
+
+/// The units of visibilities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VisUnit {
+    /// Uncalibrated correlator output. This is the default.
+    Uncalibrated,
+    /// Janskys.
+    Jansky,
+}
+
+impl Default for VisUnit {
+    fn default() -> Self {
+        Self::Uncalibrated
+    }
+}
+
+impl VisUnit {
+    /// The value of the FITS `BUNIT` key for these units.
+    pub fn fits_unit(self) -> &'static str {
+        match self {
+            Self::Uncalibrated => "UNCALIB",
+            Self::Jansky => "JY",
+        }
+    }
+
+    /// The value of the measurement set `UNIT` column keyword for these
+    /// units.
+    pub fn ms_unit(self) -> &'static str {
+        match self {
+            Self::Uncalibrated => "UNCALIB",
+            Self::Jansky => "Jy",
+        }
+    }
+}
+
+impl std::fmt::Display for VisUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Uncalibrated => write!(f, "uncalibrated"),
+            Self::Jansky => write!(f, "Jy"),
+        }
+    }
+}
+
+/// What averaging does with a bin of visibilities in which every visibility is
+/// flagged. Either way, the averaged visibility is flagged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AveragingPolicy {
+    /// Use the unweighted mean of the bin, like Cotter. This is the default.
+    UnweightedMean,
+    /// Use NaN, so that flagged visibilities can't be mistaken for data.
+    Nan,
+}
+
+impl Default for AveragingPolicy {
+    fn default() -> Self {
+        Self::UnweightedMean
+    }
+}
+
+impl std::fmt::Display for AveragingPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnweightedMean => write!(f, "unweighted mean"),
+            Self::Nan => write!(f, "NaN"),
+        }
+    }
 }
 
 // TODO: impl Default for VisContext {}
@@ -308,6 +829,9 @@ impl VisContext {
             avg_time,
             avg_freq,
             num_vis_pols,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
         }
     }
 
@@ -329,6 +853,17 @@ impl VisContext {
         )
     }
 
+    /// The number of antennas needed to describe the selected baselines, i.e.
+    /// one more than the largest antenna index, or 0 if there are no
+    /// baselines.
+    pub fn num_ants(&self) -> usize {
+        self.sel_baselines
+            .iter()
+            .map(|&(ant1, ant2)| ant1.max(ant2) + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
     /// Whether this context corresponds with the the case where the time and
     /// frequency averaging factors are both 1, and no averaging is performed.
     pub fn trivial_averaging(&self) -> bool {
@@ -367,7 +902,53 @@ impl VisContext {
         self.freq_resolution_hz * self.avg_freq as f64
     }
 
-    /// An iterator over all selected frequencies
+    /// Whether the frequency axis is descending, i.e. `freq_resolution_hz` is
+    /// negative. This is the case for e.g. lower-sideband data.
+    pub fn is_descending(&self) -> bool {
+        self.freq_resolution_hz < 0.0
+    }
+
+    /// Get a context with an ascending frequency axis describing the same
+    /// channels as this one. The accompanying arrays need to be flipped along
+    /// the channel axis to match, e.g. with `invert_axis(Axis(1))` on a view
+    /// (see [`crate::VisWrite::write_vis_ascending`] and
+    /// [`crate::VisRead::read_vis_ascending`]).
+    ///
+    /// Note that when the number of selected channels is not a multiple of the
+    /// frequency averaging factor, the averaged channels of the flipped context
+    /// are grouped differently.
+    pub fn to_ascending(&self) -> VisContext {
+        if !self.is_descending() {
+            return self.clone();
+        }
+        VisContext {
+            start_freq_hz: self.start_freq_hz
+                + (self.num_sel_chans.max(1) - 1) as f64 * self.freq_resolution_hz,
+            freq_resolution_hz: -self.freq_resolution_hz,
+            ..self.clone()
+        }
+    }
+
+    /// Get a context with a descending frequency axis describing the same
+    /// channels as this one. This is the inverse of
+    /// [`VisContext::to_ascending`].
+    pub fn to_descending(&self) -> VisContext {
+        if self.is_descending() {
+            return self.clone();
+        }
+        VisContext {
+            start_freq_hz: self.start_freq_hz
+                + (self.num_sel_chans.max(1) - 1) as f64 * self.freq_resolution_hz,
+            freq_resolution_hz: -self.freq_resolution_hz,
+            ..self.clone()
+        }
+    }
+
+    /// The centre frequency of each selected channel \[Hz\].
+    ///
+    /// All frequencies in a [`VisContext`] refer to channel centres unless
+    /// stated otherwise; see [`VisContext::chan_edges_hz`] for the channel
+    /// edges.
     ///
     /// TODO: iterator return type?
     pub fn frequencies_hz(&self) -> Vec<f64> {
@@ -376,7 +957,30 @@ impl VisContext {
             .collect()
     }
 
-    /// An iterator over averaged frequencies
+    /// The layout of the selected channels within MWA coarse channels,
+    /// assuming that the channels fill each coarse channel.
+    pub fn channel_layout(&self) -> ChannelLayout {
+        ChannelLayout::from_fine_chan_width(self.freq_resolution_hz)
+    }
+
+    /// The receiver channel numbers of the MWA coarse channels containing the
+    /// selected channels, in the order that they're first encountered.
+    pub fn rec_chans(&self) -> Vec<usize> {
+        let mut rec_chans: Vec<usize> = vec![];
+        for freq_hz in self.frequencies_hz() {
+            let rec_chan = ChannelLayout::rec_chan_at_freq(freq_hz);
+            if !rec_chans.contains(&rec_chan) {
+                rec_chans.push(rec_chan);
+            }
+        }
+        rec_chans
+    }
+
+    /// The centre frequency of each averaged channel \[Hz\], i.e. the mean of
+    /// the centres of the channels being averaged together. This is also the
+    /// midpoint of the averaged channel's edges (see
+    /// [`VisContext::avg_chan_edges_hz`]), even when the last averaged channel
+    /// has fewer channels than the others.
     ///
     /// TODO: iterator return type? Doesn't seem to work for chunks
     pub fn avg_frequencies_hz(&self) -> Vec<f64> {
@@ -386,52 +990,991 @@ impl VisContext {
             .collect()
     }
 
-    /// Get the weight factor: a measure of the resolution relative to the base
-    /// resolution of the legacy MWA correlator (1s / 10kHz).
+    /// The edges of each selected channel \[Hz\]. There is one more edge than
+    /// there are channels; channel `i` spans `edges[i]` to `edges[i + 1]`, and
+    /// its centre is halfway between them.
+    pub fn chan_edges_hz(&self) -> Vec<f64> {
+        (0..=self.num_sel_chans)
+            .map(|i| self.start_freq_hz + (i as f64 - 0.5) * self.freq_resolution_hz)
+            .collect()
+    }
+
+    /// The edges of each averaged channel \[Hz\]. There is one more edge than
+    /// there are averaged channels. The last averaged channel may be narrower
+    /// than the others if the number of selected channels is not a multiple of
+    /// the frequency averaging factor.
     ///
-    /// This is a concept from Cotter, and the legacy MWA correlator where the
-    /// value is a multiple of the frequency resolution (relative to 10kHz), and
-    /// the time averaging factor (relative to 1s).
-    pub fn weight_factor(&self) -> f64 {
-        self.int_time.in_seconds() / crate::constants::TIME_WEIGHT_FACTOR * self.freq_resolution_hz
-            / crate::constants::FREQ_WEIGHT_FACTOR
+    /// # Panics
+    ///
+    /// Panics if `avg_freq` is 0, which [`VisContext::validate`] rejects.
+    pub fn avg_chan_edges_hz(&self) -> Vec<f64> {
+        assert!(
+            self.avg_freq > 0,
+            "the frequency averaging factor of a VisContext must not be 0"
+        );
+        let edges = self.chan_edges_hz();
+        let mut avg_edges: Vec<f64> = edges.iter().step_by(self.avg_freq).copied().collect();
+        if self.num_sel_chans % self.avg_freq != 0 {
+            avg_edges.extend(edges.last());
+        }
+        avg_edges
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use hifitime::Unit;
+    /// The centre frequency of the averaged band \[Hz\]. This is the centre of
+    /// the middle averaged channel if there is an odd number of averaged
+    /// channels, otherwise the mean of the two middle channels' centres.
+    ///
+    /// This is the reference frequency of a measurement set's spectral window.
+    /// uvfits files are instead referenced to the centre of the averaged
+    /// channel at index `len / 2` of [`VisContext::avg_frequencies_hz`]
+    /// (`CRVAL4` at `CRPIX4`), which is the same frequency only when there is
+    /// an odd number of averaged channels.
+    pub fn avg_centre_freq_hz(&self) -> f64 {
+        let freqs = self.avg_frequencies_hz();
+        let len = freqs.len();
+        if len % 2 == 0 {
+            (freqs[len / 2] + freqs[len / 2 - 1]) * 0.5
+        } else {
+            freqs[len / 2]
+        }
+    }
 
-    use crate::constants::VEL_C;
+    /// The indices into `sel_baselines` of the auto-correlations.
+    pub fn auto_baseline_idxs(&self) -> Vec<usize> {
+        self.sel_baselines
+            .iter()
+            .enumerate()
+            .filter(|(_, (ant1, ant2))| ant1 == ant2)
+            .map(|(idx, _)| idx)
+            .collect()
+    }
 
-    use super::*;
-    #[test]
-    // TODO: these collects are needed because of https://github.com/nyx-space/hifitime/issues/131
-    #[allow(clippy::needless_collect)]
-    fn vis_ctx_timeseries_length() {
-        let start_timestamp = Epoch::from_gpst_seconds(1090008640.);
-        let int_time = Duration::from_f64(1., Unit::Second);
-        let mut vis_ctx = VisContext {
-            num_sel_timesteps: 1,
-            start_timestamp,
-            int_time,
-            num_sel_chans: 1,
-            start_freq_hz: VEL_C,
-            freq_resolution_hz: 10_000.,
-            sel_baselines: vec![(0, 1), (0, 2)],
-            avg_time: 2,
-            avg_freq: 1,
-            num_vis_pols: 4,
+    /// The indices into `sel_baselines` of the cross-correlations.
+    pub fn cross_baseline_idxs(&self) -> Vec<usize> {
+        self.sel_baselines
+            .iter()
+            .enumerate()
+            .filter(|(_, (ant1, ant2))| ant1 != ant2)
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Split this context into one with only the auto-correlations, and one
+    /// with only the cross-correlations. The corresponding visibilities can be
+    /// selected with [`VisContext::auto_baseline_idxs`] and
+    /// [`VisContext::cross_baseline_idxs`], e.g.
+    /// `jones_array.select(Axis(2), &vis_ctx.auto_baseline_idxs())`.
+    ///
+    /// Both writers give auto-correlations a UVW of exactly 0, so the autos can
+    /// be written on their own.
+    pub fn split_autos(&self) -> (VisContext, VisContext) {
+        let (autos, crosses) = self
+            .sel_baselines
+            .iter()
+            .partition(|(ant1, ant2)| ant1 == ant2);
+        (
+            VisContext {
+                sel_baselines: autos,
+                ..self.clone()
+            },
+            VisContext {
+                sel_baselines: crosses,
+                ..self.clone()
+            },
+        )
+    }
+
+    /// Split this context into at most `n` contiguous chunks along the time
+    /// axis. Each chunk has the correct start timestamp and, except for the
+    /// last, covers a whole number of averaged timesteps, so that the
+    /// averaged timesteps of all chunks are the same as those of `self`.
+    ///
+    /// Fewer than `n` chunks are returned if there are not enough averaged
+    /// timesteps. An `n` of 0 is treated as 1.
+    pub fn chunk_time(&self, n: usize) -> Vec<VisContext> {
+        chunk_axis(self.num_sel_timesteps, self.avg_time, n)
+            .into_iter()
+            .map(|(offset, len)| VisContext {
+                num_sel_timesteps: len,
+                start_timestamp: self.start_timestamp + self.int_time * (offset as i64),
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    /// Split this context into at most `n` contiguous chunks along the
+    /// frequency axis. Each chunk has the correct start frequency and, except
+    /// for the last, covers a whole number of averaged channels, so that the
+    /// averaged channels of all chunks are the same as those of `self`.
+    ///
+    /// Fewer than `n` chunks are returned if there are not enough averaged
+    /// channels. An `n` of 0 is treated as 1.
+    pub fn chunk_freq(&self, n: usize) -> Vec<VisContext> {
+        chunk_axis(self.num_sel_chans, self.avg_freq, n)
+            .into_iter()
+            .map(|(offset, len)| VisContext {
+                num_sel_chans: len,
+                start_freq_hz: self.start_freq_hz + offset as f64 * self.freq_resolution_hz,
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    /// Split this context into sub-bands of `chans_per_band` channels along
+    /// the frequency axis, e.g. coarse channels, with the last sub-band
+    /// holding any remaining channels. `chans_per_band` is rounded up to a
+    /// whole number of averaged channels, so that no averaged channel is
+    /// split between sub-bands.
+    pub fn split_freq(&self, chans_per_band: usize) -> Vec<VisContext> {
+        let avg_freq = self.avg_freq.max(1);
+        let band_len =
+            (chans_per_band as f64 / avg_freq as f64).ceil().max(1.0) as usize * avg_freq;
+        (0..self.num_sel_chans)
+            .step_by(band_len)
+            .map(|offset| VisContext {
+                num_sel_chans: band_len.min(self.num_sel_chans - offset),
+                start_freq_hz: self.start_freq_hz + offset as f64 * self.freq_resolution_hz,
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    /// Locate `chunk` within this context, e.g. a chunk from
+    /// [`VisContext::chunk_time`] or [`VisContext::chunk_freq`] with any subset
+    /// of the baselines. This is how [`crate::io::VisRead`] implementors find
+    /// the visibilities of a chunk.
+    ///
+    /// `None` is returned if the chunk has a different time or frequency
+    /// resolution, isn't aligned to the timesteps and channels of this
+    /// context, or isn't entirely within it.
+    pub fn locate_chunk(&self, chunk: &VisContext) -> Option<ChunkLocation> {
+        // Offsets must be within 1% of a whole number of timesteps or channels.
+        fn whole_offset(offset: f64, len: usize, chunk_len: usize) -> Option<Range<usize>> {
+            let rounded = offset.round();
+            if (offset - rounded).abs() > 0.01 || rounded < 0.0 {
+                return None;
+            }
+            let start = rounded as usize;
+            (start + chunk_len <= len).then(|| start..start + chunk_len)
+        }
+
+        if chunk.int_time != self.int_time
+            || (chunk.freq_resolution_hz - self.freq_resolution_hz).abs()
+                > f64::EPSILON * self.freq_resolution_hz.abs()
+        {
+            return None;
+        }
+
+        let time_offset = chunk.start_timestamp - self.start_timestamp;
+        let timesteps = if time_offset.total_nanoseconds() == 0 {
+            whole_offset(0.0, self.num_sel_timesteps, chunk.num_sel_timesteps)?
+        } else if self.int_time.total_nanoseconds() == 0 {
+            return None;
+        } else {
+            whole_offset(
+                time_offset.in_seconds() / self.int_time.in_seconds(),
+                self.num_sel_timesteps,
+                chunk.num_sel_timesteps,
+            )?
         };
-        vis_ctx.num_sel_timesteps = 3;
-        let times: Vec<_> = vis_ctx.timeseries(false, false).collect();
-        assert_eq!(times.len(), 3);
-        let times: Vec<_> = vis_ctx.timeseries(true, false).collect();
-        assert_eq!(times.len(), 2);
-        let times: Vec<_> = vis_ctx.timeseries(false, true).collect();
-        assert_eq!(times.len(), 3);
-        let times: Vec<_> = vis_ctx.timeseries(true, true).collect();
-        assert_eq!(times.len(), 2);
+
+        let freq_offset = chunk.start_freq_hz - self.start_freq_hz;
+        let chans = if freq_offset == 0.0 {
+            whole_offset(0.0, self.num_sel_chans, chunk.num_sel_chans)?
+        } else if self.freq_resolution_hz == 0.0 {
+            return None;
+        } else {
+            whole_offset(
+                freq_offset / self.freq_resolution_hz,
+                self.num_sel_chans,
+                chunk.num_sel_chans,
+            )?
+        };
+
+        let baseline_idxs = chunk
+            .sel_baselines
+            .iter()
+            .map(|baseline| self.sel_baselines.iter().position(|bl| bl == baseline))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(ChunkLocation {
+            timesteps,
+            chans,
+            baseline_idxs,
+        })
+    }
+
+    /// Describe the differences between this context and another, e.g. to
+    /// explain why chunks can't be concatenated. Frequencies are compared
+    /// with a relative tolerance of [`f64::EPSILON`].
+    pub fn diff(&self, other: &VisContext) -> ContextDiff {
+        fn freqs_differ(left: f64, right: f64) -> bool {
+            (left - right).abs() > f64::EPSILON * left.abs().max(right.abs())
+        }
+
+        let mut differences = vec![];
+        if self.num_sel_timesteps != other.num_sel_timesteps {
+            differences.push(ContextDifference::NumSelTimesteps {
+                left: self.num_sel_timesteps,
+                right: other.num_sel_timesteps,
+            });
+        }
+        if self.start_timestamp != other.start_timestamp {
+            differences.push(ContextDifference::StartTimestamp {
+                left: self.start_timestamp,
+                right: other.start_timestamp,
+            });
+        }
+        if self.int_time != other.int_time {
+            differences.push(ContextDifference::IntTime {
+                left: self.int_time,
+                right: other.int_time,
+            });
+        }
+        if self.num_sel_chans != other.num_sel_chans {
+            differences.push(ContextDifference::NumSelChans {
+                left: self.num_sel_chans,
+                right: other.num_sel_chans,
+            });
+        }
+        if freqs_differ(self.start_freq_hz, other.start_freq_hz) {
+            differences.push(ContextDifference::StartFreq {
+                left: self.start_freq_hz,
+                right: other.start_freq_hz,
+            });
+        }
+        if freqs_differ(self.freq_resolution_hz, other.freq_resolution_hz) {
+            differences.push(ContextDifference::FreqResolution {
+                left: self.freq_resolution_hz,
+                right: other.freq_resolution_hz,
+            });
+        }
+        if self.sel_baselines != other.sel_baselines {
+            let only_left: Vec<_> = self
+                .sel_baselines
+                .iter()
+                .filter(|bl| !other.sel_baselines.contains(bl))
+                .copied()
+                .collect();
+            let only_right: Vec<_> = other
+                .sel_baselines
+                .iter()
+                .filter(|bl| !self.sel_baselines.contains(bl))
+                .copied()
+                .collect();
+            let common_left = self
+                .sel_baselines
+                .iter()
+                .filter(|bl| other.sel_baselines.contains(bl));
+            let common_right = other
+                .sel_baselines
+                .iter()
+                .filter(|bl| self.sel_baselines.contains(bl));
+            differences.push(ContextDifference::Baselines {
+                only_left,
+                only_right,
+                reordered: !common_left.eq(common_right),
+            });
+        }
+        if self.avg_time != other.avg_time {
+            differences.push(ContextDifference::AvgTime {
+                left: self.avg_time,
+                right: other.avg_time,
+            });
+        }
+        if self.avg_freq != other.avg_freq {
+            differences.push(ContextDifference::AvgFreq {
+                left: self.avg_freq,
+                right: other.avg_freq,
+            });
+        }
+        if self.num_vis_pols != other.num_vis_pols {
+            differences.push(ContextDifference::NumVisPols {
+                left: self.num_vis_pols,
+                right: other.num_vis_pols,
+            });
+        }
+        if self.averaging_policy != other.averaging_policy {
+            differences.push(ContextDifference::AveragingPolicy {
+                left: self.averaging_policy,
+                right: other.averaging_policy,
+            });
+        }
+        if self.vis_unit != other.vis_unit {
+            differences.push(ContextDifference::VisUnit {
+                left: self.vis_unit,
+                right: other.vis_unit,
+            });
+        }
+        if (self.vis_scale - other.vis_scale).abs() > f32::EPSILON {
+            differences.push(ContextDifference::VisScale {
+                left: self.vis_scale,
+                right: other.vis_scale,
+            });
+        }
+        ContextDiff { differences }
+    }
+
+    /// Check that chunks described by this context and `other` could be
+    /// concatenated: everything but their time and frequency extents must be
+    /// the same, and their timesteps and channels must lie on the same grid.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VisContextError::Incompatible`] with the differences that
+    /// prevent concatenation, or [`VisContextError::Misaligned`] if the chunks
+    /// are offset by a fraction of a timestep or channel.
+    pub fn compatible_with(&self, other: &VisContext) -> Result<(), VisContextError> {
+        let differences: Vec<_> = self
+            .diff(other)
+            .differences
+            .into_iter()
+            .filter(|difference| {
+                !matches!(
+                    difference,
+                    ContextDifference::NumSelTimesteps { .. }
+                        | ContextDifference::StartTimestamp { .. }
+                        | ContextDifference::NumSelChans { .. }
+                        | ContextDifference::StartFreq { .. }
+                )
+            })
+            .collect();
+        if !differences.is_empty() {
+            return Err(VisContextError::Incompatible(ContextDiff { differences }));
+        }
+        self.steps_to(other).map(|_| ())
+    }
+
+    /// Concatenate this context with that of an adjacent chunk, e.g. to
+    /// describe chunks written across multiple runs as a single context.
+    /// `other` may come before or after `self`, but the chunks must cover the
+    /// same timesteps (to be concatenated in frequency) or the same channels
+    /// (to be concatenated in time). This is the inverse of
+    /// [`VisContext::chunk_time`] and [`VisContext::chunk_freq`].
+    ///
+    /// # Errors
+    ///
+    /// As well as the errors of [`VisContext::compatible_with`], this returns
+    /// [`VisContextError::NotAdjacent`] if the chunks don't abut, and
+    /// [`VisContextError::PartialAveraging`] if the first chunk doesn't cover
+    /// a whole number of averaged timesteps or channels.
+    pub fn merge(&self, other: &VisContext) -> Result<VisContext, VisContextError> {
+        self.compatible_with(other)?;
+        let (time_steps, chan_steps) = self.steps_to(other)?;
+
+        // `len` is the length of self along the axis, `other_len` that of
+        // other; find which chunk comes first.
+        fn order<'a>(
+            steps: i64,
+            len: usize,
+            other_len: usize,
+            this: &'a VisContext,
+            other: &'a VisContext,
+        ) -> Option<(&'a VisContext, &'a VisContext)> {
+            if steps == len as i64 {
+                Some((this, other))
+            } else if steps == -(other_len as i64) {
+                Some((other, this))
+            } else {
+                None
+            }
+        }
+
+        if chan_steps == 0 && self.num_sel_chans == other.num_sel_chans {
+            let (first, second) = order(
+                time_steps,
+                self.num_sel_timesteps,
+                other.num_sel_timesteps,
+                self,
+                other,
+            )
+            .ok_or(VisContextError::NotAdjacent)?;
+            if first.num_sel_timesteps % first.avg_time != 0 {
+                return Err(VisContextError::PartialAveraging { axis: "timestep" });
+            }
+            Ok(VisContext {
+                num_sel_timesteps: first.num_sel_timesteps + second.num_sel_timesteps,
+                ..first.clone()
+            })
+        } else if time_steps == 0 && self.num_sel_timesteps == other.num_sel_timesteps {
+            let (first, second) = order(
+                chan_steps,
+                self.num_sel_chans,
+                other.num_sel_chans,
+                self,
+                other,
+            )
+            .ok_or(VisContextError::NotAdjacent)?;
+            if first.num_sel_chans % first.avg_freq != 0 {
+                return Err(VisContextError::PartialAveraging { axis: "channel" });
+            }
+            Ok(VisContext {
+                num_sel_chans: first.num_sel_chans + second.num_sel_chans,
+                ..first.clone()
+            })
+        } else {
+            Err(VisContextError::NotAdjacent)
+        }
+    }
+
+    /// The number of timesteps and channels from the start of this context to
+    /// the start of `other`, which has the same resolution.
+    fn steps_to(&self, other: &VisContext) -> Result<(i64, i64), VisContextError> {
+        let time_offset = other.start_timestamp - self.start_timestamp;
+        let time_steps = if time_offset.total_nanoseconds() == 0 {
+            Some(0)
+        } else {
+            whole_steps(time_offset.in_seconds(), self.int_time.in_seconds())
+        }
+        .ok_or(VisContextError::Misaligned { axis: "time" })?;
+        let freq_offset = other.start_freq_hz - self.start_freq_hz;
+        let chan_steps = if freq_offset == 0.0 {
+            Some(0)
+        } else {
+            whole_steps(freq_offset, self.freq_resolution_hz)
+        }
+        .ok_or(VisContextError::Misaligned { axis: "frequency" })?;
+        Ok((time_steps, chan_steps))
+    }
+
+    /// Get the weight factor: a measure of the resolution relative to the base
+    /// resolution of the legacy MWA correlator (1s / 10kHz).
+    ///
+    /// This is a concept from Cotter, and the legacy MWA correlator where the
+    /// value is a multiple of the frequency resolution (relative to 10kHz), and
+    /// the time averaging factor (relative to 1s).
+    pub fn weight_factor(&self) -> f64 {
+        self.int_time.in_seconds() / crate::constants::TIME_WEIGHT_FACTOR
+            * self.freq_resolution_hz.abs()
+            / crate::constants::FREQ_WEIGHT_FACTOR
+    }
+
+    /// Start building a [`VisContext`]. See [`VisContextBuilder`].
+    pub fn builder() -> VisContextBuilder {
+        VisContextBuilder::new()
+    }
+
+    /// Check that this context is self-consistent: its axes aren't empty, its
+    /// averaging factors, integration time and frequency resolution are
+    /// sensible, and no baseline is selected more than once.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first inconsistency found.
+    pub fn validate(&self) -> Result<(), VisContextError> {
+        for (axis, len) in [
+            ("timestep", self.num_sel_timesteps),
+            ("channel", self.num_sel_chans),
+            ("baseline", self.sel_baselines.len()),
+        ] {
+            if len == 0 {
+                return Err(VisContextError::EmptyAxis { axis });
+            }
+        }
+        for (axis, avg) in [("time", self.avg_time), ("frequency", self.avg_freq)] {
+            if avg == 0 {
+                return Err(VisContextError::ZeroAveraging { axis });
+            }
+        }
+        if self.int_time.total_nanoseconds() <= 0 {
+            return Err(VisContextError::BadIntTime(self.int_time));
+        }
+        if !self.start_freq_hz.is_finite() {
+            return Err(VisContextError::BadStartFreq(self.start_freq_hz));
+        }
+        if !self.freq_resolution_hz.is_finite() || self.freq_resolution_hz == 0.0 {
+            return Err(VisContextError::BadFreqResolution(self.freq_resolution_hz));
+        }
+        if ![1, 2, 4].contains(&self.num_vis_pols) {
+            return Err(VisContextError::BadNumPols(self.num_vis_pols));
+        }
+        if !self.vis_scale.is_finite() {
+            return Err(VisContextError::BadVisScale(self.vis_scale));
+        }
+        let mut seen = std::collections::HashSet::with_capacity(self.sel_baselines.len());
+        for &baseline in &self.sel_baselines {
+            if !seen.insert(baseline) {
+                return Err(VisContextError::DuplicateBaseline(baseline));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that visibility and weight arrays with dimensions `vis_dim` and
+    /// `weights_dim` are described by this context, i.e. have the dimensions
+    /// [`VisContext::sel_dims`]. `function` names the caller in the error.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`BadArrayShape`] for the first array with the wrong
+    /// dimensions.
+    pub fn check_sel_dims(
+        &self,
+        vis_dim: (usize, usize, usize),
+        weights_dim: (usize, usize, usize),
+        function: &'static str,
+    ) -> Result<(), BadArrayShape> {
+        let sel_dims = self.sel_dims();
+        for (argument, dim) in [("vis", vis_dim), ("weights", weights_dim)] {
+            if dim != sel_dims {
+                return Err(BadArrayShape {
+                    argument,
+                    function,
+                    expected: format!("{:?}", sel_dims),
+                    received: format!("{:?}", dim),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single difference between two [`VisContext`]s, as found by
+/// [`VisContext::diff`]. `left` values come from `self`, `right` values come
+/// from the other context.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContextDifference {
+    /// The number of selected timesteps ([`VisContext::num_sel_timesteps`])
+    /// differs.
+    NumSelTimesteps { left: usize, right: usize },
+    /// The start of the first selected timestep
+    /// ([`VisContext::start_timestamp`]) differs.
+    StartTimestamp { left: Epoch, right: Epoch },
+    /// The integration time before averaging ([`VisContext::int_time`])
+    /// differs.
+    IntTime { left: Duration, right: Duration },
+    /// The number of selected channels ([`VisContext::num_sel_chans`]) differs.
+    NumSelChans { left: usize, right: usize },
+    /// The centre frequency of the first selected channel
+    /// ([`VisContext::start_freq_hz`]) differs \[Hz\].
+    StartFreq { left: f64, right: f64 },
+    /// The channel width before averaging
+    /// ([`VisContext::freq_resolution_hz`]) differs \[Hz\].
+    FreqResolution { left: f64, right: f64 },
+    /// The selected baselines differ.
+    Baselines {
+        /// Baselines only present on the left.
+        only_left: Vec<(usize, usize)>,
+        /// Baselines only present on the right.
+        only_right: Vec<(usize, usize)>,
+        /// Whether the common baselines are in a different order.
+        reordered: bool,
+    },
+    /// The time averaging factor ([`VisContext::avg_time`]) differs.
+    AvgTime { left: usize, right: usize },
+    /// The frequency averaging factor ([`VisContext::avg_freq`]) differs.
+    AvgFreq { left: usize, right: usize },
+    /// The number of polarisations ([`VisContext::num_vis_pols`]) differs.
+    NumVisPols { left: usize, right: usize },
+    /// The averaging policy ([`VisContext::averaging_policy`]) differs.
+    AveragingPolicy {
+        left: AveragingPolicy,
+        right: AveragingPolicy,
+    },
+    /// The unit of the visibilities ([`VisContext::vis_unit`]) differs.
+    VisUnit { left: VisUnit, right: VisUnit },
+    /// The scale of the visibilities ([`VisContext::vis_scale`]) differs.
+    VisScale { left: f32, right: f32 },
+}
+
+impl std::fmt::Display for ContextDifference {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::NumSelTimesteps { left, right } => {
+                write!(f, "number of selected timesteps: {left} != {right}")
+            }
+            Self::StartTimestamp { left, right } => write!(
+                f,
+                "start timestamp: GPS {}s != GPS {}s",
+                left.as_gpst_seconds(),
+                right.as_gpst_seconds()
+            ),
+            Self::IntTime { left, right } => write!(f, "integration time: {left} != {right}"),
+            Self::NumSelChans { left, right } => {
+                write!(f, "number of selected channels: {left} != {right}")
+            }
+            Self::StartFreq { left, right } => {
+                write!(f, "start frequency: {left} Hz != {right} Hz")
+            }
+            Self::FreqResolution { left, right } => {
+                write!(f, "frequency resolution: {left} Hz != {right} Hz")
+            }
+            Self::Baselines {
+                only_left,
+                only_right,
+                reordered,
+            } => write!(
+                f,
+                "baselines: {} only on the left {:?}, {} only on the right {:?}{}",
+                only_left.len(),
+                only_left,
+                only_right.len(),
+                only_right,
+                if *reordered {
+                    ", common baselines are reordered"
+                } else {
+                    ""
+                }
+            ),
+            Self::AvgTime { left, right } => {
+                write!(f, "time averaging factor: {left} != {right}")
+            }
+            Self::AvgFreq { left, right } => {
+                write!(f, "frequency averaging factor: {left} != {right}")
+            }
+            Self::NumVisPols { left, right } => {
+                write!(f, "number of visibility pols: {left} != {right}")
+            }
+            Self::AveragingPolicy { left, right } => {
+                write!(f, "averaging policy: {left} != {right}")
+            }
+            Self::VisUnit { left, right } => {
+                write!(f, "visibility units: {left} != {right}")
+            }
+            Self::VisScale { left, right } => {
+                write!(f, "visibility scale: {left} != {right}")
+            }
+        }
+    }
+}
+
+/// A structured description of the differences between two [`VisContext`]s,
+/// as found by [`VisContext::diff`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContextDiff {
+    pub differences: Vec<ContextDifference>,
+}
+
+impl ContextDiff {
+    /// Whether the contexts are the same.
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+impl std::fmt::Display for ContextDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no differences");
+        }
+        for (i, difference) in self.differences.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{difference}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An inconsistency in a [`VisContext`], found by [`VisContext::validate`] or
+/// [`VisContextBuilder::build`].
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum VisContextError {
+    #[error("VisContextBuilder::{0} wasn't called")]
+    Missing(&'static str),
+
+    #[error("the {axis} axis is empty")]
+    EmptyAxis { axis: &'static str },
+
+    #[error("the {axis} averaging factor must be at least 1")]
+    ZeroAveraging { axis: &'static str },
+
+    #[error("the integration time must be positive, not {0}")]
+    BadIntTime(Duration),
+
+    #[error("the start frequency must be finite, not {0} Hz")]
+    BadStartFreq(f64),
+
+    #[error("the frequency resolution must be finite and non-zero, not {0} Hz")]
+    BadFreqResolution(f64),
+
+    #[error("{0} visibility pols aren't supported; expected 1, 2 or 4")]
+    BadNumPols(usize),
+
+    #[error("the visibility scale must be finite, not {0}")]
+    BadVisScale(f32),
+
+    #[error("baseline {0:?} is selected more than once")]
+    DuplicateBaseline((usize, usize)),
+
+    #[error("the contexts can't be concatenated because of these differences:\n{0}")]
+    Incompatible(ContextDiff),
+
+    #[error("the contexts aren't offset by a whole number of steps along the {axis} axis")]
+    Misaligned { axis: &'static str },
+
+    #[error("the contexts don't cover the same timesteps or channels, or don't abut")]
+    NotAdjacent,
+
+    #[error("the first context doesn't cover a whole number of averaged {axis}s")]
+    PartialAveraging { axis: &'static str },
+}
+
+/// A builder of [`VisContext`]s, which checks that the context is consistent
+/// when it's built.
+///
+/// The timesteps, channels and baselines must be set. Otherwise, there are 4
+/// visibility pols, no averaging, the default [`AveragingPolicy`], and
+/// uncalibrated visibilities with a scale of 1.
+///
+/// ```
+/// use marlu::{
+///     hifitime::{Duration, Epoch, Unit},
+///     VisContext,
+/// };
+///
+/// let vis_ctx = VisContext::builder()
+///     .timesteps(
+///         Epoch::from_gpst_seconds(1090008640.),
+///         Duration::from_f64(2., Unit::Second),
+///         4,
+///     )
+///     .channels(167.035e6, 40e3, 32)
+///     .baselines(vec![(0, 1), (0, 2), (1, 2)])
+///     .averaging(2, 4)
+///     .build()
+///     .unwrap();
+/// assert_eq!(vis_ctx.sel_dims(), (4, 32, 3));
+/// assert_eq!(vis_ctx.avg_dims(), (2, 8, 3));
+/// ```
+#[derive(Debug, Clone)]
+pub struct VisContextBuilder {
+    timesteps: Option<(Epoch, Duration, usize)>,
+    channels: Option<(f64, f64, usize)>,
+    sel_baselines: Option<Vec<(usize, usize)>>,
+    num_vis_pols: usize,
+    avg_time: usize,
+    avg_freq: usize,
+    averaging_policy: AveragingPolicy,
+    vis_unit: VisUnit,
+    vis_scale: f32,
+}
+
+impl Default for VisContextBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VisContextBuilder {
+    pub fn new() -> Self {
+        Self {
+            timesteps: None,
+            channels: None,
+            sel_baselines: None,
+            num_vis_pols: 4,
+            avg_time: 1,
+            avg_freq: 1,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        }
+    }
+
+    /// Set the time axis: the start of the first timestep, the time between
+    /// timesteps, and the number of timesteps.
+    pub fn timesteps(
+        mut self,
+        start_timestamp: Epoch,
+        int_time: Duration,
+        num_timesteps: usize,
+    ) -> Self {
+        self.timesteps = Some((start_timestamp, int_time, num_timesteps));
+        self
+    }
+
+    /// Set the frequency axis: the centre frequency of the first channel
+    /// \[Hz\], the (possibly negative) channel width \[Hz\], and the number
+    /// of channels.
+    pub fn channels(
+        mut self,
+        start_freq_hz: f64,
+        freq_resolution_hz: f64,
+        num_chans: usize,
+    ) -> Self {
+        self.channels = Some((start_freq_hz, freq_resolution_hz, num_chans));
+        self
+    }
+
+    /// Set the tile index pairs of the baseline axis.
+    pub fn baselines(mut self, sel_baselines: Vec<(usize, usize)>) -> Self {
+        self.sel_baselines = Some(sel_baselines);
+        self
+    }
+
+    /// Set the number of visibility pols.
+    pub fn num_vis_pols(mut self, num_vis_pols: usize) -> Self {
+        self.num_vis_pols = num_vis_pols;
+        self
+    }
+
+    /// Set the time and frequency averaging factors.
+    pub fn averaging(mut self, avg_time: usize, avg_freq: usize) -> Self {
+        self.avg_time = avg_time;
+        self.avg_freq = avg_freq;
+        self
+    }
+
+    /// Set what averaging does with bins in which every visibility is
+    /// flagged.
+    pub fn averaging_policy(mut self, averaging_policy: AveragingPolicy) -> Self {
+        self.averaging_policy = averaging_policy;
+        self
+    }
+
+    /// Set the units of the visibilities, and the factor that writers multiply
+    /// visibilities by to get those units.
+    pub fn vis_unit(mut self, vis_unit: VisUnit, vis_scale: f32) -> Self {
+        self.vis_unit = vis_unit;
+        self.vis_scale = vis_scale;
+        self
+    }
+
+    /// Build the [`VisContext`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an axis hasn't been set, or the context isn't
+    /// consistent (see [`VisContext::validate`]).
+    pub fn build(self) -> Result<VisContext, VisContextError> {
+        let (start_timestamp, int_time, num_sel_timesteps) = self
+            .timesteps
+            .ok_or(VisContextError::Missing("timesteps"))?;
+        let (start_freq_hz, freq_resolution_hz, num_sel_chans) =
+            self.channels.ok_or(VisContextError::Missing("channels"))?;
+        let sel_baselines = self
+            .sel_baselines
+            .ok_or(VisContextError::Missing("baselines"))?;
+        let vis_ctx = VisContext {
+            num_sel_timesteps,
+            start_timestamp,
+            int_time,
+            num_sel_chans,
+            start_freq_hz,
+            freq_resolution_hz,
+            sel_baselines,
+            avg_time: self.avg_time,
+            avg_freq: self.avg_freq,
+            num_vis_pols: self.num_vis_pols,
+            averaging_policy: self.averaging_policy,
+            vis_unit: self.vis_unit,
+            vis_scale: self.vis_scale,
+        };
+        vis_ctx.validate()?;
+        Ok(vis_ctx)
+    }
+}
+
+/// The number of whole `step`s in `offset`, if `offset` is within 1% of a
+/// whole number of them.
+fn whole_steps(offset: f64, step: f64) -> Option<i64> {
+    if step == 0.0 {
+        return None;
+    }
+    let steps = offset / step;
+    let rounded = steps.round();
+    ((steps - rounded).abs() <= 0.01).then(|| rounded as i64)
+}
+
+/// Split an axis of length `len` into at most `n` `(offset, length)` chunks,
+/// where every chunk but the last is a multiple of `avg` long.
+fn chunk_axis(len: usize, avg: usize, n: usize) -> Vec<(usize, usize)> {
+    let avg = avg.max(1);
+    let num_avg = (len as f64 / avg as f64).ceil() as usize;
+    let avg_per_chunk = (num_avg as f64 / n.max(1) as f64).ceil() as usize;
+    let chunk_len = (avg_per_chunk * avg).max(1);
+    (0..len)
+        .step_by(chunk_len)
+        .map(|offset| (offset, chunk_len.min(len - offset)))
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use approx::assert_abs_diff_eq;
+    use hifitime::Unit;
+
+    use crate::constants::VEL_C;
+
+    use super::*;
+
+    /// A [`VisContext`] of 1 second timesteps and 10 kHz channels from
+    /// 192 MHz, without averaging, for tests of the readers and writers.
+    pub(crate) fn get_vis_ctx(
+        num_sel_timesteps: usize,
+        num_sel_chans: usize,
+        sel_baselines: Vec<(usize, usize)>,
+    ) -> VisContext {
+        VisContext {
+            num_sel_timesteps,
+            start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans,
+            start_freq_hz: 192000000.,
+            freq_resolution_hz: 10000.,
+            sel_baselines,
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        }
+    }
+
+    /// An [`ObsContext`] of `num_ants` unflagged antennas named `ant0`,
+    /// `ant1`, ..., to accompany [`get_vis_ctx`].
+    pub(crate) fn get_obs_ctx(num_ants: usize) -> ObsContext {
+        ObsContext {
+            sched_start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            sched_duration: Duration::from_f64(1., Unit::Second),
+            name: None,
+            field_name: None,
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::default(),
+            pointing_centre: None,
+            array_pos: LatLngHeight::default(),
+            telescope_name: "MWA".into(),
+            instrument: "MWA".into(),
+            ant_positions_enh: vec![ENH::default(); num_ants],
+            ant_names: (0..num_ants).map(|idx| format!("ant{idx}")).collect(),
+            ant_flags: vec![],
+            beams: vec![],
+        }
+    }
+
+    #[test]
+    // TODO: these collects are needed because of https://github.com/nyx-space/hifitime/issues/131
+    #[allow(clippy::needless_collect)]
+    fn vis_ctx_timeseries_length() {
+        let start_timestamp = Epoch::from_gpst_seconds(1090008640.);
+        let int_time = Duration::from_f64(1., Unit::Second);
+        let mut vis_ctx = VisContext {
+            num_sel_timesteps: 1,
+            start_timestamp,
+            int_time,
+            num_sel_chans: 1,
+            start_freq_hz: VEL_C,
+            freq_resolution_hz: 10_000.,
+            sel_baselines: vec![(0, 1), (0, 2)],
+            avg_time: 2,
+            avg_freq: 1,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+        vis_ctx.num_sel_timesteps = 3;
+        let times: Vec<_> = vis_ctx.timeseries(false, false).collect();
+        assert_eq!(times.len(), 3);
+        let times: Vec<_> = vis_ctx.timeseries(true, false).collect();
+        assert_eq!(times.len(), 2);
+        let times: Vec<_> = vis_ctx.timeseries(false, true).collect();
+        assert_eq!(times.len(), 3);
+        let times: Vec<_> = vis_ctx.timeseries(true, true).collect();
+        assert_eq!(times.len(), 2);
         vis_ctx.num_sel_timesteps = 2;
         let times: Vec<_> = vis_ctx.timeseries(false, false).collect();
         assert_eq!(times.len(), 2);
@@ -451,4 +1994,767 @@ mod tests {
         let times: Vec<_> = vis_ctx.timeseries(true, true).collect();
         assert_eq!(times.len(), 1);
     }
+
+    #[test]
+    fn instrument_states_overlapping_selection() {
+        let sched_start = Epoch::from_gpst_seconds(1090008640.);
+        let int_time = Duration::from_f64(2., Unit::Second);
+        let slewing = InstrumentState {
+            start_timestamp: sched_start,
+            pointing_centre: RADec::new_degrees(0., -27.),
+            pointing_azel: AzEl::new_degrees(0., 90.),
+            delays: vec![0; 16],
+            attenuation_db: 1.,
+            settled: false,
+        };
+        let settled = InstrumentState {
+            start_timestamp: sched_start + Duration::from_f64(4., Unit::Second),
+            settled: true,
+            ..slewing.clone()
+        };
+        let mwa_ctx = MwaObsContext {
+            ant_inputs: Array2::zeros((0, 2)),
+            ant_numbers: vec![],
+            ant_receivers: vec![],
+            ant_slots: Array2::zeros((0, 2)),
+            ant_cable_lengths: Array2::zeros((0, 2)),
+            coarse_chan_recs: vec![],
+            has_calibrator: false,
+            mode: "HW_LFILES".into(),
+            delays: vec![0; 16],
+            ant_dipole_delays: Array3::zeros((0, 2, 16)),
+            ant_dipole_gains: Array3::zeros((0, 2, 16)),
+            ant_digital_gains: Array3::zeros((0, 0, 2)),
+            instrument_states: vec![slewing.clone(), settled.clone()],
+        };
+        let mut vis_ctx = VisContext {
+            num_sel_timesteps: 2,
+            start_timestamp: sched_start,
+            int_time,
+            num_sel_chans: 1,
+            start_freq_hz: VEL_C,
+            freq_resolution_hz: 10_000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+        // The selection ends exactly when the beamformers settle.
+        assert_eq!(
+            mwa_ctx.instrument_states_in_selection(&vis_ctx),
+            vec![&slewing]
+        );
+
+        vis_ctx.num_sel_timesteps = 3;
+        assert_eq!(
+            mwa_ctx.instrument_states_in_selection(&vis_ctx),
+            vec![&slewing, &settled]
+        );
+
+        vis_ctx.start_timestamp = sched_start + Duration::from_f64(10., Unit::Second);
+        assert_eq!(
+            mwa_ctx.instrument_states_in_selection(&vis_ctx),
+            vec![&settled]
+        );
+        assert!(settled.as_comment().contains("settled"));
+    }
+
+    #[test]
+    fn obs_ctx_for_beam() {
+        let beam = AnalogueBeam {
+            id: 1,
+            name: Some("second".into()),
+            phase_centre: RADec::new_degrees(10.0, -20.0),
+            pointing_centre: None,
+        };
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            sched_duration: Duration::from_f64(8., Unit::Second),
+            name: Some("multibeam".into()),
+            field_name: Some("first".into()),
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::new_degrees(0.0, -27.0),
+            pointing_centre: Some(RADec::new_degrees(0.0, -27.0)),
+            array_pos: LatLngHeight::new_mwa(),
+            telescope_name: "MWA".into(),
+            instrument: "MWA".into(),
+            ant_positions_enh: vec![],
+            ant_names: vec![],
+            ant_flags: vec![],
+            beams: vec![beam.clone()],
+        };
+        assert_eq!(obs_ctx.beam(1), Some(&beam));
+        assert!(obs_ctx.beam(0).is_none());
+
+        let beam_ctx = obs_ctx.for_beam(&beam);
+        assert_eq!(beam_ctx.field_name.as_deref(), Some("second"));
+        assert_eq!(beam_ctx.phase_centre, beam.phase_centre);
+        assert!(beam_ctx.pointing_centre.is_none());
+        assert_eq!(beam_ctx.name, obs_ctx.name);
+        assert!(beam_ctx.beams.is_empty());
+    }
+
+    #[test]
+    fn obs_ctx_telescope() {
+        let obs_ctx = ObsContext {
+            array_pos: LatLngHeight::new_mwa(),
+            telescope_name: "SKA-Low".into(),
+            instrument: "AAVS2".into(),
+            ant_positions_enh: vec![ENH {
+                e: 1.0,
+                n: 2.0,
+                h: 3.0,
+            }],
+            ant_names: vec!["S8-1".into()],
+            ..get_obs_ctx(1)
+        };
+        let telescope = obs_ctx.telescope();
+        assert_eq!(telescope.name, "SKA-Low");
+        assert_eq!(telescope.instrument, "AAVS2");
+        assert_eq!(telescope.ant_names, obs_ctx.ant_names);
+        assert_eq!(
+            telescope.ant_positions,
+            obs_ctx.ant_positions_geodetic().collect::<Vec<_>>()
+        );
+
+        assert_eq!(obs_ctx.num_ants(), 1);
+        assert!(!obs_ctx.is_ant_flagged(0));
+        assert!(obs_ctx.flagged_ants().is_empty());
+        let flagged_ctx = ObsContext {
+            ant_flags: vec![true],
+            ..obs_ctx.clone()
+        };
+        assert!(flagged_ctx.is_ant_flagged(0));
+        assert_eq!(flagged_ctx.flagged_ants(), vec![0]);
+
+        let mwa = Telescope::mwa(vec![], vec![]);
+        assert_eq!(mwa.instrument, "MWA");
+        assert_eq!(mwa.array_pos, LatLngHeight::new_mwa());
+        assert_eq!(mwa.ant_mount(0), MountType::AltAz);
+        assert_abs_diff_eq!(mwa.ant_dish_diameter_m(0), DEFAULT_DISH_DIAMETER_M);
+        assert_abs_diff_eq!(mwa.ant_axis_offset_m(0), 0.0);
+
+        let mixed = Telescope {
+            ant_mounts: vec![MountType::AltAz, MountType::Equatorial],
+            ant_dish_diameters_m: vec![12.0, 25.0],
+            ant_axis_offsets_m: vec![0.0, 1.5],
+            ..Telescope::new("mixed", LatLngHeight::default(), vec![], vec![])
+        };
+        assert_eq!(mixed.ant_mount(1).ms_mount(), "EQUATORIAL");
+        assert_eq!(mixed.ant_mount(1).uvfits_mntsta(), 1);
+        assert_abs_diff_eq!(mixed.ant_dish_diameter_m(1), 25.0);
+        assert_abs_diff_eq!(mixed.ant_axis_offset_m(1), 1.5);
+        assert_eq!(mixed.ant_mount(2), MountType::AltAz);
+    }
+
+    #[test]
+    fn vis_ctx_split_autos() {
+        let vis_ctx = get_vis_ctx(1, 1, vec![(0, 0), (0, 1), (1, 1), (1, 2), (2, 2)]);
+        assert_eq!(vis_ctx.auto_baseline_idxs(), vec![0, 2, 4]);
+        assert_eq!(vis_ctx.cross_baseline_idxs(), vec![1, 3]);
+        let (autos, crosses) = vis_ctx.split_autos();
+        assert_eq!(autos.sel_baselines, vec![(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(crosses.sel_baselines, vec![(0, 1), (1, 2)]);
+        assert_eq!(autos.num_sel_chans, vis_ctx.num_sel_chans);
+    }
+
+    #[test]
+    fn vis_ctx_chunks() {
+        let start_timestamp = Epoch::from_gpst_seconds(1090008640.);
+        let int_time = Duration::from_f64(1., Unit::Second);
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 7,
+            start_timestamp,
+            int_time,
+            num_sel_chans: 10,
+            start_freq_hz: 128_000_000.,
+            freq_resolution_hz: 10_000.,
+            sel_baselines: vec![(0, 1), (0, 2)],
+            avg_time: 2,
+            avg_freq: 4,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+
+        let time_chunks = vis_ctx.chunk_time(3);
+        assert_eq!(
+            time_chunks
+                .iter()
+                .map(|c| c.num_sel_timesteps)
+                .collect::<Vec<_>>(),
+            vec![4, 3]
+        );
+        assert_eq!(
+            time_chunks
+                .iter()
+                .map(|c| c.num_avg_timesteps())
+                .sum::<usize>(),
+            vis_ctx.num_avg_timesteps()
+        );
+        assert_eq!(
+            time_chunks[1].start_timestamp,
+            start_timestamp + Duration::from_f64(4., Unit::Second)
+        );
+
+        let freq_chunks = vis_ctx.chunk_freq(2);
+        assert_eq!(
+            freq_chunks
+                .iter()
+                .map(|c| c.num_sel_chans)
+                .collect::<Vec<_>>(),
+            vec![8, 2]
+        );
+        let avg_freqs: Vec<f64> = freq_chunks
+            .iter()
+            .flat_map(|c| c.avg_frequencies_hz())
+            .collect();
+        assert_eq!(avg_freqs, vis_ctx.avg_frequencies_hz());
+
+        assert_eq!(vis_ctx.chunk_time(0).len(), 1);
+        assert_eq!(vis_ctx.chunk_time(100).len(), 4);
+    }
+
+    #[test]
+    fn vis_ctx_locate_chunk() {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 7,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: 10,
+            start_freq_hz: 128_000_000.,
+            freq_resolution_hz: 10_000.,
+            sel_baselines: vec![(0, 1), (0, 2), (1, 2)],
+            avg_time: 2,
+            avg_freq: 4,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+
+        let location = vis_ctx.locate_chunk(&vis_ctx).unwrap();
+        assert_eq!(location.timesteps, 0..7);
+        assert_eq!(location.chans, 0..10);
+        assert_eq!(location.baseline_idxs, vec![0, 1, 2]);
+
+        let time_chunk = &vis_ctx.chunk_time(3)[1];
+        let chunk = VisContext {
+            sel_baselines: vec![(1, 2), (0, 1)],
+            ..vis_ctx.chunk_freq(2)[1].clone()
+        };
+        let chunk = VisContext {
+            num_sel_timesteps: time_chunk.num_sel_timesteps,
+            start_timestamp: time_chunk.start_timestamp,
+            ..chunk
+        };
+        let location = vis_ctx.locate_chunk(&chunk).unwrap();
+        assert_eq!(location.timesteps, 4..7);
+        assert_eq!(location.chans, 8..10);
+        assert_eq!(location.baseline_idxs, vec![2, 0]);
+
+        // Unaligned, out of range and unknown baselines.
+        let unaligned = VisContext {
+            start_timestamp: vis_ctx.start_timestamp + Duration::from_f64(0.5, Unit::Second),
+            num_sel_timesteps: 1,
+            ..vis_ctx.clone()
+        };
+        assert!(vis_ctx.locate_chunk(&unaligned).is_none());
+        let too_long = VisContext {
+            start_freq_hz: vis_ctx.start_freq_hz + vis_ctx.freq_resolution_hz,
+            ..vis_ctx.clone()
+        };
+        assert!(vis_ctx.locate_chunk(&too_long).is_none());
+        let unknown_baseline = VisContext {
+            sel_baselines: vec![(2, 3)],
+            ..vis_ctx.clone()
+        };
+        assert!(vis_ctx.locate_chunk(&unknown_baseline).is_none());
+    }
+
+    #[test]
+    fn vis_ctx_builder() {
+        let start_timestamp = Epoch::from_gpst_seconds(1090008640.);
+        let int_time = Duration::from_f64(1., Unit::Second);
+        let vis_ctx = VisContext::builder()
+            .timesteps(start_timestamp, int_time, 7)
+            .channels(128_000_000., 10_000., 10)
+            .baselines(vec![(0, 1), (0, 2), (1, 2)])
+            .averaging(2, 4)
+            .averaging_policy(AveragingPolicy::Nan)
+            .vis_unit(VisUnit::Jansky, 0.5)
+            .build()
+            .unwrap();
+        let expected = VisContext {
+            num_sel_timesteps: 7,
+            start_timestamp,
+            int_time,
+            num_sel_chans: 10,
+            start_freq_hz: 128_000_000.,
+            freq_resolution_hz: 10_000.,
+            sel_baselines: vec![(0, 1), (0, 2), (1, 2)],
+            avg_time: 2,
+            avg_freq: 4,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::Nan,
+            vis_unit: VisUnit::Jansky,
+            vis_scale: 0.5,
+        };
+        assert!(vis_ctx.diff(&expected).is_empty());
+
+        let builder = VisContext::builder()
+            .timesteps(start_timestamp, int_time, 7)
+            .channels(128_000_000., 10_000., 10);
+        assert_eq!(
+            builder.clone().build().unwrap_err(),
+            VisContextError::Missing("baselines")
+        );
+        let builder = builder.baselines(vec![(0, 1), (1, 2)]);
+        assert!(builder.clone().build().is_ok());
+        assert_eq!(
+            builder.clone().averaging(0, 1).build().unwrap_err(),
+            VisContextError::ZeroAveraging { axis: "time" }
+        );
+        assert_eq!(
+            builder.clone().num_vis_pols(3).build().unwrap_err(),
+            VisContextError::BadNumPols(3)
+        );
+        assert_eq!(
+            builder
+                .clone()
+                .channels(128_000_000., 0., 10)
+                .build()
+                .unwrap_err(),
+            VisContextError::BadFreqResolution(0.)
+        );
+        assert_eq!(
+            builder
+                .clone()
+                .timesteps(start_timestamp, int_time, 0)
+                .build()
+                .unwrap_err(),
+            VisContextError::EmptyAxis { axis: "timestep" }
+        );
+        assert_eq!(
+            builder
+                .baselines(vec![(0, 1), (1, 2), (0, 1)])
+                .build()
+                .unwrap_err(),
+            VisContextError::DuplicateBaseline((0, 1))
+        );
+    }
+
+    #[test]
+    fn vis_ctx_check_sel_dims() {
+        let vis_ctx = VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(1., Unit::Second),
+                2,
+            )
+            .channels(128_000_000., 10_000., 3)
+            .baselines(vec![(0, 1)])
+            .build()
+            .unwrap();
+        assert!(vis_ctx.check_sel_dims((2, 3, 1), (2, 3, 1), "test").is_ok());
+        let error = vis_ctx
+            .check_sel_dims((2, 3, 1), (2, 4, 1), "test")
+            .unwrap_err();
+        assert_eq!(error.argument, "weights");
+        assert_eq!(error.function, "test");
+        assert_eq!(error.expected, "(2, 3, 1)");
+        assert_eq!(error.received, "(2, 4, 1)");
+        assert_eq!(
+            vis_ctx
+                .check_sel_dims((1, 3, 1), (1, 3, 1), "test")
+                .unwrap_err()
+                .argument,
+            "vis"
+        );
+    }
+
+    #[test]
+    fn vis_ctx_merge_chunks() {
+        let vis_ctx = VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(2., Unit::Second),
+                5,
+            )
+            .channels(128_000_000., -10_000., 7)
+            .baselines(vec![(0, 1), (0, 2), (1, 2)])
+            .averaging(2, 2)
+            .build()
+            .unwrap();
+
+        let time_chunks = vis_ctx.chunk_time(3);
+        assert_eq!(time_chunks.len(), 3);
+        let merged = time_chunks[0]
+            .merge(&time_chunks[1])
+            .unwrap()
+            .merge(&time_chunks[2])
+            .unwrap();
+        assert!(
+            merged.diff(&vis_ctx).is_empty(),
+            "{}",
+            merged.diff(&vis_ctx)
+        );
+        // The order of the chunks doesn't matter.
+        let merged = time_chunks[2].merge(&time_chunks[1]).unwrap();
+        assert_eq!(merged.start_timestamp, time_chunks[1].start_timestamp);
+        assert_eq!(merged.num_sel_timesteps, 3);
+
+        let freq_chunks = vis_ctx.chunk_freq(2);
+        let merged = freq_chunks[1].merge(&freq_chunks[0]).unwrap();
+        assert!(
+            merged.diff(&vis_ctx).is_empty(),
+            "{}",
+            merged.diff(&vis_ctx)
+        );
+        assert!(freq_chunks[0].compatible_with(&time_chunks[2]).is_ok());
+
+        // Chunks with a gap between them.
+        assert_eq!(
+            time_chunks[0].merge(&time_chunks[2]).unwrap_err(),
+            VisContextError::NotAdjacent
+        );
+        // Chunks that overlap.
+        assert_eq!(
+            vis_ctx.merge(&time_chunks[0]).unwrap_err(),
+            VisContextError::NotAdjacent
+        );
+        // Chunks that cover different channels and timesteps.
+        assert_eq!(
+            freq_chunks[0].merge(&time_chunks[1]).unwrap_err(),
+            VisContextError::NotAdjacent
+        );
+
+        // The first chunk ends part-way through an averaged timestep.
+        let odd_chunks = VisContext {
+            avg_time: 1,
+            ..vis_ctx.clone()
+        }
+        .chunk_time(5);
+        let odd_chunks: Vec<_> = odd_chunks
+            .into_iter()
+            .map(|chunk| VisContext {
+                avg_time: 2,
+                ..chunk
+            })
+            .collect();
+        assert_eq!(
+            odd_chunks[0].merge(&odd_chunks[1]).unwrap_err(),
+            VisContextError::PartialAveraging { axis: "timestep" }
+        );
+
+        let misaligned = VisContext {
+            start_freq_hz: freq_chunks[1].start_freq_hz + 5_000.,
+            ..freq_chunks[1].clone()
+        };
+        assert_eq!(
+            freq_chunks[0].compatible_with(&misaligned),
+            Err(VisContextError::Misaligned { axis: "frequency" })
+        );
+
+        let other_baselines = VisContext {
+            sel_baselines: vec![(0, 1)],
+            ..time_chunks[1].clone()
+        };
+        match time_chunks[0].merge(&other_baselines) {
+            Err(VisContextError::Incompatible(diff)) => assert!(matches!(
+                diff.differences[..],
+                [ContextDifference::Baselines { .. }]
+            )),
+            result => panic!("unexpected result {result:?}"),
+        }
+    }
+
+    #[test]
+    fn vis_ctx_split_freq() {
+        let vis_ctx = VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(2., Unit::Second),
+                2,
+            )
+            .channels(128_000_000., 10_000., 10)
+            .baselines(vec![(0, 1)])
+            .averaging(1, 2)
+            .build()
+            .unwrap();
+
+        let bands = vis_ctx.split_freq(4);
+        assert_eq!(
+            bands
+                .iter()
+                .map(|band| band.num_sel_chans)
+                .collect::<Vec<_>>(),
+            vec![4, 4, 2]
+        );
+        approx::assert_abs_diff_eq!(bands[1].start_freq_hz, 128_040_000.);
+        approx::assert_abs_diff_eq!(bands[2].start_freq_hz, 128_080_000.);
+        assert_eq!(bands[0].num_sel_timesteps, 2);
+
+        // Sub-bands are rounded up to whole averaged channels.
+        let bands = vis_ctx.split_freq(3);
+        assert_eq!(
+            bands
+                .iter()
+                .map(|band| band.num_sel_chans)
+                .collect::<Vec<_>>(),
+            vec![4, 4, 2]
+        );
+        assert_eq!(vis_ctx.split_freq(0).len(), 5);
+        assert_eq!(vis_ctx.split_freq(100).len(), 1);
+    }
+
+    #[test]
+    fn vis_ctx_channel_layout() {
+        // Two coarse channels of 40 kHz channels, starting at the first fine
+        // channel of receiver channel 143.
+        let layout = ChannelLayout::from_fine_chan_width(40e3);
+        let vis_ctx = VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(2., Unit::Second),
+                1,
+            )
+            .channels(layout.fine_chan_freq_hz(143, 0), 40e3, 64)
+            .baselines(vec![(0, 1)])
+            .build()
+            .unwrap();
+
+        assert_eq!(vis_ctx.channel_layout(), layout);
+        assert_eq!(vis_ctx.rec_chans(), vec![143, 144]);
+        let expected = layout.fine_chan_freqs_hz(&[143, 144]);
+        for (freq, expected) in vis_ctx.frequencies_hz().iter().zip(expected.iter()) {
+            approx::assert_abs_diff_eq!(freq, expected, epsilon = 1e-3);
+        }
+    }
+
+    #[test]
+    fn vis_ctx_num_ants() {
+        let mut vis_ctx = VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(1., Unit::Second),
+                1,
+            )
+            .channels(128_000_000., 10_000., 1)
+            .baselines(vec![(0, 1), (3, 2)])
+            .build()
+            .unwrap();
+        assert_eq!(vis_ctx.num_ants(), 4);
+        vis_ctx.sel_baselines.clear();
+        assert_eq!(vis_ctx.num_ants(), 0);
+    }
+
+    #[test]
+    fn vis_ctx_descending_to_ascending() {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 1,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: 4,
+            start_freq_hz: 128_030_000.,
+            freq_resolution_hz: -10_000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 2,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+        assert!(vis_ctx.is_descending());
+        assert_eq!(
+            vis_ctx.frequencies_hz(),
+            vec![128_030_000., 128_020_000., 128_010_000., 128_000_000.]
+        );
+        approx::assert_abs_diff_eq!(vis_ctx.weight_factor(), 1.0);
+
+        let ascending = vis_ctx.to_ascending();
+        assert!(!ascending.is_descending());
+        let mut freqs = vis_ctx.frequencies_hz();
+        freqs.reverse();
+        assert_eq!(ascending.frequencies_hz(), freqs);
+        let mut avg_freqs = vis_ctx.avg_frequencies_hz();
+        avg_freqs.reverse();
+        assert_eq!(ascending.avg_frequencies_hz(), avg_freqs);
+    }
+
+    #[test]
+    fn vis_ctx_chan_edges_and_centres() {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 1,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: 5,
+            start_freq_hz: 128_005_000.,
+            freq_resolution_hz: 10_000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 2,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+        let edges = vis_ctx.chan_edges_hz();
+        assert_eq!(edges.len(), 6);
+        approx::assert_abs_diff_eq!(edges[0], 128_000_000.);
+        approx::assert_abs_diff_eq!(edges[5], 128_050_000.);
+
+        let avg_edges = vis_ctx.avg_chan_edges_hz();
+        assert_eq!(
+            avg_edges,
+            vec![128_000_000., 128_020_000., 128_040_000., 128_050_000.]
+        );
+        // Averaged centres are the midpoints of the averaged edges.
+        for (centre, edges) in vis_ctx
+            .avg_frequencies_hz()
+            .into_iter()
+            .zip(avg_edges.windows(2))
+        {
+            approx::assert_abs_diff_eq!(centre, (edges[0] + edges[1]) / 2.);
+        }
+        approx::assert_abs_diff_eq!(vis_ctx.avg_centre_freq_hz(), 128_030_000.);
+    }
+
+    #[test]
+    fn vis_ctx_diff() {
+        let left = VisContext {
+            num_sel_timesteps: 2,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: 4,
+            start_freq_hz: 128_000_000.,
+            freq_resolution_hz: 10_000.,
+            sel_baselines: vec![(0, 1), (0, 2), (1, 2)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+        assert!(left.diff(&left.clone()).is_empty());
+
+        let right = VisContext {
+            freq_resolution_hz: 20_000.,
+            sel_baselines: vec![(1, 2), (0, 1), (2, 3)],
+            ..left.clone()
+        };
+        let diff = left.diff(&right);
+        assert_eq!(
+            diff.differences,
+            vec![
+                ContextDifference::FreqResolution {
+                    left: 10_000.,
+                    right: 20_000.
+                },
+                ContextDifference::Baselines {
+                    only_left: vec![(0, 2)],
+                    only_right: vec![(2, 3)],
+                    reordered: true,
+                }
+            ]
+        );
+        assert_eq!(diff.to_string().lines().count(), 2);
+
+        let right = VisContext {
+            vis_unit: VisUnit::Jansky,
+            vis_scale: 0.5,
+            ..left.clone()
+        };
+        assert_eq!(
+            left.diff(&right).differences,
+            vec![
+                ContextDifference::VisUnit {
+                    left: VisUnit::Uncalibrated,
+                    right: VisUnit::Jansky,
+                },
+                ContextDifference::VisScale {
+                    left: 1.0,
+                    right: 0.5,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn provenance_as_history_cards() {
+        let mut provenance = Provenance::new("birli 0.9.0");
+        provenance.cmd_line = Some("birli -m obs.metafits".into());
+        provenance
+            .software_versions
+            .push(("mwalib".into(), "0.15.0".into()));
+        provenance.parameters.push(("avg_time".into(), "2".into()));
+        provenance.parameters.push(("avg_freq".into(), "4".into()));
+
+        assert_eq!(
+            provenance.software_message(),
+            format!("marlu {}, mwalib 0.15.0", env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(
+            provenance.as_history_cards(),
+            vec![
+                "APPLICATION: birli 0.9.0".to_string(),
+                "CMDLINE: birli -m obs.metafits".to_string(),
+                format!("SOFTWARE: marlu {}", env!("CARGO_PKG_VERSION")),
+                "SOFTWARE: mwalib 0.15.0".to_string(),
+                "PARAM: avg_time=2".to_string(),
+                "PARAM: avg_freq=4".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_round_trip() {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 3,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_f64(2., Unit::Second),
+            num_sel_chans: 4,
+            start_freq_hz: 128_000_000.,
+            freq_resolution_hz: 10_000.,
+            sel_baselines: vec![(0, 1), (1, 2)],
+            avg_time: 1,
+            avg_freq: 2,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::Nan,
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+        let json = serde_json::to_string(&vis_ctx).unwrap();
+        let result: VisContext = serde_json::from_str(&json).unwrap();
+        assert!(vis_ctx.diff(&result).is_empty());
+        assert_eq!(result.averaging_policy, AveragingPolicy::Nan);
+
+        let vis_sel = crate::VisSelection {
+            timestep_range: 1..3,
+            coarse_chan_range: 0..2,
+            baseline_idxs: vec![1, 2, 3],
+        };
+        let json = serde_json::to_string(&vis_sel).unwrap();
+        let result: crate::VisSelection = serde_json::from_str(&json).unwrap();
+        assert_eq!(result.timestep_range, vis_sel.timestep_range);
+        assert_eq!(result.baseline_idxs, vis_sel.baseline_idxs);
+
+        let array_pos = LatLngHeight::new_mwa();
+        let json = serde_json::to_string(&array_pos).unwrap();
+        assert_eq!(
+            serde_json::from_str::<LatLngHeight>(&json).unwrap(),
+            array_pos
+        );
+        let radec = RADec::new_degrees(10.0, -27.0);
+        let json = serde_json::to_string(&radec).unwrap();
+        assert_eq!(serde_json::from_str::<RADec>(&json).unwrap(), radec);
+    }
 }
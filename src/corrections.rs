@@ -0,0 +1,1401 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Instrumental corrections applied to raw correlator visibilities, e.g. cable
+//! length delays, digital gains, coarse channel passbands, quantisation (Van
+//! Vleck) and geometric delays. Phase tracked visibilities can also be fringe
+//! stopped toward other directions, and direction-independent gains (e.g.
+//! calibration solutions) can be applied.
+//!
+//! All corrections operate in place on a jones array with dimensions
+//! `[timestep][channel][baseline]`, and the polarisations of each [`Jones`] are
+//! ordered XX, XY, YX, YY.
+
+use std::f64::consts::{PI, TAU};
+
+use hifitime::Duration;
+use ndarray::{parallel::prelude::*, prelude::*};
+use thiserror::Error;
+
+use crate::{
+    constants::VEL_C, pos::xyz::baselines_to_uvws, precession::precess_time, Complex, Jones,
+    LatLngHeight, LmnRime, RADec, VisContext, XyzGeodetic, UVW,
+};
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "mwalib")] {
+        use std::ops::Range;
+        use mwalib::{CorrelatorContext, MetafitsContext};
+        use crate::MwaObsContext;
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CorrectionError {
+    #[error("bad array shape supplied to argument {argument} of function {function}. expected {expected}, received {received}")]
+    /// Error for bad array shape in provided argument
+    BadArrayShape {
+        /// The argument name within the function
+        argument: String,
+        /// The function name
+        function: String,
+        /// The expected shape
+        expected: String,
+        /// The shape that was received instead
+        received: String,
+    },
+
+    #[error("there are no built-in passband gains at a fine channel resolution of {fine_chan_width_hz} Hz")]
+    /// Error for a fine channel resolution that the built-in passband gains
+    /// can't be averaged to
+    BadPassbandResolution { fine_chan_width_hz: u32 },
+
+    #[error("the Van Vleck correction of baseline ({ant1}, {ant2}) requires the auto-correlation of antenna {missing}")]
+    /// Error for a cross-correlation without the auto-correlations of its
+    /// antennas
+    MissingAuto {
+        ant1: usize,
+        ant2: usize,
+        missing: usize,
+    },
+}
+
+/// Correct the phases of visibilities for the differences in the electrical
+/// lengths of each antenna's cables.
+///
+/// - `jones_array` - visibilities with dimensions `[timestep][channel][baseline]`
+/// - `frequencies_hz` - the centre frequency of each channel
+/// - `ant_pairs` - the antenna indices of each baseline
+/// - `cable_lengths_m` - the electrical length of each antenna's cables, with
+///   dimensions `[antenna][pol]` (see [`crate::MwaObsContext::ant_cable_lengths`])
+///
+/// This is derived from cotter and Birli.
+///
+/// # Errors
+///
+/// Will return [`CorrectionError::BadArrayShape`] if the lengths of
+/// `frequencies_hz` or `ant_pairs` don't match `jones_array`.
+pub fn correct_cable_lengths(
+    mut jones_array: ArrayViewMut3<Jones<f32>>,
+    frequencies_hz: &[f64],
+    ant_pairs: &[(usize, usize)],
+    cable_lengths_m: ArrayView2<f64>,
+) -> Result<(), CorrectionError> {
+    let (_, num_chans, num_baselines) = jones_array.dim();
+    if frequencies_hz.len() != num_chans {
+        return Err(CorrectionError::BadArrayShape {
+            argument: "frequencies_hz".into(),
+            function: "correct_cable_lengths".into(),
+            expected: format!("({},)", num_chans),
+            received: format!("({},)", frequencies_hz.len()),
+        });
+    }
+    if ant_pairs.len() != num_baselines {
+        return Err(CorrectionError::BadArrayShape {
+            argument: "ant_pairs".into(),
+            function: "correct_cable_lengths".into(),
+            expected: format!("({},)", num_baselines),
+            received: format!("({},)", ant_pairs.len()),
+        });
+    }
+
+    jones_array
+        .axis_iter_mut(Axis(2))
+        .into_par_iter()
+        .zip(ant_pairs)
+        .for_each(|(mut bl_jones, &(ant1, ant2))| {
+            let (len1, len2) = (cable_lengths_m.row(ant1), cable_lengths_m.row(ant2));
+            // XX, XY, YX, YY
+            let length_diffs_m = [
+                len2[0] - len1[0],
+                len2[1] - len1[0],
+                len2[0] - len1[1],
+                len2[1] - len1[1],
+            ];
+            for (mut chan_jones, &freq_hz) in bl_jones.axis_iter_mut(Axis(1)).zip(frequencies_hz) {
+                let rotations = length_diffs_m.map(|length_m| {
+                    let rotation = Complex::from_polar(1.0, -TAU * length_m * freq_hz / VEL_C);
+                    Complex::new(rotation.re as f32, rotation.im as f32)
+                });
+                for jones in &mut chan_jones {
+                    for (vis, rotation) in jones.iter_mut().zip(rotations) {
+                        *vis *= rotation;
+                    }
+                }
+            }
+        });
+
+    Ok(())
+}
+
+/// Correct the cable lengths of visibilities using the metadata in a
+/// [`mwalib::CorrelatorContext`]. See [`correct_cable_lengths`].
+///
+/// Note that some correlator modes have already applied this correction; check
+/// the metafits before calling this.
+///
+/// # Errors
+///
+/// See [`correct_cable_lengths`].
+#[cfg(feature = "mwalib")]
+pub fn correct_cable_lengths_mwalib(
+    corr_ctx: &CorrelatorContext,
+    jones_array: ArrayViewMut3<Jones<f32>>,
+    coarse_chan_range: &Range<usize>,
+    baseline_idxs: &[usize],
+) -> Result<(), CorrectionError> {
+    let meta_ctx = &corr_ctx.metafits_context;
+    let fine_chans_per_coarse = meta_ctx.num_corr_fine_chans_per_coarse;
+    let frequencies_hz = &meta_ctx.metafits_fine_chan_freqs_hz[coarse_chan_range.start
+        * fine_chans_per_coarse
+        ..coarse_chan_range.end * fine_chans_per_coarse];
+    let ant_pairs: Vec<(usize, usize)> = baseline_idxs
+        .iter()
+        .map(|&idx| {
+            let baseline = &meta_ctx.baselines[idx];
+            (baseline.ant1_index, baseline.ant2_index)
+        })
+        .collect();
+    let mwa_ctx = MwaObsContext::from_mwalib(meta_ctx);
+    correct_cable_lengths(
+        jones_array,
+        frequencies_hz,
+        &ant_pairs,
+        mwa_ctx.ant_cable_lengths.view(),
+    )
+}
+
+/// Correct the phases of visibilities for a signal delay of each antenna that
+/// is common to both polarisations, e.g. the delay of a tile group (see
+/// [`crate::pos::tile_group::tile_group_delays`]).
+///
+/// - `jones_array` - visibilities with dimensions `[timestep][channel][baseline]`
+/// - `frequencies_hz` - the centre frequency of each channel
+/// - `ant_pairs` - the antenna indices of each baseline
+/// - `ant_delays_s` - the delay of each antenna \[seconds\]
+///
+/// # Errors
+///
+/// See [`correct_cable_lengths`].
+pub fn correct_delays(
+    jones_array: ArrayViewMut3<Jones<f32>>,
+    frequencies_hz: &[f64],
+    ant_pairs: &[(usize, usize)],
+    ant_delays_s: &[f64],
+) -> Result<(), CorrectionError> {
+    let lengths_m = Array2::from_shape_fn((ant_delays_s.len(), 2), |(ant, _)| {
+        ant_delays_s[ant] * VEL_C
+    });
+    correct_cable_lengths(jones_array, frequencies_hz, ant_pairs, lengths_m.view())
+}
+
+/// Divide out the digital gains of each antenna's receiver from visibilities.
+///
+/// - `jones_array` - visibilities with dimensions `[timestep][channel][baseline]`
+/// - `gains` - the digital gain of each antenna, with dimensions
+///   `[antenna][coarse_chan][pol]`. The channels of `jones_array` are divided
+///   evenly between the coarse channels.
+/// - `ant_pairs` - the antenna indices of each baseline
+///
+/// # Errors
+///
+/// Will return [`CorrectionError::BadArrayShape`] if the length of `ant_pairs`
+/// doesn't match `jones_array`, or the channels can't be evenly divided between
+/// the coarse channels.
+pub fn correct_digital_gains(
+    mut jones_array: ArrayViewMut3<Jones<f32>>,
+    gains: ArrayView3<f64>,
+    ant_pairs: &[(usize, usize)],
+) -> Result<(), CorrectionError> {
+    let (_, num_chans, num_baselines) = jones_array.dim();
+    let (_, num_coarse_chans, _) = gains.dim();
+    if ant_pairs.len() != num_baselines {
+        return Err(CorrectionError::BadArrayShape {
+            argument: "ant_pairs".into(),
+            function: "correct_digital_gains".into(),
+            expected: format!("({},)", num_baselines),
+            received: format!("({},)", ant_pairs.len()),
+        });
+    }
+    if num_coarse_chans == 0 || num_chans % num_coarse_chans != 0 {
+        return Err(CorrectionError::BadArrayShape {
+            argument: "gains".into(),
+            function: "correct_digital_gains".into(),
+            expected: format!("(_, n, 2) where n divides {}", num_chans),
+            received: format!("{:?}", gains.dim()),
+        });
+    }
+    let fine_chans_per_coarse = num_chans / num_coarse_chans;
+
+    jones_array
+        .axis_iter_mut(Axis(2))
+        .into_par_iter()
+        .zip(ant_pairs)
+        .for_each(|(mut bl_jones, &(ant1, ant2))| {
+            for (coarse_chan_idx, mut coarse_chan_jones) in bl_jones
+                .axis_chunks_iter_mut(Axis(1), fine_chans_per_coarse)
+                .enumerate()
+            {
+                let g1 = gains.slice(s![ant1, coarse_chan_idx, ..]);
+                let g2 = gains.slice(s![ant2, coarse_chan_idx, ..]);
+                // XX, XY, YX, YY
+                let factors = [g1[0] * g2[0], g1[0] * g2[1], g1[1] * g2[0], g1[1] * g2[1]]
+                    .map(|gain| (1.0 / gain) as f32);
+                for jones in &mut coarse_chan_jones {
+                    for (vis, factor) in jones.iter_mut().zip(factors) {
+                        *vis *= factor;
+                    }
+                }
+            }
+        });
+
+    Ok(())
+}
+
+/// Get the digital gains of each antenna from an [`mwalib::MetafitsContext`],
+/// with dimensions `[antenna][coarse_chan][pol]`, for the given range of mwalib
+/// coarse channel indices.
+#[cfg(feature = "mwalib")]
+pub fn digital_gains_from_mwalib(
+    meta_ctx: &MetafitsContext,
+    coarse_chan_range: &Range<usize>,
+) -> Array3<f64> {
+    Array3::from_shape_fn(
+        (meta_ctx.num_ants, coarse_chan_range.len(), 2),
+        |(ant_idx, coarse_chan_idx, pol_idx)| {
+            let ant = &meta_ctx.antennas[ant_idx];
+            let rf_input = if pol_idx == 0 {
+                &ant.rfinput_x
+            } else {
+                &ant.rfinput_y
+            };
+            rf_input.digital_gains[coarse_chan_range.start + coarse_chan_idx]
+        },
+    )
+}
+
+/// Divide out the digital gains from visibilities using the metadata in a
+/// [`mwalib::CorrelatorContext`]. See [`correct_digital_gains`].
+///
+/// # Errors
+///
+/// See [`correct_digital_gains`].
+#[cfg(feature = "mwalib")]
+pub fn correct_digital_gains_mwalib(
+    corr_ctx: &CorrelatorContext,
+    jones_array: ArrayViewMut3<Jones<f32>>,
+    coarse_chan_range: &Range<usize>,
+    baseline_idxs: &[usize],
+) -> Result<(), CorrectionError> {
+    let meta_ctx = &corr_ctx.metafits_context;
+    let gains = digital_gains_from_mwalib(meta_ctx, coarse_chan_range);
+    let ant_pairs: Vec<(usize, usize)> = baseline_idxs
+        .iter()
+        .map(|&idx| {
+            let baseline = &meta_ctx.baselines[idx];
+            (baseline.ant1_index, baseline.ant2_index)
+        })
+        .collect();
+    correct_digital_gains(jones_array, gains.view(), &ant_pairs)
+}
+
+/// The gains of the MWA coarse channel polyphase filterbank at a 10 kHz
+/// resolution, i.e. 128 fine channels per 1.28 MHz coarse channel. These are
+/// the gains used by cotter and Birli, which come from an old Levine MWA memo
+/// where they are derived from the filterbank coefficients.
+#[allow(clippy::excessive_precision)]
+pub const PFB_COTTER_2014_10KHZ: [f64; 128] = [
+    0.5002092286,
+    0.5025463233,
+    0.5095006003,
+    0.5209000511,
+    0.5364655027,
+    0.5558210624,
+    0.578507908,
+    0.6040009198,
+    0.6317276705,
+    0.6610877352,
+    0.6914726388,
+    0.7222853397,
+    0.7529582567,
+    0.7829676164,
+    0.811847953,
+    0.8392006276,
+    0.8647014754,
+    0.8881031286,
+    0.9092359525,
+    0.9280049655,
+    0.9443854775,
+    0.9584152159,
+    0.9701859925,
+    0.9798348905,
+    0.9875338705,
+    0.9934801632,
+    0.9978861616,
+    1.0009717022,
+    1.0029560443,
+    1.0040506,
+    1.0044541097,
+    1.0043486497,
+    1.0038957138,
+    1.0032352887,
+    1.0024841158,
+    1.0017360425,
+    1.0010627468,
+    1.0005145298,
+    1.0001227353,
+    0.9999016844,
+    0.9998508036,
+    0.9999576333,
+    1.0002001147,
+    1.0005493174,
+    1.0009719327,
+    1.0014325423,
+    1.0018965935,
+    1.0023317792,
+    1.0027098934,
+    1.003007776,
+    1.0032087236,
+    1.003302975,
+    1.0032879588,
+    1.003166914,
+    1.0029505018,
+    1.0026535597,
+    1.0022962373,
+    1.0019012075,
+    1.001492497,
+    1.0010952483,
+    1.0007327206,
+    1.0004265297,
+    1.0001941268,
+    1.0000490463,
+    1.0,
+    1.0000490463,
+    1.0001941268,
+    1.0004265297,
+    1.0007327206,
+    1.0010952483,
+    1.001492497,
+    1.0019012075,
+    1.0022962373,
+    1.0026535597,
+    1.0029505018,
+    1.003166914,
+    1.0032879588,
+    1.003302975,
+    1.0032087236,
+    1.003007776,
+    1.0027098934,
+    1.0023317792,
+    1.0018965935,
+    1.0014325423,
+    1.0009719327,
+    1.0005493174,
+    1.0002001147,
+    0.9999576333,
+    0.9998508036,
+    0.9999016844,
+    1.0001227353,
+    1.0005145298,
+    1.0010627468,
+    1.0017360425,
+    1.0024841158,
+    1.0032352887,
+    1.0038957138,
+    1.0043486497,
+    1.0044541097,
+    1.0040506,
+    1.0029560443,
+    1.0009717022,
+    0.9978861616,
+    0.9934801632,
+    0.9875338705,
+    0.9798348905,
+    0.9701859925,
+    0.9584152159,
+    0.9443854775,
+    0.9280049655,
+    0.9092359525,
+    0.8881031286,
+    0.8647014754,
+    0.8392006276,
+    0.811847953,
+    0.7829676164,
+    0.7529582567,
+    0.7222853397,
+    0.6914726388,
+    0.6610877352,
+    0.6317276705,
+    0.6040009198,
+    0.578507908,
+    0.5558210624,
+    0.5364655027,
+    0.5209000511,
+    0.5095006003,
+    0.5025463233,
+];
+
+/// Get the built-in MWA coarse channel passband gains (see
+/// [`PFB_COTTER_2014_10KHZ`]) at a fine channel resolution of
+/// `fine_chan_width_hz`, e.g. 10, 20 or 40 kHz. The gains are averaged in the
+/// same way as the legacy correlator averages fine channels, and can be given
+/// to [`correct_passband`].
+///
+/// # Errors
+///
+/// Will return [`CorrectionError::BadPassbandResolution`] if
+/// `fine_chan_width_hz` isn't a multiple of 10 kHz that divides the 1.28 MHz
+/// coarse channel.
+pub fn mwa_passband_gains(fine_chan_width_hz: u32) -> Result<Vec<f64>, CorrectionError> {
+    const NATIVE_WIDTH_HZ: u32 = 10_000;
+    let factor = fine_chan_width_hz / NATIVE_WIDTH_HZ;
+    if fine_chan_width_hz % NATIVE_WIDTH_HZ != 0 || factor == 0 {
+        return Err(CorrectionError::BadPassbandResolution { fine_chan_width_hz });
+    }
+    rebin_passband(&PFB_COTTER_2014_10KHZ, factor as usize)
+        .map_err(|_| CorrectionError::BadPassbandResolution { fine_chan_width_hz })
+}
+
+/// Average every `factor` adjacent gains of a passband shape, e.g. to convert a
+/// 10 kHz coarse channel shape into a 40 kHz one with a `factor` of 4.
+///
+/// # Errors
+///
+/// Will return [`CorrectionError::BadArrayShape`] if `factor` is 0 or doesn't
+/// divide the length of `passband_gains`.
+pub fn rebin_passband(passband_gains: &[f64], factor: usize) -> Result<Vec<f64>, CorrectionError> {
+    if factor == 0 || passband_gains.len() % factor != 0 {
+        return Err(CorrectionError::BadArrayShape {
+            argument: "passband_gains".into(),
+            function: "rebin_passband".into(),
+            expected: format!("(n,) where n is a multiple of {}", factor),
+            received: format!("({},)", passband_gains.len()),
+        });
+    }
+    Ok(passband_gains
+        .chunks(factor)
+        .map(|chunk| chunk.iter().sum::<f64>() / factor as f64)
+        .collect())
+}
+
+/// Divide out the shape of the coarse channel polyphase filterbank from
+/// visibilities.
+///
+/// - `jones_array` - visibilities with dimensions `[timestep][channel][baseline]`
+/// - `passband_gains` - the gain of each fine channel within a coarse channel.
+///   The channels of `jones_array` must be a whole number of coarse
+///   channels. Use [`rebin_passband`] to match the shape to the fine channel
+///   resolution.
+///
+/// # Errors
+///
+/// Will return [`CorrectionError::BadArrayShape`] if the channels of
+/// `jones_array` aren't a whole number of coarse channels.
+pub fn correct_passband(
+    mut jones_array: ArrayViewMut3<Jones<f32>>,
+    passband_gains: &[f64],
+) -> Result<(), CorrectionError> {
+    let (_, num_chans, _) = jones_array.dim();
+    if passband_gains.is_empty() || num_chans % passband_gains.len() != 0 {
+        return Err(CorrectionError::BadArrayShape {
+            argument: "passband_gains".into(),
+            function: "correct_passband".into(),
+            expected: format!("(n,) where n divides {}", num_chans),
+            received: format!("({},)", passband_gains.len()),
+        });
+    }
+    let factors: Vec<f32> = passband_gains
+        .iter()
+        .map(|&gain| (1.0 / gain) as f32)
+        .collect();
+
+    jones_array
+        .axis_chunks_iter_mut(Axis(1), factors.len())
+        .into_par_iter()
+        .for_each(|mut coarse_chan_jones| {
+            for (mut chan_jones, &factor) in coarse_chan_jones.axis_iter_mut(Axis(1)).zip(&factors)
+            {
+                for jones in &mut chan_jones {
+                    *jones *= factor;
+                }
+            }
+        });
+
+    Ok(())
+}
+
+/// The maximum output level of the MWA legacy correlator's 4-bit quantiser.
+/// The quantiser's output levels are the integers `-7..=7`, and its thresholds
+/// are the half integers in between.
+const VAN_VLECK_MAX_LEVEL: usize = 7;
+
+/// Get the standard deviation of the 4-bit quantised output of a zero-mean
+/// Gaussian signal with standard deviation `sigma` (in units of the
+/// quantisation step).
+pub fn van_vleck_sighat(sigma: f64) -> f64 {
+    let sum: f64 = (0..VAN_VLECK_MAX_LEVEL)
+        .map(|k| {
+            let threshold = k as f64 + 0.5;
+            (2 * k + 1) as f64 * libm::erf(threshold / (sigma * 2_f64.sqrt()))
+        })
+        .sum();
+    ((VAN_VLECK_MAX_LEVEL * VAN_VLECK_MAX_LEVEL) as f64 - sum)
+        .max(0.0)
+        .sqrt()
+}
+
+/// The derivative of [`van_vleck_sighat`] with respect to `sigma`.
+fn van_vleck_sighat_prime(sigma: f64, sighat: f64) -> f64 {
+    let sum: f64 = (0..VAN_VLECK_MAX_LEVEL)
+        .map(|k| {
+            let threshold = k as f64 + 0.5;
+            (2 * k + 1) as f64
+                * (2.0 / PI).sqrt()
+                * threshold
+                * (-threshold * threshold / (2.0 * sigma * sigma)).exp()
+                / (sigma * sigma)
+        })
+        .sum();
+    sum / (2.0 * sighat)
+}
+
+/// Invert [`van_vleck_sighat`] with Newton's method, i.e. get the standard
+/// deviation of a Gaussian signal from the standard deviation of its 4-bit
+/// quantised output. `sighat` values that can't be inverted (non-positive, or
+/// saturated at the maximum quantiser level) are returned unchanged.
+pub fn van_vleck_sigma(sighat: f64) -> f64 {
+    if sighat.is_nan() || sighat <= 0.0 || sighat >= VAN_VLECK_MAX_LEVEL as f64 {
+        return sighat;
+    }
+    let mut sigma = sighat;
+    for _ in 0..100 {
+        let estimate = van_vleck_sighat(sigma);
+        let step = (estimate - sighat) / van_vleck_sighat_prime(sigma, estimate);
+        // Don't let the estimate jump to (or past) 0.
+        sigma = (sigma - step).max(sigma / 2.0);
+        if step.abs() < 1e-12 * sigma {
+            break;
+        }
+    }
+    sigma
+}
+
+/// Get the gain of the 4-bit quantiser on the covariance of two weakly
+/// correlated Gaussian signals, per signal, i.e. the quantised covariance is
+/// the true covariance multiplied by `van_vleck_cross_gain(sigma1) *
+/// van_vleck_cross_gain(sigma2)`. This is the first term of the series
+/// expansion of the quantised covariance in the correlation coefficient, and
+/// is accurate to third order.
+pub fn van_vleck_cross_gain(sigma: f64) -> f64 {
+    (0..VAN_VLECK_MAX_LEVEL)
+        .map(|k| {
+            let threshold = k as f64 + 0.5;
+            // Thresholds at +/- threshold.
+            2.0 * (-threshold * threshold / (2.0 * sigma * sigma)).exp() / (sigma * TAU.sqrt())
+        })
+        .sum()
+}
+
+/// Correct visibilities from the MWA legacy correlator for the 4-bit
+/// quantisation of its inputs (the Van Vleck correction).
+///
+/// The standard deviation of each antenna's signals is found by inverting the
+/// quantised auto-correlations (see [`van_vleck_sigma`]), and the
+/// cross-correlations are divided by the quantiser gains of both signals (see
+/// [`van_vleck_cross_gain`]). The latter is a series expansion which is
+/// accurate for the small correlation coefficients of MWA cross-correlations.
+///
+/// - `jones_array` - visibilities with dimensions `[timestep][channel][baseline]`
+/// - `ant_pairs` - the antenna indices of each baseline. The auto-correlations
+///   of every antenna in a cross-correlation must be present.
+/// - `num_samples` - the number of complex samples accumulated into each
+///   visibility, i.e. the fine channel width multiplied by the integration
+///   time.
+///
+/// This should be applied before any other corrections, as they change the
+/// scale of the auto-correlations.
+///
+/// # Errors
+///
+/// Will return [`CorrectionError::BadArrayShape`] if the length of `ant_pairs`
+/// doesn't match `jones_array`, or [`CorrectionError::MissingAuto`] if an
+/// auto-correlation is missing.
+pub fn correct_van_vleck(
+    mut jones_array: ArrayViewMut3<Jones<f32>>,
+    ant_pairs: &[(usize, usize)],
+    num_samples: f64,
+) -> Result<(), CorrectionError> {
+    let (_, _, num_baselines) = jones_array.dim();
+    if ant_pairs.len() != num_baselines {
+        return Err(CorrectionError::BadArrayShape {
+            argument: "ant_pairs".into(),
+            function: "correct_van_vleck".into(),
+            expected: format!("({},)", num_baselines),
+            received: format!("({},)", ant_pairs.len()),
+        });
+    }
+    // The baseline index of each antenna's auto-correlation.
+    let num_ants = ant_pairs
+        .iter()
+        .map(|&(ant1, ant2)| ant1.max(ant2) + 1)
+        .max()
+        .unwrap_or(0);
+    let mut auto_idxs = vec![None; num_ants];
+    for (bl_idx, &(ant1, ant2)) in ant_pairs.iter().enumerate() {
+        if ant1 == ant2 {
+            auto_idxs[ant1] = Some(bl_idx);
+        }
+    }
+    for &(ant1, ant2) in ant_pairs {
+        if let Some(missing) = [ant1, ant2]
+            .into_iter()
+            .find(|&ant| auto_idxs[ant].is_none())
+        {
+            return Err(CorrectionError::MissingAuto {
+                ant1,
+                ant2,
+                missing,
+            });
+        }
+    }
+
+    // The auto-correlations are the sum of the squares of both components of
+    // each complex sample.
+    let norm = 2.0 * num_samples;
+    jones_array
+        .axis_iter_mut(Axis(0))
+        .into_par_iter()
+        .for_each(|mut jones_2d| {
+            let mut sigmas = vec![[0.0; 2]; num_ants];
+            let mut gains = vec![[1.0; 2]; num_ants];
+            for mut chan_jones in jones_2d.outer_iter_mut() {
+                for (ant, &auto_idx) in auto_idxs.iter().enumerate() {
+                    if let Some(auto_idx) = auto_idx {
+                        let auto = chan_jones[auto_idx];
+                        for (pol, vis) in [auto[0], auto[3]].into_iter().enumerate() {
+                            let sighat = (vis.re as f64 / norm).sqrt();
+                            let sigma = van_vleck_sigma(sighat);
+                            sigmas[ant][pol] = sigma;
+                            gains[ant][pol] = if sigma.is_finite() && sigma > 0.0 {
+                                van_vleck_cross_gain(sigma)
+                            } else {
+                                1.0
+                            };
+                        }
+                    }
+                }
+
+                for (jones, &(ant1, ant2)) in chan_jones.iter_mut().zip(ant_pairs) {
+                    // XX, XY, YX, YY
+                    for (vis, (pol1, pol2)) in
+                        jones.iter_mut().zip([(0, 0), (0, 1), (1, 0), (1, 1)])
+                    {
+                        if ant1 == ant2 && pol1 == pol2 {
+                            let sigma = sigmas[ant1][pol1];
+                            if sigma.is_finite() && sigma > 0.0 {
+                                *vis = Complex::new((sigma * sigma * norm) as f32, 0.0);
+                            }
+                        } else {
+                            *vis /= (gains[ant1][pol1] * gains[ant2][pol2]) as f32;
+                        }
+                    }
+                }
+            }
+        });
+
+    Ok(())
+}
+
+/// Phase track raw correlator visibilities, which are not phase tracked, to
+/// `phase_centre`. For each timestep, the antenna positions are precessed to
+/// J2000 at the timestep's centroid, and each visibility is rotated by
+/// `exp(-2πi w f / c)`.
+///
+/// - `jones_array` - visibilities with dimensions [`VisContext::sel_dims`]
+/// - `vis_ctx` - the selected timesteps, channels and baselines of `jones_array`
+/// - `tile_xyzs` - the geodetic position of each antenna, indexed by the
+///   antenna indices in `vis_ctx.sel_baselines`
+/// - `array_pos` - the position of the array
+/// - `phase_centre` - the phase centre to track
+/// - `dut1` - UT1 - UTC, which can be 0 seconds if it isn't known
+///
+/// This matches the geometric correction in cotter and Birli.
+///
+/// # Errors
+///
+/// Will return [`CorrectionError::BadArrayShape`] if the shape of `jones_array`
+/// doesn't match `vis_ctx`.
+pub fn correct_geometry(
+    mut jones_array: ArrayViewMut3<Jones<f32>>,
+    vis_ctx: &VisContext,
+    tile_xyzs: &[XyzGeodetic],
+    array_pos: LatLngHeight,
+    phase_centre: RADec,
+    dut1: Duration,
+) -> Result<(), CorrectionError> {
+    if jones_array.dim() != vis_ctx.sel_dims() {
+        return Err(CorrectionError::BadArrayShape {
+            argument: "jones_array".into(),
+            function: "correct_geometry".into(),
+            expected: format!("{:?}", vis_ctx.sel_dims()),
+            received: format!("{:?}", jones_array.dim()),
+        });
+    }
+    let frequencies_hz = vis_ctx.frequencies_hz();
+
+    for (mut jones_2d, centroid_timestamp) in jones_array
+        .outer_iter_mut()
+        .zip(vis_ctx.timeseries(false, true))
+    {
+        let prec_info = precess_time(
+            array_pos.longitude_rad,
+            array_pos.latitude_rad,
+            phase_centre,
+            centroid_timestamp,
+            dut1,
+        );
+        let tiles_xyz_precessed = prec_info.precess_xyz_parallel(tile_xyzs);
+        let uvws = baselines_to_uvws(
+            &XyzGeodetic::to_selected_baselines(&tiles_xyz_precessed, &vis_ctx.sel_baselines),
+            prec_info.hadec_j2000,
+        );
+
+        jones_2d
+            .axis_iter_mut(Axis(1))
+            .into_par_iter()
+            .zip(uvws)
+            .for_each(|(mut bl_jones, uvw)| {
+                for (jones, &freq_hz) in bl_jones.iter_mut().zip(&frequencies_hz) {
+                    let rotation = Complex::from_polar(1.0, -TAU * uvw.w * freq_hz / VEL_C);
+                    *jones *= Complex::new(rotation.re as f32, rotation.im as f32);
+                }
+            });
+    }
+
+    Ok(())
+}
+
+/// Fringe stop phase tracked visibilities toward `direction`, so that a source
+/// there has a constant phase, e.g. to peel it or to track a source that isn't
+/// at the phase centre. For each timestep, the antenna positions are precessed
+/// to J2000 at the timestep's centroid (as in [`correct_geometry`]), and each
+/// visibility is rotated by `exp(-2πi (ul + vm + w(n - 1)))`, where `(l, m, n)`
+/// is the direction relative to `phase_centre`.
+///
+/// - `jones_array` - visibilities with dimensions [`VisContext::sel_dims`],
+///   phase tracked to `phase_centre`
+/// - `vis_ctx` - the selected timesteps, channels and baselines of `jones_array`
+/// - `tile_xyzs` - the geodetic position of each antenna, indexed by the
+///   antenna indices in `vis_ctx.sel_baselines`
+/// - `array_pos` - the position of the array
+/// - `phase_centre` - the phase centre of the visibilities
+/// - `direction` - the direction to fringe stop toward
+/// - `dut1` - UT1 - UTC, which can be 0 seconds if it isn't known
+///
+/// The visibilities are still phase tracked to `phase_centre` (i.e. the `uvw`s
+/// are unchanged); fringe stopping to the phase centre does nothing.
+///
+/// # Errors
+///
+/// Will return [`CorrectionError::BadArrayShape`] if the shape of `jones_array`
+/// doesn't match `vis_ctx`.
+#[allow(clippy::too_many_arguments)]
+pub fn fringe_stop(
+    mut jones_array: ArrayViewMut3<Jones<f32>>,
+    vis_ctx: &VisContext,
+    tile_xyzs: &[XyzGeodetic],
+    array_pos: LatLngHeight,
+    phase_centre: RADec,
+    direction: RADec,
+    dut1: Duration,
+) -> Result<(), CorrectionError> {
+    if jones_array.dim() != vis_ctx.sel_dims() {
+        return Err(CorrectionError::BadArrayShape {
+            argument: "jones_array".into(),
+            function: "fringe_stop".into(),
+            expected: format!("{:?}", vis_ctx.sel_dims()),
+            received: format!("{:?}", jones_array.dim()),
+        });
+    }
+    let frequencies_hz = vis_ctx.frequencies_hz();
+    let lmn = direction.to_lmn(phase_centre).prepare_for_rime();
+
+    for (jones_2d, centroid_timestamp) in jones_array
+        .outer_iter_mut()
+        .zip(vis_ctx.timeseries(false, true))
+    {
+        let prec_info = precess_time(
+            array_pos.longitude_rad,
+            array_pos.latitude_rad,
+            phase_centre,
+            centroid_timestamp,
+            dut1,
+        );
+        let tiles_xyz_precessed = prec_info.precess_xyz_parallel(tile_xyzs);
+        let uvws = baselines_to_uvws(
+            &XyzGeodetic::to_selected_baselines(&tiles_xyz_precessed, &vis_ctx.sel_baselines),
+            prec_info.hadec_j2000,
+        );
+        fringe_stop_uvws(jones_2d, &uvws, &frequencies_hz, lmn)?;
+    }
+
+    Ok(())
+}
+
+/// Fringe stop the visibilities of a single timestep toward `lmn`, given the
+/// [`UVW`] of each baseline \[metres\]: each visibility is rotated by
+/// `exp(-i lmn.dot(uvw) f / c)`. See [`fringe_stop`].
+///
+/// - `jones_array` - visibilities with dimensions `[channel][baseline]`
+/// - `uvws` - the [`UVW`] of each baseline \[metres\]
+/// - `frequencies_hz` - the frequency of each channel \[Hz\]
+/// - `lmn` - the direction to fringe stop toward, relative to the phase centre
+///
+/// # Errors
+///
+/// Will return [`CorrectionError::BadArrayShape`] if the shape of `jones_array`
+/// doesn't match `uvws` and `frequencies_hz`.
+pub fn fringe_stop_uvws(
+    mut jones_array: ArrayViewMut2<Jones<f32>>,
+    uvws: &[UVW],
+    frequencies_hz: &[f64],
+    lmn: LmnRime,
+) -> Result<(), CorrectionError> {
+    let expected_dims = (frequencies_hz.len(), uvws.len());
+    if jones_array.dim() != expected_dims {
+        return Err(CorrectionError::BadArrayShape {
+            argument: "jones_array".into(),
+            function: "fringe_stop_uvws".into(),
+            expected: format!("{:?}", expected_dims),
+            received: format!("{:?}", jones_array.dim()),
+        });
+    }
+
+    jones_array
+        .axis_iter_mut(Axis(1))
+        .into_par_iter()
+        .zip(uvws)
+        .for_each(|(mut bl_jones, &uvw)| {
+            let phase_per_hz = -lmn.dot(uvw) / VEL_C;
+            for (jones, &freq_hz) in bl_jones.iter_mut().zip(frequencies_hz) {
+                let rotation = Complex::from_polar(1.0, phase_per_hz * freq_hz);
+                *jones *= Complex::new(rotation.re as f32, rotation.im as f32);
+            }
+        });
+
+    Ok(())
+}
+
+/// Apply direction-independent gains to visibilities, i.e. `G1 V G2^H` for
+/// each baseline, where `G1` and `G2` are the gains of the baseline's
+/// antennas. To correct visibilities with calibration solutions, apply the
+/// inverses of the solutions (see [`Jones::inv`]).
+///
+/// - `jones_array` - visibilities with dimensions `[timestep][channel][baseline]`
+/// - `gains` - the gains with dimensions `[antenna][channel]`
+/// - `ant_pairs` - the antenna indices of each baseline
+///
+/// # Errors
+///
+/// Will return [`CorrectionError::BadArrayShape`] if the length of `ant_pairs`
+/// doesn't match `jones_array`, or `gains` doesn't have a gain for every
+/// channel and antenna.
+pub fn apply_di_gains(
+    mut jones_array: ArrayViewMut3<Jones<f32>>,
+    gains: ArrayView2<Jones<f64>>,
+    ant_pairs: &[(usize, usize)],
+) -> Result<(), CorrectionError> {
+    let (_, num_chans, num_baselines) = jones_array.dim();
+    if ant_pairs.len() != num_baselines {
+        return Err(CorrectionError::BadArrayShape {
+            argument: "ant_pairs".into(),
+            function: "apply_di_gains".into(),
+            expected: format!("({},)", num_baselines),
+            received: format!("({},)", ant_pairs.len()),
+        });
+    }
+    let num_ants = ant_pairs
+        .iter()
+        .map(|&(ant1, ant2)| ant1.max(ant2) + 1)
+        .max()
+        .unwrap_or(0);
+    let (num_gain_ants, num_gain_chans) = gains.dim();
+    if num_gain_ants < num_ants || num_gain_chans != num_chans {
+        return Err(CorrectionError::BadArrayShape {
+            argument: "gains".into(),
+            function: "apply_di_gains".into(),
+            expected: format!("(>={}, {})", num_ants, num_chans),
+            received: format!("{:?}", gains.dim()),
+        });
+    }
+
+    jones_array
+        .axis_iter_mut(Axis(2))
+        .into_par_iter()
+        .zip(ant_pairs)
+        .for_each(|(mut bl_jones, &(ant1, ant2))| {
+            let (gains1, gains2) = (gains.row(ant1), gains.row(ant2));
+            for ((mut chan_jones, g1), g2) in
+                bl_jones.axis_iter_mut(Axis(1)).zip(gains1).zip(gains2)
+            {
+                for jones in &mut chan_jones {
+                    *jones = Jones::from(*g1 * Jones::<f64>::from(*jones) * g2.h());
+                }
+            }
+        });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+    use crate::{AveragingPolicy, VisUnit};
+
+    #[test]
+    fn test_correct_cable_lengths() {
+        let freq_hz = 150e6;
+        // A quarter-wavelength difference in the X cables of the second antenna.
+        let cable_lengths_m = array![[0.0, 0.0], [VEL_C / freq_hz / 4.0, 0.0]];
+        let mut jones_array = Array3::from_elem((2, 1, 2), Jones::<f32>::identity());
+        correct_cable_lengths(
+            jones_array.view_mut(),
+            &[freq_hz],
+            &[(0, 0), (0, 1)],
+            cable_lengths_m.view(),
+        )
+        .unwrap();
+
+        // Autos are unaffected.
+        assert_abs_diff_eq!(jones_array[(0, 0, 0)], Jones::identity(), epsilon = 1e-6);
+        // XX is rotated by -90 degrees, YY is unaffected.
+        let expected = Jones::from([
+            Complex::new(0.0, -1.0),
+            Complex::new(0.0, 0.0),
+            Complex::new(0.0, 0.0),
+            Complex::new(1.0, 0.0),
+        ]);
+        assert_abs_diff_eq!(jones_array[(1, 0, 1)], expected, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_correct_delays() {
+        let freq_hz = 150e6;
+        // A half-period delay on the second antenna flips the sign of all pols.
+        let mut jones_array = Array3::from_elem((1, 1, 2), Jones::<f32>::identity());
+        correct_delays(
+            jones_array.view_mut(),
+            &[freq_hz],
+            &[(0, 0), (0, 1)],
+            &[0.0, 0.5 / freq_hz],
+        )
+        .unwrap();
+
+        assert_abs_diff_eq!(jones_array[(0, 0, 0)], Jones::identity(), epsilon = 1e-6);
+        assert_abs_diff_eq!(
+            jones_array[(0, 0, 1)],
+            Jones::identity() * -1.0,
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    fn test_correct_digital_gains() {
+        // 2 antennas, 2 coarse channels with 2 fine channels each.
+        let gains = Array3::from_shape_fn((2, 2, 2), |(ant, cc, pol)| {
+            1.0 + ant as f64 + 2.0 * cc as f64 + 4.0 * pol as f64
+        });
+        let mut jones_array =
+            Array3::from_elem((1, 4, 1), Jones::from([Complex::new(1.0, 1.0); 4]));
+        correct_digital_gains(jones_array.view_mut(), gains.view(), &[(0, 1)]).unwrap();
+
+        let expected_xx = 1.0 / 2.0;
+        let expected_yy = 1.0 / 30.0;
+        assert_abs_diff_eq!(jones_array[(0, 1, 0)][0].re, expected_xx);
+        assert_abs_diff_eq!(jones_array[(0, 1, 0)][3].im, expected_yy);
+        let expected_xy = 1.0 / 24.0;
+        assert_abs_diff_eq!(jones_array[(0, 2, 0)][1].re, expected_xy);
+    }
+
+    #[test]
+    fn test_correct_digital_gains_bad_shape() {
+        let gains = Array3::ones((2, 3, 2));
+        let mut jones_array = Array3::from_elem((1, 4, 1), Jones::<f32>::identity());
+        assert!(matches!(
+            correct_digital_gains(jones_array.view_mut(), gains.view(), &[(0, 1)]),
+            Err(CorrectionError::BadArrayShape { .. })
+        ));
+    }
+
+    #[test]
+    fn test_apply_di_gains() {
+        // 3 antennas and 2 channels.
+        let gains = Array2::from_shape_fn((3, 2), |(ant, chan)| {
+            Jones::from([
+                Complex::new(1.0 + ant as f64, chan as f64),
+                Complex::new(0.1, 0.0),
+                Complex::new(0.0, -0.2 * ant as f64),
+                Complex::new(2.0, -(chan as f64)),
+            ])
+        });
+        let ant_pairs = [(0, 0), (0, 2), (1, 2)];
+        let vis = Jones::from([
+            Complex::new(1.0, 0.5),
+            Complex::new(0.1, -0.1),
+            Complex::new(-0.2, 0.0),
+            Complex::new(0.5, 2.0),
+        ]);
+        let mut jones_array = Array3::from_elem((2, 2, 3), vis);
+        apply_di_gains(jones_array.view_mut(), gains.view(), &ant_pairs).unwrap();
+
+        for ((_, chan, bl), jones) in jones_array.indexed_iter() {
+            let (ant1, ant2) = ant_pairs[bl];
+            let expected = gains[(ant1, chan)] * Jones::<f64>::from(vis) * gains[(ant2, chan)].h();
+            assert_abs_diff_eq!(Jones::<f64>::from(*jones), expected, epsilon = 1e-5);
+        }
+
+        // Applying the inverse gains restores the visibilities.
+        let inv_gains = gains.mapv(|g| g.inv());
+        apply_di_gains(jones_array.view_mut(), inv_gains.view(), &ant_pairs).unwrap();
+        for jones in &jones_array {
+            assert_abs_diff_eq!(*jones, vis, epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_apply_di_gains_bad_shape() {
+        let mut jones_array = Array3::from_elem((1, 2, 2), Jones::<f32>::identity());
+        // Missing a gain for antenna 2.
+        let gains = Array2::from_elem((2, 2), Jones::<f64>::identity());
+        assert!(matches!(
+            apply_di_gains(jones_array.view_mut(), gains.view(), &[(0, 1), (1, 2)]),
+            Err(CorrectionError::BadArrayShape { .. })
+        ));
+        // Wrong number of channels.
+        let gains = Array2::from_elem((3, 3), Jones::<f64>::identity());
+        assert!(matches!(
+            apply_di_gains(jones_array.view_mut(), gains.view(), &[(0, 1), (1, 2)]),
+            Err(CorrectionError::BadArrayShape { .. })
+        ));
+        // Wrong number of baselines.
+        assert!(matches!(
+            apply_di_gains(jones_array.view_mut(), gains.view(), &[(0, 1)]),
+            Err(CorrectionError::BadArrayShape { .. })
+        ));
+    }
+
+    #[test]
+    fn test_rebin_passband() {
+        let rebinned = rebin_passband(&[0.5, 1.0, 1.0, 1.5, 2.0, 0.0], 2).unwrap();
+        assert_eq!(rebinned.len(), 3);
+        assert_abs_diff_eq!(rebinned[0], 0.75);
+        assert_abs_diff_eq!(rebinned[1], 1.25);
+        assert_abs_diff_eq!(rebinned[2], 1.0);
+        assert!(matches!(
+            rebin_passband(&[1.0; 6], 4),
+            Err(CorrectionError::BadArrayShape { .. })
+        ));
+    }
+
+    #[test]
+    fn test_mwa_passband_gains() {
+        assert_eq!(mwa_passband_gains(10_000).unwrap(), PFB_COTTER_2014_10KHZ);
+
+        // Expected values are Birli's "simple" (legacy correlator) scrunch of
+        // the cotter 2014 gains.
+        let gains_20khz = mwa_passband_gains(20_000).unwrap();
+        assert_eq!(gains_20khz.len(), 64);
+        assert_abs_diff_eq!(gains_20khz[0], 0.50137777595, epsilon = 1e-12);
+        assert_abs_diff_eq!(gains_20khz[32], 1.00002452315, epsilon = 1e-12);
+        let gains_40khz = mwa_passband_gains(40_000).unwrap();
+        assert_eq!(gains_40khz.len(), 32);
+        assert_abs_diff_eq!(gains_40khz[0], 0.508289050825, epsilon = 1e-12);
+        assert_abs_diff_eq!(gains_40khz[8], 1.0028377902, epsilon = 1e-12);
+        assert_abs_diff_eq!(gains_40khz[16], 1.0001674257, epsilon = 1e-12);
+
+        // Correcting flat visibilities at 40 kHz leaves the inverse of the
+        // gains in each coarse channel.
+        let mut jones_array = Array3::from_elem((1, 64, 2), Jones::<f32>::identity());
+        correct_passband(jones_array.view_mut(), &gains_40khz).unwrap();
+        for (chan_idx, chan_jones) in jones_array.axis_iter(Axis(1)).enumerate() {
+            let expected = (1.0 / gains_40khz[chan_idx % 32]) as f32;
+            for jones in chan_jones.iter() {
+                assert_abs_diff_eq!(*jones, Jones::identity() * expected);
+            }
+        }
+        assert_abs_diff_eq!(
+            jones_array[(0, 32, 0)][0].re,
+            1.0 / 0.508289050825,
+            epsilon = 1e-6
+        );
+
+        for width in [0, 5_000, 15_000, 30_000, 2_560_000] {
+            assert!(matches!(
+                mwa_passband_gains(width),
+                Err(CorrectionError::BadPassbandResolution { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_correct_passband() {
+        // 2 coarse channels with 2 fine channels each.
+        let mut jones_array = Array3::from_elem((1, 4, 2), Jones::<f32>::identity());
+        correct_passband(jones_array.view_mut(), &[0.5, 2.0]).unwrap();
+        for (chan_idx, chan_jones) in jones_array.axis_iter(Axis(1)).enumerate() {
+            let expected = if chan_idx % 2 == 0 { 2.0 } else { 0.5 };
+            for jones in chan_jones.iter() {
+                assert_abs_diff_eq!(*jones, Jones::identity() * expected);
+            }
+        }
+        assert!(matches!(
+            correct_passband(jones_array.view_mut(), &[1.0; 3]),
+            Err(CorrectionError::BadArrayShape { .. })
+        ));
+    }
+
+    #[test]
+    fn test_van_vleck_sigma() {
+        for sigma in [0.3, 1.0, 2.5, 5.0] {
+            let sighat = van_vleck_sighat(sigma);
+            assert_abs_diff_eq!(van_vleck_sigma(sighat), sigma, epsilon = 1e-9);
+        }
+        // Quantisation has little effect on well-sampled signals...
+        assert_abs_diff_eq!(van_vleck_sighat(2.0), 2.0, epsilon = 0.05);
+        assert_abs_diff_eq!(van_vleck_cross_gain(2.0), 1.0, epsilon = 1e-3);
+        // ... but a large effect on poorly sampled ones.
+        assert!(van_vleck_sighat(0.2) < 0.2 * 0.9);
+        assert!(van_vleck_cross_gain(0.2) < 0.5);
+    }
+
+    #[test]
+    fn test_correct_van_vleck() {
+        let num_samples = 10_000.0;
+        let sigmas = [0.5_f64, 3.0];
+        let auto = |sigma: f64| {
+            let power = van_vleck_sighat(sigma).powi(2) * 2.0 * num_samples;
+            Complex::new(power as f32, 0.0)
+        };
+        let cross = Complex::new(100.0, -50.0);
+        let mut jones_array = Array3::from_shape_fn((1, 1, 3), |(_, _, bl)| match bl {
+            0 => Jones::from([auto(sigmas[0]), cross, cross, auto(sigmas[0])]),
+            1 => Jones::from([cross; 4]),
+            _ => Jones::from([auto(sigmas[1]), cross, cross, auto(sigmas[1])]),
+        });
+        correct_van_vleck(
+            jones_array.view_mut(),
+            &[(0, 0), (0, 1), (1, 1)],
+            num_samples,
+        )
+        .unwrap();
+
+        for (bl, &sigma) in [0, 2].into_iter().zip(&sigmas) {
+            let power = sigma * sigma * 2.0 * num_samples;
+            assert_abs_diff_eq!(jones_array[(0, 0, bl)][0].re, power as f32, epsilon = 1.0);
+            assert_abs_diff_eq!(jones_array[(0, 0, bl)][3].re, power as f32, epsilon = 1.0);
+        }
+        let gain = (van_vleck_cross_gain(sigmas[0]) * van_vleck_cross_gain(sigmas[1])) as f32;
+        assert_abs_diff_eq!(jones_array[(0, 0, 1)][1], cross / gain, epsilon = 1e-3);
+        let gain = van_vleck_cross_gain(sigmas[0]).powi(2) as f32;
+        assert_abs_diff_eq!(jones_array[(0, 0, 0)][2], cross / gain, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_correct_van_vleck_missing_auto() {
+        let mut jones_array = Array3::from_elem((1, 1, 2), Jones::<f32>::identity());
+        assert!(matches!(
+            correct_van_vleck(jones_array.view_mut(), &[(0, 0), (0, 1)], 1.0),
+            Err(CorrectionError::MissingAuto { missing: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_correct_geometry() {
+        use hifitime::{Epoch, Unit};
+
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 2,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_f64(2., Unit::Second),
+            num_sel_chans: 2,
+            start_freq_hz: 150e6,
+            freq_resolution_hz: 40e3,
+            sel_baselines: vec![(0, 0), (0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+        let tile_xyzs = [
+            XyzGeodetic {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            XyzGeodetic {
+                x: 100.0,
+                y: -50.0,
+                z: 20.0,
+            },
+        ];
+        let array_pos = LatLngHeight::new_mwa();
+        let phase_centre = RADec::new_degrees(0.0, -27.0);
+        let dut1 = Duration::from_f64(0.0, Unit::Second);
+        let mut jones_array = Array3::from_elem(vis_ctx.sel_dims(), Jones::<f32>::identity());
+        correct_geometry(
+            jones_array.view_mut(),
+            &vis_ctx,
+            &tile_xyzs,
+            array_pos,
+            phase_centre,
+            dut1,
+        )
+        .unwrap();
+
+        for (jones_2d, centroid_timestamp) in jones_array
+            .outer_iter()
+            .zip(vis_ctx.timeseries(false, true))
+        {
+            // Autos are unaffected.
+            assert_abs_diff_eq!(jones_2d[(0, 0)], Jones::identity(), epsilon = 1e-6);
+
+            let prec_info = precess_time(
+                array_pos.longitude_rad,
+                array_pos.latitude_rad,
+                phase_centre,
+                centroid_timestamp,
+                dut1,
+            );
+            let precessed = prec_info.precess_xyz_parallel(&tile_xyzs);
+            let uvw = UVW::from_xyz(precessed[0] - precessed[1], prec_info.hadec_j2000);
+            for (jones, freq_hz) in jones_2d.column(1).iter().zip(vis_ctx.frequencies_hz()) {
+                let rotation = Complex::from_polar(1.0, -TAU * uvw.w * freq_hz / VEL_C);
+                let rotation = Complex::new(rotation.re as f32, rotation.im as f32);
+                let expected =
+                    Jones::from([rotation, Complex::default(), Complex::default(), rotation]);
+                assert_abs_diff_eq!(*jones, expected, epsilon = 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_correct_geometry_bad_shape() {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 1,
+            start_timestamp: hifitime::Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_f64(1., hifitime::Unit::Second),
+            num_sel_chans: 2,
+            start_freq_hz: 150e6,
+            freq_resolution_hz: 40e3,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+        let mut jones_array = Array3::from_elem((1, 3, 1), Jones::<f32>::identity());
+        assert!(matches!(
+            correct_geometry(
+                jones_array.view_mut(),
+                &vis_ctx,
+                &[XyzGeodetic::default(); 2],
+                LatLngHeight::new_mwa(),
+                RADec::new(0.0, 0.0),
+                Duration::from_f64(0.0, hifitime::Unit::Second),
+            ),
+            Err(CorrectionError::BadArrayShape { .. })
+        ));
+    }
+
+    #[test]
+    fn test_fringe_stop() {
+        use crate::synth::{make_obs_ctx, make_vis_ctx, PointSource, VisSimulator};
+
+        let obs_ctx = make_obs_ctx(8);
+        let vis_ctx = make_vis_ctx(8, 3, 4);
+        let direction = RADec::new_degrees(5.0, -20.0);
+        let (mut jones_array, _) = VisSimulator::new(0)
+            .point_source(PointSource::new(direction, 2.0))
+            .simulate(&vis_ctx, &obs_ctx);
+        // Away from the phase centre, the phase of the source varies.
+        assert!(jones_array.iter().any(|jones| jones[0].im.abs() > 0.5));
+        let tile_xyzs: Vec<XyzGeodetic> = obs_ctx.ant_positions_geodetic().collect();
+        fringe_stop(
+            jones_array.view_mut(),
+            &vis_ctx,
+            &tile_xyzs,
+            obs_ctx.array_pos,
+            obs_ctx.phase_centre,
+            direction,
+            Duration::from_total_nanoseconds(0),
+        )
+        .unwrap();
+        // The source now has a constant (zero) phase on every baseline and in
+        // every timestep and channel.
+        for jones in jones_array.iter() {
+            assert_abs_diff_eq!(*jones, Jones::identity() * 2.0, epsilon = 1e-4);
+        }
+
+        let mut jones_array = Array3::from_elem((1, 4, 28), Jones::<f32>::identity());
+        assert!(matches!(
+            fringe_stop(
+                jones_array.view_mut(),
+                &vis_ctx,
+                &tile_xyzs,
+                obs_ctx.array_pos,
+                obs_ctx.phase_centre,
+                direction,
+                Duration::from_total_nanoseconds(0),
+            ),
+            Err(CorrectionError::BadArrayShape { .. })
+        ));
+    }
+
+    #[test]
+    fn test_fringe_stop_uvws() {
+        let phase_centre = RADec::new_degrees(0.0, -27.0);
+        let direction = RADec::new_degrees(2.0, -25.0);
+        let lmn = direction.to_lmn(phase_centre).prepare_for_rime();
+        let uvws = [
+            UVW {
+                u: 100.0,
+                v: -50.0,
+                w: 3.0,
+            },
+            UVW {
+                u: -1200.0,
+                v: 300.0,
+                w: -20.0,
+            },
+        ];
+        let frequencies_hz = [150e6, 180e6, 200e6];
+        // The visibilities of a unit point source at `direction`.
+        let mut jones_array = Array2::from_shape_fn((3, 2), |(chan, bl)| {
+            let phase = lmn.dot(uvws[bl]) * frequencies_hz[chan] / VEL_C;
+            let vis = Complex::new(phase.cos() as f32, phase.sin() as f32);
+            Jones::from([vis, Complex::default(), Complex::default(), vis])
+        });
+        fringe_stop_uvws(jones_array.view_mut(), &uvws, &frequencies_hz, lmn).unwrap();
+        for jones in jones_array.iter() {
+            assert_abs_diff_eq!(*jones, Jones::identity(), epsilon = 1e-4);
+        }
+
+        // Fringe stopping to the phase centre does nothing.
+        let lmn = phase_centre.to_lmn(phase_centre).prepare_for_rime();
+        let expected = jones_array.clone();
+        fringe_stop_uvws(jones_array.view_mut(), &uvws, &frequencies_hz, lmn).unwrap();
+        assert_abs_diff_eq!(jones_array, expected);
+
+        assert!(matches!(
+            fringe_stop_uvws(jones_array.view_mut(), &uvws[..1], &frequencies_hz, lmn),
+            Err(CorrectionError::BadArrayShape { .. })
+        ));
+    }
+}
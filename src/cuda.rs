@@ -7,6 +7,8 @@
 // This module should only be accessed by lib.rs if the "cuda" feature is
 // enabled, so we don't need to have conditional compilation here.
 
+pub mod coords;
+
 use std::ffi::{c_void, CStr, CString};
 
 use thiserror::Error;
@@ -147,6 +149,14 @@ pub enum CudaError {
 
     #[error("CUDA kernel error: {0}")]
     Kernel(String),
+
+    #[error("bad array shape supplied to argument {argument} of function {function}. expected {expected}, received {received}")]
+    BadArrayShape {
+        argument: String,
+        function: String,
+        expected: String,
+        received: String,
+    },
 }
 
 /// Turn a non-zero exit code and an error string pointer (which was originally
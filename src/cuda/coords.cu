@@ -0,0 +1,174 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Batched coordinate transforms, mirroring the CPU code in src/pos and
+// src/corrections.rs. The structs here must match the `repr(C)` Rust structs.
+
+#include <math.h>
+#include <string.h>
+
+#include <cuda_runtime.h>
+
+// This must match `marlu::cuda::ERROR_STR_LENGTH`.
+#define ERROR_STR_LENGTH 1024
+#define VEL_C 299792458.0
+#define TAU 6.283185307179586
+
+typedef struct XyzGeodetic {
+    double x, y, z;
+} XyzGeodetic;
+
+typedef struct UVW {
+    double u, v, w;
+} UVW;
+
+typedef struct HADec {
+    double ha, dec;
+} HADec;
+
+typedef struct AzEl {
+    double az, el;
+} AzEl;
+
+typedef struct JonesF32 {
+    float2 xx, xy, yx, yy;
+} JonesF32;
+
+const int BLOCK_SIZE = 256;
+
+// Copy the CUDA error (if any) into `error_str` and return a non-zero code.
+static int check_error(char *error_str) {
+    cudaError_t code = cudaPeekAtLastError();
+    if (code == cudaSuccess) {
+        code = cudaDeviceSynchronize();
+    }
+    if (code != cudaSuccess) {
+        strncpy(error_str, cudaGetErrorString(code), ERROR_STR_LENGTH - 1);
+        error_str[ERROR_STR_LENGTH - 1] = '\0';
+        return 1;
+    }
+    return 0;
+}
+
+__device__ UVW xyz_to_uvw(XyzGeodetic xyz, double s_ha, double c_ha, double s_dec, double c_dec) {
+    UVW uvw;
+    uvw.u = s_ha * xyz.x + c_ha * xyz.y;
+    uvw.v = -s_dec * c_ha * xyz.x + s_dec * s_ha * xyz.y + c_dec * xyz.z;
+    uvw.w = c_dec * c_ha * xyz.x - c_dec * s_ha * xyz.y + s_dec * xyz.z;
+    return uvw;
+}
+
+// The tiles of a cross-correlation baseline, in the same order as
+// `marlu::math::cross_correlation_baseline_to_tiles`.
+__device__ void baseline_to_tiles(int num_tiles, int i_bl, int *tile1, int *tile2) {
+    int remaining = i_bl;
+    int i = 0;
+    while (remaining >= num_tiles - 1 - i) {
+        remaining -= num_tiles - 1 - i;
+        i++;
+    }
+    *tile1 = i;
+    *tile2 = i + 1 + remaining;
+}
+
+// One thread per baseline; the y dimension of the grid is the timestep.
+__global__ void xyzs_to_cross_uvws_kernel(const XyzGeodetic *xyzs, const HADec *phase_centres,
+                                          int num_tiles, UVW *uvws) {
+    const int num_baselines = num_tiles * (num_tiles - 1) / 2;
+    const int i_bl = blockIdx.x * blockDim.x + threadIdx.x;
+    const int i_ts = blockIdx.y;
+    if (i_bl >= num_baselines)
+        return;
+
+    double s_ha, c_ha, s_dec, c_dec;
+    sincos(phase_centres[i_ts].ha, &s_ha, &c_ha);
+    sincos(phase_centres[i_ts].dec, &s_dec, &c_dec);
+
+    int tile1, tile2;
+    baseline_to_tiles(num_tiles, i_bl, &tile1, &tile2);
+    const XyzGeodetic *ts_xyzs = xyzs + i_ts * num_tiles;
+    UVW uvw1 = xyz_to_uvw(ts_xyzs[tile1], s_ha, c_ha, s_dec, c_dec);
+    UVW uvw2 = xyz_to_uvw(ts_xyzs[tile2], s_ha, c_ha, s_dec, c_dec);
+
+    UVW *uvw = &uvws[i_ts * num_baselines + i_bl];
+    uvw->u = uvw1.u - uvw2.u;
+    uvw->v = uvw1.v - uvw2.v;
+    uvw->w = uvw1.w - uvw2.w;
+}
+
+// The same as ERFA's eraHd2ae.
+__global__ void hadecs_to_azels_kernel(const HADec *hadecs, int num_directions, double latitude_rad,
+                                       AzEl *azels) {
+    const int i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i >= num_directions)
+        return;
+
+    double s_ha, c_ha, s_dec, c_dec, s_lat, c_lat;
+    sincos(hadecs[i].ha, &s_ha, &c_ha);
+    sincos(hadecs[i].dec, &s_dec, &c_dec);
+    sincos(latitude_rad, &s_lat, &c_lat);
+
+    const double x = -c_ha * c_dec * s_lat + s_dec * c_lat;
+    const double y = -s_ha * c_dec;
+    const double z = c_ha * c_dec * c_lat + s_dec * s_lat;
+    const double r = sqrt(x * x + y * y);
+    double az = (r != 0.0) ? atan2(y, x) : 0.0;
+    if (az < 0.0)
+        az += TAU;
+    azels[i].az = az;
+    azels[i].el = atan2(z, r);
+}
+
+__device__ float2 cmul(float2 a, float2 b) { return make_float2(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x); }
+
+// One thread per visibility; `jones` has dimensions [channel][baseline].
+__global__ void fringe_stop_kernel(JonesF32 *jones, const UVW *uvws, const double *freqs_hz, int num_freqs,
+                                   int num_baselines, double l, double m, double n) {
+    const int i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i >= num_freqs * num_baselines)
+        return;
+    const int i_freq = i / num_baselines;
+    const int i_bl = i % num_baselines;
+
+    const UVW uvw = uvws[i_bl];
+    const double phase = -(uvw.u * l + uvw.v * m + uvw.w * n) / VEL_C * freqs_hz[i_freq];
+    double s, c;
+    sincos(phase, &s, &c);
+    const float2 rotation = make_float2((float)c, (float)s);
+
+    JonesF32 *j = &jones[i];
+    j->xx = cmul(j->xx, rotation);
+    j->xy = cmul(j->xy, rotation);
+    j->yx = cmul(j->yx, rotation);
+    j->yy = cmul(j->yy, rotation);
+}
+
+extern "C" int xyzs_to_cross_uvws_cuda(const XyzGeodetic *d_xyzs, const HADec *d_phase_centres, int num_timesteps,
+                                       int num_tiles, UVW *d_uvws, char *error_str) {
+    const int num_baselines = num_tiles * (num_tiles - 1) / 2;
+    if (num_timesteps == 0 || num_baselines == 0)
+        return 0;
+    dim3 blocks((num_baselines + BLOCK_SIZE - 1) / BLOCK_SIZE, num_timesteps);
+    xyzs_to_cross_uvws_kernel<<<blocks, BLOCK_SIZE>>>(d_xyzs, d_phase_centres, num_tiles, d_uvws);
+    return check_error(error_str);
+}
+
+extern "C" int hadecs_to_azels_cuda(const HADec *d_hadecs, int num_directions, double latitude_rad, AzEl *d_azels,
+                                    char *error_str) {
+    if (num_directions == 0)
+        return 0;
+    const int blocks = (num_directions + BLOCK_SIZE - 1) / BLOCK_SIZE;
+    hadecs_to_azels_kernel<<<blocks, BLOCK_SIZE>>>(d_hadecs, num_directions, latitude_rad, d_azels);
+    return check_error(error_str);
+}
+
+extern "C" int fringe_stop_cuda(JonesF32 *d_jones, const UVW *d_uvws, const double *d_freqs_hz, int num_freqs,
+                                int num_baselines, double l, double m, double n, char *error_str) {
+    const int num_vis = num_freqs * num_baselines;
+    if (num_vis == 0)
+        return 0;
+    const int blocks = (num_vis + BLOCK_SIZE - 1) / BLOCK_SIZE;
+    fringe_stop_kernel<<<blocks, BLOCK_SIZE>>>(d_jones, d_uvws, d_freqs_hz, num_freqs, num_baselines, l, m, n);
+    return check_error(error_str);
+}
@@ -0,0 +1,326 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Batched coordinate transforms on a CUDA device.
+//!
+//! Each transform has a host function, mirroring the CPU API, and a `_device`
+//! function operating on [`DevicePointer`]s, so that data can stay on the
+//! device between transforms. [`XyzGeodetic`], [`UVW`], [`HADec`] and
+//! [`AzEl`] are `repr(C)`, so slices of them can be copied to the device
+//! directly.
+
+use std::ffi::CString;
+use std::os::raw::c_char;
+
+use ndarray::prelude::*;
+
+use super::{cuda_status_to_error, CudaError, DevicePointer, ERROR_STR_LENGTH};
+use crate::{AzEl, HADec, Jones, LmnRime, XyzGeodetic, UVW};
+
+extern "C" {
+    fn xyzs_to_cross_uvws_cuda(
+        d_xyzs: *const XyzGeodetic,
+        d_phase_centres: *const HADec,
+        num_timesteps: i32,
+        num_tiles: i32,
+        d_uvws: *mut UVW,
+        error_str: *mut c_char,
+    ) -> i32;
+
+    fn hadecs_to_azels_cuda(
+        d_hadecs: *const HADec,
+        num_directions: i32,
+        latitude_rad: f64,
+        d_azels: *mut AzEl,
+        error_str: *mut c_char,
+    ) -> i32;
+
+    #[allow(clippy::too_many_arguments)]
+    fn fringe_stop_cuda(
+        d_jones: *mut Jones<f32>,
+        d_uvws: *const UVW,
+        d_freqs_hz: *const f64,
+        num_freqs: i32,
+        num_baselines: i32,
+        l: f64,
+        m: f64,
+        n: f64,
+        error_str: *mut c_char,
+    ) -> i32;
+}
+
+/// Allocate a string for the CUDA code to write an error into. This must be
+/// consumed by [`cuda_status_to_error`].
+fn error_str() -> *mut c_char {
+    CString::new(vec![b' '; ERROR_STR_LENGTH - 1])
+        .unwrap()
+        .into_raw()
+}
+
+/// Convert the (precessed) tile positions of each timestep to cross-correlation
+/// [`UVW`]s, like [`crate::pos::xyz::xyzs_to_cross_uvws`] for every timestep.
+///
+/// - `tile_xyzs` - tile positions with dimensions `[timestep][tile]`
+/// - `phase_centres` - the phase centre at each timestep
+///
+/// Returns [`UVW`]s with dimensions `[timestep][baseline]`.
+///
+/// # Errors
+///
+/// Will return [`CudaError::BadArrayShape`] if there isn't a phase centre for
+/// each timestep, or another [`CudaError`] if CUDA fails.
+pub fn xyzs_to_cross_uvws(
+    tile_xyzs: ArrayView2<XyzGeodetic>,
+    phase_centres: &[HADec],
+) -> Result<Array2<UVW>, CudaError> {
+    let (num_timesteps, num_tiles) = tile_xyzs.dim();
+    if phase_centres.len() != num_timesteps {
+        return Err(CudaError::BadArrayShape {
+            argument: "phase_centres".to_string(),
+            function: "xyzs_to_cross_uvws".to_string(),
+            expected: format!("({},)", num_timesteps),
+            received: format!("({},)", phase_centres.len()),
+        });
+    }
+    let num_baselines = (num_tiles * num_tiles.saturating_sub(1)) / 2;
+    let mut uvws = Array2::default((num_timesteps, num_baselines));
+
+    unsafe {
+        let d_xyzs =
+            DevicePointer::copy_to_device(tile_xyzs.as_standard_layout().as_slice().unwrap())?;
+        let d_phase_centres = DevicePointer::copy_to_device(phase_centres)?;
+        let mut d_uvws = DevicePointer::malloc(uvws.len() * std::mem::size_of::<UVW>())?;
+        xyzs_to_cross_uvws_device(
+            &d_xyzs,
+            &d_phase_centres,
+            num_timesteps,
+            num_tiles,
+            &mut d_uvws,
+        )?;
+        d_uvws.copy_from_device(uvws.as_slice_mut().unwrap())?;
+    }
+    Ok(uvws)
+}
+
+/// The device version of [`xyzs_to_cross_uvws`]. `d_xyzs` has dimensions
+/// `[timestep][tile]`, and `d_uvws` must have room for `[timestep][baseline]`.
+///
+/// # Safety
+///
+/// This function interfaces directly with the CUDA API. The sizes of the
+/// device allocations must match the given dimensions.
+pub unsafe fn xyzs_to_cross_uvws_device(
+    d_xyzs: &DevicePointer<XyzGeodetic>,
+    d_phase_centres: &DevicePointer<HADec>,
+    num_timesteps: usize,
+    num_tiles: usize,
+    d_uvws: &mut DevicePointer<UVW>,
+) -> Result<(), CudaError> {
+    let error_str = error_str();
+    let exit_code = xyzs_to_cross_uvws_cuda(
+        d_xyzs.get(),
+        d_phase_centres.get(),
+        num_timesteps as i32,
+        num_tiles as i32,
+        d_uvws.get_mut(),
+        error_str,
+    );
+    cuda_status_to_error(exit_code, error_str)
+}
+
+/// Convert equatorial coordinates to horizon coordinates, like
+/// [`HADec::to_azel`] for every direction.
+///
+/// # Errors
+///
+/// Will return a [`CudaError`] if CUDA fails.
+pub fn hadecs_to_azels(hadecs: &[HADec], latitude_rad: f64) -> Result<Vec<AzEl>, CudaError> {
+    let mut azels = vec![AzEl::default(); hadecs.len()];
+    unsafe {
+        let d_hadecs = DevicePointer::copy_to_device(hadecs)?;
+        let mut d_azels = DevicePointer::malloc(azels.len() * std::mem::size_of::<AzEl>())?;
+        hadecs_to_azels_device(&d_hadecs, hadecs.len(), latitude_rad, &mut d_azels)?;
+        d_azels.copy_from_device(&mut azels)?;
+    }
+    Ok(azels)
+}
+
+/// The device version of [`hadecs_to_azels`].
+///
+/// # Safety
+///
+/// This function interfaces directly with the CUDA API. The sizes of the
+/// device allocations must match `num_directions`.
+pub unsafe fn hadecs_to_azels_device(
+    d_hadecs: &DevicePointer<HADec>,
+    num_directions: usize,
+    latitude_rad: f64,
+    d_azels: &mut DevicePointer<AzEl>,
+) -> Result<(), CudaError> {
+    let error_str = error_str();
+    let exit_code = hadecs_to_azels_cuda(
+        d_hadecs.get(),
+        num_directions as i32,
+        latitude_rad,
+        d_azels.get_mut(),
+        error_str,
+    );
+    cuda_status_to_error(exit_code, error_str)
+}
+
+/// Rotate the phases of visibilities toward `lmn`, like
+/// [`crate::corrections::fringe_stop_uvws`].
+///
+/// - `jones_array` - visibilities with dimensions `[channel][baseline]`
+/// - `uvws` - the [`UVW`] of each baseline
+/// - `frequencies_hz` - the centre frequency of each channel
+/// - `lmn` - the direction to rotate toward, relative to the phase centre
+///
+/// # Errors
+///
+/// Will return [`CudaError::BadArrayShape`] if the shape of `jones_array`
+/// doesn't match `frequencies_hz` and `uvws`, or another [`CudaError`] if CUDA
+/// fails.
+pub fn fringe_stop(
+    mut jones_array: ArrayViewMut2<Jones<f32>>,
+    uvws: &[UVW],
+    frequencies_hz: &[f64],
+    lmn: LmnRime,
+) -> Result<(), CudaError> {
+    let expected_dims = (frequencies_hz.len(), uvws.len());
+    if jones_array.dim() != expected_dims {
+        return Err(CudaError::BadArrayShape {
+            argument: "jones_array".to_string(),
+            function: "fringe_stop".to_string(),
+            expected: format!("{:?}", expected_dims),
+            received: format!("{:?}", jones_array.dim()),
+        });
+    }
+
+    let mut jones = jones_array.as_standard_layout().into_owned();
+    unsafe {
+        let mut d_jones = DevicePointer::copy_to_device(jones.as_slice().unwrap())?;
+        let d_uvws = DevicePointer::copy_to_device(uvws)?;
+        let d_freqs = DevicePointer::copy_to_device(frequencies_hz)?;
+        fringe_stop_device(
+            &mut d_jones,
+            &d_uvws,
+            &d_freqs,
+            frequencies_hz.len(),
+            uvws.len(),
+            lmn,
+        )?;
+        d_jones.copy_from_device(jones.as_slice_mut().unwrap())?;
+    }
+    jones_array.assign(&jones);
+    Ok(())
+}
+
+/// The device version of [`fringe_stop`]. `d_jones` has dimensions
+/// `[channel][baseline]`.
+///
+/// # Safety
+///
+/// This function interfaces directly with the CUDA API. The sizes of the
+/// device allocations must match the given dimensions.
+pub unsafe fn fringe_stop_device(
+    d_jones: &mut DevicePointer<Jones<f32>>,
+    d_uvws: &DevicePointer<UVW>,
+    d_freqs_hz: &DevicePointer<f64>,
+    num_freqs: usize,
+    num_baselines: usize,
+    lmn: LmnRime,
+) -> Result<(), CudaError> {
+    let error_str = error_str();
+    let exit_code = fringe_stop_cuda(
+        d_jones.get_mut(),
+        d_uvws.get(),
+        d_freqs_hz.get(),
+        num_freqs as i32,
+        num_baselines as i32,
+        lmn.l,
+        lmn.m,
+        lmn.n,
+        error_str,
+    );
+    cuda_status_to_error(exit_code, error_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+    use crate::{pos::xyz::xyzs_to_cross_uvws as xyzs_to_cross_uvws_cpu, Complex, RADec};
+
+    #[test]
+    fn test_xyzs_to_cross_uvws_matches_cpu() {
+        let tile_xyzs = Array2::from_shape_fn((3, 5), |(ts, tile)| XyzGeodetic {
+            x: 10.0 * tile as f64 + ts as f64,
+            y: -3.0 * (tile * tile) as f64,
+            z: 1.5 * tile as f64,
+        });
+        let phase_centres = [
+            HADec::new_degrees(1.0, -27.0),
+            HADec::new_degrees(1.5, -27.1),
+            HADec::new_degrees(2.0, -27.2),
+        ];
+        let uvws = xyzs_to_cross_uvws(tile_xyzs.view(), &phase_centres).unwrap();
+        assert_eq!(uvws.dim(), (3, 10));
+        for ((ts_xyzs, ts_uvws), &phase_centre) in tile_xyzs
+            .outer_iter()
+            .zip(uvws.outer_iter())
+            .zip(&phase_centres)
+        {
+            let expected = xyzs_to_cross_uvws_cpu(ts_xyzs.as_slice().unwrap(), phase_centre);
+            for (uvw, expected) in ts_uvws.iter().zip(expected) {
+                assert_abs_diff_eq!(*uvw, expected, epsilon = 1e-10);
+            }
+        }
+
+        assert!(xyzs_to_cross_uvws(tile_xyzs.view(), &phase_centres[..1]).is_err());
+    }
+
+    #[test]
+    fn test_hadecs_to_azels_matches_cpu() {
+        let hadecs = [
+            HADec::new_degrees(1.0, -35.0),
+            HADec::new_degrees(23.0, -35.0),
+            HADec::new_degrees(-60.0, 10.0),
+        ];
+        let latitude_rad = -26.7_f64.to_radians();
+        let azels = hadecs_to_azels(&hadecs, latitude_rad).unwrap();
+        for (azel, hadec) in azels.into_iter().zip(hadecs) {
+            assert_abs_diff_eq!(azel, hadec.to_azel(latitude_rad), epsilon = 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_fringe_stop_matches_cpu() {
+        let uvws = [
+            UVW {
+                u: 100.0,
+                v: -20.0,
+                w: 3.0,
+            },
+            UVW {
+                u: -50.0,
+                v: 400.0,
+                w: -10.0,
+            },
+        ];
+        let frequencies_hz = [150e6, 151e6, 152e6];
+        let lmn = RADec::new_degrees(1.0, -26.0)
+            .to_lmn(RADec::new_degrees(0.0, -27.0))
+            .prepare_for_rime();
+        let mut jones_array = Array2::from_elem((3, 2), Jones::identity());
+        jones_array[(1, 1)][1] = Complex::new(0.5, -0.25);
+        let mut expected = jones_array.clone();
+
+        fringe_stop(jones_array.view_mut(), &uvws, &frequencies_hz, lmn).unwrap();
+        crate::corrections::fringe_stop_uvws(expected.view_mut(), &uvws, &frequencies_hz, lmn)
+            .unwrap();
+        assert_abs_diff_eq!(jones_array, expected, epsilon = 1e-5);
+    }
+}
@@ -0,0 +1,412 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The errors of Marlu's IO code, and re-exports of the errors of its other
+//! modules, so that they can be matched on from one place.
+//!
+//! The enums are `#[non_exhaustive]`, so that variants can be added without
+//! breaking changes; matches on them need a wildcard arm. Errors from cfitsio
+//! are wrapped in a [`FitsHduError`] where the file and HDU are known, which
+//! carries the cfitsio status code.
+
+#[cfg(feature = "cfitsio")]
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+#[cfg(feature = "ms")]
+use rubbl_casatables::CasacoreError;
+
+pub use crate::{
+    averaging::AveragingError, pos::ErfaError, selection::SelectionError, weights::WeightError,
+    VisContextError,
+};
+
+#[derive(Error, Debug)]
+#[error("bad array shape supplied to argument {argument} of function {function}. expected {expected}, received {received}")]
+pub struct BadArrayShape {
+    pub argument: &'static str,
+    pub function: &'static str,
+    pub expected: String,
+    pub received: String,
+}
+
+// TODO: there are plenty of panics in ms that need enums
+#[derive(Error, Debug)]
+#[cfg(feature = "ms")]
+#[non_exhaustive]
+pub enum MeasurementSetWriteError {
+    /// An error when trying to write to an unexpected row.
+    #[error("Tried to write {rows_attempted} rows, but only {rows_remaining} rows are remaining out of {rows_total}")]
+    MeasurementSetFull {
+        /// The row number (0-indexed)
+        rows_attempted: usize,
+        /// Rows remaining.
+        rows_remaining: usize,
+        /// Total capacity of measurement set
+        rows_total: usize,
+    },
+
+    // TODO: https://github.com/pkgw/rubbl/pull/148
+    /// From Rubbl Casacore
+    #[error("Rubbl CASACore error {inner:?}")]
+    CasacoreError { inner: CasacoreError },
+
+    /// From Rubbl
+    #[error("Rubbl error {inner:?}")]
+    RubblError { inner: failure::Error },
+
+    /// Tried to select a field that isn't in the `FIELD` table.
+    #[error("Tried to select field {field_id}, but there are only {num_fields} fields")]
+    BadFieldId {
+        /// The requested field id
+        field_id: usize,
+        /// The number of rows in the `FIELD` table
+        num_fields: usize,
+    },
+
+    /// Tried to select an analogue beam that isn't in the observation.
+    #[error("Tried to select beam {beam_id}, but the observation has no such beam")]
+    BadBeamId {
+        /// The requested beam id
+        beam_id: u32,
+    },
+
+    /// The telescope given to `MeasurementSetWriter::set_telescope` describes
+    /// more or fewer antennas than the [`crate::ObsContext`].
+    #[error(
+        "The telescope describes {telescope_ants} antennas, but the observation has {obs_ants}"
+    )]
+    TelescopeAntsMismatch {
+        /// The number of antennas described by the telescope
+        telescope_ants: usize,
+        /// The number of antennas of the observation
+        obs_ants: usize,
+    },
+
+    /// Tried to write flag layers that don't match the `FLAG_CATEGORY`
+    /// categories of the measurement set.
+    #[error("Tried to write flag layers {received:?}, but the flag categories of the measurement set are {expected:?}")]
+    BadFlagCategories {
+        /// The categories given to `MeasurementSetWriter::set_flag_categories`
+        expected: Vec<String>,
+        /// The names of the layers written
+        received: Vec<String>,
+    },
+
+    /// Tried to create a directory where a file already exists
+    #[error("cannot create directory, path={path} already exists and is not a directory")]
+    NotADirectory { path: String },
+
+    #[error(transparent)]
+    BadArrayShape(#[from] BadArrayShape),
+
+    /// An IO error.
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+
+    #[error(transparent)]
+    SystemTimeError(#[from] std::time::SystemTimeError),
+}
+
+#[cfg(feature = "ms")]
+impl From<failure::Error> for MeasurementSetWriteError {
+    fn from(inner: failure::Error) -> Self {
+        Self::RubblError { inner }
+    }
+}
+
+#[cfg(feature = "ms")]
+impl From<CasacoreError> for MeasurementSetWriteError {
+    fn from(inner: CasacoreError) -> Self {
+        Self::CasacoreError { inner }
+    }
+}
+
+/// An error from cfitsio, with the file and HDU that it occurred in.
+#[derive(Error, Debug)]
+#[cfg(feature = "cfitsio")]
+#[error("{} (HDU {hdu}): {source}", .path.display())]
+pub struct FitsHduError {
+    /// The path to the FITS file.
+    pub path: PathBuf,
+
+    /// The name of the HDU, e.g. `PRIMARY` or `AIPS AN`.
+    pub hdu: String,
+
+    /// The cfitsio status code, if the error came from cfitsio itself (rather
+    /// than e.g. a bad string).
+    pub status: Option<i32>,
+
+    pub source: fitsio::errors::Error,
+}
+
+#[cfg(feature = "cfitsio")]
+impl FitsHduError {
+    pub fn new<T: AsRef<Path>>(source: fitsio::errors::Error, path: T, hdu: &str) -> Self {
+        let status = match &source {
+            fitsio::errors::Error::Fits(e) => Some(e.status),
+            _ => None,
+        };
+        Self {
+            path: path.as_ref().to_path_buf(),
+            hdu: hdu.to_string(),
+            status,
+            source,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[cfg(feature = "cfitsio")]
+#[non_exhaustive]
+pub enum UvfitsWriteError {
+    /// An error when trying to write to an unexpected row.
+    #[error("Tried to write to row number {row_num}, but only {num_rows} rows are expected")]
+    BadRowNum {
+        /// The row number (0-indexed)
+        row_num: usize,
+        /// Total number of rows expected.
+        num_rows: usize,
+    },
+
+    /// An error when less rows were written to an HDU than expected.
+    #[error("Expected {total} uvfits rows to be written, but only {current} were written")]
+    NotEnoughRowsWritten {
+        /// Number of rows written
+        current: usize,
+        /// Total number of rows expected.
+        total: usize,
+    },
+
+    /// An error when the MIRIAD baseline convention can't describe all of the
+    /// antennas.
+    #[error("Cannot encode {num_ants} antennas as uvfits baselines; the MIRIAD convention supports at most {max}")]
+    TooManyAntennas {
+        /// Number of antennas in the array
+        num_ants: usize,
+        /// The most antennas that can be encoded.
+        max: usize,
+    },
+
+    /// An error associated with ERFA.
+    #[error(transparent)]
+    Erfa(#[from] ErfaError),
+
+    /// An error associated with fitsio.
+    #[error(transparent)]
+    Fitsio(#[from] fitsio::errors::Error),
+
+    /// An error associated with fitsio, in a known file and HDU.
+    #[error(transparent)]
+    FitsHdu(#[from] FitsHduError),
+
+    /// An error when converting a Rust string to a C string.
+    #[error(transparent)]
+    BadString(#[from] std::ffi::NulError),
+
+    /// An IO error.
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+}
+
+#[cfg(feature = "cfitsio")]
+impl UvfitsWriteError {
+    /// Attach the file and HDU to a fitsio error; other errors are unchanged.
+    pub fn with_context<T: AsRef<Path>>(self, path: T, hdu: &str) -> Self {
+        match self {
+            Self::Fitsio(e) => Self::FitsHdu(FitsHduError::new(e, path, hdu)),
+            e => e,
+        }
+    }
+}
+
+#[cfg(feature = "cfitsio")]
+impl From<crate::io::uvfits::FitsioOrCStringError> for UvfitsWriteError {
+    fn from(e: crate::io::uvfits::FitsioOrCStringError) -> Self {
+        match e {
+            crate::io::uvfits::FitsioOrCStringError::Fitsio(e) => Self::Fitsio(e),
+            crate::io::uvfits::FitsioOrCStringError::Nul(e) => Self::BadString(e),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[cfg(feature = "parquet")]
+#[non_exhaustive]
+pub enum ParquetWriteError {
+    /// Tried to create a directory where a file already exists
+    #[error("cannot create directory, path={path} already exists and is not a directory")]
+    NotADirectory { path: String },
+
+    /// An error associated with Arrow.
+    #[error(transparent)]
+    Arrow(#[from] arrow_schema::ArrowError),
+
+    /// An error associated with Parquet.
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+
+    /// An IO error.
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+}
+
+#[derive(Error, Debug)]
+#[cfg(feature = "zarr")]
+#[non_exhaustive]
+pub enum ZarrWriteError {
+    /// Tried to create a directory where a file already exists
+    #[error("cannot create directory, path={path} already exists and is not a directory")]
+    NotADirectory { path: String },
+
+    /// An error when writing JSON metadata.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// An IO error.
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+}
+
+#[derive(Error, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+/// All the errors that can occur in file io operations
+#[non_exhaustive]
+pub enum IOError {
+    #[error(transparent)]
+    #[cfg(feature = "ms")]
+    /// Error derived from [`MeasurementSetWriteError`]
+    MeasurementSetWriteError(#[from] MeasurementSetWriteError),
+
+    #[cfg(feature = "mwalib")]
+    #[error(transparent)]
+    /// Error derived from [`mwalib::FitsError`]
+    FitsError(#[from] mwalib::FitsError),
+
+    #[error(transparent)]
+    #[cfg(feature = "cfitsio")]
+    /// Error derived from [`fitsio::errors::Error`]
+    FitsioError(#[from] fitsio::errors::Error),
+
+    #[error(transparent)]
+    #[cfg(feature = "cfitsio")]
+    /// Error derived from [`FitsHduError`]
+    FitsHdu(#[from] FitsHduError),
+
+    #[error(transparent)]
+    #[cfg(feature = "cfitsio")]
+    /// Error derived from [`UvfitsWriteError`]
+    UvfitsWriteError(#[from] UvfitsWriteError),
+
+    #[error(transparent)]
+    #[cfg(feature = "parquet")]
+    /// Error derived from [`ParquetWriteError`]
+    ParquetWriteError(#[from] ParquetWriteError),
+
+    #[error(transparent)]
+    #[cfg(feature = "zarr")]
+    /// Error derived from [`ZarrWriteError`]
+    ZarrWriteError(#[from] ZarrWriteError),
+
+    #[error(transparent)]
+    BadArrayShape(#[from] BadArrayShape),
+
+    #[error(transparent)]
+    AveragingError(#[from] AveragingError),
+
+    #[error(transparent)]
+    WeightError(#[from] WeightError),
+
+    /// The extension of an output path isn't a recognised visibility format.
+    #[error("can't determine the visibility format of {path} from its extension; expected .uvfits, .ms, .parquet or .zarr")]
+    UnknownFormat { path: String },
+
+    /// The visibility format requires a cargo feature that isn't enabled.
+    #[error("writing {format} files requires marlu's {feature} feature")]
+    FormatNotEnabled {
+        format: String,
+        feature: &'static str,
+    },
+
+    /// A `CHECKSUM` or `DATASUM` keyword of a FITS file doesn't match the
+    /// contents of its HDU (numbered from 1).
+    #[error("the {keyword} of HDU {hdu} of {path} doesn't match its contents")]
+    BadChecksum {
+        path: String,
+        hdu: usize,
+        keyword: &'static str,
+    },
+
+    /// A chunk to read isn't part of a [`crate::io::VisRead`] source.
+    #[error("the chunk to read isn't aligned with, or within, the visibilities of the source")]
+    ChunkNotInSource,
+
+    /// A chunk to write isn't part of the visibilities of a
+    /// [`crate::io::SplitWriter`].
+    #[error("the chunk to write isn't aligned with, or within, the visibilities of the output")]
+    ChunkNotInOutput,
+
+    /// A visibility file can't be read.
+    #[error("can't read {path}: {reason}")]
+    UnsupportedInput { path: String, reason: String },
+
+    /// No files were given to `io::concat`.
+    #[error("no visibility files were given to concatenate")]
+    NoConcatInputs,
+
+    /// The first part of a streamed upload (see `io::StreamingWriter`)
+    /// doesn't contain the whole primary header of a FITS output, which is
+    /// rewritten when the output is finalised.
+    #[error("the part size of a streamed upload ({part_size} bytes) is smaller than the primary header of the output")]
+    PartSmallerThanHeader { part_size: u64 },
+
+    /// An IO error.
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+
+    #[error(transparent)]
+    VisContextError(#[from] VisContextError),
+
+    #[cfg(feature = "mwalib")]
+    #[error(transparent)]
+    /// Error derived from [`SelectionError`]
+    SelectionError(#[from] SelectionError),
+
+    /// From Rubbl
+    #[error("Rubbl error {inner:?}")]
+    #[cfg(feature = "ms")]
+    RubblError { inner: failure::Error },
+
+    /// From Rubbl Casacore
+    #[error("Rubbl CASACore error {inner:?}")]
+    #[cfg(feature = "ms")]
+    CasacoreError { inner: CasacoreError },
+}
+
+#[cfg(feature = "cfitsio")]
+impl IOError {
+    /// Attach the file and HDU to a fitsio error; other errors are unchanged.
+    pub fn with_fits_context<T: AsRef<Path>>(self, path: T, hdu: &str) -> Self {
+        match self {
+            Self::FitsioError(e) => Self::FitsHdu(FitsHduError::new(e, path, hdu)),
+            Self::UvfitsWriteError(e) => Self::UvfitsWriteError(e.with_context(path, hdu)),
+            e => e,
+        }
+    }
+}
+
+#[cfg(feature = "ms")]
+impl From<failure::Error> for IOError {
+    fn from(inner: failure::Error) -> Self {
+        Self::RubblError { inner }
+    }
+}
+
+#[cfg(feature = "ms")]
+impl From<CasacoreError> for IOError {
+    fn from(inner: CasacoreError) -> Self {
+        Self::CasacoreError { inner }
+    }
+}
@@ -0,0 +1,439 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A C interface to coordinate and Jones matrix routines, enabled with the
+//! `ffi` feature.
+//!
+//! This allows C and C++ code (e.g. descendants of the RTS) to link against
+//! Marlu rather than duplicating ERFA/PAL glue. A header can be generated with
+//! [cbindgen](https://github.com/mozilla/cbindgen) using the `cbindgen.toml`
+//! in the repo root, e.g.
+//!
+//! ```bash
+//! cbindgen --config cbindgen.toml --output include/marlu.h
+//! ```
+//!
+//! and a shared or static library built with e.g.
+//!
+//! ```bash
+//! cargo rustc --release --features ffi --crate-type cdylib
+//! ```
+//!
+//! All functions return [`MARLU_SUCCESS`] (0) on success, or a non-zero error
+//! code. Jones matrices are passed as arrays of floats, 8 per matrix, with the
+//! real and imaginary parts of each element interleaved and the elements in
+//! the order XX, XY, YX, YY (i.e. the same layout as a C99 `double complex[4]`
+//! or `float complex[4]`).
+
+// This module should only be accessed by lib.rs if the "ffi" feature is
+// enabled, so we don't need to have conditional compilation here.
+
+use std::slice;
+
+use hifitime::{Duration, Epoch, Unit};
+
+use crate::{
+    pos::xyz::{xyzs_to_cross_uvws_parallel, xyzs_to_uvws_parallel},
+    precession::precess_time,
+    HADec, Jones, RADec, XyzGeodetic, UVW,
+};
+
+/// The function succeeded.
+pub const MARLU_SUCCESS: i32 = 0;
+/// A required pointer argument was null.
+pub const MARLU_NULL_POINTER: i32 = 1;
+
+/// The results of precessing a phase centre and array to the J2000 epoch. See
+/// [`marlu_precess_time`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MarluPrecessionInfo {
+    /// The precessed phase centre in the J2000 epoch \[radians\].
+    pub hadec_j2000: HADec,
+    /// The LMST of the current epoch \[radians\].
+    pub lmst: f64,
+    /// The precessed LMST in the J2000 epoch \[radians\].
+    pub lmst_j2000: f64,
+    /// The precessed array latitude in the J2000 epoch \[radians\].
+    pub array_latitude_j2000: f64,
+}
+
+/// Precess a phase centre and the array position at a GPS time to the J2000
+/// epoch. `dut1_s` is UT1 - UTC \[seconds\]; use 0 if it isn't known. See
+/// [`precess_time`].
+///
+/// # Safety
+///
+/// `info` must be null or point to a valid [`MarluPrecessionInfo`].
+#[no_mangle]
+pub unsafe extern "C" fn marlu_precess_time(
+    array_longitude_rad: f64,
+    array_latitude_rad: f64,
+    ra_rad: f64,
+    dec_rad: f64,
+    gps_time_s: f64,
+    dut1_s: f64,
+    info: *mut MarluPrecessionInfo,
+) -> i32 {
+    if info.is_null() {
+        return MARLU_NULL_POINTER;
+    }
+    let precession_info = precess_time(
+        array_longitude_rad,
+        array_latitude_rad,
+        RADec::new(ra_rad, dec_rad),
+        Epoch::from_gpst_seconds(gps_time_s),
+        Duration::from_f64(dut1_s, Unit::Second),
+    );
+    *info = MarluPrecessionInfo {
+        hadec_j2000: precession_info.hadec_j2000,
+        lmst: precession_info.lmst,
+        lmst_j2000: precession_info.lmst_j2000,
+        array_latitude_j2000: precession_info.array_latitude_j2000,
+    };
+    MARLU_SUCCESS
+}
+
+/// Precess `num_tiles` tile positions at a GPS time to the J2000 epoch, as
+/// done by the visibility writers. `dut1_s` is UT1 - UTC \[seconds\]; use 0
+/// if it isn't known. `xyzs` and `precessed_xyzs` may be the same array.
+///
+/// # Safety
+///
+/// `xyzs` and `precessed_xyzs` must be null or point to arrays with
+/// `num_tiles` elements.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn marlu_precess_xyzs(
+    array_longitude_rad: f64,
+    array_latitude_rad: f64,
+    ra_rad: f64,
+    dec_rad: f64,
+    gps_time_s: f64,
+    dut1_s: f64,
+    xyzs: *const XyzGeodetic,
+    num_tiles: usize,
+    precessed_xyzs: *mut XyzGeodetic,
+) -> i32 {
+    if xyzs.is_null() || precessed_xyzs.is_null() {
+        return MARLU_NULL_POINTER;
+    }
+    let precession_info = precess_time(
+        array_longitude_rad,
+        array_latitude_rad,
+        RADec::new(ra_rad, dec_rad),
+        Epoch::from_gpst_seconds(gps_time_s),
+        Duration::from_f64(dut1_s, Unit::Second),
+    );
+    let precessed = precession_info.precess_xyz_parallel(slice::from_raw_parts(xyzs, num_tiles));
+    precessed_xyzs.copy_from(precessed.as_ptr(), num_tiles);
+    MARLU_SUCCESS
+}
+
+/// Get the [`UVW`]s of all baselines (including auto-correlations) formed by
+/// `num_tiles` tile positions. `uvws` must have `num_tiles * (num_tiles + 1) /
+/// 2` elements.
+///
+/// # Safety
+///
+/// `xyzs` must be null or point to an array with `num_tiles` elements, and
+/// `uvws` must be null or point to an array with the number of elements
+/// above.
+#[no_mangle]
+pub unsafe extern "C" fn marlu_xyzs_to_uvws(
+    xyzs: *const XyzGeodetic,
+    num_tiles: usize,
+    phase_centre: HADec,
+    uvws: *mut UVW,
+) -> i32 {
+    if xyzs.is_null() || uvws.is_null() {
+        return MARLU_NULL_POINTER;
+    }
+    let bl_uvws = xyzs_to_uvws_parallel(slice::from_raw_parts(xyzs, num_tiles), phase_centre);
+    uvws.copy_from_nonoverlapping(bl_uvws.as_ptr(), bl_uvws.len());
+    MARLU_SUCCESS
+}
+
+/// Get the [`UVW`]s of the cross-correlation baselines formed by `num_tiles`
+/// tile positions. `uvws` must have `num_tiles * (num_tiles - 1) / 2`
+/// elements.
+///
+/// # Safety
+///
+/// `xyzs` must be null or point to an array with `num_tiles` elements, and
+/// `uvws` must be null or point to an array with the number of elements
+/// above.
+#[no_mangle]
+pub unsafe extern "C" fn marlu_xyzs_to_cross_uvws(
+    xyzs: *const XyzGeodetic,
+    num_tiles: usize,
+    phase_centre: HADec,
+    uvws: *mut UVW,
+) -> i32 {
+    if xyzs.is_null() || uvws.is_null() {
+        return MARLU_NULL_POINTER;
+    }
+    let bl_uvws = xyzs_to_cross_uvws_parallel(slice::from_raw_parts(xyzs, num_tiles), phase_centre);
+    uvws.copy_from_nonoverlapping(bl_uvws.as_ptr(), bl_uvws.len());
+    MARLU_SUCCESS
+}
+
+/// Multiply `num_jones` pairs of double-precision Jones matrices, i.e.
+/// `out[i] = a[i] * b[i]`. If `hermitian` is non-zero, `b[i]` is Hermitian
+/// conjugated first, i.e. `out[i] = a[i] * b[i]^H`. `out` may be the same
+/// array as `a` or `b`.
+///
+/// # Safety
+///
+/// `a`, `b` and `out` must be null or point to arrays with `8 * num_jones`
+/// elements.
+#[no_mangle]
+pub unsafe extern "C" fn marlu_jones_mul_batch(
+    a: *const f64,
+    b: *const f64,
+    out: *mut f64,
+    num_jones: usize,
+    hermitian: i32,
+) -> i32 {
+    jones_mul_batch(a, b, out, num_jones, hermitian != 0)
+}
+
+/// The same as [`marlu_jones_mul_batch`], but for single-precision Jones
+/// matrices (e.g. visibilities).
+///
+/// # Safety
+///
+/// `a`, `b` and `out` must be null or point to arrays with `8 * num_jones`
+/// elements.
+#[no_mangle]
+pub unsafe extern "C" fn marlu_jones_mul_batch_f32(
+    a: *const f32,
+    b: *const f32,
+    out: *mut f32,
+    num_jones: usize,
+    hermitian: i32,
+) -> i32 {
+    jones_mul_batch(a, b, out, num_jones, hermitian != 0)
+}
+
+/// Invert `num_jones` double-precision Jones matrices. Singular matrices are
+/// inverted to all NaNs. `out` may be the same array as `jones`.
+///
+/// # Safety
+///
+/// `jones` and `out` must be null or point to arrays with `8 * num_jones`
+/// elements.
+#[no_mangle]
+pub unsafe extern "C" fn marlu_jones_inv_batch(
+    jones: *const f64,
+    out: *mut f64,
+    num_jones: usize,
+) -> i32 {
+    jones_inv_batch(jones, out, num_jones)
+}
+
+/// The same as [`marlu_jones_inv_batch`], but for single-precision Jones
+/// matrices.
+///
+/// # Safety
+///
+/// `jones` and `out` must be null or point to arrays with `8 * num_jones`
+/// elements.
+#[no_mangle]
+pub unsafe extern "C" fn marlu_jones_inv_batch_f32(
+    jones: *const f32,
+    out: *mut f32,
+    num_jones: usize,
+) -> i32 {
+    jones_inv_batch(jones, out, num_jones)
+}
+
+// `Jones` is `repr(transparent)` over `[Complex<F>; 4]` and `Complex` is
+// `repr(C)`, so a `Jones<F>` has the same layout as `[F; 8]`. The matrices are
+// read and written one at a time so that the input and output arrays may
+// alias.
+unsafe fn jones_mul_batch<F: num_traits::Float>(
+    a: *const F,
+    b: *const F,
+    out: *mut F,
+    num_jones: usize,
+    hermitian: bool,
+) -> i32 {
+    if a.is_null() || b.is_null() || out.is_null() {
+        return MARLU_NULL_POINTER;
+    }
+    let (a, b, out) = (
+        a.cast::<Jones<F>>(),
+        b.cast::<Jones<F>>(),
+        out.cast::<Jones<F>>(),
+    );
+    for i in 0..num_jones {
+        let (a, b) = (a.add(i).read_unaligned(), b.add(i).read_unaligned());
+        let product = if hermitian { a * b.h() } else { a * b };
+        out.add(i).write_unaligned(product);
+    }
+    MARLU_SUCCESS
+}
+
+unsafe fn jones_inv_batch<F: num_traits::Float>(
+    jones: *const F,
+    out: *mut F,
+    num_jones: usize,
+) -> i32 {
+    if jones.is_null() || out.is_null() {
+        return MARLU_NULL_POINTER;
+    }
+    let (jones, out) = (jones.cast::<Jones<F>>(), out.cast::<Jones<F>>());
+    for i in 0..num_jones {
+        out.add(i)
+            .write_unaligned(jones.add(i).read_unaligned().inv());
+    }
+    MARLU_SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+    use crate::{c64, pos::xyz::xyzs_to_cross_uvws, LatLngHeight};
+
+    #[test]
+    fn test_precess_time_matches_rust() {
+        let mwa = LatLngHeight::new_mwa();
+        let phase_centre = RADec::new_degrees(0.0, -27.0);
+        let gps = 1065880128.0;
+        let expected = precess_time(
+            mwa.longitude_rad,
+            mwa.latitude_rad,
+            phase_centre,
+            Epoch::from_gpst_seconds(gps),
+            Duration::from_f64(0.0, Unit::Second),
+        );
+
+        let mut info = MarluPrecessionInfo::default();
+        let result = unsafe {
+            marlu_precess_time(
+                mwa.longitude_rad,
+                mwa.latitude_rad,
+                phase_centre.ra,
+                phase_centre.dec,
+                gps,
+                0.0,
+                &mut info,
+            )
+        };
+        assert_eq!(result, MARLU_SUCCESS);
+        assert_abs_diff_eq!(info.hadec_j2000, expected.hadec_j2000);
+        assert_abs_diff_eq!(info.lmst, expected.lmst);
+        assert_abs_diff_eq!(info.lmst_j2000, expected.lmst_j2000);
+        assert_abs_diff_eq!(info.array_latitude_j2000, expected.array_latitude_j2000);
+
+        let result = unsafe { marlu_precess_time(0.0, 0.0, 0.0, 0.0, gps, 0.0, ptr::null_mut()) };
+        assert_eq!(result, MARLU_NULL_POINTER);
+    }
+
+    #[test]
+    fn test_xyzs_to_cross_uvws_matches_rust() {
+        let xyzs = [
+            XyzGeodetic {
+                x: 289.5692922664971,
+                y: -585.6749877929688,
+                z: -259.3106530519151,
+            },
+            XyzGeodetic {
+                x: 750.5194624923599,
+                y: -565.4390258789063,
+                z: 665.2348852011041,
+            },
+            XyzGeodetic {
+                x: -10.0,
+                y: 20.0,
+                z: 30.0,
+            },
+        ];
+        let phase_centre = HADec::new(0.5, -0.4);
+        let mut uvws = [UVW::default(); 3];
+        let result = unsafe {
+            marlu_xyzs_to_cross_uvws(xyzs.as_ptr(), xyzs.len(), phase_centre, uvws.as_mut_ptr())
+        };
+        assert_eq!(result, MARLU_SUCCESS);
+        for (uvw, expected) in uvws.iter().zip(xyzs_to_cross_uvws(&xyzs, phase_centre)) {
+            assert_abs_diff_eq!(*uvw, expected);
+        }
+
+        let mut uvws = [UVW::default(); 6];
+        let result = unsafe {
+            marlu_xyzs_to_uvws(xyzs.as_ptr(), xyzs.len(), phase_centre, uvws.as_mut_ptr())
+        };
+        assert_eq!(result, MARLU_SUCCESS);
+        // The first baseline is an auto-correlation.
+        assert_abs_diff_eq!(uvws[0], UVW::default());
+    }
+
+    #[test]
+    fn test_jones_batches() {
+        let a = Jones::from([
+            c64::new(1.0, 2.0),
+            c64::new(3.0, 4.0),
+            c64::new(5.0, 6.0),
+            c64::new(7.0, 8.0),
+        ]);
+        let b = Jones::from([
+            c64::new(-1.0, 0.5),
+            c64::new(0.0, 1.0),
+            c64::new(2.0, -3.0),
+            c64::new(1.0, 0.0),
+        ]);
+        let a_arr = [a, b];
+        let b_arr = [b, a];
+        let mut out = [Jones::default(); 2];
+
+        let result = unsafe {
+            marlu_jones_mul_batch(
+                a_arr.as_ptr().cast(),
+                b_arr.as_ptr().cast(),
+                out.as_mut_ptr().cast(),
+                2,
+                0,
+            )
+        };
+        assert_eq!(result, MARLU_SUCCESS);
+        assert_abs_diff_eq!(out[0], a * b);
+        assert_abs_diff_eq!(out[1], b * a);
+
+        let result = unsafe {
+            marlu_jones_mul_batch(
+                a_arr.as_ptr().cast(),
+                b_arr.as_ptr().cast(),
+                out.as_mut_ptr().cast(),
+                2,
+                1,
+            )
+        };
+        assert_eq!(result, MARLU_SUCCESS);
+        assert_abs_diff_eq!(out[0], a * b.h());
+
+        // Invert in place.
+        let result =
+            unsafe { marlu_jones_inv_batch(out.as_ptr().cast(), out.as_mut_ptr().cast(), 2) };
+        assert_eq!(result, MARLU_SUCCESS);
+        assert_abs_diff_eq!(out[0], (a * b.h()).inv());
+
+        let a32: Jones<f32> = a.into();
+        let mut out32 = [Jones::<f32>::default()];
+        let result = unsafe {
+            marlu_jones_inv_batch_f32([a32].as_ptr().cast(), out32.as_mut_ptr().cast(), 1)
+        };
+        assert_eq!(result, MARLU_SUCCESS);
+        assert_abs_diff_eq!(out32[0] * a32, Jones::identity(), epsilon = 1e-5);
+
+        let result = unsafe {
+            marlu_jones_mul_batch_f32(ptr::null(), ptr::null(), out32.as_mut_ptr().cast(), 1, 0)
+        };
+        assert_eq!(result, MARLU_NULL_POINTER);
+    }
+}
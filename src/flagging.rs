@@ -0,0 +1,976 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Geometric flagging of visibilities, e.g. of antennas that are shadowed by
+//! other antennas, or of timesteps where the pointing is too close to the
+//! horizon, and simple RFI flagging of amplitudes, with the `SumThreshold` method
+//! or median absolute deviation (MAD) thresholds.
+//!
+//! Flags are written into a flag array with dimensions
+//! `[timestep][channel][baseline]`; existing flags are never cleared. The RFI
+//! flaggers can be composed with [`flag_rfi`], and [`flag_occupancy`]
+//! summarises the resulting flags. Flags of different origins can be kept in
+//! named [`FlagLayers`], so that they can be undone separately.
+
+use std::cmp::Ordering;
+
+use ndarray::prelude::*;
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::{errors::BadArrayShape, HADec, Jones, LatLngHeight, VisContext, XyzGeodetic, UVW};
+
+#[derive(Error, Debug)]
+pub enum FlaggingError {
+    #[error(transparent)]
+    BadArrayShape(#[from] BadArrayShape),
+}
+
+/// Find the antennas that are shadowed by another antenna when pointing at
+/// `pointing`. An antenna is shadowed if the projection of its separation from
+/// a nearer antenna onto the plane perpendicular to the pointing is less than
+/// `footprint_diameter_m`.
+///
+/// - `tile_xyzs` - the geodetic position of each antenna
+/// - `pointing` - the (apparent) pointing centre
+/// - `footprint_diameter_m` - the diameter of an antenna's footprint, e.g. the
+///   dish diameter, or the width of an MWA tile (5 metres)
+///
+/// Returns whether each antenna is shadowed.
+pub fn shadowed_tiles(
+    tile_xyzs: &[XyzGeodetic],
+    pointing: HADec,
+    footprint_diameter_m: f64,
+) -> Vec<bool> {
+    let (s_ha, c_ha) = pointing.ha.sin_cos();
+    let (s_dec, c_dec) = pointing.dec.sin_cos();
+    let tile_uvws: Vec<UVW> = tile_xyzs
+        .iter()
+        .map(|&xyz| UVW::from_xyz_inner(xyz, s_ha, c_ha, s_dec, c_dec))
+        .collect();
+
+    let mut shadowed = vec![false; tile_xyzs.len()];
+    for (i, &uvw1) in tile_uvws.iter().enumerate() {
+        for (j, &uvw2) in tile_uvws.iter().enumerate().skip(i + 1) {
+            let UVW { u, v, w } = uvw1 - uvw2;
+            if u.hypot(v) < footprint_diameter_m {
+                // The antenna further from the source is shadowed.
+                if w > 0.0 {
+                    shadowed[j] = true;
+                } else {
+                    shadowed[i] = true;
+                }
+            }
+        }
+    }
+    shadowed
+}
+
+/// Flag visibilities of antennas that are shadowed (see [`shadowed_tiles`]),
+/// and all visibilities of timesteps where the pointing is below
+/// `min_elevation_rad`.
+///
+/// - `flag_array` - flags with dimensions [`VisContext::sel_dims`]
+/// - `vis_ctx` - the selected timesteps, channels and baselines of
+///   `flag_array`
+/// - `tile_xyzs` - the geodetic position of each antenna, indexed by the
+///   antenna indices in `vis_ctx.sel_baselines`. Antennas that aren't in any
+///   selected baseline can still shadow others.
+/// - `array_pos` - the position of the array
+/// - `pointings` - the (apparent) pointing centre of each selected timestep
+/// - `footprint_diameter_m` - the diameter of an antenna's footprint
+/// - `min_elevation_rad` - the minimum elevation of the pointing centre
+///
+/// Returns the number of newly flagged visibilities.
+///
+/// # Errors
+///
+/// Will return [`FlaggingError::BadArrayShape`] if the shape of `flag_array`
+/// doesn't match `vis_ctx`, or there isn't a pointing for each timestep.
+pub fn flag_shadowing_and_elevation(
+    mut flag_array: ArrayViewMut3<bool>,
+    vis_ctx: &VisContext,
+    tile_xyzs: &[XyzGeodetic],
+    array_pos: LatLngHeight,
+    pointings: &[HADec],
+    footprint_diameter_m: f64,
+    min_elevation_rad: f64,
+) -> Result<usize, FlaggingError> {
+    if flag_array.dim() != vis_ctx.sel_dims() {
+        return Err(FlaggingError::BadArrayShape(BadArrayShape {
+            argument: "flag_array",
+            function: "flag_shadowing_and_elevation",
+            expected: format!("{:?}", vis_ctx.sel_dims()),
+            received: format!("{:?}", flag_array.dim()),
+        }));
+    }
+    if pointings.len() != vis_ctx.num_sel_timesteps {
+        return Err(FlaggingError::BadArrayShape(BadArrayShape {
+            argument: "pointings",
+            function: "flag_shadowing_and_elevation",
+            expected: format!("({},)", vis_ctx.num_sel_timesteps),
+            received: format!("({},)", pointings.len()),
+        }));
+    }
+
+    let mut num_flagged = 0;
+    for (mut flags_2d, &pointing) in flag_array.outer_iter_mut().zip(pointings) {
+        let mut flag_bl = |mut bl_flags: ArrayViewMut1<bool>| {
+            for flag in bl_flags.iter_mut().filter(|flag| !**flag) {
+                *flag = true;
+                num_flagged += 1;
+            }
+        };
+
+        if pointing.to_azel(array_pos.latitude_rad).el < min_elevation_rad {
+            for bl_flags in flags_2d.axis_iter_mut(Axis(1)) {
+                flag_bl(bl_flags);
+            }
+            continue;
+        }
+
+        let shadowed = shadowed_tiles(tile_xyzs, pointing, footprint_diameter_m);
+        for (bl_flags, &(ant1, ant2)) in flags_2d.axis_iter_mut(Axis(1)).zip(&vis_ctx.sel_baselines)
+        {
+            if shadowed[ant1] || shadowed[ant2] {
+                flag_bl(bl_flags);
+            }
+        }
+    }
+
+    Ok(num_flagged)
+}
+
+/// Parameters of the `SumThreshold` flagger, [`flag_sum_threshold`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SumThresholdParams {
+    /// The threshold for single samples, in units of the (robust) standard
+    /// deviation of the residual amplitudes.
+    pub threshold: f32,
+
+    /// The threshold for a window of `M` samples is `threshold / rho^log2(M)`.
+    pub rho: f32,
+
+    /// The largest window, in samples. Windows of 1, 2, 4, ... samples up to
+    /// this size are used.
+    pub max_window: usize,
+
+    /// The number of times that the background is estimated and the windows
+    /// are applied; each iteration excludes the flags of the previous ones
+    /// from the background.
+    pub num_iterations: usize,
+}
+
+impl Default for SumThresholdParams {
+    fn default() -> Self {
+        Self {
+            threshold: 6.0,
+            rho: 1.5,
+            max_window: 64,
+            num_iterations: 2,
+        }
+    }
+}
+
+/// Flag RFI with the `SumThreshold` method (Offringa et al. 2010), as a
+/// lightweight alternative to `AOFlagger`.
+///
+/// For each baseline and pol, the background of the amplitudes is the median
+/// over time of the unflagged amplitudes of each channel. A window of
+/// consecutive samples in time or frequency is flagged if the mean of its
+/// unflagged residuals exceeds the threshold for its size (see
+/// [`SumThresholdParams`]). Flags of any pol apply to all pols.
+///
+/// - `jones_array` - visibilities with dimensions `[timestep][channel][baseline]`
+/// - `flag_array` - flags with the same dimensions; existing flags are
+///   excluded, and never cleared
+/// - `params` - the thresholds and iterations
+///
+/// Baselines are flagged in parallel. Returns the number of newly flagged
+/// visibilities.
+///
+/// # Errors
+///
+/// Will return [`FlaggingError::BadArrayShape`] if the shapes of the arrays
+/// don't match.
+pub fn flag_sum_threshold(
+    jones_array: ArrayView3<Jones<f32>>,
+    flag_array: ArrayViewMut3<bool>,
+    params: &SumThresholdParams,
+) -> Result<usize, FlaggingError> {
+    check_rfi_shapes(jones_array, flag_array.view(), "flag_sum_threshold")?;
+    Ok(flag_baselines_by_pol(
+        jones_array,
+        flag_array,
+        |amps, mut pol_flags| {
+            for _ in 0..params.num_iterations {
+                let residuals = background_residuals(amps, pol_flags.view());
+                let sigma = robust_std_dev(residuals.view(), pol_flags.view());
+                if sigma.is_nan() || sigma <= 0.0 {
+                    break;
+                }
+                let mut window = 1;
+                while window <= params.max_window {
+                    let threshold =
+                        params.threshold * sigma / params.rho.powf((window as f32).log2());
+                    for axis in [Axis(0), Axis(1)] {
+                        for (residuals, flags) in residuals
+                            .lanes(axis)
+                            .into_iter()
+                            .zip(pol_flags.lanes_mut(axis))
+                        {
+                            sum_threshold(residuals, flags, window, threshold);
+                        }
+                    }
+                    window *= 2;
+                }
+            }
+        },
+    ))
+}
+
+/// Parameters of the median absolute deviation (MAD) flagger, [`flag_mad`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MadParams {
+    /// The threshold, in units of the standard deviation estimated from the
+    /// MAD (i.e. 1.4826 MAD).
+    pub threshold: f32,
+
+    /// Whether to flag channels.
+    pub flag_channels: bool,
+
+    /// Whether to flag timesteps.
+    pub flag_timesteps: bool,
+}
+
+impl Default for MadParams {
+    fn default() -> Self {
+        Self {
+            threshold: 5.0,
+            flag_channels: true,
+            flag_timesteps: true,
+        }
+    }
+}
+
+/// Flag whole channels and timesteps of each baseline with outlying
+/// amplitudes, using robust statistics.
+///
+/// For each baseline and pol, the statistic of a channel (timestep) is the
+/// median of its unflagged amplitudes over time (frequency). A channel
+/// (timestep) is flagged if its statistic differs from the median of the
+/// statistics of all channels (timesteps) by more than `params.threshold`
+/// times the standard deviation estimated from their median absolute deviation.
+/// Flags of any pol apply to all pols.
+///
+/// - `jones_array` - visibilities with dimensions `[timestep][channel][baseline]`
+/// - `flag_array` - flags with the same dimensions; existing flags are
+///   excluded, and never cleared
+/// - `params` - the threshold and which axes to flag
+///
+/// Baselines are flagged in parallel. Returns the number of newly flagged
+/// visibilities.
+///
+/// # Errors
+///
+/// Will return [`FlaggingError::BadArrayShape`] if the shapes of the arrays
+/// don't match.
+pub fn flag_mad(
+    jones_array: ArrayView3<Jones<f32>>,
+    flag_array: ArrayViewMut3<bool>,
+    params: &MadParams,
+) -> Result<usize, FlaggingError> {
+    check_rfi_shapes(jones_array, flag_array.view(), "flag_mad")?;
+    Ok(flag_baselines_by_pol(
+        jones_array,
+        flag_array,
+        |amps, mut pol_flags| {
+            // Channels are along axis 1, timesteps along axis 0.
+            for (enabled, axis) in [
+                (params.flag_channels, Axis(1)),
+                (params.flag_timesteps, Axis(0)),
+            ] {
+                if !enabled {
+                    continue;
+                }
+                let stats: Vec<Option<f32>> = amps
+                    .axis_iter(axis)
+                    .zip(pol_flags.axis_iter(axis))
+                    .map(|(amps, flags)| median(unflagged(amps.iter(), flags.iter())))
+                    .collect();
+                let (centre, sigma) =
+                    match robust_stats(&stats.iter().flatten().copied().collect::<Vec<_>>()) {
+                        Some(stats) => stats,
+                        None => continue,
+                    };
+                if sigma.is_nan() || sigma <= 0.0 {
+                    continue;
+                }
+                for (mut flags, stat) in pol_flags.axis_iter_mut(axis).zip(stats) {
+                    if matches!(stat, Some(stat) if (stat - centre).abs() > params.threshold * sigma)
+                    {
+                        flags.fill(true);
+                    }
+                }
+            }
+        },
+    ))
+}
+
+/// An RFI flagger and its parameters, to be composed with [`flag_rfi`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RfiFlagger {
+    /// Flag windows of samples with [`flag_sum_threshold`].
+    SumThreshold(SumThresholdParams),
+
+    /// Flag whole channels and timesteps with [`flag_mad`].
+    Mad(MadParams),
+}
+
+/// Apply each of the `flaggers` in turn, e.g. [`RfiFlagger::Mad`] to remove
+/// badly affected channels and timesteps, then [`RfiFlagger::SumThreshold`]
+/// for the remaining RFI. Each flagger excludes the flags of the previous ones.
+///
+/// Returns the total number of newly flagged visibilities.
+///
+/// # Errors
+///
+/// Will return [`FlaggingError::BadArrayShape`] if the shapes of the arrays
+/// don't match.
+pub fn flag_rfi(
+    jones_array: ArrayView3<Jones<f32>>,
+    mut flag_array: ArrayViewMut3<bool>,
+    flaggers: &[RfiFlagger],
+) -> Result<usize, FlaggingError> {
+    check_rfi_shapes(jones_array, flag_array.view(), "flag_rfi")?;
+    let mut num_flagged = 0;
+    for flagger in flaggers {
+        num_flagged += match flagger {
+            RfiFlagger::SumThreshold(params) => {
+                flag_sum_threshold(jones_array, flag_array.view_mut(), params)?
+            }
+            RfiFlagger::Mad(params) => flag_mad(jones_array, flag_array.view_mut(), params)?,
+        };
+    }
+    Ok(num_flagged)
+}
+
+/// The fraction of visibilities that are flagged, in total and along each
+/// axis, from [`flag_occupancy`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlagOccupancy {
+    /// The fraction of all visibilities that are flagged.
+    pub total: f64,
+
+    /// The fraction of visibilities flagged in each timestep.
+    pub timesteps: Array1<f64>,
+
+    /// The fraction of visibilities flagged in each channel.
+    pub channels: Array1<f64>,
+
+    /// The fraction of visibilities flagged in each baseline.
+    pub baselines: Array1<f64>,
+}
+
+/// Summarise a flag array with dimensions `[timestep][channel][baseline]`.
+/// Occupancies of empty arrays are NaN.
+pub fn flag_occupancy(flag_array: ArrayView3<bool>) -> FlagOccupancy {
+    let (num_timesteps, num_chans, num_baselines) = flag_array.dim();
+    let flags = flag_array.mapv(|flag| if flag { 1.0 } else { 0.0 });
+    let nans = |len: usize| Array1::from_elem(len, f64::NAN);
+    FlagOccupancy {
+        total: flags.mean().unwrap_or(f64::NAN),
+        timesteps: flags
+            .mean_axis(Axis(2))
+            .and_then(|flags| flags.mean_axis(Axis(1)))
+            .unwrap_or_else(|| nans(num_timesteps)),
+        channels: flags
+            .mean_axis(Axis(2))
+            .and_then(|flags| flags.mean_axis(Axis(0)))
+            .unwrap_or_else(|| nans(num_chans)),
+        baselines: flags
+            .mean_axis(Axis(1))
+            .and_then(|flags| flags.mean_axis(Axis(0)))
+            .unwrap_or_else(|| nans(num_baselines)),
+    }
+}
+
+/// The name of the flag layer of visibilities that are missing from the input,
+/// e.g. because a gpubox file or HDU is missing.
+pub const FLAG_LAYER_MISSING_DATA: &str = "MISSING_DATA";
+/// The name of the flag layer of visibilities flagged by an RFI flagger, e.g.
+/// [`flag_rfi`].
+pub const FLAG_LAYER_RFI: &str = "RFI";
+/// The name of the flag layer of visibilities flagged by the user, e.g. by
+/// antenna or channel selection.
+pub const FLAG_LAYER_USER: &str = "USER";
+
+/// Named layers of flags, each with dimensions `[timestep][channel][baseline]`.
+///
+/// A visibility is flagged if it is flagged in any layer, so a category of
+/// flags can be undone by removing its layer. Measurement sets can store the
+/// layers in their `FLAG_CATEGORY` column (see
+/// `MeasurementSetWriter::write_vis_with_flag_layers`); other formats only
+/// store the [`FlagLayers::combined`] flags.
+///
+/// ```rust
+/// use marlu::flagging::{FlagLayers, FLAG_LAYER_RFI, FLAG_LAYER_USER};
+///
+/// let mut layers = FlagLayers::new((2, 3, 4));
+/// layers.layer_mut(FLAG_LAYER_RFI)[(0, 1, 2)] = true;
+/// layers.layer_mut(FLAG_LAYER_USER)[(1, 0, 0)] = true;
+/// assert_eq!(layers.combined().iter().filter(|&&flag| flag).count(), 2);
+///
+/// layers.remove(FLAG_LAYER_RFI);
+/// assert!(!layers.combined()[(0, 1, 2)]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlagLayers {
+    dim: (usize, usize, usize),
+    layers: Vec<(String, Array3<bool>)>,
+}
+
+impl FlagLayers {
+    /// Create an empty set of layers for flag arrays with dimensions `dim`.
+    pub fn new(dim: (usize, usize, usize)) -> Self {
+        Self {
+            dim,
+            layers: vec![],
+        }
+    }
+
+    /// The dimensions of each layer.
+    pub fn dim(&self) -> (usize, usize, usize) {
+        self.dim
+    }
+
+    /// The number of layers.
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Whether there are no layers.
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// The names of the layers, in the order they were added.
+    pub fn names(&self) -> Vec<&str> {
+        self.layers.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Iterate over the names and flags of the layers, in the order they were
+    /// added.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, ArrayView3<'_, bool>)> {
+        self.layers
+            .iter()
+            .map(|(name, flags)| (name.as_str(), flags.view()))
+    }
+
+    /// The flags of the layer called `name`, if there is one.
+    pub fn layer(&self, name: &str) -> Option<ArrayView3<'_, bool>> {
+        self.layers
+            .iter()
+            .find(|(layer_name, _)| layer_name == name)
+            .map(|(_, flags)| flags.view())
+    }
+
+    /// The flags of the layer called `name`, which is added (with nothing
+    /// flagged) if there isn't one, e.g. to pass to [`flag_rfi`].
+    pub fn layer_mut(&mut self, name: &str) -> ArrayViewMut3<'_, bool> {
+        let idx = match self
+            .layers
+            .iter()
+            .position(|(layer_name, _)| layer_name == name)
+        {
+            Some(idx) => idx,
+            None => {
+                self.layers
+                    .push((name.to_string(), Array3::from_elem(self.dim, false)));
+                self.layers.len() - 1
+            }
+        };
+        self.layers[idx].1.view_mut()
+    }
+
+    /// Set the flags of the layer called `name`, replacing any existing layer
+    /// of that name.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`FlaggingError::BadArrayShape`] if `flags` doesn't have the
+    /// dimensions of these layers.
+    pub fn insert(&mut self, name: &str, flags: Array3<bool>) -> Result<(), FlaggingError> {
+        if flags.dim() != self.dim {
+            return Err(FlaggingError::BadArrayShape(BadArrayShape {
+                argument: "flags",
+                function: "FlagLayers::insert",
+                expected: format!("{:?}", self.dim),
+                received: format!("{:?}", flags.dim()),
+            }));
+        }
+        match self
+            .layers
+            .iter_mut()
+            .find(|(layer_name, _)| layer_name == name)
+        {
+            Some((_, layer)) => *layer = flags,
+            None => self.layers.push((name.to_string(), flags)),
+        }
+        Ok(())
+    }
+
+    /// Remove the layer called `name`, undoing its flags, and return it.
+    pub fn remove(&mut self, name: &str) -> Option<Array3<bool>> {
+        let idx = self
+            .layers
+            .iter()
+            .position(|(layer_name, _)| layer_name == name)?;
+        Some(self.layers.remove(idx).1)
+    }
+
+    /// The flags of all layers combined: a visibility is flagged if it is
+    /// flagged in any layer.
+    pub fn combined(&self) -> Array3<bool> {
+        let mut combined = Array3::from_elem(self.dim, false);
+        for (_, flags) in &self.layers {
+            combined.zip_mut_with(flags, |combined, &flag| *combined |= flag);
+        }
+        combined
+    }
+}
+
+fn check_rfi_shapes(
+    jones_array: ArrayView3<Jones<f32>>,
+    flag_array: ArrayView3<bool>,
+    function: &'static str,
+) -> Result<(), FlaggingError> {
+    if flag_array.dim() != jones_array.dim() {
+        return Err(FlaggingError::BadArrayShape(BadArrayShape {
+            argument: "flag_array",
+            function,
+            expected: format!("{:?}", jones_array.dim()),
+            received: format!("{:?}", flag_array.dim()),
+        }));
+    }
+    Ok(())
+}
+
+/// Run `flag_pol` on the amplitudes and flags (with dimensions
+/// `[timestep][channel]`) of each pol of each baseline in parallel, and flag
+/// the visibilities flagged in any pol. Returns the number of newly flagged
+/// visibilities.
+fn flag_baselines_by_pol<F>(
+    jones_array: ArrayView3<Jones<f32>>,
+    mut flag_array: ArrayViewMut3<bool>,
+    flag_pol: F,
+) -> usize
+where
+    F: Fn(ArrayView2<f32>, ArrayViewMut2<bool>) + Sync,
+{
+    flag_array
+        .axis_iter_mut(Axis(2))
+        .into_par_iter()
+        .zip(jones_array.axis_iter(Axis(2)))
+        .map(|(mut flags_bl, jones_bl)| {
+            let mut new_flags = flags_bl.to_owned();
+            for pol in 0..4 {
+                let amps = jones_bl.mapv(|jones| jones[pol].norm());
+                let mut pol_flags = flags_bl.to_owned();
+                flag_pol(amps.view(), pol_flags.view_mut());
+                new_flags.zip_mut_with(&pol_flags, |flag, &pol_flag| *flag |= pol_flag);
+            }
+
+            let mut num_flagged = 0;
+            for (flag, &new_flag) in flags_bl.iter_mut().zip(new_flags.iter()) {
+                if new_flag && !*flag {
+                    *flag = true;
+                    num_flagged += 1;
+                }
+            }
+            num_flagged
+        })
+        .sum()
+}
+
+/// Subtract the median over time of the unflagged amplitudes of each channel.
+fn background_residuals(amps: ArrayView2<f32>, flags: ArrayView2<bool>) -> Array2<f32> {
+    let mut residuals = amps.to_owned();
+    for (mut residuals_chan, flags_chan) in residuals
+        .axis_iter_mut(Axis(1))
+        .zip(flags.axis_iter(Axis(1)))
+    {
+        let background = median(unflagged(residuals_chan.iter(), flags_chan.iter()));
+        if let Some(background) = background {
+            residuals_chan.mapv_inplace(|amp| amp - background);
+        }
+    }
+    residuals
+}
+
+/// The standard deviation of the unflagged residuals, estimated from their
+/// median absolute deviation.
+fn robust_std_dev(residuals: ArrayView2<f32>, flags: ArrayView2<bool>) -> f32 {
+    robust_stats(&unflagged(residuals.iter(), flags.iter())).map_or(f32::NAN, |(_, sigma)| sigma)
+}
+
+/// The median of `values` and the standard deviation estimated from their
+/// median absolute deviation.
+fn robust_stats(values: &[f32]) -> Option<(f32, f32)> {
+    let centre = median(values.to_vec())?;
+    let deviations = values.iter().map(|value| (value - centre).abs()).collect();
+    median(deviations).map(|mad| (centre, 1.4826 * mad))
+}
+
+fn unflagged<'a>(
+    values: impl Iterator<Item = &'a f32>,
+    flags: impl Iterator<Item = &'a bool>,
+) -> Vec<f32> {
+    values
+        .zip(flags)
+        .filter(|(_, &flag)| !flag)
+        .map(|(&value, _)| value)
+        .collect()
+}
+
+fn median(mut values: Vec<f32>) -> Option<f32> {
+    values.retain(|value| !value.is_nan());
+    if values.is_empty() {
+        return None;
+    }
+    let mid = values.len() / 2;
+    let (_, &mut median, _) =
+        values.select_nth_unstable_by(mid, |a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    Some(median)
+}
+
+/// Flag every `window` consecutive samples whose unflagged residuals have a
+/// mean with a magnitude above `threshold`.
+fn sum_threshold(
+    residuals: ArrayView1<f32>,
+    mut flags: ArrayViewMut1<bool>,
+    window: usize,
+    threshold: f32,
+) {
+    let len = residuals.len();
+    if window > len {
+        return;
+    }
+    let mut to_flag = vec![false; len];
+    let mut sum = 0.0;
+    let mut count = 0;
+    for i in 0..len {
+        if !flags[i] {
+            sum += residuals[i];
+            count += 1;
+        }
+        if i >= window && !flags[i - window] {
+            sum -= residuals[i - window];
+            count -= 1;
+        }
+        if i + 1 >= window && count > 0 && (sum / count as f32).abs() > threshold {
+            to_flag[i + 1 - window..=i]
+                .iter_mut()
+                .for_each(|f| *f = true);
+        }
+    }
+    for (flag, to_flag) in flags.iter_mut().zip(to_flag) {
+        *flag |= to_flag;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use hifitime::{Duration, Epoch, Unit};
+
+    use super::*;
+    use crate::{synth::uniform, AzEl, ENH};
+
+    /// Three tiles: tile 0 is 10 metres east of tile 1, and tile 2 is far to
+    /// the north.
+    fn get_tile_xyzs(array_pos: LatLngHeight) -> Vec<XyzGeodetic> {
+        [(10.0, 0.0), (0.0, 0.0), (0.0, 100.0)]
+            .iter()
+            .map(|&(e, n)| ENH { e, n, h: 0.0 }.to_xyz(array_pos.latitude_rad))
+            .collect()
+    }
+
+    fn pointing(array_pos: LatLngHeight, az_deg: f64, el_deg: f64) -> HADec {
+        HADec::from_enh_direction(
+            AzEl::new_degrees(az_deg, el_deg).to_enh_direction(),
+            array_pos.latitude_rad,
+        )
+    }
+
+    #[test]
+    fn test_shadowed_tiles() {
+        let array_pos = LatLngHeight::new_mwa();
+        let tile_xyzs = get_tile_xyzs(array_pos);
+
+        // Nothing is shadowed at the zenith.
+        let shadowed = shadowed_tiles(&tile_xyzs, pointing(array_pos, 0.0, 90.0), 5.0);
+        assert_eq!(shadowed, vec![false, false, false]);
+
+        // Low in the east, tile 0 shadows tile 1, as the projected separation
+        // is 10 * sin(20°) ~= 3.4 metres.
+        let shadowed = shadowed_tiles(&tile_xyzs, pointing(array_pos, 90.0, 20.0), 5.0);
+        assert_eq!(shadowed, vec![false, true, false]);
+        // Low in the west, tile 1 shadows tile 0.
+        let shadowed = shadowed_tiles(&tile_xyzs, pointing(array_pos, 270.0, 20.0), 5.0);
+        assert_eq!(shadowed, vec![true, false, false]);
+        // Smaller footprints aren't shadowed.
+        let shadowed = shadowed_tiles(&tile_xyzs, pointing(array_pos, 90.0, 20.0), 3.0);
+        assert_eq!(shadowed, vec![false, false, false]);
+    }
+
+    #[test]
+    fn test_flag_shadowing_and_elevation() {
+        let array_pos = LatLngHeight::new_mwa();
+        let tile_xyzs = get_tile_xyzs(array_pos);
+        let vis_ctx = VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(2., Unit::Second),
+                3,
+            )
+            .channels(150e6, 40e3, 2)
+            .baselines(vec![(0, 1), (0, 2), (1, 2)])
+            .build()
+            .unwrap();
+        let pointings = [
+            pointing(array_pos, 0.0, 90.0),
+            pointing(array_pos, 90.0, 20.0),
+            pointing(array_pos, 90.0, 5.0),
+        ];
+        let mut flag_array = Array3::from_elem(vis_ctx.sel_dims(), false);
+        flag_array[(0, 1, 1)] = true;
+
+        let num_flagged = flag_shadowing_and_elevation(
+            flag_array.view_mut(),
+            &vis_ctx,
+            &tile_xyzs,
+            array_pos,
+            &pointings,
+            5.0,
+            10_f64.to_radians(),
+        )
+        .unwrap();
+
+        // Existing flags are kept.
+        assert!(flag_array[(0, 1, 1)]);
+        assert_eq!(
+            flag_array
+                .slice(s![0, .., ..])
+                .iter()
+                .filter(|f| **f)
+                .count(),
+            1
+        );
+        // Tile 1 is shadowed.
+        assert_eq!(
+            flag_array.slice(s![1, 0, ..]).to_vec(),
+            vec![true, false, true]
+        );
+        // The pointing is too low.
+        assert!(flag_array.slice(s![2, .., ..]).iter().all(|f| *f));
+        assert_eq!(num_flagged, 2 * 2 + 2 * 3);
+
+        let result = flag_shadowing_and_elevation(
+            flag_array.view_mut(),
+            &vis_ctx,
+            &tile_xyzs,
+            array_pos,
+            &pointings[..2],
+            5.0,
+            0.0,
+        );
+        assert!(matches!(result, Err(FlaggingError::BadArrayShape(_))));
+    }
+
+    #[test]
+    fn test_flag_sum_threshold() {
+        let dim = (32, 16, 2);
+        // Uniform pseudo-random noise with a standard deviation of ~0.29 on a
+        // flat background of 10, with a strong broadband burst at timestep 3
+        // and weak (3.5 to 7 sigma) narrowband RFI in channel 5 for timesteps
+        // 12 to 15.
+        let jones_array = Array3::from_shape_fn(dim, |(t, c, b)| {
+            let index = (t * dim.1 * dim.2 + c * dim.2 + b) as u64;
+            let noise = uniform(0, index) as f32 - 0.5;
+            let mut amp = 10.0 + noise;
+            if t == 3 {
+                amp += 5.0;
+            }
+            if c == 5 && (12..16).contains(&t) {
+                amp += 1.5;
+            }
+            Jones::identity() * amp
+        });
+        let mut flag_array = Array3::from_elem(dim, false);
+        flag_array[(0, 0, 0)] = true;
+
+        let num_flagged = flag_sum_threshold(
+            jones_array.view(),
+            flag_array.view_mut(),
+            &SumThresholdParams::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            num_flagged,
+            flag_array.iter().filter(|&&flag| flag).count() - 1
+        );
+        // Existing flags are kept.
+        assert!(flag_array[(0, 0, 0)]);
+        for bl in 0..dim.2 {
+            assert!(flag_array.slice(s![3, .., bl]).iter().all(|&flag| flag));
+            assert!(flag_array.slice(s![12..16, 5, bl]).iter().all(|&flag| flag));
+        }
+        // Hardly anything else is flagged.
+        let mut rfi = Array3::from_elem(dim, false);
+        rfi.slice_mut(s![3, .., ..]).fill(true);
+        rfi.slice_mut(s![12..16, 5, ..]).fill(true);
+        let num_false_positives = flag_array
+            .iter()
+            .zip(rfi.iter())
+            .filter(|(&flag, &rfi)| flag && !rfi)
+            .count();
+        assert!(num_false_positives < dim.0 * dim.1 * dim.2 / 50);
+
+        let result = flag_sum_threshold(
+            jones_array.view(),
+            flag_array.slice_mut(s![.., 1.., ..]),
+            &SumThresholdParams::default(),
+        );
+        assert!(matches!(result, Err(FlaggingError::BadArrayShape(_))));
+    }
+
+    #[test]
+    fn test_flag_mad_and_occupancy() {
+        let dim = (20, 10, 3);
+        // Amplitudes vary a little, except for a bright channel and a bright
+        // timestep on baseline 1.
+        let jones_array = Array3::from_shape_fn(dim, |(t, c, b)| {
+            let index = (t * dim.1 * dim.2 + c * dim.2 + b) as u64;
+            let noise = uniform(0, index) as f32 - 0.5;
+            let mut amp = 10.0 + 0.5 * noise;
+            if b == 1 && (c == 4 || t == 12) {
+                amp *= 3.0;
+            }
+            Jones::identity() * amp
+        });
+        let mut flag_array = Array3::from_elem(dim, false);
+
+        let num_flagged = flag_mad(
+            jones_array.view(),
+            flag_array.view_mut(),
+            &MadParams::default(),
+        )
+        .unwrap();
+        assert_eq!(num_flagged, dim.0 + dim.1 - 1);
+        assert!(flag_array.slice(s![.., 4, 1]).iter().all(|&flag| flag));
+        assert!(flag_array.slice(s![12, .., 1]).iter().all(|&flag| flag));
+
+        let occupancy = flag_occupancy(flag_array.view());
+        let num_vis = (dim.0 * dim.1 * dim.2) as f64;
+        assert_abs_diff_eq!(
+            occupancy.total,
+            num_flagged as f64 / num_vis,
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(
+            occupancy.baselines,
+            array![0.0, num_flagged as f64 / 200.0, 0.0],
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(occupancy.channels[4], 1.0 / 3.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(occupancy.channels[0], 1.0 / 60.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(occupancy.timesteps[12], 1.0 / 3.0, epsilon = 1e-12);
+        assert!(flag_occupancy(Array3::from_elem((0, 2, 2), false).view())
+            .channels
+            .iter()
+            .all(|occupancy| occupancy.is_nan()));
+
+        // Composed with SumThreshold, the MAD flags are kept.
+        let mut composed_flags = Array3::from_elem(dim, false);
+        let num_composed = flag_rfi(
+            jones_array.view(),
+            composed_flags.view_mut(),
+            &[
+                RfiFlagger::Mad(MadParams::default()),
+                RfiFlagger::SumThreshold(SumThresholdParams::default()),
+            ],
+        )
+        .unwrap();
+        assert!(num_composed >= num_flagged);
+        assert_eq!(
+            num_composed,
+            composed_flags.iter().filter(|&&flag| flag).count()
+        );
+        assert!(flag_array
+            .iter()
+            .zip(composed_flags.iter())
+            .all(|(&flag, &composed)| !flag || composed));
+
+        // Only timesteps.
+        let mut flag_array = Array3::from_elem(dim, false);
+        let params = MadParams {
+            flag_channels: false,
+            ..Default::default()
+        };
+        let num_flagged = flag_mad(jones_array.view(), flag_array.view_mut(), &params).unwrap();
+        assert_eq!(num_flagged, dim.1);
+
+        let result = flag_rfi(
+            jones_array.view(),
+            flag_array.slice_mut(s![.., .., 1..]),
+            &[RfiFlagger::Mad(params)],
+        );
+        assert!(matches!(result, Err(FlaggingError::BadArrayShape(_))));
+    }
+
+    #[test]
+    fn test_flag_layers() {
+        let dim = (3, 4, 2);
+        let mut layers = FlagLayers::new(dim);
+        assert!(layers.is_empty());
+        assert!(layers.layer(FLAG_LAYER_RFI).is_none());
+
+        layers.layer_mut(FLAG_LAYER_MISSING_DATA)[(0, 0, 0)] = true;
+        let mut user = Array3::from_elem(dim, false);
+        user.slice_mut(s![.., 3, ..]).fill(true);
+        layers.insert(FLAG_LAYER_USER, user).unwrap();
+        layers.layer_mut(FLAG_LAYER_RFI)[(2, 3, 1)] = true;
+        layers.layer_mut(FLAG_LAYER_RFI)[(1, 1, 1)] = true;
+        assert_eq!(
+            layers.names(),
+            [FLAG_LAYER_MISSING_DATA, FLAG_LAYER_USER, FLAG_LAYER_RFI]
+        );
+        assert_eq!(
+            layers
+                .layer(FLAG_LAYER_RFI)
+                .unwrap()
+                .iter()
+                .filter(|&&flag| flag)
+                .count(),
+            2
+        );
+
+        let combined = layers.combined();
+        assert_eq!(combined.iter().filter(|&&flag| flag).count(), 1 + 6 + 1);
+        assert!(combined[(0, 0, 0)] && combined[(1, 1, 1)] && combined[(2, 3, 1)]);
+
+        // Undoing the user flags keeps the RFI flags, even where they overlap.
+        let user = layers.remove(FLAG_LAYER_USER).unwrap();
+        assert_eq!(user.iter().filter(|&&flag| flag).count(), 6);
+        assert!(layers.remove(FLAG_LAYER_USER).is_none());
+        let combined = layers.combined();
+        assert_eq!(combined.iter().filter(|&&flag| flag).count(), 3);
+        assert!(combined[(2, 3, 1)]);
+
+        let result = layers.insert(FLAG_LAYER_USER, Array3::from_elem((3, 4, 1), false));
+        assert!(matches!(result, Err(FlaggingError::BadArrayShape(_))));
+        assert_eq!(layers.len(), 2);
+    }
+}
@@ -0,0 +1,545 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Simple delay and rate fringe fitting, e.g. for commissioning diagnostics.
+//!
+//! The pseudo-Stokes I visibilities, (XX + YY) / 2, of each baseline are
+//! Fourier transformed over frequency and time, and the peak of the transform
+//! gives the delay and rate of the baseline. The delays and rates of the
+//! antennas are then found from those of the baselines by least squares.
+
+use itertools::izip;
+use ndarray::prelude::*;
+use rayon::prelude::*;
+use rustfft::FftPlanner;
+use thiserror::Error;
+
+use crate::{errors::BadArrayShape, Complex, Jones, VisContext};
+
+#[derive(Error, Debug)]
+pub enum FringeError {
+    #[error(transparent)]
+    BadArrayShape(#[from] BadArrayShape),
+
+    #[error("antenna {antenna} is connected to the reference antenna {ref_ant}, but its solution is not constrained")]
+    Unconstrained { antenna: usize, ref_ant: usize },
+}
+
+/// The fringe of a baseline, found by [`fringe_fit`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fringe {
+    /// The delay \[seconds\]. A delay τ gives visibilities proportional to
+    /// `exp(2πiντ)`.
+    pub delay_s: f64,
+
+    /// The rate of change of the phase \[Hz\].
+    pub rate_hz: f64,
+
+    /// The amplitude of the fringe: the norm of the peak of the weighted
+    /// delay-rate spectrum divided by the sum of the weights. This is the
+    /// weighted mean amplitude of the Stokes I visibilities after they are
+    /// stopped at the fringe's delay and rate, so it is their amplitude if all
+    /// of their power is in the fringe, and less otherwise.
+    pub amplitude: f64,
+
+    /// The phase of the fringe at the first channel and timestep \[radians\].
+    pub phase_rad: f64,
+}
+
+/// The delay and rate of an antenna, found by [`antenna_fringes`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AntennaFringe {
+    /// The delay \[seconds\].
+    pub delay_s: f64,
+
+    /// The rate \[Hz\].
+    pub rate_hz: f64,
+}
+
+/// Find the delay and rate of each baseline by Fourier transforming its
+/// pseudo-Stokes I visibilities over frequency and time.
+///
+/// `jones_array` - visibilities with dimensions `[timestep][channel][baseline]`
+///
+/// `weight_array` - weights with dimensions `[timestep][channel][baseline]`.
+///     Flagged (negative weight) visibilities are excluded.
+///
+/// `vis_ctx` - the timesteps, channels and baselines of the arrays
+///
+/// `oversample` - the factor by which the frequency and time axes are
+///     zero-padded, so that the peak is found on a finer grid. The peak is
+///     then refined by parabolic interpolation.
+///
+/// The delays are within ±1 / (2 × the channel width), and the rates are within
+/// ±1 / (2 × the integration time). Baselines without any unflagged
+/// visibilities have NaN delays and rates, and zero amplitudes. Baselines are
+/// fitted in parallel.
+///
+/// # Errors
+///
+/// Will return [`FringeError::BadArrayShape`] if the shapes of the arrays don't
+/// match `vis_ctx`.
+pub fn fringe_fit(
+    jones_array: ArrayView3<Jones<f32>>,
+    weight_array: ArrayView3<f32>,
+    vis_ctx: &VisContext,
+    oversample: usize,
+) -> Result<Vec<Fringe>, FringeError> {
+    let sel_dims = vis_ctx.sel_dims();
+    for (argument, dim) in [
+        ("jones_array", jones_array.dim()),
+        ("weight_array", weight_array.dim()),
+    ] {
+        if dim != sel_dims {
+            return Err(FringeError::BadArrayShape(BadArrayShape {
+                argument,
+                function: "fringe_fit",
+                expected: format!("{:?}", sel_dims),
+                received: format!("{:?}", dim),
+            }));
+        }
+    }
+    let (num_timesteps, num_chans, _) = sel_dims;
+    let oversample = oversample.max(1);
+    let num_padded_timesteps = num_timesteps * oversample;
+    let num_padded_chans = num_chans * oversample;
+    let no_fringe = Fringe {
+        delay_s: f64::NAN,
+        rate_hz: f64::NAN,
+        amplitude: 0.0,
+        phase_rad: 0.0,
+    };
+    if num_timesteps == 0 || num_chans == 0 {
+        return Ok(vec![no_fringe; sel_dims.2]);
+    }
+
+    let mut planner = FftPlanner::<f64>::new();
+    let freq_fft = planner.plan_fft_forward(num_padded_chans);
+    let time_fft = planner.plan_fft_forward(num_padded_timesteps);
+    let delay_resolution_s = 1.0 / (num_padded_chans as f64 * vis_ctx.freq_resolution_hz);
+    let rate_resolution_hz = 1.0 / (num_padded_timesteps as f64 * vis_ctx.int_time.in_seconds());
+
+    let fringes = jones_array
+        .axis_iter(Axis(2))
+        .into_par_iter()
+        .zip(weight_array.axis_iter(Axis(2)))
+        .map(|(jones_bl, weight_bl)| {
+            // [padded timestep][padded channel]
+            let mut grid = Array2::<Complex<f64>>::zeros((num_padded_timesteps, num_padded_chans));
+            let mut weight_sum = 0.0;
+            for (mut grid_ts, jones_ts, weight_ts) in izip!(
+                grid.outer_iter_mut(),
+                jones_bl.outer_iter(),
+                weight_bl.outer_iter()
+            ) {
+                for (value, jones, &weight) in izip!(grid_ts.iter_mut(), jones_ts, weight_ts) {
+                    if weight > 0.0 {
+                        let weight = weight as f64;
+                        let stokes_i = (jones[0] + jones[3]) * 0.5;
+                        *value = Complex::new(stokes_i.re as f64, stokes_i.im as f64) * weight;
+                        weight_sum += weight;
+                    }
+                }
+            }
+            if weight_sum <= 0.0 {
+                return no_fringe;
+            }
+
+            // Only the unpadded timesteps have any data.
+            let mut scratch = vec![Complex::default(); freq_fft.get_inplace_scratch_len()];
+            for mut row in grid.outer_iter_mut().take(num_timesteps) {
+                let row = row.as_slice_mut().expect("grid is in standard layout");
+                freq_fft.process_with_scratch(row, &mut scratch);
+            }
+            let mut column = vec![Complex::default(); num_padded_timesteps];
+            let mut scratch = vec![Complex::default(); time_fft.get_inplace_scratch_len()];
+            for mut grid_column in grid.axis_iter_mut(Axis(1)) {
+                column
+                    .iter_mut()
+                    .zip(grid_column.iter())
+                    .for_each(|(c, &g)| *c = g);
+                time_fft.process_with_scratch(&mut column, &mut scratch);
+                grid_column
+                    .iter_mut()
+                    .zip(column.iter())
+                    .for_each(|(g, &c)| *g = c);
+            }
+
+            let mut peak = ((0, 0), 0.0);
+            for (idx, value) in grid.indexed_iter() {
+                let norm = value.norm();
+                if norm > peak.1 {
+                    peak = (idx, norm);
+                }
+            }
+            let ((rate_idx, delay_idx), peak_norm) = peak;
+            let norm_at = |rate_idx: usize, delay_idx: usize| grid[(rate_idx, delay_idx)].norm();
+            let delay_offset = parabolic_offset(
+                norm_at(
+                    rate_idx,
+                    (delay_idx + num_padded_chans - 1) % num_padded_chans,
+                ),
+                peak_norm,
+                norm_at(rate_idx, (delay_idx + 1) % num_padded_chans),
+            );
+            let rate_offset = parabolic_offset(
+                norm_at(
+                    (rate_idx + num_padded_timesteps - 1) % num_padded_timesteps,
+                    delay_idx,
+                ),
+                peak_norm,
+                norm_at((rate_idx + 1) % num_padded_timesteps, delay_idx),
+            );
+
+            Fringe {
+                delay_s: (signed_bin(delay_idx, num_padded_chans) + delay_offset)
+                    * delay_resolution_s,
+                rate_hz: (signed_bin(rate_idx, num_padded_timesteps) + rate_offset)
+                    * rate_resolution_hz,
+                amplitude: peak_norm / weight_sum,
+                phase_rad: grid[(rate_idx, delay_idx)].arg(),
+            }
+        })
+        .collect();
+
+    Ok(fringes)
+}
+
+/// The (signed) frequency of a bin of a transform of length `len`.
+fn signed_bin(idx: usize, len: usize) -> f64 {
+    if idx > len / 2 {
+        idx as f64 - len as f64
+    } else {
+        idx as f64
+    }
+}
+
+/// The offset of the vertex of the parabola through the magnitudes of a peak
+/// bin and its neighbours \[bins\].
+fn parabolic_offset(before: f64, peak: f64, after: f64) -> f64 {
+    let curvature = before - 2.0 * peak + after;
+    if curvature < 0.0 {
+        (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    }
+}
+
+/// Find the delay and rate of each antenna from the [`Fringe`]s of the
+/// baselines, by weighted least squares. The fringe of baseline (a, b) is
+/// modelled as the fringe of antenna a minus that of antenna b, and each
+/// baseline is weighted by its fringe amplitude.
+///
+/// - `fringes` - the fringe of each baseline
+/// - `baselines` - the antennas of each baseline
+/// - `num_ants` - the number of antennas
+/// - `ref_ant` - the antenna whose delay and rate are zero
+///
+/// Antennas that aren't connected to `ref_ant` by baselines with finite
+/// fringes have NaN delays and rates.
+///
+/// # Errors
+///
+/// Will return [`FringeError::BadArrayShape`] if there isn't a fringe for
+/// each baseline, or an antenna index is out of range.
+pub fn antenna_fringes(
+    fringes: &[Fringe],
+    baselines: &[(usize, usize)],
+    num_ants: usize,
+    ref_ant: usize,
+) -> Result<Vec<AntennaFringe>, FringeError> {
+    if fringes.len() != baselines.len() {
+        return Err(FringeError::BadArrayShape(BadArrayShape {
+            argument: "fringes",
+            function: "antenna_fringes",
+            expected: format!("({},)", baselines.len()),
+            received: format!("({},)", fringes.len()),
+        }));
+    }
+    let max_ant = baselines
+        .iter()
+        .map(|&(ant1, ant2)| ant1.max(ant2))
+        .chain(std::iter::once(ref_ant))
+        .max()
+        .unwrap_or(0);
+    if max_ant >= num_ants {
+        return Err(FringeError::BadArrayShape(BadArrayShape {
+            argument: "baselines",
+            function: "antenna_fringes",
+            expected: format!("antenna indices less than {num_ants}"),
+            received: format!("{max_ant}"),
+        }));
+    }
+
+    let valid: Vec<(usize, usize, &Fringe)> = baselines
+        .iter()
+        .zip(fringes)
+        .filter(|(&(ant1, ant2), fringe)| {
+            ant1 != ant2
+                && fringe.amplitude > 0.0
+                && fringe.delay_s.is_finite()
+                && fringe.rate_hz.is_finite()
+        })
+        .map(|(&(ant1, ant2), fringe)| (ant1, ant2, fringe))
+        .collect();
+
+    // Find the antennas connected to the reference antenna; the others can't
+    // be solved for.
+    let mut connected = vec![false; num_ants];
+    connected[ref_ant] = true;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(ant1, ant2, _) in &valid {
+            if connected[ant1] != connected[ant2] {
+                connected[ant1] = true;
+                connected[ant2] = true;
+                changed = true;
+            }
+        }
+    }
+    // The index of each solved antenna in the normal equations.
+    let mut unknowns = vec![None; num_ants];
+    let mut num_unknowns = 0;
+    for (ant, unknown) in unknowns.iter_mut().enumerate() {
+        if connected[ant] && ant != ref_ant {
+            *unknown = Some(num_unknowns);
+            num_unknowns += 1;
+        }
+    }
+
+    // The normal equations of Σ w (x_a - x_b - x_ab)² are a weighted graph
+    // Laplacian, without the row and column of the reference antenna.
+    let mut normal = Array2::<f64>::zeros((num_unknowns, num_unknowns));
+    let mut rhs = Array2::<f64>::zeros((num_unknowns, 2));
+    for &(ant1, ant2, fringe) in &valid {
+        let w = fringe.amplitude;
+        let values = [fringe.delay_s, fringe.rate_hz];
+        for (ant, other, sign) in [(ant1, ant2, 1.0), (ant2, ant1, -1.0)] {
+            if let Some(i) = unknowns[ant] {
+                normal[(i, i)] += w;
+                if let Some(j) = unknowns[other] {
+                    normal[(i, j)] -= w;
+                }
+                for (rhs, value) in rhs.row_mut(i).iter_mut().zip(values) {
+                    *rhs += sign * w * value;
+                }
+            }
+        }
+    }
+    let solution = solve(normal, rhs).map_err(|i| FringeError::Unconstrained {
+        antenna: unknowns.iter().position(|&u| u == Some(i)).unwrap_or(i),
+        ref_ant,
+    })?;
+
+    Ok((0..num_ants)
+        .map(|ant| match unknowns[ant] {
+            Some(i) => AntennaFringe {
+                delay_s: solution[(i, 0)],
+                rate_hz: solution[(i, 1)],
+            },
+            None if ant == ref_ant => AntennaFringe {
+                delay_s: 0.0,
+                rate_hz: 0.0,
+            },
+            None => AntennaFringe {
+                delay_s: f64::NAN,
+                rate_hz: f64::NAN,
+            },
+        })
+        .collect())
+}
+
+/// Solve `a x = b` by Gaussian elimination with partial pivoting. Returns the
+/// index of the first unknown without a pivot if `a` is singular.
+fn solve(mut a: Array2<f64>, mut b: Array2<f64>) -> Result<Array2<f64>, usize> {
+    let n = a.nrows();
+    let scale = a.iter().fold(0.0_f64, |acc, x| acc.max(x.abs()));
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&i, &j| {
+                a[(i, col)]
+                    .abs()
+                    .partial_cmp(&a[(j, col)].abs())
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap_or(col);
+        if a[(pivot, col)].abs() <= scale * 1e-12 {
+            return Err(col);
+        }
+        if pivot != col {
+            for k in 0..n {
+                a.swap((pivot, k), (col, k));
+            }
+            for k in 0..b.ncols() {
+                b.swap((pivot, k), (col, k));
+            }
+        }
+        for row in col + 1..n {
+            let factor = a[(row, col)] / a[(col, col)];
+            for k in col..n {
+                a[(row, k)] -= factor * a[(col, k)];
+            }
+            for k in 0..b.ncols() {
+                b[(row, k)] -= factor * b[(col, k)];
+            }
+        }
+    }
+    for col in (0..n).rev() {
+        for k in 0..b.ncols() {
+            let sum: f64 = (col + 1..n).map(|j| a[(col, j)] * b[(j, k)]).sum();
+            b[(col, k)] = (b[(col, k)] - sum) / a[(col, col)];
+        }
+    }
+    Ok(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use approx::assert_abs_diff_eq;
+    use hifitime::{Duration, Epoch, Unit};
+
+    use super::*;
+
+    /// The phase of a fringe with the given delay and rate, relative to the first
+    /// channel and timestep \[radians\].
+    fn fringe_phase(delay_s: f64, rate_hz: f64, freq_offset_hz: f64, time_offset_s: f64) -> f64 {
+        TAU * (delay_s * freq_offset_hz + rate_hz * time_offset_s)
+    }
+
+    fn get_vis_ctx(baselines: Vec<(usize, usize)>) -> VisContext {
+        VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(2., Unit::Second),
+                16,
+            )
+            .channels(150e6, 40e3, 32)
+            .baselines(baselines)
+            .build()
+            .unwrap()
+    }
+
+    /// Visibilities of antennas with the given delays and rates.
+    fn fringe_vis(vis_ctx: &VisContext, antennas: &[AntennaFringe]) -> Array3<Jones<f32>> {
+        let int_time_s = vis_ctx.int_time.in_seconds();
+        Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| {
+            let (ant1, ant2) = vis_ctx.sel_baselines[b];
+            let phase = fringe_phase(
+                antennas[ant1].delay_s - antennas[ant2].delay_s,
+                antennas[ant1].rate_hz - antennas[ant2].rate_hz,
+                c as f64 * vis_ctx.freq_resolution_hz,
+                t as f64 * int_time_s,
+            ) + 0.3;
+            let vis = Complex::new(phase.cos() as f32, phase.sin() as f32);
+            Jones::from([vis, Complex::default(), Complex::default(), vis])
+        })
+    }
+
+    #[test]
+    fn test_fringe_fit() {
+        let baselines = vec![(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+        let vis_ctx = get_vis_ctx(baselines.clone());
+        let antennas = [
+            AntennaFringe {
+                delay_s: 0.0,
+                rate_hz: 0.0,
+            },
+            AntennaFringe {
+                delay_s: 2.1e-6,
+                rate_hz: 0.01,
+            },
+            AntennaFringe {
+                delay_s: -3.3e-6,
+                rate_hz: -0.02,
+            },
+            AntennaFringe {
+                delay_s: 1.0e-6,
+                rate_hz: 0.035,
+            },
+        ];
+        let jones_array = fringe_vis(&vis_ctx, &antennas);
+        let mut weight_array = Array3::from_elem(vis_ctx.sel_dims(), 1.0);
+        // Flags don't move the peak.
+        weight_array.slice_mut(s![3, .., ..]).fill(-1.0);
+
+        let fringes = fringe_fit(jones_array.view(), weight_array.view(), &vis_ctx, 8).unwrap();
+        assert_eq!(fringes.len(), baselines.len());
+        // The grid is 1 / (8 * 32 * 40 kHz) ~= 98 ns and 1 / (8 * 16 * 2 s)
+        // ~= 4 mHz.
+        for (fringe, &(ant1, ant2)) in fringes.iter().zip(&baselines) {
+            assert_abs_diff_eq!(
+                fringe.delay_s,
+                antennas[ant1].delay_s - antennas[ant2].delay_s,
+                epsilon = 2e-8
+            );
+            assert_abs_diff_eq!(
+                fringe.rate_hz,
+                antennas[ant1].rate_hz - antennas[ant2].rate_hz,
+                epsilon = 1e-3
+            );
+            assert!(fringe.amplitude > 0.8, "{fringe:?}");
+        }
+
+        let solutions = antenna_fringes(&fringes, &baselines, 5, 0).unwrap();
+        assert_eq!(solutions.len(), 5);
+        for (solution, expected) in solutions.iter().zip(&antennas) {
+            assert_abs_diff_eq!(solution.delay_s, expected.delay_s, epsilon = 2e-8);
+            assert_abs_diff_eq!(solution.rate_hz, expected.rate_hz, epsilon = 1e-3);
+        }
+        // Antenna 4 isn't in any baseline.
+        assert!(solutions[4].delay_s.is_nan());
+    }
+
+    #[test]
+    fn test_fringe_fit_on_grid() {
+        let vis_ctx = get_vis_ctx(vec![(0, 1)]);
+        let antennas = [
+            AntennaFringe {
+                delay_s: 0.0,
+                rate_hz: 0.0,
+            },
+            // Exactly 3 delay bins and -2 rate bins without oversampling.
+            AntennaFringe {
+                delay_s: 3.0 / (32.0 * 40e3),
+                rate_hz: -2.0 / (16.0 * 2.0),
+            },
+        ];
+        let jones_array = fringe_vis(&vis_ctx, &antennas);
+        let weight_array = Array3::from_elem(vis_ctx.sel_dims(), 2.0);
+        let fringes = fringe_fit(jones_array.view(), weight_array.view(), &vis_ctx, 1).unwrap();
+        // The fringe of baseline (0, 1) is that of antenna 0 minus antenna 1.
+        assert_abs_diff_eq!(fringes[0].delay_s, -antennas[1].delay_s, epsilon = 1e-12);
+        assert_abs_diff_eq!(fringes[0].rate_hz, -antennas[1].rate_hz, epsilon = 1e-12);
+        assert_abs_diff_eq!(fringes[0].amplitude, 1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(fringes[0].phase_rad, 0.3, epsilon = 1e-6);
+
+        // Everything flagged.
+        let fringes = fringe_fit(
+            jones_array.view(),
+            weight_array.mapv(|w| -w).view(),
+            &vis_ctx,
+            1,
+        )
+        .unwrap();
+        assert!(fringes[0].delay_s.is_nan());
+        assert_abs_diff_eq!(fringes[0].amplitude, 0.0);
+
+        assert!(matches!(
+            fringe_fit(
+                jones_array.view(),
+                weight_array.slice(s![.., 1.., ..]),
+                &vis_ctx,
+                1
+            ),
+            Err(FringeError::BadArrayShape(_))
+        ));
+        assert!(matches!(
+            antenna_fringes(&fringes, &[(0, 1)], 1, 0),
+            Err(FringeError::BadArrayShape(_))
+        ));
+    }
+}
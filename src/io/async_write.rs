@@ -0,0 +1,539 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Write visibilities asynchronously, e.g. to stream outputs straight to
+//! S3-compatible object storage.
+//!
+//! [`StreamingWriter`] drives a [`VisWrite`] implementor on its own thread,
+//! staging the output on local disk, and uploads the output to an
+//! [`ObjectSink`] in parts as it's written. Parts are a whole number of FITS
+//! blocks, so each part of a uvfits file is a run of whole HDU blocks. The
+//! first part (containing the primary header, which is rewritten when the file
+//! is finalised) is held back until the end, so it must be at least as long as
+//! the primary header of a FITS output. Directory outputs (measurement sets,
+//! Parquet and Zarr) can't be streamed; they're archived with `tar` and
+//! uploaded in parts when finalised.
+
+use std::{
+    ffi::OsString,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+use async_trait::async_trait;
+use ndarray::prelude::*;
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt},
+    sync::oneshot,
+};
+
+use super::VisWrite;
+use crate::{errors::IOError, Jones, VisContext};
+
+/// The size of a FITS block.
+const FITS_BLOCK_LEN: u64 = 2880;
+
+/// The default size of the parts of an upload: 2048 FITS blocks (~5.9 MB),
+/// which is above the 5 MiB minimum part size of S3 multipart uploads.
+pub const DEFAULT_PART_SIZE: u64 = FITS_BLOCK_LEN * 2048;
+
+/// The number of bytes at the end of a staged file that may not have been
+/// written yet, and so aren't uploaded until the file is finalised. This is the
+/// size of cfitsio's buffers (40 FITS blocks).
+const UNSTABLE_TAIL_LEN: u64 = FITS_BLOCK_LEN * 40;
+
+/// A destination of the bytes of an output that is uploaded in parts, e.g. an
+/// S3 multipart upload.
+#[async_trait]
+pub trait ObjectSink: Send {
+    /// Upload a part of the object. Parts are numbered from 1, and are all the
+    /// same size except for the last part. Parts may be uploaded in any order.
+    async fn put_part(&mut self, part_number: usize, bytes: Vec<u8>) -> std::io::Result<()>;
+
+    /// Assemble the object once all `num_parts` parts have been uploaded.
+    async fn complete(&mut self, num_parts: usize) -> std::io::Result<()>;
+}
+
+/// An async variant of [`VisWrite`]. The arrays are owned so that they can be
+/// handed to another task or thread.
+#[async_trait]
+pub trait AsyncVisWrite: Send {
+    /// Write a chunk of visibilities; see [`VisWrite::write_vis`].
+    async fn write_vis(
+        &mut self,
+        vis: Array3<Jones<f32>>,
+        weights: Array3<f32>,
+        vis_ctx: VisContext,
+    ) -> Result<(), IOError>;
+
+    /// Perform any remaining tasks once all visibilities have been written;
+    /// see [`VisWrite::finalise`].
+    async fn finalise(&mut self) -> Result<(), IOError>;
+}
+
+enum Command {
+    Write {
+        vis: Array3<Jones<f32>>,
+        weights: Array3<f32>,
+        vis_ctx: Box<VisContext>,
+        reply: oneshot::Sender<Result<(), IOError>>,
+    },
+    Finalise {
+        reply: oneshot::Sender<Result<(), IOError>>,
+    },
+}
+
+/// An [`AsyncVisWrite`] implementor that stages the output of a [`VisWrite`]
+/// implementor at a local path and uploads it to an [`ObjectSink`]; see the
+/// [module documentation](self).
+pub struct StreamingWriter<S: ObjectSink> {
+    commands: mpsc::Sender<Command>,
+    sink: S,
+    staging_path: PathBuf,
+    part_size: u64,
+    /// The number of the next part to upload; part 1 is uploaded last.
+    next_part: usize,
+}
+
+impl<S: ObjectSink> StreamingWriter<S> {
+    /// Create the writer with `make_writer`, which is given `staging_path`.
+    /// `make_writer` is run on the writer's own thread, so the writer doesn't
+    /// need to be [`Send`] (e.g. a `UvfitsWriter`).
+    ///
+    /// `part_size` is rounded up to a whole number of FITS blocks;
+    /// [`DEFAULT_PART_SIZE`] suits S3.
+    ///
+    /// # Errors
+    ///
+    /// Will return the error of `make_writer`. Writing and finalising will
+    /// return [`IOError::PartSmallerThanHeader`] if the primary header of a
+    /// FITS output is longer than `part_size`, before any part that overlaps
+    /// the header is uploaded.
+    pub async fn new<W, F, P>(
+        make_writer: F,
+        staging_path: P,
+        sink: S,
+        part_size: u64,
+    ) -> Result<Self, IOError>
+    where
+        W: VisWrite,
+        F: FnOnce(&Path) -> Result<W, IOError> + Send + 'static,
+        P: Into<PathBuf>,
+    {
+        let staging_path = staging_path.into();
+        let (commands, receiver) = mpsc::channel();
+        let (ready, is_ready) = oneshot::channel();
+        let path = staging_path.clone();
+        std::thread::spawn(move || {
+            let mut writer = match make_writer(&path) {
+                Ok(writer) => {
+                    let _ = ready.send(Ok(()));
+                    writer
+                }
+                Err(e) => {
+                    let _ = ready.send(Err(e));
+                    return;
+                }
+            };
+            // The loop ends when the StreamingWriter is dropped.
+            for command in receiver {
+                // Nothing can be done if the reply can't be sent.
+                let _ = match command {
+                    Command::Write {
+                        vis,
+                        weights,
+                        vis_ctx,
+                        reply,
+                    } => reply.send(writer.write_vis(vis.view(), weights.view(), &vis_ctx, false)),
+                    Command::Finalise { reply } => reply.send(writer.finalise()),
+                };
+            }
+        });
+        is_ready.await.map_err(|_| writer_stopped())??;
+
+        Ok(Self {
+            commands,
+            sink,
+            staging_path,
+            part_size: (part_size.max(1) + FITS_BLOCK_LEN - 1) / FITS_BLOCK_LEN * FITS_BLOCK_LEN,
+            next_part: 2,
+        })
+    }
+
+    /// The sink that the output is uploaded to.
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Send a command to the writer's thread and wait for its reply.
+    async fn request(
+        &mut self,
+        command: impl FnOnce(oneshot::Sender<Result<(), IOError>>) -> Command,
+    ) -> Result<(), IOError> {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .map_err(|_| writer_stopped())?;
+        response.await.map_err(|_| writer_stopped())?
+    }
+
+    /// Upload the parts of the staged file (after the first) that won't
+    /// change, or all of them if the file is `finished`.
+    async fn upload_parts(&mut self, finished: bool) -> Result<(), IOError> {
+        let len = match tokio::fs::metadata(&self.staging_path).await {
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            // Nothing has been written yet, or the output is a directory.
+            _ => return Ok(()),
+        };
+        let stable_len = if finished {
+            len
+        } else {
+            len.saturating_sub(UNSTABLE_TAIL_LEN)
+        };
+        let mut file = tokio::fs::File::open(&self.staging_path).await?;
+        loop {
+            let start = (self.next_part as u64 - 1) * self.part_size;
+            let end = (start + self.part_size).min(stable_len);
+            // Only the last part can be short.
+            if start >= stable_len || (end - start < self.part_size && !finished) {
+                break;
+            }
+            if self.next_part == 2 {
+                check_header_len(&mut file, len, self.part_size).await?;
+            }
+            let bytes = read_range(&mut file, start, end).await?;
+            self.sink.put_part(self.next_part, bytes).await?;
+            self.next_part += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Check that the first part contains the whole primary header of a FITS
+/// output, so that the header can still be rewritten after the other parts
+/// have been uploaded. Other outputs aren't checked.
+///
+/// cfitsio only writes the END card when an HDU is closed, so while the output
+/// is being written, the header ends at the first block that isn't all text.
+async fn check_header_len(
+    file: &mut tokio::fs::File,
+    len: u64,
+    part_size: u64,
+) -> Result<(), IOError> {
+    let mut block_start = 0;
+    while block_start + FITS_BLOCK_LEN <= len {
+        if block_start >= part_size {
+            return Err(IOError::PartSmallerThanHeader { part_size });
+        }
+        let block = read_range(file, block_start, block_start + FITS_BLOCK_LEN).await?;
+        if block_start == 0 && !block.starts_with(b"SIMPLE  =") {
+            return Ok(());
+        }
+        if !block.iter().all(|b| (b' '..=b'~').contains(b)) {
+            break;
+        }
+        block_start += FITS_BLOCK_LEN;
+        if block
+            .chunks_exact(80)
+            .any(|card| card.starts_with(b"END") && card[3..].iter().all(|&b| b == b' '))
+        {
+            break;
+        }
+    }
+    if block_start > part_size {
+        return Err(IOError::PartSmallerThanHeader { part_size });
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl<S: ObjectSink> AsyncVisWrite for StreamingWriter<S> {
+    async fn write_vis(
+        &mut self,
+        vis: Array3<Jones<f32>>,
+        weights: Array3<f32>,
+        vis_ctx: VisContext,
+    ) -> Result<(), IOError> {
+        self.request(|reply| Command::Write {
+            vis,
+            weights,
+            vis_ctx: Box::new(vis_ctx),
+            reply,
+        })
+        .await?;
+        self.upload_parts(false).await
+    }
+
+    async fn finalise(&mut self) -> Result<(), IOError> {
+        self.request(|reply| Command::Finalise { reply }).await?;
+
+        if tokio::fs::metadata(&self.staging_path).await?.is_dir() {
+            let dir = self.staging_path.clone();
+            let mut archive_path = OsString::from(&dir);
+            archive_path.push(".tar");
+            let archive_path = PathBuf::from(archive_path);
+            let archive = archive_path.clone();
+            tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+                let mut builder = tar::Builder::new(std::fs::File::create(&archive)?);
+                let name = dir.file_name().map_or_else(|| ".".into(), PathBuf::from);
+                builder.append_dir_all(name, &dir)?;
+                builder.finish()
+            })
+            .await
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))??;
+            self.staging_path = archive_path;
+        }
+
+        self.upload_parts(true).await?;
+        let mut file = tokio::fs::File::open(&self.staging_path).await?;
+        let len = file.metadata().await?.len();
+        let first_part = read_range(&mut file, 0, self.part_size.min(len)).await?;
+        self.sink.put_part(1, first_part).await?;
+        self.sink.complete(self.next_part - 1).await?;
+        Ok(())
+    }
+}
+
+async fn read_range(file: &mut tokio::fs::File, start: u64, end: u64) -> std::io::Result<Vec<u8>> {
+    let mut bytes = vec![0; (end - start) as usize];
+    file.seek(SeekFrom::Start(start)).await?;
+    file.read_exact(&mut bytes).await?;
+    Ok(bytes)
+}
+
+fn writer_stopped() -> IOError {
+    IOError::StdIo(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "the thread of the writer has stopped",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Seek, Write},
+        sync::{Arc, Mutex},
+    };
+
+    use hifitime::{Duration, Epoch, Unit};
+    use tempfile::tempdir;
+
+    use super::*;
+
+    /// Appends a byte for each visibility written.
+    struct ByteWriter(std::fs::File);
+
+    impl VisWrite for ByteWriter {
+        fn write_vis(
+            &mut self,
+            vis: ArrayView3<Jones<f32>>,
+            _weights: ArrayView3<f32>,
+            _vis_ctx: &VisContext,
+            _draw_progress: bool,
+        ) -> Result<(), IOError> {
+            let bytes: Vec<u8> = (0..vis.len()).map(|i| i as u8).collect();
+            self.0.write_all(&bytes)?;
+            Ok(())
+        }
+
+        fn finalise(&mut self) -> Result<(), IOError> {
+            // Like the checksums of a uvfits file, the start of the file is
+            // rewritten.
+            self.0.seek(SeekFrom::Start(0))?;
+            self.0.write_all(b"final")?;
+            Ok(())
+        }
+    }
+
+    type Parts = Arc<Mutex<Vec<(usize, Vec<u8>)>>>;
+
+    /// Records the parts that are uploaded.
+    #[derive(Clone, Default)]
+    struct RecordingSink {
+        parts: Parts,
+        num_parts: Arc<Mutex<Option<usize>>>,
+    }
+
+    #[async_trait]
+    impl ObjectSink for RecordingSink {
+        async fn put_part(&mut self, part_number: usize, bytes: Vec<u8>) -> std::io::Result<()> {
+            self.parts.lock().unwrap().push((part_number, bytes));
+            Ok(())
+        }
+
+        async fn complete(&mut self, num_parts: usize) -> std::io::Result<()> {
+            *self.num_parts.lock().unwrap() = Some(num_parts);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_streaming_writer() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("out.bin");
+        let sink = RecordingSink::default();
+        let vis_ctx = VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(1., Unit::Second),
+                1,
+            )
+            .channels(150e6, 40e3, 100)
+            .baselines(vec![(0, 1)])
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let mut writer = StreamingWriter::new(
+                |path: &Path| Ok(ByteWriter(std::fs::File::create(path)?)),
+                &path,
+                sink.clone(),
+                2880,
+            )
+            .await
+            .unwrap();
+            for _ in 0..2000 {
+                writer
+                    .write_vis(
+                        Array3::default(vis_ctx.sel_dims()),
+                        Array3::default(vis_ctx.sel_dims()),
+                        vis_ctx.clone(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            // Parts are uploaded while writing.
+            assert!(!sink.parts.lock().unwrap().is_empty());
+            assert!(sink.num_parts.lock().unwrap().is_none());
+            writer.finalise().await.unwrap();
+        });
+
+        let expected = std::fs::read(&path).unwrap();
+        assert!(expected.starts_with(b"final"));
+        let mut parts = sink.parts.lock().unwrap().clone();
+        let num_parts = sink.num_parts.lock().unwrap().unwrap();
+        assert_eq!(num_parts, (expected.len() + 2879) / 2880);
+        assert_eq!(parts.len(), num_parts);
+        // The first part is uploaded last.
+        assert_eq!(parts.last().unwrap().0, 1);
+        parts.sort_by_key(|(part_number, _)| *part_number);
+        let uploaded: Vec<u8> = parts.into_iter().flat_map(|(_, bytes)| bytes).collect();
+        assert_eq!(uploaded, expected);
+
+        // Directories are archived.
+        let dir_path = tmp_dir.path().join("out.zarr");
+        let sink = RecordingSink::default();
+        runtime.block_on(async {
+            let mut writer = StreamingWriter::new(
+                |path: &Path| {
+                    std::fs::create_dir(path)?;
+                    Ok(ByteWriter(std::fs::File::create(path.join("data"))?))
+                },
+                &dir_path,
+                sink.clone(),
+                DEFAULT_PART_SIZE,
+            )
+            .await
+            .unwrap();
+            writer.finalise().await.unwrap();
+        });
+        let archive = std::fs::read(tmp_dir.path().join("out.zarr.tar")).unwrap();
+        assert_eq!(*sink.parts.lock().unwrap(), vec![(1, archive)]);
+        assert_eq!(*sink.num_parts.lock().unwrap(), Some(1));
+    }
+
+    #[test]
+    #[cfg(feature = "cfitsio")]
+    fn test_streaming_uvfits() {
+        use crate::{
+            io::{UvfitsOptions, UvfitsReader, UvfitsWriter},
+            LatLngHeight, RADec, Telescope, XyzGeodetic,
+        };
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let tmp_dir = tempdir().unwrap();
+        let vis_ctx = VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(1., Unit::Second),
+                100,
+            )
+            .channels(150e6, 40e3, 64)
+            .baselines(vec![(0, 0), (0, 1), (0, 2), (1, 1), (1, 2), (2, 2)])
+            .build()
+            .unwrap();
+        let stream = |path: &Path, part_size: u64| {
+            let sink = RecordingSink::default();
+            let result = runtime.block_on(async {
+                let writer_ctx = vis_ctx.clone();
+                let mut writer = StreamingWriter::new(
+                    move |path: &Path| {
+                        let names = vec!["Tile1".into(), "Tile2".into(), "Tile3".into()];
+                        let positions = vec![XyzGeodetic::default(); names.len()];
+                        Ok(UvfitsWriter::from_marlu_with_options(
+                            path,
+                            &writer_ctx,
+                            &Telescope::new("MWA", LatLngHeight::new_mwa(), names, positions),
+                            RADec::new_degrees(0.0, -27.0),
+                            Duration::from_total_nanoseconds(0),
+                            None,
+                            None,
+                            UvfitsOptions {
+                                checksum: true,
+                                ..Default::default()
+                            },
+                        )?)
+                    },
+                    path,
+                    sink.clone(),
+                    part_size,
+                )
+                .await?;
+                for chunk_ctx in vis_ctx.chunk_time(10) {
+                    writer
+                        .write_vis(
+                            Array3::from_elem(chunk_ctx.sel_dims(), Jones::identity()),
+                            Array3::from_elem(chunk_ctx.sel_dims(), 1.0),
+                            chunk_ctx,
+                        )
+                        .await?;
+                }
+                writer.finalise().await
+            });
+            (result, sink)
+        };
+
+        // The uploaded parts are the final file, including the checksums that
+        // are written to the header when it's finalised.
+        let path = tmp_dir.path().join("out.uvfits");
+        let (result, sink) = stream(&path, FITS_BLOCK_LEN * 8);
+        result.unwrap();
+        let expected = std::fs::read(&path).unwrap();
+        let mut parts = sink.parts.lock().unwrap().clone();
+        assert!(parts.len() > 2);
+        assert_eq!(parts.last().unwrap().0, 1);
+        parts.sort_by_key(|(part_number, _)| *part_number);
+        let uploaded: Vec<u8> = parts.into_iter().flat_map(|(_, bytes)| bytes).collect();
+        assert_eq!(uploaded, expected);
+        let mut reader = UvfitsReader::new(&path).unwrap();
+        assert!(reader.verify_checksums().unwrap());
+
+        // The primary header doesn't fit in a single block.
+        let path = tmp_dir.path().join("small_parts.uvfits");
+        let (result, sink) = stream(&path, FITS_BLOCK_LEN);
+        assert!(matches!(
+            result,
+            Err(IOError::PartSmallerThanHeader { part_size: 2880 })
+        ));
+        assert!(sink.parts.lock().unwrap().is_empty());
+    }
+}
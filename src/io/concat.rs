@@ -0,0 +1,326 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Concatenate visibility files along the time or frequency axis.
+
+use std::path::Path;
+
+use hifitime::Duration;
+use ndarray::prelude::*;
+
+use super::{VisRead, VisWriteFormat, VisWriter};
+use crate::errors::IOError;
+use crate::{History, Jones, ObsContext, VisContext};
+
+/// Open a visibility file for reading, with the format chosen by
+/// [`VisWriteFormat::from_path`].
+fn open_reader(path: &Path) -> Result<Box<dyn VisRead>, IOError> {
+    let unsupported = |reason: String| IOError::UnsupportedInput {
+        path: path.display().to_string(),
+        reason,
+    };
+    match VisWriteFormat::from_path(path) {
+        #[cfg(feature = "cfitsio")]
+        Some(VisWriteFormat::Uvfits) => Ok(Box::new(super::UvfitsReader::new(path)?)),
+
+        #[cfg(feature = "ms")]
+        Some(VisWriteFormat::MeasurementSet) => {
+            Ok(Box::new(super::MeasurementSetReader::new(path)?))
+        }
+
+        Some(format) if format.is_enabled() => {
+            Err(unsupported(format!("{format} files can't be read")))
+        }
+        Some(format) => Err(IOError::FormatNotEnabled {
+            format: format.to_string(),
+            feature: format.feature(),
+        }),
+        None => Err(IOError::UnknownFormat {
+            path: path.display().to_string(),
+        }),
+    }
+}
+
+/// Concatenate uvfits files or measurement sets written by Marlu (e.g. the
+/// outputs of runs over different chunks of an observation) into a single
+/// output, with the format of the output chosen by
+/// [`VisWriteFormat::from_path`].
+///
+/// The inputs may be given in any order, but must either all cover the same
+/// channels, in which case they are concatenated in time, or all cover the
+/// same timesteps, in which case they are concatenated in frequency. They must
+/// also have the same baselines, and abut without gaps or overlaps; see
+/// [`VisContext::merge`].
+///
+/// - `inputs` - the paths of the files to concatenate
+/// - `output` - the path of the concatenated file
+/// - `obs_ctx` - provides the phase centre, array position and antennas of the
+///   output, as for [`VisWriter::from_path`]
+/// - `dut1` - the DUT1 to use in the conversion to UT1
+/// - `history` - optional metadata about the software that wrote the file
+///
+/// Returns the context of the concatenated visibilities.
+///
+/// # Errors
+///
+/// Will return an [`IOError`] if there are no inputs, an input can't be read,
+/// the inputs can't be concatenated, or the output can't be written.
+pub fn concat<P: AsRef<Path>, Q: AsRef<Path>>(
+    inputs: &[P],
+    output: Q,
+    obs_ctx: &ObsContext,
+    dut1: Duration,
+    history: Option<&History>,
+) -> Result<VisContext, IOError> {
+    let mut readers = inputs
+        .iter()
+        .map(|input| open_reader(input.as_ref()))
+        .collect::<Result<Vec<_>, _>>()?;
+    // Sort the inputs by time, then by channel, so that each abuts the
+    // concatenation of those before it.
+    readers.sort_by(|a, b| {
+        let (a, b) = (a.vis_ctx(), b.vis_ctx());
+        (a.start_timestamp, a.start_freq_hz / a.freq_resolution_hz)
+            .partial_cmp(&(b.start_timestamp, b.start_freq_hz / b.freq_resolution_hz))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let (first, rest) = readers.split_first().ok_or(IOError::NoConcatInputs)?;
+    let vis_ctx = rest
+        .iter()
+        .try_fold(first.vis_ctx().clone(), |merged, reader| {
+            merged.merge(reader.vis_ctx())
+        })?;
+    let locations = readers
+        .iter()
+        .map(|reader| {
+            vis_ctx
+                .locate_chunk(reader.vis_ctx())
+                .ok_or(IOError::ChunkNotInSource)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut writer = VisWriter::from_path(output, &vis_ctx, obs_ctx, dut1, history)?;
+    let num_sel_timesteps = vis_ctx.num_sel_timesteps;
+    for (timestep_idx, time_chunk) in vis_ctx.chunk_time(num_sel_timesteps).iter().enumerate() {
+        let mut vis = Array3::<Jones<f32>>::zeros(time_chunk.sel_dims());
+        let mut weights = Array3::<f32>::zeros(time_chunk.sel_dims());
+        for (reader, location) in readers.iter_mut().zip(locations.iter()) {
+            if !location.timesteps.contains(&timestep_idx) {
+                continue;
+            }
+            let reader_ctx = reader.vis_ctx();
+            let chunk_ctx = VisContext {
+                num_sel_timesteps: 1,
+                start_timestamp: reader_ctx.start_timestamp
+                    + reader_ctx.int_time * ((timestep_idx - location.timesteps.start) as i64),
+                ..reader_ctx.clone()
+            };
+            let chans = location.chans.clone();
+            reader.read_vis(
+                vis.slice_mut(s![.., chans.clone(), ..]),
+                weights.slice_mut(s![.., chans, ..]),
+                &chunk_ctx,
+            )?;
+        }
+        writer.write_vis(vis.view(), weights.view(), time_chunk, false)?;
+    }
+    writer.finalise()?;
+
+    Ok(vis_ctx)
+}
+
+#[cfg(test)]
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
+mod tests {
+    use std::path::PathBuf;
+
+    use approx::assert_abs_diff_eq;
+    use hifitime::{Epoch, Unit};
+    use num_complex::Complex;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{LatLngHeight, RADec, ENH};
+
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "cfitsio")] {
+            use crate::VisContextError;
+        }
+    }
+
+    #[cfg(feature = "ms")]
+    use serial_test::serial;
+
+    fn get_contexts() -> (VisContext, ObsContext) {
+        let vis_ctx = VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1065880128.0),
+                Duration::from_f64(2.0, Unit::Second),
+                6,
+            )
+            .channels(170e6, 40e3, 6)
+            .baselines(vec![(0, 1), (0, 2), (1, 2)])
+            .build()
+            .unwrap();
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: vis_ctx.start_timestamp,
+            sched_duration: vis_ctx.int_time * vis_ctx.num_sel_timesteps as i64,
+            name: None,
+            field_name: None,
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::new_degrees(0.0, -27.0),
+            pointing_centre: None,
+            array_pos: LatLngHeight::new_mwa(),
+            telescope_name: "MWA".into(),
+            instrument: "MWA".into(),
+            ant_positions_enh: vec![ENH::default(); 3],
+            ant_names: vec!["Tile1".into(), "Tile2".into(), "Tile3".into()],
+            ant_flags: vec![],
+            beams: vec![],
+        };
+        (vis_ctx, obs_ctx)
+    }
+
+    fn get_arrays(vis_ctx: &VisContext) -> (Array3<Jones<f32>>, Array3<f32>) {
+        let jones_array = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| {
+            Jones::from([
+                Complex::new(1.0, 0.0),
+                Complex::new(2.0, 0.0),
+                Complex::new(3.0, 0.0),
+                Complex::new(4.0, 0.0),
+            ]) * (t * 100 + c * 10 + b) as f32
+        });
+        let mut weight_array = Array3::from_elem(vis_ctx.sel_dims(), 2.0);
+        weight_array[(1, 4, 0)] = -2.0;
+        (jones_array, weight_array)
+    }
+
+    /// Write each of the `chunks` of the visibilities of `get_arrays` to its
+    /// own file with the extension `ext`, returning the paths of the files.
+    fn write_chunks(dir: &Path, name: &str, ext: &str, chunks: &[VisContext]) -> Vec<PathBuf> {
+        let (vis_ctx, obs_ctx) = get_contexts();
+        let (jones_array, weight_array) = get_arrays(&vis_ctx);
+        chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let location = vis_ctx.locate_chunk(chunk).unwrap();
+                let path = dir.join(format!("{name}{i}.{ext}"));
+                let mut writer = VisWriter::from_path(
+                    &path,
+                    chunk,
+                    &obs_ctx,
+                    Duration::from_total_nanoseconds(0),
+                    None,
+                )
+                .unwrap();
+                let slice = s![location.timesteps, location.chans, ..];
+                writer
+                    .write_vis(
+                        jones_array.slice(slice),
+                        weight_array.slice(slice),
+                        chunk,
+                        false,
+                    )
+                    .unwrap();
+                writer.finalise().unwrap();
+                path
+            })
+            .collect()
+    }
+
+    /// Concatenate `paths` to `output`, and check that the result has all of
+    /// the visibilities of `get_arrays`.
+    fn check_concat(paths: &[PathBuf], output: &Path) {
+        let (vis_ctx, obs_ctx) = get_contexts();
+        let (jones_array, weight_array) = get_arrays(&vis_ctx);
+        let concat_ctx = concat(
+            paths,
+            output,
+            &obs_ctx,
+            Duration::from_total_nanoseconds(0),
+            None,
+        )
+        .unwrap();
+        assert_eq!(concat_ctx.sel_dims(), vis_ctx.sel_dims());
+
+        let mut reader = open_reader(output).unwrap();
+        let read_ctx = reader.vis_ctx().clone();
+        assert_eq!(read_ctx.sel_dims(), vis_ctx.sel_dims());
+        assert_abs_diff_eq!(read_ctx.start_freq_hz, vis_ctx.start_freq_hz);
+        let mut vis = Array3::zeros(read_ctx.sel_dims());
+        let mut weights = Array3::zeros(read_ctx.sel_dims());
+        reader
+            .read_vis(vis.view_mut(), weights.view_mut(), &read_ctx)
+            .unwrap();
+        assert_abs_diff_eq!(vis, jones_array);
+        assert_abs_diff_eq!(weights, weight_array);
+    }
+
+    #[test]
+    #[cfg(feature = "cfitsio")]
+    fn test_concat_uvfits_in_time() {
+        let (vis_ctx, _) = get_contexts();
+        let dir = tempdir().unwrap();
+        // The inputs can be in any order.
+        let mut paths = write_chunks(dir.path(), "time", "uvfits", &vis_ctx.chunk_time(3));
+        paths.reverse();
+        check_concat(&paths, &dir.path().join("concat.uvfits"));
+    }
+
+    #[test]
+    #[cfg(feature = "cfitsio")]
+    fn test_concat_uvfits_in_freq() {
+        let (vis_ctx, _) = get_contexts();
+        let dir = tempdir().unwrap();
+        let paths = write_chunks(dir.path(), "freq", "uvfits", &vis_ctx.chunk_freq(2));
+        check_concat(&paths, &dir.path().join("concat.uvfits"));
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(feature = "ms")]
+    fn test_concat_ms() {
+        let (vis_ctx, _) = get_contexts();
+        let dir = tempdir().unwrap();
+        let mut paths = write_chunks(dir.path(), "time", "ms", &vis_ctx.chunk_time(3));
+        paths.reverse();
+        check_concat(&paths, &dir.path().join("concat_time.ms"));
+
+        let paths = write_chunks(dir.path(), "freq", "ms", &vis_ctx.chunk_freq(2));
+        check_concat(&paths, &dir.path().join("concat_freq.ms"));
+    }
+
+    #[test]
+    #[cfg(feature = "cfitsio")]
+    fn test_concat_errors() {
+        let (vis_ctx, obs_ctx) = get_contexts();
+        let dir = tempdir().unwrap();
+        let paths = write_chunks(dir.path(), "time", "uvfits", &vis_ctx.chunk_time(3));
+
+        // The chunks must abut.
+        let result = concat(
+            &[&paths[0], &paths[2]],
+            dir.path().join("gap.uvfits"),
+            &obs_ctx,
+            Duration::from_total_nanoseconds(0),
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(IOError::VisContextError(VisContextError::NotAdjacent))
+        ));
+
+        let result = concat(
+            &[] as &[PathBuf],
+            dir.path().join("empty.uvfits"),
+            &obs_ctx,
+            Duration::from_total_nanoseconds(0),
+            None,
+        );
+        assert!(matches!(result, Err(IOError::NoConcatInputs)));
+    }
+}
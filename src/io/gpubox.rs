@@ -0,0 +1,214 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Reading MWA gpubox files through mwalib as a [`VisRead`] source.
+
+use ndarray::prelude::*;
+
+use super::{locate_read_chunk, IOError, VisRead};
+use crate::{mwalib::CorrelatorContext, selection::FlagPolicy, Jones, VisContext, VisSelection};
+
+/// A [`VisRead`] source of the visibilities of a [`VisSelection`] of MWA
+/// gpubox files. Missing HDUs, and anything flagged by the [`FlagPolicy`], are
+/// flagged with a weight of -1; all other visibilities have a weight of 1.
+pub struct GpuboxReader {
+    corr_ctx: CorrelatorContext,
+    vis_sel: VisSelection,
+    vis_ctx: VisContext,
+    flag_policy: FlagPolicy,
+}
+
+impl GpuboxReader {
+    /// Create a reader of the visibilities of `vis_sel`.
+    pub fn new(corr_ctx: CorrelatorContext, vis_sel: VisSelection) -> Self {
+        let vis_ctx = VisContext::from_mwalib(
+            &corr_ctx,
+            &vis_sel.timestep_range,
+            &vis_sel.coarse_chan_range,
+            &vis_sel.baseline_idxs,
+            1,
+            1,
+        );
+        Self {
+            corr_ctx,
+            vis_sel,
+            vis_ctx,
+            flag_policy: FlagPolicy::default(),
+        }
+    }
+
+    /// Set the flags to apply while reading, in addition to those of missing
+    /// HDUs.
+    pub fn set_flag_policy(&mut self, flag_policy: FlagPolicy) {
+        self.flag_policy = flag_policy;
+    }
+
+    /// The mwalib context of the gpubox files.
+    pub fn corr_ctx(&self) -> &CorrelatorContext {
+        &self.corr_ctx
+    }
+}
+
+impl VisRead for GpuboxReader {
+    fn vis_ctx(&self) -> &VisContext {
+        &self.vis_ctx
+    }
+
+    fn read_vis(
+        &mut self,
+        mut vis: ArrayViewMut3<Jones<f32>>,
+        mut weights: ArrayViewMut3<f32>,
+        chunk_ctx: &VisContext,
+    ) -> Result<(), IOError> {
+        let location = locate_read_chunk(
+            &self.vis_ctx,
+            chunk_ctx,
+            vis.dim(),
+            weights.dim(),
+            "GpuboxReader::read_vis",
+        )?;
+
+        // mwalib reads whole coarse channels, so read all of the coarse
+        // channels that the chunk overlaps.
+        let fine_chans_per_coarse = self
+            .corr_ctx
+            .metafits_context
+            .num_corr_fine_chans_per_coarse;
+        let first_coarse_chan = location.chans.start / fine_chans_per_coarse;
+        let last_coarse_chan =
+            (location.chans.end + fine_chans_per_coarse - 1) / fine_chans_per_coarse;
+        let timestep_start = self.vis_sel.timestep_range.start;
+        let coarse_chan_start = self.vis_sel.coarse_chan_range.start;
+        let chunk_sel = VisSelection {
+            timestep_range: timestep_start + location.timesteps.start
+                ..timestep_start + location.timesteps.end,
+            coarse_chan_range: coarse_chan_start + first_coarse_chan
+                ..coarse_chan_start + last_coarse_chan,
+            baseline_idxs: location
+                .baseline_idxs
+                .iter()
+                .map(|&idx| self.vis_sel.baseline_idxs[idx])
+                .collect(),
+        };
+        let mut jones_array = chunk_sel.allocate_jones(fine_chans_per_coarse)?;
+        let mut flag_array = chunk_sel.allocate_flags(fine_chans_per_coarse)?;
+        chunk_sel.read_mwalib_with_flag_policy(
+            &self.corr_ctx,
+            jones_array.view_mut(),
+            flag_array.view_mut(),
+            &self.flag_policy,
+            false,
+        )?;
+
+        let chan_offset = first_coarse_chan * fine_chans_per_coarse;
+        let chans = location.chans.start - chan_offset..location.chans.end - chan_offset;
+        vis.assign(&jones_array.slice(s![.., chans.clone(), ..]));
+        weights.zip_mut_with(&flag_array.slice(s![.., chans, ..]), |weight, &flag| {
+            *weight = if flag { -1.0 } else { 1.0 };
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+    use crate::weights::is_flagged;
+
+    fn get_mwax_context() -> CorrelatorContext {
+        CorrelatorContext::new(
+            "tests/data/1297526432_mwax/1297526432.metafits",
+            &[
+                "tests/data/1297526432_mwax/1297526432_20210216160014_ch117_000.fits",
+                "tests/data/1297526432_mwax/1297526432_20210216160014_ch117_001.fits",
+                "tests/data/1297526432_mwax/1297526432_20210216160014_ch118_000.fits",
+                "tests/data/1297526432_mwax/1297526432_20210216160014_ch118_001.fits",
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_gpubox_reader_chunks_match_read_mwalib() {
+        let corr_ctx = get_mwax_context();
+        let vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+        let fine_chans_per_coarse = corr_ctx.metafits_context.num_corr_fine_chans_per_coarse;
+        let mut flag_array = vis_sel.allocate_flags(fine_chans_per_coarse).unwrap();
+        let mut jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+        vis_sel
+            .read_mwalib(
+                &corr_ctx,
+                jones_array.view_mut(),
+                flag_array.view_mut(),
+                false,
+            )
+            .unwrap();
+
+        let mut reader = GpuboxReader::new(corr_ctx, vis_sel);
+        let vis_ctx = reader.vis_ctx().clone();
+        assert_eq!(vis_ctx.sel_dims(), jones_array.dim());
+
+        // Chunks that don't line up with coarse channels, with the baselines
+        // reversed.
+        let chunk_chans = fine_chans_per_coarse + 1;
+        let mut chan_offset = 0;
+        for freq_chunk in vis_ctx.chunk_freq(vis_ctx.num_sel_chans / chunk_chans) {
+            for (timestep_idx, time_chunk) in vis_ctx
+                .chunk_time(vis_ctx.num_sel_timesteps)
+                .iter()
+                .enumerate()
+            {
+                let chunk_ctx = VisContext {
+                    num_sel_timesteps: time_chunk.num_sel_timesteps,
+                    start_timestamp: time_chunk.start_timestamp,
+                    sel_baselines: vis_ctx.sel_baselines.iter().rev().copied().collect(),
+                    ..freq_chunk.clone()
+                };
+                let mut vis = Array3::zeros(chunk_ctx.sel_dims());
+                let mut weights = Array3::zeros(chunk_ctx.sel_dims());
+                reader
+                    .read_vis(vis.view_mut(), weights.view_mut(), &chunk_ctx)
+                    .unwrap();
+
+                let mut expected = jones_array.slice(s![
+                    timestep_idx..timestep_idx + 1,
+                    chan_offset..chan_offset + chunk_ctx.num_sel_chans,
+                    ..
+                ]);
+                expected.invert_axis(Axis(2));
+                assert_abs_diff_eq!(vis, expected);
+                let mut expected_flags = flag_array.slice(s![
+                    timestep_idx..timestep_idx + 1,
+                    chan_offset..chan_offset + chunk_ctx.num_sel_chans,
+                    ..
+                ]);
+                expected_flags.invert_axis(Axis(2));
+                for (&weight, &flag) in weights.iter().zip(expected_flags.iter()) {
+                    assert_eq!(is_flagged(weight), flag);
+                }
+            }
+            chan_offset += freq_chunk.num_sel_chans;
+        }
+        assert_eq!(chan_offset, vis_ctx.num_sel_chans);
+    }
+
+    #[test]
+    fn test_gpubox_reader_rejects_foreign_chunks() {
+        let corr_ctx = get_mwax_context();
+        let vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+        let mut reader = GpuboxReader::new(corr_ctx, vis_sel);
+        let chunk_ctx = VisContext {
+            start_freq_hz: reader.vis_ctx().start_freq_hz - reader.vis_ctx().freq_resolution_hz,
+            ..reader.vis_ctx().clone()
+        };
+        let mut vis = Array3::zeros(chunk_ctx.sel_dims());
+        let mut weights = Array3::zeros(chunk_ctx.sel_dims());
+        assert!(matches!(
+            reader.read_vis(vis.view_mut(), weights.view_mut(), &chunk_ctx),
+            Err(IOError::ChunkNotInSource)
+        ));
+    }
+}
@@ -0,0 +1,446 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Per-observation quality metrics, accumulated while visibilities are read or
+//! written.
+//!
+//! [`QualityMetrics`] keeps the flag occupancy and RMS of the cross-correlations
+//! of each channel, and the power of the auto-correlations of each tile, and
+//! saves them as a small JSON file keyed by obsid. [`MetricsWriter`] wraps a
+//! [`VisWrite`] implementor to write this file alongside its output.
+
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use ndarray::{prelude::*, Zip};
+
+use super::VisWrite;
+use crate::{errors::IOError, weights::is_flagged, Jones, VisContext};
+
+/// Quality metrics of an observation. Call [`QualityMetrics::update`] with each
+/// chunk of visibilities as it is read or written.
+#[derive(Debug, Clone)]
+pub struct QualityMetrics {
+    obsid: u32,
+
+    /// The context of all of the visibilities of the observation.
+    vis_ctx: VisContext,
+
+    /// The antennas with auto-correlations, in ascending order.
+    ants: Vec<usize>,
+
+    /// The number of visibilities of each channel.
+    num_vis: Array1<u64>,
+
+    /// The number of flagged visibilities of each channel.
+    num_flagged: Array1<u64>,
+
+    /// The sum of the squared amplitudes of the unflagged cross-correlations,
+    /// with dimensions `[channel][pol]`.
+    cross_power_sums: Array2<f64>,
+
+    /// The number of unflagged cross-correlations of each channel.
+    num_unflagged_cross: Array1<u64>,
+
+    /// The sum of the XX and YY powers of the unflagged auto-correlations,
+    /// with dimensions `[antenna][pol]`.
+    auto_power_sums: Array2<f64>,
+
+    /// The number of unflagged auto-correlations of each antenna.
+    num_unflagged_autos: Array1<u64>,
+}
+
+impl QualityMetrics {
+    /// Create empty metrics for the observation `obsid`, with visibilities
+    /// described by `vis_ctx`.
+    pub fn new(obsid: u32, vis_ctx: &VisContext) -> Self {
+        let mut ants: Vec<usize> = vis_ctx
+            .sel_baselines
+            .iter()
+            .filter(|(ant1, ant2)| ant1 == ant2)
+            .map(|&(ant, _)| ant)
+            .collect();
+        ants.sort_unstable();
+        ants.dedup();
+        let num_chans = vis_ctx.num_sel_chans;
+        Self {
+            obsid,
+            vis_ctx: vis_ctx.clone(),
+            num_vis: Array1::zeros(num_chans),
+            num_flagged: Array1::zeros(num_chans),
+            cross_power_sums: Array2::zeros((num_chans, 4)),
+            num_unflagged_cross: Array1::zeros(num_chans),
+            auto_power_sums: Array2::zeros((ants.len(), 2)),
+            num_unflagged_autos: Array1::zeros(ants.len()),
+            ants,
+        }
+    }
+
+    /// Accumulate a chunk of visibilities.
+    ///
+    /// `vis` - visibilities with dimensions `[timestep][channel][baseline]`
+    ///
+    /// `weights` - weights with the same dimensions. Negative weights are
+    ///     flagged.
+    ///
+    /// `chunk_ctx` - the context of the chunk, which must be part of the
+    ///     context of the observation (see [`VisContext::locate_chunk`]).
+    ///
+    /// # Errors
+    ///
+    /// Will return [`IOError::BadArrayShape`] if the shapes of the arrays don't
+    /// match `chunk_ctx`, or [`IOError::ChunkNotInOutput`] if the chunk isn't
+    /// part of the observation.
+    pub fn update(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        chunk_ctx: &VisContext,
+    ) -> Result<(), IOError> {
+        chunk_ctx.check_sel_dims(vis.dim(), weights.dim(), "QualityMetrics::update")?;
+        let location = self
+            .vis_ctx
+            .locate_chunk(chunk_ctx)
+            .ok_or(IOError::ChunkNotInOutput)?;
+
+        for (&bl_idx, vis_bl, weights_bl) in itertools::izip!(
+            &location.baseline_idxs,
+            vis.axis_iter(Axis(2)),
+            weights.axis_iter(Axis(2))
+        ) {
+            let (ant1, ant2) = self.vis_ctx.sel_baselines[bl_idx];
+            let ant_row = if ant1 == ant2 {
+                self.ants.binary_search(&ant1).ok()
+            } else {
+                None
+            };
+            for (((_, chunk_chan), jones), &weight) in vis_bl.indexed_iter().zip(weights_bl.iter())
+            {
+                let chan = location.chans.start + chunk_chan;
+                self.num_vis[chan] += 1;
+                if is_flagged(weight) {
+                    self.num_flagged[chan] += 1;
+                    continue;
+                }
+                if let Some(row) = ant_row {
+                    self.auto_power_sums[(row, 0)] += jones[0].re as f64;
+                    self.auto_power_sums[(row, 1)] += jones[3].re as f64;
+                    self.num_unflagged_autos[row] += 1;
+                } else {
+                    for (sum, pol) in self
+                        .cross_power_sums
+                        .row_mut(chan)
+                        .iter_mut()
+                        .zip(jones.iter())
+                    {
+                        *sum += pol.norm_sqr() as f64;
+                    }
+                    self.num_unflagged_cross[chan] += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn obsid(&self) -> u32 {
+        self.obsid
+    }
+
+    /// The fraction of all of the visibilities accumulated so far that are
+    /// flagged, or NaN if there aren't any.
+    pub fn flag_occupancy(&self) -> f64 {
+        self.num_flagged.sum() as f64 / self.num_vis.sum() as f64
+    }
+
+    /// The fraction of the visibilities of each channel that are flagged.
+    pub fn chan_flag_occupancy(&self) -> Array1<f64> {
+        Zip::from(&self.num_flagged)
+            .and(&self.num_vis)
+            .map_collect(|&num_flagged, &num_vis| num_flagged as f64 / num_vis as f64)
+    }
+
+    /// The RMS amplitude of the unflagged cross-correlations of each channel
+    /// and pol (XX, XY, YX, YY), with dimensions `[channel][pol]`.
+    pub fn chan_rms(&self) -> Array2<f64> {
+        let mut rms = self.cross_power_sums.clone();
+        for (mut rms, &num) in rms.outer_iter_mut().zip(&self.num_unflagged_cross) {
+            rms.mapv_inplace(|sum| (sum / num as f64).sqrt());
+        }
+        rms
+    }
+
+    /// The antennas of each row of [`QualityMetrics::tile_power`].
+    pub fn ants(&self) -> &[usize] {
+        &self.ants
+    }
+
+    /// The mean XX and YY power of the unflagged auto-correlations of each
+    /// tile, with dimensions `[antenna][pol]`.
+    pub fn tile_power(&self) -> Array2<f64> {
+        let mut power = self.auto_power_sums.clone();
+        for (mut power, &num) in power.outer_iter_mut().zip(&self.num_unflagged_autos) {
+            power.mapv_inplace(|sum| sum / num as f64);
+        }
+        power
+    }
+
+    /// The conventional path of the metrics of this observation alongside the
+    /// visibility file `vis_path`, i.e. `<obsid>_metrics.json` in the same
+    /// directory.
+    pub fn path_alongside<T: AsRef<Path>>(&self, vis_path: T) -> PathBuf {
+        vis_path
+            .as_ref()
+            .with_file_name(format!("{}_metrics.json", self.obsid))
+    }
+
+    /// Render the metrics as JSON. Undefined values (e.g. the RMS of a fully
+    /// flagged channel) are `null`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        // Writing to a String can't fail.
+        writeln!(json, "{{").unwrap();
+        writeln!(json, "  \"obsid\": {},", self.obsid).unwrap();
+        writeln!(
+            json,
+            "  \"chan_freqs_hz\": {},",
+            json_array(&self.vis_ctx.frequencies_hz())
+        )
+        .unwrap();
+        writeln!(
+            json,
+            "  \"flag_occupancy\": {},",
+            json_f64(self.flag_occupancy())
+        )
+        .unwrap();
+        writeln!(
+            json,
+            "  \"chan_flag_occupancy\": {},",
+            json_array(&self.chan_flag_occupancy().to_vec())
+        )
+        .unwrap();
+        writeln!(json, "  \"rms_pols\": [\"XX\", \"XY\", \"YX\", \"YY\"],").unwrap();
+        writeln!(
+            json,
+            "  \"chan_rms\": {},",
+            json_rows(self.chan_rms().view())
+        )
+        .unwrap();
+        let ants: Vec<String> = self.ants.iter().map(ToString::to_string).collect();
+        writeln!(json, "  \"ants\": [{}],", ants.join(", ")).unwrap();
+        writeln!(json, "  \"power_pols\": [\"XX\", \"YY\"],").unwrap();
+        writeln!(
+            json,
+            "  \"tile_power\": {}",
+            json_rows(self.tile_power().view())
+        )
+        .unwrap();
+        writeln!(json, "}}").unwrap();
+        json
+    }
+
+    /// Write the metrics as JSON to `path`.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`IOError::StdIo`] if the file can't be written.
+    pub fn write_json<T: AsRef<Path>>(&self, path: T) -> Result<(), IOError> {
+        File::create(path)?.write_all(self.to_json().as_bytes())?;
+        Ok(())
+    }
+}
+
+fn json_f64(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_array(values: &[f64]) -> String {
+    let values: Vec<String> = values.iter().map(|&value| json_f64(value)).collect();
+    format!("[{}]", values.join(", "))
+}
+
+fn json_rows(values: ArrayView2<f64>) -> String {
+    let rows: Vec<String> = values
+        .outer_iter()
+        .map(|row| json_array(&row.to_vec()))
+        .collect();
+    format!("[{}]", rows.join(", "))
+}
+
+/// A [`VisWrite`] implementor that accumulates [`QualityMetrics`] of the
+/// visibilities given to another writer, and writes them to a JSON file when
+/// it is finalised.
+pub struct MetricsWriter<W: VisWrite> {
+    writer: W,
+    metrics: QualityMetrics,
+    path: PathBuf,
+}
+
+impl<W: VisWrite> MetricsWriter<W> {
+    /// Wrap `writer`, whose visibilities are described by `vis_ctx`, writing
+    /// the metrics of `obsid` to `path` (e.g. from
+    /// [`QualityMetrics::path_alongside`]).
+    pub fn new<T: AsRef<Path>>(writer: W, obsid: u32, vis_ctx: &VisContext, path: T) -> Self {
+        Self {
+            writer,
+            metrics: QualityMetrics::new(obsid, vis_ctx),
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The metrics accumulated so far.
+    pub fn metrics(&self) -> &QualityMetrics {
+        &self.metrics
+    }
+
+    /// Unwrap the inner writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: VisWrite> VisWrite for MetricsWriter<W> {
+    fn write_vis(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        vis_ctx: &VisContext,
+        draw_progress: bool,
+    ) -> Result<(), IOError> {
+        self.metrics.update(vis, weights, vis_ctx)?;
+        self.writer.write_vis(vis, weights, vis_ctx, draw_progress)
+    }
+
+    fn finalise(&mut self) -> Result<(), IOError> {
+        self.writer.finalise()?;
+        self.metrics.write_json(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use hifitime::{Duration, Epoch, Unit};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::Complex;
+
+    /// A writer that discards the visibilities.
+    struct NullWriter {
+        num_chunks: usize,
+    }
+
+    impl VisWrite for NullWriter {
+        fn write_vis(
+            &mut self,
+            _: ArrayView3<Jones<f32>>,
+            _: ArrayView3<f32>,
+            _: &VisContext,
+            _: bool,
+        ) -> Result<(), IOError> {
+            self.num_chunks += 1;
+            Ok(())
+        }
+
+        fn finalise(&mut self) -> Result<(), IOError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_metrics_writer() {
+        let vis_ctx = VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(2., Unit::Second),
+                4,
+            )
+            .channels(150e6, 40e3, 3)
+            .baselines(vec![(0, 0), (0, 1), (1, 1)])
+            .build()
+            .unwrap();
+        // Autos of tile 0 have a power of 10 (XX) and 20 (YY), and tile 1 twice
+        // that. Cross-correlations have an amplitude of the channel index + 1.
+        let vis = Array3::from_shape_fn(vis_ctx.sel_dims(), |(_, chan, bl)| match bl {
+            1 => Jones::identity() * (chan + 1) as f32,
+            _ => {
+                let scale = bl as f32 / 2.0 + 1.0;
+                Jones::from([
+                    Complex::new(10.0 * scale, 0.0),
+                    Complex::default(),
+                    Complex::default(),
+                    Complex::new(20.0 * scale, 0.0),
+                ])
+            }
+        });
+        let mut weights = Array3::from_elem(vis_ctx.sel_dims(), 1.0);
+        // Flag the last channel, and a timestep of the cross-correlation with
+        // a wild value.
+        weights.slice_mut(s![.., 2, ..]).fill(-1.0);
+        weights[(3, 0, 1)] = -0.0;
+        let mut vis = vis;
+        vis[(3, 0, 1)] = Jones::identity() * 1e6;
+
+        let dir = tempdir().unwrap();
+        let mut writer = MetricsWriter::new(
+            NullWriter { num_chunks: 0 },
+            1090008640,
+            &vis_ctx,
+            dir.path().join("1090008640_metrics.json"),
+        );
+        for chunk_ctx in vis_ctx.chunk_time(2) {
+            let location = vis_ctx.locate_chunk(&chunk_ctx).unwrap();
+            writer
+                .write_vis(
+                    vis.slice(s![location.timesteps.clone(), .., ..]),
+                    weights.slice(s![location.timesteps, .., ..]),
+                    &chunk_ctx,
+                    false,
+                )
+                .unwrap();
+        }
+        writer.finalise().unwrap();
+
+        let metrics = writer.metrics();
+        assert_eq!(metrics.obsid(), 1090008640);
+        assert_abs_diff_eq!(metrics.flag_occupancy(), 13.0 / 36.0);
+        assert_abs_diff_eq!(metrics.chan_flag_occupancy(), array![1.0 / 12.0, 0.0, 1.0]);
+        let rms = metrics.chan_rms();
+        assert_abs_diff_eq!(rms[(0, 0)], 1.0);
+        assert_abs_diff_eq!(rms[(0, 1)], 0.0);
+        assert_abs_diff_eq!(rms[(1, 3)], 2.0);
+        assert!(rms[(2, 0)].is_nan());
+        assert_eq!(metrics.ants(), &[0, 1]);
+        assert_abs_diff_eq!(metrics.tile_power(), array![[10.0, 20.0], [20.0, 40.0]]);
+
+        let path = metrics.path_alongside(dir.path().join("1090008640.uvfits"));
+        assert_eq!(path, dir.path().join("1090008640_metrics.json"));
+        let json = std::fs::read_to_string(path).unwrap();
+        assert!(json.contains("\"obsid\": 1090008640,"));
+        assert!(
+            json.contains("\"chan_rms\": [[1, 0, 0, 1], [2, 0, 0, 2], [null, null, null, null]],")
+        );
+        assert!(json.contains("\"tile_power\": [[10, 20], [20, 40]]"));
+        assert_eq!(writer.into_inner().num_chunks, 2);
+
+        // A chunk that isn't part of the observation.
+        let mut metrics = QualityMetrics::new(1090008640, &vis_ctx);
+        let other_ctx = VisContext {
+            start_freq_hz: 100e6,
+            ..vis_ctx.clone()
+        };
+        assert!(matches!(
+            metrics.update(vis.view(), weights.view(), &other_ctx),
+            Err(IOError::ChunkNotInOutput)
+        ));
+    }
+}
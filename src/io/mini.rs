@@ -0,0 +1,1061 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Generate miniature, but structurally valid, MWA datasets on the fly.
+//!
+//! [`MiniDataset::builder`] writes a metafits file and MWAX gpubox files (and,
+//! with the `cfitsio` feature, a uvfits file of the same visibilities) into a
+//! directory, so that tests and examples don't need the large files in
+//! `tests/data`. The FITS files are written without cfitsio, so this is
+//! available in every build of Marlu, and the files can be read with mwalib
+//! like those of any other observation.
+//!
+//! The visibilities are a known function of their indices (see
+//! [`MiniDataset::vis`]), so tests can check that they were read correctly.
+//!
+//! ```rust
+//! use marlu::io::MiniDataset;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let dataset = MiniDataset::builder()
+//!     .num_ants(3)
+//!     .num_timesteps(4)
+//!     .build(dir.path())
+//!     .unwrap();
+//! assert!(dataset.metafits.exists());
+//! assert_eq!(dataset.gpubox.len(), 2);
+//! ```
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use hifitime::{Duration, Epoch, Unit};
+
+use crate::{
+    constants::{MWA_COARSE_CHAN_WIDTH_HZ, MWA_HEIGHT_M, MWA_LAT_DEG, MWA_LONG_RAD},
+    errors::IOError,
+    precession::get_lmst,
+    synth::make_ant_positions,
+    time::mjd_utc_days,
+    Complex, Jones, LatLngHeight, ObsContext, RADec, VisContext,
+};
+
+#[cfg(feature = "cfitsio")]
+use ndarray::prelude::*;
+
+/// The length of a FITS block \[bytes\]. Every HDU is padded to a multiple of
+/// this.
+const FITS_BLOCK_LEN: usize = 2880;
+
+/// The length of a FITS header card \[bytes\].
+const FITS_CARD_LEN: usize = 80;
+
+/// The longest string that fits in a single header card, without quotes.
+const FITS_MAX_STR_LEN: usize = 68;
+
+/// The name, `TFORM` and unit of each column of the `TILEDATA` table of a
+/// metafits file.
+const TILEDATA_COLUMNS: [(&str, &str, &str); 17] = [
+    ("Input", "I", ""),
+    ("Antenna", "I", ""),
+    ("Tile", "I", ""),
+    ("TileName", "8A", ""),
+    ("Pol", "A", ""),
+    ("Rx", "I", ""),
+    ("Slot", "I", ""),
+    ("Flag", "I", ""),
+    ("Length", "14A", ""),
+    ("North", "E", "m"),
+    ("East", "E", "m"),
+    ("Height", "E", "m"),
+    ("Gains", "24I", ""),
+    ("BFTemps", "E", "degC"),
+    ("Delays", "16I", ""),
+    ("VCSOrder", "I", ""),
+    ("Flavors", "10A", ""),
+];
+
+/// The length of a row of the `TILEDATA` table \[bytes\].
+const TILEDATA_ROW_LEN: usize = 143;
+
+/// A FITS header value.
+#[derive(Clone, Copy)]
+enum Value<'a> {
+    Logical(bool),
+    Int(i64),
+    Float(f64),
+    Str(&'a str),
+}
+
+/// A FITS header, as a sequence of 80 character cards.
+#[derive(Default)]
+struct Header(Vec<String>);
+
+impl Header {
+    fn key(&mut self, keyword: &str, value: Value, comment: &str) -> &mut Self {
+        let value = match value {
+            Value::Logical(b) => format!("{:>20}", if b { "T" } else { "F" }),
+            Value::Int(i) => format!("{:>20}", i),
+            // Always with a decimal point and an exponent, and precise to 15
+            // significant figures.
+            Value::Float(f) => format!("{:>20.14E}", f),
+            Value::Str(s) => {
+                // Long strings are split over CONTINUE cards, with a trailing &
+                // on every part but the last.
+                let s = s.replace('\'', "''");
+                let mut parts: Vec<&str> = vec![];
+                let mut rest = s.as_str();
+                while rest.len() > FITS_MAX_STR_LEN {
+                    let (part, tail) = rest.split_at(FITS_MAX_STR_LEN - 1);
+                    parts.push(part);
+                    rest = tail;
+                }
+                for (i, part) in parts.iter().enumerate() {
+                    let card = if i == 0 {
+                        format!("{:<8}= '{}&'", keyword, part)
+                    } else {
+                        format!("CONTINUE  '{}&'", part)
+                    };
+                    self.0.push(card);
+                }
+                let quoted = format!("'{:<8}'", rest);
+                if parts.is_empty() {
+                    format!("{:<20}", quoted)
+                } else {
+                    self.push_card("CONTINUE  ".to_string() + &quoted, comment);
+                    return self;
+                }
+            }
+        };
+        self.push_card(format!("{:<8}= {}", keyword, value), comment);
+        self
+    }
+
+    fn push_card(&mut self, mut card: String, comment: &str) {
+        if !comment.is_empty() {
+            card.push_str(" / ");
+            card.push_str(comment);
+        }
+        card.truncate(FITS_CARD_LEN);
+        self.0.push(card);
+    }
+
+    /// Write the cards, then `END`, padded with spaces to a whole number of
+    /// blocks.
+    fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut len = 0;
+        for card in self.0.iter().map(String::as_str).chain(["END"]) {
+            write!(writer, "{:<80}", card)?;
+            len += FITS_CARD_LEN;
+        }
+        writer.write_all(&vec![b' '; padding(len)])
+    }
+}
+
+/// The number of bytes needed to pad `len` bytes to a whole number of FITS
+/// blocks.
+fn padding(len: usize) -> usize {
+    (FITS_BLOCK_LEN - len % FITS_BLOCK_LEN) % FITS_BLOCK_LEN
+}
+
+/// Write a FITS data unit, padded with zeros to a whole number of blocks.
+fn write_data<W: Write>(writer: &mut W, data: &[u8]) -> std::io::Result<()> {
+    writer.write_all(data)?;
+    writer.write_all(&vec![0; padding(data.len())])
+}
+
+/// A date in the format of metafits files, e.g. `2021-02-16T16:00:14`.
+fn date_string(epoch: Epoch) -> String {
+    let (year, month, day, hour, minute, second, _) = epoch.as_gregorian_utc();
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year, month, day, hour, minute, second
+    )
+}
+
+/// A miniature MWAX observation written by [`MiniDatasetBuilder::build`].
+#[derive(Debug, Clone)]
+pub struct MiniDataset {
+    /// The path to the metafits file.
+    pub metafits: PathBuf,
+    /// The paths to the gpubox files, one per coarse channel.
+    pub gpubox: Vec<PathBuf>,
+    /// The path to the uvfits file, if one was written.
+    pub uvfits: Option<PathBuf>,
+    /// The observation ID, i.e. the GPS time of the start of the observation.
+    pub obsid: u32,
+    /// The number of antennas (tiles).
+    pub num_ants: usize,
+    /// The receiver channel numbers of the coarse channels.
+    pub coarse_chans: Vec<usize>,
+    /// The number of fine channels in each coarse channel.
+    pub fine_chans_per_coarse: usize,
+    /// The number of timesteps.
+    pub num_timesteps: usize,
+    /// The integration time of each timestep.
+    pub int_time: Duration,
+}
+
+impl MiniDataset {
+    /// A builder of a dataset with 4 antennas, 2 coarse channels of 2 fine
+    /// channels and 2 0.5s timesteps.
+    pub fn builder() -> MiniDatasetBuilder {
+        MiniDatasetBuilder::new()
+    }
+
+    /// The number of baselines, including auto-correlations.
+    pub fn num_baselines(&self) -> usize {
+        self.num_ants * (self.num_ants + 1) / 2
+    }
+
+    /// The total number of fine channels.
+    pub fn num_chans(&self) -> usize {
+        self.coarse_chans.len() * self.fine_chans_per_coarse
+    }
+
+    /// The width of each fine channel \[Hz\].
+    pub fn fine_chan_width_hz(&self) -> f64 {
+        MWA_COARSE_CHAN_WIDTH_HZ / self.fine_chans_per_coarse as f64
+    }
+
+    /// The visibility of a timestep, (fine) channel and baseline (in mwalib
+    /// order, with auto-correlations). Each polarisation `p` of visibility
+    /// number `n` (counting baselines fastest, then channels, then timesteps)
+    /// is `(8n + 2p) + (8n + 2p + 1)i`.
+    pub fn vis(&self, timestep_idx: usize, chan_idx: usize, baseline_idx: usize) -> Jones<f32> {
+        let n = ((timestep_idx * self.num_chans() + chan_idx) * self.num_baselines() + baseline_idx)
+            as f32;
+        let pol = |p: f32| Complex::new(8.0 * n + 2.0 * p, 8.0 * n + 2.0 * p + 1.0);
+        Jones::from([pol(0.0), pol(1.0), pol(2.0), pol(3.0)])
+    }
+
+    /// The [`VisContext`] of all of the visibilities of this dataset.
+    pub fn vis_ctx(&self) -> VisContext {
+        let sel_baselines = (0..self.num_ants)
+            .flat_map(|ant1| (ant1..self.num_ants).map(move |ant2| (ant1, ant2)))
+            .collect();
+        let fine_chan_width_hz = self.fine_chan_width_hz();
+        // The centre of the first fine channel of the first coarse channel.
+        let start_freq_hz = self.coarse_chans[0] as f64 * MWA_COARSE_CHAN_WIDTH_HZ
+            - MWA_COARSE_CHAN_WIDTH_HZ / 2.0
+            + fine_chan_width_hz / 2.0;
+        VisContext::builder()
+            .timesteps(self.start_epoch(), self.int_time, self.num_timesteps)
+            .channels(start_freq_hz, fine_chan_width_hz, self.num_chans())
+            .baselines(sel_baselines)
+            .build()
+            .expect("mini dataset visibility context is always valid")
+    }
+
+    /// The [`ObsContext`] described by the metafits file. The array points
+    /// at, and is phased to, the zenith at the start of the observation.
+    pub fn obs_ctx(&self) -> ObsContext {
+        let start = self.start_epoch();
+        let lst_deg =
+            get_lmst(MWA_LONG_RAD, start, Duration::from_total_nanoseconds(0)).to_degrees();
+        let phase_centre = RADec::new_degrees(lst_deg, MWA_LAT_DEG);
+        ObsContext {
+            sched_start_timestamp: start,
+            sched_duration: self.int_time * self.num_timesteps as i64,
+            name: Some(format!("mini_{}", self.obsid)),
+            field_name: Some("mini".into()),
+            project_id: Some("C001".into()),
+            observer: Some("marlu".into()),
+            phase_centre,
+            pointing_centre: Some(phase_centre),
+            array_pos: LatLngHeight::new_mwa(),
+            telescope_name: "MWA".into(),
+            instrument: "MWA".into(),
+            ant_positions_enh: make_ant_positions(self.num_ants),
+            ant_names: (0..self.num_ants)
+                .map(|i| format!("Tile{:03}", i + 1))
+                .collect(),
+            ant_flags: vec![false; self.num_ants],
+            beams: vec![],
+        }
+    }
+
+    fn start_epoch(&self) -> Epoch {
+        Epoch::from_gpst_seconds(f64::from(self.obsid))
+    }
+
+    fn write_metafits(&self, path: &Path) -> Result<(), IOError> {
+        let obs_ctx = self.obs_ctx();
+        let start = self.start_epoch();
+        let date = date_string(start);
+        let int_time_s = self.int_time.in_seconds();
+        let num_chans = self.coarse_chans.len();
+        let channels = self
+            .coarse_chans
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let receivers = (0..(self.num_ants + 7) / 8)
+            .map(|rx| (rx + 1).to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let delays = vec!["0"; 16].join(",");
+        let centre_freq_mhz = self.coarse_chans.iter().sum::<usize>() as f64 / num_chans as f64
+            * MWA_COARSE_CHAN_WIDTH_HZ
+            / 1e6;
+        let unix_start = start.as_unix_seconds();
+
+        let mut header = Header::default();
+        header
+            .key("SIMPLE", Value::Logical(true), "conforms to FITS standard")
+            .key("BITPIX", Value::Int(8), "array data type")
+            .key("NAXIS", Value::Int(0), "number of array dimensions")
+            .key("EXTEND", Value::Logical(true), "")
+            .key(
+                "GPSTIME",
+                Value::Int(i64::from(self.obsid)),
+                "[s] GPS time of observation start",
+            )
+            .key(
+                "EXPOSURE",
+                Value::Int((int_time_s * self.num_timesteps as f64).ceil() as i64),
+                "[s] duration of observation",
+            )
+            .key(
+                "FILENAME",
+                Value::Str(obs_ctx.name.as_deref().unwrap_or_default()),
+                "Name of observation",
+            )
+            .key(
+                "MJD",
+                Value::Float(mjd_utc_days(start)),
+                "[days] MJD of observation",
+            )
+            .key(
+                "DATE-OBS",
+                Value::Str(&date),
+                "[UT] Date and time of observation",
+            )
+            .key(
+                "LST",
+                Value::Float(obs_ctx.phase_centre.ra.to_degrees()),
+                "[deg] LST",
+            )
+            .key(
+                "HA",
+                Value::Str("00:00:00.00"),
+                "[hours] hour angle of pointing center",
+            )
+            .key(
+                "AZIMUTH",
+                Value::Float(0.0),
+                "[deg] Azimuth of pointing center",
+            )
+            .key(
+                "ALTITUDE",
+                Value::Float(90.0),
+                "[deg] Altitude of pointing center",
+            )
+            .key(
+                "RA",
+                Value::Float(obs_ctx.phase_centre.ra.to_degrees()),
+                "[deg] RA of pointing center",
+            )
+            .key(
+                "DEC",
+                Value::Float(MWA_LAT_DEG),
+                "[deg] Dec of pointing center",
+            )
+            .key(
+                "RAPHASE",
+                Value::Float(obs_ctx.phase_centre.ra.to_degrees()),
+                "[deg] RA of desired phase center",
+            )
+            .key(
+                "DECPHASE",
+                Value::Float(MWA_LAT_DEG),
+                "[deg] DEC of desired phase center",
+            )
+            .key(
+                "ATTEN_DB",
+                Value::Float(1.0),
+                "[dB] global analogue attenuation, in dB",
+            )
+            .key(
+                "SUN-DIST",
+                Value::Float(90.0),
+                "[deg] Distance from pointing center to Sun",
+            )
+            .key(
+                "MOONDIST",
+                Value::Float(90.0),
+                "[deg] Distance from pointing center to Moon",
+            )
+            .key(
+                "JUP-DIST",
+                Value::Float(90.0),
+                "[deg] Distance from pointing center to Jupiter",
+            )
+            .key("GRIDNAME", Value::Str("sweet"), "Pointing grid name")
+            .key("GRIDNUM", Value::Int(0), "Pointing grid number")
+            .key("CREATOR", Value::Str("marlu"), "Observation creator")
+            .key("PROJECT", Value::Str("C001"), "Project ID")
+            .key("MODE", Value::Str("HW_LFILES"), "Observation mode")
+            .key("RECVRS", Value::Str(&receivers), "Active receivers")
+            .key("DELAYS", Value::Str(&delays), "Beamformer delays")
+            .key(
+                "CALIBRAT",
+                Value::Logical(false),
+                "Intended for calibration",
+            )
+            .key("CALIBSRC", Value::Str("NONE"), "Calibrator source")
+            .key(
+                "CENTCHAN",
+                Value::Int(self.coarse_chans[num_chans / 2] as i64),
+                "Center coarse channel",
+            )
+            .key("CHANNELS", Value::Str(&channels), "Coarse channels")
+            .key("SUN-ALT", Value::Float(-50.0), "[deg] Altitude of Sun")
+            .key(
+                "FINECHAN",
+                Value::Float(self.fine_chan_width_hz() / 1e3),
+                "[kHz] Fine channel width - correlator freq_res",
+            )
+            .key(
+                "INTTIME",
+                Value::Float(int_time_s),
+                "[s] Individual integration time",
+            )
+            .key("NAV_FREQ", Value::Int(1), "Assumed frequency averaging")
+            .key(
+                "NSCANS",
+                Value::Int(self.num_timesteps as i64),
+                "Number of time instants in correlation products",
+            )
+            .key(
+                "NINPUTS",
+                Value::Int(2 * self.num_ants as i64),
+                "Number of inputs into the correlation products",
+            )
+            .key(
+                "NCHANS",
+                Value::Int(self.num_chans() as i64),
+                "Number of (averaged) fine channels in spectrum",
+            )
+            .key(
+                "BANDWDTH",
+                Value::Float(num_chans as f64 * MWA_COARSE_CHAN_WIDTH_HZ / 1e6),
+                "[MHz] Total bandwidth",
+            )
+            .key(
+                "FREQCENT",
+                Value::Float(centre_freq_mhz),
+                "[MHz] Center frequency of observation",
+            )
+            .key(
+                "TIMEOFF",
+                Value::Int(0),
+                "[s] Deprecated, use QUACKTIM or GOODTIME",
+            )
+            .key(
+                "DATESTRT",
+                Value::Str(&date),
+                "[UT] Date and time of correlations start",
+            )
+            .key("VERSION", Value::Float(2.0), "METAFITS version number")
+            .key("TELESCOP", Value::Str("MWA"), "")
+            .key("INSTRUME", Value::Str("128T"), "")
+            .key(
+                "QUACKTIM",
+                Value::Float(0.0),
+                "Seconds of bad data after observation starts",
+            )
+            .key(
+                "GOODTIME",
+                Value::Float(unix_start),
+                "OBSID+QUACKTIME as Unix timestamp",
+            )
+            .key("DATE", Value::Str(&date), "UT Date of file creation");
+
+        let mut table_header = Header::default();
+        table_header
+            .key("XTENSION", Value::Str("BINTABLE"), "binary table extension")
+            .key("BITPIX", Value::Int(8), "array data type")
+            .key("NAXIS", Value::Int(2), "number of array dimensions")
+            .key(
+                "NAXIS1",
+                Value::Int(TILEDATA_ROW_LEN as i64),
+                "length of dimension 1",
+            )
+            .key(
+                "NAXIS2",
+                Value::Int(2 * self.num_ants as i64),
+                "length of dimension 2",
+            )
+            .key("PCOUNT", Value::Int(0), "number of group parameters")
+            .key("GCOUNT", Value::Int(1), "number of groups")
+            .key(
+                "TFIELDS",
+                Value::Int(TILEDATA_COLUMNS.len() as i64),
+                "number of table fields",
+            )
+            .key("EXTNAME", Value::Str("TILEDATA"), "extension name");
+        for (i, (name, form, unit)) in TILEDATA_COLUMNS.iter().enumerate() {
+            table_header
+                .key(&format!("TTYPE{}", i + 1), Value::Str(name), "")
+                .key(&format!("TFORM{}", i + 1), Value::Str(form), "");
+            if !unit.is_empty() {
+                table_header.key(&format!("TUNIT{}", i + 1), Value::Str(unit), "");
+            }
+        }
+
+        // Each antenna has a Y input, then an X input.
+        let mut rows = Vec::with_capacity(2 * self.num_ants * TILEDATA_ROW_LEN);
+        for (ant, (enh, name)) in obs_ctx
+            .ant_positions_enh
+            .iter()
+            .zip(obs_ctx.ant_names.iter())
+            .enumerate()
+        {
+            for (i_pol, pol) in ["Y", "X"].into_iter().enumerate() {
+                let input = 2 * ant + i_pol;
+                let push_i16 = |rows: &mut Vec<u8>, value: usize| {
+                    rows.extend((value as i16).to_be_bytes());
+                };
+                let push_f32 = |rows: &mut Vec<u8>, value: f64| {
+                    rows.extend((value as f32).to_be_bytes());
+                };
+                let push_str = |rows: &mut Vec<u8>, value: &str, width: usize| {
+                    rows.extend(format!("{:<width$.width$}", value, width = width).bytes());
+                };
+                push_i16(&mut rows, input);
+                push_i16(&mut rows, ant);
+                push_i16(&mut rows, ant + 1);
+                push_str(&mut rows, name, 8);
+                push_str(&mut rows, pol, 1);
+                push_i16(&mut rows, ant / 8 + 1);
+                push_i16(&mut rows, ant % 8 + 1);
+                push_i16(&mut rows, obs_ctx.ant_flags[ant] as usize);
+                push_str(&mut rows, "EL_0.00", 14);
+                push_f32(&mut rows, enh.n);
+                push_f32(&mut rows, enh.e);
+                push_f32(&mut rows, enh.h + MWA_HEIGHT_M);
+                for _ in 0..24 {
+                    push_i16(&mut rows, 64);
+                }
+                push_f32(&mut rows, 0.0);
+                for _ in 0..16 {
+                    push_i16(&mut rows, 0);
+                }
+                push_i16(&mut rows, input);
+                push_str(&mut rows, "RG6_90", 10);
+            }
+        }
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        header.write(&mut writer)?;
+        table_header.write(&mut writer)?;
+        write_data(&mut writer, &rows)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn write_gpubox(&self, path: &Path, coarse_chan_idx: usize) -> Result<(), IOError> {
+        let start = self.start_epoch();
+        let num_baselines = self.num_baselines();
+        let int_time_ms = self.int_time.in_unit(Unit::Millisecond).round() as i64;
+        let unix_start = start.as_unix_seconds().round() as i64;
+
+        let mut header = Header::default();
+        header
+            .key("SIMPLE", Value::Logical(true), "conforms to FITS standard")
+            .key("BITPIX", Value::Int(8), "array data type")
+            .key("NAXIS", Value::Int(0), "number of array dimensions")
+            .key("EXTEND", Value::Logical(true), "")
+            .key("CORR_VER", Value::Int(2), "MWA Correlator Version")
+            .key(
+                "MARKER",
+                Value::Int(0),
+                "Data offset marker (all channels should match)",
+            )
+            .key("TIME", Value::Int(unix_start), "Unix time (seconds)")
+            .key("MILLITIM", Value::Int(0), "Milliseconds since TIME")
+            .key("PROJID", Value::Str("C001"), "MWA Project Id")
+            .key(
+                "OBSID",
+                Value::Int(i64::from(self.obsid)),
+                "MWA Observation Id",
+            )
+            .key(
+                "FINECHAN",
+                Value::Float(self.fine_chan_width_hz() / 1e3),
+                "[kHz] Fine channel width",
+            )
+            .key(
+                "NFINECHS",
+                Value::Int(self.fine_chans_per_coarse as i64),
+                "Number of fine channels in this coarse channel",
+            )
+            .key(
+                "INTTIME",
+                Value::Float(self.int_time.in_seconds()),
+                "Integration time (s)",
+            )
+            .key(
+                "NINPUTS",
+                Value::Int(2 * self.num_ants as i64),
+                "Number of rf inputs into the correlation products",
+            )
+            .key("CORRHOST", Value::Str("mwax01"), "Correlator host")
+            .key(
+                "CORRCHAN",
+                Value::Int(coarse_chan_idx as i64 + 1),
+                "Correlator coarse channel",
+            )
+            .key("MC_IP", Value::Str("0.0.0.0"), "Multicast IP")
+            .key("MC_PORT", Value::Int(0), "Multicast Port");
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        header.write(&mut writer)?;
+
+        for timestep_idx in 0..self.num_timesteps {
+            let offset_ms = unix_start * 1000 + timestep_idx as i64 * int_time_ms;
+            let image_header = |bitpix: i64, naxis1: usize| {
+                let mut header = Header::default();
+                header
+                    .key("XTENSION", Value::Str("IMAGE"), "IMAGE extension")
+                    .key(
+                        "BITPIX",
+                        Value::Int(bitpix),
+                        "number of bits per data pixel",
+                    )
+                    .key("NAXIS", Value::Int(2), "number of data axes")
+                    .key("NAXIS1", Value::Int(naxis1 as i64), "length of data axis 1")
+                    .key(
+                        "NAXIS2",
+                        Value::Int(num_baselines as i64),
+                        "length of data axis 2",
+                    )
+                    .key("PCOUNT", Value::Int(0), "required keyword; must = 0")
+                    .key("GCOUNT", Value::Int(1), "required keyword; must = 1")
+                    .key("TIME", Value::Int(offset_ms / 1000), "Unix time (seconds)")
+                    .key(
+                        "MILLITIM",
+                        Value::Int(offset_ms % 1000),
+                        "Milliseconds since TIME",
+                    )
+                    .key(
+                        "MARKER",
+                        Value::Int(timestep_idx as i64),
+                        "Data offset marker (all channels should match)",
+                    );
+                header
+            };
+
+            // Visibilities are [baseline][fine channel][pol][re, im].
+            let mut data = Vec::with_capacity(num_baselines * self.fine_chans_per_coarse * 32);
+            for baseline_idx in 0..num_baselines {
+                for fine_chan_idx in 0..self.fine_chans_per_coarse {
+                    let chan_idx = coarse_chan_idx * self.fine_chans_per_coarse + fine_chan_idx;
+                    for vis in self.vis(timestep_idx, chan_idx, baseline_idx).iter() {
+                        data.extend((vis.re as i32).to_be_bytes());
+                        data.extend((vis.im as i32).to_be_bytes());
+                    }
+                }
+            }
+            image_header(32, self.fine_chans_per_coarse * 8).write(&mut writer)?;
+            write_data(&mut writer, &data)?;
+
+            // Weights are [baseline][pol].
+            let weights: Vec<u8> = (0..num_baselines * 4)
+                .flat_map(|_| 1.0_f32.to_be_bytes())
+                .collect();
+            image_header(-32, 4).write(&mut writer)?;
+            write_data(&mut writer, &weights)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    #[cfg(feature = "cfitsio")]
+    fn write_uvfits(&self, path: &Path) -> Result<(), IOError> {
+        use super::{UvfitsWriter, VisWrite};
+
+        let vis_ctx = self.vis_ctx();
+        let obs_ctx = self.obs_ctx();
+        let jones_array = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| self.vis(t, c, b));
+        let weight_array = Array3::from_elem(vis_ctx.sel_dims(), 1.0);
+        let mut writer = UvfitsWriter::from_marlu(
+            path,
+            &vis_ctx,
+            &obs_ctx.telescope(),
+            obs_ctx.phase_centre,
+            Duration::from_total_nanoseconds(0),
+            obs_ctx.name.as_deref(),
+            None,
+        )?;
+        writer.write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)?;
+        writer.finalise()
+    }
+}
+
+/// A builder of a [`MiniDataset`].
+#[derive(Debug, Clone)]
+pub struct MiniDatasetBuilder {
+    obsid: u32,
+    num_ants: usize,
+    coarse_chans: Vec<usize>,
+    fine_chans_per_coarse: usize,
+    num_timesteps: usize,
+    int_time: Duration,
+    #[cfg(feature = "cfitsio")]
+    uvfits: bool,
+}
+
+impl Default for MiniDatasetBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MiniDatasetBuilder {
+    pub fn new() -> Self {
+        Self {
+            obsid: 1_090_008_640,
+            num_ants: 4,
+            coarse_chans: vec![117, 118],
+            fine_chans_per_coarse: 2,
+            num_timesteps: 2,
+            int_time: Duration::from_f64(0.5, Unit::Second),
+            #[cfg(feature = "cfitsio")]
+            uvfits: false,
+        }
+    }
+
+    /// Set the observation ID, i.e. the GPS time of the start of the
+    /// observation.
+    pub fn obsid(mut self, obsid: u32) -> Self {
+        self.obsid = obsid;
+        self
+    }
+
+    /// Set the number of antennas (tiles).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_ants` is 0.
+    pub fn num_ants(mut self, num_ants: usize) -> Self {
+        assert!(num_ants > 0, "a mini dataset needs at least one antenna");
+        self.num_ants = num_ants;
+        self
+    }
+
+    /// Set the receiver channel numbers of the coarse channels. A gpubox file
+    /// is written for each.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `coarse_chans` is empty, or isn't sorted and contiguous.
+    pub fn coarse_chans(mut self, coarse_chans: Vec<usize>) -> Self {
+        assert!(
+            !coarse_chans.is_empty(),
+            "a mini dataset needs at least one coarse channel"
+        );
+        assert!(
+            coarse_chans.windows(2).all(|pair| pair[1] == pair[0] + 1),
+            "the coarse channels of a mini dataset must be sorted and contiguous"
+        );
+        self.coarse_chans = coarse_chans;
+        self
+    }
+
+    /// Set the number of fine channels in each coarse channel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fine_chans_per_coarse` is 0.
+    pub fn fine_chans_per_coarse(mut self, fine_chans_per_coarse: usize) -> Self {
+        assert!(
+            fine_chans_per_coarse > 0,
+            "a mini dataset needs at least one fine channel"
+        );
+        self.fine_chans_per_coarse = fine_chans_per_coarse;
+        self
+    }
+
+    /// Set the number of timesteps.
+    pub fn num_timesteps(mut self, num_timesteps: usize) -> Self {
+        self.num_timesteps = num_timesteps;
+        self
+    }
+
+    /// Set the integration time of each timestep. This should be a whole
+    /// number of milliseconds.
+    pub fn int_time(mut self, int_time: Duration) -> Self {
+        self.int_time = int_time;
+        self
+    }
+
+    /// Set whether a uvfits file of the visibilities is written too.
+    #[cfg(feature = "cfitsio")]
+    pub fn uvfits(mut self, uvfits: bool) -> Self {
+        self.uvfits = uvfits;
+        self
+    }
+
+    /// Write the dataset into `dir`, which must exist.
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`IOError`] if a file can't be written.
+    pub fn build<P: AsRef<Path>>(self, dir: P) -> Result<MiniDataset, IOError> {
+        let dir = dir.as_ref();
+        let mut dataset = MiniDataset {
+            metafits: dir.join(format!("{}.metafits", self.obsid)),
+            gpubox: vec![],
+            uvfits: None,
+            obsid: self.obsid,
+            num_ants: self.num_ants,
+            coarse_chans: self.coarse_chans,
+            fine_chans_per_coarse: self.fine_chans_per_coarse,
+            num_timesteps: self.num_timesteps,
+            int_time: self.int_time,
+        };
+        dataset.write_metafits(&dataset.metafits)?;
+
+        let start = date_string(dataset.start_epoch()).replace(['-', 'T', ':'], "");
+        for (coarse_chan_idx, rec_chan) in dataset.coarse_chans.iter().enumerate() {
+            let path = dir.join(format!(
+                "{}_{}_ch{:03}_000.fits",
+                dataset.obsid, start, rec_chan
+            ));
+            dataset.write_gpubox(&path, coarse_chan_idx)?;
+            dataset.gpubox.push(path);
+        }
+
+        #[cfg(feature = "cfitsio")]
+        if self.uvfits {
+            let path = dir.join(format!("{}.uvfits", dataset.obsid));
+            dataset.write_uvfits(&path)?;
+            dataset.uvfits = Some(path);
+        }
+
+        Ok(dataset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    /// The keyword and value of each card of each HDU of a FITS file.
+    fn read_headers(bytes: &[u8]) -> Vec<Vec<(String, String)>> {
+        let mut hdus = vec![];
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let mut cards = vec![];
+            loop {
+                let card = String::from_utf8_lossy(&bytes[offset..offset + FITS_CARD_LEN]);
+                offset += FITS_CARD_LEN;
+                let keyword = card[..8].trim_end();
+                if keyword == "END" {
+                    break;
+                }
+                if !keyword.is_empty() {
+                    let value = card[10..].split(" / ").next().unwrap().trim();
+                    cards.push((keyword.to_string(), value.to_string()));
+                }
+            }
+            offset += padding(offset);
+
+            let int = |keyword: &str| {
+                cards
+                    .iter()
+                    .find(|(k, _)| k == keyword)
+                    .map_or(0, |(_, v)| v.parse::<i64>().unwrap())
+            };
+            let naxis = int("NAXIS");
+            if naxis > 0 {
+                let len = (1..=naxis)
+                    .map(|i| int(&format!("NAXIS{}", i)))
+                    .product::<i64>()
+                    * int("BITPIX").abs()
+                    / 8;
+                offset += len as usize + padding(len as usize);
+            }
+            hdus.push(cards);
+        }
+        hdus
+    }
+
+    #[test]
+    fn test_header_cards() {
+        let mut header = Header::default();
+        header
+            .key("SIMPLE", Value::Logical(true), "conforms to FITS standard")
+            .key("NAXIS", Value::Int(0), "")
+            .key("FINECHAN", Value::Float(640.0), "[kHz]")
+            .key("GRIDNAME", Value::Str("sweet"), "")
+            .key("CHANNELS", Value::Str(&"1,".repeat(40)), "Coarse channels");
+        let mut bytes = vec![];
+        header.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), FITS_BLOCK_LEN);
+        let cards: Vec<_> = bytes
+            .chunks(FITS_CARD_LEN)
+            .map(|card| String::from_utf8_lossy(card).trim_end().to_string())
+            .take(8)
+            .collect();
+        assert_eq!(
+            cards[0],
+            "SIMPLE  =                    T / conforms to FITS standard"
+        );
+        assert_eq!(cards[1], "NAXIS   =                    0");
+        assert_eq!(cards[2], "FINECHAN=   6.40000000000000E2 / [kHz]");
+        assert_eq!(cards[3], "GRIDNAME= 'sweet   '");
+        assert_eq!(cards[4], format!("CHANNELS= '{}&'", &"1,".repeat(40)[..67]));
+        assert_eq!(
+            cards[5],
+            format!(
+                "CONTINUE  '{:<8}' / Coarse channels",
+                &"1,".repeat(40)[67..]
+            )
+        );
+        assert_eq!(cards[6], "END");
+    }
+
+    #[test]
+    fn test_mini_dataset_matches_real_files() {
+        let dir = tempdir().unwrap();
+        let dataset = MiniDataset::builder().build(dir.path()).unwrap();
+        assert_eq!(
+            dataset.gpubox[0].file_name().unwrap(),
+            "1090008640_20140721201024_ch117_000.fits"
+        );
+
+        // Every keyword of the real MWAX files is in the generated ones.
+        let real_dir = Path::new("tests/data/1297526432_mwax");
+        for (real, mini) in [
+            (real_dir.join("1297526432.metafits"), &dataset.metafits),
+            (
+                real_dir.join("1297526432_20210216160014_ch117_000.fits"),
+                &dataset.gpubox[0],
+            ),
+        ] {
+            let real = std::fs::read(real).unwrap();
+            let mini = std::fs::read(mini).unwrap();
+            assert_eq!(mini.len() % FITS_BLOCK_LEN, 0);
+            let real = read_headers(&real);
+            let mini = read_headers(&mini);
+            assert_eq!(real.len(), mini.len());
+            for (real, mini) in real.iter().zip(mini.iter()) {
+                for (keyword, real_value) in real {
+                    if ["CHANSEL", "COMMENT", "HISTORY", "CONTINUE"].contains(&keyword.as_str()) {
+                        continue;
+                    }
+                    let mini_value = &mini
+                        .iter()
+                        .find(|(k, _)| k == keyword)
+                        .unwrap_or_else(|| panic!("{} is missing", keyword))
+                        .1;
+                    // The layouts of the HDUs are the same.
+                    if ["TTYPE", "TFORM", "TUNIT"]
+                        .iter()
+                        .any(|prefix| keyword.starts_with(prefix))
+                        || [
+                            "XTENSION", "BITPIX", "NAXIS", "NAXIS1", "TFIELDS", "EXTNAME",
+                        ]
+                        .contains(&keyword.as_str())
+                    {
+                        assert_eq!(mini_value, real_value, "{}", keyword);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mini_dataset_gpubox_data() {
+        let dir = tempdir().unwrap();
+        let dataset = MiniDataset::builder()
+            .num_ants(3)
+            .build(dir.path())
+            .unwrap();
+        let bytes = std::fs::read(&dataset.gpubox[1]).unwrap();
+        let headers = read_headers(&bytes);
+        // The primary HDU, then visibilities and weights for each timestep.
+        assert_eq!(headers.len(), 5);
+        assert!(headers[3].contains(&("MILLITIM".into(), "500".into())));
+
+        // The first visibility of the second coarse channel is the first of
+        // its first fine channel, i.e. channel 2.
+        let data = &bytes[2 * FITS_BLOCK_LEN..];
+        let value = |i: usize| i32::from_be_bytes(data[4 * i..4 * i + 4].try_into().unwrap());
+        let expected = dataset.vis(0, 2, 0);
+        assert_eq!(value(0) as f32, expected[0].re);
+        assert_eq!(value(1) as f32, expected[0].im);
+        assert_eq!(value(7) as f32, expected[3].im);
+        let expected = dataset.vis(0, 3, 0);
+        assert_eq!(value(8) as f32, expected[0].re);
+        let expected = dataset.vis(0, 2, 1);
+        assert_eq!(value(16) as f32, expected[0].re);
+    }
+
+    #[test]
+    #[cfg(feature = "mwalib")]
+    fn test_mini_dataset_read_mwalib() {
+        use crate::VisSelection;
+        use mwalib::CorrelatorContext;
+
+        let dir = tempdir().unwrap();
+        let dataset = MiniDataset::builder()
+            .num_ants(3)
+            .num_timesteps(3)
+            .build(dir.path())
+            .unwrap();
+        let corr_ctx = CorrelatorContext::new(dataset.metafits.clone(), &dataset.gpubox).unwrap();
+        assert_eq!(corr_ctx.metafits_context.num_ants, 3);
+        assert_eq!(corr_ctx.num_common_timesteps, 3);
+        assert_eq!(corr_ctx.num_common_coarse_chans, 2);
+
+        let vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+        let mut jones_array = vis_sel.allocate_jones(2).unwrap();
+        let mut flag_array = vis_sel.allocate_flags(2).unwrap();
+        vis_sel
+            .read_mwalib(
+                &corr_ctx,
+                jones_array.view_mut(),
+                flag_array.view_mut(),
+                false,
+            )
+            .unwrap();
+        for ((t, c, b), jones) in jones_array.indexed_iter() {
+            assert_eq!(*jones, dataset.vis(t, c, b));
+        }
+        assert!(flag_array.iter().all(|&f| !f));
+    }
+
+    #[test]
+    #[cfg(feature = "cfitsio")]
+    fn test_mini_dataset_uvfits() {
+        use crate::{UvfitsReader, VisRead};
+
+        let dir = tempdir().unwrap();
+        let dataset = MiniDataset::builder()
+            .uvfits(true)
+            .build(dir.path())
+            .unwrap();
+        let mut reader = UvfitsReader::new(dataset.uvfits.as_ref().unwrap()).unwrap();
+        let vis_ctx = reader.vis_ctx().clone();
+        assert_eq!(vis_ctx.sel_dims(), dataset.vis_ctx().sel_dims());
+        let mut jones_array = Array3::zeros(vis_ctx.sel_dims());
+        let mut weight_array = Array3::zeros(vis_ctx.sel_dims());
+        reader
+            .read_vis(jones_array.view_mut(), weight_array.view_mut(), &vis_ctx)
+            .unwrap();
+        for ((t, c, b), jones) in jones_array.indexed_iter() {
+            assert_eq!(*jones, dataset.vis(t, c, b));
+        }
+    }
+}
@@ -0,0 +1,486 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Reading MWAX gpubox files by memory-mapping them, rather than through
+//! cfitsio.
+//!
+//! mwalib only uses the metadata of the gpubox files; the data sections of the
+//! HDUs are located by parsing the FITS headers, and visibilities are decoded
+//! straight from the mapped files into the chunk being read. This avoids
+//! cfitsio's buffering (and reading whole HDUs for a few baselines), which is
+//! much faster for cold-cache reads. Only uncompressed images are supported;
+//! use [`super::GpuboxReader`] for anything else.
+
+use std::fs::File;
+
+use itertools::izip;
+use memmap2::Mmap;
+use ndarray::prelude::*;
+
+use super::{locate_read_chunk, IOError, VisRead};
+use crate::{
+    mwalib::{CorrelatorContext, MWAVersion},
+    selection::FlagPolicy,
+    Jones, VisContext, VisSelection,
+};
+
+/// The size of a FITS block, which headers and data sections are padded to.
+const FITS_BLOCK_LEN: usize = 2880;
+
+/// The length of a FITS header card.
+const FITS_CARD_LEN: usize = 80;
+
+/// The layout of an HDU of a FITS file.
+#[derive(Debug, Clone)]
+struct HduLayout {
+    /// The byte offset of the data section in the file.
+    data_offset: usize,
+    bitpix: i64,
+    /// `NAXIS1`, `NAXIS2`, ...
+    naxes: Vec<usize>,
+    bscale: f64,
+    bzero: f64,
+    /// Is this a tile-compressed image?
+    compressed: bool,
+}
+
+/// Where the visibilities of a timestep and coarse channel are.
+#[derive(Debug, Clone, Copy)]
+struct HduData {
+    /// The index of the mapped file.
+    map_idx: usize,
+    /// The byte offset of the data section in the file.
+    offset: usize,
+    /// Are the visibilities 32-bit integers, rather than floats?
+    int: bool,
+}
+
+/// A [`VisRead`] source of the visibilities of a [`VisSelection`] of MWAX
+/// gpubox files, read from memory maps of the files. Like
+/// [`super::GpuboxReader`], missing HDUs, and anything flagged by the
+/// [`FlagPolicy`], are flagged with a weight of -1; all other visibilities have
+/// a weight of 1.
+pub struct MmapGpuboxReader {
+    corr_ctx: CorrelatorContext,
+    vis_sel: VisSelection,
+    vis_ctx: VisContext,
+    flag_policy: FlagPolicy,
+    maps: Vec<Mmap>,
+    /// The HDU of each selected timestep and coarse channel, or `None` if it's
+    /// missing.
+    hdus: Array2<Option<HduData>>,
+}
+
+impl MmapGpuboxReader {
+    /// Map the gpubox files of `corr_ctx` and create a reader of the
+    /// visibilities of `vis_sel`.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`IOError::UnsupportedInput`] if the files aren't MWAX
+    /// gpubox files with uncompressed visibilities, or [`IOError::StdIo`] if a
+    /// file can't be mapped.
+    pub fn new(corr_ctx: CorrelatorContext, vis_sel: VisSelection) -> Result<Self, IOError> {
+        let meta_ctx = &corr_ctx.metafits_context;
+        let fine_chans_per_coarse = meta_ctx.num_corr_fine_chans_per_coarse;
+        if corr_ctx.mwa_version != MWAVersion::CorrMWAXv2 {
+            return Err(IOError::UnsupportedInput {
+                path: meta_ctx.metafits_filename.clone(),
+                reason: "only MWAX gpubox files can be memory-mapped".to_string(),
+            });
+        }
+
+        let mut maps = vec![];
+        // [batch][file] -> (channel identifier, map index, path, layout of
+        // each HDU)
+        let mut batch_layouts = vec![];
+        for batch in &corr_ctx.gpubox_batches {
+            let mut file_layouts = vec![];
+            for gpubox_file in &batch.gpubox_files {
+                let file = File::open(&gpubox_file.filename)?;
+                // SAFETY: The map is only valid while the file isn't modified,
+                // which we assume of raw gpubox files.
+                let map = unsafe { Mmap::map(&file)? };
+                let layouts =
+                    parse_hdu_layouts(&map).map_err(|reason| IOError::UnsupportedInput {
+                        path: gpubox_file.filename.clone(),
+                        reason,
+                    })?;
+                file_layouts.push((
+                    gpubox_file.channel_identifier,
+                    maps.len(),
+                    &gpubox_file.filename,
+                    layouts,
+                ));
+                maps.push(map);
+            }
+            batch_layouts.push(file_layouts);
+        }
+
+        let expected_naxes = vec![
+            fine_chans_per_coarse * meta_ctx.num_visibility_pols * 2,
+            meta_ctx.num_baselines,
+        ];
+        let mut hdus = Array2::from_elem(
+            (
+                vis_sel.timestep_range.len(),
+                vis_sel.coarse_chan_range.len(),
+            ),
+            None,
+        );
+        for ((timestep_idx, coarse_chan_idx), hdu) in hdus.indexed_iter_mut() {
+            let timestep = &corr_ctx.timesteps[vis_sel.timestep_range.start + timestep_idx];
+            let coarse_chan =
+                &corr_ctx.coarse_chans[vis_sel.coarse_chan_range.start + coarse_chan_idx];
+            let (batch_idx, hdu_idx) = match corr_ctx
+                .gpubox_time_map
+                .get(&timestep.unix_time_ms)
+                .and_then(|chans| chans.get(&coarse_chan.gpubox_number))
+            {
+                Some(&location) => location,
+                None => continue,
+            };
+            let (_, map_idx, path, layouts) =
+                match batch_layouts[batch_idx]
+                    .iter()
+                    .find(|(channel_identifier, _, _, _)| {
+                        *channel_identifier == coarse_chan.gpubox_number
+                    }) {
+                    Some(file) => file,
+                    None => continue,
+                };
+            let unsupported = |reason: String| IOError::UnsupportedInput {
+                path: path.to_string(),
+                reason: format!("HDU {}: {}", hdu_idx + 1, reason),
+            };
+            let layout = layouts
+                .get(hdu_idx)
+                .ok_or_else(|| unsupported("the HDU doesn't exist".to_string()))?;
+            if layout.compressed {
+                return Err(unsupported("the visibilities are compressed".to_string()));
+            }
+            if !(layout.bitpix == 32 || layout.bitpix == -32) {
+                return Err(unsupported(format!("unsupported BITPIX {}", layout.bitpix)));
+            }
+            if (layout.bscale - 1.0).abs() > f64::EPSILON || layout.bzero.abs() > f64::EPSILON {
+                return Err(unsupported("the visibilities are scaled".to_string()));
+            }
+            if layout.naxes != expected_naxes {
+                return Err(unsupported(format!(
+                    "expected dimensions {:?}, found {:?}",
+                    expected_naxes, layout.naxes
+                )));
+            }
+            *hdu = Some(HduData {
+                map_idx: *map_idx,
+                offset: layout.data_offset,
+                int: layout.bitpix == 32,
+            });
+        }
+
+        let vis_ctx = VisContext::from_mwalib(
+            &corr_ctx,
+            &vis_sel.timestep_range,
+            &vis_sel.coarse_chan_range,
+            &vis_sel.baseline_idxs,
+            1,
+            1,
+        );
+        Ok(Self {
+            corr_ctx,
+            vis_sel,
+            vis_ctx,
+            flag_policy: FlagPolicy::default(),
+            maps,
+            hdus,
+        })
+    }
+
+    /// Set the flags to apply while reading, in addition to those of missing
+    /// HDUs.
+    pub fn set_flag_policy(&mut self, flag_policy: FlagPolicy) {
+        self.flag_policy = flag_policy;
+    }
+
+    /// The mwalib context of the gpubox files.
+    pub fn corr_ctx(&self) -> &CorrelatorContext {
+        &self.corr_ctx
+    }
+}
+
+impl VisRead for MmapGpuboxReader {
+    fn vis_ctx(&self) -> &VisContext {
+        &self.vis_ctx
+    }
+
+    fn read_vis(
+        &mut self,
+        mut vis: ArrayViewMut3<Jones<f32>>,
+        mut weights: ArrayViewMut3<f32>,
+        chunk_ctx: &VisContext,
+    ) -> Result<(), IOError> {
+        let location = locate_read_chunk(
+            &self.vis_ctx,
+            chunk_ctx,
+            vis.dim(),
+            weights.dim(),
+            "MmapGpuboxReader::read_vis",
+        )?;
+        let fine_chans_per_coarse = self
+            .corr_ctx
+            .metafits_context
+            .num_corr_fine_chans_per_coarse;
+        // MWAX HDUs are ordered [baseline][chan][pol][complex].
+        let floats_per_baseline = fine_chans_per_coarse * 8;
+        let mwalib_baseline_idxs: Vec<usize> = location
+            .baseline_idxs
+            .iter()
+            .map(|&idx| self.vis_sel.baseline_idxs[idx])
+            .collect();
+
+        // The flag policy works on whole coarse channels, so flags are made for
+        // all of the coarse channels that the chunk overlaps.
+        let first_coarse_chan = location.chans.start / fine_chans_per_coarse;
+        let last_coarse_chan =
+            (location.chans.end + fine_chans_per_coarse - 1) / fine_chans_per_coarse;
+        let timestep_start = self.vis_sel.timestep_range.start;
+        let coarse_chan_start = self.vis_sel.coarse_chan_range.start;
+        let chunk_sel = VisSelection {
+            timestep_range: timestep_start + location.timesteps.start
+                ..timestep_start + location.timesteps.end,
+            coarse_chan_range: coarse_chan_start + first_coarse_chan
+                ..coarse_chan_start + last_coarse_chan,
+            baseline_idxs: mwalib_baseline_idxs.clone(),
+        };
+        let mut flag_array = chunk_sel.allocate_flags(fine_chans_per_coarse)?;
+        let chan_offset = first_coarse_chan * fine_chans_per_coarse;
+
+        for (timestep, mut vis, mut flags) in izip!(
+            location.timesteps.clone(),
+            vis.outer_iter_mut(),
+            flag_array.outer_iter_mut(),
+        ) {
+            for (chan, mut vis) in location.chans.clone().zip(vis.outer_iter_mut()) {
+                let (coarse_chan, fine_chan) =
+                    (chan / fine_chans_per_coarse, chan % fine_chans_per_coarse);
+                let hdu = match self.hdus[(timestep, coarse_chan)] {
+                    Some(hdu) => hdu,
+                    None => {
+                        flags.row_mut(chan - chan_offset).fill(true);
+                        vis.fill(Jones::default());
+                        continue;
+                    }
+                };
+                let data = &self.maps[hdu.map_idx][hdu.offset..];
+                for (jones, &baseline_idx) in vis.iter_mut().zip(mwalib_baseline_idxs.iter()) {
+                    let first_float = baseline_idx * floats_per_baseline + fine_chan * 8;
+                    let bytes = &data[4 * first_float..4 * (first_float + 8)];
+                    let mut floats = [0.0; 8];
+                    for (float, bytes) in floats.iter_mut().zip(bytes.chunks_exact(4)) {
+                        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                        // FITS data are big endian.
+                        *float = if hdu.int {
+                            i32::from_be_bytes(bytes) as f32
+                        } else {
+                            f32::from_be_bytes(bytes)
+                        };
+                    }
+                    *jones = Jones::from(floats);
+                }
+            }
+        }
+        self.flag_policy
+            .apply(&self.corr_ctx, &chunk_sel, flag_array.view_mut());
+
+        let chans = location.chans.start - chan_offset..location.chans.end - chan_offset;
+        weights.zip_mut_with(&flag_array.slice(s![.., chans, ..]), |weight, &flag| {
+            *weight = if flag { -1.0 } else { 1.0 };
+        });
+        Ok(())
+    }
+}
+
+/// Find the layout of each HDU of a FITS file from its headers.
+fn parse_hdu_layouts(bytes: &[u8]) -> Result<Vec<HduLayout>, String> {
+    let mut layouts = vec![];
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let mut bitpix = None;
+        let mut naxis = 0;
+        let mut naxes = vec![];
+        let (mut pcount, mut gcount) = (0, 1);
+        let (mut bscale, mut bzero) = (1.0, 0.0);
+        let mut compressed = false;
+
+        let mut card_offset = offset;
+        loop {
+            let card = bytes
+                .get(card_offset..card_offset + FITS_CARD_LEN)
+                .ok_or_else(|| "the file is truncated".to_string())?;
+            card_offset += FITS_CARD_LEN;
+            let keyword = String::from_utf8_lossy(&card[..8]);
+            let keyword = keyword.trim_end();
+            if keyword == "END" {
+                break;
+            }
+            if &card[8..10] != b"= " {
+                continue;
+            }
+            let value = String::from_utf8_lossy(&card[10..]);
+            // Strings don't matter here, so the value ends at any comment.
+            let value = value.split('/').next().unwrap_or_default().trim();
+            let parse_int = || {
+                value
+                    .parse::<i64>()
+                    .map_err(|_| format!("bad value of {}: {}", keyword, value))
+            };
+            match keyword {
+                "BITPIX" => bitpix = Some(parse_int()?),
+                "NAXIS" => naxis = parse_int()?,
+                "PCOUNT" => pcount = parse_int()?,
+                "GCOUNT" => gcount = parse_int()?,
+                "BSCALE" | "BZERO" => {
+                    let value = value
+                        .parse::<f64>()
+                        .map_err(|_| format!("bad value of {}: {}", keyword, value))?;
+                    if keyword == "BSCALE" {
+                        bscale = value;
+                    } else {
+                        bzero = value;
+                    }
+                }
+                "ZIMAGE" => compressed = value == "T",
+                _ => {
+                    if let Some(axis) = keyword.strip_prefix("NAXIS") {
+                        if let Ok(axis) = axis.parse::<usize>() {
+                            if naxes.len() < axis {
+                                naxes.resize(axis, 0);
+                            }
+                            naxes[axis - 1] = parse_int()? as usize;
+                        }
+                    }
+                }
+            }
+        }
+        let bitpix = bitpix.ok_or_else(|| format!("HDU {} has no BITPIX", layouts.len() + 1))?;
+        naxes.truncate(naxis as usize);
+
+        let data_offset = offset + round_up_to_block(card_offset - offset);
+        let num_elements = if naxes.is_empty() {
+            0
+        } else {
+            naxes.iter().product::<usize>()
+        };
+        let data_len = (bitpix.unsigned_abs() as usize / 8)
+            * gcount as usize
+            * (pcount as usize + num_elements);
+        if data_offset + data_len > bytes.len() {
+            return Err("the file is truncated".to_string());
+        }
+        layouts.push(HduLayout {
+            data_offset,
+            bitpix,
+            naxes,
+            bscale,
+            bzero,
+            compressed,
+        });
+        offset = data_offset + round_up_to_block(data_len);
+    }
+    Ok(layouts)
+}
+
+/// Round a number of bytes up to a whole number of FITS blocks.
+fn round_up_to_block(len: usize) -> usize {
+    (len + FITS_BLOCK_LEN - 1) / FITS_BLOCK_LEN * FITS_BLOCK_LEN
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+    use crate::io::GpuboxReader;
+
+    fn get_mwax_context() -> CorrelatorContext {
+        CorrelatorContext::new(
+            "tests/data/1297526432_mwax/1297526432.metafits",
+            &[
+                "tests/data/1297526432_mwax/1297526432_20210216160014_ch117_000.fits",
+                "tests/data/1297526432_mwax/1297526432_20210216160014_ch117_001.fits",
+                "tests/data/1297526432_mwax/1297526432_20210216160014_ch118_000.fits",
+                "tests/data/1297526432_mwax/1297526432_20210216160014_ch118_001.fits",
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_hdu_layouts() {
+        let bytes =
+            std::fs::read("tests/data/1297526432_mwax/1297526432_20210216160014_ch117_000.fits")
+                .unwrap();
+        let layouts = parse_hdu_layouts(&bytes).unwrap();
+        // The primary HDU, then visibilities and weights for each timestep.
+        assert_eq!(layouts.len(), 5);
+        assert_eq!(layouts[0].naxes, Vec::<usize>::new());
+        assert_eq!(layouts[1].bitpix, 32);
+        assert_eq!(layouts[1].naxes, vec![16, 3]);
+        assert_eq!(layouts[1].data_offset % FITS_BLOCK_LEN, 0);
+        assert_eq!(layouts[2].bitpix, -32);
+        assert_eq!(layouts[2].naxes, vec![4, 3]);
+        assert!(!layouts[1].compressed);
+
+        assert!(parse_hdu_layouts(&bytes[..bytes.len() - FITS_BLOCK_LEN]).is_err());
+    }
+
+    #[test]
+    fn test_mmap_gpubox_reader_matches_gpubox_reader() {
+        let corr_ctx = get_mwax_context();
+        let vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+        let mut reader = GpuboxReader::new(get_mwax_context(), vis_sel.clone());
+        let mut mmap_reader = MmapGpuboxReader::new(corr_ctx, vis_sel).unwrap();
+        let vis_ctx = reader.vis_ctx().clone();
+        let mmap_vis_ctx = mmap_reader.vis_ctx();
+        assert_eq!(mmap_vis_ctx.sel_dims(), vis_ctx.sel_dims());
+        assert_eq!(mmap_vis_ctx.start_timestamp, vis_ctx.start_timestamp);
+        assert_eq!(mmap_vis_ctx.int_time, vis_ctx.int_time);
+        assert_abs_diff_eq!(mmap_vis_ctx.start_freq_hz, vis_ctx.start_freq_hz);
+        assert_abs_diff_eq!(mmap_vis_ctx.freq_resolution_hz, vis_ctx.freq_resolution_hz);
+        assert_eq!(mmap_vis_ctx.sel_baselines, vis_ctx.sel_baselines);
+
+        // Chunks that don't line up with coarse channels, with the baselines
+        // reversed.
+        let fine_chans_per_coarse = reader
+            .corr_ctx()
+            .metafits_context
+            .num_corr_fine_chans_per_coarse;
+        for freq_chunk in vis_ctx.chunk_freq(vis_ctx.num_sel_chans / (fine_chans_per_coarse + 1)) {
+            for time_chunk in vis_ctx.chunk_time(1) {
+                let chunk_ctx = VisContext {
+                    num_sel_timesteps: time_chunk.num_sel_timesteps,
+                    start_timestamp: time_chunk.start_timestamp,
+                    sel_baselines: vis_ctx.sel_baselines.iter().rev().copied().collect(),
+                    ..freq_chunk.clone()
+                };
+                let mut expected_vis = Array3::zeros(chunk_ctx.sel_dims());
+                let mut expected_weights = Array3::zeros(chunk_ctx.sel_dims());
+                reader
+                    .read_vis(
+                        expected_vis.view_mut(),
+                        expected_weights.view_mut(),
+                        &chunk_ctx,
+                    )
+                    .unwrap();
+                let mut vis = Array3::zeros(chunk_ctx.sel_dims());
+                let mut weights = Array3::zeros(chunk_ctx.sel_dims());
+                mmap_reader
+                    .read_vis(vis.view_mut(), weights.view_mut(), &chunk_ctx)
+                    .unwrap();
+                assert_abs_diff_eq!(vis, expected_vis);
+                assert_abs_diff_eq!(weights, expected_weights);
+            }
+        }
+    }
+}
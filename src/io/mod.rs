@@ -2,18 +2,33 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-pub mod error;
+pub mod metrics;
+pub mod mini;
+mod pol;
+pub mod retry;
+pub mod validate;
+mod writer;
+
 use ndarray::prelude::*;
 
-use crate::{context::VisContext, Jones};
-use error::IOError;
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
+use crate::context::ChunkLocation;
+use crate::{context::VisContext, errors::IOError, Jones};
+pub use metrics::{MetricsWriter, QualityMetrics};
+pub use mini::{MiniDataset, MiniDatasetBuilder};
+pub use pol::PolFrame;
+pub use retry::{RetryHook, RetryPolicy, RetryReader};
+pub use writer::{OutputEstimate, SplitWriter, VisWriteFormat, VisWriter};
+
+/// The errors of the IO code, which have moved to [`crate::errors`].
+pub use crate::errors as error;
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "cfitsio")] {
         pub mod uvfits;
 
-        pub use error::UvfitsWriteError;
-        pub use uvfits::UvfitsWriter;
+        pub use crate::errors::UvfitsWriteError;
+        pub use uvfits::{BaselineEncoding, DateEncoding, UvfitsOptions, UvfitsReader, UvfitsWriter};
     }
 }
 
@@ -21,38 +36,129 @@ cfg_if::cfg_if! {
     if #[cfg(feature = "ms")] {
         pub mod ms;
 
-        pub use error::MeasurementSetWriteError;
-        pub use ms::MeasurementSetWriter;
+        pub use crate::errors::MeasurementSetWriteError;
+        pub use ms::{MeasurementSetReader, MeasurementSetWriter, SigmaPolicy};
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "parquet")] {
+        pub mod parquet;
+
+        pub use crate::errors::ParquetWriteError;
+        pub use self::parquet::ParquetWriter;
     }
 }
 
+cfg_if::cfg_if! {
+    if #[cfg(feature = "zarr")] {
+        pub mod zarr;
+
+        pub use crate::errors::ZarrWriteError;
+        pub use zarr::ZarrWriter;
+    }
+}
+
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
+mod concat;
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
+pub use concat::concat;
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "mwalib")] {
-        use std::ops::Range;
-        use mwalib::CorrelatorContext;
+        mod gpubox;
+
+        pub use gpubox::GpuboxReader;
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "mmap")] {
+        mod mmap;
+
+        pub use mmap::MmapGpuboxReader;
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "async")] {
+        pub mod async_write;
+
+        pub use async_write::{AsyncVisWrite, ObjectSink, StreamingWriter};
     }
 }
 
-/// The container has visibilities which can be read by passing in a mwalib
-/// context and the range of values to read.
-#[cfg(feature = "mwalib")]
-pub trait VisRead: Sync + Send {
-    /// Read the visibilities and weights for the selected timesteps, coarse
-    /// channels and baselines into the provided arrays.
+/// A source of visibilities that can be read in chunks, e.g. gpubox files, a
+/// uvfits file or a measurement set. Like [`VisWrite`] for outputs, this allows
+/// pipelines to be generic over the input format.
+pub trait VisRead {
+    /// The context of all of the visibilities of this source, without
+    /// averaging.
+    fn vis_ctx(&self) -> &VisContext;
+
+    /// Read the visibilities and weights of a chunk of this source into the
+    /// provided arrays.
     ///
-    /// # Errors
+    /// `vis` - a three dimensional array of jones matrix visibilities.
+    ///     The dimensions of the array are `[timestep][channel][baseline]`
+    ///
+    /// `weights` - a three dimensional array of visibility weights, where the sign
+    ///     of the weight is the flag. The dimensions of the array are
+    ///     `[timestep][channel][baseline]`
+    ///
+    /// `chunk_ctx` - a [`VisContext`] describing the chunk to read. This is a
+    ///     contiguous part of [`VisRead::vis_ctx`] in time and frequency with
+    ///     any of its baselines, e.g. from [`VisContext::chunk_time`].
     ///
-    /// Can throw `IOError` if there is an issue reading.
+    /// # Errors
     ///
-    fn read_vis_mwalib(
-        &self,
-        jones_array: ArrayViewMut3<Jones<f32>>,
-        weight_array: ArrayViewMut3<f32>,
-        context: &CorrelatorContext,
-        timestep_range: &Range<usize>,
-        coarse_chan_range: &Range<usize>,
-        baseline_idxs: &[usize],
+    /// Will return [`IOError::ChunkNotInSource`] if the chunk isn't part of
+    /// this source, or another [`IOError`] if reading fails.
+    fn read_vis(
+        &mut self,
+        vis: ArrayViewMut3<Jones<f32>>,
+        weights: ArrayViewMut3<f32>,
+        chunk_ctx: &VisContext,
     ) -> Result<(), IOError>;
+
+    /// Read a chunk of visibilities like [`VisRead::read_vis`], but if the
+    /// frequency axis of this source is descending, read them in ascending
+    /// frequency order. Only the order of the channels changes; the
+    /// visibilities themselves (including their phases) are unchanged.
+    ///
+    /// `chunk_ctx` - a [`VisContext`] describing the chunk to read, which is
+    ///     part of the ascending [`VisContext::to_ascending`] of
+    ///     [`VisRead::vis_ctx`].
+    fn read_vis_ascending(
+        &mut self,
+        vis: ArrayViewMut3<Jones<f32>>,
+        weights: ArrayViewMut3<f32>,
+        chunk_ctx: &VisContext,
+    ) -> Result<(), IOError> {
+        if !self.vis_ctx().is_descending() {
+            return self.read_vis(vis, weights, chunk_ctx);
+        }
+        let (mut vis, mut weights) = (vis, weights);
+        vis.invert_axis(Axis(1));
+        weights.invert_axis(Axis(1));
+        self.read_vis(vis, weights, &chunk_ctx.to_descending())
+    }
+}
+
+/// Locate a chunk to be read with [`VisRead::read_vis`] in the context of its
+/// source, checking the shapes of the arrays to read into.
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
+fn locate_read_chunk(
+    vis_ctx: &VisContext,
+    chunk_ctx: &VisContext,
+    vis_dim: (usize, usize, usize),
+    weights_dim: (usize, usize, usize),
+    function: &'static str,
+) -> Result<ChunkLocation, IOError> {
+    chunk_ctx.check_sel_dims(vis_dim, weights_dim, function)?;
+    vis_ctx
+        .locate_chunk(chunk_ctx)
+        .ok_or(IOError::ChunkNotInSource)
 }
 
 /// The container can accept a chunk of visibilities to be written.
@@ -81,6 +187,27 @@ pub trait VisWrite {
         draw_progress: bool,
     ) -> Result<(), IOError>;
 
+    /// Write a chunk of visibilities like [`VisWrite::write_vis`], but if the
+    /// frequency axis of `vis_ctx` is descending, flip the visibilities and
+    /// weights so that they are written in ascending frequency order. Like
+    /// [`VisRead::read_vis_ascending`], only the order of the channels
+    /// changes.
+    fn write_vis_ascending(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        vis_ctx: &VisContext,
+        draw_progress: bool,
+    ) -> Result<(), IOError> {
+        if !vis_ctx.is_descending() {
+            return self.write_vis(vis, weights, vis_ctx, draw_progress);
+        }
+        let (mut vis, mut weights) = (vis, weights);
+        vis.invert_axis(Axis(1));
+        weights.invert_axis(Axis(1));
+        self.write_vis(vis, weights, &vis_ctx.to_ascending(), draw_progress)
+    }
+
     /// When all visibilities have been given to this [`VisWrite`] implementor,
     /// calling this function will perform any remaining tasks before the writer
     /// can be dropped.
@@ -10,7 +10,7 @@ use std::{
 };
 
 use flate2::read::GzDecoder;
-use hifitime::{Duration, Unit};
+use hifitime::{Duration, Epoch, Unit};
 use indicatif::{ProgressDrawTarget, ProgressStyle};
 use itertools::izip;
 use lazy_static::lazy_static;
@@ -21,17 +21,22 @@ use rubbl_casatables::{
 };
 use tar::Archive;
 
-use super::{
-    error::{BadArrayShape, MeasurementSetWriteError},
-    VisWrite,
-};
+use super::{locate_read_chunk, OutputEstimate, VisRead, VisWrite};
 use crate::{
-    average_chunk_f64, c32,
-    io::error::{IOError, MeasurementSetWriteError::MeasurementSetFull},
-    ndarray::{array, Array2, Array3, ArrayView, ArrayView3, Axis},
+    averaging::average_chunk_f64,
+    c32,
+    errors::{
+        BadArrayShape, IOError, MeasurementSetWriteError,
+        MeasurementSetWriteError::{BadFlagCategories, MeasurementSetFull},
+    },
+    flagging::FlagLayers,
+    iers::PolarMotion,
+    ndarray::{array, s, Array1, Array2, Array3, ArrayView3, ArrayViewMut3, Axis},
     num_complex::Complex,
-    precession::precess_time,
-    History, Jones, LatLngHeight, MwaObsContext, ObsContext, RADec, VisContext, XyzGeodetic, UVW,
+    precession::{precess_time_with_polar_motion, UvwFrame},
+    weights::flags_to_negative_weights,
+    AveragingPolicy, History, InstrumentState, Jones, LatLngHeight, MwaObsContext, ObsContext,
+    PolFrame, Provenance, RADec, Telescope, VisContext, VisUnit, WeightFlags, XyzGeodetic,
 };
 
 #[cfg(feature = "mwalib")]
@@ -46,6 +51,35 @@ lazy_static! {
 const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
 const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 
+/// How the `SIGMA` column of a measurement set is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigmaPolicy {
+    /// `SIGMA` is always 1, like cotter.
+    Unity,
+
+    /// `SIGMA` is `1 / sqrt(WEIGHT)`, and a `SIGMA_SPECTRUM` column is written
+    /// with `1 / sqrt(WEIGHT_SPECTRUM)`. Flagged visibilities (negative
+    /// weights) use the magnitude of their weight. Like CASA's `mstransform`,
+    /// the sigma of a zero weight is -1.
+    FromWeights,
+}
+
+impl Default for SigmaPolicy {
+    fn default() -> Self {
+        Self::Unity
+    }
+}
+
+/// Convert a (non-negative) weight to a sigma according to
+/// [`SigmaPolicy::FromWeights`].
+fn weight_to_sigma(weight: f32) -> f32 {
+    if weight > 0. {
+        1. / weight.sqrt()
+    } else {
+        -1.
+    }
+}
+
 /// A helper struct to write out a CASA Measurement Set.
 pub struct MeasurementSetWriter {
     /// The path to the root of the measurement set (typically ends in .ms)
@@ -54,6 +88,10 @@ pub struct MeasurementSetWriter {
     /// The RA/Dec where this observation is phased to
     phase_centre: RADec,
 
+    /// The row of the `FIELD` table that visibilities are written to; see
+    /// [`MeasurementSetWriter::select_field`].
+    field_id: i32,
+
     /// Array Position [Latitude (radians), Longitude (radians), Height (m)]
     array_pos: LatLngHeight,
 
@@ -61,13 +99,38 @@ pub struct MeasurementSetWriter {
     pub main_row_idx: usize,
 
     /// The *unprecessed* positions of the antennas. The writing code will
-    /// precess these positions to J2000 for each timestep.
+    /// precess these positions to J2000 for each timestep, unless `uvw_frame`
+    /// is [`UvwFrame::OfDate`].
     antenna_positions: Vec<XyzGeodetic>,
 
     /// UT1 - UTC, a.k.a. DUT1. We assume that this value is suitable for all
     /// timesteps being written; this is pretty sensible, because the value
     /// should change very slowly (a few milliseconds over ~5 days?).
     dut1: Duration,
+
+    /// The polarisation products written; see
+    /// [`MeasurementSetWriter::set_pol_frame`].
+    pol_frame: PolFrame,
+
+    /// The frame of the written UVWs; see
+    /// [`MeasurementSetWriter::set_uvw_frame`].
+    uvw_frame: UvwFrame,
+
+    /// The polar motion used when forming UVWs; see
+    /// [`MeasurementSetWriter::set_polar_motion`].
+    polar_motion: PolarMotion,
+
+    /// How `SIGMA` is written; see [`MeasurementSetWriter::set_sigma_policy`].
+    sigma_policy: SigmaPolicy,
+
+    /// The names of the `FLAG_CATEGORY` layers; see
+    /// [`MeasurementSetWriter::set_flag_categories`].
+    flag_categories: Vec<String>,
+
+    /// The telescope whose antenna mounts, dish diameters and axis offsets are
+    /// written to the `ANTENNA` table; see
+    /// [`MeasurementSetWriter::set_telescope`].
+    telescope: Option<Telescope>,
 }
 
 impl MeasurementSetWriter {
@@ -81,11 +144,104 @@ impl MeasurementSetWriter {
         MeasurementSetWriter {
             path: path.as_ref().to_path_buf(),
             phase_centre,
+            field_id: 0,
             array_pos,
             main_row_idx: 0,
             antenna_positions,
             dut1,
+            pol_frame: PolFrame::default(),
+            uvw_frame: UvwFrame::default(),
+            polar_motion: PolarMotion::default(),
+            sigma_policy: SigmaPolicy::default(),
+            flag_categories: vec![],
+            telescope: None,
+        }
+    }
+
+    /// Create a writer for the telescope described by an [`ObsContext`],
+    /// taking the phase centre, array position and antenna positions from it.
+    pub fn from_marlu<T: AsRef<Path>>(path: T, obs_ctx: &ObsContext, dut1: Duration) -> Self {
+        Self::new(
+            path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            obs_ctx.ant_positions_geodetic().collect(),
+            dut1,
+        )
+    }
+
+    /// Write visibilities in the given [`PolFrame`] rather than as linear
+    /// polarisations. This must be called before the measurement set is
+    /// initialized, as it changes the shape of the data columns.
+    pub fn set_pol_frame(&mut self, pol_frame: PolFrame) {
+        self.pol_frame = pol_frame;
+    }
+
+    /// Write UVWs in the given [`UvwFrame`] rather than precessing them to
+    /// J2000. Note that the measures of the phase centre are not changed.
+    pub fn set_uvw_frame(&mut self, uvw_frame: UvwFrame) {
+        self.uvw_frame = uvw_frame;
+    }
+
+    /// Correct the array position for [`PolarMotion`] (e.g. from an
+    /// [`crate::iers::IersTable`]) when forming UVWs. By default, polar motion
+    /// is ignored.
+    pub fn set_polar_motion(&mut self, polar_motion: PolarMotion) {
+        self.polar_motion = polar_motion;
+    }
+
+    /// Choose how the `SIGMA` column is written. This must be called before
+    /// the measurement set is initialized, as [`SigmaPolicy::FromWeights`]
+    /// adds a `SIGMA_SPECTRUM` column.
+    pub fn set_sigma_policy(&mut self, sigma_policy: SigmaPolicy) {
+        self.sigma_policy = sigma_policy;
+    }
+
+    /// Populate `FLAG_CATEGORY` with the named flag layers `categories`, e.g.
+    /// [`crate::flagging::FLAG_LAYER_RFI`], in this order. This must be called
+    /// before the measurement set is initialized, as the names are stored in
+    /// the `CATEGORY` keyword of the column. The layers are written with
+    /// [`MeasurementSetWriter::write_vis_with_flag_layers`].
+    pub fn set_flag_categories<S: AsRef<str>>(&mut self, categories: &[S]) {
+        self.flag_categories = categories
+            .iter()
+            .map(|category| category.as_ref().to_string())
+            .collect();
+    }
+
+    /// Take the mount, dish diameter and axis offset of each antenna in the
+    /// `ANTENNA` table from `telescope`, rather than writing MWA tiles. This
+    /// must be called before the measurement set is initialized. The names and
+    /// positions of the antennas are still taken from the [`ObsContext`].
+    pub fn set_telescope(&mut self, telescope: Telescope) {
+        self.telescope = Some(telescope);
+    }
+
+    /// Estimate the size of a measurement set of the averaged visibilities of
+    /// `vis_ctx`, written with the current settings of this writer, and the
+    /// memory used to write it in chunks of `timesteps_per_chunk` timesteps.
+    /// Nothing is written to disk.
+    ///
+    /// Flags are assumed to be stored as bits, and the subtables are assumed
+    /// to take 256 kiB, which is typical unless there are many antennas or
+    /// `HISTORY` rows.
+    pub fn estimate(&self, vis_ctx: &VisContext, timesteps_per_chunk: usize) -> OutputEstimate {
+        const SUBTABLE_BYTES: u64 = 256 * 1024;
+        // UVW, then TIME, TIME_CENTROID, INTERVAL and EXPOSURE, then the 11
+        // integer ID columns, then FLAG_ROW.
+        const SCALAR_BYTES: usize = 3 * 8 + 4 * 8 + 11 * 4 + 1;
+
+        let num_pols = self.pol_frame.num_pols();
+        let num_vis = num_pols * vis_ctx.num_avg_chans();
+        // DATA and WEIGHT_SPECTRUM, and maybe SIGMA_SPECTRUM.
+        let mut row_bytes = num_vis * (std::mem::size_of::<c32>() + std::mem::size_of::<f32>());
+        if self.sigma_policy == SigmaPolicy::FromWeights {
+            row_bytes += num_vis * std::mem::size_of::<f32>();
         }
+        // FLAG and FLAG_CATEGORY, then SIGMA and WEIGHT.
+        let num_flags = num_vis * (1 + self.flag_categories.len());
+        row_bytes += (num_flags + 7) / 8 + 2 * num_pols * std::mem::size_of::<f32>() + SCALAR_BYTES;
+        OutputEstimate::new(vis_ctx, row_bytes, SUBTABLE_BYTES, timesteps_per_chunk)
     }
 
     pub fn validate_path(&self, path: &Path) -> Result<(), MeasurementSetWriteError> {
@@ -130,7 +286,7 @@ impl MeasurementSetWriter {
         );
         let mut main_table = Table::open(&self.path, TableOpenMode::ReadWrite)?;
         // TODO: why isn't it let data_shape = [4, num_channels as _];
-        let data_shape = [num_channels as _, 4];
+        let data_shape = [num_channels as _, self.pol_frame.num_pols() as _];
         main_table.add_array_column(
             GlueDataType::TpComplex,
             "DATA",
@@ -224,6 +380,33 @@ impl MeasurementSetWriter {
             false,
         )?;
 
+        // Not written by cotter, but useful for beam models and calibration.
+        let comment = format!("added by {} {}", PKG_VERSION, PKG_NAME);
+        ant_table.add_array_column(
+            GlueDataType::TpInt,
+            "MWA_DIPOLE_DELAYS",
+            Some(comment.as_str()),
+            None,
+            false,
+            false,
+        )?;
+        ant_table.add_array_column(
+            GlueDataType::TpDouble,
+            "MWA_DIPOLE_GAINS",
+            Some(comment.as_str()),
+            None,
+            false,
+            false,
+        )?;
+        ant_table.add_array_column(
+            GlueDataType::TpDouble,
+            "MWA_DIGITAL_GAINS",
+            Some(comment.as_str()),
+            None,
+            false,
+            false,
+        )?;
+
         Ok(())
     }
 
@@ -993,6 +1176,78 @@ impl MeasurementSetWriter {
         Ok(())
     }
 
+    /// Append a row to the `HISTORY` table for each of the given
+    /// [`InstrumentState`]s, e.g. those from
+    /// [`MwaObsContext::instrument_states_in_selection`]. This should be called
+    /// after the measurement set has been initialized.
+    pub fn write_instrument_state_history<'a, I>(
+        &self,
+        states: I,
+    ) -> Result<(), MeasurementSetWriteError>
+    where
+        I: IntoIterator<Item = &'a InstrumentState>,
+    {
+        self.write_history_messages(states.into_iter().map(InstrumentState::as_comment))
+    }
+
+    /// Append a row to the `HISTORY` table for each message, e.g.
+    /// [`crate::selection::AntennaRenumbering::as_comments`]. This should be
+    /// called after the measurement set has been initialized.
+    pub fn write_history_messages<I, S>(&self, messages: I) -> Result<(), MeasurementSetWriteError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut hist_table = Table::open(&self.path.join("HISTORY"), TableOpenMode::ReadWrite)?;
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_millis() as f64
+            / 1000.;
+        let application = format!("{} {}", PKG_NAME, PKG_VERSION);
+        for message in messages {
+            let idx = hist_table.n_rows();
+            hist_table.add_rows(1)?;
+            self.write_history_row(
+                &mut hist_table,
+                idx,
+                time,
+                "",
+                message.as_ref(),
+                &application,
+                "",
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Append a row to the `HISTORY` table describing the [`Provenance`] of
+    /// the measurement set. The software versions are in `MESSAGE`, and each
+    /// parameter is an element of `APP_PARAMS`. This should be called after
+    /// the measurement set has been initialized.
+    pub fn write_provenance(
+        &self,
+        provenance: &Provenance,
+    ) -> Result<(), MeasurementSetWriteError> {
+        let mut hist_table = Table::open(&self.path.join("HISTORY"), TableOpenMode::ReadWrite)?;
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_millis() as f64
+            / 1000.;
+        let idx = hist_table.n_rows();
+        hist_table.add_rows(1)?;
+        self.write_history_row(
+            &mut hist_table,
+            idx,
+            time,
+            provenance.cmd_line.as_deref().unwrap_or_default(),
+            &provenance.software_message(),
+            &provenance.application,
+            "",
+        )?;
+        hist_table.put_cell("APP_PARAMS", idx, &provenance.parameter_strings())?;
+        Ok(())
+    }
+
     /// Write a row into the `FEED` table.
     ///
     /// - `table` - [`rubbl_casatables::Table`] object to write to.
@@ -1132,15 +1387,6 @@ impl MeasurementSetWriter {
         Ok(())
     }
 
-    fn get_centre_freq(freqs: &[f64]) -> f64 {
-        let len = freqs.len();
-        if len % 2 == 0 {
-            (freqs[len / 2] + freqs[len / 2 - 1]) * 0.5
-        } else {
-            freqs[len / 2]
-        }
-    }
-
     /// Create an MWA measurement set, with all tables (except the main visibility table)
     /// prefilled with metadata from a [`mwalib::CorrelatorContext`]
     ///
@@ -1158,6 +1404,8 @@ impl MeasurementSetWriter {
     ///
     /// `avg_freq` - the frequency averaging factor which determines the number of frequencies that
     ///     will be written
+    ///
+    /// Tiles flagged in the metafits have their `FLAG_ROW` set in the `ANTENNA` table.
     #[cfg(feature = "mwalib")]
     #[allow(clippy::too_many_arguments)]
     pub fn initialize_from_mwalib(
@@ -1217,13 +1465,26 @@ impl MeasurementSetWriter {
         // //////////// //
 
         let mut ant_table = Table::open(&self.path.join("ANTENNA"), TableOpenMode::ReadWrite)?;
-        for (idx, input, number, receiver, slot, length) in izip!(
+        for (
+            idx,
+            input,
+            number,
+            receiver,
+            slot,
+            length,
+            dipole_delays,
+            dipole_gains,
+            digital_gains,
+        ) in izip!(
             0..,
             mwa_ctx.ant_inputs.outer_iter(),
             mwa_ctx.ant_numbers.iter(),
             mwa_ctx.ant_receivers.iter(),
             mwa_ctx.ant_slots.outer_iter(),
             mwa_ctx.ant_cable_lengths.outer_iter(),
+            mwa_ctx.ant_dipole_delays.outer_iter(),
+            mwa_ctx.ant_dipole_gains.outer_iter(),
+            mwa_ctx.ant_digital_gains.outer_iter(),
         ) {
             ant_table.put_cell(
                 "MWA_INPUT",
@@ -1242,6 +1503,21 @@ impl MeasurementSetWriter {
                 idx as _,
                 &vec![length[[0]] as f64, length[[1]] as f64],
             )?;
+            // [pol][dipole]
+            ant_table.put_cell(
+                "MWA_DIPOLE_DELAYS",
+                idx as _,
+                &dipole_delays.mapv(|delay| delay as i32),
+            )?;
+            ant_table.put_cell("MWA_DIPOLE_GAINS", idx as _, &dipole_gains.to_owned())?;
+            // [coarse_chan][pol], for the selected coarse channels
+            ant_table.put_cell(
+                "MWA_DIGITAL_GAINS",
+                idx as _,
+                &digital_gains
+                    .slice(s![coarse_chan_range.clone(), ..])
+                    .to_owned(),
+            )?;
         }
 
         // /////////////////// //
@@ -1325,6 +1601,13 @@ impl MeasurementSetWriter {
     /// Create an MWA measurement set, with all tables (except the main visibility table, and
     /// custom MWA tables) prefilled with metadata from a [`VisContext`] and [`ObsContext`] (except
     /// custom MWA columns).
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MeasurementSetWriteError::TelescopeAntsMismatch`] if the
+    /// telescope given to [`MeasurementSetWriter::set_telescope`] doesn't have
+    /// the antennas of `obs_ctx`, or another [`MeasurementSetWriteError`] if
+    /// writing fails.
     pub fn initialize(
         &self,
         vis_ctx: &VisContext,
@@ -1333,6 +1616,28 @@ impl MeasurementSetWriter {
     ) -> Result<(), MeasurementSetWriteError> {
         trace!("initialize");
 
+        let telescope = self
+            .telescope
+            .clone()
+            .unwrap_or_else(|| obs_ctx.telescope());
+        // Antennas may be missing a mount, dish diameter or axis offset, but
+        // not a name.
+        let telescope_ants = [
+            telescope.ant_names.len(),
+            telescope.ant_mounts.len(),
+            telescope.ant_dish_diameters_m.len(),
+            telescope.ant_axis_offsets_m.len(),
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or(0);
+        if telescope.ant_names.len() != obs_ctx.num_ants() || telescope_ants != obs_ctx.num_ants() {
+            return Err(MeasurementSetWriteError::TelescopeAntsMismatch {
+                telescope_ants,
+                obs_ants: obs_ctx.num_ants(),
+            });
+        }
+
         // times
         let sched_start_centroid = obs_ctx.sched_start_timestamp + vis_ctx.int_time / 2.;
         let sched_end_centroid = sched_start_centroid + obs_ctx.sched_duration;
@@ -1378,6 +1683,27 @@ impl MeasurementSetWriter {
         main_table.put_column_keyword("TIME", "MEASINFO", &meas_info)?;
         main_table.put_column_keyword("TIME_CENTROID", "MEASINFO", &meas_info)?;
 
+        // The visibilities are scaled by `vis_ctx.vis_scale` as they're
+        // written, so they're in these units.
+        main_table.put_column_keyword("DATA", "UNIT", &vis_ctx.vis_unit.ms_unit().to_string())?;
+
+        if !self.flag_categories.is_empty() {
+            main_table.put_column_keyword("FLAG_CATEGORY", "CATEGORY", &self.flag_categories)?;
+        }
+
+        if self.sigma_policy == SigmaPolicy::FromWeights {
+            let comment = format!("added by {} {}", PKG_VERSION, PKG_NAME);
+            let data_shape = [num_avg_chans as _, self.pol_frame.num_pols() as _];
+            main_table.add_array_column(
+                GlueDataType::TpFloat,
+                "SIGMA_SPECTRUM",
+                Some(comment.as_str()),
+                Some(&data_shape),
+                false,
+                false,
+            )?;
+        }
+
         main_table.add_rows(num_avg_rows)?;
 
         // /////////////// //
@@ -1387,15 +1713,17 @@ impl MeasurementSetWriter {
         let mut spw_table =
             Table::open(&self.path.join("SPECTRAL_WINDOW"), TableOpenMode::ReadWrite)?;
 
-        let chan_info = Array2::from_shape_fn((num_avg_chans, 4), |(c, i)| {
-            if i == 0 {
-                avg_fine_chan_freqs_hz[c]
-            } else {
-                avg_chan_width_hz as f64
-            }
+        // Descending frequency axes are described by negative channel widths,
+        // but the effective bandwidths and resolutions are always positive.
+        let chan_info = Array2::from_shape_fn((num_avg_chans, 4), |(c, i)| match i {
+            0 => avg_fine_chan_freqs_hz[c],
+            1 => avg_chan_width_hz as f64,
+            _ => avg_chan_width_hz.abs(),
         });
 
-        let center_freq_hz = Self::get_centre_freq(&avg_fine_chan_freqs_hz);
+        // `CHAN_FREQ` holds channel centres, and the reference frequency is the
+        // centre of the band.
+        let center_freq_hz = vis_ctx.avg_centre_freq_hz();
 
         spw_table.add_rows(1)?;
 
@@ -1405,9 +1733,12 @@ impl MeasurementSetWriter {
             format!("MWA_BAND_{:.1}", center_freq_hz / 1_000_000.).as_str(),
             center_freq_hz,
             &chan_info,
-            avg_chan_width_hz as f64 * num_avg_chans as f64,
+            avg_chan_width_hz.abs() * num_avg_chans as f64,
             false,
         )?;
+        if vis_ctx.is_descending() {
+            spw_table.put_cell("NET_SIDEBAND", 0, &-1_i32)?;
+        }
 
         // //////////////// //
         // Data Description //
@@ -1436,13 +1767,21 @@ impl MeasurementSetWriter {
                 &mut ant_table,
                 idx as _,
                 name,
-                "MWA",
+                &obs_ctx.telescope_name,
                 "GROUND-BASED",
-                "ALT-AZ",
+                telescope.ant_mount(idx).ms_mount(),
                 &vec![position_geoc.x, position_geoc.y, position_geoc.z],
-                4.0,
-                false,
+                telescope.ant_dish_diameter_m(idx),
+                obs_ctx.is_ant_flagged(idx),
             )?;
+            // The axis offset is along the first axis of the mount.
+            if !telescope.ant_axis_offsets_m.is_empty() {
+                ant_table.put_cell(
+                    "OFFSET",
+                    idx as _,
+                    &vec![telescope.ant_axis_offset_m(idx), 0., 0.],
+                )?;
+            }
         }
 
         // //////////// //
@@ -1454,11 +1793,13 @@ impl MeasurementSetWriter {
         // - XY (0, 1)
         // - YX (1, 0)
         // - YY (1, 1)
+        //
+        // but these may be written as circular polarizations or Stokes I.
 
         let mut pol_table = Table::open(&self.path.join("POLARIZATION"), TableOpenMode::ReadWrite)?;
 
-        let corr_product = array![[0, 0], [0, 1], [1, 0], [1, 1]];
-        let corr_type = vec![9, 10, 11, 12];
+        let corr_product = self.pol_frame.ms_corr_products();
+        let corr_type = self.pol_frame.ms_corr_types();
         pol_table.add_rows(1)?;
 
         self.write_polarization_row(&mut pol_table, 0, &corr_type, &corr_product, false)?;
@@ -1527,7 +1868,7 @@ impl MeasurementSetWriter {
         self.write_observation_row(
             &mut obs_table,
             0,
-            "MWA",
+            &obs_ctx.telescope_name,
             (
                 sched_start_centroid.as_mjd_utc_seconds(),
                 sched_end_centroid.as_mjd_utc_seconds(),
@@ -1605,9 +1946,124 @@ impl MeasurementSetWriter {
             )?;
         }
 
+        // ///// //
+        // Beams //
+        // ///// //
+
+        // Each analogue beam of a multi-beam observation gets its own field,
+        // after the observation's; see `select_beam`.
+        for beam in &obs_ctx.beams {
+            let name = match &beam.name {
+                Some(name) => name.clone(),
+                None => format!("beam {}", beam.id),
+            };
+            self.add_field(&name, beam.phase_centre)?;
+        }
+
+        Ok(())
+    }
+
+    /// Add another field to an initialized measurement set, e.g. for one
+    /// pointing of a mosaic, and return its `FIELD_ID`. The new `FIELD` row and
+    /// `SOURCE` row have the same times as those written by
+    /// [`MeasurementSetWriter::initialize`].
+    ///
+    /// Visibilities are only written to the new field once it is selected with
+    /// [`MeasurementSetWriter::select_field`].
+    pub fn add_field(
+        &self,
+        name: &str,
+        phase_centre: RADec,
+    ) -> Result<usize, MeasurementSetWriteError> {
+        let mut field_table = Table::open(&self.path.join("FIELD"), TableOpenMode::ReadWrite)?;
+        let field_id = field_table.n_rows();
+        let field_time: f64 = field_table.get_cell("TIME", 0)?;
+        let dir_info = array![
+            [[phase_centre.ra, phase_centre.dec]],
+            [[phase_centre.ra, phase_centre.dec]],
+            [[phase_centre.ra, phase_centre.dec]],
+        ];
+        field_table.add_rows(1)?;
+        self.write_field_row(
+            &mut field_table,
+            field_id,
+            name,
+            "",
+            field_time,
+            &dir_info,
+            field_id as _,
+            false,
+        )?;
+
+        let mut source_table = Table::open(&self.path.join("SOURCE"), TableOpenMode::ReadWrite)?;
+        let source_idx = source_table.n_rows();
+        let source_time: f64 = source_table.get_cell("TIME", 0)?;
+        let source_interval: f64 = source_table.get_cell("INTERVAL", 0)?;
+        source_table.add_rows(1)?;
+        self.write_source_row(
+            &mut source_table,
+            source_idx,
+            field_id as _,
+            source_time,
+            source_interval,
+            0,
+            0,
+            name,
+            0,
+            "",
+            phase_centre,
+            &[0., 0.],
+        )?;
+
+        Ok(field_id as _)
+    }
+
+    /// Select the field that subsequent visibilities are written to. Their
+    /// UVWs are computed towards the field's `PHASE_DIR`, and the main table's
+    /// `FIELD_ID` column is set to `field_id`. The visibilities themselves are
+    /// written as given, so they must already be phased to the field.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MeasurementSetWriteError::BadFieldId`] if the `FIELD` table
+    /// doesn't have a row for `field_id`.
+    pub fn select_field(&mut self, field_id: usize) -> Result<(), MeasurementSetWriteError> {
+        let mut field_table = Table::open(&self.path.join("FIELD"), TableOpenMode::Read)?;
+        let num_fields = field_table.n_rows() as usize;
+        if field_id >= num_fields {
+            return Err(MeasurementSetWriteError::BadFieldId {
+                field_id,
+                num_fields,
+            });
+        }
+        let phase_dir: Vec<f64> = field_table.get_cell_as_vec("PHASE_DIR", field_id as _)?;
+        self.phase_centre = RADec::new(phase_dir[0], phase_dir[1]);
+        self.field_id = field_id as _;
         Ok(())
     }
 
+    /// Select the field of the analogue beam `beam_id` of `obs_ctx`, the
+    /// context given to [`MeasurementSetWriter::initialize`], so that
+    /// subsequent visibilities are written to that beam's field. See
+    /// [`MeasurementSetWriter::select_field`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MeasurementSetWriteError::BadBeamId`] if `obs_ctx` has no
+    /// beam with the identifier `beam_id`.
+    pub fn select_beam(
+        &mut self,
+        obs_ctx: &ObsContext,
+        beam_id: u32,
+    ) -> Result<(), MeasurementSetWriteError> {
+        let beam_idx = obs_ctx
+            .beams
+            .iter()
+            .position(|beam| beam.id == beam_id)
+            .ok_or(MeasurementSetWriteError::BadBeamId { beam_id })?;
+        self.select_field(beam_idx + 1)
+    }
+
     /// Write a row into the main table.
     ///
     /// The main table holds measurements from a Telescope
@@ -1660,7 +2116,7 @@ impl MeasurementSetWriter {
         weights: &Array2<f32>,
         flag_row: bool,
     ) -> Result<(), MeasurementSetWriteError> {
-        let num_pols = 4;
+        let num_pols = self.pol_frame.num_pols();
 
         if uvw.len() != 3 {
             return Err(MeasurementSetWriteError::BadArrayShape(BadArrayShape {
@@ -1714,6 +2170,7 @@ impl MeasurementSetWriter {
         table.put_cell("ANTENNA1", idx, &antenna1)?;
         table.put_cell("ANTENNA2", idx, &antenna2)?;
         table.put_cell("DATA_DESC_ID", idx, &data_desc_id)?;
+        table.put_cell("FIELD_ID", idx, &self.field_id)?;
         table.put_cell("UVW", idx, uvw)?;
         table.put_cell("INTERVAL", idx, &interval)?;
         // TODO: really?
@@ -1732,35 +2189,95 @@ impl MeasurementSetWriter {
     }
 }
 
-impl VisWrite for MeasurementSetWriter {
-    fn write_vis(
+impl MeasurementSetWriter {
+    /// Add a data column with the same shape as `DATA`, e.g. `MODEL_DATA` or
+    /// `CORRECTED_DATA`, to an initialized measurement set. The column can be
+    /// written to with [`MeasurementSetWriter::write_vis_columns`].
+    pub fn add_data_column(
+        &self,
+        col_name: &str,
+        num_channels: usize,
+    ) -> Result<(), MeasurementSetWriteError> {
+        let comment = format!("added by {} {}", PKG_VERSION, PKG_NAME);
+        let mut main_table = Table::open(&self.path, TableOpenMode::ReadWrite)?;
+        let data_shape = [num_channels as _, self.pol_frame.num_pols() as _];
+        main_table.add_array_column(
+            GlueDataType::TpComplex,
+            col_name,
+            Some(comment.as_str()),
+            Some(&data_shape),
+            false,
+            false,
+        )?;
+        Ok(())
+    }
+
+    /// Write a cell of a data column added by
+    /// [`MeasurementSetWriter::add_data_column`].
+    fn write_data_cell(
+        &self,
+        table: &mut Table,
+        idx: u64,
+        col_name: &str,
+        data: &Array2<c32>,
+    ) -> Result<(), MeasurementSetWriteError> {
+        table.put_cell(col_name, idx, data)?;
+        Ok(())
+    }
+
+    /// Write a chunk of visibilities like [`VisWrite::write_vis`], and in the
+    /// same pass, write other visibilities into extra data columns.
+    ///
+    /// `extra_vis` - the name of each extra column (e.g. `MODEL_DATA` or
+    ///     `CORRECTED_DATA`, which must have been added with
+    ///     [`MeasurementSetWriter::add_data_column`]) and its visibilities, with
+    ///     the same dimensions as `vis`. These are averaged with `weights`.
+    pub fn write_vis_columns(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        extra_vis: &[(&str, ArrayView3<Jones<f32>>)],
+        weights: ArrayView3<f32>,
+        vis_ctx: &VisContext,
+        draw_progress: bool,
+    ) -> Result<(), IOError> {
+        vis_ctx.check_sel_dims(
+            vis.dim(),
+            weights.dim(),
+            "MeasurementSetWriter::write_vis_columns",
+        )?;
+        self.write_vis_rows(vis, extra_vis, weights, None, vis_ctx, draw_progress)
+    }
+
+    /// The implementation of [`MeasurementSetWriter::write_vis_columns`],
+    /// which also writes `FLAG_CATEGORY` from `flag_layers`, if given. The
+    /// dimensions of `vis` and `weights` must already have been checked.
+    fn write_vis_rows(
         &mut self,
         vis: ArrayView3<Jones<f32>>,
+        extra_vis: &[(&str, ArrayView3<Jones<f32>>)],
         weights: ArrayView3<f32>,
+        flag_layers: Option<&FlagLayers>,
         vis_ctx: &VisContext,
         draw_progress: bool,
     ) -> Result<(), IOError> {
         let sel_dims = vis_ctx.sel_dims();
-        if vis.dim() != sel_dims {
-            return Err(IOError::BadArrayShape(BadArrayShape {
-                argument: "vis",
-                function: "write_vis_marlu",
-                expected: format!("{:?}", sel_dims),
-                received: format!("{:?}", vis.dim()),
-            }));
-        }
-        if weights.dim() != sel_dims {
-            return Err(IOError::BadArrayShape(BadArrayShape {
-                argument: "weights",
-                function: "write_vis_marlu",
-                expected: format!("{:?}", sel_dims),
-                received: format!("{:?}", weights.dim()),
-            }));
+        for (_, extra) in extra_vis {
+            if extra.dim() != sel_dims {
+                return Err(IOError::BadArrayShape(BadArrayShape {
+                    argument: "extra_vis",
+                    function: "MeasurementSetWriter::write_vis_columns",
+                    expected: format!("{:?}", sel_dims),
+                    received: format!("{:?}", extra.dim()),
+                }));
+            }
         }
 
         let num_avg_timesteps = vis_ctx.num_avg_timesteps();
         let num_avg_chans = vis_ctx.num_avg_chans();
-        let num_vis_pols = vis_ctx.num_vis_pols;
+        let pol_frame = self.pol_frame;
+        let num_vis_pols = pol_frame.num_pols();
+        let weight_factor = pol_frame.weight_factor();
+        let vis_scale = vis_ctx.vis_scale;
         let num_avg_rows = num_avg_timesteps * vis_ctx.sel_baselines.len();
 
         // Progress bars
@@ -1793,39 +2310,59 @@ impl VisWrite for MeasurementSetWriter {
         }
 
         let mut uvw_tmp = vec![0.; 3];
-        let sigma_tmp = vec![1.; 4];
+        let mut sigma_tmp = vec![1.; num_vis_pols];
+        let mut sigma_spectrum_tmp = Array2::zeros((num_avg_chans, num_vis_pols));
         let mut data_tmp = Array2::zeros((num_avg_chans, num_vis_pols));
         let mut weights_tmp = Array2::zeros((num_avg_chans, num_vis_pols));
         let mut flags_tmp = Array2::from_elem((num_avg_chans, num_vis_pols), false);
+        let mut extra_tmp = Array2::zeros((num_avg_chans, num_vis_pols));
+        // The weights and flags of the extra columns are the same as those of
+        // `DATA`, but averaging needs somewhere to put them.
+        let mut extra_weights_tmp = Array1::<f32>::zeros(num_avg_chans);
+        let mut extra_flags_tmp = Array1::from_elem(num_avg_chans, false);
+        let layers: Vec<ArrayView3<bool>> = flag_layers
+            .map(|flag_layers| flag_layers.iter().map(|(_, layer)| layer).collect())
+            .unwrap_or_default();
+        let mut category_tmp =
+            Array3::from_elem((layers.len(), num_avg_chans, num_vis_pols), false);
         let mut avg_weight: f32;
         let mut avg_flag: bool;
+        let mut avg_jones: Jones<f32>;
 
-        for (avg_centroid_timestamp, vis_chunk, weight_chunk) in izip!(
+        for (timestep_idx, (avg_centroid_timestamp, vis_chunk, weight_chunk)) in izip!(
             vis_ctx.timeseries(true, true),
             vis.axis_chunks_iter(Axis(0), vis_ctx.avg_time),
             weights.axis_chunks_iter(Axis(0), vis_ctx.avg_time),
-        ) {
+        )
+        .enumerate()
+        {
+            let timestep_range = timestep_idx * vis_ctx.avg_time
+                ..((timestep_idx + 1) * vis_ctx.avg_time).min(vis_ctx.num_sel_timesteps);
             let scan_centroid_mjd_utc_s = avg_centroid_timestamp.as_mjd_utc_seconds();
 
-            let prec_info = precess_time(
+            let prec_info = precess_time_with_polar_motion(
                 self.array_pos.longitude_rad,
                 self.array_pos.latitude_rad,
                 self.phase_centre,
                 avg_centroid_timestamp,
                 self.dut1,
+                self.polar_motion,
             );
 
-            let tiles_xyz_precessed = prec_info.precess_xyz_parallel(&self.antenna_positions);
+            let uvws = prec_info.baseline_uvws(
+                self.uvw_frame,
+                &self.antenna_positions,
+                &vis_ctx.sel_baselines,
+            );
 
-            for ((ant1_idx, ant2_idx), vis_chunk, weight_chunk) in izip!(
+            for (baseline_idx, ((ant1_idx, ant2_idx), uvw, vis_chunk, weight_chunk)) in izip!(
                 vis_ctx.sel_baselines.iter(),
+                uvws,
                 vis_chunk.axis_iter(Axis(2)),
                 weight_chunk.axis_iter(Axis(2)),
-            ) {
-                let baseline_xyz_precessed =
-                    tiles_xyz_precessed[*ant1_idx] - tiles_xyz_precessed[*ant2_idx];
-                let uvw = UVW::from_xyz(baseline_xyz_precessed, prec_info.hadec_j2000);
-
+            )
+            .enumerate()
+            {
                 // copy values into temporary arrays to avoid heap allocs.
                 uvw_tmp.clone_from_slice(&[uvw.u, uvw.v, uvw.w]);
 
@@ -1848,26 +2385,43 @@ impl VisWrite for MeasurementSetWriter {
                     weights_tmp.outer_iter_mut(),
                     flags_tmp.outer_iter_mut()
                 ) {
-                    avg_weight = weight_chunk[[0, 0]];
-                    avg_flag = avg_weight < 0.;
-                    if vis_ctx.trivial_averaging() {
-                        data_tmp_view.assign(&ArrayView::from(vis_chunk[[0, 0]].as_slice()));
-                    } else {
+                    WeightFlags {
+                        weight: avg_weight,
+                        flag: avg_flag,
+                    } = WeightFlags::from_signed(weight_chunk[[0, 0]]);
+                    avg_jones = vis_chunk[[0, 0]];
+                    if !vis_ctx.trivial_averaging() {
                         average_chunk_f64!(
                             vis_chunk,
                             weight_chunk,
-                            data_tmp_view,
+                            avg_jones,
                             avg_weight,
                             avg_flag
                         );
                     }
-                    if avg_flag {
-                        avg_weight = avg_weight.abs();
+                    if avg_flag && vis_ctx.averaging_policy == AveragingPolicy::Nan {
+                        avg_jones = Jones::nan();
+                    }
+                    avg_jones = pol_frame.convert(avg_jones * vis_scale);
+                    for (data, &pol_idx) in data_tmp_view.iter_mut().zip(pol_frame.ms_pol_idxs()) {
+                        *data = avg_jones[pol_idx];
                     }
-                    weights_tmp_view.fill(avg_weight);
+                    weights_tmp_view.fill(avg_weight * weight_factor);
                     flags_tmp_view.fill(avg_flag);
                 }
 
+                // Flagged weights have already been made positive.
+                if self.sigma_policy == SigmaPolicy::FromWeights {
+                    for (sigma, weights_pol) in
+                        sigma_tmp.iter_mut().zip(weights_tmp.axis_iter(Axis(1)))
+                    {
+                        *sigma = weight_to_sigma(weights_pol.sum());
+                    }
+                    sigma_spectrum_tmp.zip_mut_with(&weights_tmp, |sigma, &weight| {
+                        *sigma = weight_to_sigma(weight);
+                    });
+                }
+
                 let flag_row = flags_tmp.iter().all(|&x| x);
                 self.write_main_row(
                     &mut main_table,
@@ -1888,6 +2442,63 @@ impl VisWrite for MeasurementSetWriter {
                     &weights_tmp,
                     flag_row,
                 )?;
+                if self.sigma_policy == SigmaPolicy::FromWeights {
+                    main_table
+                        .put_cell(
+                            "SIGMA_SPECTRUM",
+                            self.main_row_idx as _,
+                            &sigma_spectrum_tmp,
+                        )
+                        .map_err(MeasurementSetWriteError::from)?;
+                }
+
+                for &(col_name, extra) in extra_vis {
+                    let extra_chunk = extra.slice(s![timestep_range.clone(), .., baseline_idx]);
+                    for (vis_chunk, weight_chunk, mut extra_view, weight, flag) in izip!(
+                        extra_chunk.axis_chunks_iter(Axis(1), vis_ctx.avg_freq),
+                        weight_chunk.axis_chunks_iter(Axis(1), vis_ctx.avg_freq),
+                        extra_tmp.outer_iter_mut(),
+                        extra_weights_tmp.iter_mut(),
+                        extra_flags_tmp.iter_mut(),
+                    ) {
+                        avg_jones = vis_chunk[[0, 0]];
+                        if !vis_ctx.trivial_averaging() {
+                            average_chunk_f64!(vis_chunk, weight_chunk, avg_jones, *weight, *flag);
+                        }
+                        avg_jones = pol_frame.convert(avg_jones * vis_scale);
+                        for (data, &pol_idx) in extra_view.iter_mut().zip(pol_frame.ms_pol_idxs()) {
+                            *data = avg_jones[pol_idx];
+                        }
+                    }
+                    self.write_data_cell(
+                        &mut main_table,
+                        self.main_row_idx as _,
+                        col_name,
+                        &extra_tmp,
+                    )?;
+                }
+
+                // An averaged visibility is flagged in a category if it is
+                // flagged, and any of its visibilities are flagged in that
+                // category's layer, so no category is set without `FLAG`. The
+                // reverse doesn't hold: negative weights set `FLAG` without
+                // flagging any category.
+                if !layers.is_empty() {
+                    for (layer, mut category_view) in izip!(&layers, category_tmp.outer_iter_mut())
+                    {
+                        let layer_chunk = layer.slice(s![timestep_range.clone(), .., baseline_idx]);
+                        for (layer_chunk, mut category_flags, &flag) in izip!(
+                            layer_chunk.axis_chunks_iter(Axis(1), vis_ctx.avg_freq),
+                            category_view.outer_iter_mut(),
+                            flags_tmp.column(0),
+                        ) {
+                            category_flags.fill(flag && layer_chunk.iter().any(|&flag| flag));
+                        }
+                    }
+                    main_table
+                        .put_cell("FLAG_CATEGORY", self.main_row_idx as _, &category_tmp)
+                        .map_err(MeasurementSetWriteError::from)?;
+                }
 
                 self.main_row_idx += 1;
 
@@ -1898,12 +2509,345 @@ impl VisWrite for MeasurementSetWriter {
         Ok(())
     }
 
-    fn finalise(&mut self) -> Result<(), IOError> {
-        Ok(())
-    }
-}
-
-#[cfg(test)]
+    /// Write a chunk of visibilities like [`VisWrite::write_vis`], but take the
+    /// `FLAG` column from `flags` instead of the signs of `weights`. Only the
+    /// magnitudes of `weights` are written, so a visibility can be flagged
+    /// while its weight is positive, or unflagged while its weight is negative.
+    /// Flagged visibilities are excluded from averaging.
+    ///
+    /// `flags` - a three dimensional array of flags, with the same dimensions
+    ///     as `vis`.
+    pub fn write_vis_with_flags(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        flags: ArrayView3<bool>,
+        vis_ctx: &VisContext,
+        draw_progress: bool,
+    ) -> Result<(), IOError> {
+        vis_ctx.check_sel_dims(
+            vis.dim(),
+            weights.dim(),
+            "MeasurementSetWriter::write_vis_with_flags",
+        )?;
+        let weights = flags_to_negative_weights(weights, flags)?;
+        self.write_vis_columns(vis, &[], weights.view(), vis_ctx, draw_progress)
+    }
+
+    /// Write a chunk of visibilities like
+    /// [`MeasurementSetWriter::write_vis_with_flags`], taking the `FLAG` column
+    /// from the [`FlagLayers::combined`] flags of `flag_layers`, and writing
+    /// each layer into `FLAG_CATEGORY`, so that categories of flags can be
+    /// undone later. The names of the layers must match those given to
+    /// [`MeasurementSetWriter::set_flag_categories`], in the same order.
+    ///
+    /// When averaging, a category is flagged for an averaged visibility if the
+    /// averaged visibility is flagged, and any of the visibilities averaged
+    /// into it are flagged in that category.
+    pub fn write_vis_with_flag_layers(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        flag_layers: &FlagLayers,
+        vis_ctx: &VisContext,
+        draw_progress: bool,
+    ) -> Result<(), IOError> {
+        vis_ctx.check_sel_dims(
+            vis.dim(),
+            weights.dim(),
+            "MeasurementSetWriter::write_vis_with_flag_layers",
+        )?;
+        if flag_layers.names() != self.flag_categories {
+            return Err(IOError::MeasurementSetWriteError(BadFlagCategories {
+                expected: self.flag_categories.clone(),
+                received: flag_layers
+                    .names()
+                    .into_iter()
+                    .map(ToString::to_string)
+                    .collect(),
+            }));
+        }
+        let weights = flags_to_negative_weights(weights, flag_layers.combined().view())?;
+        self.write_vis_rows(
+            vis,
+            &[],
+            weights.view(),
+            Some(flag_layers),
+            vis_ctx,
+            draw_progress,
+        )
+    }
+}
+
+impl VisWrite for MeasurementSetWriter {
+    fn write_vis(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        vis_ctx: &VisContext,
+        draw_progress: bool,
+    ) -> Result<(), IOError> {
+        self.write_vis_columns(vis, &[], weights, vis_ctx, draw_progress)
+    }
+
+    fn finalise(&mut self) -> Result<(), IOError> {
+        Ok(())
+    }
+}
+
+/// A [`VisRead`] source of the `DATA` column of a measurement set, e.g. one
+/// written by [`MeasurementSetWriter`]. Only measurement sets with a single
+/// spectral window, XX, XY, YX and YY polarisations, and the same baselines in
+/// the same order at every timestep, are supported.
+///
+/// The weight of each visibility is from `WEIGHT_SPECTRUM` (or `WEIGHT`, divided
+/// by the number of channels, if it isn't present) of its first polarisation,
+/// and is negative if any of its polarisations are flagged. The units of the
+/// visibilities are from the `UNIT` keyword of the `DATA` column.
+pub struct MeasurementSetReader {
+    path: PathBuf,
+    vis_ctx: VisContext,
+    has_weight_spectrum: bool,
+}
+
+impl MeasurementSetReader {
+    /// Open a measurement set for reading. The `TIME`, `ANTENNA1` and
+    /// `ANTENNA2` columns are read to determine the times and baselines of
+    /// the visibilities.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the measurement set can't be read, or isn't laid out
+    /// in a way that this reader supports.
+    pub fn new<T: AsRef<Path>>(path: T) -> Result<Self, IOError> {
+        let path = path.as_ref().to_path_buf();
+        let unsupported = |reason: &str| IOError::UnsupportedInput {
+            path: path.display().to_string(),
+            reason: reason.to_string(),
+        };
+
+        let mut pol_table = Table::open(path.join("POLARIZATION"), TableOpenMode::Read)?;
+        if pol_table.n_rows() != 1 {
+            return Err(unsupported("only one polarisation setup can be read"));
+        }
+        let corr_types: Vec<i32> = pol_table.get_cell_as_vec("CORR_TYPE", 0)?;
+        if corr_types != [9, 10, 11, 12] {
+            return Err(unsupported(
+                "only XX, XY, YX and YY polarisations can be read",
+            ));
+        }
+
+        let mut spw_table = Table::open(path.join("SPECTRAL_WINDOW"), TableOpenMode::Read)?;
+        if spw_table.n_rows() != 1 {
+            return Err(unsupported("only one spectral window can be read"));
+        }
+        let chan_freqs: Vec<f64> = spw_table.get_cell_as_vec("CHAN_FREQ", 0)?;
+        let chan_widths: Vec<f64> = spw_table.get_cell_as_vec("CHAN_WIDTH", 0)?;
+        let (start_freq_hz, freq_resolution_hz) = match (chan_freqs.first(), chan_widths.first()) {
+            (Some(&freq), Some(&width)) => (freq, width),
+            _ => return Err(unsupported("the spectral window has no channels")),
+        };
+
+        let mut main_table = Table::open(&path, TableOpenMode::Read)?;
+        let has_weight_spectrum = main_table
+            .column_names()?
+            .iter()
+            .any(|name| name == "WEIGHT_SPECTRUM");
+        let times: Vec<f64> = main_table.get_col_as_vec("TIME")?;
+        let ant1s: Vec<i32> = main_table.get_col_as_vec("ANTENNA1")?;
+        let ant2s: Vec<i32> = main_table.get_col_as_vec("ANTENNA2")?;
+        if times.is_empty() {
+            return Err(unsupported("there are no rows"));
+        }
+        let interval: f64 = main_table.get_cell("INTERVAL", 0)?;
+        let vis_unit = if main_table
+            .column_keyword_names("DATA")?
+            .iter()
+            .any(|name| name == "UNIT")
+        {
+            let unit: String = main_table
+                .get_column_keyword_record("DATA")?
+                .get_field("UNIT")?;
+            if unit.eq_ignore_ascii_case("JY") {
+                VisUnit::Jansky
+            } else {
+                VisUnit::Uncalibrated
+            }
+        } else {
+            VisUnit::Uncalibrated
+        };
+
+        // Rows are grouped by timestep, and every timestep must have the same
+        // baselines.
+        let num_baselines = times.iter().take_while(|&&time| time == times[0]).count();
+        if times.len() % num_baselines != 0 {
+            return Err(unsupported(
+                "timesteps don't all have the same number of rows",
+            ));
+        }
+        let baselines: Vec<(i32, i32)> = ant1s.into_iter().zip(ant2s).collect();
+        for (times, timestep_baselines) in times
+            .chunks_exact(num_baselines)
+            .zip(baselines.chunks_exact(num_baselines))
+        {
+            if times.iter().any(|&time| time != times[0])
+                || timestep_baselines[..] != baselines[..num_baselines]
+            {
+                return Err(unsupported("timesteps don't all have the same baselines"));
+            }
+        }
+
+        // TIME is the centroid of each timestep.
+        let int_time = Duration::from_f64((interval * 1e3).round(), Unit::Millisecond);
+        let first_centroid = Epoch::from_mjd_utc(times[0] / 86400.0);
+        let vis_ctx = VisContext {
+            num_sel_timesteps: times.len() / num_baselines,
+            start_timestamp: first_centroid - 0.5 * int_time,
+            int_time,
+            num_sel_chans: chan_freqs.len(),
+            start_freq_hz,
+            freq_resolution_hz,
+            sel_baselines: baselines[..num_baselines]
+                .iter()
+                .map(|&(ant1, ant2)| (ant1 as usize, ant2 as usize))
+                .collect(),
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit,
+            vis_scale: 1.0,
+        };
+        Ok(Self {
+            path,
+            vis_ctx,
+            has_weight_spectrum,
+        })
+    }
+
+    /// Read the flag layers in the `FLAG_CATEGORY` column, e.g. written by
+    /// [`MeasurementSetWriter::write_vis_with_flag_layers`], for every
+    /// timestep, channel and baseline. A visibility is flagged in a layer if
+    /// any of its polarisations are.
+    ///
+    /// Returns `None` if the column has no `CATEGORY` keyword, i.e. no flag
+    /// categories were written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the measurement set can't be read, e.g. if a row's
+    /// `FLAG_CATEGORY` cell wasn't written.
+    pub fn read_flag_layers(&self) -> Result<Option<FlagLayers>, IOError> {
+        let mut main_table = Table::open(&self.path, TableOpenMode::Read)?;
+        if !main_table
+            .column_keyword_names("FLAG_CATEGORY")?
+            .iter()
+            .any(|name| name == "CATEGORY")
+        {
+            return Ok(None);
+        }
+        let categories: Vec<String> = main_table
+            .get_column_keyword_record("FLAG_CATEGORY")?
+            .get_field("CATEGORY")?;
+
+        let sel_dims = self.vis_ctx.sel_dims();
+        let (_, num_chans, num_baselines) = sel_dims;
+        let num_pols = self.vis_ctx.num_vis_pols;
+        let mut layers = vec![Array3::from_elem(sel_dims, false); categories.len()];
+        for row_idx in 0..main_table.n_rows() {
+            let timestep = row_idx as usize / num_baselines;
+            let baseline_idx = row_idx as usize % num_baselines;
+            // Cells are [category][chan][pol].
+            let cell: Vec<bool> = main_table.get_cell_as_vec("FLAG_CATEGORY", row_idx)?;
+            for (category_flags, layer) in
+                izip!(cell.chunks_exact(num_chans * num_pols), layers.iter_mut())
+            {
+                for (pol_flags, flag) in izip!(
+                    category_flags.chunks_exact(num_pols),
+                    layer.slice_mut(s![timestep, .., baseline_idx]),
+                ) {
+                    *flag = pol_flags.iter().any(|&flag| flag);
+                }
+            }
+        }
+
+        let mut flag_layers = FlagLayers::new(sel_dims);
+        for (category, layer) in izip!(&categories, &layers) {
+            flag_layers.layer_mut(category).assign(layer);
+        }
+        Ok(Some(flag_layers))
+    }
+}
+
+impl VisRead for MeasurementSetReader {
+    fn vis_ctx(&self) -> &VisContext {
+        &self.vis_ctx
+    }
+
+    fn read_vis(
+        &mut self,
+        mut vis: ArrayViewMut3<Jones<f32>>,
+        mut weights: ArrayViewMut3<f32>,
+        chunk_ctx: &VisContext,
+    ) -> Result<(), IOError> {
+        let location = locate_read_chunk(
+            &self.vis_ctx,
+            chunk_ctx,
+            vis.dim(),
+            weights.dim(),
+            "MeasurementSetReader::read_vis",
+        )?;
+        let num_baselines = self.vis_ctx.sel_baselines.len();
+        let num_pols = self.vis_ctx.num_vis_pols;
+
+        let mut main_table = Table::open(&self.path, TableOpenMode::Read)?;
+        for (timestep, mut vis, mut weights) in izip!(
+            location.timesteps.clone(),
+            vis.outer_iter_mut(),
+            weights.outer_iter_mut(),
+        ) {
+            for (&baseline_idx, mut vis, mut weights) in izip!(
+                &location.baseline_idxs,
+                vis.axis_iter_mut(Axis(1)),
+                weights.axis_iter_mut(Axis(1)),
+            ) {
+                let row_idx = (timestep * num_baselines + baseline_idx) as u64;
+                // Cells are [chan][pol].
+                let data: Vec<c32> = main_table.get_cell_as_vec("DATA", row_idx)?;
+                let flags: Vec<bool> = main_table.get_cell_as_vec("FLAG", row_idx)?;
+                let row_weights: Vec<f32> = if self.has_weight_spectrum {
+                    main_table.get_cell_as_vec("WEIGHT_SPECTRUM", row_idx)?
+                } else {
+                    // WEIGHT is the sum of the weights of each polarisation
+                    // over all channels; share it evenly between them.
+                    let num_chans = self.vis_ctx.num_sel_chans;
+                    let weight: Vec<f32> = main_table.get_cell_as_vec("WEIGHT", row_idx)?;
+                    weight
+                        .into_iter()
+                        .map(|weight| weight / num_chans as f32)
+                        .collect::<Vec<_>>()
+                        .repeat(num_chans)
+                };
+
+                let chans = num_pols * location.chans.start..num_pols * location.chans.end;
+                for (data, flags, &row_weight, jones, weight) in izip!(
+                    data[chans.clone()].chunks_exact(num_pols),
+                    flags[chans.clone()].chunks_exact(num_pols),
+                    row_weights[chans].iter().step_by(num_pols),
+                    vis.iter_mut(),
+                    weights.iter_mut(),
+                ) {
+                    *jones = Jones::from([data[0], data[1], data[2], data[3]]);
+                    *weight =
+                        WeightFlags::new(row_weight, flags.iter().any(|&flag| flag)).to_signed();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
 mod tests {
     use std::{
         collections::{BTreeMap, HashSet},
@@ -1913,7 +2857,7 @@ mod tests {
 
     use super::*;
 
-    use approx::abs_diff_eq;
+    use approx::{abs_diff_eq, assert_abs_diff_eq};
     use hifitime::Epoch;
     use itertools::izip;
     use lexical::parse;
@@ -1923,8 +2867,11 @@ mod tests {
 
     use crate::{
         c64,
+        context::tests::{get_obs_ctx, get_vis_ctx},
+        flagging::{FLAG_LAYER_MISSING_DATA, FLAG_LAYER_RFI, FLAG_LAYER_USER},
         ndarray::{s, Array, Array4},
-        Jones, VisSelection, XyzGeocentric, ENH,
+        weights::split_weight_sign,
+        AnalogueBeam, Jones, MountType, VisSelection, VisUnit, XyzGeocentric, ENH,
     };
 
     cfg_if::cfg_if! {
@@ -1948,11 +2895,7 @@ mod tests {
 
     #[cfg(feature = "mwalib")]
     fn encode_flags(weights: ArrayView3<f32>, flags: ArrayView3<bool>) -> Array3<f32> {
-        let mut new_weights = weights.to_owned();
-        for (weight, &flag) in izip!(new_weights.iter_mut(), flags.iter()) {
-            *weight = if flag { -(weight.abs()) } else { weight.abs() };
-        }
-        new_weights
+        flags_to_negative_weights(weights, flags).unwrap()
     }
 
     #[test]
@@ -4565,6 +5508,30 @@ mod tests {
             let slot: Vec<i32> = ant_table.get_cell_as_vec("MWA_SLOT", idx as _).unwrap();
             assert_eq!(slot, exp_slot.to_vec());
         }
+
+        let meta_ctx = &corr_ctx.metafits_context;
+        for (idx, ant) in meta_ctx.antennas.iter().enumerate() {
+            let dipole_delays: Vec<i32> = ant_table
+                .get_cell_as_vec("MWA_DIPOLE_DELAYS", idx as _)
+                .unwrap();
+            let exp_dipole_delays: Vec<i32> = [&ant.rfinput_x, &ant.rfinput_y]
+                .iter()
+                .flat_map(|rf| rf.dipole_delays.iter().map(|&d| d as i32))
+                .collect();
+            assert_eq!(dipole_delays, exp_dipole_delays);
+            let dipole_gains: Vec<f64> = ant_table
+                .get_cell_as_vec("MWA_DIPOLE_GAINS", idx as _)
+                .unwrap();
+            assert_eq!(dipole_gains.len(), 32);
+            let digital_gains: Vec<f64> = ant_table
+                .get_cell_as_vec("MWA_DIGITAL_GAINS", idx as _)
+                .unwrap();
+            assert_eq!(digital_gains.len(), vis_sel.coarse_chan_range.len() * 2);
+            assert_abs_diff_eq!(
+                digital_gains[1],
+                ant.rfinput_y.digital_gains[vis_sel.coarse_chan_range.start]
+            );
+        }
     }
 
     type VisTestData = (
@@ -5375,6 +6342,9 @@ mod tests {
             avg_time: 1,
             avg_freq: 1,
             num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
         };
 
         let obs_ctx = ObsContext {
@@ -5387,6 +6357,8 @@ mod tests {
             phase_centre: RADec::default(),
             pointing_centre: None,
             array_pos: LatLngHeight::default(),
+            telescope_name: "MWA".into(),
+            instrument: "MWA".into(),
             ant_positions_enh: vec![
                 ENH::default(),
                 ENH {
@@ -5396,6 +6368,8 @@ mod tests {
                 },
             ],
             ant_names: vec!["ant0".into(), "ant1".into()],
+            ant_flags: vec![],
+            beams: vec![],
         };
 
         let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
@@ -5477,6 +6451,9 @@ mod tests {
             avg_time: 1,
             avg_freq: 1,
             num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
         };
 
         let obs_ctx = ObsContext {
@@ -5489,6 +6466,8 @@ mod tests {
             phase_centre: RADec::default(),
             pointing_centre: None,
             array_pos: LatLngHeight::default(),
+            telescope_name: "MWA".into(),
+            instrument: "MWA".into(),
             ant_positions_enh: vec![
                 ENH::default(),
                 ENH {
@@ -5498,6 +6477,8 @@ mod tests {
                 },
             ],
             ant_names: vec!["ant0".into(), "ant1".into()],
+            ant_flags: vec![],
+            beams: vec![],
         };
 
         let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
@@ -5522,4 +6503,871 @@ mod tests {
             Err(IOError::MeasurementSetWriteError(MeasurementSetFull { .. }))
         ));
     }
+
+    #[test]
+    #[serial]
+    fn test_write_vis_multiple_fields() {
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+
+        let vis_sel = VisSelection {
+            timestep_range: 0..2,
+            coarse_chan_range: 0..1,
+            baseline_idxs: vec![1],
+        };
+
+        let fine_chans_per_coarse = 2;
+
+        let vis_ctx = VisContext {
+            num_sel_timesteps: vis_sel.timestep_range.len(),
+            start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: vis_sel.coarse_chan_range.len() * fine_chans_per_coarse,
+            start_freq_hz: 192000000.,
+            freq_resolution_hz: 10000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            sched_duration: Duration::from_f64(2., Unit::Second),
+            name: None,
+            field_name: Some("first".into()),
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::new(0., -0.47123889803846897),
+            pointing_centre: None,
+            array_pos: LatLngHeight::new_mwa(),
+            telescope_name: "MWA".into(),
+            instrument: "MWA".into(),
+            ant_positions_enh: vec![
+                ENH::default(),
+                ENH {
+                    e: 10.,
+                    n: 1.,
+                    h: 0.,
+                },
+            ],
+            ant_names: vec!["ant0".into(), "ant1".into()],
+            ant_flags: vec![],
+            beams: vec![],
+        };
+
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::from_total_nanoseconds(0),
+        );
+        // Double the rows so that both fields can be written.
+        let mut double_ctx = vis_ctx.clone();
+        double_ctx.num_sel_timesteps *= 2;
+        ms_writer.initialize(&double_ctx, &obs_ctx, None).unwrap();
+
+        let second_centre = RADec::new(0.5, -0.3);
+        let field_id = ms_writer.add_field("second", second_centre).unwrap();
+        assert_eq!(field_id, 1);
+        assert!(matches!(
+            ms_writer.select_field(2),
+            Err(MeasurementSetWriteError::BadFieldId {
+                field_id: 2,
+                num_fields: 2
+            })
+        ));
+
+        let jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+        let weight_array = vis_sel.allocate_weights(fine_chans_per_coarse).unwrap();
+        ms_writer
+            .write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
+            .unwrap();
+        ms_writer.select_field(field_id).unwrap();
+        ms_writer
+            .write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
+            .unwrap();
+
+        let mut field_table = Table::open(&table_path.join("FIELD"), TableOpenMode::Read).unwrap();
+        assert_eq!(field_table.n_rows(), 2);
+        let phase_dir: Vec<f64> = field_table.get_cell_as_vec("PHASE_DIR", 1).unwrap();
+        assert_eq!(phase_dir, vec![second_centre.ra, second_centre.dec]);
+        let name: String = field_table.get_cell("NAME", 1).unwrap();
+        assert_eq!(name, "second");
+
+        let mut source_table =
+            Table::open(&table_path.join("SOURCE"), TableOpenMode::Read).unwrap();
+        assert_eq!(source_table.n_rows(), 2);
+        let source_id: i32 = source_table.get_cell("SOURCE_ID", 1).unwrap();
+        assert_eq!(source_id, 1);
+
+        let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+        let field_ids: Vec<i32> = main_table.get_col_as_vec("FIELD_ID").unwrap();
+        assert_eq!(field_ids, vec![0, 0, 1, 1]);
+
+        // The second field's rows are phased to its centre, so their UVWs
+        // differ from the first field's.
+        let uvw_first: Vec<f64> = main_table.get_cell_as_vec("UVW", 0).unwrap();
+        let uvw_second: Vec<f64> = main_table.get_cell_as_vec("UVW", 2).unwrap();
+        assert!((uvw_first[2] - uvw_second[2]).abs() > 1e-3);
+    }
+
+    #[test]
+    #[serial]
+    fn test_initialize_beams() {
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+
+        let vis_ctx = get_vis_ctx(2, 2, vec![(0, 1)]);
+
+        let beams = vec![
+            AnalogueBeam {
+                id: 3,
+                name: Some("north".into()),
+                phase_centre: RADec::new(0.1, -0.4),
+                pointing_centre: None,
+            },
+            AnalogueBeam {
+                id: 7,
+                name: None,
+                phase_centre: RADec::new(0.2, -0.5),
+                pointing_centre: None,
+            },
+        ];
+        let obs_ctx = ObsContext {
+            sched_duration: Duration::from_f64(2., Unit::Second),
+            field_name: Some("multibeam".into()),
+            beams: beams.clone(),
+            ..get_obs_ctx(2)
+        };
+
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::from_total_nanoseconds(0),
+        );
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+
+        // The observation's field, then one for each beam.
+        let mut field_table = Table::open(&table_path.join("FIELD"), TableOpenMode::Read).unwrap();
+        assert_eq!(field_table.n_rows(), 3);
+        for (field_id, name, beam) in izip!(1.., ["north", "beam 7"], &beams) {
+            let field_name: String = field_table.get_cell("NAME", field_id).unwrap();
+            assert_eq!(field_name, name);
+            let phase_dir: Vec<f64> = field_table.get_cell_as_vec("PHASE_DIR", field_id).unwrap();
+            assert_eq!(phase_dir, vec![beam.phase_centre.ra, beam.phase_centre.dec]);
+        }
+
+        ms_writer.select_beam(&obs_ctx, 7).unwrap();
+        assert_eq!(ms_writer.field_id, 2);
+        assert_eq!(ms_writer.phase_centre, beams[1].phase_centre);
+        assert!(matches!(
+            ms_writer.select_beam(&obs_ctx, 0),
+            Err(MeasurementSetWriteError::BadBeamId { beam_id: 0 })
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_vis_columns() {
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+
+        let vis_sel = VisSelection {
+            timestep_range: 0..2,
+            coarse_chan_range: 0..1,
+            baseline_idxs: vec![1],
+        };
+
+        let fine_chans_per_coarse = 4;
+
+        let vis_ctx = VisContext {
+            num_sel_timesteps: vis_sel.timestep_range.len(),
+            start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: vis_sel.coarse_chan_range.len() * fine_chans_per_coarse,
+            start_freq_hz: 192000000.,
+            freq_resolution_hz: 10000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 2,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            sched_duration: Duration::from_f64(2., Unit::Second),
+            name: None,
+            field_name: None,
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::default(),
+            pointing_centre: None,
+            array_pos: LatLngHeight::default(),
+            telescope_name: "MWA".into(),
+            instrument: "MWA".into(),
+            ant_positions_enh: vec![
+                ENH::default(),
+                ENH {
+                    e: 0.,
+                    n: 1.,
+                    h: 0.,
+                },
+            ],
+            ant_names: vec!["ant0".into(), "ant1".into()],
+            ant_flags: vec![],
+            beams: vec![],
+        };
+
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::from_total_nanoseconds(0),
+        );
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+        let num_avg_chans = vis_ctx.num_avg_chans();
+        ms_writer
+            .add_data_column("MODEL_DATA", num_avg_chans)
+            .unwrap();
+        ms_writer
+            .add_data_column("CORRECTED_DATA", num_avg_chans)
+            .unwrap();
+
+        let jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+        let weight_array = vis_sel.allocate_weights(fine_chans_per_coarse).unwrap();
+        let model_array =
+            Array3::from_shape_fn(jones_array.dim(), |(_, c, _)| Jones::identity() * c as f32);
+        let corrected_array = Array3::from_elem(jones_array.dim(), Jones::identity() * 2.0);
+
+        // The extra visibilities must have the same shape as the visibilities.
+        let bad_array = Array3::from_elem((1, 4, 1), Jones::identity());
+        assert!(matches!(
+            ms_writer.write_vis_columns(
+                jones_array.view(),
+                &[("MODEL_DATA", bad_array.view())],
+                weight_array.view(),
+                &vis_ctx,
+                false,
+            ),
+            Err(IOError::BadArrayShape { .. })
+        ));
+
+        ms_writer
+            .write_vis_columns(
+                jones_array.view(),
+                &[
+                    ("MODEL_DATA", model_array.view()),
+                    ("CORRECTED_DATA", corrected_array.view()),
+                ],
+                weight_array.view(),
+                &vis_ctx,
+                false,
+            )
+            .unwrap();
+
+        let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+        let model: Vec<c32> = main_table.get_cell_as_vec("MODEL_DATA", 1).unwrap();
+        // Channels 0 and 1 are averaged, then 2 and 3.
+        assert_eq!(model.len(), num_avg_chans * 4);
+        assert_abs_diff_eq!(model[0], c32::new(0.5, 0.));
+        assert_abs_diff_eq!(model[1], c32::new(0., 0.));
+        assert_abs_diff_eq!(model[4], c32::new(2.5, 0.));
+        let corrected: Vec<c32> = main_table.get_cell_as_vec("CORRECTED_DATA", 0).unwrap();
+        assert_abs_diff_eq!(corrected[7], c32::new(2., 0.));
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_vis_pol_frames() {
+        let vis_ctx = get_vis_ctx(1, 2, vec![(0, 1)]);
+        let obs_ctx = get_obs_ctx(2);
+
+        // XX = 3, YY = 1 and XY = YX = 0, i.e. I = 2, Q = 1.
+        let jones = Jones::from([
+            c32::new(3., 0.),
+            c32::new(0., 0.),
+            c32::new(0., 0.),
+            c32::new(1., 0.),
+        ]);
+        let jones_array = Array3::from_elem(vis_ctx.sel_dims(), jones);
+        let weight_array = Array3::from_elem(vis_ctx.sel_dims(), 1.0);
+
+        for (pol_frame, corr_type, expected) in [
+            (
+                PolFrame::Circular,
+                vec![5, 6, 7, 8],
+                vec![
+                    c32::new(2., 0.),
+                    c32::new(1., 0.),
+                    c32::new(1., 0.),
+                    c32::new(2., 0.),
+                ],
+            ),
+            (
+                PolFrame::LinearXXYY,
+                vec![9, 12],
+                vec![c32::new(3., 0.), c32::new(1., 0.)],
+            ),
+            (PolFrame::StokesI, vec![1], vec![c32::new(2., 0.)]),
+        ] {
+            let temp_dir = tempdir().unwrap();
+            let table_path = temp_dir.path().join("test.ms");
+            let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+            let mut ms_writer = MeasurementSetWriter::new(
+                &table_path,
+                obs_ctx.phase_centre,
+                obs_ctx.array_pos,
+                antenna_positions,
+                Duration::from_total_nanoseconds(0),
+            );
+            ms_writer.set_pol_frame(pol_frame);
+            ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+            ms_writer
+                .write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
+                .unwrap();
+
+            let mut pol_table =
+                Table::open(&table_path.join("POLARIZATION"), TableOpenMode::Read).unwrap();
+            let num_corr: i32 = pol_table.get_cell("NUM_CORR", 0).unwrap();
+            assert_eq!(num_corr as usize, corr_type.len());
+            let corr_type_read: Vec<i32> = pol_table.get_cell_as_vec("CORR_TYPE", 0).unwrap();
+            assert_eq!(corr_type_read, corr_type);
+
+            let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+            let data: Vec<c32> = main_table.get_cell_as_vec("DATA", 0).unwrap();
+            assert_eq!(data.len(), vis_ctx.num_avg_chans() * expected.len());
+            for (data, expected) in data.iter().zip(expected.iter().cycle()) {
+                assert_abs_diff_eq!(*data, *expected);
+            }
+            // Stokes I has twice the weight of each linear polarisation.
+            let weights: Vec<f32> = main_table.get_cell_as_vec("WEIGHT_SPECTRUM", 0).unwrap();
+            let expected_weight = if pol_frame == PolFrame::StokesI {
+                2.0
+            } else {
+                1.0
+            };
+            for weight in weights {
+                assert_abs_diff_eq!(weight, expected_weight);
+            }
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_vis_sigma_from_weights() {
+        let vis_ctx = get_vis_ctx(1, 3, vec![(0, 1)]);
+        let obs_ctx = get_obs_ctx(2);
+
+        let jones_array = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        // The second channel is flagged, and the third has no weight.
+        let mut weight_array = Array3::from_elem(vis_ctx.sel_dims(), 4.0);
+        weight_array[(0, 1, 0)] = -16.0;
+        weight_array[(0, 2, 0)] = 0.0;
+
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::from_total_nanoseconds(0),
+        );
+        ms_writer.set_sigma_policy(SigmaPolicy::FromWeights);
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+        ms_writer
+            .write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
+            .unwrap();
+
+        let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+        let weight: Vec<f32> = main_table.get_cell_as_vec("WEIGHT", 0).unwrap();
+        let sigma: Vec<f32> = main_table.get_cell_as_vec("SIGMA", 0).unwrap();
+        assert_eq!(weight.len(), 4);
+        for (&weight, &sigma) in weight.iter().zip(sigma.iter()) {
+            assert_abs_diff_eq!(weight, 20.0);
+            assert_abs_diff_eq!(sigma, 1. / 20_f32.sqrt());
+        }
+
+        // Cells are [chan][pol].
+        let sigma_spectrum: Vec<f32> = main_table.get_cell_as_vec("SIGMA_SPECTRUM", 0).unwrap();
+        assert_eq!(sigma_spectrum.len(), 3 * 4);
+        for (chan_idx, expected) in [0.5, 0.25, -1.0].into_iter().enumerate() {
+            for &sigma in &sigma_spectrum[chan_idx * 4..(chan_idx + 1) * 4] {
+                assert_abs_diff_eq!(sigma, expected);
+            }
+        }
+        let flags: Vec<bool> = main_table.get_cell_as_vec("FLAG", 0).unwrap();
+        assert!(flags[4..8].iter().all(|&f| f));
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_vis_with_flags() {
+        let vis_ctx = get_vis_ctx(1, 4, vec![(0, 1)]);
+        let obs_ctx = get_obs_ctx(2);
+
+        let jones_array = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        // The flags disagree with the signs of the weights in every channel
+        // but the first.
+        let weight_array = array![[[2.0], [3.0], [-4.0], [-0.0]]];
+        let flag_array = array![[[false], [true], [false], [true]]];
+
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::from_total_nanoseconds(0),
+        );
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+
+        let result = ms_writer.write_vis_with_flags(
+            jones_array.view(),
+            weight_array.view(),
+            flag_array.slice(s![.., ..2, ..]),
+            &vis_ctx,
+            false,
+        );
+        assert!(matches!(result, Err(IOError::WeightError(_))));
+
+        ms_writer
+            .write_vis_with_flags(
+                jones_array.view(),
+                weight_array.view(),
+                flag_array.view(),
+                &vis_ctx,
+                false,
+            )
+            .unwrap();
+
+        // Cells are [chan][pol].
+        let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+        let weights: Vec<f32> = main_table.get_cell_as_vec("WEIGHT_SPECTRUM", 0).unwrap();
+        let flags: Vec<bool> = main_table.get_cell_as_vec("FLAG", 0).unwrap();
+        assert_eq!(weights.len(), 4 * 4);
+        for (chan_idx, (expected_weight, expected_flag)) in [2.0, 3.0, 4.0, 0.0]
+            .into_iter()
+            .zip([false, true, false, true])
+            .enumerate()
+        {
+            for pol_idx in chan_idx * 4..(chan_idx + 1) * 4 {
+                assert_abs_diff_eq!(weights[pol_idx], expected_weight);
+                assert_eq!(flags[pol_idx], expected_flag);
+            }
+        }
+        let flag_row: bool = main_table.get_cell("FLAG_ROW", 0).unwrap();
+        assert!(!flag_row);
+
+        // Reading the measurement set gives the flags in the signs of the
+        // weights.
+        let mut reader = MeasurementSetReader::new(&table_path).unwrap();
+        let mut vis = Array3::zeros(vis_ctx.sel_dims());
+        let mut weights = Array3::zeros(vis_ctx.sel_dims());
+        reader
+            .read_vis(vis.view_mut(), weights.view_mut(), &vis_ctx)
+            .unwrap();
+        let (weights, flags) = split_weight_sign(weights.view());
+        assert_abs_diff_eq!(weights, array![[[2.0], [3.0], [4.0], [0.0]]]);
+        assert_eq!(flags, flag_array);
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_vis_with_flag_layers() {
+        let mut vis_ctx = get_vis_ctx(1, 4, vec![(0, 1)]);
+        let obs_ctx = get_obs_ctx(2);
+
+        let jones_array = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        let weight_array = Array3::from_elem(vis_ctx.sel_dims(), 1.0);
+        let categories = [FLAG_LAYER_MISSING_DATA, FLAG_LAYER_RFI, FLAG_LAYER_USER];
+        let mut flag_layers = FlagLayers::new(vis_ctx.sel_dims());
+        flag_layers.layer_mut(FLAG_LAYER_MISSING_DATA)[(0, 0, 0)] = true;
+        flag_layers
+            .layer_mut(FLAG_LAYER_RFI)
+            .slice_mut(s![.., 1..3, ..])
+            .fill(true);
+        flag_layers.layer_mut(FLAG_LAYER_USER)[(0, 2, 0)] = true;
+
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+        let mut ms_writer = MeasurementSetWriter::from_marlu(
+            &table_path,
+            &obs_ctx,
+            Duration::from_total_nanoseconds(0),
+        );
+        ms_writer.set_flag_categories(&categories);
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+
+        // The layers must match the categories.
+        let mut user_layers = FlagLayers::new(vis_ctx.sel_dims());
+        user_layers.layer_mut(FLAG_LAYER_USER);
+        let result = ms_writer.write_vis_with_flag_layers(
+            jones_array.view(),
+            weight_array.view(),
+            &user_layers,
+            &vis_ctx,
+            false,
+        );
+        assert!(matches!(
+            result,
+            Err(IOError::MeasurementSetWriteError(
+                MeasurementSetWriteError::BadFlagCategories { .. }
+            ))
+        ));
+
+        ms_writer
+            .write_vis_with_flag_layers(
+                jones_array.view(),
+                weight_array.view(),
+                &flag_layers,
+                &vis_ctx,
+                false,
+            )
+            .unwrap();
+
+        // With positive weights, FLAG is the union of the layers, and
+        // FLAG_CATEGORY cells are [category][chan][pol].
+        let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+        let flags: Vec<bool> = main_table.get_cell_as_vec("FLAG", 0).unwrap();
+        let expected_flags = [true, true, true, false];
+        for (chan_flags, &expected) in flags.chunks_exact(4).zip(expected_flags.iter()) {
+            assert!(chan_flags.iter().all(|&flag| flag == expected));
+        }
+        let category_flags: Array3<bool> = main_table.get_cell("FLAG_CATEGORY", 0).unwrap();
+        assert_eq!(category_flags.dim(), (3, 4, 4));
+        let category_names: Vec<String> = main_table
+            .get_column_keyword_record("FLAG_CATEGORY")
+            .unwrap()
+            .get_field("CATEGORY")
+            .unwrap();
+        assert_eq!(category_names, categories);
+        drop(main_table);
+
+        let reader = MeasurementSetReader::new(&table_path).unwrap();
+        assert_eq!(
+            reader.read_flag_layers().unwrap().as_ref(),
+            Some(&flag_layers)
+        );
+
+        // When averaging, a category is only flagged for flagged averaged
+        // visibilities.
+        vis_ctx.avg_freq = 2;
+        let table_path = temp_dir.path().join("test_avg.ms");
+        let mut ms_writer = MeasurementSetWriter::from_marlu(
+            &table_path,
+            &obs_ctx,
+            Duration::from_total_nanoseconds(0),
+        );
+        ms_writer.set_flag_categories(&categories);
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+        ms_writer
+            .write_vis_with_flag_layers(
+                jones_array.view(),
+                weight_array.view(),
+                &flag_layers,
+                &vis_ctx,
+                false,
+            )
+            .unwrap();
+        let read_layers = MeasurementSetReader::new(&table_path)
+            .unwrap()
+            .read_flag_layers()
+            .unwrap()
+            .unwrap();
+        assert_eq!(read_layers.names(), categories);
+        assert_eq!(
+            read_layers.layer(FLAG_LAYER_MISSING_DATA).unwrap(),
+            array![[[true], [false]]]
+        );
+        assert_eq!(
+            read_layers.layer(FLAG_LAYER_RFI).unwrap(),
+            array![[[true], [false]]]
+        );
+        assert_eq!(
+            read_layers.layer(FLAG_LAYER_USER).unwrap(),
+            array![[[false], [false]]]
+        );
+
+        // Without categories, there are no layers to read.
+        let table_path = temp_dir.path().join("test_no_categories.ms");
+        let mut ms_writer = MeasurementSetWriter::from_marlu(
+            &table_path,
+            &obs_ctx,
+            Duration::from_total_nanoseconds(0),
+        );
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+        ms_writer
+            .write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
+            .unwrap();
+        let reader = MeasurementSetReader::new(&table_path).unwrap();
+        assert!(reader.read_flag_layers().unwrap().is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_provenance() {
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+
+        let vis_ctx = get_vis_ctx(1, 2, vec![(0, 1)]);
+        let obs_ctx = get_obs_ctx(2);
+
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::from_total_nanoseconds(0),
+        );
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+
+        let mut provenance = Provenance::new("birli");
+        provenance.cmd_line = Some("birli -m obs.metafits".into());
+        provenance
+            .software_versions
+            .push(("mwalib".into(), "0.15.0".into()));
+        provenance.parameters.push(("avg_time".into(), "2".into()));
+        ms_writer.write_provenance(&provenance).unwrap();
+
+        let mut hist_table = Table::open(&table_path.join("HISTORY"), TableOpenMode::Read).unwrap();
+        let idx = hist_table.n_rows() - 1;
+        let application: String = hist_table.get_cell("APPLICATION", idx).unwrap();
+        assert_eq!(application, "birli");
+        let message: String = hist_table.get_cell("MESSAGE", idx).unwrap();
+        assert_eq!(message, provenance.software_message());
+        let app_params: Vec<String> = hist_table.get_cell_as_vec("APP_PARAMS", idx).unwrap();
+        assert_eq!(app_params, vec!["avg_time=2".to_string()]);
+    }
+
+    #[test]
+    fn test_estimate() {
+        let vis_ctx = VisContext {
+            avg_time: 2,
+            avg_freq: 4,
+            ..get_vis_ctx(4, 32, vec![(0, 1), (0, 2), (1, 2)])
+        };
+        let mut ms_writer = MeasurementSetWriter::new(
+            "unused.ms",
+            RADec::default(),
+            LatLngHeight::new_mwa(),
+            vec![XyzGeodetic::default(); 3],
+            Duration::from_total_nanoseconds(0),
+        );
+        let estimate = ms_writer.estimate(&vis_ctx, 2);
+        assert_eq!(estimate.num_rows, 2 * 3);
+        assert!(!Path::new("unused.ms").exists());
+
+        // Stokes I has a quarter of the data, and sigmas from weights add a
+        // column.
+        ms_writer.set_pol_frame(PolFrame::StokesI);
+        assert!(ms_writer.estimate(&vis_ctx, 2).bytes < estimate.bytes);
+        ms_writer.set_pol_frame(PolFrame::Linear);
+        ms_writer.set_sigma_policy(SigmaPolicy::FromWeights);
+        assert!(ms_writer.estimate(&vis_ctx, 2).bytes > estimate.bytes);
+    }
+
+    #[test]
+    #[serial]
+    fn test_initialize_flags_antennas() {
+        let vis_ctx = get_vis_ctx(1, 2, vec![(0, 1), (0, 2), (1, 2)]);
+        // The last antenna has no flag, so it's unflagged.
+        let obs_ctx = ObsContext {
+            ant_flags: vec![false, true],
+            ..get_obs_ctx(3)
+        };
+
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+        let ms_writer = MeasurementSetWriter::from_marlu(
+            &table_path,
+            &obs_ctx,
+            Duration::from_total_nanoseconds(0),
+        );
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+
+        let mut ant_table = Table::open(&table_path.join("ANTENNA"), TableOpenMode::Read).unwrap();
+        let flag_row: Vec<bool> = ant_table.get_col_as_vec("FLAG_ROW").unwrap();
+        assert_eq!(flag_row, vec![false, true, false]);
+    }
+
+    #[test]
+    #[serial]
+    fn test_initialize_telescope_antennas() {
+        let vis_ctx = get_vis_ctx(1, 2, vec![(0, 1), (0, 2), (1, 2)]);
+        let obs_ctx = ObsContext {
+            telescope_name: "mixed".into(),
+            instrument: "mixed".into(),
+            ..get_obs_ctx(3)
+        };
+
+        // The last antenna has the defaults.
+        let telescope = Telescope {
+            ant_mounts: vec![MountType::AltAz, MountType::Equatorial],
+            ant_dish_diameters_m: vec![12.0, 25.0],
+            ant_axis_offsets_m: vec![0.0, 1.5],
+            ..obs_ctx.telescope()
+        };
+
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+        let mut ms_writer = MeasurementSetWriter::from_marlu(
+            &table_path,
+            &obs_ctx,
+            Duration::from_total_nanoseconds(0),
+        );
+        ms_writer.set_telescope(telescope);
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+
+        let mut ant_table = Table::open(&table_path.join("ANTENNA"), TableOpenMode::Read).unwrap();
+        let mounts: Vec<String> = (0..3)
+            .map(|idx| ant_table.get_cell("MOUNT", idx).unwrap())
+            .collect();
+        assert_eq!(mounts, ["ALT-AZ", "EQUATORIAL", "ALT-AZ"]);
+        let dish_diameters: Vec<f64> = ant_table.get_col_as_vec("DISH_DIAMETER").unwrap();
+        assert_abs_diff_eq!(dish_diameters[..], [12.0, 25.0, 4.0][..]);
+        let offset: Vec<f64> = ant_table.get_cell_as_vec("OFFSET", 1).unwrap();
+        assert_abs_diff_eq!(offset[..], [1.5, 0.0, 0.0][..]);
+
+        // The telescope can't describe more antennas than the observation has.
+        let table_path = temp_dir.path().join("test_mismatch.ms");
+        let mut ms_writer = MeasurementSetWriter::from_marlu(
+            &table_path,
+            &obs_ctx,
+            Duration::from_total_nanoseconds(0),
+        );
+        ms_writer.set_telescope(Telescope {
+            ant_mounts: vec![MountType::AltAz; 4],
+            ..obs_ctx.telescope()
+        });
+        assert!(matches!(
+            ms_writer.initialize(&vis_ctx, &obs_ctx, None),
+            Err(MeasurementSetWriteError::TelescopeAntsMismatch {
+                telescope_ants: 4,
+                obs_ants: 3
+            })
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn test_read_vis_round_trip() {
+        let vis_ctx = VisContext {
+            int_time: Duration::from_f64(2., Unit::Second),
+            vis_unit: VisUnit::Jansky,
+            ..get_vis_ctx(3, 4, vec![(0, 1), (0, 2), (1, 2)])
+        };
+        let obs_ctx = ObsContext {
+            sched_duration: Duration::from_f64(6., Unit::Second),
+            ..get_obs_ctx(3)
+        };
+
+        let jones_array = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| {
+            Jones::identity() * (t * 100 + c * 10 + b) as f32
+        });
+        let mut weight_array = Array3::from_elem(vis_ctx.sel_dims(), 2.0);
+        weight_array[(1, 2, 0)] = -2.0;
+
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::from_total_nanoseconds(0),
+        );
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+        ms_writer
+            .write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
+            .unwrap();
+
+        let mut reader = MeasurementSetReader::new(&table_path).unwrap();
+        let read_ctx = reader.vis_ctx().clone();
+        assert_eq!(read_ctx.sel_dims(), vis_ctx.sel_dims());
+        assert_eq!(read_ctx.sel_baselines, vis_ctx.sel_baselines);
+        assert_eq!(read_ctx.int_time, vis_ctx.int_time);
+        assert_eq!(read_ctx.vis_unit, VisUnit::Jansky);
+        assert_abs_diff_eq!(read_ctx.start_freq_hz, vis_ctx.start_freq_hz);
+        assert_abs_diff_eq!(
+            (read_ctx.start_timestamp - vis_ctx.start_timestamp).in_seconds(),
+            0.0,
+            epsilon = 1e-5
+        );
+
+        // The last two timesteps and middle two channels of the last two
+        // baselines, in reverse.
+        let chunk_ctx = VisContext {
+            num_sel_timesteps: 2,
+            start_timestamp: read_ctx.start_timestamp + read_ctx.int_time,
+            num_sel_chans: 2,
+            start_freq_hz: read_ctx.start_freq_hz + read_ctx.freq_resolution_hz,
+            sel_baselines: vec![(1, 2), (0, 2)],
+            ..read_ctx
+        };
+        let mut vis = Array3::zeros(chunk_ctx.sel_dims());
+        let mut weights = Array3::zeros(chunk_ctx.sel_dims());
+        reader
+            .read_vis(vis.view_mut(), weights.view_mut(), &chunk_ctx)
+            .unwrap();
+        let mut expected_vis = jones_array.slice(s![1.., 1..3, 1..]);
+        expected_vis.invert_axis(Axis(2));
+        assert_abs_diff_eq!(vis, expected_vis);
+        let mut expected_weights = weight_array.slice(s![1.., 1..3, 1..]);
+        expected_weights.invert_axis(Axis(2));
+        assert_abs_diff_eq!(weights, expected_weights);
+
+        // The flagged visibility keeps its flag.
+        let chunk_ctx = VisContext {
+            sel_baselines: vec![(0, 1)],
+            ..reader.vis_ctx().clone()
+        };
+        let mut vis = Array3::zeros(chunk_ctx.sel_dims());
+        let mut weights = Array3::zeros(chunk_ctx.sel_dims());
+        reader
+            .read_vis(vis.view_mut(), weights.view_mut(), &chunk_ctx)
+            .unwrap();
+        assert_abs_diff_eq!(weights[(1, 2, 0)], -2.0);
+        assert_abs_diff_eq!(vis, jones_array.slice(s![.., .., 0..1]));
+
+        // Without WEIGHT_SPECTRUM, the weights are from WEIGHT, which is summed
+        // over the channels.
+        Table::open(&table_path, TableOpenMode::ReadWrite)
+            .unwrap()
+            .remove_column("WEIGHT_SPECTRUM")
+            .unwrap();
+        let mut reader = MeasurementSetReader::new(&table_path).unwrap();
+        let chunk_ctx = VisContext {
+            sel_baselines: vec![(0, 2)],
+            ..reader.vis_ctx().clone()
+        };
+        let mut weights = Array3::zeros(chunk_ctx.sel_dims());
+        reader
+            .read_vis(
+                Array3::zeros(chunk_ctx.sel_dims()).view_mut(),
+                weights.view_mut(),
+                &chunk_ctx,
+            )
+            .unwrap();
+        assert_abs_diff_eq!(weights, Array3::from_elem(chunk_ctx.sel_dims(), 2.0));
+    }
 }
@@ -0,0 +1,432 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Write visibilities as Apache Parquet (Arrow) tables, for dataframe-based
+//! analysis, e.g. with Polars or Spark.
+//!
+//! The output is a directory of Hive-style partitions, one per (averaged)
+//! timestep:
+//!
+//! ```text
+//! out.parquet/
+//!     timestep=0/part-0.parquet
+//!     timestep=1/part-0.parquet
+//!     ...
+//! ```
+//!
+//! which can be read in its entirety with e.g.
+//! `polars.scan_parquet("out.parquet/**/*.parquet", hive_partitioning=True)`.
+//! Each row is a single polarisation of a single visibility; see
+//! [`ParquetWriter::schema`] for the columns.
+
+use std::{
+    fs::{create_dir_all, File},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use ::parquet::arrow::ArrowWriter;
+use arrow_array::{
+    ArrayRef, BooleanArray, Float32Array, Float64Array, RecordBatch, StringArray, UInt32Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use indicatif::{ProgressDrawTarget, ProgressStyle};
+use log::trace;
+use ndarray::prelude::*;
+
+use super::VisWrite;
+use crate::{
+    averaging::average_vis_with_policy,
+    errors::{IOError, ParquetWriteError},
+    AveragingPolicy, Jones, VisContext, WeightFlags,
+};
+
+/// The polarisations written when a [`VisContext`] has 4 visibility pols, and
+/// their indices into a [`Jones`] matrix.
+const POLS_4: [(usize, &str); 4] = [(0, "XX"), (1, "XY"), (2, "YX"), (3, "YY")];
+/// The polarisations written when a [`VisContext`] has fewer than 4 visibility
+/// pols (only the first `num_vis_pols` are used), matching the uvfits and
+/// measurement set writers.
+const POLS_2: [(usize, &str); 2] = [(0, "XX"), (3, "YY")];
+
+/// A [`VisWrite`] implementor which writes a directory of Parquet files,
+/// partitioned by timestep.
+pub struct ParquetWriter {
+    /// The path to the output directory.
+    path: PathBuf,
+
+    /// The index of the next (averaged) timestep to be written, which names
+    /// its partition.
+    next_timestep: usize,
+
+    /// The Arrow schema of every partition.
+    schema: SchemaRef,
+}
+
+impl ParquetWriter {
+    /// Create a new [`ParquetWriter`], creating the output directory `path`
+    /// if it doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`ParquetWriteError::NotADirectory`] if `path` exists but
+    /// isn't a directory, or an IO error if it can't be created.
+    pub fn new<T: AsRef<Path>>(path: T) -> Result<Self, ParquetWriteError> {
+        let path = path.as_ref().to_path_buf();
+        if path.exists() && !path.is_dir() {
+            return Err(ParquetWriteError::NotADirectory {
+                path: path.display().to_string(),
+            });
+        }
+        create_dir_all(&path)?;
+
+        Ok(Self {
+            path,
+            next_timestep: 0,
+            schema: Self::schema(),
+        })
+    }
+
+    /// The Arrow schema of the written tables, with one row per visibility
+    /// polarisation:
+    ///
+    /// - `time_gps_s` - the centroid of the (averaged) timestep \[GPS seconds\]
+    /// - `baseline` - the index of the baseline in
+    ///   [`VisContext::sel_baselines`]
+    /// - `ant1`, `ant2` - the antenna indices of the baseline
+    /// - `channel` - the index of the (averaged) channel
+    /// - `freq_hz` - the centre frequency of the channel \[Hz\]
+    /// - `pol` - the polarisation, e.g. `"XX"`
+    /// - `vis_re`, `vis_im` - the real and imaginary parts of the visibility
+    /// - `weight` - the (non-negative) weight of the visibility
+    /// - `flag` - whether the visibility is flagged
+    pub fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("time_gps_s", DataType::Float64, false),
+            Field::new("baseline", DataType::UInt32, false),
+            Field::new("ant1", DataType::UInt32, false),
+            Field::new("ant2", DataType::UInt32, false),
+            Field::new("channel", DataType::UInt32, false),
+            Field::new("freq_hz", DataType::Float64, false),
+            Field::new("pol", DataType::Utf8, false),
+            Field::new("vis_re", DataType::Float32, false),
+            Field::new("vis_im", DataType::Float32, false),
+            Field::new("weight", DataType::Float32, false),
+            Field::new("flag", DataType::Boolean, false),
+        ]))
+    }
+
+    /// The path to the partition of a timestep.
+    fn partition_path(&self, timestep: usize) -> PathBuf {
+        self.path
+            .join(format!("timestep={timestep}"))
+            .join("part-0.parquet")
+    }
+
+    /// Build the table of a single averaged timestep. `jones` and `weights`
+    /// have dimensions `[channel][baseline]`.
+    fn timestep_batch(
+        &self,
+        jones: ArrayView2<Jones<f32>>,
+        weights: ArrayView2<f32>,
+        time_gps_s: f64,
+        freqs_hz: &[f64],
+        vis_ctx: &VisContext,
+    ) -> Result<RecordBatch, ParquetWriteError> {
+        let pols: &[(usize, &str)] = if vis_ctx.num_vis_pols == 4 {
+            &POLS_4
+        } else {
+            &POLS_2[..vis_ctx.num_vis_pols.min(2)]
+        };
+        let (num_chans, num_baselines) = jones.dim();
+        let num_rows = num_baselines * num_chans * pols.len();
+
+        let mut baseline_col = Vec::with_capacity(num_rows);
+        let mut ant1_col = Vec::with_capacity(num_rows);
+        let mut ant2_col = Vec::with_capacity(num_rows);
+        let mut channel_col = Vec::with_capacity(num_rows);
+        let mut freq_col = Vec::with_capacity(num_rows);
+        let mut pol_col = Vec::with_capacity(num_rows);
+        let mut re_col = Vec::with_capacity(num_rows);
+        let mut im_col = Vec::with_capacity(num_rows);
+        let mut weight_col = Vec::with_capacity(num_rows);
+        let mut flag_col = Vec::with_capacity(num_rows);
+
+        for (i_bl, ((ant1, ant2), jones_bl, weights_bl)) in vis_ctx
+            .sel_baselines
+            .iter()
+            .zip(jones.axis_iter(Axis(1)))
+            .zip(weights.axis_iter(Axis(1)))
+            .map(|((bl, j), w)| (bl, j, w))
+            .enumerate()
+        {
+            for (i_chan, (jones, &weight)) in jones_bl.iter().zip(weights_bl.iter()).enumerate() {
+                let WeightFlags { weight, flag } = WeightFlags::from_signed(weight);
+                let mut jones = *jones * vis_ctx.vis_scale;
+                if flag && vis_ctx.averaging_policy == AveragingPolicy::Nan {
+                    jones = Jones::nan();
+                }
+                for &(i_pol, pol) in pols {
+                    baseline_col.push(i_bl as u32);
+                    ant1_col.push(*ant1 as u32);
+                    ant2_col.push(*ant2 as u32);
+                    channel_col.push(i_chan as u32);
+                    freq_col.push(freqs_hz[i_chan]);
+                    pol_col.push(pol);
+                    re_col.push(jones[i_pol].re);
+                    im_col.push(jones[i_pol].im);
+                    weight_col.push(weight);
+                    flag_col.push(flag);
+                }
+            }
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(Float64Array::from(vec![time_gps_s; num_rows])),
+            Arc::new(UInt32Array::from(baseline_col)),
+            Arc::new(UInt32Array::from(ant1_col)),
+            Arc::new(UInt32Array::from(ant2_col)),
+            Arc::new(UInt32Array::from(channel_col)),
+            Arc::new(Float64Array::from(freq_col)),
+            Arc::new(StringArray::from(pol_col)),
+            Arc::new(Float32Array::from(re_col)),
+            Arc::new(Float32Array::from(im_col)),
+            Arc::new(Float32Array::from(weight_col)),
+            Arc::new(BooleanArray::from(flag_col)),
+        ];
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+
+    /// Write a table to the partition of a timestep.
+    fn write_partition(
+        &self,
+        timestep: usize,
+        batch: &RecordBatch,
+    ) -> Result<(), ParquetWriteError> {
+        let path = self.partition_path(timestep);
+        trace!("writing parquet partition {}", path.display());
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        let mut writer = ArrowWriter::try_new(File::create(&path)?, self.schema.clone(), None)?;
+        writer.write(batch)?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+impl VisWrite for ParquetWriter {
+    fn write_vis(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        vis_ctx: &VisContext,
+        draw_progress: bool,
+    ) -> Result<(), IOError> {
+        vis_ctx.check_sel_dims(vis.dim(), weights.dim(), "ParquetWriter::write_vis")?;
+
+        let averaged;
+        let (vis, weights) = if vis_ctx.trivial_averaging() {
+            (vis.view(), weights.view())
+        } else {
+            averaged = average_vis_with_policy(
+                vis,
+                weights,
+                vis_ctx.avg_time,
+                vis_ctx.avg_freq,
+                vis_ctx.averaging_policy,
+            )?;
+            (averaged.0.view(), averaged.1.view())
+        };
+        let freqs_hz = vis_ctx.avg_frequencies_hz();
+
+        let draw_target = if draw_progress {
+            ProgressDrawTarget::stderr()
+        } else {
+            ProgressDrawTarget::hidden()
+        };
+        let write_progress = indicatif::ProgressBar::with_draw_target(
+            Some(vis_ctx.num_avg_timesteps() as u64),
+            draw_target,
+        );
+        write_progress.set_style(
+            ProgressStyle::default_bar()
+                .template(
+                    "{msg:16}: [{elapsed_precise}] [{wide_bar:.cyan/blue}] {percent:3}% ({eta:5})",
+                )
+                .unwrap()
+                .progress_chars("=> "),
+        );
+        write_progress.set_message("write parquet");
+
+        for (timestamp, jones, weights) in itertools::izip!(
+            vis_ctx.timeseries(true, true),
+            vis.outer_iter(),
+            weights.outer_iter(),
+        ) {
+            let batch = self.timestep_batch(
+                jones,
+                weights,
+                timestamp.as_gpst_seconds(),
+                &freqs_hz,
+                vis_ctx,
+            )?;
+            self.write_partition(self.next_timestep, &batch)?;
+            self.next_timestep += 1;
+            write_progress.inc(1);
+        }
+
+        write_progress.finish();
+        Ok(())
+    }
+
+    fn finalise(&mut self) -> Result<(), IOError> {
+        // Every partition is closed as soon as it's written.
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use hifitime::{Duration, Epoch, Unit};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::c32;
+
+    fn get_vis_ctx() -> VisContext {
+        VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(2., Unit::Second),
+                4,
+            )
+            .channels(128_000_000., 10_000., 4)
+            .baselines(vec![(0, 0), (0, 1), (1, 1)])
+            .build()
+            .unwrap()
+    }
+
+    fn read_partition(path: &Path) -> RecordBatch {
+        use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batch = reader.next().unwrap().unwrap();
+        assert!(reader.next().is_none());
+        batch
+    }
+
+    #[test]
+    fn test_write_vis_partitions() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("out.parquet");
+        let vis_ctx = get_vis_ctx();
+        let (num_timesteps, num_chans, num_baselines) = vis_ctx.sel_dims();
+
+        let vis = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| {
+            Jones::from([
+                c32::new(t as f32, c as f32),
+                c32::new(b as f32, 0.),
+                c32::new(0., b as f32),
+                c32::new(1., -1.),
+            ])
+        });
+        let mut weights = Array3::from_elem(vis_ctx.sel_dims(), 2.0_f32);
+        weights[(1, 2, 1)] = -2.0;
+
+        let mut writer = ParquetWriter::new(&path).unwrap();
+        // Write the timesteps in two chunks; the partitions continue on.
+        for (chunk_ctx, vis, weights) in itertools::izip!(
+            vis_ctx.chunk_time(2),
+            vis.axis_chunks_iter(Axis(0), 2),
+            weights.axis_chunks_iter(Axis(0), 2),
+        ) {
+            writer.write_vis(vis, weights, &chunk_ctx, false).unwrap();
+        }
+        writer.finalise().unwrap();
+
+        for t in 0..num_timesteps {
+            let batch = read_partition(&path.join(format!("timestep={t}/part-0.parquet")));
+            assert_eq!(batch.schema(), ParquetWriter::schema());
+            assert_eq!(batch.num_rows(), num_baselines * num_chans * 4);
+
+            let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+            let time = column("time_gps_s");
+            let time = time.as_any().downcast_ref::<Float64Array>().unwrap();
+            assert_abs_diff_eq!(time.value(0), 1090008640. + 2. * t as f64 + 1.);
+
+            // Rows are ordered by baseline, then channel, then pol. Check
+            // baseline 1, channel 2.
+            let row = (num_chans + 2) * 4;
+            let ant2 = column("ant2");
+            let ant2 = ant2.as_any().downcast_ref::<UInt32Array>().unwrap();
+            assert_eq!(ant2.value(row), 1);
+            let freq = column("freq_hz");
+            let freq = freq.as_any().downcast_ref::<Float64Array>().unwrap();
+            assert_abs_diff_eq!(freq.value(row), 128_020_000.);
+            let pol = column("pol");
+            let pol = pol.as_any().downcast_ref::<StringArray>().unwrap();
+            assert_eq!(
+                (0..4).map(|i| pol.value(row + i)).collect::<Vec<_>>(),
+                ["XX", "XY", "YX", "YY"]
+            );
+            let vis_re = column("vis_re");
+            let vis_re = vis_re.as_any().downcast_ref::<Float32Array>().unwrap();
+            assert_abs_diff_eq!(vis_re.value(row), t as f32);
+            assert_abs_diff_eq!(vis_re.value(row + 1), 1.);
+            let weight = column("weight");
+            let weight = weight.as_any().downcast_ref::<Float32Array>().unwrap();
+            assert_abs_diff_eq!(weight.value(row), 2.);
+            let flag = column("flag");
+            let flag = flag.as_any().downcast_ref::<BooleanArray>().unwrap();
+            assert_eq!(flag.value(row), t == 1);
+        }
+    }
+
+    #[test]
+    fn test_write_vis_averaged() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("out.parquet");
+        let mut vis_ctx = get_vis_ctx();
+        vis_ctx.avg_time = 2;
+        vis_ctx.avg_freq = 4;
+        vis_ctx.num_vis_pols = 2;
+        vis_ctx.vis_scale = 0.5;
+
+        let vis = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        let weights = Array3::from_elem(vis_ctx.sel_dims(), 1.0_f32);
+        let mut writer = ParquetWriter::new(&path).unwrap();
+        writer
+            .write_vis(vis.view(), weights.view(), &vis_ctx, false)
+            .unwrap();
+
+        assert!(!path.join("timestep=2").exists());
+        let batch = read_partition(&path.join("timestep=1/part-0.parquet"));
+        // 3 baselines, 1 averaged channel, XX and YY.
+        assert_eq!(batch.num_rows(), 3 * 2);
+        let weight = batch.column_by_name("weight").unwrap();
+        let weight = weight.as_any().downcast_ref::<Float32Array>().unwrap();
+        assert_abs_diff_eq!(weight.value(0), 8.);
+        // The visibilities are scaled, but the weights aren't.
+        let vis_re = batch.column_by_name("vis_re").unwrap();
+        let vis_re = vis_re.as_any().downcast_ref::<Float32Array>().unwrap();
+        assert_abs_diff_eq!(vis_re.value(0), 0.5);
+        let pol = batch.column_by_name("pol").unwrap();
+        let pol = pol.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!((pol.value(0), pol.value(1)), ("XX", "YY"));
+    }
+
+    #[test]
+    fn test_new_not_a_directory() {
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        assert!(matches!(
+            ParquetWriter::new(tmp_file.path()),
+            Err(ParquetWriteError::NotADirectory { .. })
+        ));
+    }
+}
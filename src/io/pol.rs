@@ -0,0 +1,200 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The polarisation products written by visibility writers.
+
+use crate::{
+    ndarray::{array, Array2},
+    Complex, Jones,
+};
+
+/// The polarisation frame of the visibilities written by the uvfits and
+/// measurement set writers.
+///
+/// Visibilities are always given to writers as linear (XX, XY, YX, YY) Jones
+/// matrices; other frames are converted after averaging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolFrame {
+    /// XX, XY, YX and YY.
+    Linear,
+
+    /// Only XX and YY, for when the cross-hands aren't wanted.
+    LinearXXYY,
+
+    /// Only XX.
+    LinearXX,
+
+    /// RR, RL, LR and LL, where R = (X + iY) / sqrt(2) and
+    /// L = (X - iY) / sqrt(2). This gives RR = I + V and RL = Q + iU.
+    Circular,
+
+    /// Only Stokes I, (XX + YY) / 2.
+    StokesI,
+}
+
+impl Default for PolFrame {
+    fn default() -> Self {
+        Self::Linear
+    }
+}
+
+impl PolFrame {
+    /// The number of polarisation products written for each visibility.
+    pub fn num_pols(self) -> usize {
+        self.ms_pol_idxs().len()
+    }
+
+    /// Convert a linear visibility into this frame. The result has the same
+    /// layout as the input, i.e. (pp, pq, qp, qq) for feeds p and q; Stokes I
+    /// is in the first element.
+    pub fn convert(self, jones: Jones<f32>) -> Jones<f32> {
+        match self {
+            Self::Linear | Self::LinearXXYY | Self::LinearXX => jones,
+            Self::Circular => {
+                let i = Complex::new(0.0, 1.0);
+                let sum = jones[0] + jones[3];
+                let diff = jones[0] - jones[3];
+                let cross_diff = i * (jones[1] - jones[2]);
+                let cross_sum = i * (jones[1] + jones[2]);
+                Jones::from([
+                    (sum - cross_diff) * 0.5,
+                    (diff + cross_sum) * 0.5,
+                    (diff - cross_sum) * 0.5,
+                    (sum + cross_diff) * 0.5,
+                ])
+            }
+            Self::StokesI => {
+                let stokes_i = (jones[0] + jones[3]) * 0.5;
+                Jones::from([stokes_i, Complex::default(), Complex::default(), stokes_i])
+            }
+        }
+    }
+
+    /// The factor applied to the weights of visibilities written in this
+    /// frame. Stokes I is the mean of XX and YY, which have independent noise,
+    /// so its variance is half, and its weight twice, that of XX or YY.
+    pub fn weight_factor(self) -> f32 {
+        match self {
+            Self::Linear | Self::LinearXXYY | Self::LinearXX | Self::Circular => 1.0,
+            Self::StokesI => 2.0,
+        }
+    }
+
+    /// Indices into a [`PolFrame::convert`]ed Jones matrix of each uvfits
+    /// polarisation, which are ordered pp, qq, pq, qp.
+    pub fn uvfits_pol_idxs(self) -> &'static [usize] {
+        match self {
+            Self::Linear | Self::Circular => &[0, 3, 1, 2],
+            Self::LinearXXYY => &[0, 3],
+            Self::LinearXX | Self::StokesI => &[0],
+        }
+    }
+
+    /// The AIPS Stokes code of the first uvfits polarisation (`CRVAL3`), and
+    /// the increment of subsequent codes (`CDELT3`).
+    pub fn uvfits_stokes(self) -> (i64, i64) {
+        match self {
+            Self::Linear | Self::LinearXXYY | Self::LinearXX => (-5, -1),
+            Self::Circular => (-1, -1),
+            Self::StokesI => (1, 1),
+        }
+    }
+
+    /// Indices into a [`PolFrame::convert`]ed Jones matrix of each measurement
+    /// set correlation.
+    pub fn ms_pol_idxs(self) -> &'static [usize] {
+        match self {
+            Self::Linear | Self::Circular => &[0, 1, 2, 3],
+            Self::LinearXXYY => &[0, 3],
+            Self::LinearXX | Self::StokesI => &[0],
+        }
+    }
+
+    /// The measurement set `CORR_TYPE`s (CASA `Stokes` enum values) of each
+    /// correlation.
+    pub fn ms_corr_types(self) -> Vec<i32> {
+        match self {
+            Self::Linear => vec![9, 10, 11, 12],
+            Self::LinearXXYY => vec![9, 12],
+            Self::LinearXX => vec![9],
+            Self::Circular => vec![5, 6, 7, 8],
+            Self::StokesI => vec![1],
+        }
+    }
+
+    /// The measurement set `CORR_PRODUCT`s, i.e. the pair of receptors of
+    /// each correlation.
+    pub fn ms_corr_products(self) -> Array2<i32> {
+        match self {
+            Self::Linear | Self::Circular => array![[0, 0], [0, 1], [1, 0], [1, 1]],
+            Self::LinearXXYY => array![[0, 0], [1, 1]],
+            Self::LinearXX | Self::StokesI => array![[0, 0]],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    /// Linear visibilities of a source with the given Stokes parameters.
+    fn linear_vis(i: f32, q: f32, u: f32, v: f32) -> Jones<f32> {
+        Jones::from([
+            Complex::new(i + q, 0.0),
+            Complex::new(u, v),
+            Complex::new(u, -v),
+            Complex::new(i - q, 0.0),
+        ])
+    }
+
+    #[test]
+    fn test_circular() {
+        let circ = PolFrame::Circular.convert(linear_vis(1.0, 0.2, 0.3, 0.4));
+        assert_abs_diff_eq!(circ[0], Complex::new(1.4, 0.0), epsilon = 1e-6);
+        assert_abs_diff_eq!(circ[1], Complex::new(0.2, 0.3), epsilon = 1e-6);
+        assert_abs_diff_eq!(circ[2], Complex::new(0.2, -0.3), epsilon = 1e-6);
+        assert_abs_diff_eq!(circ[3], Complex::new(0.6, 0.0), epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_stokes_i() {
+        let stokes = PolFrame::StokesI.convert(linear_vis(1.0, 0.2, 0.3, 0.4));
+        assert_abs_diff_eq!(stokes[0], Complex::new(1.0, 0.0), epsilon = 1e-6);
+        assert_eq!(PolFrame::StokesI.num_pols(), 1);
+        assert_eq!(PolFrame::StokesI.uvfits_pol_idxs(), &[0]);
+        assert_abs_diff_eq!(PolFrame::StokesI.weight_factor(), 2.0);
+        assert_abs_diff_eq!(PolFrame::Circular.weight_factor(), 1.0);
+    }
+
+    #[test]
+    fn test_linear_subsets() {
+        let jones = linear_vis(1.0, 0.2, 0.3, 0.4);
+        let xxyy = PolFrame::LinearXXYY;
+        let pols: Vec<_> = xxyy
+            .ms_pol_idxs()
+            .iter()
+            .map(|&i| xxyy.convert(jones)[i])
+            .collect();
+        assert_eq!(pols, [jones[0], jones[3]]);
+        assert_eq!(xxyy.uvfits_pol_idxs(), xxyy.ms_pol_idxs());
+        assert_eq!(PolFrame::LinearXX.ms_corr_types(), [9]);
+    }
+
+    #[test]
+    fn test_pol_counts_match() {
+        for frame in [
+            PolFrame::Linear,
+            PolFrame::LinearXXYY,
+            PolFrame::LinearXX,
+            PolFrame::Circular,
+            PolFrame::StokesI,
+        ] {
+            assert_eq!(frame.uvfits_pol_idxs().len(), frame.num_pols());
+            assert_eq!(frame.ms_corr_types().len(), frame.num_pols());
+            assert_eq!(frame.ms_corr_products().dim(), (frame.num_pols(), 2));
+        }
+    }
+}
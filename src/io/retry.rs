@@ -0,0 +1,384 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Retrying IO operations that fail transiently.
+//!
+//! Network filesystems (e.g. Lustre or NFS) occasionally fail an operation
+//! with an error like `EIO` that succeeds if it is simply tried again. A
+//! [`RetryPolicy`] describes how many times to try an operation and how long to
+//! wait between attempts; [`RetryReader`] applies it to any [`VisRead`] source
+//! (e.g. a [`crate::io::GpuboxReader`]), and
+//! [`crate::io::UvfitsWriter::set_retry_policy`] to the rows of a uvfits file.
+//! Each retry is logged, and can also be given to a [`RetryHook`].
+
+use std::{fmt, sync::Arc, thread, time::Duration};
+
+use log::warn;
+use ndarray::prelude::*;
+
+use super::VisRead;
+use crate::{errors::IOError, Jones, VisContext};
+
+/// A function called before each retry, e.g. to count or report the retries.
+pub type RetryHook = Arc<dyn Fn(&RetryEvent) + Send + Sync>;
+
+/// How to retry an operation that fails transiently. The wait before the `n`th
+/// retry is `initial_backoff * backoff_factor^(n - 1)`, up to `max_backoff`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of times to try an operation, including the first
+    /// attempt. 1 (or 0) means operations are never retried.
+    pub max_attempts: usize,
+
+    /// The wait before the first retry.
+    pub initial_backoff: Duration,
+
+    /// The factor that the wait grows by with each retry.
+    pub backoff_factor: f64,
+
+    /// The longest wait between attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            backoff_factor: 2.0,
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn never() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// The wait before retry number `retry` (starting at 1).
+    pub fn backoff(&self, retry: usize) -> Duration {
+        let factor = self.backoff_factor.powi(retry.saturating_sub(1) as i32);
+        let secs = self.initial_backoff.as_secs_f64() * factor;
+        if secs.is_finite() && secs < self.max_backoff.as_secs_f64() {
+            Duration::from_secs_f64(secs)
+        } else {
+            self.max_backoff
+        }
+    }
+}
+
+/// A retry of a failed operation, given to a [`RetryHook`].
+pub struct RetryEvent<'a> {
+    /// A description of the operation, e.g. `"read visibilities"`.
+    pub operation: &'a str,
+
+    /// The attempt that failed (starting at 1).
+    pub attempt: usize,
+
+    /// The wait before the next attempt.
+    pub backoff: Duration,
+
+    /// The error of the failed attempt.
+    pub error: &'a dyn fmt::Display,
+}
+
+/// Run `op` until it succeeds, it fails with an error that isn't transient
+/// (according to `is_transient`), or `policy.max_attempts` attempts have been
+/// made. Before each retry, a warning is logged, `hook` is called, and the
+/// thread sleeps for the backoff of `policy`.
+///
+/// # Errors
+///
+/// Returns the error of the last attempt.
+pub fn retry<T, E, F, P>(
+    policy: &RetryPolicy,
+    operation: &str,
+    hook: Option<&RetryHook>,
+    is_transient: P,
+    mut op: F,
+) -> Result<T, E>
+where
+    E: fmt::Display,
+    F: FnMut() -> Result<T, E>,
+    P: Fn(&E) -> bool,
+{
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Err(e) if attempt < policy.max_attempts && is_transient(&e) => {
+                let backoff = policy.backoff(attempt);
+                warn!(
+                    "{} failed (attempt {} of {}), retrying in {:?}: {}",
+                    operation, attempt, policy.max_attempts, backoff, e
+                );
+                if let Some(hook) = hook {
+                    hook(&RetryEvent {
+                        operation,
+                        attempt,
+                        backoff,
+                        error: &e,
+                    });
+                }
+                thread::sleep(backoff);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether an IO error might succeed if retried, i.e. it was interrupted or
+/// timed out, or is an `EIO` or `ESTALE` from a (network) filesystem.
+pub fn is_transient_io(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    const EIO: i32 = 5;
+    #[cfg(target_os = "macos")]
+    const ESTALE: i32 = 70;
+    #[cfg(not(target_os = "macos"))]
+    const ESTALE: i32 = 116;
+
+    matches!(
+        error.kind(),
+        ErrorKind::Interrupted | ErrorKind::TimedOut | ErrorKind::WouldBlock
+    ) || matches!(error.raw_os_error(), Some(EIO | ESTALE))
+}
+
+/// Whether a fitsio error might succeed if retried, i.e. it is a cfitsio read,
+/// write or seek error, or a transient IO error.
+#[cfg(feature = "cfitsio")]
+pub fn is_transient_fits(error: &fitsio::errors::Error) -> bool {
+    // cfitsio's WRITE_ERROR, READ_ERROR and SEEK_ERROR.
+    const TRANSIENT_STATUSES: [i32; 3] = [106, 108, 116];
+
+    match error {
+        fitsio::errors::Error::Fits(e) => TRANSIENT_STATUSES.contains(&e.status),
+        fitsio::errors::Error::Io(e) => is_transient_io(e),
+        _ => false,
+    }
+}
+
+/// Whether an [`IOError`] might succeed if retried. Errors from mwalib while
+/// reading gpubox files are assumed to be transient, because the files were
+/// readable when the `CorrelatorContext` was created.
+pub fn is_transient(error: &IOError) -> bool {
+    match error {
+        IOError::StdIo(e) => is_transient_io(e),
+        #[cfg(feature = "cfitsio")]
+        IOError::FitsioError(e) => is_transient_fits(e),
+        #[cfg(feature = "cfitsio")]
+        IOError::FitsHdu(e) => is_transient_fits(&e.source),
+        #[cfg(feature = "mwalib")]
+        IOError::FitsError(_) | IOError::SelectionError(crate::SelectionError::Mwalib(_)) => true,
+        _ => false,
+    }
+}
+
+/// A [`VisRead`] source that retries the reads of another source according to
+/// a [`RetryPolicy`]. Reads are only retried if the error is transient (see
+/// [`is_transient`]).
+pub struct RetryReader<R: VisRead> {
+    reader: R,
+    policy: RetryPolicy,
+    hook: Option<RetryHook>,
+}
+
+impl<R: VisRead> RetryReader<R> {
+    pub fn new(reader: R, policy: RetryPolicy) -> Self {
+        Self {
+            reader,
+            policy,
+            hook: None,
+        }
+    }
+
+    /// Call `hook` before each retry, in addition to logging it.
+    pub fn with_hook(mut self, hook: RetryHook) -> Self {
+        self.hook = Some(hook);
+        self
+    }
+
+    /// Unwrap the inner source.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: VisRead> VisRead for RetryReader<R> {
+    fn vis_ctx(&self) -> &VisContext {
+        self.reader.vis_ctx()
+    }
+
+    fn read_vis(
+        &mut self,
+        mut vis: ArrayViewMut3<Jones<f32>>,
+        mut weights: ArrayViewMut3<f32>,
+        chunk_ctx: &VisContext,
+    ) -> Result<(), IOError> {
+        let reader = &mut self.reader;
+        retry(
+            &self.policy,
+            "read visibilities",
+            self.hook.as_ref(),
+            is_transient,
+            || reader.read_vis(vis.view_mut(), weights.view_mut(), chunk_ctx),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use approx::assert_abs_diff_eq;
+    use hifitime::{Epoch, Unit};
+
+    use super::*;
+
+    fn no_backoff(max_attempts: usize) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::ZERO,
+            ..Default::default()
+        }
+    }
+
+    fn eio() -> std::io::Error {
+        std::io::Error::from_raw_os_error(5)
+    }
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(10), Duration::from_secs(60));
+        assert_eq!(policy.backoff(10_000), Duration::from_secs(60));
+
+        assert!(is_transient_io(&eio()));
+        assert!(!is_transient_io(&std::io::Error::from(
+            std::io::ErrorKind::NotFound
+        )));
+    }
+
+    #[test]
+    fn test_retry() {
+        let num_retries = Arc::new(AtomicUsize::new(0));
+        let hook: RetryHook = {
+            let num_retries = Arc::clone(&num_retries);
+            Arc::new(move |event| {
+                assert_eq!(event.operation, "test");
+                num_retries.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+
+        // Succeeds on the third attempt.
+        let mut attempts = 0;
+        let result = retry(&no_backoff(3), "test", Some(&hook), is_transient_io, || {
+            attempts += 1;
+            if attempts < 3 {
+                Err(eio())
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(num_retries.load(Ordering::SeqCst), 2);
+
+        // Gives up after the maximum number of attempts.
+        let mut attempts = 0;
+        let result: Result<(), _> = retry(&no_backoff(3), "test", None, is_transient_io, || {
+            attempts += 1;
+            Err(eio())
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+
+        // Errors that aren't transient aren't retried.
+        let mut attempts = 0;
+        let result: Result<(), _> = retry(&no_backoff(3), "test", None, is_transient_io, || {
+            attempts += 1;
+            Err(std::io::Error::from(std::io::ErrorKind::NotFound))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    /// A source that fails with `EIO` a number of times before each read
+    /// succeeds.
+    struct FlakyReader {
+        vis_ctx: VisContext,
+        num_failures: usize,
+    }
+
+    impl VisRead for FlakyReader {
+        fn vis_ctx(&self) -> &VisContext {
+            &self.vis_ctx
+        }
+
+        fn read_vis(
+            &mut self,
+            mut vis: ArrayViewMut3<Jones<f32>>,
+            mut weights: ArrayViewMut3<f32>,
+            _: &VisContext,
+        ) -> Result<(), IOError> {
+            if self.num_failures > 0 {
+                self.num_failures -= 1;
+                // Partially fill the arrays before failing.
+                vis.fill(Jones::identity() * 2.0);
+                return Err(eio().into());
+            }
+            vis.fill(Jones::identity());
+            weights.fill(1.0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_retry_reader() {
+        let vis_ctx = VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                hifitime::Duration::from_f64(2., Unit::Second),
+                2,
+            )
+            .channels(150e6, 40e3, 3)
+            .baselines(vec![(0, 1)])
+            .build()
+            .unwrap();
+        let mut vis = Array3::from_elem(vis_ctx.sel_dims(), Jones::default());
+        let mut weights = Array3::from_elem(vis_ctx.sel_dims(), 0.0);
+
+        let mut reader = RetryReader::new(
+            FlakyReader {
+                vis_ctx: vis_ctx.clone(),
+                num_failures: 2,
+            },
+            no_backoff(3),
+        );
+        reader
+            .read_vis(vis.view_mut(), weights.view_mut(), &vis_ctx)
+            .unwrap();
+        assert_abs_diff_eq!(vis, Array3::from_elem(vis.dim(), Jones::identity()));
+        assert_abs_diff_eq!(weights, Array3::from_elem(weights.dim(), 1.0));
+
+        let mut reader = RetryReader::new(
+            FlakyReader {
+                vis_ctx: vis_ctx.clone(),
+                num_failures: 2,
+            },
+            RetryPolicy::never(),
+        );
+        assert!(matches!(
+            reader.read_vis(vis.view_mut(), weights.view_mut(), &vis_ctx),
+            Err(IOError::StdIo(_))
+        ));
+        assert_eq!(reader.into_inner().num_failures, 1);
+    }
+}
@@ -2,7 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-//! Module for writing the uvfits file format.
+//! Module for reading and writing the uvfits file format.
 
 use std::{
     ffi::CString,
@@ -10,27 +10,35 @@ use std::{
 };
 
 use crate::{
-    average_chunk_f64,
+    averaging::average_chunk_f64,
     constants::VEL_C,
-    erfa_sys::{eraGst06a, ERFA_DJM0},
-    hifitime::{Duration, Epoch},
-    io::error::BadArrayShape,
-    ndarray::{ArrayView3, Axis},
+    errors::{FitsHduError, IOError, UvfitsWriteError},
+    hifitime::{Duration, Epoch, Unit},
+    iers::PolarMotion,
+    ndarray::{ArrayView3, ArrayViewMut3, Axis},
     num_complex::Complex,
-    precession::precess_time,
-    History, Jones, LatLngHeight, RADec, VisContext, XyzGeodetic, UVW,
+    pos::erfa::epj,
+    precession::{get_gast, precess_time_with_polar_motion, UvwFrame},
+    time::{jd_tt_days, jd_utc_days_since, jd_utc_split, mjd_utc_days},
+    weights::is_flagged,
+    AveragingPolicy, History, InstrumentState, Jones, LatLngHeight, PolFrame, Provenance, RADec,
+    Telescope, VisContext, VisUnit, WeightFlags, XyzGeodetic,
 };
-use fitsio::errors::check_status as fits_check_status;
+use fitsio::{errors::check_status as fits_check_status, FitsFile};
 use fitsio_sys;
 use indicatif::{ProgressDrawTarget, ProgressStyle};
 use itertools::{izip, Itertools};
 use log::trace;
 
 use super::{
-    error::{IOError, UvfitsWriteError},
-    VisWrite,
+    locate_read_chunk,
+    retry::{is_transient_fits, retry, RetryHook, RetryPolicy},
+    OutputEstimate, VisRead, VisWrite,
 };
 
+#[cfg(all(test, feature = "mwalib"))]
+use crate::UVW;
+
 /// From a `hifitime` [`Epoch`], get a formatted date string with the hours,
 /// minutes and seconds set to 0.
 fn get_truncated_date_string(epoch: Epoch) -> String {
@@ -91,6 +99,154 @@ pub const fn decode_uvfits_baseline(bl: usize) -> (usize, usize) {
     }
 }
 
+/// The largest (1-indexed) antenna number that [`encode_uvfits_baseline`] can
+/// represent.
+pub const MAX_MIRIAD_ANTENNA: usize = 2047;
+
+/// How the antennas of each uvfits row are recorded in its group parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaselineEncoding {
+    /// A single `BASELINE` group parameter, encoded with
+    /// [`encode_uvfits_baseline`]. This is what most software expects, but it
+    /// can only describe up to [`MAX_MIRIAD_ANTENNA`] antennas.
+    Miriad,
+
+    /// Separate `ANTENNA1` and `ANTENNA2` group parameters (indices start at
+    /// 1), which are not limited in the number of antennas.
+    AntennaParams,
+}
+
+impl BaselineEncoding {
+    /// Use [`BaselineEncoding::Miriad`] if it can describe `num_ants`
+    /// antennas, otherwise [`BaselineEncoding::AntennaParams`].
+    pub fn for_num_ants(num_ants: usize) -> Self {
+        if num_ants <= MAX_MIRIAD_ANTENNA {
+            Self::Miriad
+        } else {
+            Self::AntennaParams
+        }
+    }
+
+    /// The names of the baseline group parameters (`PTYPEn`), which follow
+    /// the `WW` parameter.
+    fn group_params(self) -> &'static [&'static str] {
+        match self {
+            Self::Miriad => &["BASELINE"],
+            Self::AntennaParams => &["ANTENNA1", "ANTENNA2"],
+        }
+    }
+
+    /// Write the baseline group parameters of a row into `params`, which
+    /// starts just after the `WW` parameter. Antenna indices start at 0.
+    fn fill_baseline_params(self, params: &mut [f32], ant1_idx: usize, ant2_idx: usize) {
+        match self {
+            Self::Miriad => {
+                params[0] = encode_uvfits_baseline(ant1_idx + 1, ant2_idx + 1) as f32;
+            }
+            Self::AntennaParams => {
+                params[0] = (ant1_idx + 1) as f32;
+                params[1] = (ant2_idx + 1) as f32;
+            }
+        }
+    }
+}
+
+/// How the time of each uvfits row is recorded in its group parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateEncoding {
+    /// A single `DATE` group parameter, like cotter. Group parameters are
+    /// `f32`s, so times are quantised to a few milliseconds.
+    Single,
+
+    /// Two `DATE` group parameters, which readers sum to get the Julian date
+    /// (the AIPS convention for double-precision dates). Only the first has a
+    /// `PZERO`, and the second holds what the first can't represent.
+    Double,
+}
+
+impl Default for DateEncoding {
+    fn default() -> Self {
+        Self::Single
+    }
+}
+
+impl DateEncoding {
+    /// The number of `DATE` group parameters.
+    fn num_params(self) -> usize {
+        match self {
+            Self::Single => 1,
+            Self::Double => 2,
+        }
+    }
+
+    /// Write the date group parameters of a row into `params`, given the
+    /// number of days since the `PZERO` of the first `DATE` parameter.
+    ///
+    /// `jd_frac` must stay double precision until it's split here; rounding it
+    /// to single precision earlier would lose the remainder that the second
+    /// `DATE` parameter holds.
+    fn fill_date_params(self, params: &mut [f32], jd_frac: f64) {
+        match self {
+            Self::Single => {
+                params[0] = jd_frac as f32;
+            }
+            Self::Double => {
+                let coarse = jd_frac as f32;
+                params[0] = coarse;
+                params[1] = (jd_frac - f64::from(coarse)) as f32;
+            }
+        }
+    }
+}
+
+/// The names of the group parameters (`PTYPEn`) of each uvfits row.
+fn group_params(
+    baseline_encoding: BaselineEncoding,
+    date_encoding: DateEncoding,
+    inttim: bool,
+) -> Vec<&'static str> {
+    let mut params = vec!["UU", "VV", "WW"];
+    params.extend_from_slice(baseline_encoding.group_params());
+    params.extend(std::iter::repeat("DATE").take(date_encoding.num_params()));
+    if inttim {
+        params.push("INTTIM");
+    }
+    params
+}
+
+/// Options for the layout of a uvfits file, given to
+/// [`UvfitsWriter::new_with_options`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UvfitsOptions {
+    /// How baselines are written into the group parameters. If this is `None`,
+    /// the encoding is picked with [`BaselineEncoding::for_num_ants`].
+    pub baseline_encoding: Option<BaselineEncoding>,
+
+    /// The polarisation products to write.
+    pub pol_frame: PolFrame,
+
+    /// The frame of the written UVWs. If this is [`UvwFrame::OfDate`], the
+    /// phase centre is also written with the epoch of the first scan rather
+    /// than J2000.
+    pub uvw_frame: UvwFrame,
+
+    /// The polar motion used when forming UVWs. This is also written to the
+    /// antenna table (`POLARX` and `POLARY`).
+    pub polar_motion: PolarMotion,
+
+    /// How the time of each row is written into the group parameters.
+    pub date_encoding: DateEncoding,
+
+    /// Write an `INTTIM` group parameter, the integration time of each row
+    /// \[seconds\]. This isn't written by default, like cotter.
+    pub inttim: bool,
+
+    /// Write the `CHECKSUM` and `DATASUM` keywords of each HDU when the file
+    /// is finalised, so that truncation or corruption can be detected (e.g.
+    /// with [`UvfitsReader::verify_checksums`]).
+    pub checksum: bool,
+}
+
 /// A helper struct to write out a uvfits file.
 ///
 /// Note: only a single contiguous spectral window is supported.
@@ -131,6 +287,9 @@ pub struct UvfitsWriter {
     /// The [`RADec`] where this observation is phased to
     phase_centre: RADec,
 
+    /// The name of the array, used to populate the `ARRNAM` key.
+    array_name: String,
+
     /// Array Position [Latitude (radians), Longitude (radians), Height (m)]
     array_pos: LatLngHeight,
 
@@ -138,13 +297,52 @@ pub struct UvfitsWriter {
     antenna_names: Vec<String>,
 
     /// The *unprecessed* positions of the antennas. The writing code will
-    /// precess these positions to J2000 for each timestep.
+    /// precess these positions to J2000 for each timestep, unless `uvw_frame`
+    /// is [`UvwFrame::OfDate`].
     antenna_positions: Vec<XyzGeodetic>,
 
+    /// The `MNTSTA` of each antenna; see [`Telescope::ant_mount`].
+    antenna_mntstas: Vec<i32>,
+
+    /// The `STAXOF` of each antenna; see [`Telescope::ant_axis_offset_m`].
+    antenna_axis_offsets_m: Vec<f32>,
+
     /// UT1 - UTC, a.k.a. DUT1. We assume that this value is suitable for all
     /// timesteps being written; this is pretty sensible, because the value
     /// should change very slowly (a few milliseconds over ~5 days?).
     dut1: Duration,
+
+    /// How baselines are written into the group parameters.
+    baseline_encoding: BaselineEncoding,
+
+    /// The polarisation products written.
+    pol_frame: PolFrame,
+
+    /// The frame of the written UVWs.
+    uvw_frame: UvwFrame,
+
+    /// The polar motion used when forming UVWs.
+    polar_motion: PolarMotion,
+
+    /// How the time of each row is written into the group parameters.
+    date_encoding: DateEncoding,
+
+    /// Is there an `INTTIM` group parameter?
+    inttim: bool,
+
+    /// The `INTTIM` of written rows, if not the averaged integration time of
+    /// the [`VisContext`]; see [`UvfitsWriter::set_int_time`].
+    int_time_override: Option<Duration>,
+
+    /// How to retry writing rows that fail transiently; see
+    /// [`UvfitsWriter::set_retry_policy`].
+    retry_policy: RetryPolicy,
+
+    /// Called before each retry.
+    retry_hook: Option<RetryHook>,
+
+    /// Are `CHECKSUM` and `DATASUM` keywords written to each HDU?
+    checksum: bool,
 }
 
 impl UvfitsWriter {
@@ -182,6 +380,13 @@ impl UvfitsWriter {
     /// `obs_name` an optional name for the object under observation. Used to
     /// populate the `OBJECT` keys.
     ///
+    /// `telescope` describes the array and its antennas, and populates the
+    /// `TELESCOP`, `INSTRUME` and `ARRNAM` keys.
+    ///
+    /// Baselines are encoded with [`BaselineEncoding::for_num_ants`], and
+    /// linear polarisations are written; use [`UvfitsWriter::new_with_options`]
+    /// to change these.
+    ///
     /// # Errors
     ///
     /// Will return an [`UvfitsWriteError`] if:
@@ -201,13 +406,107 @@ impl UvfitsWriter {
         centre_freq_chan: usize,
         phase_centre: RADec,
         obs_name: Option<&str>,
-        array_pos: LatLngHeight,
-        antenna_names: Vec<String>,
-        antenna_positions: Vec<XyzGeodetic>,
+        telescope: &Telescope,
+        dut1: Duration,
+        history: Option<&History>,
+    ) -> Result<UvfitsWriter, UvfitsWriteError> {
+        Self::new_with_options(
+            path,
+            num_timesteps,
+            num_baselines,
+            num_chans,
+            start_epoch,
+            fine_chan_width_hz,
+            centre_freq_hz,
+            centre_freq_chan,
+            phase_centre,
+            obs_name,
+            telescope,
+            dut1,
+            history,
+            UvfitsOptions::default(),
+        )
+    }
+
+    /// Create a new uvfits file at the specified path, laid out according to
+    /// `options`. The other arguments are the same as [`UvfitsWriter::new`].
+    ///
+    /// # Errors
+    ///
+    /// See [`UvfitsWriter::new`]. Additionally, returns
+    /// [`UvfitsWriteError::TooManyAntennas`] if the baseline encoding is
+    /// [`BaselineEncoding::Miriad`] and `telescope` has more than
+    /// [`MAX_MIRIAD_ANTENNA`] antennas.
+    ///
+    /// fitsio errors are returned as [`UvfitsWriteError::FitsHdu`], with the
+    /// path and HDU.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_options<T: AsRef<Path>>(
+        path: T,
+        num_timesteps: usize,
+        num_baselines: usize,
+        num_chans: usize,
+        start_epoch: Epoch,
+        fine_chan_width_hz: f64,
+        centre_freq_hz: f64,
+        centre_freq_chan: usize,
+        phase_centre: RADec,
+        obs_name: Option<&str>,
+        telescope: &Telescope,
         dut1: Duration,
         history: Option<&History>,
+        options: UvfitsOptions,
     ) -> Result<UvfitsWriter, UvfitsWriteError> {
         let path = path.as_ref();
+        Self::create(
+            path,
+            num_timesteps,
+            num_baselines,
+            num_chans,
+            start_epoch,
+            fine_chan_width_hz,
+            centre_freq_hz,
+            centre_freq_chan,
+            phase_centre,
+            obs_name,
+            telescope,
+            dut1,
+            history,
+            options,
+        )
+        .map_err(|e| e.with_context(path, "PRIMARY"))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create(
+        path: &Path,
+        num_timesteps: usize,
+        num_baselines: usize,
+        num_chans: usize,
+        start_epoch: Epoch,
+        fine_chan_width_hz: f64,
+        centre_freq_hz: f64,
+        centre_freq_chan: usize,
+        phase_centre: RADec,
+        obs_name: Option<&str>,
+        telescope: &Telescope,
+        dut1: Duration,
+        history: Option<&History>,
+        options: UvfitsOptions,
+    ) -> Result<UvfitsWriter, UvfitsWriteError> {
+        let num_ants = telescope.ant_positions.len();
+        let baseline_encoding = options
+            .baseline_encoding
+            .unwrap_or_else(|| BaselineEncoding::for_num_ants(num_ants));
+        let pol_frame = options.pol_frame;
+        let uvw_frame = options.uvw_frame;
+        if baseline_encoding == BaselineEncoding::Miriad && num_ants > MAX_MIRIAD_ANTENNA {
+            return Err(UvfitsWriteError::TooManyAntennas {
+                num_ants,
+                max: MAX_MIRIAD_ANTENNA,
+            });
+        }
+
         // Delete any file that already exists.
         if path.exists() {
             trace!("file {:?} exists, deleting", &path);
@@ -230,8 +529,9 @@ impl UvfitsWriter {
         fits_check_status(status)?;
 
         // Initialise the group header. Copied from cotter. -32 means FLOAT_IMG.
-        let mut naxes = [0, 3, 4, num_chans as i64, 1, 1];
-        let num_group_params = 5;
+        let mut naxes = [0, 3, pol_frame.num_pols() as i64, num_chans as i64, 1, 1];
+        let group_params = group_params(baseline_encoding, options.date_encoding, options.inttim);
+        let num_group_params = group_params.len() as i64;
         let total_num_rows = num_timesteps * num_baselines;
         assert!(
             total_num_rows > 0,
@@ -256,22 +556,19 @@ impl UvfitsWriter {
 
         fits_write_double(fptr, "BSCALE", 1.0, None)?;
 
-        // Set header names and scales.
-        for (i, &param) in ["UU", "VV", "WW", "BASELINE", "DATE"].iter().enumerate() {
+        // Set header names and scales. Only the first DATE column has a zero
+        // level; readers sum all DATE columns.
+        let mut date_zero = Some(jd_utc_split(start_epoch).0 + 0.5);
+        for (i, &param) in group_params.iter().enumerate() {
             let ii = i + 1;
             fits_write_string(fptr, &format!("PTYPE{}", ii), param, None)?;
             fits_write_double(fptr, &format!("PSCAL{}", ii), 1.0, None)?;
-            if param == "DATE" {
-                // Set the zero level for the DATE column.
-                fits_write_double(
-                    fptr,
-                    &format!("PZERO{}", ii),
-                    start_epoch.as_jde_utc_days().floor() + 0.5,
-                    None,
-                )?;
+            let zero = if param == "DATE" {
+                date_zero.take().unwrap_or(0.0)
             } else {
-                fits_write_double(fptr, &format!("PZERO{}", ii), 0.0, None)?;
-            }
+                0.0
+            };
+            fits_write_double(fptr, &format!("PZERO{}", ii), zero, None)?;
         }
         fits_write_string(
             fptr,
@@ -286,10 +583,11 @@ impl UvfitsWriter {
         fits_write_double(fptr, "CRPIX2", 1.0, None)?;
         fits_write_double(fptr, "CDELT2", 1.0, None)?;
 
-        // Linearly polarised.
+        // Linearly polarised, unless another frame was requested.
+        let (stokes_crval, stokes_cdelt) = pol_frame.uvfits_stokes();
         fits_write_string(fptr, "CTYPE3", "STOKES", None)?;
-        fits_write_int(fptr, "CRVAL3", -5, None)?;
-        fits_write_int(fptr, "CDELT3", -1, None)?;
+        fits_write_int(fptr, "CRVAL3", stokes_crval, None)?;
+        fits_write_int(fptr, "CDELT3", stokes_cdelt, None)?;
         fits_write_double(fptr, "CRPIX3", 1.0, None)?;
 
         fits_write_string(fptr, "CTYPE4", "FREQ", None)?;
@@ -309,11 +607,20 @@ impl UvfitsWriter {
 
         fits_write_double(fptr, "OBSRA", phase_centre.ra.to_degrees(), None)?;
         fits_write_double(fptr, "OBSDEC", phase_centre.dec.to_degrees(), None)?;
-        fits_write_double(fptr, "EPOCH", 2000.0, None)?;
+        // The phase centre (CRVAL5, CRVAL6, OBSRA and OBSDEC) and the UVWs are
+        // all in the frame of this epoch. Unprecessed UVWs are formed with the
+        // phase centre as it's given, so it's in the frame of the first scan.
+        let epoch = match uvw_frame {
+            UvwFrame::J2000 => 2000.0,
+            // Julian epoch of the first scan.
+            UvwFrame::OfDate => epj(jd_tt_days(start_epoch), 0.0),
+        };
+        fits_write_double(fptr, "EPOCH", epoch, None)?;
+        fits_write_double(fptr, "EQUINOX", epoch, None)?;
 
         fits_write_string(fptr, "OBJECT", obs_name.unwrap_or("Undefined"), None)?;
-        fits_write_string(fptr, "TELESCOP", "MWA", None)?;
-        fits_write_string(fptr, "INSTRUME", "MWA", None)?;
+        fits_write_string(fptr, "TELESCOP", &telescope.name, None)?;
+        fits_write_string(fptr, "INSTRUME", &telescope.instrument, None)?;
 
         // This is apparently required...
         fits_write_history(fptr, "AIPS WTSCAL =  1.0")?;
@@ -346,6 +653,18 @@ impl UvfitsWriter {
             None,
         )?;
 
+        // The CHECKSUM and DATASUM keywords are written when the file is
+        // finalised. Reserve space for them before any visibilities are
+        // written, so that the header never grows and shifts the
+        // visibilities (e.g. after they've been streamed elsewhere).
+        if options.checksum {
+            unsafe {
+                // ffhdef = fits_set_hdrsize
+                fitsio_sys::ffhdef(fptr, 2, &mut status);
+            }
+            fits_check_status(status)?;
+        }
+
         Ok(UvfitsWriter {
             path: path.to_path_buf(),
             fptr,
@@ -355,30 +674,84 @@ impl UvfitsWriter {
             centre_freq: centre_freq_hz,
             start_epoch,
             phase_centre,
-            array_pos,
-            antenna_names,
-            antenna_positions,
+            array_name: telescope.name.clone(),
+            array_pos: telescope.array_pos,
+            antenna_names: telescope.ant_names.clone(),
+            antenna_positions: telescope.ant_positions.clone(),
+            antenna_mntstas: (0..num_ants)
+                .map(|ant_idx| telescope.ant_mount(ant_idx).uvfits_mntsta())
+                .collect(),
+            antenna_axis_offsets_m: (0..num_ants)
+                .map(|ant_idx| telescope.ant_axis_offset_m(ant_idx) as f32)
+                .collect(),
             dut1,
+            baseline_encoding,
+            pol_frame,
+            uvw_frame,
+            polar_motion: options.polar_motion,
+            date_encoding: options.date_encoding,
+            inttim: options.inttim,
+            int_time_override: None,
+            retry_policy: RetryPolicy::never(),
+            retry_hook: None,
+            checksum: options.checksum,
         })
     }
 
+    /// Create a new uvfits file at the specified path, using the dimensions and
+    /// frequencies of a [`VisContext`]. The frequencies written are the centres
+    /// of the averaged channels (see [`VisContext::avg_frequencies_hz`]).
+    ///
+    /// # Errors
+    ///
+    /// See [`UvfitsWriter::new`].
     #[allow(clippy::too_many_arguments)]
     pub fn from_marlu<T: AsRef<Path>>(
         path: T,
         vis_ctx: &VisContext,
-        array_pos: LatLngHeight,
+        telescope: &Telescope,
+        phase_centre: RADec,
+        dut1: Duration,
+        obs_name: Option<&str>,
+        history: Option<&History>,
+    ) -> Result<UvfitsWriter, UvfitsWriteError> {
+        Self::from_marlu_with_options(
+            path,
+            vis_ctx,
+            telescope,
+            phase_centre,
+            dut1,
+            obs_name,
+            history,
+            UvfitsOptions::default(),
+        )
+    }
+
+    /// Like [`UvfitsWriter::from_marlu`], but laid out according to `options`
+    /// (see [`UvfitsWriter::new_with_options`]).
+    ///
+    /// # Errors
+    ///
+    /// See [`UvfitsWriter::new_with_options`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_marlu_with_options<T: AsRef<Path>>(
+        path: T,
+        vis_ctx: &VisContext,
+        telescope: &Telescope,
         phase_centre: RADec,
         dut1: Duration,
         obs_name: Option<&str>,
-        antenna_names: Vec<String>,
-        antenna_positions: Vec<XyzGeodetic>,
         history: Option<&History>,
+        options: UvfitsOptions,
     ) -> Result<UvfitsWriter, UvfitsWriteError> {
+        // The frequency axis is described by the centre of the middle averaged
+        // channel and its index (CRVAL4 and CRPIX4), which is consistent with
+        // the channel centres of `VisContext::avg_frequencies_hz`.
         let avg_freqs_hz: Vec<f64> = vis_ctx.avg_frequencies_hz();
         let avg_centre_chan = avg_freqs_hz.len() / 2;
         let avg_centre_freq_hz = avg_freqs_hz[avg_centre_chan];
 
-        Self::new(
+        Self::new_with_options(
             path,
             vis_ctx.num_avg_timesteps(),
             vis_ctx.sel_baselines.len(),
@@ -389,14 +762,99 @@ impl UvfitsWriter {
             avg_centre_chan,
             phase_centre,
             obs_name,
-            array_pos,
-            antenna_names,
-            antenna_positions,
+            telescope,
             dut1,
             history,
+            options,
         )
     }
 
+    /// Estimate the size of a uvfits file of the averaged visibilities of
+    /// `vis_ctx` from `num_ants` antennas, written with `options`, and the
+    /// memory used to write it in chunks of `timesteps_per_chunk` timesteps.
+    /// Nothing is written to disk.
+    ///
+    /// The primary header and the header of the antenna table are assumed to
+    /// take 4 and 2 FITS blocks, respectively, which is typical unless there
+    /// are many `HISTORY` cards.
+    pub fn estimate(
+        vis_ctx: &VisContext,
+        num_ants: usize,
+        options: &UvfitsOptions,
+        timesteps_per_chunk: usize,
+    ) -> OutputEstimate {
+        const FITS_BLOCK: u64 = 2880;
+        // The size of a row of the antenna table.
+        const AN_ROW_BYTES: u64 = 78;
+        let pad = |bytes: u64| (bytes + FITS_BLOCK - 1) / FITS_BLOCK * FITS_BLOCK - bytes;
+
+        let baseline_encoding = options
+            .baseline_encoding
+            .unwrap_or_else(|| BaselineEncoding::for_num_ants(num_ants));
+        let num_group_params =
+            group_params(baseline_encoding, options.date_encoding, options.inttim).len();
+        let row_floats =
+            num_group_params + 3 * options.pol_frame.num_pols() * vis_ctx.num_avg_chans();
+        let row_bytes = row_floats * std::mem::size_of::<f32>();
+
+        let num_rows = vis_ctx.num_avg_timesteps() * vis_ctx.sel_baselines.len();
+        let an_bytes = num_ants as u64 * AN_ROW_BYTES;
+        let extra_bytes =
+            6 * FITS_BLOCK + pad((num_rows * row_bytes) as u64) + an_bytes + pad(an_bytes);
+        OutputEstimate::new(vis_ctx, row_bytes, extra_bytes, timesteps_per_chunk)
+    }
+
+    /// Write a `HISTORY` card into the primary header for each of the given
+    /// [`InstrumentState`]s, e.g. those from
+    /// [`crate::MwaObsContext::instrument_states_in_selection`].
+    ///
+    /// This must be called before the antenna table is written.
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`UvfitsWriteError`] if a fits operation fails.
+    pub fn write_instrument_state_history<'a, I>(
+        &mut self,
+        states: I,
+    ) -> Result<(), UvfitsWriteError>
+    where
+        I: IntoIterator<Item = &'a InstrumentState>,
+    {
+        self.write_history_messages(states.into_iter().map(InstrumentState::as_comment))
+    }
+
+    /// Write a `HISTORY` card into the primary header for each message, e.g.
+    /// [`crate::selection::AntennaRenumbering::as_comments`].
+    ///
+    /// This must be called before the antenna table is written.
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`UvfitsWriteError`] if a fits operation fails.
+    pub fn write_history_messages<I, S>(&mut self, messages: I) -> Result<(), UvfitsWriteError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for message in messages {
+            fits_write_history(self.fptr, message.as_ref())
+                .map_err(|e| UvfitsWriteError::from(e).with_context(&self.path, "PRIMARY"))?;
+        }
+        Ok(())
+    }
+
+    /// Write `HISTORY` cards into the primary header describing the
+    /// [`Provenance`] of the file (see [`Provenance::as_history_cards`]).
+    ///
+    /// This must be called before the antenna table is written.
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`UvfitsWriteError`] if a fits operation fails.
+    pub fn write_provenance(&mut self, provenance: &Provenance) -> Result<(), UvfitsWriteError> {
+        self.write_history_messages(provenance.as_history_cards())
+    }
+
     /// Write the antenna table to a uvfits file. This consumes the
     /// [`UvfitsWriter`], preventing any further modifications.
     ///
@@ -407,8 +865,14 @@ impl UvfitsWriter {
     ///
     /// # Errors
     ///
-    /// Will return an [`UvfitsWriteError`] if a fits operation fails.
+    /// Will return an [`UvfitsWriteError`] if a fits operation fails; fitsio
+    /// errors are returned as [`UvfitsWriteError::FitsHdu`].
     pub fn write_uvfits_antenna_table(&mut self) -> Result<(), UvfitsWriteError> {
+        self.write_antenna_table_inner()
+            .map_err(|e| e.with_context(&self.path, "AIPS AN"))
+    }
+
+    fn write_antenna_table_inner(&mut self) -> Result<(), UvfitsWriteError> {
         if self.current_num_rows != self.total_num_rows {
             return Err(UvfitsWriteError::NotEnoughRowsWritten {
                 current: self.current_num_rows,
@@ -477,17 +941,17 @@ impl UvfitsWriter {
         // Antenna position reference frame
         fits_write_string(self.fptr, "FRAME", "ITRF", None)?;
 
-        // Get the Greenwich apparent sidereal time from ERFA.
-        let mjd = self.start_epoch.as_mjd_utc_days();
-        let gst = unsafe { eraGst06a(ERFA_DJM0, mjd.floor(), ERFA_DJM0, mjd.floor()) }.to_degrees();
+        // The Greenwich apparent sidereal time at 0h UTC on the reference date.
+        let midnight = Epoch::from_mjd_utc(mjd_utc_days(self.start_epoch).floor());
+        let gst = get_gast(midnight, self.dut1).to_degrees();
         fits_write_double(self.fptr, "GSTIA0", gst, None)?;
         fits_write_double(self.fptr, "DEGPDY", 3.60985e2, None)?; // Earth's rotation rate
 
         let date_truncated = get_truncated_date_string(self.start_epoch);
         fits_write_string(self.fptr, "RDATE", &date_truncated, None)?;
 
-        fits_write_double(self.fptr, "POLARX", 0.0, None)?;
-        fits_write_double(self.fptr, "POLARY", 0.0, None)?;
+        fits_write_double(self.fptr, "POLARX", self.polar_motion.x_arcsec, None)?;
+        fits_write_double(self.fptr, "POLARY", self.polar_motion.y_arcsec, None)?;
         fits_write_double(
             self.fptr,
             "UT1UTC",
@@ -499,7 +963,7 @@ impl UvfitsWriter {
         // AIPS 117 calls this TIMESYS, but Cotter calls in TIMSYS, so we do both.
         fits_write_string(self.fptr, "TIMSYS", "UTC", None)?;
         fits_write_string(self.fptr, "TIMESYS", "UTC", None)?;
-        fits_write_string(self.fptr, "ARRNAM", "MWA", None)?;
+        fits_write_string(self.fptr, "ARRNAM", &self.array_name, None)?;
         fits_write_int(self.fptr, "NUMORB", 0, None)?; // number of orbital parameters in table
         fits_write_int(self.fptr, "NOPCAL", 3, None)?; // Nr pol calibration values / IF(N_pcal)
         fits_write_int(self.fptr, "FREQID", -1, None)?; // Frequency setup number
@@ -532,10 +996,16 @@ impl UvfitsWriter {
         // Write to the table row by row.
         let mut x_c_str = CString::new("X")?.into_raw();
         let mut y_c_str = CString::new("Y")?.into_raw();
-        for (i, (pos, name)) in self
+        for (i, ((pos, name), (mut mntsta, mut axis_offset_m))) in self
             .antenna_positions
             .iter()
             .zip_eq(self.antenna_names.iter())
+            .zip(
+                self.antenna_mntstas
+                    .iter()
+                    .copied()
+                    .zip(self.antenna_axis_offsets_m.iter().copied()),
+            )
             .enumerate()
         {
             let row = i as i64 + 1;
@@ -586,12 +1056,23 @@ impl UvfitsWriter {
                     row,         /* I - first row to write (1 = 1st row)        */
                     1,           /* I - first vector element to write (1 = 1st) */
                     1,           /* I - number of values to write               */
-                    &mut 0,      /* I - array of values to write                */
+                    &mut mntsta, /* I - array of values to write                */
                     &mut status, /* IO - error status                           */
                 );
                 fits_check_status(status)?;
 
-                // No row 5?
+                // STAXOF. ffpcle = fits_write_col_flt
+                fitsio_sys::ffpcle(
+                    self.fptr,          /* I - FITS file pointer                       */
+                    5,                  /* I - number of column to write (1 = 1st col) */
+                    row,                /* I - first row to write (1 = 1st row)        */
+                    1,                  /* I - first vector element to write (1 = 1st) */
+                    1,                  /* I - number of values to write               */
+                    &mut axis_offset_m, /* I - array of values to write                */
+                    &mut status,        /* IO - error status                           */
+                );
+                fits_check_status(status)?;
+
                 // POLTYA
                 fitsio_sys::ffpcls(
                     self.fptr,    /* I - FITS file pointer                       */
@@ -672,6 +1153,21 @@ impl UvfitsWriter {
             drop(CString::from_raw(y_c_str));
         }
 
+        // The checksums must be the last change to each HDU.
+        if self.checksum {
+            for (hdu_num, hdu_name) in [(2, "AIPS AN"), (1, "PRIMARY")] {
+                let mut status = 0;
+                unsafe {
+                    // ffmahd = fits_movabs_hdu
+                    fitsio_sys::ffmahd(self.fptr, hdu_num, std::ptr::null_mut(), &mut status);
+                    // ffpcks = fits_write_chksum
+                    fitsio_sys::ffpcks(self.fptr, &mut status);
+                }
+                fits_check_status(status)
+                    .map_err(|e| FitsHduError::new(e, &self.path, hdu_name))?;
+            }
+        }
+
         // Close the fits file.
         trace!("closing fits file ({})", self.path.display());
         let mut status = 0;
@@ -684,6 +1180,43 @@ impl UvfitsWriter {
         Ok(())
     }
 
+    /// Override the `INTTIM` of subsequently written rows, e.g. when the rows
+    /// given to [`VisWrite::write_vis`] were averaged by different amounts
+    /// (baseline-dependent averaging). With `None`, the averaged integration
+    /// time of the [`VisContext`] is used. This does nothing if the file
+    /// doesn't have an `INTTIM` group parameter (see [`UvfitsOptions`]).
+    pub fn set_int_time(&mut self, int_time: Option<Duration>) {
+        self.int_time_override = int_time;
+    }
+
+    /// Retry writing rows of visibilities that fail transiently (see
+    /// [`is_transient_fits`]), e.g. on a network filesystem, according to
+    /// `policy`, calling `hook` before each retry. By default, writes aren't
+    /// retried. Rewriting a row is harmless, as each row is written to a fixed
+    /// position in the file.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy, hook: Option<RetryHook>) {
+        self.retry_policy = policy;
+        self.retry_hook = hook;
+    }
+
+    /// The indices of the first `DATE` group parameter and the `INTTIM` group
+    /// parameter (if there is one), and the number of group parameters.
+    fn group_param_layout(&self) -> (usize, Option<usize>, usize) {
+        let date_idx = 3 + self.baseline_encoding.group_params().len();
+        let end_of_dates = date_idx + self.date_encoding.num_params();
+        if self.inttim {
+            (date_idx, Some(end_of_dates), end_of_dates + 1)
+        } else {
+            (date_idx, None, end_of_dates)
+        }
+    }
+
+    /// The Julian date written to the `PZERO` of the `DATE` group parameter;
+    /// `DATE` values are days since this.
+    fn jd_zero(&self) -> f64 {
+        jd_utc_split(self.start_epoch).0 + 0.5
+    }
+
     /// Write a visibility row into the uvfits file.
     ///
     /// `tile_index1` and `tile_index2` are expected to be zero indexed; they
@@ -713,16 +1246,26 @@ impl UvfitsWriter {
             });
         }
 
-        let jd_trunc = self.start_epoch.as_jde_utc_days().floor() + 0.5;
-        let jd_frac = epoch.as_jde_utc_days() - jd_trunc;
-
-        self.buffer.extend_from_slice(&[
-            (uvw.u / VEL_C) as f32,
-            (uvw.v / VEL_C) as f32,
-            (uvw.w / VEL_C) as f32,
-            encode_uvfits_baseline(tile_index1 + 1, tile_index2 + 1) as f32,
-            jd_frac as f32,
-        ]);
+        let jd_frac = jd_utc_days_since(epoch, self.jd_zero());
+
+        let (date_idx, inttim_idx, num_group_params) = self.group_param_layout();
+        self.buffer.resize(num_group_params, 0.0);
+        self.buffer[0] = (uvw.u / VEL_C) as f32;
+        self.buffer[1] = (uvw.v / VEL_C) as f32;
+        self.buffer[2] = (uvw.w / VEL_C) as f32;
+        self.baseline_encoding.fill_baseline_params(
+            &mut self.buffer[3..date_idx],
+            tile_index1,
+            tile_index2,
+        );
+        let end_of_dates = inttim_idx.unwrap_or(num_group_params);
+        self.date_encoding
+            .fill_date_params(&mut self.buffer[date_idx..end_of_dates], jd_frac);
+        if let Some(inttim_idx) = inttim_idx {
+            self.buffer[inttim_idx] = self
+                .int_time_override
+                .map_or(0.0, |int_time| int_time.in_seconds() as f32);
+        }
         self.buffer.extend_from_slice(vis);
 
         Self::write_vis_row_inner(self.fptr, &mut self.current_num_rows, &mut self.buffer)?;
@@ -777,27 +1320,14 @@ impl VisWrite for UvfitsWriter {
         vis_ctx: &VisContext,
         draw_progress: bool,
     ) -> Result<(), IOError> {
-        let sel_dims = vis_ctx.sel_dims();
-        if vis.dim() != sel_dims {
-            return Err(IOError::BadArrayShape(BadArrayShape {
-                argument: "vis",
-                function: "write_vis_marlu",
-                expected: format!("{:?}", sel_dims),
-                received: format!("{:?}", vis.dim()),
-            }));
-        }
-        if weights.dim() != sel_dims {
-            return Err(IOError::BadArrayShape(BadArrayShape {
-                argument: "weights",
-                function: "write_vis_marlu",
-                expected: format!("{:?}", sel_dims),
-                received: format!("{:?}", weights.dim()),
-            }));
-        }
+        vis_ctx.check_sel_dims(vis.dim(), weights.dim(), "UvfitsWriter::write_vis")?;
 
         let num_avg_timesteps = vis_ctx.num_avg_timesteps();
         let num_avg_chans = vis_ctx.num_avg_chans();
-        let num_vis_pols = vis_ctx.num_vis_pols;
+        let pol_frame = self.pol_frame;
+        let num_vis_pols = pol_frame.num_pols();
+        let weight_factor = pol_frame.weight_factor();
+        let vis_scale = vis_ctx.vis_scale;
         let num_avg_rows = num_avg_timesteps * vis_ctx.sel_baselines.len();
 
         // Progress bars
@@ -830,56 +1360,86 @@ impl VisWrite for UvfitsWriter {
             self.total_num_rows
         );
 
+        // The units of the visibilities are only known once they're given to
+        // us. BSCALE stays at 1, because it would also scale the weights;
+        // `vis_scale` is applied to the visibilities as they're written.
+        if self.current_num_rows == 0 {
+            fits_write_string(self.fptr, "BUNIT", vis_ctx.vis_unit.fits_unit(), None)
+                .map_err(|e| UvfitsWriteError::from(e).with_context(&self.path, "PRIMARY"))?;
+        }
+
         // Ensure our buffer is the correct size. Reusing the buffer means we
         // avoid a heap allocation every time this function is called.
+        let baseline_encoding = self.baseline_encoding;
+        let date_encoding = self.date_encoding;
+        let (date_idx, inttim_idx, num_group_params) = self.group_param_layout();
+        let end_of_dates = inttim_idx.unwrap_or(num_group_params);
+        let inttim = self
+            .int_time_override
+            .unwrap_or_else(|| vis_ctx.avg_int_time())
+            .in_seconds() as f32;
         self.buffer
-            .resize(5 + 3 * num_vis_pols * num_avg_chans, 0.0);
+            .resize(num_group_params + 3 * num_vis_pols * num_avg_chans, 0.0);
         let mut avg_weight: f32;
         let mut avg_flag: bool;
         let mut avg_jones: Jones<f32>;
 
-        let jd_trunc = self.start_epoch.as_jde_utc_days().floor() + 0.5;
+        let jd_zero = self.jd_zero();
 
         for (avg_centroid_timestamp, jones_chunk, weight_chunk) in izip!(
             vis_ctx.timeseries(true, true),
             vis.axis_chunks_iter(Axis(0), vis_ctx.avg_time),
             weights.axis_chunks_iter(Axis(0), vis_ctx.avg_time),
         ) {
-            let jd_frac = (avg_centroid_timestamp.as_jde_utc_days() - jd_trunc) as f32;
-            let prec_info = precess_time(
+            let jd_frac = jd_utc_days_since(avg_centroid_timestamp, jd_zero);
+            let prec_info = precess_time_with_polar_motion(
                 self.array_pos.longitude_rad,
                 self.array_pos.latitude_rad,
                 self.phase_centre,
                 avg_centroid_timestamp,
                 self.dut1,
+                self.polar_motion,
             );
 
-            let tiles_xyz_precessed = prec_info.precess_xyz_parallel(&self.antenna_positions);
+            let uvws = prec_info.baseline_uvws(
+                self.uvw_frame,
+                &self.antenna_positions,
+                &vis_ctx.sel_baselines,
+            );
 
-            for ((ant1_idx, ant2_idx), jones_chunk, weight_chunk) in izip!(
+            for ((ant1_idx, ant2_idx), uvw, jones_chunk, weight_chunk) in izip!(
                 vis_ctx.sel_baselines.iter().copied(),
+                uvws,
                 jones_chunk.axis_iter(Axis(2)),
                 weight_chunk.axis_iter(Axis(2)),
             ) {
-                let baseline_xyz_precessed =
-                    tiles_xyz_precessed[ant1_idx] - tiles_xyz_precessed[ant2_idx];
-                let uvw = UVW::from_xyz(baseline_xyz_precessed, prec_info.hadec_j2000) / VEL_C;
+                let uvw = uvw / VEL_C;
 
                 self.buffer[0] = uvw.u as f32;
                 self.buffer[1] = uvw.v as f32;
                 self.buffer[2] = uvw.w as f32;
-                self.buffer[3] = encode_uvfits_baseline(ant1_idx + 1, ant2_idx + 1) as f32;
-                self.buffer[4] = jd_frac;
+                baseline_encoding.fill_baseline_params(
+                    &mut self.buffer[3..date_idx],
+                    ant1_idx,
+                    ant2_idx,
+                );
+                date_encoding.fill_date_params(&mut self.buffer[date_idx..end_of_dates], jd_frac);
+                if let Some(inttim_idx) = inttim_idx {
+                    self.buffer[inttim_idx] = inttim;
+                }
 
                 // MWA/CASA/AOFlagger visibility order is XX,XY,YX,YY
-                // UVFits visibility order is XX,YY,XY,YX
+                // UVFits visibility order is XX,YY,XY,YX (or RR,LL,RL,LR)
 
                 for (jones_chunk, weight_chunk, vis_chunk) in izip!(
                     jones_chunk.axis_chunks_iter(Axis(1), vis_ctx.avg_freq),
                     weight_chunk.axis_chunks_iter(Axis(1), vis_ctx.avg_freq),
-                    self.buffer[5..].chunks_exact_mut(3 * num_vis_pols),
+                    self.buffer[num_group_params..].chunks_exact_mut(3 * num_vis_pols),
                 ) {
-                    avg_weight = weight_chunk[[0, 0]];
+                    WeightFlags {
+                        weight: avg_weight,
+                        flag: avg_flag,
+                    } = WeightFlags::from_signed(weight_chunk[[0, 0]]);
                     avg_jones = jones_chunk[[0, 0]];
 
                     if !vis_ctx.trivial_averaging() {
@@ -891,33 +1451,35 @@ impl VisWrite for UvfitsWriter {
                             avg_flag
                         );
                     }
-
-                    // vis_chunk has 12 elements if num_vis_pols is 4, but, it
-                    // is possible that this is 2 instead. By iterating over the
-                    // Jones elements and applying them, we write the correct
-                    // polarisations for however long vis_chunk actually is.
-                    vis_chunk
-                        .iter_mut()
-                        .zip([
-                            avg_jones[0].re,
-                            avg_jones[0].im,
-                            avg_weight,
-                            avg_jones[3].re,
-                            avg_jones[3].im,
-                            avg_weight,
-                            avg_jones[1].re,
-                            avg_jones[1].im,
-                            avg_weight,
-                            avg_jones[2].re,
-                            avg_jones[2].im,
-                            avg_weight,
-                        ])
-                        .for_each(|(vis_chunk_element, vis)| {
-                            *vis_chunk_element = vis;
-                        });
+                    if avg_flag && vis_ctx.averaging_policy == AveragingPolicy::Nan {
+                        avg_jones = Jones::nan();
+                    }
+                    avg_jones = pol_frame.convert(avg_jones * vis_scale);
+                    let signed_weight =
+                        WeightFlags::new(avg_weight * weight_factor, avg_flag).to_signed();
+
+                    // vis_chunk has 12 elements if all 4 pols are written, but
+                    // the pol frame may write fewer.
+                    for (vis_pol_chunk, &pol_idx) in vis_chunk
+                        .chunks_exact_mut(3)
+                        .zip(pol_frame.uvfits_pol_idxs())
+                    {
+                        vis_pol_chunk[0] = avg_jones[pol_idx].re;
+                        vis_pol_chunk[1] = avg_jones[pol_idx].im;
+                        vis_pol_chunk[2] = signed_weight;
+                    }
                 }
 
-                Self::write_vis_row_inner(self.fptr, &mut self.current_num_rows, &mut self.buffer)?;
+                let (fptr, current_num_rows, buffer) =
+                    (self.fptr, &mut self.current_num_rows, &mut self.buffer);
+                retry(
+                    &self.retry_policy,
+                    "write uvfits row",
+                    self.retry_hook.as_ref(),
+                    is_transient_fits,
+                    || Self::write_vis_row_inner(fptr, current_num_rows, buffer),
+                )
+                .map_err(|e| FitsHduError::new(e, &self.path, "PRIMARY"))?;
                 write_progress.inc(1);
             }
         }
@@ -933,48 +1495,378 @@ impl VisWrite for UvfitsWriter {
     }
 }
 
-fn fits_write_int(
-    fptr: *mut fitsio_sys::fitsfile,
-    keyname: &str,
-    value: i64,
-    comment: Option<&str>,
-) -> Result<(), FitsioOrCStringError> {
-    let mut status = 0;
-    let keyname = CString::new(keyname)?;
-    let comment = match comment {
-        Some(c) => Some(CString::new(c)?),
-        None => None,
-    };
-    unsafe {
-        // ffukyj = fits_update_key_lng
-        fitsio_sys::ffukyj(
-            fptr,                                                    /* I - FITS file pointer  */
-            keyname.as_ptr(),                                        /* I - keyword name       */
-            value,                                                   /* I - keyword value      */
-            comment.map(|c| c.as_ptr()).unwrap_or(std::ptr::null()), /* I - keyword comment    */
-            &mut status,                                             /* IO - error status      */
-        );
-    }
-    fits_check_status(status)?;
-    Ok(())
+/// A [`VisRead`] source of the visibilities in a uvfits file, e.g. one written
+/// by [`UvfitsWriter`]. Only files with all four linear polarisations, and the
+/// same baselines in the same order at every timestep, are supported.
+///
+/// The weight of each visibility is the weight of its XX polarisation, and is
+/// negative if the weight of any of its polarisations is negative (uvfits
+/// encodes flags in the signs of the weights).
+pub struct UvfitsReader {
+    path: PathBuf,
+    fptr: FitsFile,
+    vis_ctx: VisContext,
+    /// A buffer for a row of visibilities, reused between reads.
+    buffer: Vec<f32>,
 }
 
-fn fits_write_double(
-    fptr: *mut fitsio_sys::fitsfile,
-    keyname: &str,
-    value: f64,
-    comment: Option<&str>,
-) -> Result<(), FitsioOrCStringError> {
-    let mut status = 0;
-    let keyname = CString::new(keyname)?;
-    let comment = match comment {
-        Some(c) => Some(CString::new(c)?),
-        None => None,
-    };
-    unsafe {
-        // ffukyd = fits_update_key_dbl
-        fitsio_sys::ffukyd(
-            fptr,                                                    /* I - FITS file pointer  */
+impl UvfitsReader {
+    /// Open a uvfits file for reading. All of the group parameters are read
+    /// to determine the times and baselines of the visibilities.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read, or isn't laid out in a way
+    /// that this reader supports. fitsio errors are returned as
+    /// [`IOError::FitsHdu`], with the path and HDU.
+    pub fn new<T: AsRef<Path>>(path: T) -> Result<Self, IOError> {
+        let path = path.as_ref();
+        Self::open(path).map_err(|e| e.with_fits_context(path, "PRIMARY"))
+    }
+
+    fn open(path: &Path) -> Result<Self, IOError> {
+        let unsupported = |reason: &str| IOError::UnsupportedInput {
+            path: path.display().to_string(),
+            reason: reason.to_string(),
+        };
+
+        let mut fptr = FitsFile::open(path)?;
+        let hdu = fptr.primary_hdu()?;
+        let num_pols: i64 = hdu.read_key(&mut fptr, "NAXIS3")?;
+        let stokes = (
+            hdu.read_key::<i64>(&mut fptr, "CRVAL3")?,
+            hdu.read_key::<i64>(&mut fptr, "CDELT3")?,
+        );
+        if num_pols != 4 || stokes != PolFrame::Linear.uvfits_stokes() {
+            return Err(unsupported(
+                "only XX, YY, XY and YX polarisations can be read",
+            ));
+        }
+        let num_chans: i64 = hdu.read_key(&mut fptr, "NAXIS4")?;
+        let centre_freq_hz: f64 = hdu.read_key(&mut fptr, "CRVAL4")?;
+        let freq_resolution_hz: f64 = hdu.read_key(&mut fptr, "CDELT4")?;
+        let centre_freq_pix: f64 = hdu.read_key(&mut fptr, "CRPIX4")?;
+        let vis_unit = match hdu.read_key::<String>(&mut fptr, "BUNIT") {
+            Ok(unit) if unit.eq_ignore_ascii_case("JY") => VisUnit::Jansky,
+            _ => VisUnit::Uncalibrated,
+        };
+
+        // Group parameters.
+        let pcount: i64 = hdu.read_key(&mut fptr, "PCOUNT")?;
+        let gcount: i64 = hdu.read_key(&mut fptr, "GCOUNT")?;
+        let mut ptypes = Vec::with_capacity(pcount as usize);
+        let mut pscals = Vec::with_capacity(pcount as usize);
+        let mut pzeros = Vec::with_capacity(pcount as usize);
+        for i in 1..=pcount {
+            ptypes.push(hdu.read_key::<String>(&mut fptr, &format!("PTYPE{i}"))?);
+            pscals.push(
+                hdu.read_key::<f64>(&mut fptr, &format!("PSCAL{i}"))
+                    .unwrap_or(1.0),
+            );
+            pzeros.push(
+                hdu.read_key::<f64>(&mut fptr, &format!("PZERO{i}"))
+                    .unwrap_or(0.0),
+            );
+        }
+        let param_idx = |ptype: &str| ptypes.iter().position(|p| p == ptype);
+        let date_idxs: Vec<usize> = (0..ptypes.len()).filter(|&i| ptypes[i] == "DATE").collect();
+        let jd_zero = match date_idxs.first() {
+            Some(&i) => pzeros[i],
+            None => return Err(unsupported("there is no DATE group parameter")),
+        };
+        let baseline_idxs = match (
+            param_idx("BASELINE"),
+            param_idx("ANTENNA1"),
+            param_idx("ANTENNA2"),
+        ) {
+            (Some(i), _, _) => vec![i],
+            (None, Some(i), Some(j)) => vec![i, j],
+            _ => return Err(unsupported("there are no baseline group parameters")),
+        };
+        let inttim_idx = param_idx("INTTIM");
+
+        // The days since `jd_zero` and the baseline of every row.
+        let mut group_params = vec![0.0_f32; pcount as usize];
+        let mut row_days = Vec::with_capacity(gcount as usize);
+        let mut row_baselines = Vec::with_capacity(gcount as usize);
+        let mut inttim = None;
+        let mut status = 0;
+        for group in 1..=gcount {
+            unsafe {
+                // ffggpe = fits_read_grppar_flt
+                fitsio_sys::ffggpe(
+                    fptr.as_raw(),             /* I - FITS file pointer                       */
+                    group,                     /* I - group to read (1 = 1st group)           */
+                    1,                         /* I - first vector element to read (1 = 1st)  */
+                    pcount,                    /* I - number of values to read                */
+                    group_params.as_mut_ptr(), /* O - array of values that are returned       */
+                    &mut status,               /* IO - error status                           */
+                );
+            }
+            fits_check_status(status)?;
+            let value = |i: usize| f64::from(group_params[i]) * pscals[i] + pzeros[i];
+            row_days.push(date_idxs.iter().map(|&i| value(i)).sum::<f64>() - jd_zero);
+            row_baselines.push(match baseline_idxs[..] {
+                [i] => decode_uvfits_baseline(value(i).round() as usize),
+                [i, j] => (value(i).round() as usize, value(j).round() as usize),
+                _ => unreachable!(),
+            });
+            if group == 1 {
+                inttim = inttim_idx.map(&value);
+            }
+        }
+        if row_baselines
+            .iter()
+            .any(|&(ant1, ant2)| ant1 == 0 || ant2 == 0)
+        {
+            return Err(unsupported("not all rows have valid baselines"));
+        }
+
+        // Rows are grouped by timestep, and every timestep must have the same
+        // baselines. Rows of the same timestep are within a millisecond.
+        let same_time = |a: f64, b: f64| (a - b).abs() < 1e-3 / 86400.0;
+        let num_baselines = row_days
+            .iter()
+            .take_while(|&&days| same_time(days, row_days[0]))
+            .count();
+        if num_baselines == 0 || row_days.len() % num_baselines != 0 {
+            return Err(unsupported(
+                "timesteps don't all have the same number of rows",
+            ));
+        }
+        let sel_baselines: Vec<(usize, usize)> = row_baselines[..num_baselines]
+            .iter()
+            .map(|&(ant1, ant2)| (ant1 - 1, ant2 - 1))
+            .collect();
+        let num_timesteps = row_days.len() / num_baselines;
+        for (days, baselines) in row_days
+            .chunks_exact(num_baselines)
+            .zip(row_baselines.chunks_exact(num_baselines))
+        {
+            if days.iter().any(|&d| !same_time(d, days[0]))
+                || baselines[..] != row_baselines[..num_baselines]
+            {
+                return Err(unsupported("timesteps don't all have the same baselines"));
+            }
+        }
+
+        // Without an INTTIM, the integration time is the mean time between
+        // timesteps. A single DATE is single-precision and only precise to a
+        // few milliseconds, so round to 10 ms; otherwise round to the
+        // millisecond.
+        let int_time_ms = match (inttim, num_timesteps) {
+            (Some(inttim), _) => (inttim * 1e3).round(),
+            (None, 1) => return Err(unsupported(
+                "the integration time can't be determined from a single timestep without INTTIM",
+            )),
+            (None, _) => {
+                let int_time_ms = (row_days[row_days.len() - 1] - row_days[0]) * 86400e3
+                    / (num_timesteps - 1) as f64;
+                if date_idxs.len() == 1 {
+                    (int_time_ms / 10.0).round() * 10.0
+                } else {
+                    int_time_ms.round()
+                }
+            }
+        };
+        let int_time = Duration::from_f64(int_time_ms, Unit::Millisecond);
+        let first_centroid =
+            Epoch::from_jde_utc(jd_zero) + Duration::from_f64(row_days[0], Unit::Day);
+
+        let vis_ctx = VisContext {
+            num_sel_timesteps: num_timesteps,
+            start_timestamp: first_centroid - 0.5 * int_time,
+            int_time,
+            num_sel_chans: num_chans as usize,
+            start_freq_hz: centre_freq_hz - (centre_freq_pix - 1.0) * freq_resolution_hz,
+            freq_resolution_hz,
+            sel_baselines,
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit,
+            vis_scale: 1.0,
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            fptr,
+            vis_ctx,
+            buffer: vec![],
+        })
+    }
+
+    /// Verify the `CHECKSUM` and `DATASUM` keywords of every HDU of the file
+    /// (see [`UvfitsOptions::checksum`]). Returns `false` if any HDU doesn't
+    /// have them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::BadChecksum`] if a checksum doesn't match the
+    /// contents of its HDU, i.e. the file is truncated or corrupt.
+    pub fn verify_checksums(&mut self) -> Result<bool, IOError> {
+        let fptr = unsafe { self.fptr.as_raw() };
+        let mut num_hdus = 0;
+        let mut status = 0;
+        unsafe {
+            // ffthdu = fits_get_num_hdus
+            fitsio_sys::ffthdu(fptr, &mut num_hdus, &mut status);
+        }
+        fits_check_status(status).map_err(|e| FitsHduError::new(e, &self.path, "PRIMARY"))?;
+
+        let mut all_present = true;
+        for hdu_num in 1..=num_hdus {
+            // 1 if the keyword is correct, 0 if it's missing and -1 if it's
+            // incorrect.
+            let (mut data_ok, mut hdu_ok) = (0, 0);
+            unsafe {
+                // ffmahd = fits_movabs_hdu
+                fitsio_sys::ffmahd(fptr, hdu_num, std::ptr::null_mut(), &mut status);
+                // ffvcks = fits_verify_chksum
+                fitsio_sys::ffvcks(fptr, &mut data_ok, &mut hdu_ok, &mut status);
+            }
+            fits_check_status(status)
+                .map_err(|e| FitsHduError::new(e, &self.path, &format!("{hdu_num}")))?;
+            for (ok, keyword) in [(data_ok, "DATASUM"), (hdu_ok, "CHECKSUM")] {
+                match ok {
+                    -1 => {
+                        return Err(IOError::BadChecksum {
+                            path: self.path.display().to_string(),
+                            hdu: hdu_num as usize,
+                            keyword,
+                        })
+                    }
+                    0 => all_present = false,
+                    _ => (),
+                }
+            }
+        }
+
+        // The visibilities are read from the primary HDU.
+        unsafe {
+            // ffmahd = fits_movabs_hdu
+            fitsio_sys::ffmahd(fptr, 1, std::ptr::null_mut(), &mut status);
+        }
+        fits_check_status(status).map_err(|e| FitsHduError::new(e, &self.path, "PRIMARY"))?;
+        Ok(all_present)
+    }
+}
+
+impl VisRead for UvfitsReader {
+    fn vis_ctx(&self) -> &VisContext {
+        &self.vis_ctx
+    }
+
+    fn read_vis(
+        &mut self,
+        mut vis: ArrayViewMut3<Jones<f32>>,
+        mut weights: ArrayViewMut3<f32>,
+        chunk_ctx: &VisContext,
+    ) -> Result<(), IOError> {
+        let location = locate_read_chunk(
+            &self.vis_ctx,
+            chunk_ctx,
+            vis.dim(),
+            weights.dim(),
+            "UvfitsReader::read_vis",
+        )?;
+        let num_baselines = self.vis_ctx.sel_baselines.len();
+        // Only read the selected channels of each row.
+        let first_elem = 1 + 12 * location.chans.start;
+        self.buffer.resize(12 * location.chans.len(), 0.0);
+
+        let mut status = 0;
+        for (timestep, mut vis, mut weights) in izip!(
+            location.timesteps.clone(),
+            vis.outer_iter_mut(),
+            weights.outer_iter_mut(),
+        ) {
+            for (&baseline_idx, mut vis, mut weights) in izip!(
+                &location.baseline_idxs,
+                vis.axis_iter_mut(Axis(1)),
+                weights.axis_iter_mut(Axis(1)),
+            ) {
+                let group = 1 + timestep * num_baselines + baseline_idx;
+                unsafe {
+                    // ffgpve = fits_read_img_flt
+                    fitsio_sys::ffgpve(
+                        self.fptr.as_raw(),       /* I - FITS file pointer                       */
+                        group as i64,             /* I - group to read (1 = 1st group)           */
+                        first_elem as i64,        /* I - first vector element to read (1 = 1st)  */
+                        self.buffer.len() as i64, /* I - number of values to read                */
+                        0.0,                      /* I - value for undefined pixels              */
+                        self.buffer.as_mut_ptr(), /* O - array of values that are returned       */
+                        &mut 0,                   /* O - set to 1 if any values are null; else 0 */
+                        &mut status,              /* IO - error status                           */
+                    );
+                }
+                fits_check_status(status)
+                    .map_err(|e| FitsHduError::new(e, &self.path, "PRIMARY"))?;
+
+                // UVFits visibility order is XX,YY,XY,YX; Jones order is
+                // XX,XY,YX,YY.
+                for (chan, jones, weight) in izip!(
+                    self.buffer.chunks_exact(12),
+                    vis.iter_mut(),
+                    weights.iter_mut()
+                ) {
+                    for (pol, &uvfits_pol) in PolFrame::Linear.uvfits_pol_idxs().iter().enumerate()
+                    {
+                        jones[uvfits_pol] = Complex::new(chan[3 * pol], chan[3 * pol + 1]);
+                    }
+                    // A visibility is flagged if the weight of any of its
+                    // polarisations is negative.
+                    let flag = chan.chunks_exact(3).any(|pol| is_flagged(pol[2]));
+                    *weight = WeightFlags::new(chan[2], flag).to_signed();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn fits_write_int(
+    fptr: *mut fitsio_sys::fitsfile,
+    keyname: &str,
+    value: i64,
+    comment: Option<&str>,
+) -> Result<(), FitsioOrCStringError> {
+    let mut status = 0;
+    let keyname = CString::new(keyname)?;
+    let comment = match comment {
+        Some(c) => Some(CString::new(c)?),
+        None => None,
+    };
+    unsafe {
+        // ffukyj = fits_update_key_lng
+        fitsio_sys::ffukyj(
+            fptr,                                                    /* I - FITS file pointer  */
+            keyname.as_ptr(),                                        /* I - keyword name       */
+            value,                                                   /* I - keyword value      */
+            comment.map(|c| c.as_ptr()).unwrap_or(std::ptr::null()), /* I - keyword comment    */
+            &mut status,                                             /* IO - error status      */
+        );
+    }
+    fits_check_status(status)?;
+    Ok(())
+}
+
+fn fits_write_double(
+    fptr: *mut fitsio_sys::fitsfile,
+    keyname: &str,
+    value: f64,
+    comment: Option<&str>,
+) -> Result<(), FitsioOrCStringError> {
+    let mut status = 0;
+    let keyname = CString::new(keyname)?;
+    let comment = match comment {
+        Some(c) => Some(CString::new(c)?),
+        None => None,
+    };
+    unsafe {
+        // ffukyd = fits_update_key_dbl
+        fitsio_sys::ffukyd(
+            fptr,                                                    /* I - FITS file pointer  */
             keyname.as_ptr(),                                        /* I - keyword name       */
             value,                                                   /* I - keyword value      */
             -15,                                                     /* I - no of decimals     */
@@ -1051,7 +1943,7 @@ fn fits_write_history(
 }
 
 #[derive(thiserror::Error, Debug)]
-pub(super) enum FitsioOrCStringError {
+pub(crate) enum FitsioOrCStringError {
     #[error(transparent)]
     Fitsio(#[from] fitsio::errors::Error),
 
@@ -1079,8 +1971,12 @@ mod tests {
         constants::{
             COTTER_MWA_HEIGHT_METRES, COTTER_MWA_LATITUDE_RADIANS, COTTER_MWA_LONGITUDE_RADIANS,
         },
+        context::tests::get_vis_ctx,
+        hifitime::Unit,
+        ndarray::{array, s, Array3},
         selection::VisSelection,
-        ENH,
+        weights::{flags_to_negative_weights, split_weight_sign},
+        MountType, ENH,
     };
 
     macro_rules! assert_short_string_keys_eq {
@@ -1635,12 +2531,10 @@ mod tests {
         let mut u = UvfitsWriter::from_marlu(
             tmp_uvfits_file.path(),
             &vis_ctx,
-            array_pos,
+            &Telescope::new("MWA", array_pos, names, positions),
             phase_centre,
             Duration::from_total_nanoseconds(0),
             Some(obs_name),
-            names,
-            positions,
             None,
         )
         .unwrap();
@@ -1715,12 +2609,10 @@ mod tests {
         let mut u = UvfitsWriter::from_marlu(
             tmp_uvfits_file.path(),
             &vis_ctx,
-            array_pos,
+            &Telescope::new("MWA", array_pos, names, positions),
             RADec::from_mwalib_phase_or_pointing(&corr_ctx.metafits_context),
             Duration::from_total_nanoseconds(0),
             Some(&corr_ctx.metafits_context.obs_name),
-            names,
-            positions,
             None,
         )
         .unwrap();
@@ -1782,9 +2674,7 @@ mod tests {
             3,
             RADec::new_degrees(0.0, 60.0),
             Some("test"),
-            LatLngHeight::new_mwa(),
-            names,
-            positions,
+            &Telescope::new("MWA", LatLngHeight::new_mwa(), names, positions),
             Duration::from_total_nanoseconds(0),
             None,
         )
@@ -1817,6 +2707,807 @@ mod tests {
         u.finalise().unwrap();
     }
 
+    #[test]
+    fn antenna_mounts_and_offsets() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let start_epoch = Epoch::from_gpst_seconds(1065880128.0);
+        let telescope = Telescope {
+            ant_mounts: vec![MountType::AltAz, MountType::Equatorial],
+            ant_axis_offsets_m: vec![0.0, 1.5],
+            ..Telescope::new(
+                "mixed",
+                LatLngHeight::new_mwa(),
+                vec!["ant1".into(), "ant2".into(), "ant3".into()],
+                vec![XyzGeodetic::default(); 3],
+            )
+        };
+
+        let mut u = UvfitsWriter::new(
+            tmp_uvfits_file.path(),
+            1,
+            1,
+            1,
+            start_epoch,
+            40e3,
+            170e6,
+            0,
+            RADec::new_degrees(0.0, 60.0),
+            None,
+            &telescope,
+            Duration::from_total_nanoseconds(0),
+            None,
+        )
+        .unwrap();
+        u.write_vis_row(UVW::default(), 0, 1, start_epoch, &[0.0; 12])
+            .unwrap();
+        u.finalise().unwrap();
+
+        // The third antenna has the defaults.
+        let mut fptr = FitsFile::open(tmp_uvfits_file.path()).unwrap();
+        let hdu = fptr.hdu("AIPS AN").unwrap();
+        let mntstas: Vec<i32> = hdu.read_col(&mut fptr, "MNTSTA").unwrap();
+        let axis_offsets_m: Vec<f32> = hdu.read_col(&mut fptr, "STAXOF").unwrap();
+        assert_eq!(mntstas, [0, 1, 0]);
+        assert_abs_diff_eq!(axis_offsets_m[..], [0.0, 1.5, 0.0][..]);
+    }
+
+    #[test]
+    fn baseline_encoding_for_num_ants() {
+        assert_eq!(
+            BaselineEncoding::for_num_ants(128),
+            BaselineEncoding::Miriad
+        );
+        assert_eq!(
+            BaselineEncoding::for_num_ants(MAX_MIRIAD_ANTENNA),
+            BaselineEncoding::Miriad
+        );
+        assert_eq!(
+            BaselineEncoding::for_num_ants(MAX_MIRIAD_ANTENNA + 1),
+            BaselineEncoding::AntennaParams
+        );
+    }
+
+    #[test]
+    fn miriad_baseline_encoding_rejects_too_many_antennas() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let num_ants = MAX_MIRIAD_ANTENNA + 1;
+        let names = (0..num_ants).map(|i| format!("Tile{i}")).collect();
+        let positions = vec![XyzGeodetic::default(); num_ants];
+
+        let result = UvfitsWriter::new_with_options(
+            tmp_uvfits_file.path(),
+            1,
+            1,
+            1,
+            Epoch::from_gpst_seconds(1065880128.0),
+            40e3,
+            170e6,
+            0,
+            RADec::new_degrees(0.0, 60.0),
+            None,
+            &Telescope::new("MWA", LatLngHeight::new_mwa(), names, positions),
+            Duration::from_total_nanoseconds(0),
+            None,
+            UvfitsOptions {
+                baseline_encoding: Some(BaselineEncoding::Miriad),
+                ..Default::default()
+            },
+        );
+        assert!(matches!(
+            result,
+            Err(UvfitsWriteError::TooManyAntennas { num_ants: 2048, .. })
+        ));
+    }
+
+    #[test]
+    fn fits_errors_have_context() {
+        // The parent of the path is a file, so cfitsio can't create it.
+        let tmp_file = NamedTempFile::new().unwrap();
+        let path = tmp_file.path().join("test.uvfits");
+        let result = UvfitsWriter::new(
+            &path,
+            1,
+            1,
+            1,
+            Epoch::from_gpst_seconds(1065880128.0),
+            40e3,
+            170e6,
+            0,
+            RADec::new_degrees(0.0, 60.0),
+            None,
+            &Telescope::new(
+                "MWA",
+                LatLngHeight::new_mwa(),
+                vec!["Tile0".into()],
+                vec![XyzGeodetic::default()],
+            ),
+            Duration::from_total_nanoseconds(0),
+            None,
+        );
+        match result {
+            Err(UvfitsWriteError::FitsHdu(e)) => {
+                assert_eq!(e.path, path);
+                assert_eq!(e.hdu, "PRIMARY");
+                assert!(e.status.is_some());
+            }
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(_) => panic!("expected an error"),
+        }
+
+        // An empty file isn't a FITS file.
+        let result = UvfitsReader::new(tmp_file.path());
+        assert!(matches!(
+            result,
+            Err(IOError::FitsHdu(FitsHduError { ref path, .. })) if path == tmp_file.path()
+        ));
+    }
+
+    #[test]
+    fn antenna_params_baseline_encoding() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let num_timesteps = 1;
+        let num_baselines = 3;
+        let num_chans = 2;
+        let start_epoch = Epoch::from_gpst_seconds(1065880128.0);
+
+        let names = vec!["Tile1".into(), "Tile2".into(), "Tile3".into()];
+        let positions = vec![XyzGeodetic::default(); names.len()];
+
+        let mut u = UvfitsWriter::new_with_options(
+            tmp_uvfits_file.path(),
+            num_timesteps,
+            num_baselines,
+            num_chans,
+            start_epoch,
+            40e3,
+            170e6,
+            1,
+            RADec::new_degrees(0.0, 60.0),
+            Some("test"),
+            &Telescope::new("MWA", LatLngHeight::new_mwa(), names, positions),
+            Duration::from_total_nanoseconds(0),
+            None,
+            UvfitsOptions {
+                baseline_encoding: Some(BaselineEncoding::AntennaParams),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let baselines = [(0, 1), (0, 2), (1, 2)];
+        for &(tile1, tile2) in &baselines {
+            u.write_vis_row(
+                UVW::default(),
+                tile1,
+                tile2,
+                start_epoch,
+                &vec![0.0; num_chans * 4 * 3],
+            )
+            .unwrap();
+        }
+        u.finalise().unwrap();
+
+        let mut fptr = fits_open!(&tmp_uvfits_file.path()).unwrap();
+        let hdu = fits_open_hdu!(&mut fptr, 0).unwrap();
+        let columns = get_group_column_description(&mut fptr, &hdu).unwrap();
+        assert_eq!(columns, ["UU", "VV", "WW", "ANTENNA1", "ANTENNA2", "DATE"]);
+
+        for (row_idx, &(tile1, tile2)) in baselines.iter().enumerate() {
+            let group_params = read_group_params(&mut fptr, 1 + row_idx as i64, columns.len());
+            assert_abs_diff_eq!(group_params[3], (tile1 + 1) as f32);
+            assert_abs_diff_eq!(group_params[4], (tile2 + 1) as f32);
+        }
+    }
+
+    /// A writer of a single baseline between two MWA tiles, with `num_chans`
+    /// 40 kHz channels from 170 MHz, for tests of the header options.
+    fn new_test_writer(
+        path: &Path,
+        num_timesteps: usize,
+        num_chans: usize,
+        start_epoch: Epoch,
+        options: UvfitsOptions,
+    ) -> UvfitsWriter {
+        let names = vec!["Tile1".into(), "Tile2".into()];
+        let positions = vec![XyzGeodetic::default(); names.len()];
+        UvfitsWriter::new_with_options(
+            path,
+            num_timesteps,
+            1,
+            num_chans,
+            start_epoch,
+            40e3,
+            170e6,
+            num_chans / 2,
+            RADec::new_degrees(0.0, 60.0),
+            None,
+            &Telescope::new("MWA", LatLngHeight::new_mwa(), names, positions),
+            Duration::from_total_nanoseconds(0),
+            None,
+            options,
+        )
+        .unwrap()
+    }
+
+    /// Read the first `num` (unscaled) group parameters of `group` (1 = 1st
+    /// group).
+    fn read_group_params(fptr: &mut FitsFile, group: i64, num: usize) -> Vec<f32> {
+        let mut group_params = vec![0.0_f32; num];
+        let mut status = 0;
+        unsafe {
+            // ffggpe = fits_read_grppar_flt
+            fitsio_sys::ffggpe(
+                fptr.as_raw(),             /* I - FITS file pointer                       */
+                group,                     /* I - group to read (1 = 1st group)           */
+                1,                         /* I - first vector element to read (1 = 1st)  */
+                group_params.len() as i64, /* I - number of values to read                */
+                group_params.as_mut_ptr(), /* O - array of values that are returned       */
+                &mut status,               /* IO - error status                           */
+            );
+        }
+        fits_check_status(status).unwrap();
+        group_params
+    }
+
+    /// Read the Julian date of each row, summing all `DATE` group parameters
+    /// and their `PZERO`s.
+    fn read_row_jds(fptr: &mut FitsFile, hdu: &FitsHdu, num_rows: usize) -> Vec<f64> {
+        let columns = get_group_column_description(fptr, hdu).unwrap();
+        let zeros: Vec<f64> = (1..=columns.len())
+            .map(|i| get_required_fits_key!(fptr, hdu, format!("PZERO{i}").as_str()).unwrap())
+            .collect();
+        (0..num_rows)
+            .map(|row_idx| {
+                let group_params = read_group_params(fptr, 1 + row_idx as i64, columns.len());
+                izip!(&columns, &zeros, &group_params)
+                    .filter(|(name, _, _)| name.as_str() == "DATE")
+                    .map(|(_, &zero, &param)| zero + f64::from(param))
+                    .sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn double_date_encoding() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let start_epoch = Epoch::from_gpst_seconds(1065880128.0);
+        // Times that a single f32 DATE can't tell apart.
+        let epochs = [
+            start_epoch + Duration::from_f64(0.25, Unit::Second),
+            start_epoch + Duration::from_f64(0.2505, Unit::Second),
+        ];
+
+        let mut u = new_test_writer(
+            tmp_uvfits_file.path(),
+            epochs.len(),
+            1,
+            start_epoch,
+            UvfitsOptions {
+                date_encoding: DateEncoding::Double,
+                ..Default::default()
+            },
+        );
+        for &epoch in &epochs {
+            u.write_vis_row(UVW::default(), 0, 1, epoch, &[0.0; 12])
+                .unwrap();
+        }
+        u.finalise().unwrap();
+
+        let mut fptr = fits_open!(&tmp_uvfits_file.path()).unwrap();
+        let hdu = fits_open_hdu!(&mut fptr, 0).unwrap();
+        let columns = get_group_column_description(&mut fptr, &hdu).unwrap();
+        assert_eq!(columns, ["UU", "VV", "WW", "BASELINE", "DATE", "DATE"]);
+        let pzero6: f64 = get_required_fits_key!(&mut fptr, &hdu, "PZERO6").unwrap();
+        assert_abs_diff_eq!(pzero6, 0.0);
+
+        let jds = read_row_jds(&mut fptr, &hdu, epochs.len());
+        for (jd, epoch) in jds.into_iter().zip(epochs) {
+            let (days, frac) = jd_utc_split(epoch);
+            // Both DATEs are single precision, and a day's worth of seconds in
+            // an f32 is only good to a few microseconds each, so allow 25
+            // microseconds.
+            assert_abs_diff_eq!((jd - days - frac) * 86400.0, 0.0, epsilon = 2.5e-5);
+        }
+    }
+
+    #[test]
+    fn inttim_group_param() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let start_epoch = Epoch::from_gpst_seconds(1065880128.0);
+        let mut u = new_test_writer(
+            tmp_uvfits_file.path(),
+            1,
+            1,
+            start_epoch,
+            UvfitsOptions {
+                inttim: true,
+                ..Default::default()
+            },
+        );
+        u.set_int_time(Some(Duration::from_f64(2.0, Unit::Second)));
+        u.write_vis_row(UVW::default(), 0, 1, start_epoch, &[0.0; 12])
+            .unwrap();
+        u.finalise().unwrap();
+
+        let mut fptr = fits_open!(&tmp_uvfits_file.path()).unwrap();
+        let hdu = fits_open_hdu!(&mut fptr, 0).unwrap();
+        let columns = get_group_column_description(&mut fptr, &hdu).unwrap();
+        assert_eq!(columns, ["UU", "VV", "WW", "BASELINE", "DATE", "INTTIM"]);
+        let group_params = read_group_params(&mut fptr, 1, columns.len());
+        assert_abs_diff_eq!(group_params[5], 2.0);
+    }
+
+    #[test]
+    fn read_inttim() {
+        let start_epoch = Epoch::from_gpst_seconds(1065880128.0);
+        for inttim in [true, false] {
+            let tmp_uvfits_file = NamedTempFile::new().unwrap();
+            let mut u = new_test_writer(
+                tmp_uvfits_file.path(),
+                1,
+                1,
+                start_epoch,
+                UvfitsOptions {
+                    inttim,
+                    ..Default::default()
+                },
+            );
+            u.set_int_time(Some(Duration::from_f64(2.0, Unit::Second)));
+            u.write_vis_row(UVW::default(), 0, 1, start_epoch, &[0.0; 12])
+                .unwrap();
+            u.finalise().unwrap();
+
+            // The integration time of a single timestep is only known from
+            // INTTIM.
+            let result = UvfitsReader::new(tmp_uvfits_file.path());
+            if inttim {
+                let vis_ctx = result.unwrap().vis_ctx().clone();
+                assert_eq!(vis_ctx.num_sel_timesteps, 1);
+                assert_eq!(vis_ctx.int_time, Duration::from_f64(2.0, Unit::Second));
+            } else {
+                assert!(matches!(result, Err(IOError::UnsupportedInput { .. })));
+            }
+        }
+    }
+
+    #[test]
+    fn estimate_matches_written_file() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let vis_ctx = VisContext {
+            avg_time: 2,
+            avg_freq: 4,
+            ..get_vis_ctx(4, 32, vec![(0, 1), (0, 2), (1, 2)])
+        };
+        let names = vec!["Tile1".into(), "Tile2".into(), "Tile3".into()];
+        let positions = vec![XyzGeodetic::default(); names.len()];
+        let estimate = UvfitsWriter::estimate(&vis_ctx, names.len(), &UvfitsOptions::default(), 2);
+        assert_eq!(estimate.num_rows, 2 * 3);
+
+        let mut u = UvfitsWriter::from_marlu(
+            tmp_uvfits_file.path(),
+            &vis_ctx,
+            &Telescope::new("MWA", LatLngHeight::new_mwa(), names, positions),
+            RADec::new_degrees(0.0, 60.0),
+            Duration::from_total_nanoseconds(0),
+            None,
+            None,
+        )
+        .unwrap();
+        let jones_array = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        let weight_array = Array3::from_elem(vis_ctx.sel_dims(), 1.0);
+        u.write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
+            .unwrap();
+        u.finalise().unwrap();
+
+        // Only the size of the headers is uncertain.
+        let bytes = std::fs::metadata(tmp_uvfits_file.path()).unwrap().len();
+        assert!(
+            (bytes as i64 - estimate.bytes as i64).abs() <= 2 * 2880,
+            "estimated {} bytes, but wrote {bytes}",
+            estimate.bytes
+        );
+    }
+
+    #[test]
+    fn read_vis_round_trip() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let vis_ctx = get_vis_ctx(3, 4, vec![(0, 1), (0, 2), (1, 2)]);
+        let names = vec!["Tile1".into(), "Tile2".into(), "Tile3".into()];
+        let positions = vec![XyzGeodetic::default(); names.len()];
+        let mut u = UvfitsWriter::from_marlu(
+            tmp_uvfits_file.path(),
+            &vis_ctx,
+            &Telescope::new("MWA", LatLngHeight::new_mwa(), names, positions),
+            RADec::new_degrees(0.0, 60.0),
+            Duration::from_total_nanoseconds(0),
+            None,
+            None,
+        )
+        .unwrap();
+        let jones_array = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| {
+            Jones::from([
+                Complex::new(1.0, 0.0),
+                Complex::new(2.0, 0.0),
+                Complex::new(3.0, 0.0),
+                Complex::new(4.0, 0.0),
+            ]) * (t * 100 + c * 10 + b) as f32
+        });
+        let mut weight_array = Array3::from_elem(vis_ctx.sel_dims(), 2.0);
+        weight_array[(1, 2, 0)] = -2.0;
+        u.write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
+            .unwrap();
+        u.finalise().unwrap();
+
+        let mut reader = UvfitsReader::new(tmp_uvfits_file.path()).unwrap();
+        let read_ctx = reader.vis_ctx().clone();
+        assert_eq!(read_ctx.sel_dims(), vis_ctx.sel_dims());
+        assert_eq!(read_ctx.sel_baselines, vis_ctx.sel_baselines);
+        assert_eq!(read_ctx.int_time, vis_ctx.int_time);
+        assert_abs_diff_eq!(read_ctx.start_freq_hz, vis_ctx.start_freq_hz);
+        assert_abs_diff_eq!(read_ctx.freq_resolution_hz, vis_ctx.freq_resolution_hz);
+        // A single f32 DATE is only precise to a few milliseconds.
+        assert_abs_diff_eq!(
+            (read_ctx.start_timestamp - vis_ctx.start_timestamp).in_seconds(),
+            0.0,
+            epsilon = 1e-2
+        );
+
+        // The last two timesteps and middle two channels of the last two
+        // baselines, in reverse.
+        let chunk_ctx = VisContext {
+            num_sel_timesteps: 2,
+            start_timestamp: read_ctx.start_timestamp + read_ctx.int_time,
+            num_sel_chans: 2,
+            start_freq_hz: read_ctx.start_freq_hz + read_ctx.freq_resolution_hz,
+            sel_baselines: vec![(1, 2), (0, 2)],
+            ..read_ctx.clone()
+        };
+        let mut vis = Array3::zeros(chunk_ctx.sel_dims());
+        let mut weights = Array3::zeros(chunk_ctx.sel_dims());
+        reader
+            .read_vis(vis.view_mut(), weights.view_mut(), &chunk_ctx)
+            .unwrap();
+        let mut expected_vis = jones_array.slice(s![1.., 1..3, 1..]);
+        expected_vis.invert_axis(Axis(2));
+        assert_abs_diff_eq!(vis, expected_vis);
+        let mut expected_weights = weight_array.slice(s![1.., 1..3, 1..]);
+        expected_weights.invert_axis(Axis(2));
+        assert_abs_diff_eq!(weights, expected_weights);
+
+        let mut vis = Array3::zeros(read_ctx.sel_dims());
+        let mut weights = Array3::zeros(read_ctx.sel_dims());
+        reader
+            .read_vis(vis.view_mut(), weights.view_mut(), &read_ctx)
+            .unwrap();
+        assert_abs_diff_eq!(vis, jones_array);
+        assert_abs_diff_eq!(weights, weight_array);
+
+        // Chunks from elsewhere are rejected.
+        let chunk_ctx = VisContext {
+            start_freq_hz: read_ctx.start_freq_hz - read_ctx.freq_resolution_hz,
+            ..read_ctx
+        };
+        assert!(matches!(
+            reader.read_vis(vis.view_mut(), weights.view_mut(), &chunk_ctx),
+            Err(IOError::ChunkNotInSource)
+        ));
+    }
+
+    #[test]
+    fn read_vis_ascending_round_trip() {
+        let vis_ctx = VisContext {
+            start_freq_hz: 192030000.,
+            freq_resolution_hz: -10000.,
+            ..get_vis_ctx(2, 4, vec![(0, 1)])
+        };
+        let jones_array = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, _)| {
+            Jones::identity() * Complex::new(1.0, (t * 10 + c) as f32)
+        });
+        let weight_array = Array3::from_shape_fn(vis_ctx.sel_dims(), |(_, c, _)| c as f32);
+        let names = vec!["Tile1".into(), "Tile2".into()];
+        let positions = vec![XyzGeodetic::default(); names.len()];
+        let telescope = Telescope::new("MWA", LatLngHeight::new_mwa(), names, positions);
+
+        // The descending visibilities are flipped, but otherwise unchanged.
+        let mut expected_vis = jones_array.clone();
+        expected_vis.invert_axis(Axis(1));
+        let mut expected_weights = weight_array.clone();
+        expected_weights.invert_axis(Axis(1));
+
+        // Written as they are, the file's frequency axis is descending, and
+        // reading it in ascending order flips them.
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let mut u = UvfitsWriter::from_marlu(
+            tmp_uvfits_file.path(),
+            &vis_ctx,
+            &telescope,
+            RADec::new_degrees(0.0, 60.0),
+            Duration::from_total_nanoseconds(0),
+            None,
+            None,
+        )
+        .unwrap();
+        u.write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
+            .unwrap();
+        u.finalise().unwrap();
+
+        let mut reader = UvfitsReader::new(tmp_uvfits_file.path()).unwrap();
+        assert!(reader.vis_ctx().is_descending());
+        let read_ctx = reader.vis_ctx().to_ascending();
+        assert_abs_diff_eq!(read_ctx.start_freq_hz, 192000000.);
+        let mut vis = Array3::zeros(read_ctx.sel_dims());
+        let mut weights = Array3::zeros(read_ctx.sel_dims());
+        reader
+            .read_vis_ascending(vis.view_mut(), weights.view_mut(), &read_ctx)
+            .unwrap();
+        assert_abs_diff_eq!(vis, expected_vis);
+        assert_abs_diff_eq!(weights, expected_weights);
+        // The phase of each visibility is that of the same channel before the
+        // round trip.
+        let num_chans = vis_ctx.num_sel_chans;
+        for ((t, c, b), jones) in vis.indexed_iter() {
+            let original = jones_array[(t, num_chans - 1 - c, b)];
+            for (received, original) in jones.iter().zip(original.iter()) {
+                assert_abs_diff_eq!(received.arg(), original.arg());
+            }
+        }
+
+        // Written in ascending order, the file matches what was read.
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let mut u = UvfitsWriter::from_marlu(
+            tmp_uvfits_file.path(),
+            &read_ctx,
+            &telescope,
+            RADec::new_degrees(0.0, 60.0),
+            Duration::from_total_nanoseconds(0),
+            None,
+            None,
+        )
+        .unwrap();
+        u.write_vis_ascending(jones_array.view(), weight_array.view(), &vis_ctx, false)
+            .unwrap();
+        u.finalise().unwrap();
+
+        let mut reader = UvfitsReader::new(tmp_uvfits_file.path()).unwrap();
+        assert!(!reader.vis_ctx().is_descending());
+        let mut vis = Array3::zeros(read_ctx.sel_dims());
+        let mut weights = Array3::zeros(read_ctx.sel_dims());
+        reader
+            .read_vis_ascending(vis.view_mut(), weights.view_mut(), &read_ctx)
+            .unwrap();
+        assert_abs_diff_eq!(vis, expected_vis);
+        assert_abs_diff_eq!(weights, expected_weights);
+    }
+
+    #[test]
+    fn write_vis_flags_round_trip() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let vis_ctx = get_vis_ctx(2, 3, vec![(0, 1)]);
+        let names = vec!["Tile1".into(), "Tile2".into()];
+        let positions = vec![XyzGeodetic::default(); names.len()];
+        let mut u = UvfitsWriter::from_marlu(
+            tmp_uvfits_file.path(),
+            &vis_ctx,
+            &Telescope::new("MWA", LatLngHeight::new_mwa(), names, positions),
+            RADec::new_degrees(0.0, 60.0),
+            Duration::from_total_nanoseconds(0),
+            None,
+            None,
+        )
+        .unwrap();
+        let jones_array = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        // Without averaging, every flag (including that of a flagged zero
+        // weight) must be written as-is.
+        let weight_array = array![[[1.0], [-2.0], [-0.0]], [[0.0], [3.0], [-4.0]]];
+        u.write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
+            .unwrap();
+        u.finalise().unwrap();
+
+        let mut reader = UvfitsReader::new(tmp_uvfits_file.path()).unwrap();
+        let read_ctx = reader.vis_ctx().clone();
+        assert_eq!(read_ctx.sel_dims(), vis_ctx.sel_dims());
+        let mut vis = Array3::zeros(read_ctx.sel_dims());
+        let mut weights = Array3::zeros(read_ctx.sel_dims());
+        reader
+            .read_vis(vis.view_mut(), weights.view_mut(), &read_ctx)
+            .unwrap();
+        let (weights, flags) = split_weight_sign(weights.view());
+        assert_abs_diff_eq!(
+            weights,
+            array![[[1.0], [2.0], [0.0]], [[0.0], [3.0], [4.0]]]
+        );
+        assert_eq!(
+            flags,
+            array![[[false], [true], [true]], [[false], [false], [true]]]
+        );
+    }
+
+    #[test]
+    fn read_vis_flagged_weights() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let vis_ctx = VisContext {
+            avg_freq: 2,
+            ..get_vis_ctx(1, 6, vec![(0, 1)])
+        };
+        let names = vec!["Tile1".into(), "Tile2".into()];
+        let positions = vec![XyzGeodetic::default(); names.len()];
+        // The integration time of a single timestep is only known from INTTIM.
+        let mut u = UvfitsWriter::from_marlu_with_options(
+            tmp_uvfits_file.path(),
+            &vis_ctx,
+            &Telescope::new("MWA", LatLngHeight::new_mwa(), names, positions),
+            RADec::new_degrees(0.0, 60.0),
+            Duration::from_total_nanoseconds(0),
+            None,
+            None,
+            UvfitsOptions {
+                inttim: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let jones_array = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        // The second averaged channel is entirely flagged, and the third is a
+        // flagged zero weight.
+        let weight_array = array![[[1.0], [3.0], [-2.0], [-1.0], [-0.0], [-0.0]]];
+        u.write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
+            .unwrap();
+        u.finalise().unwrap();
+
+        let mut reader = UvfitsReader::new(tmp_uvfits_file.path()).unwrap();
+        let read_ctx = reader.vis_ctx().clone();
+        assert_eq!(read_ctx.sel_dims(), (1, 3, 1));
+        let mut vis = Array3::zeros(read_ctx.sel_dims());
+        let mut weights = Array3::zeros(read_ctx.sel_dims());
+        reader
+            .read_vis(vis.view_mut(), weights.view_mut(), &read_ctx)
+            .unwrap();
+        let (weights, flags) = split_weight_sign(weights.view());
+        assert_abs_diff_eq!(weights, array![[[4.0], [0.0], [0.0]]]);
+        assert_eq!(flags, array![[[false], [true], [true]]]);
+    }
+
+    #[test]
+    fn pol_frame_header() {
+        for (pol_frame, num_pols, expected_crval3, expected_cdelt3) in [
+            (PolFrame::Linear, 4, -5, -1),
+            (PolFrame::LinearXXYY, 2, -5, -1),
+            (PolFrame::LinearXX, 1, -5, -1),
+            (PolFrame::Circular, 4, -1, -1),
+            (PolFrame::StokesI, 1, 1, 1),
+        ] {
+            let tmp_uvfits_file = NamedTempFile::new().unwrap();
+            let num_chans = 2;
+            let start_epoch = Epoch::from_gpst_seconds(1065880128.0);
+            let mut u = new_test_writer(
+                tmp_uvfits_file.path(),
+                1,
+                num_chans,
+                start_epoch,
+                UvfitsOptions {
+                    pol_frame,
+                    ..Default::default()
+                },
+            );
+            u.write_vis_row(
+                UVW::default(),
+                0,
+                1,
+                start_epoch,
+                &vec![0.0; num_chans * num_pols * 3],
+            )
+            .unwrap();
+            u.finalise().unwrap();
+
+            let mut fptr = fits_open!(&tmp_uvfits_file.path()).unwrap();
+            let hdu = fits_open_hdu!(&mut fptr, 0).unwrap();
+            let naxis3: usize = get_required_fits_key!(&mut fptr, &hdu, "NAXIS3").unwrap();
+            let crval3: i64 = get_required_fits_key!(&mut fptr, &hdu, "CRVAL3").unwrap();
+            let cdelt3: i64 = get_required_fits_key!(&mut fptr, &hdu, "CDELT3").unwrap();
+            assert_eq!(naxis3, num_pols);
+            assert_eq!(crval3, expected_crval3);
+            assert_eq!(cdelt3, expected_cdelt3);
+        }
+    }
+
+    #[test]
+    fn uvw_frame_of_date_epoch() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let start_epoch = Epoch::from_gpst_seconds(1065880128.0);
+        let mut u = new_test_writer(
+            tmp_uvfits_file.path(),
+            1,
+            1,
+            start_epoch,
+            UvfitsOptions {
+                uvw_frame: UvwFrame::OfDate,
+                ..Default::default()
+            },
+        );
+        u.write_vis_row(UVW::default(), 0, 1, start_epoch, &[0.0; 12])
+            .unwrap();
+        u.finalise().unwrap();
+
+        let mut fptr = fits_open!(&tmp_uvfits_file.path()).unwrap();
+        let hdu = fits_open_hdu!(&mut fptr, 0).unwrap();
+        let epoch: f64 = get_required_fits_key!(&mut fptr, &hdu, "EPOCH").unwrap();
+        // The Julian epoch of 2013-10-15 (GPS 1065880128) is of the TT
+        // Julian date, i.e. 2000 + (JD_TT - 2451545) / 365.25; with the UTC
+        // Julian date, it would be wrong by ~2e-6.
+        assert_abs_diff_eq!(epoch, 2013.7880250457572, epsilon = 1e-9);
+        // Everything else is in the same frame.
+        let equinox: f64 = get_required_fits_key!(&mut fptr, &hdu, "EQUINOX").unwrap();
+        assert_abs_diff_eq!(equinox, epoch);
+        for (crval_key, obs_key, expected) in [("CRVAL5", "OBSRA", 0.0), ("CRVAL6", "OBSDEC", 60.0)]
+        {
+            let crval: f64 = get_required_fits_key!(&mut fptr, &hdu, crval_key).unwrap();
+            let obs: f64 = get_required_fits_key!(&mut fptr, &hdu, obs_key).unwrap();
+            assert_abs_diff_eq!(crval, expected, epsilon = 1e-10);
+            assert_abs_diff_eq!(obs, expected, epsilon = 1e-10);
+        }
+    }
+
+    #[test]
+    fn checksums() {
+        let start_epoch = Epoch::from_gpst_seconds(1065880128.0);
+        let write = |path: &Path, checksum: bool| {
+            let mut u = new_test_writer(
+                path,
+                1,
+                1,
+                start_epoch,
+                UvfitsOptions {
+                    // The integration time of a single timestep is only known
+                    // from INTTIM.
+                    inttim: true,
+                    checksum,
+                    ..Default::default()
+                },
+            );
+            u.write_vis_row(UVW::default(), 0, 1, start_epoch, &[1.0; 12])
+                .unwrap();
+            u.finalise().unwrap();
+        };
+
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let path = tmp_uvfits_file.path();
+        write(path, false);
+        let mut reader = UvfitsReader::new(path).unwrap();
+        assert!(!reader.verify_checksums().unwrap());
+
+        write(path, true);
+        let mut reader = UvfitsReader::new(path).unwrap();
+        assert!(reader.verify_checksums().unwrap());
+        // The reader still works after verifying.
+        let vis_ctx = reader.vis_ctx().clone();
+        let mut vis = Array3::zeros(vis_ctx.sel_dims());
+        let mut weights = Array3::zeros(vis_ctx.sel_dims());
+        reader
+            .read_vis(vis.view_mut(), weights.view_mut(), &vis_ctx)
+            .unwrap();
+        assert_abs_diff_eq!(weights[(0, 0, 0)], 1.0);
+
+        // Corrupt the visibilities, which start after the primary header.
+        let mut bytes = std::fs::read(path).unwrap();
+        let header_len = 80
+            * (1 + bytes
+                .chunks(80)
+                .position(|card| card.starts_with(b"END "))
+                .unwrap());
+        let data_start = (header_len + 2879) / 2880 * 2880;
+        bytes[data_start + 4] ^= 0xff;
+        std::fs::write(path, bytes).unwrap();
+        let mut reader = UvfitsReader::new(path).unwrap();
+        assert!(matches!(
+            reader.verify_checksums(),
+            Err(IOError::BadChecksum {
+                hdu: 1,
+                keyword: "DATASUM",
+                ..
+            })
+        ));
+    }
+
     /// This test ensures center frequencies are calculated correctly.
     /// See: <https://github.com/MWATelescope/Birli/issues/6>
     #[test]
@@ -1862,12 +3553,10 @@ mod tests {
         let mut u = UvfitsWriter::from_marlu(
             tmp_uvfits_file.path(),
             &vis_ctx,
-            array_pos,
+            &Telescope::new("MWA", array_pos, names, positions),
             phase_centre,
             Duration::from_total_nanoseconds(0),
             None,
-            names,
-            positions,
             None,
         )
         .unwrap();
@@ -1889,12 +3578,8 @@ mod tests {
             )
             .unwrap();
 
-        weight_array
-            .iter_mut()
-            .zip(flag_array.iter())
-            .for_each(|(w, f)| {
-                *w = if *f { -(*w).abs() } else { (*w).abs() };
-            });
+        let weight_array =
+            flags_to_negative_weights(weight_array.view(), flag_array.view()).unwrap();
 
         u.write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
             .unwrap();
@@ -1966,12 +3651,10 @@ mod tests {
         let mut u = UvfitsWriter::from_marlu(
             tmp_uvfits_file.path(),
             &vis_ctx,
-            array_pos,
+            &Telescope::new("MWA", array_pos, names, positions),
             phase_centre,
             Duration::from_total_nanoseconds(0),
             None,
-            names,
-            positions,
             None,
         )
         .unwrap();
@@ -1993,12 +3676,8 @@ mod tests {
             )
             .unwrap();
 
-        weight_array
-            .iter_mut()
-            .zip(flag_array.iter())
-            .for_each(|(w, f)| {
-                *w = if *f { -(*w).abs() } else { (*w).abs() };
-            });
+        let weight_array =
+            flags_to_negative_weights(weight_array.view(), flag_array.view()).unwrap();
 
         u.write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
             .unwrap();
@@ -2076,12 +3755,10 @@ mod tests {
         let mut u = UvfitsWriter::from_marlu(
             tmp_uvfits_file.path(),
             &vis_ctx,
-            array_pos,
+            &Telescope::new("MWA", array_pos, names, positions),
             phase_centre,
             Duration::from_total_nanoseconds(0),
             Some(&field_name),
-            names,
-            positions,
             None,
         )
         .unwrap();
@@ -2103,12 +3780,8 @@ mod tests {
             )
             .unwrap();
 
-        weight_array
-            .iter_mut()
-            .zip(flag_array.iter())
-            .for_each(|(w, f)| {
-                *w = if *f { -(*w).abs() } else { (*w).abs() };
-            });
+        let weight_array =
+            flags_to_negative_weights(weight_array.view(), flag_array.view()).unwrap();
 
         u.write_vis(jones_array.view(), weight_array.view(), &vis_ctx, false)
             .unwrap();
@@ -2156,11 +3829,13 @@ mod tests {
             get_required_fits_key!(&mut birli_fptr, &birli_vis_hdu, "BSCALE").unwrap();
         assert_abs_diff_eq!(birli_ant_bscale, 1.);
         // -> BUNIT - units,
-        // ---> not in Cotter, not mandatory, so not written
-        // let birli_ant_bunit: String =
-        // get_required_fits_key!(&mut birli_fptr, &birli_vis_hdu, "BUNIT").unwrap();
+        // ---> not in Cotter, not mandatory, but written from `VisContext::vis_unit`
+        let birli_vis_bunit: String =
+            get_required_fits_key!(&mut birli_fptr, &birli_vis_hdu, "BUNIT").unwrap();
+        assert_eq!(birli_vis_bunit, "UNCALIB");
         // -> EQUINOX - Equinox of source coordinates and uvw
-        // ---> not in Cotter, not mandatory, so not written
+        // ---> not in Cotter, not mandatory, but written with the same value as
+        //      EPOCH
         // -> ALTRPIX - Reference pixel for velocity
         // ---> not in Cotter, not mandatory, so not written
 
@@ -2366,12 +4041,10 @@ mod tests {
         let mut u = UvfitsWriter::from_marlu(
             tmp_uvfits_file.path(),
             &vis_ctx,
-            array_pos,
+            &Telescope::new("MWA", array_pos, names, positions),
             RADec::from_mwalib_phase_or_pointing(&corr_ctx.metafits_context),
             Duration::from_total_nanoseconds(0),
             Some(&corr_ctx.metafits_context.obs_name),
-            names,
-            positions,
             Some(&history),
         )
         .unwrap();
@@ -0,0 +1,706 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Check written visibility files for compliance with their specifications.
+//!
+//! [`validate_uvfits`] checks a uvfits file against the AIPS 117 memo, and
+//! [`validate_ms`] checks a measurement set against the MS v2 definition.
+//! Rather than stopping at the first problem, all of the checks are run and
+//! the problems found are collected into a [`ValidationReport`].
+
+use std::fmt;
+
+use thiserror::Error;
+
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
+use std::path::Path;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "cfitsio")] {
+        use fitsio::{hdu::FitsHdu, FitsFile};
+        use super::uvfits::decode_uvfits_baseline;
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "ms")] {
+        use rubbl_casatables::{CasacoreError, Table, TableOpenMode};
+    }
+}
+
+/// A problem found when validating a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// A mandatory header keyword is missing.
+    MissingKey { hdu: String, key: String },
+
+    /// A mandatory random group parameter (`PTYPEn`) is missing.
+    MissingGroupParam(String),
+
+    /// The number of rows written doesn't match the number declared (e.g.
+    /// `GCOUNT`).
+    RowCount { expected: u64, found: u64 },
+
+    /// A row refers to an antenna that isn't in the antenna table. `antenna`
+    /// is 0-indexed.
+    AntennaOutOfRange {
+        row: u64,
+        antenna: i64,
+        num_antennas: u64,
+    },
+
+    /// A row refers to a data description that isn't in the
+    /// `DATA_DESCRIPTION` table.
+    DataDescOutOfRange {
+        row: u64,
+        data_desc_id: i64,
+        num_data_descs: u64,
+    },
+
+    /// The time of a row is before the time of the previous row.
+    TimeDecreases { row: u64 },
+
+    /// A mandatory table (a uvfits extension or measurement set subtable) is
+    /// missing.
+    MissingTable(String),
+
+    /// A mandatory column is missing from a table.
+    MissingColumn { table: String, column: String },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingKey { hdu, key } => write!(f, "HDU {hdu} is missing key {key}"),
+            Self::MissingGroupParam(ptype) => write!(f, "group parameter {ptype} is missing"),
+            Self::RowCount { expected, found } => {
+                write!(f, "expected {expected} rows, but found {found}")
+            }
+            Self::AntennaOutOfRange {
+                row,
+                antenna,
+                num_antennas,
+            } => write!(
+                f,
+                "row {row} refers to antenna {antenna}, but there are only {num_antennas} antennas"
+            ),
+            Self::DataDescOutOfRange {
+                row,
+                data_desc_id,
+                num_data_descs,
+            } => write!(
+                f,
+                "row {row} refers to data description {data_desc_id}, but there are only {num_data_descs}"
+            ),
+            Self::TimeDecreases { row } => {
+                write!(f, "the time of row {row} is before the previous row's")
+            }
+            Self::MissingTable(table) => write!(f, "table {table} is missing"),
+            Self::MissingColumn { table, column } => {
+                write!(f, "table {table} is missing column {column}")
+            }
+        }
+    }
+}
+
+/// The results of validating a file.
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    /// A description of each check that was run.
+    pub checks: Vec<String>,
+
+    /// The problems that were found.
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Were no problems found?
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ValidationError {
+    /// The file couldn't be read; this is distinct from the file being
+    /// invalid.
+    #[cfg(feature = "cfitsio")]
+    #[error(transparent)]
+    Fitsio(#[from] fitsio::errors::Error),
+
+    /// From Rubbl Casacore
+    #[cfg(feature = "ms")]
+    #[error("Rubbl CASACore error {inner:?}")]
+    CasacoreError { inner: CasacoreError },
+
+    /// From Rubbl
+    #[cfg(feature = "ms")]
+    #[error("Rubbl error {inner:?}")]
+    RubblError { inner: failure::Error },
+
+    /// An IO error.
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+}
+
+#[cfg(feature = "ms")]
+impl From<failure::Error> for ValidationError {
+    fn from(inner: failure::Error) -> Self {
+        Self::RubblError { inner }
+    }
+}
+
+#[cfg(feature = "ms")]
+impl From<CasacoreError> for ValidationError {
+    fn from(inner: CasacoreError) -> Self {
+        Self::CasacoreError { inner }
+    }
+}
+
+/// Check that each of `times` is no earlier than the one before it.
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
+fn check_monotonic_times<I: IntoIterator<Item = f64>>(times: I, issues: &mut Vec<ValidationIssue>) {
+    let mut prev = f64::NEG_INFINITY;
+    for (row, time) in times.into_iter().enumerate() {
+        if time < prev {
+            issues.push(ValidationIssue::TimeDecreases { row: row as u64 });
+        }
+        prev = time;
+    }
+}
+
+/// Keys that AIPS 117 requires in the primary HDU of a uvfits file.
+#[cfg(feature = "cfitsio")]
+const UVFITS_PRIMARY_KEYS: [&str; 10] = [
+    "SIMPLE", "BITPIX", "NAXIS", "NAXIS1", "GROUPS", "PCOUNT", "GCOUNT", "OBJECT", "TELESCOP",
+    "EPOCH",
+];
+
+/// Keys that AIPS 117 requires in the antenna table of a uvfits file.
+#[cfg(feature = "cfitsio")]
+const UVFITS_ANTENNA_KEYS: [&str; 10] = [
+    "EXTNAME", "ARRAYX", "ARRAYY", "ARRAYZ", "ARRNAM", "FREQ", "RDATE", "GSTIA0", "DEGPDY",
+    "TIMSYS",
+];
+
+/// Read the key `key` of `hdu`, adding an issue if it's missing.
+#[cfg(feature = "cfitsio")]
+fn read_uvfits_key<T: fitsio::headers::ReadsKey>(
+    fptr: &mut FitsFile,
+    hdu: &FitsHdu,
+    hdu_name: &str,
+    key: &str,
+    issues: &mut Vec<ValidationIssue>,
+) -> Option<T> {
+    match hdu.read_key(fptr, key) {
+        Ok(value) => Some(value),
+        Err(_) => {
+            issues.push(ValidationIssue::MissingKey {
+                hdu: hdu_name.to_string(),
+                key: key.to_string(),
+            });
+            None
+        }
+    }
+}
+
+/// Validate a uvfits file against the AIPS 117 memo. This checks that
+///
+/// - the mandatory keys of the primary HDU and antenna table are present;
+/// - the mandatory random group parameters are present;
+/// - `GCOUNT` rows have been written;
+/// - the baselines of each row refer to antennas in the antenna table; and
+/// - the `DATE` of each row is no earlier than the previous row's.
+///
+/// Rows are considered unwritten if all of their group parameters are zero.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be opened or read. Problems with the
+/// contents of the file are reported in the [`ValidationReport`] instead.
+#[cfg(feature = "cfitsio")]
+pub fn validate_uvfits<P: AsRef<Path>>(path: P) -> Result<ValidationReport, ValidationError> {
+    let mut report = ValidationReport::default();
+    let issues = &mut report.issues;
+    let mut fptr = FitsFile::open(path)?;
+
+    // Primary HDU keys.
+    let hdu = fptr.primary_hdu()?;
+    for key in UVFITS_PRIMARY_KEYS {
+        let _: Option<String> = read_uvfits_key(&mut fptr, &hdu, "PRIMARY", key, issues);
+    }
+    let naxis: i64 = read_uvfits_key(&mut fptr, &hdu, "PRIMARY", "NAXIS", issues).unwrap_or(0);
+    for i in 2..=naxis {
+        for key in ["NAXIS", "CTYPE", "CRVAL", "CDELT", "CRPIX"] {
+            let key = format!("{key}{i}");
+            let _: Option<String> = read_uvfits_key(&mut fptr, &hdu, "PRIMARY", &key, issues);
+        }
+    }
+    let pcount: i64 = read_uvfits_key(&mut fptr, &hdu, "PRIMARY", "PCOUNT", issues).unwrap_or(0);
+    let mut ptypes = Vec::with_capacity(pcount as usize);
+    let mut pscals = Vec::with_capacity(pcount as usize);
+    let mut pzeros = Vec::with_capacity(pcount as usize);
+    for i in 1..=pcount {
+        let ptype: Option<String> =
+            read_uvfits_key(&mut fptr, &hdu, "PRIMARY", &format!("PTYPE{i}"), issues);
+        ptypes.push(ptype.unwrap_or_default());
+        let pscal: Option<f64> =
+            read_uvfits_key(&mut fptr, &hdu, "PRIMARY", &format!("PSCAL{i}"), issues);
+        pscals.push(pscal.unwrap_or(1.0));
+        let pzero: Option<f64> =
+            read_uvfits_key(&mut fptr, &hdu, "PRIMARY", &format!("PZERO{i}"), issues);
+        pzeros.push(pzero.unwrap_or(0.0));
+    }
+    report
+        .checks
+        .push("uvfits: mandatory primary HDU keys".into());
+
+    // Group parameters.
+    let has_ptype = |ptype: &str| ptypes.iter().any(|p| p == ptype);
+    for ptype in ["UU", "VV", "WW", "DATE"] {
+        if !has_ptype(ptype) {
+            issues.push(ValidationIssue::MissingGroupParam(ptype.into()));
+        }
+    }
+    if !has_ptype("BASELINE") {
+        for ptype in ["ANTENNA1", "ANTENNA2"] {
+            if !has_ptype(ptype) {
+                issues.push(ValidationIssue::MissingGroupParam(ptype.into()));
+            }
+        }
+    }
+    report
+        .checks
+        .push("uvfits: mandatory random group parameters".into());
+
+    // Read the group parameters of every row, applying PSCAL and PZERO.
+    let gcount: i64 = read_uvfits_key(&mut fptr, &hdu, "PRIMARY", "GCOUNT", issues).unwrap_or(0);
+    let mut rows = Vec::with_capacity(gcount as usize);
+    let mut group_params = vec![0.0_f32; pcount as usize];
+    for group in 1..=gcount {
+        let mut status = 0;
+        unsafe {
+            // ffggpe = fits_read_grppar_flt
+            fitsio_sys::ffggpe(
+                fptr.as_raw(),
+                group,
+                1,
+                pcount,
+                group_params.as_mut_ptr(),
+                &mut status,
+            );
+        }
+        fitsio::errors::check_status(status)?;
+        rows.push(group_params.clone());
+    }
+    // Unwritten rows are all zeros.
+    let num_written = rows
+        .iter()
+        .rposition(|params| params.iter().any(|&p| p != 0.0))
+        .map_or(0, |last| last + 1);
+    rows.truncate(num_written);
+    if num_written as i64 != gcount {
+        issues.push(ValidationIssue::RowCount {
+            expected: gcount as u64,
+            found: num_written as u64,
+        });
+    }
+    report.checks.push("uvfits: rows written vs GCOUNT".into());
+
+    let param = |params: &[f32], ptype: &str| -> Option<f64> {
+        let mut values = ptypes
+            .iter()
+            .zip(pscals.iter().zip(pzeros.iter()))
+            .zip(params)
+            .filter(|((p, _), _)| p.as_str() == ptype)
+            .map(|((_, (&scale, &zero)), &value)| f64::from(value) * scale + zero)
+            .peekable();
+        values.peek()?;
+        Some(values.sum())
+    };
+    if has_ptype("DATE") {
+        check_monotonic_times(
+            rows.iter()
+                .map(|params| param(params, "DATE").unwrap_or(0.0)),
+            issues,
+        );
+        report.checks.push("uvfits: monotonic DATEs".into());
+    }
+
+    // Antenna table.
+    match fptr.hdu("AIPS AN") {
+        Ok(ant_hdu) => {
+            for key in UVFITS_ANTENNA_KEYS {
+                let _: Option<String> =
+                    read_uvfits_key(&mut fptr, &ant_hdu, "AIPS AN", key, issues);
+            }
+            let num_antennas: i64 =
+                read_uvfits_key(&mut fptr, &ant_hdu, "AIPS AN", "NAXIS2", issues).unwrap_or(0);
+            let nostas: Vec<i32> = ant_hdu.read_col(&mut fptr, "NOSTA")?;
+            if nostas.len() as i64 != num_antennas {
+                issues.push(ValidationIssue::RowCount {
+                    expected: num_antennas as u64,
+                    found: nostas.len() as u64,
+                });
+            }
+            // Antennas are 1-indexed in the group parameters.
+            for (row, params) in rows.iter().enumerate() {
+                let (ant1, ant2) = match param(params, "BASELINE") {
+                    Some(bl) => {
+                        let (ant1, ant2) = decode_uvfits_baseline(bl.round() as usize);
+                        (ant1 as i64, ant2 as i64)
+                    }
+                    None => (
+                        param(params, "ANTENNA1").unwrap_or(0.0).round() as i64,
+                        param(params, "ANTENNA2").unwrap_or(0.0).round() as i64,
+                    ),
+                };
+                for ant in [ant1, ant2] {
+                    if ant < 1 || ant > num_antennas {
+                        issues.push(ValidationIssue::AntennaOutOfRange {
+                            row: row as u64,
+                            antenna: ant - 1,
+                            num_antennas: num_antennas as u64,
+                        });
+                    }
+                }
+            }
+            report
+                .checks
+                .push("uvfits: antenna table consistency".into());
+        }
+        Err(_) => issues.push(ValidationIssue::MissingTable("AIPS AN".into())),
+    }
+
+    Ok(report)
+}
+
+/// Subtables that the MS v2 definition requires.
+#[cfg(feature = "ms")]
+const MS_SUBTABLES: [&str; 12] = [
+    "ANTENNA",
+    "DATA_DESCRIPTION",
+    "FEED",
+    "FIELD",
+    "FLAG_CMD",
+    "HISTORY",
+    "OBSERVATION",
+    "POINTING",
+    "POLARIZATION",
+    "PROCESSOR",
+    "SPECTRAL_WINDOW",
+    "STATE",
+];
+
+/// Columns that the MS v2 definition requires in the main table.
+#[cfg(feature = "ms")]
+const MS_MAIN_COLUMNS: [&str; 21] = [
+    "TIME",
+    "ANTENNA1",
+    "ANTENNA2",
+    "FEED1",
+    "FEED2",
+    "DATA_DESC_ID",
+    "PROCESSOR_ID",
+    "FIELD_ID",
+    "INTERVAL",
+    "EXPOSURE",
+    "TIME_CENTROID",
+    "SCAN_NUMBER",
+    "ARRAY_ID",
+    "OBSERVATION_ID",
+    "STATE_ID",
+    "UVW",
+    "SIGMA",
+    "WEIGHT",
+    "FLAG",
+    "FLAG_CATEGORY",
+    "FLAG_ROW",
+];
+
+/// Validate a measurement set against the MS v2 definition. This checks that
+///
+/// - the mandatory subtables and main table columns are present;
+/// - the antennas and data descriptions of each row are in their subtables;
+///   and
+/// - the `TIME` of each row is no earlier than the previous row's.
+///
+/// # Errors
+///
+/// Returns an error if the main table can't be opened or read. Problems with
+/// the contents of the measurement set are reported in the
+/// [`ValidationReport`] instead.
+#[cfg(feature = "ms")]
+pub fn validate_ms<P: AsRef<Path>>(path: P) -> Result<ValidationReport, ValidationError> {
+    let path = path.as_ref();
+    let mut report = ValidationReport::default();
+    let issues = &mut report.issues;
+
+    let subtable_rows = |name: &str, issues: &mut Vec<ValidationIssue>| -> Option<u64> {
+        match Table::open(path.join(name), TableOpenMode::Read) {
+            Ok(table) => Some(table.n_rows()),
+            Err(_) => {
+                issues.push(ValidationIssue::MissingTable(name.into()));
+                None
+            }
+        }
+    };
+    let mut num_antennas = None;
+    let mut num_data_descs = None;
+    for name in MS_SUBTABLES {
+        let num_rows = subtable_rows(name, issues);
+        match name {
+            "ANTENNA" => num_antennas = num_rows,
+            "DATA_DESCRIPTION" => num_data_descs = num_rows,
+            _ => (),
+        }
+    }
+    report.checks.push("ms: mandatory subtables".into());
+
+    let mut main_table = Table::open(path, TableOpenMode::Read)?;
+    let column_names = main_table.column_names()?;
+    for column in MS_MAIN_COLUMNS {
+        if !column_names.iter().any(|c| c == column) {
+            issues.push(ValidationIssue::MissingColumn {
+                table: "MAIN".into(),
+                column: column.into(),
+            });
+        }
+    }
+    let has_column = |column: &str| column_names.iter().any(|c| c == column);
+    report
+        .checks
+        .push("ms: mandatory main table columns".into());
+
+    if let Some(num_antennas) = num_antennas {
+        for column in ["ANTENNA1", "ANTENNA2"] {
+            if !has_column(column) {
+                continue;
+            }
+            let antennas: Vec<i32> = main_table.get_col_as_vec(column)?;
+            for (row, &antenna) in antennas.iter().enumerate() {
+                if antenna < 0 || antenna as u64 >= num_antennas {
+                    issues.push(ValidationIssue::AntennaOutOfRange {
+                        row: row as u64,
+                        antenna: antenna.into(),
+                        num_antennas,
+                    });
+                }
+            }
+        }
+        report.checks.push("ms: antenna table consistency".into());
+    }
+
+    if let (Some(num_data_descs), true) = (num_data_descs, has_column("DATA_DESC_ID")) {
+        let data_desc_ids: Vec<i32> = main_table.get_col_as_vec("DATA_DESC_ID")?;
+        for (row, &data_desc_id) in data_desc_ids.iter().enumerate() {
+            if data_desc_id < 0 || data_desc_id as u64 >= num_data_descs {
+                issues.push(ValidationIssue::DataDescOutOfRange {
+                    row: row as u64,
+                    data_desc_id: data_desc_id.into(),
+                    num_data_descs,
+                });
+            }
+        }
+        report
+            .checks
+            .push("ms: data description table consistency".into());
+    }
+
+    if has_column("TIME") {
+        let times: Vec<f64> = main_table.get_col_as_vec("TIME")?;
+        check_monotonic_times(times, issues);
+        report.checks.push("ms: monotonic TIMEs".into());
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    cfg_if::cfg_if! {
+        if #[cfg(any(feature = "cfitsio", feature = "ms"))] {
+            use super::*;
+            use hifitime::{Duration, Epoch, Unit};
+            use ndarray::prelude::*;
+            use tempfile::tempdir;
+            use crate::{AveragingPolicy, Jones, LatLngHeight, RADec, VisContext, VisUnit, VisWrite};
+
+            fn vis_ctx() -> VisContext {
+                VisContext {
+                    num_sel_timesteps: 2,
+                    start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+                    int_time: Duration::from_f64(2., Unit::Second),
+                    num_sel_chans: 2,
+                    start_freq_hz: 150_000_000.,
+                    freq_resolution_hz: 40_000.,
+                    sel_baselines: vec![(0, 0), (0, 1), (1, 1)],
+                    avg_time: 1,
+                    avg_freq: 1,
+                    num_vis_pols: 4,
+                    averaging_policy: AveragingPolicy::default(),
+                    vis_unit: VisUnit::default(),
+                    vis_scale: 1.0,
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(any(feature = "cfitsio", feature = "ms"))]
+    fn test_monotonic_times() {
+        let mut issues = vec![];
+        check_monotonic_times([1.0, 1.0, 2.0], &mut issues);
+        assert!(issues.is_empty());
+
+        check_monotonic_times([1.0, 2.0, 1.5, 3.0], &mut issues);
+        assert_eq!(issues, [ValidationIssue::TimeDecreases { row: 2 }]);
+    }
+
+    #[test]
+    #[cfg(feature = "cfitsio")]
+    fn test_validate_uvfits() {
+        use crate::{Telescope, UvfitsWriter, XyzGeodetic};
+
+        let vis_ctx = vis_ctx();
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("test.uvfits");
+        let names = vec!["Tile1".into(), "Tile2".into()];
+        let positions = vec![XyzGeodetic::default(); names.len()];
+        let mut writer = UvfitsWriter::from_marlu(
+            &path,
+            &vis_ctx,
+            &Telescope::new("MWA", LatLngHeight::new_mwa(), names, positions),
+            RADec::new_degrees(0.0, -27.0),
+            Duration::from_total_nanoseconds(0),
+            Some("test"),
+            None,
+        )
+        .unwrap();
+        let vis = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        let weights = Array3::from_elem(vis_ctx.sel_dims(), 1.0);
+        writer
+            .write_vis(vis.view(), weights.view(), &vis_ctx, false)
+            .unwrap();
+        writer.finalise().unwrap();
+
+        let report = validate_uvfits(&path).unwrap();
+        assert!(report.is_valid(), "{:?}", report.issues);
+        assert!(!report.checks.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "cfitsio")]
+    fn test_validate_uvfits_missing_rows() {
+        use crate::{Telescope, UvfitsWriter, XyzGeodetic};
+
+        let vis_ctx = vis_ctx();
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("test.uvfits");
+        let names = vec!["Tile1".into(), "Tile2".into()];
+        let positions = vec![XyzGeodetic::default(); names.len()];
+        let mut writer = UvfitsWriter::from_marlu(
+            &path,
+            &vis_ctx,
+            &Telescope::new("MWA", LatLngHeight::new_mwa(), names, positions),
+            RADec::new_degrees(0.0, -27.0),
+            Duration::from_total_nanoseconds(0),
+            Some("test"),
+            None,
+        )
+        .unwrap();
+        let vis = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        let weights = Array3::from_elem(vis_ctx.sel_dims(), 1.0);
+        writer
+            .write_vis(vis.view(), weights.view(), &vis_ctx, false)
+            .unwrap();
+        writer.finalise().unwrap();
+
+        // Zero the rows of the last timestep, like a write that was
+        // interrupted after the first timestep.
+        let mut fptr = FitsFile::open(&path).unwrap();
+        let hdu = fptr.primary_hdu().unwrap();
+        let pcount: i64 = hdu.read_key(&mut fptr, "PCOUNT").unwrap();
+        let naxis: i64 = hdu.read_key(&mut fptr, "NAXIS").unwrap();
+        let row_len = pcount
+            + (2..=naxis)
+                .map(|i| {
+                    hdu.read_key::<i64>(&mut fptr, &format!("NAXIS{i}"))
+                        .unwrap()
+                })
+                .product::<i64>();
+        drop(fptr);
+        let mut bytes = std::fs::read(&path).unwrap();
+        let header_len = 80
+            * (1 + bytes
+                .chunks(80)
+                .position(|card| card.starts_with(b"END "))
+                .unwrap());
+        let data_start = (header_len + 2879) / 2880 * 2880;
+        // Each value is a 4-byte float.
+        let row_bytes = row_len as usize * 4;
+        bytes[data_start + 3 * row_bytes..data_start + 6 * row_bytes].fill(0);
+        std::fs::write(&path, bytes).unwrap();
+
+        let report = validate_uvfits(&path).unwrap();
+        assert_eq!(
+            report.issues,
+            [ValidationIssue::RowCount {
+                expected: 6,
+                found: 3
+            }]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "ms")]
+    fn test_validate_ms() {
+        use crate::{MeasurementSetWriter, ObsContext, ENH};
+
+        let vis_ctx = vis_ctx();
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: vis_ctx.start_timestamp,
+            sched_duration: vis_ctx.int_time * vis_ctx.num_sel_timesteps as f64,
+            name: None,
+            field_name: None,
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::new_degrees(0.0, -27.0),
+            pointing_centre: None,
+            array_pos: LatLngHeight::new_mwa(),
+            telescope_name: "MWA".into(),
+            instrument: "MWA".into(),
+            ant_positions_enh: vec![ENH::default(); 2],
+            ant_names: vec!["Tile1".into(), "Tile2".into()],
+            ant_flags: vec![],
+            beams: vec![],
+        };
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("test.ms");
+        let mut writer = MeasurementSetWriter::new(
+            &path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            obs_ctx.ant_positions_geodetic().collect(),
+            Duration::from_total_nanoseconds(0),
+        );
+        writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+        let vis = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        let weights = Array3::from_elem(vis_ctx.sel_dims(), 1.0);
+        writer
+            .write_vis(vis.view(), weights.view(), &vis_ctx, false)
+            .unwrap();
+        writer.finalise().unwrap();
+
+        let report = validate_ms(&path).unwrap();
+        assert!(report.is_valid(), "{:?}", report.issues);
+
+        std::fs::remove_dir_all(path.join("FLAG_CMD")).unwrap();
+        let report = validate_ms(&path).unwrap();
+        assert_eq!(
+            report.issues,
+            [ValidationIssue::MissingTable("FLAG_CMD".into())]
+        );
+    }
+}
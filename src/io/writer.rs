@@ -0,0 +1,572 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Pick a [`VisWrite`] implementor from the extension of an output path.
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use hifitime::Duration;
+use ndarray::prelude::*;
+
+use super::VisWrite;
+use crate::{errors::IOError, History, Jones, ObsContext, VisContext};
+
+/// A file format that visibilities can be written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisWriteFormat {
+    /// uvfits, with a `.uvfits` extension. Requires the `cfitsio` feature.
+    Uvfits,
+
+    /// A CASA measurement set, with a `.ms` extension. Requires the `ms`
+    /// feature.
+    MeasurementSet,
+
+    /// A directory of Parquet files partitioned by timestep, with a
+    /// `.parquet` extension. Requires the `parquet` feature.
+    Parquet,
+
+    /// A Zarr group of chunked arrays, with a `.zarr` extension. Requires the
+    /// `zarr` feature.
+    Zarr,
+}
+
+impl VisWriteFormat {
+    /// Determine the format from the extension of `path` (case insensitive),
+    /// or `None` if the extension isn't recognised.
+    pub fn from_path<T: AsRef<Path>>(path: T) -> Option<VisWriteFormat> {
+        let ext = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "uvfits" => Some(VisWriteFormat::Uvfits),
+            "ms" => Some(VisWriteFormat::MeasurementSet),
+            "parquet" => Some(VisWriteFormat::Parquet),
+            "zarr" => Some(VisWriteFormat::Zarr),
+            _ => None,
+        }
+    }
+
+    /// The cargo feature that must be enabled to write this format.
+    pub fn feature(self) -> &'static str {
+        match self {
+            VisWriteFormat::Uvfits => "cfitsio",
+            VisWriteFormat::MeasurementSet => "ms",
+            VisWriteFormat::Parquet => "parquet",
+            VisWriteFormat::Zarr => "zarr",
+        }
+    }
+
+    /// Whether this build of Marlu can write this format.
+    pub fn is_enabled(self) -> bool {
+        match self {
+            VisWriteFormat::Uvfits => cfg!(feature = "cfitsio"),
+            VisWriteFormat::MeasurementSet => cfg!(feature = "ms"),
+            VisWriteFormat::Parquet => cfg!(feature = "parquet"),
+            VisWriteFormat::Zarr => cfg!(feature = "zarr"),
+        }
+    }
+}
+
+impl Display for VisWriteFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VisWriteFormat::Uvfits => write!(f, "uvfits"),
+            VisWriteFormat::MeasurementSet => write!(f, "measurement set"),
+            VisWriteFormat::Parquet => write!(f, "parquet"),
+            VisWriteFormat::Zarr => write!(f, "zarr"),
+        }
+    }
+}
+
+/// A factory for [`VisWrite`] implementors, so that tools don't need to
+/// match on output formats themselves.
+pub struct VisWriter;
+
+impl VisWriter {
+    /// Create a writer for `path`, with the format chosen by
+    /// [`VisWriteFormat::from_path`]. The writer is ready for
+    /// [`VisWrite::write_vis`]; call [`VisWrite::finalise`] once all of the
+    /// visibilities have been written.
+    ///
+    /// - `vis_ctx` - describes all of the visibilities that will be written
+    /// - `obs_ctx` - provides the phase centre, array position and antennas
+    /// - `dut1` - the DUT1 to use in the conversion to UT1
+    /// - `history` - optional metadata about the software that wrote the file
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`IOError`] if the extension of `path` isn't recognised,
+    /// the format's feature isn't enabled, or the writer can't be created.
+    #[cfg_attr(not(any(feature = "cfitsio", feature = "ms")), allow(unused_variables))]
+    pub fn from_path<T: AsRef<Path>>(
+        path: T,
+        vis_ctx: &VisContext,
+        obs_ctx: &ObsContext,
+        dut1: Duration,
+        history: Option<&History>,
+    ) -> Result<Box<dyn VisWrite>, IOError> {
+        let path = path.as_ref();
+        let format = VisWriteFormat::from_path(path).ok_or_else(|| IOError::UnknownFormat {
+            path: path.display().to_string(),
+        })?;
+        if !format.is_enabled() {
+            return Err(IOError::FormatNotEnabled {
+                format: format.to_string(),
+                feature: format.feature(),
+            });
+        }
+
+        match format {
+            #[cfg(feature = "cfitsio")]
+            VisWriteFormat::Uvfits => Ok(Box::new(super::UvfitsWriter::from_marlu(
+                path,
+                vis_ctx,
+                &obs_ctx.telescope(),
+                obs_ctx.phase_centre,
+                dut1,
+                obs_ctx.name.as_deref(),
+                history,
+            )?)),
+
+            #[cfg(feature = "ms")]
+            VisWriteFormat::MeasurementSet => {
+                let ms_writer = super::MeasurementSetWriter::from_marlu(path, obs_ctx, dut1);
+                ms_writer.initialize(vis_ctx, obs_ctx, history)?;
+                Ok(Box::new(ms_writer))
+            }
+
+            #[cfg(feature = "parquet")]
+            VisWriteFormat::Parquet => Ok(Box::new(super::ParquetWriter::new(path)?)),
+
+            #[cfg(feature = "zarr")]
+            VisWriteFormat::Zarr => Ok(Box::new(super::ZarrWriter::new(path, vis_ctx, None)?)),
+
+            #[allow(unreachable_patterns)]
+            _ => unreachable!("disabled formats are handled above"),
+        }
+    }
+}
+
+/// The expected size of an output, and the memory needed to write it,
+/// estimated without touching the disk, e.g. so that jobs on HPC systems can
+/// request the right resources. See `UvfitsWriter::estimate` and
+/// `MeasurementSetWriter::estimate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputEstimate {
+    /// The number of rows (uvfits random groups, or rows of the main table of
+    /// a measurement set): one per averaged timestep and baseline.
+    pub num_rows: usize,
+    /// The estimated size of the output \[bytes\].
+    pub bytes: u64,
+    /// The estimated peak memory use while writing a chunk \[bytes\].
+    pub bytes_per_chunk: usize,
+}
+
+impl OutputEstimate {
+    /// Estimate an output of the averaged visibilities of `vis_ctx`, where
+    /// each row takes `row_bytes`, and headers and tables take another
+    /// `extra_bytes`. The memory use is that of the visibility and weight
+    /// arrays given to [`VisWrite::write_vis`] for chunks of
+    /// `timesteps_per_chunk` timesteps, plus the averaged rows of each chunk.
+    pub fn new(
+        vis_ctx: &VisContext,
+        row_bytes: usize,
+        extra_bytes: u64,
+        timesteps_per_chunk: usize,
+    ) -> Self {
+        let num_baselines = vis_ctx.sel_baselines.len();
+        let num_rows = vis_ctx.num_avg_timesteps() * num_baselines;
+        let timesteps_per_chunk = timesteps_per_chunk.clamp(1, vis_ctx.num_sel_timesteps.max(1));
+        let avg_timesteps_per_chunk =
+            (timesteps_per_chunk as f64 / vis_ctx.avg_time.max(1) as f64).ceil() as usize;
+        let bytes_per_vis = std::mem::size_of::<Jones<f32>>() + std::mem::size_of::<f32>();
+        Self {
+            num_rows,
+            bytes: (num_rows * row_bytes) as u64 + extra_bytes,
+            bytes_per_chunk: timesteps_per_chunk
+                * vis_ctx.num_sel_chans
+                * num_baselines
+                * bytes_per_vis
+                + avg_timesteps_per_chunk * num_baselines * row_bytes,
+        }
+    }
+}
+
+/// A [`VisWrite`] implementor that splits the channels of the visibilities
+/// it's given into sub-bands (e.g. coarse channels), writing each sub-band to
+/// its own file in a single pass. This suits workflows that process each
+/// sub-band separately, e.g. calibration.
+pub struct SplitWriter {
+    /// The context of all of the visibilities that will be written.
+    vis_ctx: VisContext,
+
+    /// The context, path and writer of each sub-band.
+    bands: Vec<(VisContext, PathBuf, Box<dyn VisWrite>)>,
+}
+
+impl SplitWriter {
+    /// Create a writer for each sub-band of `chans_per_band` channels of
+    /// `vis_ctx` (see [`VisContext::split_freq`]), at the path given by
+    /// [`SplitWriter::band_path`]. The format and other arguments are as for
+    /// [`VisWriter::from_path`], so each file describes the frequencies of its
+    /// own sub-band.
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`IOError`] if any of the writers can't be created.
+    pub fn from_path<T: AsRef<Path>>(
+        path: T,
+        vis_ctx: &VisContext,
+        obs_ctx: &ObsContext,
+        dut1: Duration,
+        history: Option<&History>,
+        chans_per_band: usize,
+    ) -> Result<Self, IOError> {
+        let bands = vis_ctx
+            .split_freq(chans_per_band)
+            .into_iter()
+            .enumerate()
+            .map(|(band_idx, band_ctx)| {
+                let band_path = Self::band_path(&path, band_idx);
+                let writer = VisWriter::from_path(&band_path, &band_ctx, obs_ctx, dut1, history)?;
+                Ok((band_ctx, band_path, writer))
+            })
+            .collect::<Result<_, IOError>>()?;
+        Ok(Self {
+            vis_ctx: vis_ctx.clone(),
+            bands,
+        })
+    }
+
+    /// The path of the sub-band numbered `band_idx`, which is inserted before
+    /// the extension of `path`, e.g. `out.uvfits` becomes `out_002.uvfits`.
+    pub fn band_path<T: AsRef<Path>>(path: T, band_idx: usize) -> PathBuf {
+        let path = path.as_ref();
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let file_name = match path.extension() {
+            Some(ext) => format!("{stem}_{band_idx:03}.{}", ext.to_string_lossy()),
+            None => format!("{stem}_{band_idx:03}"),
+        };
+        path.with_file_name(file_name)
+    }
+
+    /// The context and path of each sub-band.
+    pub fn bands(&self) -> impl Iterator<Item = (&VisContext, &Path)> {
+        self.bands
+            .iter()
+            .map(|(band_ctx, band_path, _)| (band_ctx, band_path.as_path()))
+    }
+}
+
+impl VisWrite for SplitWriter {
+    fn write_vis(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        vis_ctx: &VisContext,
+        draw_progress: bool,
+    ) -> Result<(), IOError> {
+        vis_ctx.check_sel_dims(vis.dim(), weights.dim(), "SplitWriter::write_vis")?;
+        let location = self
+            .vis_ctx
+            .locate_chunk(vis_ctx)
+            .ok_or(IOError::ChunkNotInOutput)?;
+
+        // Write the part of the chunk that overlaps each sub-band.
+        let mut band_start = 0;
+        for (band_ctx, _, writer) in &mut self.bands {
+            let band_end = band_start + band_ctx.num_sel_chans;
+            let start = location.chans.start.max(band_start) - location.chans.start;
+            let end = location
+                .chans
+                .end
+                .min(band_end)
+                .saturating_sub(location.chans.start);
+            band_start = band_end;
+            if start >= end {
+                continue;
+            }
+            let chunk_ctx = VisContext {
+                num_sel_chans: end - start,
+                start_freq_hz: vis_ctx.start_freq_hz + start as f64 * vis_ctx.freq_resolution_hz,
+                ..vis_ctx.clone()
+            };
+            writer.write_vis(
+                vis.slice(s![.., start..end, ..]),
+                weights.slice(s![.., start..end, ..]),
+                &chunk_ctx,
+                draw_progress,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn finalise(&mut self) -> Result<(), IOError> {
+        for (_, _, writer) in &mut self.bands {
+            writer.finalise()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use hifitime::{Epoch, Unit};
+
+    use super::*;
+    use crate::{weights::split_weight_sign, AveragingPolicy, LatLngHeight, RADec, VisUnit, ENH};
+
+    #[test]
+    fn test_vis_write_format_from_path() {
+        assert_eq!(
+            VisWriteFormat::from_path("/tmp/1090008640.uvfits"),
+            Some(VisWriteFormat::Uvfits)
+        );
+        assert_eq!(
+            VisWriteFormat::from_path("out.MS/"),
+            Some(VisWriteFormat::MeasurementSet)
+        );
+        assert_eq!(
+            VisWriteFormat::from_path("out.parquet"),
+            Some(VisWriteFormat::Parquet)
+        );
+        assert_eq!(
+            VisWriteFormat::from_path("out.zarr/"),
+            Some(VisWriteFormat::Zarr)
+        );
+        assert_eq!(VisWriteFormat::from_path("out.fits"), None);
+        assert_eq!(VisWriteFormat::from_path("out"), None);
+    }
+
+    /// A chunk written to a [`RecordingWriter`].
+    type RecordedWrite = (VisContext, Array3<Jones<f32>>, Array3<f32>);
+
+    /// Records the visibilities and weights it's given.
+    #[derive(Default)]
+    struct RecordingWriter {
+        writes: std::rc::Rc<std::cell::RefCell<Vec<RecordedWrite>>>,
+    }
+
+    impl VisWrite for RecordingWriter {
+        fn write_vis(
+            &mut self,
+            vis: ArrayView3<Jones<f32>>,
+            weights: ArrayView3<f32>,
+            vis_ctx: &VisContext,
+            _draw_progress: bool,
+        ) -> Result<(), IOError> {
+            self.writes
+                .borrow_mut()
+                .push((vis_ctx.clone(), vis.to_owned(), weights.to_owned()));
+            Ok(())
+        }
+
+        fn finalise(&mut self) -> Result<(), IOError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_output_estimate() {
+        let vis_ctx = VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(1., Unit::Second),
+                5,
+            )
+            .channels(128_000_000., 10_000., 8)
+            .baselines(vec![(0, 1), (0, 2), (1, 2)])
+            .averaging(2, 4)
+            .build()
+            .unwrap();
+        let estimate = OutputEstimate::new(&vis_ctx, 100, 1000, 4);
+        // 3 averaged timesteps of 3 baselines.
+        assert_eq!(estimate.num_rows, 9);
+        assert_eq!(estimate.bytes, 9 * 100 + 1000);
+        // 4 timesteps of 8 channels and 3 baselines of 36 byte visibilities
+        // and weights, and 2 averaged timesteps of rows.
+        assert_eq!(estimate.bytes_per_chunk, 4 * 8 * 3 * 36 + 2 * 3 * 100);
+        // Chunks can't be bigger than the whole selection.
+        assert_eq!(
+            OutputEstimate::new(&vis_ctx, 100, 1000, 100).bytes_per_chunk,
+            5 * 8 * 3 * 36 + 3 * 3 * 100
+        );
+    }
+
+    #[test]
+    fn test_split_writer_band_path() {
+        assert_eq!(
+            SplitWriter::band_path("/tmp/out.uvfits", 2),
+            PathBuf::from("/tmp/out_002.uvfits")
+        );
+        assert_eq!(
+            SplitWriter::band_path("out.ms", 12),
+            PathBuf::from("out_012.ms")
+        );
+        assert_eq!(SplitWriter::band_path("out", 0), PathBuf::from("out_000"));
+    }
+
+    #[test]
+    fn test_split_writer_splits_channels() {
+        let vis_ctx = VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(1., Unit::Second),
+                2,
+            )
+            .channels(128_000_000., 10_000., 5)
+            .baselines(vec![(0, 1), (0, 2)])
+            .build()
+            .unwrap();
+        let band_ctxs = vis_ctx.split_freq(2);
+        let recorders: Vec<RecordingWriter> = band_ctxs
+            .iter()
+            .map(|_| RecordingWriter::default())
+            .collect();
+        let writes: Vec<_> = recorders.iter().map(|r| r.writes.clone()).collect();
+        let mut writer = SplitWriter {
+            vis_ctx: vis_ctx.clone(),
+            bands: band_ctxs
+                .into_iter()
+                .zip(recorders)
+                .enumerate()
+                .map(|(i, (band_ctx, recorder))| {
+                    let writer: Box<dyn VisWrite> = Box::new(recorder);
+                    (band_ctx, SplitWriter::band_path("out.uvfits", i), writer)
+                })
+                .collect(),
+        };
+        assert_eq!(writer.bands().count(), 3);
+
+        let jones_array = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| {
+            Jones::identity() * (t * 100 + c * 10 + b) as f32
+        });
+        // Flags are the signs of the weights; flag every third visibility.
+        let weight_array = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| {
+            let weight = (1 + t * 100 + c * 10 + b) as f32;
+            if (t + c + b) % 3 == 0 {
+                -weight
+            } else {
+                weight
+            }
+        });
+        // Write a timestep at a time, and then a chunk of the last timestep
+        // that straddles the first two sub-bands.
+        for time_chunk in vis_ctx.chunk_time(2) {
+            let location = vis_ctx.locate_chunk(&time_chunk).unwrap();
+            let slice = s![location.timesteps, .., ..];
+            writer
+                .write_vis(
+                    jones_array.slice(slice),
+                    weight_array.slice(slice),
+                    &time_chunk,
+                    false,
+                )
+                .unwrap();
+        }
+        let chunk_ctx = VisContext {
+            num_sel_timesteps: 1,
+            start_timestamp: vis_ctx.start_timestamp + vis_ctx.int_time,
+            num_sel_chans: 2,
+            start_freq_hz: vis_ctx.start_freq_hz + vis_ctx.freq_resolution_hz,
+            ..vis_ctx.clone()
+        };
+        let slice = s![1..2, 1..3, ..];
+        writer
+            .write_vis(
+                jones_array.slice(slice),
+                weight_array.slice(slice),
+                &chunk_ctx,
+                false,
+            )
+            .unwrap();
+        writer.finalise().unwrap();
+
+        let writes: Vec<_> = writes.iter().map(|w| w.borrow().clone()).collect();
+        assert_eq!(writes[0].len(), 3);
+        assert_eq!(writes[1].len(), 3);
+        assert_eq!(writes[2].len(), 2);
+        for (band_idx, band_writes) in writes.iter().enumerate() {
+            for (timestep_idx, (chunk_ctx, vis, weights)) in band_writes.iter().take(2).enumerate()
+            {
+                let chans = band_idx * 2..(band_idx * 2 + 2).min(5);
+                assert_abs_diff_eq!(
+                    chunk_ctx.start_freq_hz,
+                    vis_ctx.start_freq_hz + chans.start as f64 * vis_ctx.freq_resolution_hz
+                );
+                assert_eq!(chunk_ctx.num_sel_chans, chans.len());
+                let slice = s![timestep_idx..timestep_idx + 1, chans, ..];
+                assert_eq!(vis, &jones_array.slice(slice));
+                assert_eq!(weights, &weight_array.slice(slice));
+                assert_eq!(
+                    split_weight_sign(weights.view()).1,
+                    split_weight_sign(weight_array.slice(slice)).1
+                );
+            }
+        }
+        for (band_idx, chans) in [(0, 1..2), (1, 2..3)] {
+            let (_, vis, weights) = &writes[band_idx][2];
+            let slice = s![1..2, chans, ..];
+            assert_eq!(vis, &jones_array.slice(slice));
+            assert_eq!(weights, &weight_array.slice(slice));
+        }
+
+        // Chunks from elsewhere are rejected.
+        let chunk_ctx = VisContext {
+            start_freq_hz: vis_ctx.start_freq_hz - vis_ctx.freq_resolution_hz,
+            ..vis_ctx.clone()
+        };
+        assert!(matches!(
+            writer.write_vis(jones_array.view(), weight_array.view(), &chunk_ctx, false),
+            Err(IOError::ChunkNotInOutput)
+        ));
+    }
+
+    #[test]
+    fn test_vis_writer_unknown_format() {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 1,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: 1,
+            start_freq_hz: 128_000_000.,
+            freq_resolution_hz: 10_000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: vis_ctx.start_timestamp,
+            sched_duration: vis_ctx.int_time,
+            name: None,
+            field_name: None,
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::default(),
+            pointing_centre: None,
+            array_pos: LatLngHeight::new_mwa(),
+            telescope_name: "MWA".into(),
+            instrument: "MWA".into(),
+            ant_positions_enh: vec![ENH::default(); 2],
+            ant_names: vec!["ant0".into(), "ant1".into()],
+            ant_flags: vec![],
+            beams: vec![],
+        };
+
+        let result = VisWriter::from_path(
+            "out.fits",
+            &vis_ctx,
+            &obs_ctx,
+            Duration::from_total_nanoseconds(0),
+            None,
+        );
+        assert!(matches!(result, Err(IOError::UnknownFormat { .. })));
+    }
+}
@@ -0,0 +1,487 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Write visibilities as chunked, compressed [Zarr](https://zarr.dev) (v2)
+//! arrays, so that selections can be staged on object storage and lazily
+//! re-read, e.g. with `xarray.open_zarr` or `zarr.open_consolidated`.
+//!
+//! The output is a Zarr group containing the (averaged) arrays
+//!
+//! - `vis` - `complex64` visibilities with dimensions
+//!   `[time][frequency][baseline][pol]`, with pols in the order XX, XY, YX, YY
+//! - `weights` - `float32` (non-negative) weights with dimensions
+//!   `[time][frequency][baseline]`
+//! - `flags` - `bool` flags with dimensions `[time][frequency][baseline]`
+//! - `time` - the centroid of each timestep \[GPS seconds\]
+//! - `frequency` - the centre frequency of each channel \[Hz\]
+//!
+//! and attributes derived from the [`VisContext`]. Chunks are zlib compressed
+//! and contain a single timestep; see [`ZarrWriter::new`]. Consolidated
+//! metadata (`.zmetadata`) is written by [`VisWrite::finalise`].
+
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use flate2::{write::ZlibEncoder, Compression};
+use indicatif::{ProgressDrawTarget, ProgressStyle};
+use log::trace;
+use ndarray::prelude::*;
+use serde_json::{json, Value};
+
+use super::VisWrite;
+use crate::{
+    averaging::average_vis_with_policy,
+    errors::{BadArrayShape, IOError, ZarrWriteError},
+    AveragingPolicy, Jones, VisContext, WeightFlags,
+};
+
+/// The zlib compression level of chunks.
+const ZLIB_LEVEL: u32 = 1;
+
+/// A [`VisWrite`] implementor which writes a Zarr group of chunked arrays.
+pub struct ZarrWriter {
+    /// The path to the Zarr group.
+    path: PathBuf,
+
+    /// The dimensions of all of the averaged visibilities that will be
+    /// written, `[timestep][channel][baseline]`.
+    avg_dims: (usize, usize, usize),
+
+    /// The number of channels in each chunk.
+    chans_per_chunk: usize,
+
+    /// The index of the next (averaged) timestep to be written.
+    next_timestep: usize,
+
+    /// The metadata of every node of the group, keyed by path relative to the
+    /// group, which is consolidated into `.zmetadata`.
+    metadata: BTreeMap<String, Value>,
+}
+
+impl ZarrWriter {
+    /// Create a new [`ZarrWriter`] and write the metadata of the group.
+    ///
+    /// - `path` - the directory of the Zarr group, which is created if it
+    ///   doesn't exist
+    /// - `vis_ctx` - describes all of the visibilities that will be written
+    /// - `chans_per_chunk` - the number of (averaged) channels in each chunk,
+    ///   or `None` to put all channels of a timestep in one chunk
+    ///
+    /// # Errors
+    ///
+    /// Will return [`ZarrWriteError::NotADirectory`] if `path` exists but
+    /// isn't a directory, or an IO error if the metadata can't be written.
+    pub fn new<T: AsRef<Path>>(
+        path: T,
+        vis_ctx: &VisContext,
+        chans_per_chunk: Option<usize>,
+    ) -> Result<Self, ZarrWriteError> {
+        let path = path.as_ref().to_path_buf();
+        if path.exists() && !path.is_dir() {
+            return Err(ZarrWriteError::NotADirectory {
+                path: path.display().to_string(),
+            });
+        }
+        create_dir_all(&path)?;
+
+        let avg_dims = vis_ctx.avg_dims();
+        let (num_timesteps, num_chans, num_baselines) = avg_dims;
+        let chans_per_chunk = chans_per_chunk
+            .unwrap_or(num_chans)
+            .clamp(1, num_chans.max(1));
+        let mut writer = Self {
+            path,
+            avg_dims,
+            chans_per_chunk,
+            next_timestep: 0,
+            metadata: BTreeMap::new(),
+        };
+
+        writer.write_metadata(".zgroup", json!({ "zarr_format": 2 }))?;
+        writer.write_metadata(
+            ".zattrs",
+            json!({
+                "marlu_version": env!("CARGO_PKG_VERSION"),
+                "start_timestamp_gps_s": vis_ctx.start_timestamp.as_gpst_seconds(),
+                "int_time_s": vis_ctx.avg_int_time().in_seconds(),
+                "freq_resolution_hz": vis_ctx.avg_freq_resolution_hz(),
+                "avg_time": vis_ctx.avg_time,
+                "avg_freq": vis_ctx.avg_freq,
+                "num_vis_pols": vis_ctx.num_vis_pols,
+                "vis_unit": vis_ctx.vis_unit.ms_unit(),
+                "pols": ["XX", "XY", "YX", "YY"],
+                "sel_baselines": vis_ctx.sel_baselines,
+            }),
+        )?;
+
+        writer.write_array_metadata(
+            "vis",
+            &[num_timesteps, num_chans, num_baselines, 4],
+            &[1, chans_per_chunk, num_baselines, 4],
+            "<c8",
+            &Value::Null,
+            &["time", "frequency", "baseline", "pol"],
+        )?;
+        writer.write_array_metadata(
+            "weights",
+            &[num_timesteps, num_chans, num_baselines],
+            &[1, chans_per_chunk, num_baselines],
+            "<f4",
+            &json!(0.0),
+            &["time", "frequency", "baseline"],
+        )?;
+        writer.write_array_metadata(
+            "flags",
+            &[num_timesteps, num_chans, num_baselines],
+            &[1, chans_per_chunk, num_baselines],
+            "|b1",
+            &json!(true),
+            &["time", "frequency", "baseline"],
+        )?;
+
+        // The coordinates are known up front, so write them now.
+        let times: Vec<f64> = vis_ctx
+            .timeseries(true, true)
+            .map(|epoch| epoch.as_gpst_seconds())
+            .collect();
+        let freqs = vis_ctx.avg_frequencies_hz();
+        for (name, values) in [("time", times), ("frequency", freqs)] {
+            writer.write_array_metadata(
+                name,
+                &[values.len()],
+                &[values.len().max(1)],
+                "<f8",
+                &Value::Null,
+                &[name],
+            )?;
+            let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+            writer.write_chunk(name, "0", &bytes)?;
+        }
+
+        Ok(writer)
+    }
+
+    /// Write a JSON metadata file, and keep it for consolidation.
+    fn write_metadata(&mut self, key: &str, value: Value) -> Result<(), ZarrWriteError> {
+        let path = self.path.join(key);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        serde_json::to_writer_pretty(File::create(&path)?, &value)?;
+        self.metadata.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    /// Write the `.zarray` and `.zattrs` of an array. `dims` names the
+    /// dimensions, as used by xarray.
+    fn write_array_metadata(
+        &mut self,
+        name: &str,
+        shape: &[usize],
+        chunks: &[usize],
+        dtype: &str,
+        fill_value: &Value,
+        dims: &[&str],
+    ) -> Result<(), ZarrWriteError> {
+        self.write_metadata(
+            &format!("{name}/.zarray"),
+            json!({
+                "zarr_format": 2,
+                "shape": shape,
+                "chunks": chunks,
+                "dtype": dtype,
+                "compressor": { "id": "zlib", "level": ZLIB_LEVEL },
+                "fill_value": fill_value,
+                "order": "C",
+                "filters": null,
+                "dimension_separator": ".",
+            }),
+        )?;
+        self.write_metadata(
+            &format!("{name}/.zattrs"),
+            json!({ "_ARRAY_DIMENSIONS": dims }),
+        )
+    }
+
+    /// Compress and write a chunk of an array. `key` is the chunk's indices
+    /// joined with ".".
+    fn write_chunk(&self, name: &str, key: &str, bytes: &[u8]) -> Result<(), ZarrWriteError> {
+        let path = self.path.join(name).join(key);
+        trace!("writing zarr chunk {}", path.display());
+        let mut encoder = ZlibEncoder::new(File::create(path)?, Compression::new(ZLIB_LEVEL));
+        encoder.write_all(bytes)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    /// Write the chunks of a single averaged timestep. `jones` and `weights`
+    /// have dimensions `[channel][baseline]`. Chunks at the end of the
+    /// frequency axis are padded to the full chunk size, as Zarr expects.
+    fn write_timestep(
+        &self,
+        timestep: usize,
+        jones: ArrayView2<Jones<f32>>,
+        weights: ArrayView2<f32>,
+        vis_ctx: &VisContext,
+    ) -> Result<(), ZarrWriteError> {
+        let num_baselines = jones.dim().1;
+        let chunk_len = self.chans_per_chunk * num_baselines;
+
+        for (i_chunk, (jones, weights)) in jones
+            .axis_chunks_iter(Axis(0), self.chans_per_chunk)
+            .zip(weights.axis_chunks_iter(Axis(0), self.chans_per_chunk))
+            .enumerate()
+        {
+            let mut vis_bytes = Vec::with_capacity(chunk_len * 32);
+            let mut weight_bytes = Vec::with_capacity(chunk_len * 4);
+            let mut flag_bytes = Vec::with_capacity(chunk_len);
+            for (jones, &weight) in jones.iter().zip(weights.iter()) {
+                let WeightFlags { weight, flag } = WeightFlags::from_signed(weight);
+                let jones = if flag && vis_ctx.averaging_policy == AveragingPolicy::Nan {
+                    Jones::nan()
+                } else {
+                    *jones * vis_ctx.vis_scale
+                };
+                for c in jones.iter() {
+                    vis_bytes.extend_from_slice(&c.re.to_le_bytes());
+                    vis_bytes.extend_from_slice(&c.im.to_le_bytes());
+                }
+                weight_bytes.extend_from_slice(&weight.to_le_bytes());
+                flag_bytes.push(u8::from(flag));
+            }
+            // Pad with the fill values.
+            vis_bytes.resize(chunk_len * 32, 0);
+            weight_bytes.resize(chunk_len * 4, 0);
+            flag_bytes.resize(chunk_len, 1);
+
+            self.write_chunk("vis", &format!("{timestep}.{i_chunk}.0.0"), &vis_bytes)?;
+            self.write_chunk("weights", &format!("{timestep}.{i_chunk}.0"), &weight_bytes)?;
+            self.write_chunk("flags", &format!("{timestep}.{i_chunk}.0"), &flag_bytes)?;
+        }
+        Ok(())
+    }
+}
+
+impl VisWrite for ZarrWriter {
+    fn write_vis(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        vis_ctx: &VisContext,
+        draw_progress: bool,
+    ) -> Result<(), IOError> {
+        vis_ctx.check_sel_dims(vis.dim(), weights.dim(), "ZarrWriter::write_vis")?;
+        let (num_timesteps, num_chans, num_baselines) = vis_ctx.avg_dims();
+        if (num_chans, num_baselines) != (self.avg_dims.1, self.avg_dims.2)
+            || self.next_timestep + num_timesteps > self.avg_dims.0
+        {
+            return Err(IOError::BadArrayShape(BadArrayShape {
+                argument: "vis_ctx",
+                function: "ZarrWriter::write_vis",
+                expected: format!(
+                    "at most {} more averaged timesteps, with {} channels and {} baselines",
+                    self.avg_dims.0 - self.next_timestep,
+                    self.avg_dims.1,
+                    self.avg_dims.2
+                ),
+                received: format!("{:?}", vis_ctx.avg_dims()),
+            }));
+        }
+
+        let averaged;
+        let (vis, weights) = if vis_ctx.trivial_averaging() {
+            (vis.view(), weights.view())
+        } else {
+            averaged = average_vis_with_policy(
+                vis,
+                weights,
+                vis_ctx.avg_time,
+                vis_ctx.avg_freq,
+                vis_ctx.averaging_policy,
+            )?;
+            (averaged.0.view(), averaged.1.view())
+        };
+
+        let draw_target = if draw_progress {
+            ProgressDrawTarget::stderr()
+        } else {
+            ProgressDrawTarget::hidden()
+        };
+        let write_progress =
+            indicatif::ProgressBar::with_draw_target(Some(num_timesteps as u64), draw_target);
+        write_progress.set_style(
+            ProgressStyle::default_bar()
+                .template(
+                    "{msg:16}: [{elapsed_precise}] [{wide_bar:.cyan/blue}] {percent:3}% ({eta:5})",
+                )
+                .unwrap()
+                .progress_chars("=> "),
+        );
+        write_progress.set_message("write zarr");
+
+        for (jones, weights) in vis.outer_iter().zip(weights.outer_iter()) {
+            self.write_timestep(self.next_timestep, jones, weights, vis_ctx)?;
+            self.next_timestep += 1;
+            write_progress.inc(1);
+        }
+
+        write_progress.finish();
+        Ok(())
+    }
+
+    fn finalise(&mut self) -> Result<(), IOError> {
+        let consolidated = json!({
+            "zarr_consolidated_format": 1,
+            "metadata": self.metadata,
+        });
+        let file = File::create(self.path.join(".zmetadata")).map_err(ZarrWriteError::from)?;
+        serde_json::to_writer_pretty(file, &consolidated).map_err(ZarrWriteError::from)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use approx::assert_abs_diff_eq;
+    use flate2::read::ZlibDecoder;
+    use hifitime::{Duration, Epoch, Unit};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::c32;
+
+    fn get_vis_ctx() -> VisContext {
+        VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(2., Unit::Second),
+                3,
+            )
+            .channels(128_000_000., 10_000., 5)
+            .baselines(vec![(0, 0), (0, 1), (1, 1)])
+            .build()
+            .unwrap()
+    }
+
+    fn read_chunk(path: &Path) -> Vec<u8> {
+        let mut bytes = vec![];
+        ZlibDecoder::new(File::open(path).unwrap())
+            .read_to_end(&mut bytes)
+            .unwrap();
+        bytes
+    }
+
+    fn read_json(path: &Path) -> Value {
+        serde_json::from_reader(File::open(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_write_vis_chunks() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("out.zarr");
+        let vis_ctx = get_vis_ctx();
+        let (num_timesteps, num_chans, num_baselines) = vis_ctx.sel_dims();
+
+        let vis = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| {
+            Jones::from([
+                c32::new(t as f32, c as f32),
+                c32::new(b as f32, 0.),
+                c32::new(0., b as f32),
+                c32::new(1., -1.),
+            ])
+        });
+        let mut weights = Array3::from_elem(vis_ctx.sel_dims(), 2.0_f32);
+        weights[(1, 4, 2)] = -2.0;
+
+        let mut writer = ZarrWriter::new(&path, &vis_ctx, Some(2)).unwrap();
+        for (chunk_ctx, vis, weights) in itertools::izip!(
+            vis_ctx.chunk_time(2),
+            vis.axis_chunks_iter(Axis(0), 2),
+            weights.axis_chunks_iter(Axis(0), 2),
+        ) {
+            writer.write_vis(vis, weights, &chunk_ctx, false).unwrap();
+        }
+        writer.finalise().unwrap();
+
+        let zarray = read_json(&path.join("vis/.zarray"));
+        assert_eq!(
+            zarray["shape"],
+            json!([num_timesteps, num_chans, num_baselines, 4])
+        );
+        assert_eq!(zarray["chunks"], json!([1, 2, num_baselines, 4]));
+        assert_eq!(zarray["dtype"], json!("<c8"));
+        let zmetadata = read_json(&path.join(".zmetadata"));
+        assert_eq!(zmetadata["metadata"]["vis/.zarray"], zarray);
+        assert_eq!(
+            zmetadata["metadata"][".zattrs"]["sel_baselines"],
+            json!([[0, 0], [0, 1], [1, 1]])
+        );
+
+        // 5 channels in chunks of 2 means 3 chunks per timestep.
+        for t in 0..num_timesteps {
+            for i_chunk in 0..3 {
+                let vis_chunk = read_chunk(&path.join(format!("vis/{t}.{i_chunk}.0.0")));
+                assert_eq!(vis_chunk.len(), 2 * num_baselines * 4 * 8);
+                let floats: Vec<f32> = vis_chunk
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                    .collect();
+                // The XX of channel 2 * i_chunk, baseline 0.
+                assert_abs_diff_eq!(floats[0], t as f32);
+                assert_abs_diff_eq!(floats[1], (2 * i_chunk) as f32);
+                // The XY of baseline 2.
+                assert_abs_diff_eq!(floats[2 * 8 + 2], 2.);
+            }
+        }
+
+        let weight_chunk = read_chunk(&path.join("weights/1.2.0"));
+        assert_eq!(weight_chunk.len(), 2 * num_baselines * 4);
+        assert_abs_diff_eq!(
+            f32::from_le_bytes(weight_chunk[8..12].try_into().unwrap()),
+            2.0
+        );
+        let flag_chunk = read_chunk(&path.join("flags/1.2.0"));
+        // Channel 4, then the padding channel.
+        assert_eq!(flag_chunk, [0, 0, 1, 1, 1, 1]);
+
+        let times: Vec<f64> = read_chunk(&path.join("time/0"))
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_abs_diff_eq!(times[..], [1090008641., 1090008643., 1090008645.][..]);
+    }
+
+    #[test]
+    fn test_write_vis_averaged_and_too_many_timesteps() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("out.zarr");
+        let mut vis_ctx = get_vis_ctx();
+        vis_ctx.avg_time = 3;
+        vis_ctx.avg_freq = 5;
+
+        let vis = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        let weights = Array3::from_elem(vis_ctx.sel_dims(), 1.0_f32);
+        let mut writer = ZarrWriter::new(&path, &vis_ctx, None).unwrap();
+        writer
+            .write_vis(vis.view(), weights.view(), &vis_ctx, false)
+            .unwrap();
+
+        let weight_chunk = read_chunk(&path.join("weights/0.0.0"));
+        assert_eq!(weight_chunk.len(), 3 * 4);
+        assert_abs_diff_eq!(
+            f32::from_le_bytes(weight_chunk[..4].try_into().unwrap()),
+            15.0
+        );
+
+        // Everything has been written already.
+        let result = writer.write_vis(vis.view(), weights.view(), &vis_ctx, false);
+        assert!(matches!(result, Err(IOError::BadArrayShape(_))));
+    }
+}
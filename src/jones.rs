@@ -56,6 +56,18 @@ impl<F: Float> Jones<F> {
         ])
     }
 
+    /// From an input Jones matrix, get a copy with each element complex
+    /// conjugated (`J^*`).
+    #[inline]
+    pub fn conj(self) -> Self {
+        Self::from([
+            self[0].conj(),
+            self[1].conj(),
+            self[2].conj(),
+            self[3].conj(),
+        ])
+    }
+
     /// Multiply by a Jones matrix which gets Hermitian conjugated (`J^H`).
     #[inline]
     pub fn mul_hermitian(self, b: Self) -> Self {
@@ -68,7 +80,7 @@ impl<F: Float> Jones<F> {
     /// which case the contents of `J^I` are all NaN.
     #[inline]
     pub fn inv(self) -> Self {
-        let inv_det = Complex::new(F::one(), F::zero()) / (self[0] * self[3] - self[1] * self[2]);
+        let inv_det = Complex::new(F::one(), F::zero()) / self.det();
         Self::from([
             inv_det * self[3],
             -inv_det * self[1],
@@ -88,6 +100,46 @@ impl<F: Float> Jones<F> {
         ]
     }
 
+    /// Get the determinant of the Jones matrix.
+    #[inline]
+    pub fn det(self) -> Complex<F> {
+        self[0] * self[3] - self[1] * self[2]
+    }
+
+    /// Get the trace of the Jones matrix, i.e. the sum of XX and YY.
+    #[inline]
+    pub fn trace(self) -> Complex<F> {
+        self[0] + self[3]
+    }
+
+    /// Get the Frobenius norm of the Jones matrix, i.e. the square root of the
+    /// sum of the squared magnitudes of all elements.
+    #[inline]
+    pub fn frobenius_norm(self) -> F {
+        let [a, b, c, d] = self.norm_sqr();
+        (a + b + c + d).sqrt()
+    }
+
+    /// Get the (2-norm) condition number of the Jones matrix, i.e. the ratio
+    /// of its largest and smallest singular values. This is 1 for unitary
+    /// matrices and infinite for singular matrices; large values indicate that
+    /// the inverse is sensitive to errors.
+    pub fn condition_number(self) -> F {
+        let two = F::one() + F::one();
+        // The squared singular values sum to the squared Frobenius norm, and
+        // their product is the magnitude of the determinant.
+        let [a, b, c, d] = self.norm_sqr();
+        let frob_sqr = a + b + c + d;
+        let det_norm = self.det().norm();
+        if det_norm <= F::zero() {
+            return F::infinity();
+        }
+        let discriminant =
+            ((frob_sqr - two * det_norm) * (frob_sqr + two * det_norm)).max(F::zero());
+        let max_sing_sqr = (frob_sqr + discriminant.sqrt()) / two;
+        max_sing_sqr / det_norm
+    }
+
     #[inline]
     pub fn axb(a: Self, b: Self) -> Self {
         a * b
@@ -116,6 +168,205 @@ impl<F: Float> Jones<F> {
             self.0[3].im,
         ]
     }
+
+    /// Decompose the Jones matrix into per-polarisation gains and leakage
+    /// terms (d-terms), such that
+    ///
+    /// ```text
+    /// J = [gx  0 ] [1   dxy]
+    ///     [0   gy] [dyx 1  ]
+    /// ```
+    ///
+    /// If a gain is zero, its leakage term is NaN.
+    pub fn decompose_gains(self) -> GainDecomposition<F> {
+        let gx = self[0];
+        let gy = self[3];
+        GainDecomposition {
+            amplitudes: [gx.norm(), gy.norm()],
+            phases: [gx.arg(), gy.arg()],
+            leakages: [self[1] / gx, self[2] / gy],
+        }
+    }
+
+    /// Get the polar decomposition of the Jones matrix, `J = U P`, where `U`
+    /// is unitary and `P` is Hermitian positive semi-definite. `U` is the
+    /// closest unitary matrix to `J`, i.e. the "rotation" part, and `P` is
+    /// the "stretch" part. The decomposition is returned as `(U, P)`.
+    ///
+    /// For a 2x2 matrix, `U` is proportional to `J + e^{i arg(det J)}
+    /// adj(J)^H`, which avoids inverting `J`. If `J` is singular, `U` isn't
+    /// unique, but is still unitary.
+    pub fn polar_decomposition(self) -> (Self, Self) {
+        let zero = Complex::new(F::zero(), F::zero());
+        let det = self.det();
+        let det_norm = det.norm();
+        let phase = if det_norm > F::zero() {
+            det / det_norm
+        } else {
+            Complex::new(F::one(), F::zero())
+        };
+        let b = Self::from([
+            self[0] + phase * self[3].conj(),
+            self[1] - phase * self[2].conj(),
+            self[2] - phase * self[1].conj(),
+            self[3] + phase * self[0].conj(),
+        ]);
+        // Every column of a unitary matrix has unit length, so the Frobenius
+        // norm of `b` is sqrt(2) times the normalisation.
+        let frobenius = b.frobenius_norm();
+        if frobenius > F::zero() {
+            let u = b * Complex::new(F::from(2.0).unwrap().sqrt() / frobenius, F::zero());
+            (u, u.h() * self)
+        } else {
+            (Self::identity(), Self::from([zero; 4]))
+        }
+    }
+
+    /// Get the QR decomposition of the Jones matrix, `J = Q R`, where `Q` is
+    /// unitary with a determinant of 1 and `R` is upper triangular with a
+    /// real, non-negative first element. The decomposition is returned as
+    /// `(Q, R)`.
+    pub fn qr(self) -> (Self, Self) {
+        let r00 = self[0].norm().hypot(self[2].norm());
+        if r00 > F::zero() {
+            let inv_r00 = Complex::new(r00.recip(), F::zero());
+            let q = Self::from([
+                self[0] * inv_r00,
+                -self[2].conj() * inv_r00,
+                self[2] * inv_r00,
+                self[0].conj() * inv_r00,
+            ]);
+            let mut r = q.h() * self;
+            // These are zero and real by construction; don't keep rounding
+            // errors.
+            r[0] = Complex::new(r00, F::zero());
+            r[2] = Complex::new(F::zero(), F::zero());
+            (q, r)
+        } else {
+            (Self::identity(), self)
+        }
+    }
+
+    /// Get the matrix exponential of the Jones matrix.
+    pub fn exp(self) -> Self {
+        let (m, s, n) = self.split_trace();
+        // exp(J) = exp(m) (cosh(s) I + sinh(s) / s N)
+        let sinhc = if s.norm() < F::from(1e-3).unwrap() {
+            let s2 = s * s;
+            Complex::new(F::one(), F::zero())
+                + s2 / F::from(6.0).unwrap()
+                + s2 * s2 / F::from(120.0).unwrap()
+        } else {
+            s.sinh() / s
+        };
+        (Self::identity() * s.cosh() + n * sinhc) * m.exp()
+    }
+
+    /// Get the principal matrix logarithm of the Jones matrix, i.e. the
+    /// inverse of [`Jones::exp`]. This is only defined if neither eigenvalue
+    /// is zero; the results are not finite for singular matrices. Eigenvalues
+    /// on the negative real axis use the principal branch of the complex
+    /// logarithm.
+    pub fn log(self) -> Self {
+        let (m, s, n) = self.split_trace();
+        let two = F::one() + F::one();
+        let c0 = ((m + s).ln() + (m - s).ln()) / two;
+        // The divided difference of the logarithms of the eigenvalues; when
+        // the eigenvalues are (nearly) equal, use a series of
+        // atanh(s / m) / s instead.
+        let c1 = if s.norm() < F::from(1e-3).unwrap() * m.norm() {
+            let z = s / m;
+            let z2 = z * z;
+            (Complex::new(F::one(), F::zero())
+                + z2 / F::from(3.0).unwrap()
+                + z2 * z2 / F::from(5.0).unwrap())
+                / m
+        } else {
+            ((m + s).ln() - (m - s).ln()) / (s * two)
+        };
+        Self::identity() * c0 + n * c1
+    }
+
+    /// Raise the Jones matrix to the (real) power `alpha`, using the principal
+    /// matrix logarithm, i.e. `exp(alpha log(J))`. For example, `alpha` = 0.5
+    /// gives the principal square root.
+    pub fn powf(self, alpha: F) -> Self {
+        (self.log() * alpha).exp()
+    }
+
+    /// Interpolate between the Jones matrices `a` (`fraction` = 0) and `b`
+    /// (`fraction` = 1) along the geodesic `a (a^I b)^fraction`. Unlike
+    /// interpolating each element linearly, this smoothly varies the
+    /// amplitudes and phases of e.g. calibration solutions.
+    pub fn geodesic_interpolation(a: Self, b: Self, fraction: F) -> Self {
+        a * (a.inv() * b).powf(fraction)
+    }
+
+    /// Is the Jones matrix Hermitian, i.e. equal to its Hermitian conjugate,
+    /// within an absolute tolerance of `epsilon`? Coherency matrices are
+    /// Hermitian.
+    pub fn is_hermitian(self, epsilon: F) -> bool {
+        self[0].im.abs() <= epsilon
+            && self[3].im.abs() <= epsilon
+            && (self[1] - self[2].conj()).norm() <= epsilon
+    }
+
+    /// Is the Jones matrix Hermitian (see [`Jones::is_hermitian`]) and positive
+    /// definite? This is true if both diagonal elements and the determinant
+    /// are positive.
+    pub fn is_positive_definite(self, epsilon: F) -> bool {
+        self.is_hermitian(epsilon) && self[0].re > F::zero() && self.det().re > F::zero()
+    }
+
+    /// Get the Cholesky factorisation of a Hermitian positive definite Jones
+    /// matrix, i.e. the lower-triangular matrix `L` with a real, positive
+    /// diagonal such that `J = L L^H`. Only the diagonal and the lower (YX)
+    /// element are used. `None` is returned if the matrix isn't positive
+    /// definite.
+    pub fn cholesky(self) -> Option<Self> {
+        let zero = Complex::new(F::zero(), F::zero());
+        let l00 = self[0].re;
+        if l00.is_nan() || l00 <= F::zero() {
+            return None;
+        }
+        let l00 = l00.sqrt();
+        let l10 = self[2] / l00;
+        let l11 = self[3].re - l10.norm_sqr();
+        if l11.is_nan() || l11 <= F::zero() {
+            return None;
+        }
+        Some(Self::from([
+            Complex::new(l00, F::zero()),
+            zero,
+            l10,
+            Complex::new(l11.sqrt(), F::zero()),
+        ]))
+    }
+
+    /// Split the Jones matrix into `m I + N`, where `m` is half the trace and
+    /// `N^2 = s^2 I`, i.e. the eigenvalues are `m + s` and `m - s`. Any
+    /// analytic function `f` of the matrix is then `c0 I + c1 N`.
+    fn split_trace(self) -> (Complex<F>, Complex<F>, Self) {
+        let m = self.trace() / (F::one() + F::one());
+        let s = (m * m - self.det()).sqrt();
+        let n = self - Self::identity() * m;
+        (m, s, n)
+    }
+}
+
+/// The gains and leakage terms of a [`Jones`] matrix; see
+/// [`Jones::decompose_gains`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GainDecomposition<F: Float> {
+    /// The amplitudes of the X and Y gains.
+    pub amplitudes: [F; 2],
+
+    /// The phases of the X and Y gains \[radians\].
+    pub phases: [F; 2],
+
+    /// The leakage terms `dxy` and `dyx`, relative to the X and Y gains,
+    /// respectively.
+    pub leakages: [Complex<F>; 2],
 }
 
 impl<F: Float + FloatCore> Jones<F> {
@@ -642,6 +893,312 @@ mod tests {
     use crate::{c32, c64};
     use approx::assert_abs_diff_eq;
 
+    fn is_unitary(j: Jones<f64>) -> bool {
+        approx::abs_diff_eq!(j * j.h(), Jones::identity(), epsilon = 1e-12)
+    }
+
+    #[test]
+    fn test_det_trace_norms() {
+        let j = one_through_eight();
+        // (1+2i)(7+8i) - (3+4i)(5+6i)
+        assert_abs_diff_eq!(j.det(), c64::new(0.0, -16.0));
+        assert_abs_diff_eq!(j.trace(), c64::new(8.0, 10.0));
+        assert_abs_diff_eq!(j.frobenius_norm(), 204_f64.sqrt());
+        assert_abs_diff_eq!(Jones::<f32>::identity().det(), c32::new(1.0, 0.0));
+        assert_abs_diff_eq!(Jones::<f32>::identity().frobenius_norm(), 2_f32.sqrt());
+    }
+
+    #[test]
+    fn test_condition_number() {
+        assert_abs_diff_eq!(Jones::<f64>::identity().condition_number(), 1.0);
+        let j = Jones::from([
+            c64::new(4.0, 0.0),
+            c64::new(0.0, 0.0),
+            c64::new(0.0, 0.0),
+            c64::new(0.0, -0.5),
+        ]);
+        assert_abs_diff_eq!(j.condition_number(), 8.0, epsilon = 1e-12);
+        // Scaling doesn't change the condition number.
+        assert_abs_diff_eq!(
+            (j * c64::new(-3.0, 1e5)).condition_number(),
+            8.0,
+            epsilon = 1e-9
+        );
+
+        // Compare against the singular values of [[1, 2], [3, 4]].
+        let j = Jones::from([
+            c64::new(1.0, 0.0),
+            c64::new(2.0, 0.0),
+            c64::new(3.0, 0.0),
+            c64::new(4.0, 0.0),
+        ]);
+        assert_abs_diff_eq!(
+            j.condition_number(),
+            5.464985704219043 / 0.365966190626258,
+            epsilon = 1e-9
+        );
+
+        assert!(Jones::<f64>::default().condition_number().is_infinite());
+        let singular = Jones::from([
+            c64::new(1.0, 2.0),
+            c64::new(2.0, 4.0),
+            c64::new(-0.5, 0.0),
+            c64::new(-1.0, 0.0),
+        ]);
+        assert!(singular.condition_number().is_infinite());
+    }
+
+    #[test]
+    fn test_exp_log() {
+        assert_abs_diff_eq!(Jones::<f64>::default().exp(), Jones::identity());
+        assert_abs_diff_eq!(Jones::<f64>::identity().log(), Jones::default());
+
+        let diag = Jones::from([
+            c64::new(1.0, 0.5),
+            c64::new(0.0, 0.0),
+            c64::new(0.0, 0.0),
+            c64::new(-2.0, 3.0),
+        ]);
+        assert_abs_diff_eq!(
+            diag.exp(),
+            Jones::from([
+                c64::new(1.0, 0.5).exp(),
+                c64::new(0.0, 0.0),
+                c64::new(0.0, 0.0),
+                c64::new(-2.0, 3.0).exp(),
+            ]),
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(diag.exp().log(), diag, epsilon = 1e-12);
+
+        // Not diagonalisable.
+        let jordan = Jones::from([
+            c64::new(2.0, 0.0),
+            c64::new(1.0, 0.0),
+            c64::new(0.0, 0.0),
+            c64::new(2.0, 0.0),
+        ]);
+        let e2 = 2_f64.exp();
+        assert_abs_diff_eq!(
+            jordan.exp(),
+            Jones::from([
+                c64::new(e2, 0.0),
+                c64::new(e2, 0.0),
+                c64::new(0.0, 0.0),
+                c64::new(e2, 0.0),
+            ]),
+            epsilon = 1e-12
+        );
+        let ln2 = 2_f64.ln();
+        assert_abs_diff_eq!(
+            jordan.log(),
+            Jones::from([
+                c64::new(ln2, 0.0),
+                c64::new(0.5, 0.0),
+                c64::new(0.0, 0.0),
+                c64::new(ln2, 0.0),
+            ]),
+            epsilon = 1e-12
+        );
+
+        let j = one_through_eight();
+        assert_abs_diff_eq!(j.log().exp(), j, epsilon = 1e-10);
+        let small = j * 0.1;
+        assert_abs_diff_eq!(small.exp().log(), small, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_powf() {
+        let j = one_through_eight();
+        assert_abs_diff_eq!(j.powf(1.0), j, epsilon = 1e-10);
+        assert_abs_diff_eq!(j.powf(2.0), j * j, epsilon = 1e-9);
+        assert_abs_diff_eq!(j.powf(-1.0), j.inv(), epsilon = 1e-12);
+        let sqrt = j.powf(0.5);
+        assert_abs_diff_eq!(sqrt * sqrt, j, epsilon = 1e-10);
+        assert_abs_diff_eq!(j.powf(0.0), Jones::identity(), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_geodesic_interpolation() {
+        let a = Jones::from([
+            c64::from_polar(1.0, 0.1),
+            c64::new(0.01, 0.0),
+            c64::new(0.0, -0.02),
+            c64::from_polar(1.2, -0.3),
+        ]);
+        let b = Jones::from([
+            c64::from_polar(1.1, 0.5),
+            c64::new(0.0, 0.01),
+            c64::new(0.03, 0.0),
+            c64::from_polar(0.9, 0.2),
+        ]);
+        assert_abs_diff_eq!(Jones::geodesic_interpolation(a, b, 0.0), a, epsilon = 1e-12);
+        assert_abs_diff_eq!(Jones::geodesic_interpolation(a, b, 1.0), b, epsilon = 1e-12);
+
+        // For diagonal matrices, the amplitudes are interpolated
+        // geometrically and the phases linearly.
+        let a = Jones::from([
+            c64::from_polar(1.0, 0.1),
+            c64::new(0.0, 0.0),
+            c64::new(0.0, 0.0),
+            c64::from_polar(4.0, -0.3),
+        ]);
+        let b = Jones::from([
+            c64::from_polar(4.0, 0.5),
+            c64::new(0.0, 0.0),
+            c64::new(0.0, 0.0),
+            c64::from_polar(1.0, 0.2),
+        ]);
+        let mid = Jones::geodesic_interpolation(a, b, 0.5);
+        assert_abs_diff_eq!(mid[0], c64::from_polar(2.0, 0.3), epsilon = 1e-12);
+        assert_abs_diff_eq!(mid[3], c64::from_polar(2.0, -0.05), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_hermitian_and_cholesky() {
+        // A coherency matrix made from a Jones matrix is Hermitian positive
+        // definite.
+        let j = one_through_eight();
+        let coherency = j * j.h();
+        assert!(!j.is_hermitian(1e-10));
+        assert!(coherency.is_hermitian(1e-10));
+        assert!(coherency.is_positive_definite(1e-10));
+
+        let l = coherency.cholesky().unwrap();
+        assert_abs_diff_eq!(l[1], c64::new(0.0, 0.0));
+        assert!(l[0].re > 0.0 && l[3].re > 0.0);
+        assert_abs_diff_eq!(l[0].im, 0.0);
+        assert_abs_diff_eq!(l[3].im, 0.0);
+        assert_abs_diff_eq!(l * l.h(), coherency, epsilon = 1e-10);
+
+        // Whitening with the inverse factor gives the identity.
+        let l_inv = l.inv();
+        assert_abs_diff_eq!(
+            l_inv * coherency * l_inv.h(),
+            Jones::identity(),
+            epsilon = 1e-10
+        );
+
+        // Small deviations are tolerated by the Hermitian check.
+        let mut averaged = coherency;
+        averaged[1] += c64::new(1e-12, 0.0);
+        assert!(averaged.is_hermitian(1e-10));
+        assert!(!averaged.is_hermitian(0.0));
+
+        // Hermitian but not positive definite.
+        let indefinite = Jones::from([
+            c64::new(1.0, 0.0),
+            c64::new(2.0, 1.0),
+            c64::new(2.0, -1.0),
+            c64::new(1.0, 0.0),
+        ]);
+        assert!(indefinite.is_hermitian(0.0));
+        assert!(!indefinite.is_positive_definite(0.0));
+        assert!(indefinite.cholesky().is_none());
+        assert!((Jones::<f64>::identity() * -1.0).cholesky().is_none());
+        assert!(Jones::<f64>::default().cholesky().is_none());
+        assert!(Jones::<f64>::nan().cholesky().is_none());
+    }
+
+    #[test]
+    fn test_decompose_gains() {
+        let gains = Jones::from([
+            c64::from_polar(2.0, 0.5),
+            c64::new(0.0, 0.0),
+            c64::new(0.0, 0.0),
+            c64::from_polar(0.5, -3.0),
+        ]);
+        let leakages = Jones::from([
+            c64::new(1.0, 0.0),
+            c64::new(0.01, -0.02),
+            c64::new(-0.03, 0.005),
+            c64::new(1.0, 0.0),
+        ]);
+        let decomposition = (gains * leakages).decompose_gains();
+        assert_abs_diff_eq!(decomposition.amplitudes[0], 2.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(decomposition.amplitudes[1], 0.5, epsilon = 1e-12);
+        assert_abs_diff_eq!(decomposition.phases[0], 0.5, epsilon = 1e-12);
+        assert_abs_diff_eq!(decomposition.phases[1], -3.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(decomposition.leakages[0], leakages[1], epsilon = 1e-12);
+        assert_abs_diff_eq!(decomposition.leakages[1], leakages[2], epsilon = 1e-12);
+
+        let decomposition = Jones::<f64>::default().decompose_gains();
+        assert!(decomposition.leakages[0].is_nan());
+    }
+
+    #[test]
+    fn test_polar_decomposition() {
+        for j in [
+            one_through_eight(),
+            Jones::identity(),
+            Jones::from([
+                c64::new(0.0, 1e-8),
+                c64::new(-3.0, 0.5),
+                c64::new(2e5, 0.0),
+                c64::new(0.0, 0.0),
+            ]),
+            // Singular.
+            Jones::from([
+                c64::new(1.0, 2.0),
+                c64::new(2.0, 4.0),
+                c64::new(-0.5, 0.0),
+                c64::new(-1.0, 0.0),
+            ]),
+        ] {
+            let (u, p) = j.polar_decomposition();
+            assert!(is_unitary(u), "{u}");
+            assert_abs_diff_eq!(
+                u * p,
+                j,
+                epsilon = 1e-9 * j[0].norm().max(j[2].norm()).max(1.0)
+            );
+
+            // P is Hermitian positive semi-definite.
+            assert_abs_diff_eq!(p, p.h(), epsilon = 1e-9 * p[0].norm().max(1.0));
+            let det = p.det();
+            assert!(p[0].re >= 0.0 && p[3].re >= 0.0);
+            assert!(det.re >= -1e-9 * p[0].norm_sqr().max(1.0));
+        }
+
+        // A unitary matrix is its own unitary part.
+        let (a, b, phase) = (c64::from_polar(0.6, 0.3), c64::from_polar(0.8, -1.0), 0.7);
+        let u = Jones::from([
+            a,
+            b,
+            -c64::from_polar(1.0, phase) * b.conj(),
+            c64::from_polar(1.0, phase) * a.conj(),
+        ]);
+        assert!(is_unitary(u));
+        let (u2, p) = u.polar_decomposition();
+        assert_abs_diff_eq!(u2, u, epsilon = 1e-12);
+        assert_abs_diff_eq!(p, Jones::identity(), epsilon = 1e-12);
+
+        let (u, p) = Jones::<f64>::default().polar_decomposition();
+        assert!(is_unitary(u));
+        assert_abs_diff_eq!(p, Jones::default());
+    }
+
+    #[test]
+    fn test_qr() {
+        for j in [
+            one_through_eight(),
+            Jones::from([
+                c64::new(0.0, 0.0),
+                c64::new(-3.0, 0.5),
+                c64::new(0.0, 0.0),
+                c64::new(1.0, 1.0),
+            ]),
+        ] {
+            let (q, r) = j.qr();
+            assert!(is_unitary(q));
+            assert_abs_diff_eq!(q.det(), c64::new(1.0, 0.0), epsilon = 1e-12);
+            assert_abs_diff_eq!(r[2], c64::new(0.0, 0.0));
+            assert!(r[0].re >= 0.0);
+            assert_abs_diff_eq!(r[0].im, 0.0);
+            assert_abs_diff_eq!(q * r, j, epsilon = 1e-12);
+        }
+    }
+
     fn one_through_eight() -> Jones<f64> {
         Jones([
             c64::new(1.0, 2.0),
@@ -724,6 +1281,23 @@ mod tests {
         assert_abs_diff_eq!(result, expected, epsilon = 1e-10);
     }
 
+    #[test]
+    fn test_conj() {
+        let a = Jones([
+            c64::new(1.0, 2.0),
+            c64::new(3.0, 4.0),
+            c64::new(5.0, 6.0),
+            c64::new(7.0, 8.0),
+        ]);
+        let expected = Jones([
+            c64::new(1.0, -2.0),
+            c64::new(3.0, -4.0),
+            c64::new(5.0, -6.0),
+            c64::new(7.0, -8.0),
+        ]);
+        assert_abs_diff_eq!(a.conj(), expected, epsilon = 1e-10);
+    }
+
     #[test]
     fn test_div() {
         let a = Jones([
@@ -0,0 +1,177 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A structure-of-arrays container of [`Jones`] matrices.
+//!
+//! An `Array3<Jones<f32>>` interleaves the real and imaginary parts of all four
+//! polarisations of each visibility. [`JonesArray`] instead stores each part of
+//! each polarisation in its own contiguous plane, so that kernels can operate
+//! on many baselines at once with vector instructions, and planes can be handed
+//! to GPU or FFT libraries without copying.
+
+use ndarray::prelude::*;
+
+use crate::{Complex, Jones};
+
+/// [`Jones`] matrices with dimensions `[timestep][channel][baseline]`, stored
+/// as separate real and imaginary planes with dimensions
+/// `[pol][timestep][channel][baseline]`. The polarisations are ordered XX, XY,
+/// YX, YY, and the planes are always in standard (C) layout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JonesArray {
+    re: Array4<f32>,
+    im: Array4<f32>,
+}
+
+impl JonesArray {
+    /// Create a [`JonesArray`] of zeros with dimensions
+    /// `[timestep][channel][baseline]`.
+    pub fn zeros(dim: (usize, usize, usize)) -> Self {
+        let (num_timesteps, num_chans, num_baselines) = dim;
+        let plane_dim = (4, num_timesteps, num_chans, num_baselines);
+        Self {
+            re: Array4::zeros(plane_dim),
+            im: Array4::zeros(plane_dim),
+        }
+    }
+
+    /// Copy an array of [`Jones`] matrices into a [`JonesArray`].
+    pub fn from_jones(jones_array: ArrayView3<Jones<f32>>) -> Self {
+        let mut new = Self::zeros(jones_array.dim());
+        for (pol, (mut re, mut im)) in new
+            .re
+            .outer_iter_mut()
+            .zip(new.im.outer_iter_mut())
+            .enumerate()
+        {
+            re.zip_mut_with(&jones_array, |re, jones| *re = jones[pol].re);
+            im.zip_mut_with(&jones_array, |im, jones| *im = jones[pol].im);
+        }
+        new
+    }
+
+    /// Copy the [`JonesArray`] into an array of [`Jones`] matrices.
+    pub fn to_jones(&self) -> Array3<Jones<f32>> {
+        let mut jones_array = Array3::<Jones<f32>>::zeros(self.dim());
+        for (pol, (re, im)) in self.re.outer_iter().zip(self.im.outer_iter()).enumerate() {
+            jones_array.zip_mut_with(&re, |jones, &re| jones[pol].re = re);
+            jones_array.zip_mut_with(&im, |jones, &im| jones[pol].im = im);
+        }
+        jones_array
+    }
+
+    /// The dimensions `[timestep][channel][baseline]`.
+    pub fn dim(&self) -> (usize, usize, usize) {
+        let (_, num_timesteps, num_chans, num_baselines) = self.re.dim();
+        (num_timesteps, num_chans, num_baselines)
+    }
+
+    /// Get the [`Jones`] matrix at `[timestep][channel][baseline]`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn get(&self, index: (usize, usize, usize)) -> Jones<f32> {
+        let (t, c, b) = index;
+        let mut jones = [Complex::new(0.0, 0.0); 4];
+        for (pol, jones) in jones.iter_mut().enumerate() {
+            *jones = Complex::new(self.re[(pol, t, c, b)], self.im[(pol, t, c, b)]);
+        }
+        Jones::from(jones)
+    }
+
+    /// Set the [`Jones`] matrix at `[timestep][channel][baseline]`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set(&mut self, index: (usize, usize, usize), jones: Jones<f32>) {
+        let (t, c, b) = index;
+        for (pol, jones) in jones.iter().enumerate() {
+            self.re[(pol, t, c, b)] = jones.re;
+            self.im[(pol, t, c, b)] = jones.im;
+        }
+    }
+
+    /// The real planes, with dimensions `[pol][timestep][channel][baseline]`.
+    pub fn re(&self) -> ArrayView4<'_, f32> {
+        self.re.view()
+    }
+
+    /// The imaginary planes, with dimensions
+    /// `[pol][timestep][channel][baseline]`.
+    pub fn im(&self) -> ArrayView4<'_, f32> {
+        self.im.view()
+    }
+
+    /// Mutable views of the real and imaginary planes, with dimensions
+    /// `[pol][timestep][channel][baseline]`.
+    pub fn planes_mut(&mut self) -> (ArrayViewMut4<'_, f32>, ArrayViewMut4<'_, f32>) {
+        (self.re.view_mut(), self.im.view_mut())
+    }
+
+    /// The contiguous real and imaginary planes, e.g. to copy to a GPU.
+    pub fn as_slices(&self) -> (&[f32], &[f32]) {
+        (
+            self.re.as_slice().expect("planes are in standard layout"),
+            self.im.as_slice().expect("planes are in standard layout"),
+        )
+    }
+
+    /// Consume the [`JonesArray`], returning the real and imaginary planes
+    /// without copying.
+    pub fn into_planes(self) -> (Array4<f32>, Array4<f32>) {
+        (self.re, self.im)
+    }
+}
+
+impl From<ArrayView3<'_, Jones<f32>>> for JonesArray {
+    fn from(jones_array: ArrayView3<Jones<f32>>) -> Self {
+        Self::from_jones(jones_array)
+    }
+}
+
+impl From<&JonesArray> for Array3<Jones<f32>> {
+    fn from(jones_array: &JonesArray) -> Self {
+        jones_array.to_jones()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_jones_array_round_trip() {
+        let jones_array = Array3::from_shape_fn((2, 3, 4), |(t, c, b)| {
+            Jones::from([
+                Complex::new(t as f32, c as f32),
+                Complex::new(b as f32, 1.0),
+                Complex::new(-(t as f32), 2.0),
+                Complex::new(3.0, (t * c * b) as f32),
+            ])
+        });
+        let soa = JonesArray::from(jones_array.view());
+        assert_eq!(soa.dim(), (2, 3, 4));
+        assert_eq!(soa.re().dim(), (4, 2, 3, 4));
+        assert_abs_diff_eq!(soa.re()[(1, 1, 2, 3)], 3.0);
+        assert_abs_diff_eq!(soa.im()[(3, 1, 2, 3)], 6.0);
+        assert_abs_diff_eq!(soa.get((1, 2, 3)), jones_array[(1, 2, 3)]);
+
+        let (re, im) = soa.as_slices();
+        assert_eq!(re.len(), 4 * 2 * 3 * 4);
+        assert_eq!(im.len(), re.len());
+        // The baseline axis is contiguous; this is XY at (0, 0, 3).
+        assert_abs_diff_eq!(re[2 * 3 * 4 + 3], 3.0);
+
+        assert_eq!(Array3::from(&soa), jones_array);
+
+        let mut soa = JonesArray::zeros((2, 3, 4));
+        soa.set((0, 1, 2), Jones::identity());
+        assert_abs_diff_eq!(soa.get((0, 1, 2)), Jones::identity());
+        assert_abs_diff_eq!(soa.get((0, 1, 1)), Jones::default());
+    }
+}
@@ -30,42 +30,90 @@ pub type c32 = num_complex::Complex<f32>;
 pub type c64 = num_complex::Complex<f64>;
 
 pub mod averaging;
+pub mod beam;
+pub mod capabilities;
+pub mod catalog;
+pub mod channels;
 pub mod constants;
 pub mod context;
+pub mod corrections;
+pub mod errors;
+pub mod flagging;
 pub mod jones;
+pub mod jones_array;
 pub mod math;
 pub mod pos;
+pub mod prelude;
+pub mod resample;
+pub mod runtime;
 pub mod selection;
+pub mod self_test;
+pub mod sensitivity;
 pub mod sexagesimal;
+pub mod synth;
+pub mod time;
+pub mod weights;
 
 pub mod io;
 #[cfg(feature = "ms")]
 pub use io::ms;
 #[cfg(feature = "cfitsio")]
 pub use io::uvfits;
-#[cfg(feature = "mwalib")]
-pub use io::VisRead;
-pub use io::VisWrite;
+pub use io::{OutputEstimate, PolFrame, SplitWriter, VisRead, VisWrite, VisWriteFormat, VisWriter};
+#[cfg(feature = "parquet")]
+pub use io::{ParquetWriteError, ParquetWriter};
+#[cfg(feature = "zarr")]
+pub use io::{ZarrWriteError, ZarrWriter};
+
+#[cfg(feature = "bench")]
+pub mod bench;
+
+#[cfg(feature = "fft")]
+pub mod fringe;
+#[cfg(feature = "fft")]
+pub mod transform;
 
 #[cfg(feature = "cuda")]
 pub mod cuda;
 
+#[cfg(feature = "python")]
+pub mod python;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
 // Re-exports.
-pub use context::{History, MwaObsContext, ObsContext, VisContext};
-pub use jones::Jones;
+pub use capabilities::{capabilities, Capabilities};
+pub use catalog::{Catalog, CatalogMatch};
+pub use channels::ChannelLayout;
+pub use context::{
+    AnalogueBeam, AveragingPolicy, ChunkLocation, ContextDiff, ContextDifference, History,
+    InstrumentState, MountType, MwaObsContext, ObsContext, Provenance, Telescope, VisContext,
+    VisContextBuilder, VisContextError, VisUnit,
+};
+pub use jones::{GainDecomposition, Jones};
+pub use jones_array::JonesArray;
 pub use pos::{
     azel::AzEl,
     earth::{Ellipsoid, LatLngHeight},
     enh::ENH,
     hadec::HADec,
+    iers,
     lmn::{LmnRime, LMN},
     pal, precession,
     radec::RADec,
+    tile_group::TileGroup,
     uvw::UVW,
     xyz::{XyzGeocentric, XyzGeodetic},
 };
-pub use selection::{SelectionError, VisSelection};
+pub use selection::{
+    AntennaRenumbering, FlagPolicy, MemoryPlan, SelectionError, TileBaselineMaps, VisSelection,
+};
+#[cfg(feature = "mwalib")]
+pub use selection::{VisChunk, VisChunkIter};
+pub use weights::WeightFlags;
 
+#[cfg(feature = "erfa")]
 pub use erfa_sys;
 pub use hifitime;
 pub use ndarray;
@@ -86,17 +134,18 @@ cfg_if::cfg_if! {
     if #[cfg(feature = "mwalib")] {
         pub use mwalib;
         pub use mwalib::{fitsio, fitsio_sys};
+        pub use io::GpuboxReader;
     }
 }
 
 #[cfg(feature = "cfitsio")]
-pub use io::{UvfitsWriteError, UvfitsWriter};
+pub use io::{UvfitsReader, UvfitsWriteError, UvfitsWriter};
 
 // If "ms" is enabled, re-export rubbl_casatables here.
 cfg_if::cfg_if! {
     if #[cfg(feature = "ms")] {
         pub use rubbl_casatables;
-        pub use io::MeasurementSetWriter;
+        pub use io::{MeasurementSetReader, MeasurementSetWriter};
     }
 }
 
@@ -5,10 +5,11 @@
 //! Handle (azimuth, elevation) coordinates (also known as horizontal
 //! coordinates).
 
-use super::hadec::HADec;
-use std::f64::consts::FRAC_PI_2;
+use super::{enh::ENH, erfa, hadec::HADec};
+use std::f64::consts::{FRAC_PI_2, TAU};
 
 /// A struct containing an Azimuth and Elevation. All units are in radians.
+#[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct AzEl {
     /// Hour angle \[radians\]
@@ -36,14 +37,33 @@ impl AzEl {
         FRAC_PI_2 - self.el
     }
 
+    /// Convert the horizon coordinates to a unit direction vector in the local
+    /// topocentric [`ENH`] frame. Azimuth is measured from north towards east.
+    pub fn to_enh_direction(self) -> ENH {
+        let (s_az, c_az) = self.az.sin_cos();
+        let (s_el, c_el) = self.el.sin_cos();
+        ENH {
+            e: c_el * s_az,
+            n: c_el * c_az,
+            h: s_el,
+        }
+    }
+
+    /// Convert a direction vector in the local topocentric [`ENH`] frame to
+    /// horizon coordinates. The vector doesn't need to be normalised. The
+    /// azimuth is in the range \[0, 2pi).
+    pub fn from_enh_direction(enh: ENH) -> AzEl {
+        let az = enh.e.atan2(enh.n).rem_euclid(TAU);
+        let el = enh.h.atan2(enh.e.hypot(enh.n));
+        AzEl::new(az, el)
+    }
+
     /// Convert the horizon coordinates to equatorial coordinates (Hour Angle
     /// and Declination), given the local latitude on Earth.
     ///
     /// Uses ERFA.
     pub fn to_hadec(self, latitude_rad: f64) -> HADec {
-        let mut ha = 0.0;
-        let mut dec = 0.0;
-        unsafe { erfa_sys::eraAe2hd(self.az, self.el, latitude_rad, &mut ha, &mut dec) }
+        let (ha, dec) = erfa::ae2hd(self.az, self.el, latitude_rad);
         HADec::new(ha, dec)
     }
 
@@ -126,6 +146,51 @@ mod tests {
         assert_abs_diff_eq!(result, expected, epsilon = 1e-10);
     }
 
+    #[test]
+    fn test_enh_direction() {
+        let ae = AzEl::new_degrees(0.0, 0.0);
+        assert_abs_diff_eq!(
+            ae.to_enh_direction(),
+            ENH {
+                e: 0.0,
+                n: 1.0,
+                h: 0.0
+            },
+            epsilon = 1e-10
+        );
+        let ae = AzEl::new_degrees(90.0, 0.0);
+        assert_abs_diff_eq!(
+            ae.to_enh_direction(),
+            ENH {
+                e: 1.0,
+                n: 0.0,
+                h: 0.0
+            },
+            epsilon = 1e-10
+        );
+
+        for ae in [
+            AzEl::new_degrees(45.0, 30.0),
+            AzEl::new_degrees(200.0, 80.0),
+            AzEl::new_degrees(350.0, -10.0),
+        ] {
+            let enh = ae.to_enh_direction();
+            assert_abs_diff_eq!(
+                enh.e * enh.e + enh.n * enh.n + enh.h * enh.h,
+                1.0,
+                epsilon = 1e-10
+            );
+            assert_abs_diff_eq!(AzEl::from_enh_direction(enh), ae, epsilon = 1e-10);
+            // The vector doesn't need to be normalised.
+            let enh = ENH {
+                e: enh.e * 3.0,
+                n: enh.n * 3.0,
+                h: enh.h * 3.0,
+            };
+            assert_abs_diff_eq!(AzEl::from_enh_direction(enh), ae, epsilon = 1e-10);
+        }
+    }
+
     #[test]
     fn test_za() {
         let ae = AzEl::new(0.261700, 0.785400);
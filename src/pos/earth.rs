@@ -6,15 +6,14 @@
 
 use std::fmt::Display;
 
-use erfa_sys::{ERFA_GRS80, ERFA_WGS72, ERFA_WGS84};
-
-use super::ErfaError;
+use super::{erfa, ErfaError};
 use crate::{
     constants::{MWA_HEIGHT_M, MWA_LAT_RAD, MWA_LONG_RAD},
     XyzGeocentric,
 };
 
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// An earth position: Latitude, Longitude and Height [radians, meters]
 pub struct LatLngHeight {
     /// Longitude \[radians\]
@@ -28,11 +27,11 @@ pub struct LatLngHeight {
 /// Enum of erfa-compatible reference ellipsoids.
 pub enum Ellipsoid {
     /// WGS84 reference ellipsoid
-    WGS84 = ERFA_WGS84 as isize,
+    WGS84 = erfa::WGS84 as isize,
     /// GRS80 reference ellipsoid
-    GRS80 = ERFA_GRS80 as isize,
+    GRS80 = erfa::GRS80 as isize,
     /// WGS72 reference ellipsoid
-    WGS72 = ERFA_WGS72 as isize,
+    WGS72 = erfa::WGS72 as isize,
 }
 
 impl LatLngHeight {
@@ -45,31 +44,25 @@ impl LatLngHeight {
         }
     }
 
-    /// Convert to [`XyzGeocentric`] via [`erfa_sys::eraGd2gc`] with the specified
+    /// Convert to [`XyzGeocentric`] via ERFA's `eraGd2gc` with the specified
     /// [`Ellipsoid`]
     ///
     /// # Errors
     ///
-    /// Can return an [`ErfaError`] if [`erfa_sys::eraGd2gc`] fails.
+    /// Can return an [`ErfaError`] if `eraGd2gc` fails.
     pub fn to_geocentric(self, ellipsoid: Ellipsoid) -> Result<XyzGeocentric, ErfaError> {
-        let mut geocentric_vector: [f64; 3] = [0.0; 3];
-        let status = unsafe {
-            erfa_sys::eraGd2gc(
-                ellipsoid as i32,               // ellipsoid identifier (Note 1)
-                self.longitude_rad,             // longitude (radians, east +ve)
-                self.latitude_rad,              // latitude (geodetic, radians, Note 3)
-                self.height_metres,             // height above ellipsoid (geodetic, Notes 2,3)
-                geocentric_vector.as_mut_ptr(), // geocentric vector (Note 2)
-            )
-        };
-        if status != 0 {
-            return Err(ErfaError {
-                source_file: file!(),
-                source_line: line!(),
-                status,
-                function: "eraGd2gc",
-            });
-        }
+        let geocentric_vector = erfa::gd2gc(
+            ellipsoid as i32,   // ellipsoid identifier (Note 1)
+            self.longitude_rad, // longitude (radians, east +ve)
+            self.latitude_rad,  // latitude (geodetic, radians, Note 3)
+            self.height_metres, // height above ellipsoid (geodetic, Notes 2,3)
+        )
+        .map_err(|status| ErfaError {
+            source_file: file!(),
+            source_line: line!(),
+            status,
+            function: "eraGd2gc",
+        })?;
         Ok(XyzGeocentric {
             x: geocentric_vector[0],
             y: geocentric_vector[1],
@@ -81,7 +74,7 @@ impl LatLngHeight {
     ///
     /// # Errors
     ///
-    /// Can return an [`ErfaError`] if [`erfa_sys::eraGd2gc`] fails.
+    /// Can return an [`ErfaError`] if `eraGd2gc` fails.
     pub fn to_geocentric_wgs84(self) -> Result<XyzGeocentric, ErfaError> {
         self.to_geocentric(Ellipsoid::WGS84)
     }
@@ -0,0 +1,515 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The ERFA routines used by Marlu.
+//!
+//! With the `erfa` feature (enabled by default), these call the ERFA C library
+//! through [`erfa_sys`]. Without it, the pure-Rust ports in [`ports`] are used
+//! instead, so Marlu can be built without a C toolchain. The ports follow ERFA
+//! operation-for-operation, and the tests below check them against the ERFA
+//! test suite (and, with the `erfa` feature, against ERFA itself).
+
+mod ports;
+#[rustfmt::skip]
+mod tables;
+
+pub(crate) use ports::{epj, epj2jd, ir, pdp, pn, rx, rxp, rxr, rz, DJM0, GRS80, WGS72, WGS84};
+
+#[cfg(not(feature = "erfa"))]
+pub(crate) use ports::{
+    ae2hd, anp, c2s, epv00, era00, gc2gd, gd2gc, gmst06, gst06a, hd2ae, hd2pa, num06a, p06e, pas,
+    pmat06, pnm06a, s2c, seps,
+};
+
+#[cfg(feature = "erfa")]
+pub(crate) use ffi::{
+    ae2hd, anp, c2s, epv00, era00, gc2gd, gd2gc, gmst06, gst06a, hd2ae, hd2pa, num06a, p06e, pas,
+    pmat06, pnm06a, s2c, seps,
+};
+
+/// Safe wrappers of the ERFA C library with the same signatures as the
+/// functions in [`ports`].
+#[cfg(feature = "erfa")]
+mod ffi {
+    use super::ports::P06e;
+
+    pub(crate) fn anp(a: f64) -> f64 {
+        unsafe { erfa_sys::eraAnp(a) }
+    }
+
+    pub(crate) fn s2c(theta: f64, phi: f64) -> [f64; 3] {
+        let mut c = [0.0; 3];
+        unsafe { erfa_sys::eraS2c(theta, phi, c.as_mut_ptr()) };
+        c
+    }
+
+    pub(crate) fn c2s(p: &[f64; 3]) -> (f64, f64) {
+        let mut p = *p;
+        let mut theta = 0.0;
+        let mut phi = 0.0;
+        unsafe { erfa_sys::eraC2s(p.as_mut_ptr(), &mut theta, &mut phi) };
+        (theta, phi)
+    }
+
+    pub(crate) fn seps(al: f64, ap: f64, bl: f64, bp: f64) -> f64 {
+        unsafe { erfa_sys::eraSeps(al, ap, bl, bp) }
+    }
+
+    pub(crate) fn pas(al: f64, ap: f64, bl: f64, bp: f64) -> f64 {
+        unsafe { erfa_sys::eraPas(al, ap, bl, bp) }
+    }
+
+    pub(crate) fn hd2ae(ha: f64, dec: f64, phi: f64) -> (f64, f64) {
+        let mut az = 0.0;
+        let mut el = 0.0;
+        unsafe { erfa_sys::eraHd2ae(ha, dec, phi, &mut az, &mut el) };
+        (az, el)
+    }
+
+    pub(crate) fn ae2hd(az: f64, el: f64, phi: f64) -> (f64, f64) {
+        let mut ha = 0.0;
+        let mut dec = 0.0;
+        unsafe { erfa_sys::eraAe2hd(az, el, phi, &mut ha, &mut dec) };
+        (ha, dec)
+    }
+
+    pub(crate) fn hd2pa(ha: f64, dec: f64, phi: f64) -> f64 {
+        unsafe { erfa_sys::eraHd2pa(ha, dec, phi) }
+    }
+
+    pub(crate) fn gd2gc(n: i32, elong: f64, phi: f64, height: f64) -> Result<[f64; 3], i32> {
+        let mut xyz = [0.0; 3];
+        match unsafe { erfa_sys::eraGd2gc(n, elong, phi, height, xyz.as_mut_ptr()) } {
+            0 => Ok(xyz),
+            status => Err(status),
+        }
+    }
+
+    pub(crate) fn gc2gd(n: i32, xyz: &[f64; 3]) -> Result<(f64, f64, f64), i32> {
+        let mut xyz = *xyz;
+        let mut elong = 0.0;
+        let mut phi = 0.0;
+        let mut height = 0.0;
+        match unsafe { erfa_sys::eraGc2gd(n, xyz.as_mut_ptr(), &mut elong, &mut phi, &mut height) }
+        {
+            0 => Ok((elong, phi, height)),
+            status => Err(status),
+        }
+    }
+
+    pub(crate) fn era00(dj1: f64, dj2: f64) -> f64 {
+        unsafe { erfa_sys::eraEra00(dj1, dj2) }
+    }
+
+    pub(crate) fn gmst06(uta: f64, utb: f64, tta: f64, ttb: f64) -> f64 {
+        unsafe { erfa_sys::eraGmst06(uta, utb, tta, ttb) }
+    }
+
+    pub(crate) fn gst06a(uta: f64, utb: f64, tta: f64, ttb: f64) -> f64 {
+        unsafe { erfa_sys::eraGst06a(uta, utb, tta, ttb) }
+    }
+
+    pub(crate) fn pmat06(date1: f64, date2: f64) -> [[f64; 3]; 3] {
+        let mut r = [[0.0; 3]; 3];
+        unsafe { erfa_sys::eraPmat06(date1, date2, r.as_mut_ptr()) };
+        r
+    }
+
+    pub(crate) fn num06a(date1: f64, date2: f64) -> [[f64; 3]; 3] {
+        let mut r = [[0.0; 3]; 3];
+        unsafe { erfa_sys::eraNum06a(date1, date2, r.as_mut_ptr()) };
+        r
+    }
+
+    pub(crate) fn pnm06a(date1: f64, date2: f64) -> [[f64; 3]; 3] {
+        let mut r = [[0.0; 3]; 3];
+        unsafe { erfa_sys::eraPnm06a(date1, date2, r.as_mut_ptr()) };
+        r
+    }
+
+    pub(crate) fn p06e(date1: f64, date2: f64) -> P06e {
+        let mut p = P06e::default();
+        unsafe {
+            erfa_sys::eraP06e(
+                date1,
+                date2,
+                &mut p.eps0,
+                &mut p.psia,
+                &mut p.oma,
+                &mut p.bpa,
+                &mut p.bqa,
+                &mut p.pia,
+                &mut p.bpia,
+                &mut p.epsa,
+                &mut p.chia,
+                &mut p.za,
+                &mut p.zetaa,
+                &mut p.thetaa,
+                &mut p.pa,
+                &mut p.gam,
+                &mut p.phi,
+                &mut p.psi,
+            );
+        }
+        p
+    }
+
+    pub(crate) fn epv00(date1: f64, date2: f64) -> ([[f64; 3]; 2], [[f64; 3]; 2]) {
+        let mut pvh = [[0.0; 3]; 2];
+        let mut pvb = [[0.0; 3]; 2];
+        unsafe { erfa_sys::eraEpv00(date1, date2, pvh.as_mut_ptr(), pvb.as_mut_ptr()) };
+        (pvh, pvb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::ports;
+
+    // Expected values are from the ERFA test suite (t_erfa_c.c).
+
+    #[test]
+    fn test_angles() {
+        assert_abs_diff_eq!(ports::anp(-0.1), 6.183185307179586477, epsilon = 1e-12);
+        assert_abs_diff_eq!(
+            ports::seps(1.0, 0.1, 0.2, -3.0),
+            2.346722016996998842,
+            epsilon = 1e-14
+        );
+        assert_abs_diff_eq!(
+            ports::pas(1.0, 0.1, 0.2, -1.0),
+            -2.724544922932270424,
+            epsilon = 1e-12
+        );
+
+        let (az, el) = ports::hd2ae(1.1, 1.2, 0.3);
+        assert_abs_diff_eq!(az, 5.916889243730066194, epsilon = 1e-13);
+        assert_abs_diff_eq!(el, 0.4472186304990486228, epsilon = 1e-14);
+        let (ha, dec) = ports::ae2hd(5.5, 1.1, 0.7);
+        assert_abs_diff_eq!(ha, 0.5933291115507309663, epsilon = 1e-14);
+        assert_abs_diff_eq!(dec, 0.9613934761647817620, epsilon = 1e-14);
+        assert_abs_diff_eq!(
+            ports::hd2pa(1.1, 1.2, 0.3),
+            1.906227428001995580,
+            epsilon = 1e-13
+        );
+    }
+
+    #[test]
+    fn test_geodetic() {
+        assert_eq!(ports::gd2gc(0, 3.1, -0.5, 2500.0), Err(-1));
+        assert_eq!(ports::gd2gc(4, 3.1, -0.5, 2500.0), Err(-1));
+        for (n, expected) in [
+            (
+                ports::WGS84,
+                [-5599000.5577049947, 233011.67223479203, -3040909.4706983363],
+            ),
+            (
+                ports::GRS80,
+                [
+                    -5599000.5577260984,
+                    233011.6722356702949,
+                    -3040909.4706095476,
+                ],
+            ),
+            (
+                ports::WGS72,
+                [
+                    -5598998.7626301490,
+                    233011.5975297822211,
+                    -3040908.6861467111,
+                ],
+            ),
+        ] {
+            let xyz = ports::gd2gc(n, 3.1, -0.5, 2500.0).unwrap();
+            assert_abs_diff_eq!(xyz[..], expected[..], epsilon = 1e-7);
+        }
+
+        let xyz = [2e6, 3e6, 5.244e6];
+        assert_eq!(ports::gc2gd(0, &xyz), Err(-1));
+        assert_eq!(ports::gc2gd(4, &xyz), Err(-1));
+        for (n, expected_phi, expected_height) in [
+            (ports::WGS84, 0.97160184819075459, 331.4172461426059892),
+            (ports::GRS80, 0.97160184820607853, 331.41731754844348),
+            (ports::WGS72, 0.9716018181101511937, 333.2770726130318123),
+        ] {
+            let (elong, phi, height) = ports::gc2gd(n, &xyz).unwrap();
+            assert_abs_diff_eq!(elong, 0.9827937232473290680, epsilon = 1e-14);
+            assert_abs_diff_eq!(phi, expected_phi, epsilon = 1e-14);
+            assert_abs_diff_eq!(height, expected_height, epsilon = 1e-8);
+        }
+    }
+
+    #[test]
+    fn test_sidereal_time() {
+        assert_abs_diff_eq!(
+            ports::era00(2400000.5, 54388.0),
+            0.4022837240028158102,
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(
+            ports::gmst06(2400000.5, 53736.0, 2400000.5, 53736.0),
+            1.754174971870091203,
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(
+            ports::gst06a(2400000.5, 53736.0, 2400000.5, 53736.0),
+            1.754166137675019159,
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(
+            ports::s06(
+                2400000.5,
+                53736.0,
+                0.5791308486706011000e-3,
+                0.4020579816732961219e-4
+            ),
+            -0.1220032213076463117e-7,
+            epsilon = 1e-18
+        );
+    }
+
+    #[test]
+    fn test_nutation() {
+        assert_abs_diff_eq!(
+            ports::obl06(2400000.5, 54388.0),
+            0.4090749229387258204,
+            epsilon = 1e-14
+        );
+
+        let (dpsi, deps) = ports::nut00a(2400000.5, 53736.0);
+        assert_abs_diff_eq!(dpsi, -0.9630909107115518431e-5, epsilon = 1e-13);
+        assert_abs_diff_eq!(deps, 0.4063239174001678710e-4, epsilon = 1e-13);
+        let (dpsi, deps) = ports::nut06a(2400000.5, 53736.0);
+        assert_abs_diff_eq!(dpsi, -0.9630912025820308797e-5, epsilon = 1e-13);
+        assert_abs_diff_eq!(deps, 0.4063238496887249798e-4, epsilon = 1e-13);
+
+        let expected = [
+            [
+                0.9999999999536227668,
+                0.8836241998111535233e-5,
+                0.3830834608415287707e-5,
+            ],
+            [
+                -0.8836086334870740138e-5,
+                0.9999999991354657474,
+                -0.4063240188248455065e-4,
+            ],
+            [
+                -0.3831193642839398128e-5,
+                0.4063236803101479770e-4,
+                0.9999999991671663114,
+            ],
+        ];
+        let r = ports::num06a(2400000.5, 53736.0);
+        for (r, expected) in r.iter().zip(expected) {
+            assert_abs_diff_eq!(r[..], expected[..], epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_precession() {
+        let expected = [
+            [
+                0.9999995505176007047,
+                0.8695404617348208406e-3,
+                0.3779735201865589104e-3,
+            ],
+            [
+                -0.8695404723772031414e-3,
+                0.9999996219496027161,
+                -0.1361752497080270143e-6,
+            ],
+            [
+                -0.3779734957034089490e-3,
+                -0.1924880847894457113e-6,
+                0.9999999285679971958,
+            ],
+        ];
+        let r = ports::pmat06(2400000.5, 50123.9999);
+        for (r, expected) in r.iter().zip(expected) {
+            assert_abs_diff_eq!(r[..], expected[..], epsilon = 1e-14);
+        }
+
+        let expected = [
+            [
+                0.9999995832794205484,
+                0.8372382772630962111e-3,
+                0.3639684771140623099e-3,
+            ],
+            [
+                -0.8372533744743683605e-3,
+                0.9999996486492861646,
+                0.4132905944611019498e-4,
+            ],
+            [
+                -0.3639337469629464969e-3,
+                -0.4163377605910663999e-4,
+                0.9999999329094260057,
+            ],
+        ];
+        let r = ports::pnm06a(2400000.5, 50123.9999);
+        for (r, expected) in r.iter().zip(expected) {
+            assert_abs_diff_eq!(r[..], expected[..], epsilon = 1e-14);
+        }
+
+        let p = ports::p06e(2400000.5, 52541.0);
+        for (received, expected) in [
+            (p.eps0, 0.4090926006005828715),
+            (p.psia, 0.6664369630191613431e-3),
+            (p.oma, 0.4090925973783255982),
+            (p.bpa, 0.5561149371265209445e-6),
+            (p.bqa, -0.6191517193290621270e-5),
+            (p.pia, 0.6216441751884382923e-5),
+            (p.bpia, 3.052014180023779882),
+            (p.epsa, 0.4090864054922431688),
+            (p.chia, 0.1387703379530915364e-5),
+            (p.za, 0.2921789846651790546e-3),
+            (p.zetaa, 0.3178773290332009310e-3),
+            (p.thetaa, 0.2650932701657497181e-3),
+            (p.pa, 0.6651637681381016288e-3),
+            (p.gam, 0.1398077115963754987e-5),
+            (p.phi, 0.4090864090837462602),
+            (p.psi, 0.6664464807480920325e-3),
+        ] {
+            assert_abs_diff_eq!(received, expected, epsilon = 1e-14);
+        }
+    }
+
+    #[test]
+    fn test_epv00() {
+        let (pvh, pvb) = ports::epv00(2400000.5, 53411.52501161);
+        assert_abs_diff_eq!(
+            pvh[0][..],
+            [
+                -0.7757238809297706813,
+                0.5598052241363340596,
+                0.2426998466481686993
+            ][..],
+            epsilon = 1e-14
+        );
+        assert_abs_diff_eq!(
+            pvh[1][..],
+            [
+                -0.1091891824147313846e-1,
+                -0.1247187268440845008e-1,
+                -0.5407569418065039061e-2
+            ][..],
+            epsilon = 1e-15
+        );
+        assert_abs_diff_eq!(
+            pvb[0][..],
+            [
+                -0.7714104440491111971,
+                0.5598412061824171323,
+                0.2425996277722452400
+            ][..],
+            epsilon = 1e-14
+        );
+        assert_abs_diff_eq!(
+            pvb[1][..],
+            [
+                -0.1091874268116823295e-1,
+                -0.1246525461732861538e-1,
+                -0.5404773180966231279e-2
+            ][..],
+            epsilon = 1e-15
+        );
+    }
+
+    /// The ports should agree with ERFA itself over a range of dates and
+    /// angles, not just at the test suite's values.
+    #[cfg(feature = "erfa")]
+    #[test]
+    fn test_ports_match_ffi() {
+        use super::ffi;
+
+        // 1950 to 2050.
+        for mjd in (33282..=69807).step_by(997).map(|d| f64::from(d) + 0.123) {
+            let date = (2400000.5, mjd);
+            assert_abs_diff_eq!(
+                ports::era00(date.0, date.1),
+                ffi::era00(date.0, date.1),
+                epsilon = 1e-15
+            );
+            assert_abs_diff_eq!(
+                ports::gmst06(date.0, date.1, date.0, date.1),
+                ffi::gmst06(date.0, date.1, date.0, date.1),
+                epsilon = 1e-15
+            );
+            assert_abs_diff_eq!(
+                ports::gst06a(date.0, date.1, date.0, date.1),
+                ffi::gst06a(date.0, date.1, date.0, date.1),
+                epsilon = 1e-15
+            );
+            for (port, ffi) in [
+                (ports::pmat06(date.0, date.1), ffi::pmat06(date.0, date.1)),
+                (ports::num06a(date.0, date.1), ffi::num06a(date.0, date.1)),
+                (ports::pnm06a(date.0, date.1), ffi::pnm06a(date.0, date.1)),
+            ] {
+                for (port, ffi) in port.iter().zip(ffi) {
+                    assert_abs_diff_eq!(port[..], ffi[..], epsilon = 1e-15);
+                }
+            }
+            let port = ports::p06e(date.0, date.1);
+            let ffi = ffi::p06e(date.0, date.1);
+            assert_abs_diff_eq!(port.chia, ffi.chia, epsilon = 1e-15);
+            assert_abs_diff_eq!(port.oma, ffi.oma, epsilon = 1e-15);
+            assert_abs_diff_eq!(port.psia, ffi.psia, epsilon = 1e-15);
+            assert_abs_diff_eq!(port.eps0, ffi.eps0, epsilon = 1e-15);
+            let (port_h, port_b) = ports::epv00(date.0, date.1);
+            let (ffi_h, ffi_b) = ffi::epv00(date.0, date.1);
+            for (port, ffi) in port_h.iter().chain(&port_b).zip(ffi_h.iter().chain(&ffi_b)) {
+                assert_abs_diff_eq!(port[..], ffi[..], epsilon = 1e-15);
+            }
+        }
+
+        for i in -10..=10 {
+            let a = f64::from(i) * 0.3;
+            let b = f64::from(i) * -0.14;
+            let phi = -0.47;
+            assert_abs_diff_eq!(ports::anp(a * 7.0), ffi::anp(a * 7.0), epsilon = 1e-15);
+            assert_abs_diff_eq!(
+                ports::seps(a, b, b, a),
+                ffi::seps(a, b, b, a),
+                epsilon = 1e-15
+            );
+            assert_abs_diff_eq!(
+                ports::pas(a, b, b, a),
+                ffi::pas(a, b, b, a),
+                epsilon = 1e-15
+            );
+            assert_abs_diff_eq!(
+                ports::hd2pa(a, b, phi),
+                ffi::hd2pa(a, b, phi),
+                epsilon = 1e-15
+            );
+            let port = ports::hd2ae(a, b, phi);
+            let ffi = ffi::hd2ae(a, b, phi);
+            assert_abs_diff_eq!(port.0, ffi.0, epsilon = 1e-15);
+            assert_abs_diff_eq!(port.1, ffi.1, epsilon = 1e-15);
+            let port = ports::ae2hd(a, b, phi);
+            let ffi = ffi::ae2hd(a, b, phi);
+            assert_abs_diff_eq!(port.0, ffi.0, epsilon = 1e-15);
+            assert_abs_diff_eq!(port.1, ffi.1, epsilon = 1e-15);
+            let c = ports::s2c(a, b);
+            assert_abs_diff_eq!(c[..], ffi::s2c(a, b)[..], epsilon = 1e-15);
+            let port = ports::c2s(&c);
+            let ffi = ffi::c2s(&c);
+            assert_abs_diff_eq!(port.0, ffi.0, epsilon = 1e-15);
+            assert_abs_diff_eq!(port.1, ffi.1, epsilon = 1e-15);
+
+            let port = ports::gd2gc(ports::WGS84, a, b, 377.8).unwrap();
+            let ffi = ffi::gd2gc(ports::WGS84, a, b, 377.8).unwrap();
+            assert_abs_diff_eq!(port[..], ffi[..], epsilon = 1e-8);
+            let port = ports::gc2gd(ports::WGS84, &port).unwrap();
+            let ffi = ffi::gc2gd(ports::WGS84, &ffi).unwrap();
+            assert_abs_diff_eq!(port.0, ffi.0, epsilon = 1e-15);
+            assert_abs_diff_eq!(port.1, ffi.1, epsilon = 1e-15);
+            assert_abs_diff_eq!(port.2, ffi.2, epsilon = 1e-8);
+        }
+    }
+}
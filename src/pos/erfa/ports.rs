@@ -0,0 +1,835 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Pure-Rust ports of the ERFA routines used by Marlu.
+//!
+//! Each function is a line-by-line port of the ERFA 2.0.0 function with the
+//! same name (less the `era` prefix), with the same order of operations, so
+//! results agree with ERFA to within rounding error. Output arguments are
+//! returned rather than written through pointers. See the ERFA documentation
+//! for details of each function.
+
+#![allow(clippy::excessive_precision)]
+#![cfg_attr(feature = "erfa", allow(dead_code))]
+
+use std::f64::consts::{PI, TAU};
+
+use super::tables::{
+    EPV00_E0X, EPV00_E0Y, EPV00_E0Z, EPV00_E1X, EPV00_E1Y, EPV00_E1Z, EPV00_E2X, EPV00_E2Y,
+    EPV00_E2Z, EPV00_S0X, EPV00_S0Y, EPV00_S0Z, EPV00_S1X, EPV00_S1Y, EPV00_S1Z, EPV00_S2X,
+    EPV00_S2Y, EPV00_S2Z, NUT00A_LS, NUT00A_PL, S06_S0, S06_S1, S06_S2, S06_S3, S06_S4,
+};
+
+/// Arcseconds to radians.
+pub(crate) const DAS2R: f64 = 4.848136811095359935899141e-6;
+/// Arcseconds in a full circle.
+const TURNAS: f64 = 1296000.0;
+/// Reference epoch (J2000.0), Julian Date.
+pub(crate) const DJ00: f64 = 2451545.0;
+/// Julian Date of Modified Julian Date zero.
+pub(crate) const DJM0: f64 = 2400000.5;
+/// Reference epoch (J2000.0), Modified Julian Date.
+pub(crate) const DJM00: f64 = 51544.5;
+/// Days per Julian year.
+pub(crate) const DJY: f64 = 365.25;
+/// Days per Julian century.
+const DJC: f64 = 36525.0;
+/// 0.1 microarcseconds to radians.
+const U2R: f64 = DAS2R / 1e7;
+
+/// The reference ellipsoid identifiers of [`eform`].
+pub(crate) const WGS84: i32 = 1;
+pub(crate) const GRS80: i32 = 2;
+pub(crate) const WGS72: i32 = 3;
+
+/// The precession angles returned by [`p06e`], in radians.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct P06e {
+    pub(crate) eps0: f64,
+    pub(crate) psia: f64,
+    pub(crate) oma: f64,
+    pub(crate) bpa: f64,
+    pub(crate) bqa: f64,
+    pub(crate) pia: f64,
+    pub(crate) bpia: f64,
+    pub(crate) epsa: f64,
+    pub(crate) chia: f64,
+    pub(crate) za: f64,
+    pub(crate) zetaa: f64,
+    pub(crate) thetaa: f64,
+    pub(crate) pa: f64,
+    pub(crate) gam: f64,
+    pub(crate) phi: f64,
+    pub(crate) psi: f64,
+}
+
+/// Julian centuries since J2000.0 of a two-part Julian Date.
+fn centuries(date1: f64, date2: f64) -> f64 {
+    ((date1 - DJ00) + date2) / DJC
+}
+
+/// Normalise an angle into the range \[0, 2pi).
+pub(crate) fn anp(a: f64) -> f64 {
+    let w = a % TAU;
+    if w < 0.0 {
+        w + TAU
+    } else {
+        w
+    }
+}
+
+/// Spherical coordinates to a unit vector.
+pub(crate) fn s2c(theta: f64, phi: f64) -> [f64; 3] {
+    let cp = phi.cos();
+    [theta.cos() * cp, theta.sin() * cp, phi.sin()]
+}
+
+/// A vector to spherical coordinates.
+pub(crate) fn c2s(p: &[f64; 3]) -> (f64, f64) {
+    let [x, y, z] = *p;
+    let d2 = x * x + y * y;
+    let theta = if d2 == 0.0 { 0.0 } else { y.atan2(x) };
+    let phi = if z == 0.0 { 0.0 } else { z.atan2(d2.sqrt()) };
+    (theta, phi)
+}
+
+/// The scalar product of two vectors.
+pub(crate) fn pdp(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// The vector product of two vectors.
+pub(crate) fn pxp(a: &[f64; 3], b: &[f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// The modulus of a vector.
+pub(crate) fn pm(p: &[f64; 3]) -> f64 {
+    pdp(p, p).sqrt()
+}
+
+/// The modulus and unit vector of a vector. The unit vector of a null vector
+/// is null.
+pub(crate) fn pn(p: &[f64; 3]) -> (f64, [f64; 3]) {
+    let w = pm(p);
+    if w == 0.0 {
+        (w, [0.0; 3])
+    } else {
+        let s = 1.0 / w;
+        (w, [s * p[0], s * p[1], s * p[2]])
+    }
+}
+
+/// Multiply a vector by a matrix.
+pub(crate) fn rxp(r: &[[f64; 3]; 3], p: &[f64; 3]) -> [f64; 3] {
+    [pdp(&r[0], p), pdp(&r[1], p), pdp(&r[2], p)]
+}
+
+/// Multiply two matrices.
+pub(crate) fn rxr(a: &[[f64; 3]; 3], b: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut atb = [[0.0; 3]; 3];
+    for (atb_row, a_row) in atb.iter_mut().zip(a) {
+        for (j, atb) in atb_row.iter_mut().enumerate() {
+            *atb = a_row[0] * b[0][j] + a_row[1] * b[1][j] + a_row[2] * b[2][j];
+        }
+    }
+    atb
+}
+
+/// The identity matrix.
+pub(crate) fn ir() -> [[f64; 3]; 3] {
+    [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+}
+
+/// Rotate a matrix about the x-axis.
+pub(crate) fn rx(phi: f64, r: &mut [[f64; 3]; 3]) {
+    let s = phi.sin();
+    let c = phi.cos();
+    let [_, r1, r2] = *r;
+    r[1] = [
+        c * r1[0] + s * r2[0],
+        c * r1[1] + s * r2[1],
+        c * r1[2] + s * r2[2],
+    ];
+    r[2] = [
+        -s * r1[0] + c * r2[0],
+        -s * r1[1] + c * r2[1],
+        -s * r1[2] + c * r2[2],
+    ];
+}
+
+/// Rotate a matrix about the z-axis.
+pub(crate) fn rz(psi: f64, r: &mut [[f64; 3]; 3]) {
+    let s = psi.sin();
+    let c = psi.cos();
+    let [r0, r1, _] = *r;
+    r[0] = [
+        c * r0[0] + s * r1[0],
+        c * r0[1] + s * r1[1],
+        c * r0[2] + s * r1[2],
+    ];
+    r[1] = [
+        -s * r0[0] + c * r1[0],
+        -s * r0[1] + c * r1[1],
+        -s * r0[2] + c * r1[2],
+    ];
+}
+
+/// A two-part Julian Date to a Julian epoch.
+pub(crate) fn epj(dj1: f64, dj2: f64) -> f64 {
+    2000.0 + ((dj1 - DJ00) + dj2) / DJY
+}
+
+/// A Julian epoch to a two-part Modified Julian Date.
+pub(crate) fn epj2jd(epj: f64) -> (f64, f64) {
+    (DJM0, DJM00 + (epj - 2000.0) * DJY)
+}
+
+/// The angular separation of two vectors.
+pub(crate) fn sepp(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    let ss = pm(&pxp(a, b));
+    let cs = pdp(a, b);
+    if ss != 0.0 || cs != 0.0 {
+        ss.atan2(cs)
+    } else {
+        0.0
+    }
+}
+
+/// The angular separation of two sets of spherical coordinates.
+pub(crate) fn seps(al: f64, ap: f64, bl: f64, bp: f64) -> f64 {
+    sepp(&s2c(al, ap), &s2c(bl, bp))
+}
+
+/// The position angle of `b` with respect to `a`.
+pub(crate) fn pas(al: f64, ap: f64, bl: f64, bp: f64) -> f64 {
+    let dl = bl - al;
+    let y = dl.sin() * bp.cos();
+    let x = bp.sin() * ap.cos() - bp.cos() * ap.sin() * dl.cos();
+    if x != 0.0 || y != 0.0 {
+        y.atan2(x)
+    } else {
+        0.0
+    }
+}
+
+/// Hour angle and declination to azimuth and elevation, at latitude `phi`.
+pub(crate) fn hd2ae(ha: f64, dec: f64, phi: f64) -> (f64, f64) {
+    let (sh, ch) = (ha.sin(), ha.cos());
+    let (sd, cd) = (dec.sin(), dec.cos());
+    let (sp, cp) = (phi.sin(), phi.cos());
+    let x = -ch * cd * sp + sd * cp;
+    let y = -sh * cd;
+    let z = ch * cd * cp + sd * sp;
+    let r = (x * x + y * y).sqrt();
+    let a = if r == 0.0 { 0.0 } else { y.atan2(x) };
+    let az = if a < 0.0 { a + TAU } else { a };
+    (az, z.atan2(r))
+}
+
+/// Azimuth and elevation to hour angle and declination, at latitude `phi`.
+pub(crate) fn ae2hd(az: f64, el: f64, phi: f64) -> (f64, f64) {
+    let (sa, ca) = (az.sin(), az.cos());
+    let (se, ce) = (el.sin(), el.cos());
+    let (sp, cp) = (phi.sin(), phi.cos());
+    let x = -ca * ce * sp + se * cp;
+    let y = -sa * ce;
+    let z = ca * ce * cp + se * sp;
+    let r = (x * x + y * y).sqrt();
+    let ha = if r == 0.0 { 0.0 } else { y.atan2(x) };
+    (ha, z.atan2(r))
+}
+
+/// The parallactic angle of an hour angle and declination, at latitude `phi`.
+pub(crate) fn hd2pa(ha: f64, dec: f64, phi: f64) -> f64 {
+    let cp = phi.cos();
+    let sqsz = cp * ha.sin();
+    let cqsz = phi.sin() * dec.cos() - cp * dec.sin() * ha.cos();
+    if sqsz != 0.0 || cqsz != 0.0 {
+        sqsz.atan2(cqsz)
+    } else {
+        0.0
+    }
+}
+
+/// The equatorial radius and flattening of a reference ellipsoid. Fails with
+/// -1 for an unknown ellipsoid.
+pub(crate) fn eform(n: i32) -> Result<(f64, f64), i32> {
+    match n {
+        WGS84 => Ok((6378137.0, 1.0 / 298.257223563)),
+        GRS80 => Ok((6378137.0, 1.0 / 298.257222101)),
+        WGS72 => Ok((6378135.0, 1.0 / 298.26)),
+        _ => Err(-1),
+    }
+}
+
+/// Geodetic to geocentric coordinates on an ellipsoid given by its equatorial
+/// radius and flattening. Fails with -1 for an illegal case.
+pub(crate) fn gd2gce(a: f64, f: f64, elong: f64, phi: f64, height: f64) -> Result<[f64; 3], i32> {
+    let sp = phi.sin();
+    let cp = phi.cos();
+    let mut w = 1.0 - f;
+    w *= w;
+    let d = cp * cp + w * sp * sp;
+    if d <= 0.0 {
+        return Err(-1);
+    }
+    let ac = a / d.sqrt();
+    let r#as = w * ac;
+    let r = (ac + height) * cp;
+    Ok([r * elong.cos(), r * elong.sin(), (r#as + height) * sp])
+}
+
+/// Geodetic to geocentric coordinates on the reference ellipsoid `n`. Fails
+/// with -1 for an unknown ellipsoid, or -2 for an illegal case.
+pub(crate) fn gd2gc(n: i32, elong: f64, phi: f64, height: f64) -> Result<[f64; 3], i32> {
+    let (a, f) = eform(n)?;
+    gd2gce(a, f, elong, phi, height).map_err(|_| -2)
+}
+
+/// Geocentric to geodetic coordinates (longitude, latitude and height) on an
+/// ellipsoid given by its equatorial radius and flattening. Fails with -1 for
+/// an illegal flattening, or -2 for an illegal radius.
+pub(crate) fn gc2gde(a: f64, f: f64, xyz: &[f64; 3]) -> Result<(f64, f64, f64), i32> {
+    if !(0.0..1.0).contains(&f) {
+        return Err(-1);
+    }
+    if a <= 0.0 {
+        return Err(-2);
+    }
+
+    let aeps2 = a * a * 1e-32;
+    let e2 = (2.0 - f) * f;
+    let e4t = e2 * e2 * 1.5;
+    let ec2 = 1.0 - e2;
+    if ec2 <= 0.0 {
+        return Err(-1);
+    }
+    let ec = ec2.sqrt();
+    let b = a * ec;
+
+    let [x, y, z] = *xyz;
+    let p2 = x * x + y * y;
+    let elong = if p2 > 0.0 { y.atan2(x) } else { 0.0 };
+    let absz = z.abs();
+
+    let (mut phi, height) = if p2 > aeps2 {
+        let p = p2.sqrt();
+        let s0 = absz / a;
+        let pn = p / a;
+        let zc = ec * s0;
+        let c0 = ec * pn;
+        let c02 = c0 * c0;
+        let c03 = c02 * c0;
+        let s02 = s0 * s0;
+        let s03 = s02 * s0;
+        let a02 = c02 + s02;
+        let a0 = a02.sqrt();
+        let a03 = a02 * a0;
+        let d0 = zc * a03 + e2 * s03;
+        let f0 = pn * a03 - e2 * c03;
+        let b0 = e4t * s02 * c02 * pn * (a0 - ec);
+        let s1 = d0 * f0 - b0 * s0;
+        let cc = ec * (f0 * f0 - b0 * c0);
+        let s12 = s1 * s1;
+        let cc2 = cc * cc;
+        (
+            (s1 / cc).atan(),
+            (p * cc + absz * s1 - a * (ec2 * s12 + cc2).sqrt()) / (s12 + cc2).sqrt(),
+        )
+    } else {
+        (PI / 2.0, absz - b)
+    };
+    if z < 0.0 {
+        phi = -phi;
+    }
+    Ok((elong, phi, height))
+}
+
+/// Geocentric to geodetic coordinates (longitude, latitude and height) on the
+/// reference ellipsoid `n`. Fails with -1 for an unknown ellipsoid, or -2 for
+/// an illegal case.
+pub(crate) fn gc2gd(n: i32, xyz: &[f64; 3]) -> Result<(f64, f64, f64), i32> {
+    let (a, f) = eform(n)?;
+    gc2gde(a, f, xyz).map_err(|_| -2)
+}
+
+/// Earth rotation angle (IAU 2000) of a two-part UT1 Julian Date.
+pub(crate) fn era00(dj1: f64, dj2: f64) -> f64 {
+    let (d1, d2) = if dj1 < dj2 { (dj1, dj2) } else { (dj2, dj1) };
+    let t = d1 + (d2 - DJ00);
+    let f = d1 % 1.0 + d2 % 1.0;
+    anp(TAU * (f + 0.7790572732640 + 0.00273781191135448 * t))
+}
+
+/// Greenwich mean sidereal time (consistent with IAU 2006 precession).
+pub(crate) fn gmst06(uta: f64, utb: f64, tta: f64, ttb: f64) -> f64 {
+    let t = centuries(tta, ttb);
+    anp(era00(uta, utb)
+        + (0.014506
+            + (4612.156534
+                + (1.3915817 + (-0.00000044 + (-0.000029956 + (-0.0000000368) * t) * t) * t) * t)
+                * t)
+            * DAS2R)
+}
+
+/// Mean obliquity of the ecliptic (IAU 2006).
+pub(crate) fn obl06(date1: f64, date2: f64) -> f64 {
+    let t = centuries(date1, date2);
+    (84381.406
+        + (-46.836769
+            + (-0.0001831 + (0.00200340 + (-0.000000576 + (-0.0000000434) * t) * t) * t) * t)
+            * t)
+        * DAS2R
+}
+
+/// Precession angles (IAU 2006, Fukushima-Williams 4-angle formulation):
+/// `(gamb, phib, psib, epsa)`.
+pub(crate) fn pfw06(date1: f64, date2: f64) -> (f64, f64, f64, f64) {
+    let t = centuries(date1, date2);
+    let gamb = (-0.052928
+        + (10.556378
+            + (0.4932044 + (-0.00031238 + (-0.000002788 + (0.0000000260) * t) * t) * t) * t)
+            * t)
+        * DAS2R;
+    let phib = (84381.412819
+        + (-46.811016
+            + (0.0511268 + (0.00053289 + (-0.000000440 + (-0.0000000176) * t) * t) * t) * t)
+            * t)
+        * DAS2R;
+    let psib = (-0.041775
+        + (5038.481484
+            + (1.5584175 + (-0.00018522 + (-0.000026452 + (-0.0000000148) * t) * t) * t) * t)
+            * t)
+        * DAS2R;
+    (gamb, phib, psib, obl06(date1, date2))
+}
+
+/// Form a rotation matrix given the Fukushima-Williams angles.
+pub(crate) fn fw2m(gamb: f64, phib: f64, psi: f64, eps: f64) -> [[f64; 3]; 3] {
+    let mut r = ir();
+    rz(gamb, &mut r);
+    rx(phib, &mut r);
+    rz(-psi, &mut r);
+    rx(-eps, &mut r);
+    r
+}
+
+/// Precession matrix (including frame bias) from GCRS to a specified date,
+/// IAU 2006 model.
+pub(crate) fn pmat06(date1: f64, date2: f64) -> [[f64; 3]; 3] {
+    let (gamb, phib, psib, epsa) = pfw06(date1, date2);
+    fw2m(gamb, phib, psib, epsa)
+}
+
+/// Nutation, IAU 2000A model: `(dpsi, deps)`.
+pub(crate) fn nut00a(date1: f64, date2: f64) -> (f64, f64) {
+    let t = centuries(date1, date2);
+
+    // Luni-solar nutation: fundamental (Delaunay) arguments.
+    let el = fal03(t);
+    let elp = ((1287104.79305
+        + t * (129596581.0481 + t * (-0.5532 + t * (0.000136 + t * (-0.00001149)))))
+        % TURNAS)
+        * DAS2R;
+    let f = faf03(t);
+    let d = ((1072260.70369
+        + t * (1602961601.2090 + t * (-6.3706 + t * (0.006593 + t * (-0.00003169)))))
+        % TURNAS)
+        * DAS2R;
+    let om = faom03(t);
+
+    // Summed in reverse order, smallest terms first.
+    let mut dp = 0.0;
+    let mut de = 0.0;
+    for ([nl, nlp, nf, nd, nom], [sp, spt, cp, ce, cet, se]) in NUT00A_LS.iter().rev() {
+        let arg = (f64::from(*nl) * el
+            + f64::from(*nlp) * elp
+            + f64::from(*nf) * f
+            + f64::from(*nd) * d
+            + f64::from(*nom) * om)
+            % TAU;
+        let sarg = arg.sin();
+        let carg = arg.cos();
+        dp += (sp + spt * t) * sarg + cp * carg;
+        de += (ce + cet * t) * carg + se * sarg;
+    }
+    let dpsils = dp * U2R;
+    let depsls = de * U2R;
+
+    // Planetary nutation: fundamental arguments (the mean Delaunay arguments
+    // are the simplified MHB2000 ones).
+    let al = (2.35555598 + 8328.6914269554 * t) % TAU;
+    let af = (1.627905234 + 8433.466158131 * t) % TAU;
+    let ad = (5.198466741 + 7771.3771468121 * t) % TAU;
+    let aom = (2.18243920 - 33.757045 * t) % TAU;
+    let apa = fapa03(t);
+    let alme = fame03(t);
+    let alve = fave03(t);
+    let alea = fae03(t);
+    let alma = fama03(t);
+    let alju = faju03(t);
+    let alsa = fasa03(t);
+    let alur = faur03(t);
+    let alne = (5.321159000 + 3.8127774000 * t) % TAU;
+    let args = [
+        al, af, ad, aom, alme, alve, alea, alma, alju, alsa, alur, alne, apa,
+    ];
+
+    let mut dp = 0.0;
+    let mut de = 0.0;
+    for (multipliers, [sp, cp, se, ce]) in NUT00A_PL.iter().rev() {
+        let mut arg = 0.0;
+        for (&n, a) in multipliers.iter().zip(args) {
+            arg += f64::from(n) * a;
+        }
+        let arg = arg % TAU;
+        let sarg = arg.sin();
+        let carg = arg.cos();
+        dp += f64::from(*sp) * sarg + f64::from(*cp) * carg;
+        de += f64::from(*se) * sarg + f64::from(*ce) * carg;
+    }
+    let dpsipl = dp * U2R;
+    let depspl = de * U2R;
+
+    (dpsils + dpsipl, depsls + depspl)
+}
+
+/// Nutation, IAU 2000A model adjusted to be consistent with IAU 2006
+/// precession: `(dpsi, deps)`.
+pub(crate) fn nut06a(date1: f64, date2: f64) -> (f64, f64) {
+    let t = centuries(date1, date2);
+    let fj2 = -2.7774e-6 * t;
+    let (dp, de) = nut00a(date1, date2);
+    (dp + dp * (0.4697e-6 + fj2), de + de * fj2)
+}
+
+/// Bias-precession-nutation matrix, IAU 2006/2000A.
+pub(crate) fn pnm06a(date1: f64, date2: f64) -> [[f64; 3]; 3] {
+    let (gamb, phib, psib, epsa) = pfw06(date1, date2);
+    let (dp, de) = nut06a(date1, date2);
+    fw2m(gamb, phib, psib + dp, epsa + de)
+}
+
+/// Form the matrix of nutation from the mean obliquity and nutation
+/// components.
+pub(crate) fn numat(epsa: f64, dpsi: f64, deps: f64) -> [[f64; 3]; 3] {
+    let mut r = ir();
+    rx(epsa, &mut r);
+    rz(-dpsi, &mut r);
+    rx(-(epsa + deps), &mut r);
+    r
+}
+
+/// Nutation matrix, IAU 2006/2000A.
+pub(crate) fn num06a(date1: f64, date2: f64) -> [[f64; 3]; 3] {
+    let eps = obl06(date1, date2);
+    let (dp, de) = nut06a(date1, date2);
+    numat(eps, dp, de)
+}
+
+/// The CIO locator `s`, given the CIP's X,Y coordinates, IAU 2006/2000A.
+pub(crate) fn s06(date1: f64, date2: f64, x: f64, y: f64) -> f64 {
+    // Polynomial coefficients.
+    const SP: [f64; 6] = [
+        94.00e-6,
+        3808.65e-6,
+        -122.68e-6,
+        -72574.11e-6,
+        27.98e-6,
+        15.62e-6,
+    ];
+
+    let t = centuries(date1, date2);
+    let fa = [
+        fal03(t),
+        falp03(t),
+        faf03(t),
+        fad03(t),
+        faom03(t),
+        fave03(t),
+        fae03(t),
+        fapa03(t),
+    ];
+    let series = |terms: &[([i8; 8], f64, f64)], mut w: f64| {
+        for (nfa, s, c) in terms.iter().rev() {
+            let mut a = 0.0;
+            for (&n, fa) in nfa.iter().zip(fa) {
+                a += f64::from(n) * fa;
+            }
+            w += s * a.sin() + c * a.cos();
+        }
+        w
+    };
+    let w0 = series(&S06_S0, SP[0]);
+    let w1 = series(&S06_S1, SP[1]);
+    let w2 = series(&S06_S2, SP[2]);
+    let w3 = series(&S06_S3, SP[3]);
+    let w4 = series(&S06_S4, SP[4]);
+    let w5 = SP[5];
+
+    (w0 + (w1 + (w2 + (w3 + (w4 + w5 * t) * t) * t) * t) * t) * DAS2R - x * y / 2.0
+}
+
+/// The equation of the origins, given the classical NPB matrix and the
+/// quantity `s`.
+pub(crate) fn eors(rnpb: &[[f64; 3]; 3], s: f64) -> f64 {
+    let x = rnpb[2][0];
+    let ax = x / (1.0 + rnpb[2][2]);
+    let xs = 1.0 - ax * x;
+    let ys = -ax * rnpb[2][1];
+    let zs = -x;
+    let p = rnpb[0][0] * xs + rnpb[0][1] * ys + rnpb[0][2] * zs;
+    let q = rnpb[1][0] * xs + rnpb[1][1] * ys + rnpb[1][2] * zs;
+    if p != 0.0 || q != 0.0 {
+        s - q.atan2(p)
+    } else {
+        s
+    }
+}
+
+/// Greenwich apparent sidereal time, given the NPB matrix.
+pub(crate) fn gst06(uta: f64, utb: f64, tta: f64, ttb: f64, rnpb: &[[f64; 3]; 3]) -> f64 {
+    let (x, y) = (rnpb[2][0], rnpb[2][1]);
+    let s = s06(tta, ttb, x, y);
+    anp(era00(uta, utb) - eors(rnpb, s))
+}
+
+/// Greenwich apparent sidereal time (consistent with IAU 2000 and 2006
+/// resolutions).
+pub(crate) fn gst06a(uta: f64, utb: f64, tta: f64, ttb: f64) -> f64 {
+    gst06(uta, utb, tta, ttb, &pnm06a(tta, ttb))
+}
+
+/// Precession angles, IAU 2006, equinox based.
+pub(crate) fn p06e(date1: f64, date2: f64) -> P06e {
+    let t = centuries(date1, date2);
+    let eps0 = 84381.406 * DAS2R;
+    P06e {
+        eps0,
+        psia: (5038.481507
+            + (-1.0790069 + (-0.00114045 + (0.000132851 + (-0.0000000951) * t) * t) * t) * t)
+            * t
+            * DAS2R,
+        oma: eps0
+            + (-0.025754
+                + (0.0512623 + (-0.00772503 + (-0.000000467 + (0.0000003337) * t) * t) * t) * t)
+                * t
+                * DAS2R,
+        bpa: (4.199094
+            + (0.1939873 + (-0.00022466 + (-0.000000912 + (0.0000000120) * t) * t) * t) * t)
+            * t
+            * DAS2R,
+        bqa: (-46.811015
+            + (0.0510283 + (0.00052413 + (-0.000000646 + (-0.0000000172) * t) * t) * t) * t)
+            * t
+            * DAS2R,
+        pia: (46.998973
+            + (-0.0334926 + (-0.00012559 + (0.000000113 + (-0.0000000022) * t) * t) * t) * t)
+            * t
+            * DAS2R,
+        bpia: (629546.7936
+            + (-867.95758
+                + (0.157992 + (-0.0005371 + (-0.00004797 + (0.000000072) * t) * t) * t) * t)
+                * t)
+            * DAS2R,
+        epsa: obl06(date1, date2),
+        chia: (10.556403
+            + (-2.3814292 + (-0.00121197 + (0.000170663 + (-0.0000000560) * t) * t) * t) * t)
+            * t
+            * DAS2R,
+        za: (-2.650545
+            + (2306.077181
+                + (1.0927348 + (0.01826837 + (-0.000028596 + (-0.0000002904) * t) * t) * t) * t)
+                * t)
+            * DAS2R,
+        zetaa: (2.650545
+            + (2306.083227
+                + (0.2988499 + (0.01801828 + (-0.000005971 + (-0.0000003173) * t) * t) * t) * t)
+                * t)
+            * DAS2R,
+        thetaa: (2004.191903
+            + (-0.4294934 + (-0.04182264 + (-0.000007089 + (-0.0000001274) * t) * t) * t) * t)
+            * t
+            * DAS2R,
+        pa: (5028.796195
+            + (1.1054348 + (0.00007964 + (-0.000023857 + (-0.0000000383) * t) * t) * t) * t)
+            * t
+            * DAS2R,
+        gam: (10.556403
+            + (0.4932044 + (-0.00031238 + (-0.000002788 + (0.0000000260) * t) * t) * t) * t)
+            * t
+            * DAS2R,
+        phi: eps0
+            + (-46.811015
+                + (0.0511269 + (0.00053289 + (-0.000000440 + (-0.0000000176) * t) * t) * t) * t)
+                * t
+                * DAS2R,
+        psi: (5038.481507
+            + (1.5584176 + (-0.00018522 + (-0.000026452 + (-0.0000000148) * t) * t) * t) * t)
+            * t
+            * DAS2R,
+    }
+}
+
+/// Earth position and velocity, heliocentric and barycentric, with respect to
+/// the Barycentric Celestial Reference System: `(pvh, pvb)`, each of which is
+/// a position (au) and velocity (au/day). Like ERFA, dates outside 1900-2100
+/// are not rejected, but are less accurate.
+pub(crate) fn epv00(date1: f64, date2: f64) -> ([[f64; 3]; 2], [[f64; 3]; 2]) {
+    // Matrix elements for orienting the analytical model to DE405.
+    const AM12: f64 = 0.000000211284;
+    const AM13: f64 = -0.000000091603;
+    const AM21: f64 = -0.000000230286;
+    const AM22: f64 = 0.917482137087;
+    const AM23: f64 = -0.397776982902;
+    const AM32: f64 = 0.397776982902;
+    const AM33: f64 = 0.917482137087;
+
+    let ce0 = [&EPV00_E0X[..], &EPV00_E0Y[..], &EPV00_E0Z[..]];
+    let ce1 = [&EPV00_E1X[..], &EPV00_E1Y[..], &EPV00_E1Z[..]];
+    let ce2 = [&EPV00_E2X[..], &EPV00_E2Y[..], &EPV00_E2Z[..]];
+    let cs0 = [&EPV00_S0X[..], &EPV00_S0Y[..], &EPV00_S0Z[..]];
+    let cs1 = [&EPV00_S1X[..], &EPV00_S1Y[..], &EPV00_S1Z[..]];
+    let cs2 = [&EPV00_S2X[..], &EPV00_S2Y[..], &EPV00_S2Z[..]];
+
+    // Time since reference epoch, Julian years.
+    let t = ((date1 - DJ00) + date2) / DJY;
+    let t2 = t * t;
+
+    // Sums of the terms of order 0, 1 and 2 in t for a coordinate, accumulated
+    // onto `xyz` and `xyzd`.
+    let sum = |c0: &[[f64; 3]], c1: &[[f64; 3]], c2: &[[f64; 3]], xyz: &mut f64, xyzd: &mut f64| {
+        for &[a, b, c] in c0 {
+            let p = b + c * t;
+            *xyz += a * p.cos();
+            *xyzd -= a * c * p.sin();
+        }
+        for &[a, b, c] in c1 {
+            let ct = c * t;
+            let p = b + ct;
+            let cp = p.cos();
+            *xyz += a * t * cp;
+            *xyzd += a * (cp - ct * p.sin());
+        }
+        for &[a, b, c] in c2 {
+            let ct = c * t;
+            let p = b + ct;
+            let cp = p.cos();
+            *xyz += a * t2 * cp;
+            *xyzd += a * t * (2.0 * cp - ct * p.sin());
+        }
+    };
+
+    let mut ph = [0.0; 3];
+    let mut vh = [0.0; 3];
+    let mut pb = [0.0; 3];
+    let mut vb = [0.0; 3];
+    for i in 0..3 {
+        let mut xyz = 0.0;
+        let mut xyzd = 0.0;
+
+        // Sun to Earth.
+        sum(ce0[i], ce1[i], ce2[i], &mut xyz, &mut xyzd);
+        ph[i] = xyz;
+        vh[i] = xyzd / DJY;
+
+        // SSB to Sun, plus Sun to Earth.
+        sum(cs0[i], cs1[i], cs2[i], &mut xyz, &mut xyzd);
+        pb[i] = xyz;
+        vb[i] = xyzd / DJY;
+    }
+
+    // Rotate from ecliptic to BCRS coordinates.
+    let rotate = |[x, y, z]: [f64; 3]| {
+        [
+            x + AM12 * y + AM13 * z,
+            AM21 * x + AM22 * y + AM23 * z,
+            AM32 * y + AM33 * z,
+        ]
+    };
+    ([rotate(ph), rotate(vh)], [rotate(pb), rotate(vb)])
+}
+
+// Fundamental arguments (IERS Conventions 2003), as functions of `t`, Julian
+// centuries since J2000.0 TDB.
+
+/// Mean anomaly of the Moon.
+fn fal03(t: f64) -> f64 {
+    ((485868.249036 + t * (1717915923.2178 + t * (31.8792 + t * (0.051635 + t * (-0.00024470)))))
+        % TURNAS)
+        * DAS2R
+}
+
+/// Mean anomaly of the Sun.
+fn falp03(t: f64) -> f64 {
+    ((1287104.793048 + t * (129596581.0481 + t * (-0.5532 + t * (0.000136 + t * (-0.00001149)))))
+        % TURNAS)
+        * DAS2R
+}
+
+/// Mean longitude of the Moon minus that of the ascending node.
+fn faf03(t: f64) -> f64 {
+    ((335779.526232 + t * (1739527262.8478 + t * (-12.7512 + t * (-0.001037 + t * (0.00000417)))))
+        % TURNAS)
+        * DAS2R
+}
+
+/// Mean elongation of the Moon from the Sun.
+fn fad03(t: f64) -> f64 {
+    ((1072260.703692 + t * (1602961601.2090 + t * (-6.3706 + t * (0.006593 + t * (-0.00003169)))))
+        % TURNAS)
+        * DAS2R
+}
+
+/// Mean longitude of the Moon's ascending node.
+fn faom03(t: f64) -> f64 {
+    ((450160.398036 + t * (-6962890.5431 + t * (7.4722 + t * (0.007702 + t * (-0.00005939)))))
+        % TURNAS)
+        * DAS2R
+}
+
+/// Mean longitude of Mercury.
+fn fame03(t: f64) -> f64 {
+    (4.402608842 + 2608.7903141574 * t) % TAU
+}
+
+/// Mean longitude of Venus.
+fn fave03(t: f64) -> f64 {
+    (3.176146697 + 1021.3285546211 * t) % TAU
+}
+
+/// Mean longitude of Earth.
+fn fae03(t: f64) -> f64 {
+    (1.753470314 + 628.3075849991 * t) % TAU
+}
+
+/// Mean longitude of Mars.
+fn fama03(t: f64) -> f64 {
+    (6.203480913 + 334.0612426700 * t) % TAU
+}
+
+/// Mean longitude of Jupiter.
+fn faju03(t: f64) -> f64 {
+    (0.599546497 + 52.9690962641 * t) % TAU
+}
+
+/// Mean longitude of Saturn.
+fn fasa03(t: f64) -> f64 {
+    (0.874016757 + 21.3299104960 * t) % TAU
+}
+
+/// Mean longitude of Uranus.
+fn faur03(t: f64) -> f64 {
+    (5.481293872 + 7.4781598567 * t) % TAU
+}
+
+/// General accumulated precession in longitude.
+fn fapa03(t: f64) -> f64 {
+    (0.024381750 + 0.00000538691 * t) * t
+}
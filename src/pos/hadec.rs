@@ -4,10 +4,13 @@
 
 //! Handle (hour angle, declination) coordinates.
 
-use crate::{constants::MWA_LAT_RAD, AzEl, RADec};
+use super::erfa;
+use crate::{constants::MWA_LAT_RAD, AzEl, LatLngHeight, RADec, XyzGeocentric, XyzGeodetic, ENH};
 
 /// A struct containing an Hour Angle and Declination. All units are in radians.
+#[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(clippy::upper_case_acronyms)]
 pub struct HADec {
     /// Hour angle \[radians\]
@@ -51,9 +54,7 @@ impl HADec {
     ///
     /// Uses ERFA.
     pub fn to_azel(self, latitude_rad: f64) -> AzEl {
-        let mut az = 0.0;
-        let mut el = 0.0;
-        unsafe { erfa_sys::eraHd2ae(self.ha, self.dec, latitude_rad, &mut az, &mut el) }
+        let (az, el) = erfa::hd2ae(self.ha, self.dec, latitude_rad);
         AzEl::new(az, el)
     }
 
@@ -65,11 +66,80 @@ impl HADec {
         self.to_azel(MWA_LAT_RAD)
     }
 
+    /// Convert the equatorial coordinates to a unit direction vector in the
+    /// 'local' [`XyzGeodetic`] frame, i.e. X points to (HA 0h, Dec 0), Y points
+    /// to (HA -6h, Dec 0) and Z points to the north celestial pole.
+    ///
+    /// Uses ERFA.
+    pub fn to_xyz_direction(self) -> XyzGeodetic {
+        let c = erfa::s2c(-self.ha, self.dec);
+        XyzGeodetic {
+            x: c[0],
+            y: c[1],
+            z: c[2],
+        }
+    }
+
+    /// Convert a direction vector in the 'local' [`XyzGeodetic`] frame (see
+    /// [`HADec::to_xyz_direction`]) to equatorial coordinates. The vector
+    /// doesn't need to be normalised.
+    ///
+    /// Uses ERFA.
+    pub fn from_xyz_direction(xyz: XyzGeodetic) -> HADec {
+        let (theta, phi) = erfa::c2s(&[xyz.x, xyz.y, xyz.z]);
+        HADec::new(-theta, phi)
+    }
+
+    /// Convert the equatorial coordinates to a unit direction vector in the
+    /// [`XyzGeocentric`] frame, given the array position on Earth. This is the
+    /// 'local' direction vector (see [`HADec::to_xyz_direction`]) rotated by
+    /// the array's longitude; it isn't translated, because it's a direction.
+    pub fn to_geocentric_direction(self, array_pos: LatLngHeight) -> XyzGeocentric {
+        let xyz = self.to_xyz_direction();
+        let (s_long, c_long) = array_pos.longitude_rad.sin_cos();
+        XyzGeocentric {
+            x: xyz.x * c_long - xyz.y * s_long,
+            y: xyz.x * s_long + xyz.y * c_long,
+            z: xyz.z,
+        }
+    }
+
+    /// Convert a direction vector in the [`XyzGeocentric`] frame to equatorial
+    /// coordinates, given the array position on Earth. This is the inverse of
+    /// [`HADec::to_geocentric_direction`].
+    ///
+    /// Uses ERFA.
+    pub fn from_geocentric_direction(xyz: XyzGeocentric, array_pos: LatLngHeight) -> HADec {
+        let (s_long, c_long) = array_pos.longitude_rad.sin_cos();
+        HADec::from_xyz_direction(XyzGeodetic {
+            x: xyz.x * c_long + xyz.y * s_long,
+            y: -xyz.x * s_long + xyz.y * c_long,
+            z: xyz.z,
+        })
+    }
+
+    /// Convert the equatorial coordinates to a unit direction vector in the
+    /// local topocentric [`ENH`] frame, given the local latitude on Earth. This
+    /// is the same as converting to [`AzEl`] with [`HADec::to_azel`] and then
+    /// using [`AzEl::to_enh_direction`].
+    pub fn to_enh_direction(self, latitude_rad: f64) -> ENH {
+        self.to_xyz_direction().to_enh(latitude_rad)
+    }
+
+    /// Convert a direction vector in the local topocentric [`ENH`] frame to
+    /// equatorial coordinates, given the local latitude on Earth. The vector
+    /// doesn't need to be normalised.
+    ///
+    /// Uses ERFA.
+    pub fn from_enh_direction(enh: ENH, latitude_rad: f64) -> HADec {
+        HADec::from_xyz_direction(enh.to_xyz(latitude_rad))
+    }
+
     /// Calculate the distance between two sets of coordinates.
     ///
     /// Uses ERFA.
     pub fn separation(self, b: Self) -> f64 {
-        unsafe { erfa_sys::eraSeps(self.ha, self.dec, b.ha, b.dec) }
+        erfa::seps(self.ha, self.dec, b.ha, b.dec)
     }
 
     /// Get the [parallactic
@@ -77,7 +147,7 @@ impl HADec {
     ///
     /// Uses ERFA.
     pub fn get_parallactic_angle(self, latitude_rad: f64) -> f64 {
-        unsafe { erfa_sys::eraHd2pa(self.ha, self.dec, latitude_rad) }
+        erfa::hd2pa(self.ha, self.dec, latitude_rad)
     }
 
     /// Get the [parallactic
@@ -86,7 +156,7 @@ impl HADec {
     ///
     /// Uses ERFA.
     pub fn get_parallactic_angle_mwa(self) -> f64 {
-        unsafe { erfa_sys::eraHd2pa(self.ha, self.dec, MWA_LAT_RAD) }
+        erfa::hd2pa(self.ha, self.dec, MWA_LAT_RAD)
     }
 }
 
@@ -139,6 +209,105 @@ mod tests {
     use super::*;
     use approx::assert_abs_diff_eq;
 
+    #[test]
+    fn test_xyz_direction() {
+        let xyz = HADec::new_degrees(0.0, 0.0).to_xyz_direction();
+        assert_abs_diff_eq!(
+            xyz,
+            XyzGeodetic {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0
+            },
+            epsilon = 1e-10
+        );
+        // East of the meridian has negative hour angle.
+        let xyz = HADec::new_degrees(-90.0, 0.0).to_xyz_direction();
+        assert_abs_diff_eq!(
+            xyz,
+            XyzGeodetic {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0
+            },
+            epsilon = 1e-10
+        );
+        let xyz = HADec::new_degrees(30.0, 90.0).to_xyz_direction();
+        assert_abs_diff_eq!(
+            xyz,
+            XyzGeodetic {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0
+            },
+            epsilon = 1e-10
+        );
+
+        let hd = HADec::new_degrees(-20.0, -35.0);
+        let xyz = hd.to_xyz_direction();
+        assert_abs_diff_eq!(HADec::from_xyz_direction(xyz), hd, epsilon = 1e-10);
+        let xyz = XyzGeodetic {
+            x: xyz.x * 2.0,
+            y: xyz.y * 2.0,
+            z: xyz.z * 2.0,
+        };
+        assert_abs_diff_eq!(HADec::from_xyz_direction(xyz), hd, epsilon = 1e-10);
+    }
+
+    #[test]
+    fn test_enh_direction() {
+        let array_pos = LatLngHeight::new_mwa();
+        for hd in [
+            HADec::new_degrees(1.0, -35.0),
+            HADec::new_degrees(-60.0, 10.0),
+            HADec::new_degrees(150.0, -80.0),
+        ] {
+            // Rotating into the ENH frame agrees with converting to AzEl.
+            let enh = hd.to_enh_direction(array_pos.latitude_rad);
+            let expected = hd.to_azel(array_pos.latitude_rad).to_enh_direction();
+            assert_abs_diff_eq!(enh, expected, epsilon = 1e-10);
+            assert_abs_diff_eq!(
+                HADec::from_enh_direction(enh, array_pos.latitude_rad),
+                hd,
+                epsilon = 1e-10
+            );
+        }
+        // The zenith has an HA of 0 and a Dec of the latitude.
+        let zenith = ENH {
+            e: 0.0,
+            n: 0.0,
+            h: 1.0,
+        };
+        assert_abs_diff_eq!(
+            HADec::from_enh_direction(zenith, array_pos.latitude_rad),
+            HADec::new(0.0, array_pos.latitude_rad),
+            epsilon = 1e-10
+        );
+    }
+
+    #[test]
+    fn test_geocentric_direction() {
+        let array_pos = LatLngHeight::new_mwa();
+        let hd = HADec::new_degrees(-20.0, -35.0);
+        let xyz = hd.to_geocentric_direction(array_pos);
+        assert_abs_diff_eq!(
+            xyz.x * xyz.x + xyz.y * xyz.y + xyz.z * xyz.z,
+            1.0,
+            epsilon = 1e-10
+        );
+        assert_abs_diff_eq!(
+            HADec::from_geocentric_direction(xyz, array_pos),
+            hd,
+            epsilon = 1e-10
+        );
+
+        // The local meridian at the equator points along the array's
+        // longitude.
+        let xyz = HADec::new(0.0, 0.0).to_geocentric_direction(array_pos);
+        assert_abs_diff_eq!(xyz.y.atan2(xyz.x), array_pos.longitude_rad, epsilon = 1e-10);
+        assert_abs_diff_eq!(xyz.z, 0.0, epsilon = 1e-10);
+    }
+
     #[test]
     fn to_azel() {
         let hd = HADec::new_degrees(1.0, -35.0);
@@ -0,0 +1,267 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Earth orientation parameters (DUT1 and polar motion), and a reader for IERS
+//! tables of them.
+//!
+//! IERS Bulletin A tables ("finals2000A.all") are available here:
+//! <https://datacenter.iers.org/data/9/finals2000A.all>
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use hifitime::{Duration, Epoch, Unit};
+use thiserror::Error;
+
+use crate::time::mjd_utc_days;
+
+/// The position of the Celestial Intermediate Pole with respect to the
+/// terrestrial reference frame, i.e. the IERS `PM-x` and `PM-y` values.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PolarMotion {
+    /// \[arcseconds\]
+    pub x_arcsec: f64,
+    /// \[arcseconds\]
+    pub y_arcsec: f64,
+}
+
+impl PolarMotion {
+    /// Correct a mean longitude and latitude \[radians\] for polar motion,
+    /// returning the true longitude and latitude. This uses the small-angle
+    /// approximation (e.g. SLALIB's `sla_POLMO`), which is accurate to well
+    /// below a milliarcsecond for realistic polar motion.
+    pub fn correct_long_lat(self, longitude_rad: f64, latitude_rad: f64) -> (f64, f64) {
+        let x = (self.x_arcsec / 3600.0).to_radians();
+        let y = (self.y_arcsec / 3600.0).to_radians();
+        let (s_long, c_long) = longitude_rad.sin_cos();
+        (
+            longitude_rad + (x * s_long + y * c_long) * latitude_rad.tan(),
+            latitude_rad + (x * c_long - y * s_long),
+        )
+    }
+}
+
+/// Earth orientation parameters at an instant.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EarthOrientation {
+    /// UT1 - UTC, a.k.a. DUT1.
+    pub dut1: Duration,
+    pub polar_motion: PolarMotion,
+}
+
+impl Default for EarthOrientation {
+    fn default() -> Self {
+        Self {
+            dut1: Duration::from_total_nanoseconds(0),
+            polar_motion: PolarMotion::default(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum IersError {
+    #[error("line {line_num} of the IERS table couldn't be parsed: {reason}")]
+    Parse { line_num: usize, reason: String },
+
+    #[error("the IERS table has no usable rows")]
+    Empty,
+
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+}
+
+/// A table of daily Earth orientation parameters, e.g. from the IERS
+/// `finals2000A.all` file.
+#[derive(Clone, Debug)]
+pub struct IersTable {
+    /// The UTC MJDs of each row, in ascending order.
+    mjds: Vec<f64>,
+    /// \[seconds\]
+    dut1s: Vec<f64>,
+    polar_motions: Vec<PolarMotion>,
+}
+
+impl IersTable {
+    /// Read an IERS `finals2000A` (Bulletin A) table from a file.
+    ///
+    /// # Errors
+    ///
+    /// See [`IersTable::read_finals`].
+    pub fn from_finals_file<T: AsRef<Path>>(path: T) -> Result<IersTable, IersError> {
+        Self::read_finals(BufReader::new(File::open(path)?))
+    }
+
+    /// Read an IERS `finals2000A` (Bulletin A) table. This is a fixed-width
+    /// format; rows without a polar motion or DUT1 value (i.e. beyond the
+    /// predictions) are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a row is malformed or no rows have values.
+    pub fn read_finals<R: BufRead>(reader: R) -> Result<IersTable, IersError> {
+        let mut table = IersTable {
+            mjds: vec![],
+            dut1s: vec![],
+            polar_motions: vec![],
+        };
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line_num = i + 1;
+            // Columns are 1-indexed in the IERS documentation.
+            let field = |start: usize, end: usize| -> Option<&str> {
+                line.get(start - 1..end)
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+            };
+            let parse = |name: &str, s: &str| -> Result<f64, IersError> {
+                s.parse().map_err(|_| IersError::Parse {
+                    line_num,
+                    reason: format!("invalid {name} '{s}'"),
+                })
+            };
+
+            let mjd = match field(8, 15) {
+                Some(s) => parse("MJD", s)?,
+                None => continue,
+            };
+            let (x, y, dut1) = match (field(19, 27), field(38, 46), field(59, 68)) {
+                (Some(x), Some(y), Some(dut1)) => (x, y, dut1),
+                _ => continue,
+            };
+            if table.mjds.last().map_or(false, |&last| mjd <= last) {
+                return Err(IersError::Parse {
+                    line_num,
+                    reason: format!("MJD {mjd} isn't after the previous row's"),
+                });
+            }
+            table.mjds.push(mjd);
+            table.polar_motions.push(PolarMotion {
+                x_arcsec: parse("PM-x", x)?,
+                y_arcsec: parse("PM-y", y)?,
+            });
+            table.dut1s.push(parse("UT1-UTC", dut1)?);
+        }
+
+        if table.mjds.is_empty() {
+            return Err(IersError::Empty);
+        }
+        Ok(table)
+    }
+
+    /// Get the Earth orientation parameters at `time` by linearly
+    /// interpolating between rows. `None` is returned if `time` isn't covered
+    /// by the table.
+    ///
+    /// Interpolation isn't done across leap seconds; DUT1 jumps by a second at
+    /// those, so the earlier row is used instead.
+    pub fn get(&self, time: Epoch) -> Option<EarthOrientation> {
+        let mjd = mjd_utc_days(time);
+        let first = *self.mjds.first()?;
+        let last = *self.mjds.last()?;
+        if mjd < first || mjd > last {
+            return None;
+        }
+
+        let i = self
+            .mjds
+            .partition_point(|&row_mjd| row_mjd <= mjd)
+            .saturating_sub(1);
+        let (dut1, polar_motion) = if i + 1 == self.mjds.len() {
+            (self.dut1s[i], self.polar_motions[i])
+        } else {
+            let frac = (mjd - self.mjds[i]) / (self.mjds[i + 1] - self.mjds[i]);
+            let lerp = |a: f64, b: f64| a + (b - a) * frac;
+            let (pm1, pm2) = (self.polar_motions[i], self.polar_motions[i + 1]);
+            let dut1 = if (self.dut1s[i + 1] - self.dut1s[i]).abs() > 0.5 {
+                self.dut1s[i]
+            } else {
+                lerp(self.dut1s[i], self.dut1s[i + 1])
+            };
+            (
+                dut1,
+                PolarMotion {
+                    x_arcsec: lerp(pm1.x_arcsec, pm2.x_arcsec),
+                    y_arcsec: lerp(pm1.y_arcsec, pm2.y_arcsec),
+                },
+            )
+        };
+
+        Some(EarthOrientation {
+            dut1: Duration::from_f64(dut1, Unit::Second),
+            polar_motion,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    // Three rows of finals2000A.all, the last without values.
+    const FINALS: &str = "\
+131015 56580.00 I  0.157211 0.000030  0.376612 0.000029  I 0.0716812 0.0000063  0.0606 0.0046  I    -0.203    0.285     0.083    0.124  0.157168  0.376640  0.0716980    -0.193     0.066
+131016 56581.00 I  0.155960 0.000030  0.375734 0.000030  I 0.0702582 0.0000063  1.7436 0.0049  I    -0.210    0.285     0.093    0.124  0.155924  0.375775  0.0702670    -0.203     0.067
+131017 56582.00
+";
+
+    #[test]
+    fn test_read_finals() {
+        let table = IersTable::read_finals(FINALS.as_bytes()).unwrap();
+        assert_eq!(table.mjds, [56580.0, 56581.0]);
+
+        let eop = table.get(Epoch::from_mjd_utc(56580.0)).unwrap();
+        assert_abs_diff_eq!(eop.dut1.in_seconds(), 0.0716812, epsilon = 1e-9);
+        assert_abs_diff_eq!(eop.polar_motion.x_arcsec, 0.157211, epsilon = 1e-9);
+        assert_abs_diff_eq!(eop.polar_motion.y_arcsec, 0.376612, epsilon = 1e-9);
+
+        let eop = table.get(Epoch::from_mjd_utc(56580.5)).unwrap();
+        assert_abs_diff_eq!(eop.dut1.in_seconds(), 0.0709697, epsilon = 1e-9);
+        assert_abs_diff_eq!(eop.polar_motion.x_arcsec, 0.1565855, epsilon = 1e-9);
+
+        assert!(table.get(Epoch::from_mjd_utc(56581.0)).is_some());
+        assert!(table.get(Epoch::from_mjd_utc(56581.5)).is_none());
+        assert!(table.get(Epoch::from_mjd_utc(56579.5)).is_none());
+    }
+
+    #[test]
+    fn test_read_finals_errors() {
+        assert!(matches!(
+            IersTable::read_finals("".as_bytes()),
+            Err(IersError::Empty)
+        ));
+
+        let bad = FINALS.replace("0.157211", "0.1x7211");
+        assert!(matches!(
+            IersTable::read_finals(bad.as_bytes()),
+            Err(IersError::Parse { line_num: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_polar_motion_correction() {
+        let (long, lat) = (2.0362898668561042, -0.4660608448386394);
+        assert_eq!(
+            PolarMotion::default().correct_long_lat(long, lat),
+            (long, lat)
+        );
+
+        // A 1 arcsecond shift of the pole along the x axis moves the latitude
+        // by cos(long) arcseconds.
+        let pm = PolarMotion {
+            x_arcsec: 1.0,
+            y_arcsec: 0.0,
+        };
+        let (_, new_lat) = pm.correct_long_lat(long, lat);
+        assert_abs_diff_eq!(
+            (new_lat - lat).to_degrees() * 3600.0,
+            long.cos(),
+            epsilon = 1e-9
+        );
+    }
+}
@@ -19,6 +19,7 @@ use super::uvw::UVW;
 /// Synthesis in Radio Astronomy, Third Edition, Section 3: Analysis of the
 /// Interferometer Response.
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(clippy::upper_case_acronyms)]
 pub struct LMN {
     /// l coordinate \[dimensionless\]
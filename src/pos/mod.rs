@@ -7,11 +7,14 @@
 pub mod azel;
 pub mod earth;
 pub mod enh;
+pub(crate) mod erfa;
 pub mod hadec;
+pub mod iers;
 pub mod lmn;
 pub mod pal;
 pub mod precession;
 pub mod radec;
+pub mod tile_group;
 pub mod uvw;
 pub mod xyz;
 
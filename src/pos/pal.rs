@@ -8,15 +8,18 @@
 //! named libstarlink-pal or libpal, (2) it depends on ERFA so statically
 //! compiling it in a -sys crate is much harder than it should be, (3) this code
 //! changes so slowly that we're unlikely to be out-of-date.
+//!
+//! The ERFA routines that PAL uses come from [`super::erfa`], i.e. the ERFA C
+//! library with the `erfa` feature, or Rust ports of it without.
 
 #![allow(non_snake_case)]
 #![allow(clippy::excessive_precision)]
 
-use erfa_sys::{
-    eraAnp, eraC2s, eraEpj, eraEpj2jd, eraEpv00, eraGmst06, eraIr, eraP06e, eraPdp, eraPmat06,
-    eraPn, eraPnm06a, eraRx, eraRxp, eraRxpv, eraRxr, eraRz, eraS2c, ERFA_AULT, ERFA_DAYSEC,
-    ERFA_DJM0,
-};
+use super::erfa::{self, anp, c2s, epj, epj2jd, pdp, pn, rx, rxp, rxr, rz, s2c, DJM0};
+use crate::constants::{DAYSEC, VEL_C};
+
+/// Light time for 1 au \[seconds\].
+const AULT: f64 = 149597870.7e3 / VEL_C;
 
 /// Greenwich mean sidereal time (consistent with IAU 2006 precession)
 ///
@@ -35,7 +38,7 @@ use erfa_sys::{
 ///
 /// Original: <https://github.com/Starlink/pal/blob/7af65f05fcd33fd7362c586eae7e98972cb03f29/palOne2One.c#L1332>
 pub fn palGmst(ut1: f64) -> f64 {
-    unsafe { eraGmst06(ERFA_DJM0, ut1, ERFA_DJM0, ut1) }
+    erfa::gmst06(DJM0, ut1, DJM0, ut1)
 }
 
 /// Spherical coordinates to direction cosines
@@ -53,7 +56,7 @@ pub fn palGmst(ut1: f64) -> f64 {
 ///    are right handed, with the x axis at zero longitude and latitude, and
 ///    the z axis at the +ve latitude pole.
 /// Notes:
-///    - Uses eraS2c(). See SOFA/ERFA documentation for details.
+///    - Port of `eraS2c()`. See SOFA/ERFA documentation for details.
 ///
 /// Original: <https://github.com/Starlink/pal/blob/7af65f05fcd33fd7362c586eae7e98972cb03f29/palOne2One.c#L368>
 ///
@@ -61,7 +64,7 @@ pub fn palGmst(ut1: f64) -> f64 {
 ///
 /// v will be modified
 pub unsafe fn palDcs2c(a: f64, b: f64, v: *mut f64) {
-    eraS2c(a, b, v);
+    std::slice::from_raw_parts_mut(v, 3).copy_from_slice(&s2c(a, b));
 }
 
 /// Cartesian to spherical coordinates
@@ -79,7 +82,7 @@ pub unsafe fn palDcs2c(a: f64, b: f64, v: *mut f64) {
 ///    are right handed, with the x axis at zero longitude and latitude, and
 ///    the z axis at the +ve latitude pole.
 /// Notes:
-///    - Uses eraC2s(). See SOFA/ERFA documentation for details.
+///    - Port of `eraC2s()`. See SOFA/ERFA documentation for details.
 ///
 /// Original: <https://github.com/Starlink/pal/blob/7af65f05fcd33fd7362c586eae7e98972cb03f29/palOne2One.c#L327>
 ///
@@ -87,7 +90,7 @@ pub unsafe fn palDcs2c(a: f64, b: f64, v: *mut f64) {
 ///
 /// `a` and `b` will be modified.
 pub unsafe fn palDcc2s(v: *mut f64, a: &mut f64, b: &mut f64) {
-    eraC2s(v, a, b);
+    (*a, *b) = c2s(&*(v as *const [f64; 3]));
 }
 
 /// Normalize angle into range 0-2 pi
@@ -100,11 +103,11 @@ pub unsafe fn palDcc2s(v: *mut f64, a: &mut f64, b: &mut f64) {
 /// Description:
 ///    Normalize angle into range 0-2 pi.
 /// Notes:
-///    - Uses eraAnp(). See SOFA/ERFA documentation for details.
+///    - Port of `eraAnp()`. See SOFA/ERFA documentation for details.
 ///
 /// Original: <https://github.com/Starlink/pal/blob/7af65f05fcd33fd7362c586eae7e98972cb03f29/palOne2One.c#L766>
 pub fn palDranrm(angle: f64) -> f64 {
-    unsafe { eraAnp(angle) }
+    anp(angle)
 }
 
 /// Normalizes a 3-vector also giving the modulus
@@ -119,7 +122,7 @@ pub fn palDranrm(angle: f64) -> f64 {
 /// Description:
 ///    Normalizes a 3-vector also giving the modulus.
 /// Notes:
-///    - Uses eraPn(). See SOFA/ERFA documentation for details.
+///    - Port of `eraPn()`. See SOFA/ERFA documentation for details.
 ///    - the arguments are flipped
 ///
 /// Original: <https://github.com/Starlink/pal/blob/7af65f05fcd33fd7362c586eae7e98972cb03f29/palOne2One.c#L1015>
@@ -128,7 +131,9 @@ pub fn palDranrm(angle: f64) -> f64 {
 ///
 /// `uv` and `vm` will be modified.
 pub unsafe fn palDvn(v: *mut f64, uv: *mut f64, vm: *mut f64) {
-    eraPn(v, vm, uv);
+    let (modulus, unit) = pn(&*(v as *const [f64; 3]));
+    std::slice::from_raw_parts_mut(uv, 3).copy_from_slice(&unit);
+    *vm = modulus;
 }
 
 /// Scalar product of two 3-vectors
@@ -140,14 +145,14 @@ pub unsafe fn palDvn(v: *mut f64, uv: *mut f64, vm: *mut f64) {
 /// Returned Value:
 ///    Scalar product va.vb
 /// Notes:
-///    - Uses eraPdp(). See SOFA/ERFA documentation for details.
+///    - Port of `eraPdp()`. See SOFA/ERFA documentation for details.
 ///
 /// # Safety
 ///
-/// [`erfa_sys::eraPdp`] requires `va` and `vb` to be mutable, even though
-/// they are not mutated.
+/// `va` and `vb` must point to 3 elements. They are mutable for
+/// compatibility with ERFA's `eraPdp`, but are not mutated.
 pub unsafe fn palDvdv(va: *mut f64, vb: *mut f64) -> f64 {
-    eraPdp(va, vb)
+    pdp(&*(va as *const [f64; 3]), &*(vb as *const [f64; 3]))
 }
 
 /// Performs the 3-D forward unitary transformation
@@ -160,13 +165,14 @@ pub unsafe fn palDvdv(va: *mut f64, vb: *mut f64) -> f64 {
 ///    dp = double\[3\] (Returned)
 ///       result vector
 /// Notes:
-///    - Uses eraRxp(). See SOFA/ERFA documentation for details.
+///    - Port of `eraRxp()`. See SOFA/ERFA documentation for details.
 ///
 /// # Safety
 ///
 /// `dp` will be modified.
 pub unsafe fn palDmxv(dm: *mut [f64; 3], va: *mut f64, vb: *mut f64) {
-    eraRxp(dm, va, vb);
+    let rp = rxp(&*(dm as *const [[f64; 3]; 3]), &*(va as *const [f64; 3]));
+    std::slice::from_raw_parts_mut(vb, 3).copy_from_slice(&rp);
 }
 
 /// Barycentric and heliocentric velocity and position of the Earth.
@@ -202,25 +208,19 @@ pub unsafe fn palEvp(
     dvh: *mut f64,
     dph: *mut f64,
 ) {
-    /* Local Variables; */
-    let mut pvh = [[0.0; 3]; 2];
-    let mut pvb = [[0.0; 3]; 2];
-    let mut d1 = 0.0;
-    let mut d2 = 0.0;
-    let mut r = [[0.0; 3]; 3];
-
     /* BCRS PV-vectors. */
-    eraEpv00(2400000.5, date, pvh.as_mut_ptr(), pvb.as_mut_ptr());
+    let (mut pvh, mut pvb) = erfa::epv00(DJM0, date);
 
     /* Was precession to another equinox requested? */
     if deqx > 0.0 {
         /* Yes: compute precession matrix from J2000.0 to deqx. */
-        eraEpj2jd(deqx, &mut d1, &mut d2);
-        eraPmat06(d1, d2, r.as_mut_ptr());
+        let (d1, d2) = epj2jd(deqx);
+        let r = erfa::pmat06(d1, d2);
 
         /* Rotate the PV-vectors. */
-        eraRxpv(r.as_mut_ptr(), pvh.as_mut_ptr(), pvh.as_mut_ptr());
-        eraRxpv(r.as_mut_ptr(), pvb.as_mut_ptr(), pvb.as_mut_ptr());
+        for pv in pvh.iter_mut().chain(pvb.iter_mut()) {
+            *pv = rxp(&r, pv);
+        }
     }
 
     /* Return the required vectors. */
@@ -229,8 +229,8 @@ pub unsafe fn palEvp(
     let dvh = std::slice::from_raw_parts_mut(dvh, 3);
     let dph = std::slice::from_raw_parts_mut(dph, 3);
     for i in 0..3 {
-        dvh[i] = pvh[1][i] / ERFA_DAYSEC;
-        dvb[i] = pvb[1][i] / ERFA_DAYSEC;
+        dvh[i] = pvh[1][i] / DAYSEC;
+        dvb[i] = pvb[1][i] / DAYSEC;
         dph[i] = pvh[0][i];
         dpb[i] = pvb[0][i];
     }
@@ -260,63 +260,22 @@ pub unsafe fn palEvp(
 ///
 /// `epoch`, `date`, and `rmatpn` will be modified.
 pub unsafe fn palPrenut(epoch: f64, date: f64, rmatpn: *mut [f64; 3]) {
-    /* Local Variables: */
-    let mut bpa = 0.0;
-    let mut bpia = 0.0;
-    let mut bqa = 0.0;
-    let mut chia = 0.0;
-    let mut d1 = 0.0;
-    let mut d2 = 0.0;
-    let mut eps0 = 0.0;
-    let mut epsa = 0.0;
-    let mut gam = 0.0;
-    let mut oma = 0.0;
-    let mut pa = 0.0;
-    let mut phi = 0.0;
-    let mut pia = 0.0;
-    let mut psi = 0.0;
-    let mut psia = 0.0;
-    let mut r1 = [[0.0; 3]; 3];
-    let mut r2 = [[0.0; 3]; 3];
-    let mut thetaa = 0.0;
-    let mut za = 0.0;
-    let mut zetaa = 0.0;
-
     /* Specified Julian epoch as a 2-part JD. */
-    eraEpj2jd(epoch, &mut d1, &mut d2);
+    let (d1, d2) = epj2jd(epoch);
 
     /* P matrix, from specified epoch to J2000.0. */
-    eraP06e(
-        d1,
-        d2,
-        &mut eps0,
-        &mut psia,
-        &mut oma,
-        &mut bpa,
-        &mut bqa,
-        &mut pia,
-        &mut bpia,
-        &mut epsa,
-        &mut chia,
-        &mut za,
-        &mut zetaa,
-        &mut thetaa,
-        &mut pa,
-        &mut gam,
-        &mut phi,
-        &mut psi,
-    );
-    eraIr(r1.as_mut_ptr());
-    eraRz(-chia, r1.as_mut_ptr());
-    eraRx(oma, r1.as_mut_ptr());
-    eraRz(psia, r1.as_mut_ptr());
-    eraRx(-eps0, r1.as_mut_ptr());
+    let p06e = erfa::p06e(d1, d2);
+    let mut r1 = erfa::ir();
+    rz(-p06e.chia, &mut r1);
+    rx(p06e.oma, &mut r1);
+    rz(p06e.psia, &mut r1);
+    rx(-p06e.eps0, &mut r1);
 
     /* NPB matrix, from J2000.0 to date. */
-    eraPnm06a(ERFA_DJM0, date, r2.as_mut_ptr());
+    let r2 = erfa::pnm06a(DJM0, date);
 
     /* NPB matrix, from specified epoch to date. */
-    eraRxr(r2.as_mut_ptr(), r1.as_mut_ptr(), rmatpn);
+    std::slice::from_raw_parts_mut(rmatpn, 3).copy_from_slice(&rxr(&r2, &r1));
 }
 
 /// Compute parameters needed by palAmpqk and palMapqk.
@@ -357,16 +316,13 @@ pub unsafe fn palMappa(eq: f64, date: f64, amprms: *mut f64) {
     let mut ebd = [0.0; 3];
     let mut ehd = [0.0; 3];
     let mut eh = [0.0; 3];
-    let mut e = 0.0;
-    let mut vn = [0.0; 3];
-    let mut vm = 0.0;
 
     /* Initialise so that unsused values are returned holding zero */
     let amprms = std::slice::from_raw_parts_mut(amprms, 21);
     amprms.fill(0.0);
 
     /* Time interval for proper motion correction. */
-    amprms[0] = eraEpj(ERFA_DJM0, date) - eq;
+    amprms[0] = epj(DJM0, date) - eq;
 
     /* Get Earth barycentric and heliocentric position and velocity. */
     palEvp(
@@ -379,16 +335,17 @@ pub unsafe fn palMappa(eq: f64, date: f64, amprms: *mut f64) {
     );
 
     /* Heliocentric direction of Earth (normalized) and modulus. */
-    eraPn(eh.as_mut_ptr(), &mut e, &mut amprms[4]);
+    let (e, eh_unit) = pn(&eh);
+    amprms[4..7].copy_from_slice(&eh_unit);
 
     /* Light deflection parameter */
     amprms[7] = gr2 / e;
 
     /* Aberration parameters. */
     for i in 0..3 {
-        amprms[i + 8] = ebd[i] * ERFA_AULT;
+        amprms[i + 8] = ebd[i] * AULT;
     }
-    eraPn(&mut amprms[8], &mut vm, vn.as_mut_ptr());
+    let (vm, _) = pn(&[amprms[8], amprms[9], amprms[10]]);
     amprms[11] = (1.0 - vm * vm).sqrt();
 
     /* NPB matrix. */
@@ -542,9 +499,35 @@ mod tests {
         assert_abs_diff_eq!(dph, phex, epsilon = 1e-12);
     }
 
+    /// PAL doesn't test this. At an epoch of J2000.0, the precession from the
+    /// epoch to J2000.0 is the identity, so we expect the ERFA test suite's
+    /// eraPnm06a matrix.
     #[test]
     fn prenut() {
-        // TODO: PAL doesn't test this.
+        let mut rmatpn = [[0.0; 3]; 3];
+        let expected = [
+            [
+                0.9999995832794205484,
+                0.8372382772630962111e-3,
+                0.3639684771140623099e-3,
+            ],
+            [
+                -0.8372533744743683605e-3,
+                0.9999996486492861646,
+                0.4132905944611019498e-4,
+            ],
+            [
+                -0.3639337469629464969e-3,
+                -0.4163377605910663999e-4,
+                0.9999999329094260057,
+            ],
+        ];
+
+        unsafe { palPrenut(2000.0, 50123.9999, rmatpn.as_mut_ptr()) };
+
+        for (received, expected) in rmatpn.iter().zip(expected) {
+            assert_abs_diff_eq!(received[..], expected[..], epsilon = 1e-12);
+        }
     }
 
     /// Original: <https://github.com/Starlink/pal/blob/7af65f05fcd33fd7362c586eae7e98972cb03f29/palTest.c#L1395>
@@ -13,18 +13,50 @@
 //! A harder-to-read source of info is here:
 //! <https://www.aanda.org/articles/aa/pdf/2003/48/aa4068.pdf>
 
-use std::f64::consts::TAU;
+use std::f64::consts::{PI, TAU};
 
 use hifitime::{Duration, Epoch};
 use rayon::prelude::*;
 
-use crate::{pal, HADec, RADec, XyzGeodetic};
+use crate::{
+    pal,
+    pos::{
+        erfa::{self, DJM0},
+        iers::PolarMotion,
+        xyz::baselines_to_uvws,
+    },
+    time::{jd_tt_days, mjd_ut1_days},
+    HADec, RADec, XyzGeodetic, UVW,
+};
+
+/// The frame that visibility writers form [`UVW`]s in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UvwFrame {
+    /// Precess the tile positions and phase centre to the J2000 epoch. This is
+    /// what cotter and Birli do.
+    J2000,
+
+    /// Don't precess; UVWs are in the frame of the current epoch (i.e. "of
+    /// date"). This is useful for comparing against instruments and tools
+    /// that don't precess.
+    OfDate,
+}
+
+impl Default for UvwFrame {
+    fn default() -> Self {
+        Self::J2000
+    }
+}
 
 #[derive(Debug)]
 pub struct PrecessionInfo {
     /// Bias procession rotation matrix.
     rotation_matrix: [[f64; 3]; 3],
 
+    /// The (unprecessed) phase centre at the LMST of the current epoch.
+    pub hadec: HADec,
+
     /// The precessed phase centre in the J2000 epoch.
     pub hadec_j2000: HADec,
 
@@ -36,9 +68,33 @@ pub struct PrecessionInfo {
 
     /// The precessed array latitude in the J2000 epoch.
     pub array_latitude_j2000: f64,
+
+    /// The UT1 modified Julian date of the current epoch, used for
+    /// aberration \[days\].
+    pub mjd: f64,
 }
 
 impl PrecessionInfo {
+    /// The bias precession rotation matrix, which rotates vectors from the true
+    /// equator and equinox of the current epoch to the J2000 mean equator and
+    /// equinox.
+    pub fn rotation_matrix(&self) -> [[f64; 3]; 3] {
+        self.rotation_matrix
+    }
+
+    /// Precess [`RADec`]s from the frame of the current epoch to the J2000
+    /// epoch with the same rotation as the array (see
+    /// [`PrecessionInfo::rotation_matrix`]), e.g. to move a source list into
+    /// the same frame as the precessed visibilities.
+    pub fn precess_radec(&self, radecs: &[RADec]) -> Vec<RADec> {
+        let mut out = Vec::with_capacity(radecs.len());
+        radecs
+            .par_iter()
+            .map(|&radec| rotate(self.rotation_matrix, radec))
+            .collect_into_vec(&mut out);
+        out
+    }
+
     // Blatently stolen from cotter.
     pub fn precess_xyz_parallel(&self, xyzs: &[XyzGeodetic]) -> Vec<XyzGeodetic> {
         let (sep, cep) = self.lmst.sin_cos();
@@ -67,16 +123,142 @@ impl PrecessionInfo {
             .collect_into_vec(&mut out);
         out
     }
+
+    /// The inverse of [`PrecessionInfo::precess_xyz_parallel`]; bring
+    /// [`XyzGeodetic`]s precessed to the J2000 epoch back to the current epoch.
+    pub fn unprecess_xyz_parallel(&self, xyzs_j2000: &[XyzGeodetic]) -> Vec<XyzGeodetic> {
+        let mut out = Vec::with_capacity(xyzs_j2000.len());
+        xyzs_j2000
+            .par_iter()
+            .map(|&xyz| self.unprecess_xyz(xyz))
+            .collect_into_vec(&mut out);
+        out
+    }
+
+    /// The inverse of the phase centre precession; get the phase centre at the
+    /// LMST of the current epoch (like [`PrecessionInfo::hadec`]) from a
+    /// precessed phase centre in the J2000 epoch (like
+    /// [`PrecessionInfo::hadec_j2000`]).
+    pub fn unprecess_hadec(&self, hadec_j2000: HADec) -> HADec {
+        let radec_aber = RADec::new(
+            pal::palDranrm(self.lmst_j2000 - hadec_j2000.ha),
+            hadec_j2000.dec,
+        );
+        unaber_radec_rad(2000.0, self.mjd, radec_aber).to_hadec(self.lmst)
+    }
+
+    /// Bring [`UVW`]s in the J2000 frame (e.g. as written by cotter, Birli or
+    /// [`UvwFrame::J2000`]) back to the frame of the current epoch, i.e. the
+    /// equivalent of [`UvwFrame::OfDate`]. The UVWs must be for this phase
+    /// centre.
+    pub fn unprecess_uvws(&self, uvws_j2000: &[UVW]) -> Vec<UVW> {
+        let mut out = Vec::with_capacity(uvws_j2000.len());
+        uvws_j2000
+            .par_iter()
+            .map(|uvw| {
+                let xyz = self.unprecess_xyz(uvw.to_xyz(self.hadec_j2000));
+                UVW::from_xyz(xyz, self.hadec)
+            })
+            .collect_into_vec(&mut out);
+        out
+    }
+
+    fn unprecess_xyz(&self, xyz: XyzGeodetic) -> XyzGeodetic {
+        let (sep, cep) = self.lmst.sin_cos();
+        let (s2000, c2000) = self.lmst_j2000.sin_cos();
+
+        // rotate to frame with x axis at zero RA in J2000
+        let xpr2 = c2000 * xyz.x - s2000 * xyz.y;
+        let ypr2 = s2000 * xyz.x + c2000 * xyz.y;
+        let zpr2 = xyz.z;
+
+        // apply the transposed (inverse) rotation matrix
+        let rmat = &self.rotation_matrix;
+        let xpr = rmat[0][0] * xpr2 + rmat[1][0] * ypr2 + rmat[2][0] * zpr2;
+        let ypr = rmat[0][1] * xpr2 + rmat[1][1] * ypr2 + rmat[2][1] * zpr2;
+        let zpr = rmat[0][2] * xpr2 + rmat[1][2] * ypr2 + rmat[2][2] * zpr2;
+
+        // rotate back to frame with x axis at the current lmst
+        XyzGeodetic {
+            x: cep * xpr + sep * ypr,
+            y: -sep * xpr + cep * ypr,
+            z: zpr,
+        }
+    }
+
+    /// Get the [`UVW`]s of the given pairs of tile indices (e.g.
+    /// [`crate::VisContext::sel_baselines`]) in the given [`UvwFrame`].
+    pub fn baseline_uvws(
+        &self,
+        frame: UvwFrame,
+        xyzs: &[XyzGeodetic],
+        baselines: &[(usize, usize)],
+    ) -> Vec<UVW> {
+        match frame {
+            UvwFrame::J2000 => {
+                let xyzs_precessed = self.precess_xyz_parallel(xyzs);
+                baselines_to_uvws(
+                    &XyzGeodetic::to_selected_baselines(&xyzs_precessed, baselines),
+                    self.hadec_j2000,
+                )
+            }
+            UvwFrame::OfDate => baselines_to_uvws(
+                &XyzGeodetic::to_selected_baselines(xyzs, baselines),
+                self.hadec,
+            ),
+        }
+    }
 }
 
-/// Get the local mean sidereal time. `time` should be in the UTC frame, and
-/// `dut1` (i.e. UT1 - UTC) provides a better estimate of the LMST. If DUT1
-/// isn't known, then a [`Duration`] of 0 seconds can be used; the results are
-/// wrong by up to 0.9 seconds.
+/// The UT1 and TT modified Julian dates of `time` \[days\].
+fn mjd_ut1_tt_days(time: Epoch, dut1: Duration) -> (f64, f64) {
+    (mjd_ut1_days(time, dut1), jd_tt_days(time) - DJM0)
+}
+
+/// Get the Earth rotation angle (ERA) \[radians\], in the range \[0, 2pi).
+/// `time` should be in the UTC frame, and `dut1` (i.e. UT1 - UTC) provides a
+/// better estimate of the ERA. If DUT1 isn't known, then a [`Duration`] of 0
+/// seconds can be used; the results are wrong by up to 0.9 seconds.
+///
+/// Uses ERFA.
+pub fn get_era(time: Epoch, dut1: Duration) -> f64 {
+    let ut1 = mjd_ut1_days(time, dut1);
+    erfa::era00(DJM0, ut1)
+}
+
+/// Get the Greenwich mean sidereal time (GMST, consistent with IAU 2006
+/// precession) \[radians\], in the range \[0, 2pi). `time` should be in the
+/// UTC frame, and `dut1` is as for [`get_era`].
+///
+/// Uses ERFA.
+pub fn get_gmst(time: Epoch, dut1: Duration) -> f64 {
+    let (ut1, tt) = mjd_ut1_tt_days(time, dut1);
+    erfa::gmst06(DJM0, ut1, DJM0, tt)
+}
+
+/// Get the Greenwich apparent sidereal time (GAST, IAU 2006/2000A), i.e. the
+/// GMST corrected for nutation (the equation of the equinoxes) \[radians\], in
+/// the range \[0, 2pi). `time` should be in the UTC frame, and `dut1` is as
+/// for [`get_era`].
+///
+/// Uses ERFA.
+pub fn get_gast(time: Epoch, dut1: Duration) -> f64 {
+    let (ut1, tt) = mjd_ut1_tt_days(time, dut1);
+    erfa::gst06a(DJM0, ut1, DJM0, tt)
+}
+
+/// Get the local mean sidereal time \[radians\], in the range \[0, 2pi).
+/// `time` should be in the UTC frame, and `dut1` (i.e. UT1 - UTC) provides a
+/// better estimate of the LMST. If DUT1 isn't known, then a [`Duration`] of 0
+/// seconds can be used; the results are wrong by up to 0.9 seconds.
 pub fn get_lmst(array_longitude_rad: f64, time: Epoch, dut1: Duration) -> f64 {
-    let ut1 = (time + dut1).as_mjd_utc_days();
-    let gmst = pal::palGmst(ut1);
-    (gmst + array_longitude_rad) % TAU
+    (get_gmst(time, dut1) + array_longitude_rad).rem_euclid(TAU)
+}
+
+/// Get the local apparent sidereal time \[radians\], in the range \[0, 2pi).
+/// See [`get_gast`] and [`get_lmst`].
+pub fn get_last(array_longitude_rad: f64, time: Epoch, dut1: Duration) -> f64 {
+    (get_gast(time, dut1) + array_longitude_rad).rem_euclid(TAU)
 }
 
 /// Obtain precessed coordinate information. `time` should be in the UTC frame,
@@ -97,32 +279,124 @@ pub fn precess_time(
     let lmst = get_lmst(array_longitude_rad, time, dut1);
 
     let j2000 = 2000.0;
-    let mjd = (time + dut1).as_mjd_utc_days();
+    let mjd = mjd_ut1_days(time, dut1);
     let radec_aber = aber_radec_rad(j2000, mjd, phase_centre);
     let mut rotation_matrix = [[0.0; 3]; 3];
     unsafe { pal::palPrenut(j2000, mjd, rotation_matrix.as_mut_ptr()) };
 
-    // Transpose the rotation matrix.
-    let mut rotation_matrix = {
-        let mut new = [[0.0; 3]; 3];
-        let old = rotation_matrix;
-        for (i, old) in old.iter().enumerate() {
-            for (j, new) in new.iter_mut().enumerate() {
-                new[i] = old[j];
-            }
-        }
-        new
-    };
+    let mut rotation_matrix = transpose(rotation_matrix);
 
     let precessed = hadec_j2000(&mut rotation_matrix, lmst, array_latitude_rad, radec_aber);
 
     PrecessionInfo {
         rotation_matrix,
+        hadec: phase_centre.to_hadec(lmst),
         hadec_j2000: precessed.hadec,
         lmst,
         lmst_j2000: precessed.lmst,
         array_latitude_j2000: precessed.latitude,
+        mjd,
+    }
+}
+
+/// The same as [`precess_time`], but the array position is first corrected for
+/// [`PolarMotion`] (e.g. from an [`crate::iers::IersTable`]). Ignoring
+/// polar motion gives errors of up to ~0.5 arcseconds.
+pub fn precess_time_with_polar_motion(
+    array_longitude_rad: f64,
+    array_latitude_rad: f64,
+    phase_centre: RADec,
+    time: Epoch,
+    dut1: Duration,
+    polar_motion: PolarMotion,
+) -> PrecessionInfo {
+    let (array_longitude_rad, array_latitude_rad) =
+        polar_motion.correct_long_lat(array_longitude_rad, array_latitude_rad);
+    precess_time(
+        array_longitude_rad,
+        array_latitude_rad,
+        phase_centre,
+        time,
+        dut1,
+    )
+}
+
+/// The IAU 2006 precession matrix (including the frame bias), which rotates
+/// vectors from the J2000 mean equator and equinox to the mean equator and
+/// equinox of `time`. `time` can be in any frame.
+///
+/// Uses ERFA.
+pub fn precession_matrix(time: Epoch) -> [[f64; 3]; 3] {
+    let tt = jd_tt_days(time) - DJM0;
+    erfa::pmat06(DJM0, tt)
+}
+
+/// The IAU 2006/2000A nutation matrix, which rotates vectors from the mean
+/// equator and equinox of `time` to the true equator and equinox of `time`.
+/// `time` can be in any frame.
+///
+/// Uses ERFA.
+pub fn nutation_matrix(time: Epoch) -> [[f64; 3]; 3] {
+    let tt = jd_tt_days(time) - DJM0;
+    erfa::num06a(DJM0, tt)
+}
+
+/// Precess `radec` from the J2000 mean equator and equinox to the mean equator
+/// and equinox of `time`, without nutation or aberration (see
+/// [`precession_matrix`]).
+pub fn precess_only(radec: RADec, time: Epoch) -> RADec {
+    rotate(precession_matrix(time), radec)
+}
+
+/// The inverse of [`precess_only`]; precess `radec` from the mean equator and
+/// equinox of `time` to the J2000 mean equator and equinox.
+pub fn precess_only_inverse(radec: RADec, time: Epoch) -> RADec {
+    rotate(transpose(precession_matrix(time)), radec)
+}
+
+/// Nutate `radec` from the mean equator and equinox of `time` to the true
+/// equator and equinox of `time`, without precession or aberration (see
+/// [`nutation_matrix`]).
+pub fn nutate_only(radec: RADec, time: Epoch) -> RADec {
+    rotate(nutation_matrix(time), radec)
+}
+
+/// The inverse of [`nutate_only`]; nutate `radec` from the true equator and
+/// equinox of `time` to the mean equator and equinox of `time`.
+pub fn nutate_only_inverse(radec: RADec, time: Epoch) -> RADec {
+    rotate(transpose(nutation_matrix(time)), radec)
+}
+
+/// Precess `radec` from the mean equator and equinox of `from` to the mean
+/// equator and equinox of `to`, e.g. to compare B1950 and J2000 positions.
+///
+/// Unlike [`precess_only`], the frame bias cancels, so precessing from J2000
+/// treats `radec` as being in the (dynamical) J2000 mean frame, not the ICRS.
+pub fn precess_between_equinoxes(radec: RADec, from: Epoch, to: Epoch) -> RADec {
+    let to_j2000 = transpose(precession_matrix(from));
+    let from_j2000 = precession_matrix(to);
+    let mut rotation_matrix = [[0.0; 3]; 3];
+    for (new_row, from_j2000_row) in rotation_matrix.iter_mut().zip(from_j2000) {
+        for (j, new) in new_row.iter_mut().enumerate() {
+            *new = (0..3).map(|k| from_j2000_row[k] * to_j2000[k][j]).sum();
+        }
     }
+    rotate(rotation_matrix, radec)
+}
+
+fn transpose(matrix: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut new = [[0.0; 3]; 3];
+    for (i, old) in matrix.iter().enumerate() {
+        for (j, new) in new.iter_mut().enumerate() {
+            new[i] = old[j];
+        }
+    }
+    new
+}
+
+fn rotate(mut rotation_matrix: [[f64; 3]; 3], radec: RADec) -> RADec {
+    let (ra, dec) = rotate_radec(&mut rotation_matrix, radec.ra, radec.dec);
+    RADec::new(ra, dec)
 }
 
 // Blatently stolen from cotter.
@@ -142,6 +416,21 @@ fn aber_radec_rad(eq: f64, mjd: f64, radec: RADec) -> RADec {
     }
 }
 
+/// The inverse of `aber_radec_rad`. Aberration is at most ~20 arcseconds, so
+/// a few iterations are plenty.
+fn unaber_radec_rad(eq: f64, mjd: f64, radec_aber: RADec) -> RADec {
+    let mut radec = radec_aber;
+    for _ in 0..4 {
+        let aber = aber_radec_rad(eq, mjd, radec);
+        let d_ra = (radec_aber.ra - aber.ra + PI).rem_euclid(TAU) - PI;
+        radec = RADec::new(
+            (radec.ra + d_ra).rem_euclid(TAU),
+            radec.dec + radec_aber.dec - aber.dec,
+        );
+    }
+    radec
+}
+
 // Blatently stolen from cotter.
 fn stelaber(eq: f64, mjd: f64, v1: &mut [f64; 3], v2: &mut [f64; 3]) {
     let mut amprms = [0.0; 21];
@@ -266,6 +555,84 @@ mod tests {
         );
     }
 
+    // The expected GMSTs are the astropy LMSTs of `test_get_lst` minus the
+    // MWA's longitude, and the expected ERAs are from the IAU 2000 definition
+    //
+    // ERA = 2pi * (0.7790572732640 + 1.00273781191135448 * (JD_UT1 - 2451545.0))
+    //
+    // (as also used by astropy's `Time.earth_rotation_angle`).
+    #[test]
+    fn test_get_era_gmst() {
+        let dut1 = Duration::from_f64(-0.31295757, Unit::Second);
+        for (gps, expected_era, expected_gmst) in [
+            (1090008642.0, 4.2225210293940115, 4.225775259743918),
+            (1090008643.0, 4.2225939505454785, 4.225848180902276),
+            (1090008644.0, 4.2226668716969456, 4.225921102060649),
+        ] {
+            let epoch = Epoch::from_gpst_seconds(gps);
+            assert_abs_diff_eq!(get_era(epoch, dut1), expected_era, epsilon = 1e-9);
+            let gmst = get_gmst(epoch, dut1);
+            assert_abs_diff_eq!(gmst, expected_gmst, epsilon = 1e-9);
+            assert_abs_diff_eq!(
+                get_lmst(MWA_LONG_RAD, epoch, dut1),
+                (gmst + MWA_LONG_RAD) % TAU,
+                epsilon = 1e-12
+            );
+        }
+
+        // LSTs are positive, even west of Greenwich.
+        let epoch = Epoch::from_gpst_seconds(1090008642.0);
+        let lmst = get_lmst(-MWA_LONG_RAD - 3.0, epoch, dut1);
+        assert!((0.0..TAU).contains(&lmst));
+    }
+
+    // astropy's apparent sidereal time, e.g.
+    //
+    // Time("1090008642", format="gps", scale="utc", location=loc).sidereal_time("apparent", "greenwich")
+    //
+    // is ERFA's gst06a of the UT1 and TT dates of the time. The expected
+    // values were calculated that way, with the DUT1s of `test_get_lst`; this
+    // also reproduces the astropy LMSTs of `test_get_lst` to better than 1e-10
+    // radians.
+    #[test]
+    fn test_get_gast() {
+        for (gps, dut1, expected_gast, expected_last) in [
+            (
+                1090008642.0,
+                -0.31295757,
+                4.2258108864232948,
+                6.262100753279399,
+            ),
+            (
+                1090008643.0,
+                -0.31295757,
+                4.2258838075845642,
+                6.262173674440668,
+            ),
+            (
+                1090008644.0,
+                -0.31295757,
+                4.2259567287458202,
+                6.262246595601924,
+            ),
+            (
+                1090008647.0,
+                -0.31295758,
+                4.2261754922288484,
+                6.262465359084953,
+            ),
+        ] {
+            let epoch = Epoch::from_gpst_seconds(gps);
+            let dut1 = Duration::from_f64(dut1, Unit::Second);
+            assert_abs_diff_eq!(get_gast(epoch, dut1), expected_gast, epsilon = 1e-9);
+            assert_abs_diff_eq!(
+                get_last(MWA_LONG_RAD, epoch, dut1),
+                expected_last,
+                epsilon = 1e-9
+            );
+        }
+    }
+
     #[test]
     // TODO: reduce cognitive complexity
     #[allow(clippy::cognitive_complexity)]
@@ -415,10 +782,10 @@ mod tests {
         assert_abs_diff_ne!(p.rotation_matrix[2][0], 0.0, epsilon = 1e-5);
         assert_abs_diff_ne!(p.rotation_matrix[2][1], 0.0, epsilon = 1e-5);
 
-        assert_abs_diff_eq!(p.hadec_j2000.ha, 6.0714305189419715, epsilon = 1e-10);
+        assert_abs_diff_eq!(p.hadec_j2000.ha, 6.0714305193721465, epsilon = 1e-10);
         assert_abs_diff_eq!(p.hadec_j2000.dec, -0.47122418312765446, epsilon = 1e-10);
-        assert_abs_diff_eq!(p.lmst, 6.0747789094260245, epsilon = 1e-10);
-        assert_abs_diff_eq!(p.lmst_j2000, 6.071524853456497, epsilon = 1e-10);
+        assert_abs_diff_eq!(p.lmst, 6.074778909856257, epsilon = 1e-10);
+        assert_abs_diff_eq!(p.lmst_j2000, 6.071524853887019, epsilon = 1e-10);
         assert_abs_diff_eq!(p.array_latitude_j2000, -0.467396549790915, epsilon = 1e-10);
         assert_abs_diff_ne!(p.array_latitude_j2000, MWA_LAT_RAD, epsilon = 1e-5);
 
@@ -452,10 +819,10 @@ mod tests {
         assert_abs_diff_ne!(p.rotation_matrix[2][0], 0.0, epsilon = 1e-5);
         assert_abs_diff_ne!(p.rotation_matrix[2][1], 0.0, epsilon = 1e-5);
 
-        assert_abs_diff_eq!(p.hadec_j2000.ha, 0.409885996082088, epsilon = 1e-10);
+        assert_abs_diff_eq!(p.hadec_j2000.ha, 0.4098859965577972, epsilon = 1e-10);
         assert_abs_diff_eq!(p.hadec_j2000.dec, -0.5235637661235192, epsilon = 1e-10);
-        assert_abs_diff_eq!(p.lmst, 1.4598017673520172, epsilon = 1e-10);
-        assert_abs_diff_eq!(p.lmst_j2000, 1.4571918352968762, epsilon = 1e-10);
+        assert_abs_diff_eq!(p.lmst, 1.4598017678280968, epsilon = 1e-10);
+        assert_abs_diff_eq!(p.lmst_j2000, 1.4571918357729838, epsilon = 1e-10);
         assert_abs_diff_eq!(p.array_latitude_j2000, -0.4661807836570052, epsilon = 1e-10);
         assert_abs_diff_ne!(p.array_latitude_j2000, MWA_LAT_RAD, epsilon = 1e-5);
 
@@ -491,10 +858,10 @@ mod tests {
         assert_abs_diff_ne!(p.rotation_matrix[2][0], 0.0, epsilon = 1e-5);
         assert_abs_diff_ne!(p.rotation_matrix[2][1], 0.0, epsilon = 1e-5);
 
-        assert_abs_diff_eq!(p.hadec_j2000.ha, 0.4098571004815674, epsilon = 1e-10);
+        assert_abs_diff_eq!(p.hadec_j2000.ha, 0.4098571009604828, epsilon = 1e-10);
         assert_abs_diff_eq!(p.hadec_j2000.dec, -0.5235637661235192, epsilon = 1e-10);
-        assert_abs_diff_eq!(p.lmst, 1.4597728734531659, epsilon = 1e-10);
-        assert_abs_diff_eq!(p.lmst_j2000, 1.457162939693978, epsilon = 1e-10);
+        assert_abs_diff_eq!(p.lmst, 1.459772873932053, epsilon = 1e-10);
+        assert_abs_diff_eq!(p.lmst_j2000, 1.4571629401728934, epsilon = 1e-10);
         assert_abs_diff_eq!(p.array_latitude_j2000, -0.4661808254250147, epsilon = 1e-10);
         assert_abs_diff_ne!(p.array_latitude_j2000, MWA_LAT_RAD, epsilon = 1e-5);
 
@@ -504,4 +871,204 @@ mod tests {
         assert_abs_diff_eq!(ha_diff_arcmin, 9.344552279378359, epsilon = 1e-5);
         assert_abs_diff_eq!(dec_diff_arcmin, -0.12035370887056628, epsilon = 1e-5);
     }
+
+    #[test]
+    fn test_baseline_uvws_frames() {
+        let epoch = Epoch::from_gpst_seconds(1065880128.0);
+        let phase_centre = RADec::new_degrees(0.0, -27.0);
+        let p = precess_time(
+            MWA_LONG_RAD,
+            MWA_LAT_RAD,
+            phase_centre,
+            epoch,
+            Duration::from_total_nanoseconds(0),
+        );
+        let xyzs = [
+            XyzGeodetic::default(),
+            XyzGeodetic {
+                x: 100.0,
+                y: -200.0,
+                z: 50.0,
+            },
+        ];
+        let baselines = [(0, 1)];
+
+        let of_date = p.baseline_uvws(UvwFrame::OfDate, &xyzs, &baselines);
+        let expected = UVW::from_xyz(xyzs[0] - xyzs[1], phase_centre.to_hadec(p.lmst));
+        assert_abs_diff_eq!(of_date[0], expected, epsilon = 1e-10);
+
+        // Precession is a rotation, so only the direction of the UVW changes.
+        let j2000 = p.baseline_uvws(UvwFrame::J2000, &xyzs, &baselines);
+        let length = |uvw: UVW| (uvw.u * uvw.u + uvw.v * uvw.v + uvw.w * uvw.w).sqrt();
+        assert_abs_diff_eq!(length(j2000[0]), length(of_date[0]), epsilon = 1e-6);
+        assert_abs_diff_ne!(j2000[0], of_date[0], epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_precess_radec() {
+        let epoch = Epoch::from_gpst_seconds(1065880128.0);
+        let p = precess_time(
+            MWA_LONG_RAD,
+            MWA_LAT_RAD,
+            RADec::new_degrees(0.0, -27.0),
+            epoch,
+            Duration::from_total_nanoseconds(0),
+        );
+
+        // The zenith of the current epoch goes to the precessed zenith.
+        let precessed = p.precess_radec(&[
+            RADec::new(p.lmst, MWA_LAT_RAD),
+            RADec::new_degrees(60.0, -27.0),
+        ]);
+        assert_abs_diff_eq!(
+            precessed[0],
+            RADec::new(p.lmst_j2000, p.array_latitude_j2000),
+            epsilon = 1e-10
+        );
+
+        // Precession from the current epoch is the inverse of PAL's matrix
+        // from J2000.
+        let mut prenut = [[0.0; 3]; 3];
+        unsafe {
+            pal::palPrenut(
+                2000.0,
+                mjd_ut1_days(epoch, Duration::from_total_nanoseconds(0)),
+                prenut.as_mut_ptr(),
+            )
+        };
+        assert_abs_diff_eq!(
+            rotate(prenut, precessed[1]),
+            RADec::new_degrees(60.0, -27.0),
+            epsilon = 1e-10
+        );
+        assert_abs_diff_eq!(p.rotation_matrix()[0][1], prenut[1][0], epsilon = 1e-15);
+    }
+
+    #[test]
+    fn test_unprecess() {
+        let epoch = Epoch::from_gpst_seconds(1099334672.0);
+        let phase_centre = RADec::new_degrees(60.0, -27.0);
+        let p = precess_time(
+            MWA_LONG_RAD,
+            MWA_LAT_RAD,
+            phase_centre,
+            epoch,
+            Duration::from_f64(-0.3, Unit::Second),
+        );
+        let xyzs = [
+            XyzGeodetic::default(),
+            XyzGeodetic {
+                x: 100.0,
+                y: -200.0,
+                z: 50.0,
+            },
+            XyzGeodetic {
+                x: -1000.0,
+                y: 20.0,
+                z: 300.0,
+            },
+        ];
+
+        let precessed = p.precess_xyz_parallel(&xyzs);
+        let unprecessed = p.unprecess_xyz_parallel(&precessed);
+        for (unprecessed, xyz) in unprecessed.into_iter().zip(xyzs) {
+            assert_abs_diff_eq!(unprecessed, xyz, epsilon = 1e-9);
+        }
+
+        assert_abs_diff_eq!(p.unprecess_hadec(p.hadec_j2000), p.hadec, epsilon = 1e-10);
+
+        let baselines = [(0, 1), (0, 2), (1, 2)];
+        let j2000 = p.baseline_uvws(UvwFrame::J2000, &xyzs, &baselines);
+        let of_date = p.baseline_uvws(UvwFrame::OfDate, &xyzs, &baselines);
+        for (unprecessed, expected) in p.unprecess_uvws(&j2000).into_iter().zip(of_date) {
+            assert_abs_diff_eq!(unprecessed, expected, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_precession_matrix() {
+        // Expected values are from the ERFA test suite (t_erfa_c.c).
+        let epoch = Epoch::from_tt_seconds((50123.9999 - 15020.0) * 86400.0);
+        assert_abs_diff_eq!(jd_tt_days(epoch) - DJM0, 50123.9999, epsilon = 1e-9);
+        let expected = [
+            [
+                0.9999995505176007047,
+                0.8695404617348208406e-3,
+                0.3779735201865589104e-3,
+            ],
+            [
+                -0.8695404723772031414e-3,
+                0.9999996219496027161,
+                -0.1361752497080270143e-6,
+            ],
+            [
+                -0.3779734957034089490e-3,
+                -0.1924880847894457113e-6,
+                0.9999999285679971958,
+            ],
+        ];
+        let result = precession_matrix(epoch);
+        for (result, expected) in result.iter().zip(expected) {
+            for (result, expected) in result.iter().zip(expected) {
+                assert_abs_diff_eq!(*result, expected, epsilon = 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_precess_only() {
+        let j2000 = Epoch::from_tt_seconds((51544.5 - 15020.0) * 86400.0);
+        assert_abs_diff_eq!(jd_tt_days(j2000), 2451545.0, epsilon = 1e-9);
+        let b1950 = Epoch::from_tt_seconds((33281.9235 - 15020.0) * 86400.0);
+        let time = Epoch::from_gpst_seconds(1090008640.0);
+        for radec in [
+            RADec::new_degrees(0.0, -27.0),
+            RADec::new_degrees(359.9, 60.0),
+            RADec::new_degrees(150.0, -89.5),
+        ] {
+            // Only the frame bias (~20 mas) is applied at J2000.
+            let bias = precess_only(radec, j2000);
+            assert!(radec.separation(bias) < (0.03 / 3600_f64).to_radians());
+
+            // ~50 arcseconds per year for 14.5 years about the ecliptic pole,
+            // which is ~23 degrees from the celestial poles.
+            let precessed = precess_only(radec, time);
+            assert!(radec.separation(precessed) > 0.05_f64.to_radians());
+            let unprecessed = precess_only_inverse(precessed, time);
+            assert_abs_diff_eq!(radec.separation(unprecessed), 0.0, epsilon = 1e-10);
+
+            // The frame bias cancels when going between equinoxes.
+            let between = precess_between_equinoxes(bias, j2000, time);
+            assert_abs_diff_eq!(between.separation(precessed), 0.0, epsilon = 1e-10);
+            let between = precess_between_equinoxes(precessed, time, j2000);
+            assert_abs_diff_eq!(between.separation(bias), 0.0, epsilon = 1e-10);
+
+            // Going between two non-J2000 equinoxes and back again.
+            let b1950_radec = precess_between_equinoxes(radec, time, b1950);
+            let back = precess_between_equinoxes(b1950_radec, b1950, time);
+            assert_abs_diff_eq!(back.separation(radec), 0.0, epsilon = 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_nutate_only() {
+        let time = Epoch::from_gpst_seconds(1090008640.0);
+        let radec = RADec::new_degrees(60.0, -27.0);
+
+        // Nutation moves positions by no more than ~20 arcseconds.
+        let nutated = nutate_only(radec, time);
+        let separation = radec.separation(nutated);
+        assert!(separation > 0.0);
+        assert!(separation < (20.0 / 3600_f64).to_radians());
+        let unnutated = nutate_only_inverse(nutated, time);
+        assert_abs_diff_eq!(radec.separation(unnutated), 0.0, epsilon = 1e-10);
+
+        // Precession then nutation is the same as PAL's precession-nutation
+        // matrix from J2000.
+        let mut expected = [[0.0; 3]; 3];
+        unsafe { pal::palPrenut(2000.0, jd_tt_days(time) - DJM0, expected.as_mut_ptr()) };
+        let mut precessed = precess_only(radec, time);
+        precessed = nutate_only(precessed, time);
+        assert_abs_diff_eq!(precessed, rotate(expected, radec), epsilon = 1e-10);
+    }
 }
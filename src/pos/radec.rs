@@ -8,14 +8,19 @@ use std::f64::consts::{FRAC_PI_4, PI, TAU};
 
 use log::warn;
 
-use crate::sexagesimal::{degrees_to_sexagesimal_dms, degrees_to_sexagesimal_hms};
+use crate::sexagesimal::{
+    degrees_to_sexagesimal_dms, degrees_to_sexagesimal_hms, sexagesimal_dms_str_to_radians,
+    sexagesimal_hms_str_to_radians, SexagesimalError,
+};
 
+use super::erfa;
 use super::hadec::HADec;
 use super::lmn::LMN;
 
 /// A struct containing a Right Ascension and Declination. All units are in
 /// radians.
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(clippy::upper_case_acronyms)]
 pub struct RADec {
     /// Right ascension \[radians\]
@@ -39,6 +44,40 @@ impl RADec {
         Self::new(ra_deg.to_radians(), dec_deg.to_radians())
     }
 
+    /// Make a new [`RADec`] struct from sexagesimal strings, e.g. from a source
+    /// list. The RA is in "hours minutes seconds" and the Dec is in "degrees
+    /// minutes seconds"; see [`sexagesimal_hms_str_to_radians`] and
+    /// [`sexagesimal_dms_str_to_radians`] for the accepted formats.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use marlu::{sexagesimal::SexagesimalError, RADec};
+    /// # use approx::assert_abs_diff_eq;
+    /// # fn main() -> Result<(), SexagesimalError> {
+    /// let radec = RADec::from_sexagesimal("04h37m34.5s", "-47d15m09s")?;
+    /// assert_abs_diff_eq!(radec.ra.to_degrees(), 69.39375, epsilon = 1e-10);
+    /// assert_abs_diff_eq!(radec.dec.to_degrees(), -47.2525, epsilon = 1e-10);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return a [`SexagesimalError`] if either string can't be parsed, the
+    /// RA isn't in \[0h, 24h) or the Dec isn't in \[-90d, 90d\].
+    pub fn from_sexagesimal(ra: &str, dec: &str) -> Result<RADec, SexagesimalError> {
+        let ra_rad = sexagesimal_hms_str_to_radians(ra)?;
+        if !(0.0..TAU).contains(&ra_rad) {
+            return Err(SexagesimalError::RaOutOfRange(ra.to_string()));
+        }
+        let dec_rad = sexagesimal_dms_str_to_radians(dec)?;
+        if dec_rad.abs() > PI / 2.0 {
+            return Err(SexagesimalError::DecOutOfRange(dec.to_string()));
+        }
+        Ok(Self::new(ra_rad, dec_rad))
+    }
+
     /// Given a local sidereal time, make a new [`HADec`] struct from a [`RADec`].
     pub fn to_hadec(self, lst_rad: f64) -> HADec {
         HADec {
@@ -134,7 +173,58 @@ impl RADec {
     ///
     /// Uses ERFA.
     pub fn separation(&self, b: Self) -> f64 {
-        unsafe { erfa_sys::eraSeps(self.ra, self.dec, b.ra, b.dec) }
+        erfa::seps(self.ra, self.dec, b.ra, b.dec)
+    }
+
+    /// Calculate the bearing (position angle) of `other` from these
+    /// coordinates, i.e. the direction of the great circle from these
+    /// coordinates to `other`, measured from north towards east \[radians\].
+    /// The result is in the range \[-pi, pi\].
+    ///
+    /// Uses ERFA.
+    pub fn bearing_to(&self, other: Self) -> f64 {
+        erfa::pas(self.ra, self.dec, other.ra, other.dec)
+    }
+
+    /// Find the coordinates a fraction `f` of the way along the great circle
+    /// from these coordinates to `other`, e.g. an `f` of 0.5 gives the
+    /// midpoint. The RA of the result is in the range \[0, 2pi).
+    ///
+    /// There is no unique great circle between coincident or antipodal
+    /// coordinates; in these cases, these coordinates are returned.
+    ///
+    /// Uses ERFA.
+    pub fn intermediate_point(&self, other: Self, f: f64) -> RADec {
+        let sep = self.separation(other);
+        let sin_sep = sep.sin();
+        if sin_sep.abs() < 1e-12 {
+            return *self;
+        }
+        let a_weight = ((1.0 - f) * sep).sin() / sin_sep;
+        let b_weight = (f * sep).sin() / sin_sep;
+
+        let a = erfa::s2c(self.ra, self.dec);
+        let b = erfa::s2c(other.ra, other.dec);
+        let mut p = [0.0; 3];
+        for ((p, a), b) in p.iter_mut().zip(a).zip(b) {
+            *p = a_weight * a + b_weight * b;
+        }
+        let (ra, dec) = erfa::c2s(&p);
+        Self::new(erfa::anp(ra), dec)
+    }
+
+    /// Find the coordinates at the angular distance `distance` \[radians\]
+    /// along the great circle leaving these coordinates at the bearing
+    /// `bearing` (measured from north towards east) \[radians\]. This is the
+    /// inverse of [`RADec::bearing_to`] and [`RADec::separation`]. The RA of
+    /// the result is in the range \[0, 2pi).
+    pub fn offset(&self, bearing: f64, distance: f64) -> RADec {
+        let (s_dec, c_dec) = self.dec.sin_cos();
+        let (s_dist, c_dist) = distance.sin_cos();
+        let (s_bearing, c_bearing) = bearing.sin_cos();
+        let dec = (s_dec * c_dist + c_dec * s_dist * c_bearing).asin();
+        let d_ra = (s_bearing * s_dist * c_dec).atan2(c_dist - s_dec * dec.sin());
+        Self::new(erfa::anp(self.ra + d_ra), dec)
     }
 
     /// Given an [`mwalib::MetafitsContext`], make an [`Option<RADec>`] from the
@@ -226,6 +316,8 @@ impl approx::RelativeEq for RADec {
 
 #[cfg(test)]
 mod tests {
+    use std::f64::consts::FRAC_PI_2;
+
     use super::*;
     use approx::assert_abs_diff_eq;
 
@@ -315,6 +407,98 @@ mod tests {
         assert!(RADec::weighted_average(&arr, &[1.0]).is_none());
     }
 
+    #[test]
+    fn test_from_sexagesimal() {
+        let radec = RADec::from_sexagesimal("04h37m34.5s", "-47d15m09s").unwrap();
+        assert_abs_diff_eq!(
+            radec,
+            RADec::new_degrees(69.39375, -47.2525),
+            epsilon = 1e-10
+        );
+        // Parsing the formatted position gets the position back.
+        let ra = degrees_to_sexagesimal_hms(radec.ra.to_degrees());
+        let dec = degrees_to_sexagesimal_dms(radec.dec.to_degrees());
+        assert_abs_diff_eq!(
+            RADec::from_sexagesimal(&ra, &dec).unwrap(),
+            radec,
+            epsilon = 1e-8
+        );
+
+        assert!(matches!(
+            RADec::from_sexagesimal("24h00m00s", "0d"),
+            Err(SexagesimalError::RaOutOfRange(_))
+        ));
+        assert!(matches!(
+            RADec::from_sexagesimal("0h", "-90d00m01s"),
+            Err(SexagesimalError::DecOutOfRange(_))
+        ));
+        assert!(matches!(
+            RADec::from_sexagesimal("0h", "-47x"),
+            Err(SexagesimalError::ParseFloat(_))
+        ));
+    }
+
+    #[test]
+    fn test_bearing_to() {
+        let a = RADec::new_degrees(60.0, -30.0);
+        // North, south and west.
+        assert_abs_diff_eq!(a.bearing_to(RADec::new_degrees(60.0, -20.0)), 0.0);
+        // Due east along the equator.
+        assert_abs_diff_eq!(
+            RADec::new_degrees(60.0, 0.0).bearing_to(RADec::new_degrees(70.0, 0.0)),
+            FRAC_PI_2,
+            epsilon = 1e-10
+        );
+        assert_abs_diff_eq!(
+            a.bearing_to(RADec::new_degrees(60.0, -40.0)).abs(),
+            PI,
+            epsilon = 1e-10
+        );
+        assert!(a.bearing_to(RADec::new_degrees(50.0, -30.0)) < 0.0);
+        // Across the RA branch cut.
+        let b = RADec::new_degrees(359.0, 0.0);
+        assert_abs_diff_eq!(
+            b.bearing_to(RADec::new_degrees(1.0, 0.0)),
+            FRAC_PI_2,
+            epsilon = 1e-10
+        );
+    }
+
+    #[test]
+    fn test_intermediate_point() {
+        let a = RADec::new_degrees(350.0, -10.0);
+        let b = RADec::new_degrees(20.0, 15.0);
+        let sep = a.separation(b);
+        assert_abs_diff_eq!(a.intermediate_point(b, 0.0), a, epsilon = 1e-10);
+        assert_abs_diff_eq!(a.intermediate_point(b, 1.0), b, epsilon = 1e-10);
+        for f in [0.1, 0.25, 0.5, 0.9] {
+            let p = a.intermediate_point(b, f);
+            assert_abs_diff_eq!(a.separation(p), f * sep, epsilon = 1e-10);
+            assert_abs_diff_eq!(p.separation(b), (1.0 - f) * sep, epsilon = 1e-10);
+            assert!((0.0..TAU).contains(&p.ra));
+        }
+        // Along the equator.
+        let p =
+            RADec::new_degrees(10.0, 0.0).intermediate_point(RADec::new_degrees(30.0, 0.0), 0.5);
+        assert_abs_diff_eq!(p, RADec::new_degrees(20.0, 0.0), epsilon = 1e-10);
+        // Coincident points.
+        assert_abs_diff_eq!(a.intermediate_point(a, 0.5), a);
+    }
+
+    #[test]
+    fn test_offset() {
+        let a = RADec::new_degrees(350.0, -10.0);
+        let b = RADec::new_degrees(20.0, 15.0);
+        let c = a.offset(a.bearing_to(b), a.separation(b));
+        assert_abs_diff_eq!(c, b, epsilon = 1e-10);
+
+        let c = a.offset(0.0, 5_f64.to_radians());
+        assert_abs_diff_eq!(c, RADec::new_degrees(350.0, -5.0), epsilon = 1e-10);
+        // Over the pole.
+        let c = RADec::new_degrees(10.0, 80.0).offset(0.0, 20_f64.to_radians());
+        assert_abs_diff_eq!(c, RADec::new_degrees(190.0, 80.0), epsilon = 1e-10);
+    }
+
     #[test]
     fn test_display_radec() {
         let radec = RADec { ra: 0.0, dec: 0.0 };
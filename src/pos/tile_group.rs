@@ -0,0 +1,180 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Groups of tiles with their own reference positions and delays.
+//!
+//! The tiles of the extended (long baseline) MWA configurations are too far
+//! from the array centre for a single flat east-north-height frame; an outlying
+//! tile's local "up" is not the array centre's "up". Instead, each group of
+//! tiles has its own reference position, and the tiles' [`ENH`] positions are
+//! relative to that. Converting via geocentric coordinates then gives
+//! [`XyzGeodetic`] positions relative to the array centre that account for the
+//! curvature of the Earth, and can be used to calculate [UVW](crate::UVW)s as
+//! usual.
+
+use super::ErfaError;
+use crate::{LatLngHeight, XyzGeocentric, XyzGeodetic, ENH};
+
+/// A group of tiles whose [`ENH`] positions are relative to a common reference
+/// position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileGroup {
+    /// The name of the group.
+    pub name: String,
+
+    /// The Earth position that the [`ENH`] positions of the group's tiles are
+    /// relative to.
+    pub reference_pos: LatLngHeight,
+
+    /// A signal delay common to all of the group's tiles \[seconds\], e.g. from
+    /// a long fibre run to the correlator. See
+    /// [`crate::corrections::correct_delays`].
+    pub delay_s: f64,
+}
+
+impl TileGroup {
+    /// Convert an [`ENH`] position relative to this group's reference position
+    /// into an [`XyzGeodetic`] position relative to `array_pos`.
+    ///
+    /// # Errors
+    ///
+    /// Can return an [`ErfaError`] if the reference positions can't be
+    /// converted to geocentric coordinates.
+    pub fn enh_to_xyz(&self, enh: ENH, array_pos: LatLngHeight) -> Result<XyzGeodetic, ErfaError> {
+        Ok(tile_group_xyzs(&[enh], &[0], std::slice::from_ref(self), array_pos)?[0])
+    }
+}
+
+/// Get the [`XyzGeodetic`] position of each tile relative to `array_pos`.
+///
+/// - `tile_enhs` - the position of each tile, relative to the reference
+///   position of its group
+/// - `tile_group_idxs` - the index into `tile_groups` of each tile's group
+/// - `tile_groups` - the groups of tiles
+/// - `array_pos` - the position of the array, which [`XyzGeodetic`] positions
+///   are relative to
+///
+/// If all groups have the array position as their reference position, this is
+/// the same as [`ENH::to_xyz`] with the array's latitude.
+///
+/// # Errors
+///
+/// Can return an [`ErfaError`] if the reference positions can't be converted
+/// to geocentric coordinates.
+pub fn tile_group_xyzs(
+    tile_enhs: &[ENH],
+    tile_group_idxs: &[usize],
+    tile_groups: &[TileGroup],
+    array_pos: LatLngHeight,
+) -> Result<Vec<XyzGeodetic>, ErfaError> {
+    let array_vector = XyzGeocentric::get_geocentric_vector(array_pos)?;
+    let (array_sin_long, array_cos_long) = array_pos.longitude_rad.sin_cos();
+    let group_vectors = tile_groups
+        .iter()
+        .map(|group| XyzGeocentric::get_geocentric_vector(group.reference_pos))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(tile_enhs
+        .iter()
+        .zip(tile_group_idxs)
+        .map(|(&enh, &group_idx)| {
+            let reference_pos = tile_groups[group_idx].reference_pos;
+            let (sin_long, cos_long) = reference_pos.longitude_rad.sin_cos();
+            enh.to_xyz(reference_pos.latitude_rad)
+                .to_geocentric_inner(group_vectors[group_idx], sin_long, cos_long)
+                .to_geodetic_inner(array_vector, array_sin_long, array_cos_long)
+        })
+        .collect())
+}
+
+/// Get the delay of each tile \[seconds\] from the delay of its group.
+pub fn tile_group_delays(tile_group_idxs: &[usize], tile_groups: &[TileGroup]) -> Vec<f64> {
+    tile_group_idxs
+        .iter()
+        .map(|&group_idx| tile_groups[group_idx].delay_s)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_tile_group_xyzs_at_array_pos() {
+        let array_pos = LatLngHeight::new_mwa();
+        let group = TileGroup {
+            name: "core".into(),
+            reference_pos: array_pos,
+            delay_s: 0.0,
+        };
+        let enhs = [
+            ENH {
+                e: 100.0,
+                n: -20.0,
+                h: 1.0,
+            },
+            ENH {
+                e: -300.0,
+                n: 50.0,
+                h: -2.0,
+            },
+        ];
+        let xyzs = tile_group_xyzs(&enhs, &[0, 0], &[group], array_pos).unwrap();
+        for (xyz, enh) in xyzs.into_iter().zip(enhs) {
+            assert_abs_diff_eq!(xyz, enh.to_xyz(array_pos.latitude_rad), epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_tile_group_xyzs_long_baseline() {
+        let array_pos = LatLngHeight::new_mwa();
+        // An outlying group ~50 km west of the array centre.
+        let reference_pos = LatLngHeight {
+            longitude_rad: array_pos.longitude_rad - 0.5_f64.to_radians(),
+            ..array_pos
+        };
+        let groups = [
+            TileGroup {
+                name: "core".into(),
+                reference_pos: array_pos,
+                delay_s: 0.0,
+            },
+            TileGroup {
+                name: "outlier".into(),
+                reference_pos,
+                delay_s: 2e-4,
+            },
+        ];
+        let xyzs = tile_group_xyzs(&[ENH::default(); 2], &[0, 1], &groups, array_pos).unwrap();
+        assert_abs_diff_eq!(xyzs[0], XyzGeodetic::default(), epsilon = 1e-6);
+
+        // The baseline is the chord between the two reference positions.
+        let expected = reference_pos
+            .to_geocentric_wgs84()
+            .unwrap()
+            .to_geodetic(array_pos)
+            .unwrap();
+        assert_abs_diff_eq!(xyzs[1], expected, epsilon = 1e-6);
+        let chord_m = {
+            let a = array_pos.to_geocentric_wgs84().unwrap();
+            let b = reference_pos.to_geocentric_wgs84().unwrap();
+            ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+        };
+        let length_m = (xyzs[1].x.powi(2) + xyzs[1].y.powi(2) + xyzs[1].z.powi(2)).sqrt();
+        assert_abs_diff_eq!(length_m, chord_m, epsilon = 1e-6);
+
+        // A flat array would put the outlier at the array centre's height, but
+        // the Earth curves away by ~d^2 / 2R (~200 m over 50 km).
+        let enh = xyzs[1].to_enh(array_pos.latitude_rad);
+        assert!(enh.e < -40e3);
+        assert!(enh.h < -100.0 && enh.h > -300.0, "{}", enh.h);
+
+        assert_eq!(
+            tile_group_delays(&[0, 1, 1], &groups),
+            vec![0.0, 2e-4, 2e-4]
+        );
+    }
+}
@@ -9,7 +9,9 @@ use super::xyz::XyzGeodetic;
 
 /// The (u,v,w) coordinates of a baseline. All units are in terms of wavelength,
 /// with units of metres.
+#[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(clippy::upper_case_acronyms)]
 pub struct UVW {
     /// u coordinate \[meters\]
@@ -47,6 +49,18 @@ impl UVW {
             w: c_dec * c_ha * xyz.x - c_dec * s_ha * xyz.y + s_dec * xyz.z,
         }
     }
+
+    /// Convert a [`UVW`] back to an [`XyzGeodetic`], given the phase centre.
+    /// This is the inverse of [`UVW::from_xyz`].
+    pub fn to_xyz(self, phase_centre: HADec) -> XyzGeodetic {
+        let (s_ha, c_ha) = phase_centre.ha.sin_cos();
+        let (s_dec, c_dec) = phase_centre.dec.sin_cos();
+        XyzGeodetic {
+            x: s_ha * self.u - s_dec * c_ha * self.v + c_dec * c_ha * self.w,
+            y: c_ha * self.u + s_dec * s_ha * self.v - c_dec * s_ha * self.w,
+            z: c_dec * self.v + s_dec * self.w,
+        }
+    }
 }
 
 impl std::ops::Sub<UVW> for UVW {
@@ -163,4 +177,21 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_uvw_to_xyz() {
+        let xyz = XyzGeodetic {
+            x: 100.0,
+            y: -200.0,
+            z: 50.0,
+        };
+        for phase_centre in [
+            HADec::new(0.0, 0.0),
+            HADec::new_degrees(30.0, -27.0),
+            HADec::new_degrees(-100.0, 80.0),
+        ] {
+            let uvw = UVW::from_xyz(xyz, phase_centre);
+            assert_abs_diff_eq!(uvw.to_xyz(phase_centre), xyz, epsilon = 1e-10);
+        }
+    }
 }
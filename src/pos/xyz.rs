@@ -17,7 +17,7 @@
 
 use rayon::prelude::*;
 
-use super::ErfaError;
+use super::{erfa, ErfaError};
 use crate::{
     constants::MWA_LAT_RAD,
     math::{baseline_to_tiles, cross_correlation_baseline_to_tiles},
@@ -30,7 +30,9 @@ use crate::{
 /// This coordinate system is discussed at length in Interferometry and
 /// Synthesis in Radio Astronomy, Third Edition, Section 4: Geometrical
 /// Relationships, Polarimetry, and the Measurement Equation.
+#[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct XyzGeodetic {
     /// x-coordinate \[meters\]
     pub x: f64,
@@ -105,6 +107,43 @@ impl XyzGeodetic {
         self.to_geocentric(LatLngHeight::new_mwa())
     }
 
+    /// Rotate this [`XyzGeodetic`] by a rotation matrix, i.e. `rotation * xyz`.
+    pub fn rotate(self, rotation: &[[f64; 3]; 3]) -> XyzGeodetic {
+        XyzGeodetic {
+            x: rotation[0][0] * self.x + rotation[0][1] * self.y + rotation[0][2] * self.z,
+            y: rotation[1][0] * self.x + rotation[1][1] * self.y + rotation[1][2] * self.z,
+            z: rotation[2][0] * self.x + rotation[2][1] * self.y + rotation[2][2] * self.z,
+        }
+    }
+
+    /// Form the [`XyzGeodetic`] baseline of every pair of tiles, i.e. `xyzs[i] -
+    /// xyzs[j]` for `i < j`, or `i <= j` if `include_autos` is true. The
+    /// baselines are in the same order as the [`UVW`]s of
+    /// [`xyzs_to_cross_uvws`] or [`xyzs_to_uvws`].
+    pub fn to_baselines(xyzs: &[XyzGeodetic], include_autos: bool) -> Vec<XyzGeodetic> {
+        let num_tiles = xyzs.len();
+        let offset = usize::from(!include_autos);
+        let mut baselines = Vec::with_capacity(num_tiles * (num_tiles + 1) / 2);
+        for (i, &xyz1) in xyzs.iter().enumerate() {
+            for &xyz2 in xyzs.iter().skip(i + offset) {
+                baselines.push(xyz1 - xyz2);
+            }
+        }
+        baselines
+    }
+
+    /// Form the [`XyzGeodetic`] baselines between the given pairs of tile
+    /// indices, e.g. [`crate::VisContext::sel_baselines`].
+    pub fn to_selected_baselines(
+        xyzs: &[XyzGeodetic],
+        baselines: &[(usize, usize)],
+    ) -> Vec<XyzGeodetic> {
+        baselines
+            .iter()
+            .map(|&(ant1, ant2)| xyzs[ant1] - xyzs[ant2])
+            .collect()
+    }
+
     /// For each tile listed in an [`mwalib::MetafitsContext`], calculate a
     /// [`XyzGeodetic`] coordinate. The tile coordinates are in the same order
     /// as the metafits' antennas.
@@ -134,6 +173,23 @@ impl XyzGeodetic {
     }
 }
 
+/// Rotate every [`XyzGeodetic`] by a rotation matrix. See
+/// [`XyzGeodetic::rotate`].
+pub fn rotate_xyzs(xyzs: &[XyzGeodetic], rotation: &[[f64; 3]; 3]) -> Vec<XyzGeodetic> {
+    xyzs.iter().map(|xyz| xyz.rotate(rotation)).collect()
+}
+
+/// Convert [`XyzGeodetic`] baselines (e.g. from [`XyzGeodetic::to_baselines`])
+/// to [`UVW`]s, given the phase centre.
+pub fn baselines_to_uvws(baselines: &[XyzGeodetic], phase_centre: HADec) -> Vec<UVW> {
+    let (s_ha, c_ha) = phase_centre.ha.sin_cos();
+    let (s_dec, c_dec) = phase_centre.dec.sin_cos();
+    baselines
+        .iter()
+        .map(|&xyz| UVW::from_xyz_inner(xyz, s_ha, c_ha, s_dec, c_dec))
+        .collect()
+}
+
 /// Convert [`XyzGeodetic`] tile coordinates to [`UVW`] baseline coordinates
 /// without having to form [`XyzGeodetic`] baselines first.
 pub fn xyzs_to_uvws(xyzs: &[XyzGeodetic], phase_centre: HADec) -> Vec<UVW> {
@@ -284,6 +340,7 @@ impl approx::RelativeEq for XyzGeodetic {
 /// Synthesis in Radio Astronomy, Third Edition, Section 4: Geometrical
 /// Relationships, Polarimetry, and the Measurement Equation.
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct XyzGeocentric {
     /// x-coordinate \[meters\]
     pub x: f64,
@@ -297,24 +354,18 @@ impl XyzGeocentric {
     /// Get a geocentric coordinate vector with the given geodetic coordinates
     /// (longitude, latitude and height). The ellipsoid model is WGS84.
     pub fn get_geocentric_vector(earth_pos: LatLngHeight) -> Result<XyzGeocentric, ErfaError> {
-        let mut geocentric_vector: [f64; 3] = [0.0; 3];
-        let status = unsafe {
-            erfa_sys::eraGd2gc(
-                erfa_sys::ERFA_WGS84,           // ellipsoid identifier (Note 1)
-                earth_pos.longitude_rad,        // longitude (radians, east +ve)
-                earth_pos.latitude_rad,         // latitude (geodetic, radians, Note 3)
-                earth_pos.height_metres,        // height above ellipsoid (geodetic, Notes 2,3)
-                geocentric_vector.as_mut_ptr(), // geocentric vector (Note 2)
-            )
-        };
-        if status != 0 {
-            return Err(ErfaError {
-                source_file: file!(),
-                source_line: line!(),
-                status,
-                function: "eraGd2gc",
-            });
-        }
+        let geocentric_vector = erfa::gd2gc(
+            erfa::WGS84,             // ellipsoid identifier (Note 1)
+            earth_pos.longitude_rad, // longitude (radians, east +ve)
+            earth_pos.latitude_rad,  // latitude (geodetic, radians, Note 3)
+            earth_pos.height_metres, // height above ellipsoid (geodetic, Notes 2,3)
+        )
+        .map_err(|status| ErfaError {
+            source_file: file!(),
+            source_line: line!(),
+            status,
+            function: "eraGd2gc",
+        })?;
         Ok(XyzGeocentric {
             x: geocentric_vector[0],
             y: geocentric_vector[1],
@@ -376,29 +427,20 @@ impl XyzGeocentric {
     /// specified [`Ellipsoid`]. If in doubt, use [`Ellipsoid::WGS84`] (i.e. the
     /// latest one that's typically used).
     pub fn to_earth(self, ellipsoid: Ellipsoid) -> Result<LatLngHeight, ErfaError> {
-        let mut earth = LatLngHeight {
-            longitude_rad: 0.0,
-            latitude_rad: 0.0,
-            height_metres: 0.0,
-        };
-        unsafe {
-            let status = erfa_sys::eraGc2gd(
-                ellipsoid as i32,
-                [self.x, self.y, self.z].as_mut_ptr(),
-                &mut earth.longitude_rad,
-                &mut earth.latitude_rad,
-                &mut earth.height_metres,
-            );
-            if status != 0 {
-                return Err(ErfaError {
+        let (longitude_rad, latitude_rad, height_metres) =
+            erfa::gc2gd(ellipsoid as i32, &[self.x, self.y, self.z]).map_err(|status| {
+                ErfaError {
                     source_file: file!(),
                     source_line: line!(),
                     status,
                     function: "eraGc2gd",
-                });
-            }
-        }
-        Ok(earth)
+                }
+            })?;
+        Ok(LatLngHeight {
+            longitude_rad,
+            latitude_rad,
+            height_metres,
+        })
     }
 
     /// Convert a [`XyzGeocentric`] coordinate to [`LatLngHeight`] using the
@@ -623,6 +665,63 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_to_baselines() {
+        let xyzs: Vec<XyzGeodetic> = (0..4)
+            .map(|i| XyzGeodetic {
+                x: i as f64 * 10.0,
+                y: (i * i) as f64,
+                z: -(i as f64),
+            })
+            .collect();
+        let phase = HADec::new(6.0163, -0.453121);
+
+        let cross = XyzGeodetic::to_baselines(&xyzs, false);
+        assert_eq!(cross.len(), 6);
+        assert_abs_diff_eq!(cross[4], xyzs[1] - xyzs[3]);
+        assert_abs_diff_eq!(
+            Array1::from(baselines_to_uvws(&cross, phase)),
+            Array1::from(xyzs_to_cross_uvws(&xyzs, phase)),
+            epsilon = 1e-10
+        );
+
+        let all = XyzGeodetic::to_baselines(&xyzs, true);
+        assert_eq!(all.len(), 10);
+        assert_abs_diff_eq!(all[0], XyzGeodetic::default());
+        assert_abs_diff_eq!(
+            Array1::from(baselines_to_uvws(&all, phase)),
+            Array1::from(xyzs_to_uvws(&xyzs, phase)),
+            epsilon = 1e-10
+        );
+
+        let selected = XyzGeodetic::to_selected_baselines(&xyzs, &[(1, 3), (2, 2)]);
+        assert_abs_diff_eq!(
+            Array1::from(selected),
+            Array1::from(vec![cross[4], XyzGeodetic::default()])
+        );
+    }
+
+    #[test]
+    fn test_rotate_xyzs() {
+        let xyz = XyzGeodetic {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+        };
+        // A rotation of 90 degrees about z.
+        let rotation = [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+        let expected = XyzGeodetic {
+            x: -2.0,
+            y: 1.0,
+            z: 3.0,
+        };
+        assert_abs_diff_eq!(xyz.rotate(&rotation), expected);
+        assert_abs_diff_eq!(
+            Array1::from(rotate_xyzs(&[xyz, xyz], &rotation)),
+            Array1::from_elem(2, expected)
+        );
+    }
+
     #[test]
     fn xyzs_to_cross_uvws_parallel_test() {
         let xyzs = vec![
@@ -0,0 +1,20 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The most commonly used Marlu types, for glob importing.
+//!
+//! ```
+//! use marlu::prelude::*;
+//! ```
+
+pub use crate::{
+    c32, c64, Complex, HADec, History, Jones, LatLngHeight, MwaObsContext, ObsContext, RADec,
+    VisContext, VisRead, VisSelection, VisWrite, XyzGeodetic, UVW,
+};
+
+#[cfg(feature = "cfitsio")]
+pub use crate::{UvfitsReader, UvfitsWriter};
+
+#[cfg(feature = "ms")]
+pub use crate::{MeasurementSetReader, MeasurementSetWriter};
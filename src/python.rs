@@ -0,0 +1,559 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Python bindings, enabled with the `python` feature.
+//!
+//! The extension module is built with [maturin](https://www.maturin.rs), e.g.
+//! `maturin develop --release`, which uses the settings in `pyproject.toml`.
+//!
+//! Visibilities are passed as `numpy` arrays; Jones matrices are `complex64`
+//! arrays with dimensions `[timestep][channel][baseline][pol]`, with pols in
+//! the order XX, XY, YX, YY, and weights are `float32` arrays with dimensions
+//! `[timestep][channel][baseline]`.
+
+// This module should only be accessed by lib.rs if the "python" feature is
+// enabled, so we don't need to have conditional compilation here.
+
+use hifitime::{Duration, Epoch, Unit};
+use numpy::{
+    Complex64, PyArray1, PyArray2, PyArrayMethods, PyReadonlyArrayDyn, PyUntypedArrayMethods,
+};
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{AveragingPolicy, Jones, LatLngHeight, RADec, VisContext, VisUnit, UVW};
+
+cfg_if::cfg_if! {
+    if #[cfg(any(feature = "cfitsio", feature = "ms"))] {
+        use std::path::PathBuf;
+
+        use ndarray::Array3;
+        use numpy::Complex32;
+        use pyo3::exceptions::PyIOError;
+
+        use crate::{ObsContext, VisWrite, VisWriteFormat, VisWriter, ENH};
+    }
+}
+
+/// Equatorial coordinates (right ascension and declination) \[radians\].
+#[pyclass(name = "RADec", module = "marlu")]
+#[derive(Clone, Copy)]
+pub struct PyRADec(pub RADec);
+
+#[pymethods]
+impl PyRADec {
+    #[new]
+    fn new(ra_rad: f64, dec_rad: f64) -> Self {
+        Self(RADec::new(ra_rad, dec_rad))
+    }
+
+    #[staticmethod]
+    fn from_degrees(ra_deg: f64, dec_deg: f64) -> Self {
+        Self(RADec::new_degrees(ra_deg, dec_deg))
+    }
+
+    #[getter]
+    fn ra(&self) -> f64 {
+        self.0.ra
+    }
+
+    #[getter]
+    fn dec(&self) -> f64 {
+        self.0.dec
+    }
+
+    /// The (l, m, n) direction cosines of this direction relative to
+    /// `phase_centre`.
+    // pyo3 methods can't take `self` by value.
+    #[allow(clippy::wrong_self_convention)]
+    fn to_lmn(&self, phase_centre: &PyRADec) -> (f64, f64, f64) {
+        let lmn = self.0.to_lmn(phase_centre.0);
+        (lmn.l, lmn.m, lmn.n)
+    }
+
+    /// The angular distance to `other` \[radians\].
+    fn separation(&self, other: &PyRADec) -> f64 {
+        self.0.separation(other.0)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("RADec(ra={}, dec={})", self.0.ra, self.0.dec)
+    }
+}
+
+/// The (u, v, w) coordinates of a baseline \[metres\].
+#[pyclass(name = "UVW", module = "marlu")]
+#[derive(Clone, Copy)]
+pub struct PyUVW(pub UVW);
+
+#[pymethods]
+impl PyUVW {
+    #[new]
+    fn new(u: f64, v: f64, w: f64) -> Self {
+        Self(UVW { u, v, w })
+    }
+
+    #[getter]
+    fn u(&self) -> f64 {
+        self.0.u
+    }
+
+    #[getter]
+    fn v(&self) -> f64 {
+        self.0.v
+    }
+
+    #[getter]
+    fn w(&self) -> f64 {
+        self.0.w
+    }
+
+    fn __repr__(&self) -> String {
+        format!("UVW(u={}, v={}, w={})", self.0.u, self.0.v, self.0.w)
+    }
+}
+
+/// An earth position: longitude, latitude \[radians\] and height \[metres\].
+#[pyclass(name = "LatLngHeight", module = "marlu")]
+#[derive(Clone, Copy)]
+pub struct PyLatLngHeight(pub LatLngHeight);
+
+#[pymethods]
+impl PyLatLngHeight {
+    #[new]
+    fn new(longitude_rad: f64, latitude_rad: f64, height_metres: f64) -> Self {
+        Self(LatLngHeight {
+            longitude_rad,
+            latitude_rad,
+            height_metres,
+        })
+    }
+
+    /// The position of the MWA.
+    #[staticmethod]
+    fn mwa() -> Self {
+        Self(LatLngHeight::new_mwa())
+    }
+
+    #[getter]
+    fn longitude_rad(&self) -> f64 {
+        self.0.longitude_rad
+    }
+
+    #[getter]
+    fn latitude_rad(&self) -> f64 {
+        self.0.latitude_rad
+    }
+
+    #[getter]
+    fn height_metres(&self) -> f64 {
+        self.0.height_metres
+    }
+}
+
+/// A 2x2 Jones matrix, with elements in the order XX, XY, YX, YY.
+#[pyclass(name = "Jones", module = "marlu")]
+#[derive(Clone, Copy)]
+pub struct PyJones(pub Jones<f64>);
+
+#[pymethods]
+impl PyJones {
+    #[new]
+    fn new(elements: [Complex64; 4]) -> Self {
+        Self(Jones::from(elements))
+    }
+
+    #[staticmethod]
+    fn identity() -> Self {
+        Self(Jones::identity())
+    }
+
+    /// Create a Jones matrix from a `complex128` array with 4 elements, e.g.
+    /// with shape (2, 2).
+    #[staticmethod]
+    #[allow(clippy::needless_pass_by_value)]
+    fn from_numpy(array: PyReadonlyArrayDyn<'_, Complex64>) -> PyResult<Self> {
+        let elements: Vec<Complex64> = array.as_array().iter().copied().collect();
+        let elements: [Complex64; 4] = elements.try_into().map_err(|_| {
+            PyValueError::new_err(format!(
+                "expected an array with 4 elements, received shape {:?}",
+                array.shape()
+            ))
+        })?;
+        Ok(Self(Jones::from(elements)))
+    }
+
+    /// This Jones matrix as a `complex128` array with shape (2, 2).
+    #[allow(clippy::wrong_self_convention)]
+    fn to_numpy<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<Complex64>>> {
+        PyArray1::from_slice(py, self.0.as_slice()).reshape([2, 2])
+    }
+
+    /// The conjugate transpose.
+    fn h(&self) -> Self {
+        Self(self.0.h())
+    }
+
+    fn inv(&self) -> Self {
+        Self(self.0.inv())
+    }
+
+    fn __mul__(&self, other: &PyJones) -> Self {
+        Self(self.0 * other.0)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Jones({:?})", self.0.as_slice())
+    }
+}
+
+/// Describes the timesteps, channels and baselines of visibilities.
+#[pyclass(name = "VisContext", module = "marlu")]
+#[derive(Clone)]
+pub struct PyVisContext(pub VisContext);
+
+#[pymethods]
+impl PyVisContext {
+    #[new]
+    #[pyo3(signature = (
+        num_sel_timesteps,
+        start_timestamp_gps_s,
+        int_time_s,
+        num_sel_chans,
+        start_freq_hz,
+        freq_resolution_hz,
+        sel_baselines,
+        avg_time = 1,
+        avg_freq = 1,
+        num_vis_pols = 4,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        num_sel_timesteps: usize,
+        start_timestamp_gps_s: f64,
+        int_time_s: f64,
+        num_sel_chans: usize,
+        start_freq_hz: f64,
+        freq_resolution_hz: f64,
+        sel_baselines: Vec<(usize, usize)>,
+        avg_time: usize,
+        avg_freq: usize,
+        num_vis_pols: usize,
+    ) -> Self {
+        Self(VisContext {
+            num_sel_timesteps,
+            start_timestamp: Epoch::from_gpst_seconds(start_timestamp_gps_s),
+            int_time: Duration::from_f64(int_time_s, Unit::Second),
+            num_sel_chans,
+            start_freq_hz,
+            freq_resolution_hz,
+            sel_baselines,
+            avg_time,
+            avg_freq,
+            num_vis_pols,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        })
+    }
+
+    #[getter]
+    fn num_sel_timesteps(&self) -> usize {
+        self.0.num_sel_timesteps
+    }
+
+    #[getter]
+    fn num_sel_chans(&self) -> usize {
+        self.0.num_sel_chans
+    }
+
+    #[getter]
+    fn sel_baselines(&self) -> Vec<(usize, usize)> {
+        self.0.sel_baselines.clone()
+    }
+
+    /// The shape of the selected visibilities, (timesteps, channels,
+    /// baselines).
+    fn sel_dims(&self) -> (usize, usize, usize) {
+        self.0.sel_dims()
+    }
+
+    /// The shape of the visibilities after averaging.
+    fn avg_dims(&self) -> (usize, usize, usize) {
+        self.0.avg_dims()
+    }
+
+    /// The centre frequency of each selected channel \[Hz\].
+    fn frequencies_hz(&self) -> Vec<f64> {
+        self.0.frequencies_hz()
+    }
+
+    /// The centre frequency of each averaged channel \[Hz\].
+    fn avg_frequencies_hz(&self) -> Vec<f64> {
+        self.0.avg_frequencies_hz()
+    }
+}
+
+/// Copy a `complex64` array with dimensions `[timestep][channel][baseline][pol]`
+/// into a [`Jones`] array.
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
+fn jones_from_numpy(array: &PyReadonlyArrayDyn<'_, Complex32>) -> PyResult<Array3<Jones<f32>>> {
+    let shape = array.shape();
+    if shape.len() != 4 || shape[3] != 4 {
+        return Err(PyValueError::new_err(format!(
+            "expected a jones array with shape (timesteps, channels, baselines, 4), received {:?}",
+            shape
+        )));
+    }
+    let dims = (shape[0], shape[1], shape[2]);
+    let jones: Vec<Jones<f32>> = array
+        .as_array()
+        .iter()
+        .copied()
+        .collect::<Vec<_>>()
+        .chunks_exact(4)
+        .map(|pols| Jones::from([pols[0], pols[1], pols[2], pols[3]]))
+        .collect();
+    Array3::from_shape_vec(dims, jones).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Copy a `float32` array with dimensions `[timestep][channel][baseline]`.
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
+fn weights_from_numpy(array: &PyReadonlyArrayDyn<'_, f32>) -> PyResult<Array3<f32>> {
+    let shape = array.shape();
+    if shape.len() != 3 {
+        return Err(PyValueError::new_err(format!(
+            "expected a weight array with shape (timesteps, channels, baselines), received {:?}",
+            shape
+        )));
+    }
+    let weights: Vec<f32> = array.as_array().iter().copied().collect();
+    Array3::from_shape_vec((shape[0], shape[1], shape[2]), weights)
+        .map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Create a writer of `format` with [`VisWriter::from_path`].
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
+#[allow(clippy::too_many_arguments)]
+fn new_writer(
+    format: VisWriteFormat,
+    path: PathBuf,
+    vis_ctx: &PyVisContext,
+    phase_centre: &PyRADec,
+    array_pos: &PyLatLngHeight,
+    ant_names: Vec<String>,
+    ant_positions_enh: Vec<(f64, f64, f64)>,
+    dut1_s: f64,
+) -> PyResult<Box<dyn VisWrite>> {
+    if VisWriteFormat::from_path(&path) != Some(format) {
+        return Err(PyValueError::new_err(format!(
+            "{} is not a {} path",
+            path.display(),
+            format
+        )));
+    }
+    let vis_ctx = &vis_ctx.0;
+    let obs_ctx = ObsContext {
+        sched_start_timestamp: vis_ctx.start_timestamp,
+        sched_duration: vis_ctx.int_time * vis_ctx.num_sel_timesteps as i64,
+        name: None,
+        field_name: None,
+        project_id: None,
+        observer: None,
+        phase_centre: phase_centre.0,
+        pointing_centre: None,
+        array_pos: array_pos.0,
+        telescope_name: "MWA".into(),
+        instrument: "MWA".into(),
+        ant_positions_enh: ant_positions_enh
+            .into_iter()
+            .map(|(e, n, h)| ENH { e, n, h })
+            .collect(),
+        ant_names,
+        ant_flags: vec![],
+        beams: vec![],
+    };
+    VisWriter::from_path(
+        &path,
+        vis_ctx,
+        &obs_ctx,
+        Duration::from_f64(dut1_s, Unit::Second),
+        None,
+    )
+    .map_err(|e| PyIOError::new_err(e.to_string()))
+}
+
+/// Write a chunk of visibilities with `writer`.
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
+fn write_vis(
+    writer: &mut dyn VisWrite,
+    jones: PyReadonlyArrayDyn<'_, Complex32>,
+    weights: PyReadonlyArrayDyn<'_, f32>,
+    vis_ctx: &PyVisContext,
+    draw_progress: bool,
+) -> PyResult<()> {
+    let jones = jones_from_numpy(&jones)?;
+    let weights = weights_from_numpy(&weights)?;
+    writer
+        .write_vis(jones.view(), weights.view(), &vis_ctx.0, draw_progress)
+        .map_err(|e| PyIOError::new_err(e.to_string()))
+}
+
+/// Writes visibilities to a uvfits file.
+#[cfg(feature = "cfitsio")]
+#[pyclass(name = "UvfitsWriter", module = "marlu", unsendable)]
+pub struct PyUvfitsWriter(Box<dyn VisWrite>);
+
+#[cfg(feature = "cfitsio")]
+#[pymethods]
+impl PyUvfitsWriter {
+    #[new]
+    #[pyo3(signature = (path, vis_ctx, phase_centre, array_pos, ant_names, ant_positions_enh, dut1_s = 0.0))]
+    fn new(
+        path: PathBuf,
+        vis_ctx: &PyVisContext,
+        phase_centre: &PyRADec,
+        array_pos: &PyLatLngHeight,
+        ant_names: Vec<String>,
+        ant_positions_enh: Vec<(f64, f64, f64)>,
+        dut1_s: f64,
+    ) -> PyResult<Self> {
+        new_writer(
+            VisWriteFormat::Uvfits,
+            path,
+            vis_ctx,
+            phase_centre,
+            array_pos,
+            ant_names,
+            ant_positions_enh,
+            dut1_s,
+        )
+        .map(Self)
+    }
+
+    #[pyo3(signature = (jones, weights, vis_ctx, draw_progress = false))]
+    fn write_vis(
+        &mut self,
+        jones: PyReadonlyArrayDyn<'_, Complex32>,
+        weights: PyReadonlyArrayDyn<'_, f32>,
+        vis_ctx: &PyVisContext,
+        draw_progress: bool,
+    ) -> PyResult<()> {
+        write_vis(self.0.as_mut(), jones, weights, vis_ctx, draw_progress)
+    }
+
+    fn finalise(&mut self) -> PyResult<()> {
+        self.0
+            .finalise()
+            .map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+}
+
+/// Writes visibilities to a CASA measurement set.
+#[cfg(feature = "ms")]
+#[pyclass(name = "MeasurementSetWriter", module = "marlu", unsendable)]
+pub struct PyMeasurementSetWriter(Box<dyn VisWrite>);
+
+#[cfg(feature = "ms")]
+#[pymethods]
+impl PyMeasurementSetWriter {
+    #[new]
+    #[pyo3(signature = (path, vis_ctx, phase_centre, array_pos, ant_names, ant_positions_enh, dut1_s = 0.0))]
+    fn new(
+        path: PathBuf,
+        vis_ctx: &PyVisContext,
+        phase_centre: &PyRADec,
+        array_pos: &PyLatLngHeight,
+        ant_names: Vec<String>,
+        ant_positions_enh: Vec<(f64, f64, f64)>,
+        dut1_s: f64,
+    ) -> PyResult<Self> {
+        new_writer(
+            VisWriteFormat::MeasurementSet,
+            path,
+            vis_ctx,
+            phase_centre,
+            array_pos,
+            ant_names,
+            ant_positions_enh,
+            dut1_s,
+        )
+        .map(Self)
+    }
+
+    #[pyo3(signature = (jones, weights, vis_ctx, draw_progress = false))]
+    fn write_vis(
+        &mut self,
+        jones: PyReadonlyArrayDyn<'_, Complex32>,
+        weights: PyReadonlyArrayDyn<'_, f32>,
+        vis_ctx: &PyVisContext,
+        draw_progress: bool,
+    ) -> PyResult<()> {
+        write_vis(self.0.as_mut(), jones, weights, vis_ctx, draw_progress)
+    }
+
+    fn finalise(&mut self) -> PyResult<()> {
+        self.0
+            .finalise()
+            .map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+}
+
+/// The `marlu` Python module.
+#[pymodule]
+#[pyo3(name = "marlu")]
+fn marlu_python(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add_class::<PyRADec>()?;
+    m.add_class::<PyUVW>()?;
+    m.add_class::<PyLatLngHeight>()?;
+    m.add_class::<PyJones>()?;
+    m.add_class::<PyVisContext>()?;
+    #[cfg(feature = "cfitsio")]
+    m.add_class::<PyUvfitsWriter>()?;
+    #[cfg(feature = "ms")]
+    m.add_class::<PyMeasurementSetWriter>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_py_vis_context() {
+        let vis_ctx = PyVisContext::new(
+            4,
+            1090008640.,
+            2.,
+            8,
+            128_000_000.,
+            10_000.,
+            vec![(0, 1), (0, 2), (1, 2)],
+            2,
+            4,
+            4,
+        );
+        assert_eq!(vis_ctx.sel_dims(), (4, 8, 3));
+        assert_eq!(vis_ctx.avg_dims(), (2, 2, 3));
+        assert_eq!(vis_ctx.frequencies_hz().len(), 8);
+    }
+
+    #[test]
+    fn test_py_jones() {
+        let jones = PyJones::new([
+            Complex64::new(1.0, 1.0),
+            Complex64::new(0.0, 0.5),
+            Complex64::new(2.0, 0.0),
+            Complex64::new(1.0, -1.0),
+        ]);
+        assert_abs_diff_eq!(
+            (jones.__mul__(&jones.inv())).0,
+            Jones::identity(),
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(jones.h().0, jones.0.h());
+    }
+}
@@ -0,0 +1,339 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Resampling of visibilities onto a new, uniform time grid, e.g. to combine
+//! data from correlator modes with different integration times.
+//!
+//! Timesteps are placed at their centroids, i.e. timestep `i` of a
+//! [`VisContext`] is at `start_timestamp + (i + 0.5) * int_time`.
+
+use hifitime::{Duration, Epoch};
+use itertools::izip;
+use ndarray::prelude::*;
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::{
+    averaging::VisData33, errors::BadArrayShape, AveragingPolicy, Jones, VisContext, WeightFlags,
+};
+
+#[derive(Error, Debug)]
+pub enum ResampleError {
+    #[error(transparent)]
+    BadArrayShape(#[from] BadArrayShape),
+
+    #[error(
+        "the integration time of the visibilities to resample must be positive, not {int_time}"
+    )]
+    BadIntTime { int_time: Duration },
+}
+
+/// How visibilities are resampled onto a new time grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeInterpolation {
+    /// Use the timestep whose centroid is nearest to each new timestep.
+    Nearest,
+
+    /// Interpolate linearly between the two timesteps either side of each new
+    /// timestep.
+    Linear,
+}
+
+/// Where a new timestep comes from.
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    /// Outside of the existing timesteps.
+    Outside,
+    /// A copy of an existing timestep.
+    Copy(usize),
+    /// Between an existing timestep and the next one, with the given fraction
+    /// of the way to the next one.
+    Between(usize, f64),
+}
+
+/// Resample visibilities (`jones_array`, `weight_array`, described by
+/// `vis_ctx`) onto `num_timesteps` timesteps of `int_time`, starting at
+/// `start_timestamp`. The resampled visibilities are described by `vis_ctx`
+/// with these three fields replaced (and no averaging).
+///
+/// With [`TimeInterpolation::Linear`], each visibility is the linear
+/// interpolation of the unflagged visibilities either side of it, and its
+/// weight is the inverse of the interpolated variance. If only one side is
+/// unflagged, it is used as is. If neither side is unflagged, the visibility is
+/// interpolated without weights and flagged, or NaN if the
+/// [`VisContext::averaging_policy`] is [`AveragingPolicy::Nan`]. Visibilities
+/// are never extrapolated; new timesteps within half an integration of the
+/// first or last timestep are copies of it.
+///
+/// New timesteps that aren't within the existing timesteps are flagged with
+/// zero weights, and their visibilities are zero (or NaN, as above).
+///
+/// Returns the resampled visibilities and weights, with dimensions
+/// `[num_timesteps][channel][baseline]`.
+///
+/// # Errors
+///
+/// Will return [`ResampleError::BadArrayShape`] if the shapes of the arrays
+/// don't match `vis_ctx`, or [`ResampleError::BadIntTime`] if the integration
+/// time of `vis_ctx` isn't positive.
+pub fn resample_time(
+    jones_array: ArrayView3<Jones<f32>>,
+    weight_array: ArrayView3<f32>,
+    vis_ctx: &VisContext,
+    start_timestamp: Epoch,
+    int_time: Duration,
+    num_timesteps: usize,
+    interpolation: TimeInterpolation,
+) -> Result<VisData33, ResampleError> {
+    let sel_dims = vis_ctx.sel_dims();
+    for (argument, dim) in [
+        ("jones_array", jones_array.dim()),
+        ("weight_array", weight_array.dim()),
+    ] {
+        if dim != sel_dims {
+            return Err(ResampleError::BadArrayShape(BadArrayShape {
+                argument,
+                function: "resample_time",
+                expected: format!("{:?}", sel_dims),
+                received: format!("{:?}", dim),
+            }));
+        }
+    }
+    let old_int_time_s = vis_ctx.int_time.in_seconds();
+    if old_int_time_s.is_nan() || old_int_time_s <= 0.0 {
+        return Err(ResampleError::BadIntTime {
+            int_time: vis_ctx.int_time,
+        });
+    }
+
+    // The position of each new timestep in units of the old timesteps.
+    let num_old_timesteps = vis_ctx.num_sel_timesteps;
+    let sources: Vec<Source> = (0..num_timesteps)
+        .map(|i| {
+            let centroid = start_timestamp + (i as f64 + 0.5) * int_time;
+            let x = (centroid - vis_ctx.start_timestamp).in_seconds() / old_int_time_s - 0.5;
+            if num_old_timesteps == 0 || x < -0.5 || x > num_old_timesteps as f64 - 0.5 {
+                return Source::Outside;
+            }
+            let x = x.max(0.0).min((num_old_timesteps - 1) as f64);
+            match interpolation {
+                TimeInterpolation::Nearest => Source::Copy(x.round() as usize),
+                TimeInterpolation::Linear => {
+                    let j = x.floor() as usize;
+                    let fraction = x - j as f64;
+                    if fraction > 0.0 {
+                        Source::Between(j, fraction)
+                    } else {
+                        Source::Copy(j)
+                    }
+                }
+            }
+        })
+        .collect();
+
+    let new_dims = (num_timesteps, sel_dims.1, sel_dims.2);
+    let mut new_jones_array = Array3::<Jones<f32>>::zeros(new_dims);
+    let mut new_weight_array = Array3::<f32>::zeros(new_dims);
+    let flagged_jones = if vis_ctx.averaging_policy == AveragingPolicy::Nan {
+        Jones::nan()
+    } else {
+        Jones::default()
+    };
+
+    new_jones_array
+        .axis_iter_mut(Axis(2))
+        .into_par_iter()
+        .zip(new_weight_array.axis_iter_mut(Axis(2)))
+        .zip(jones_array.axis_iter(Axis(2)))
+        .zip(weight_array.axis_iter(Axis(2)))
+        .for_each(|(((mut new_jones_bl, mut new_weight_bl), jones_bl), weight_bl)| {
+            // arrays: [timestep][channel]
+            for (mut new_jones_ts, mut new_weight_ts, source) in izip!(
+                new_jones_bl.outer_iter_mut(),
+                new_weight_bl.outer_iter_mut(),
+                &sources,
+            ) {
+                match *source {
+                    Source::Outside => {
+                        new_jones_ts.fill(flagged_jones);
+                        new_weight_ts.fill(-0.0);
+                    }
+                    Source::Copy(j) => {
+                        new_jones_ts.assign(&jones_bl.row(j));
+                        new_weight_ts.assign(&weight_bl.row(j));
+                    }
+                    Source::Between(j, fraction) => {
+                        for (new_jones, new_weight, jones_a, jones_b, &weight_a, &weight_b) in
+                            izip!(
+                                new_jones_ts.iter_mut(),
+                                new_weight_ts.iter_mut(),
+                                jones_bl.row(j),
+                                jones_bl.row(j + 1),
+                                weight_bl.row(j),
+                                weight_bl.row(j + 1),
+                            )
+                        {
+                            (*new_jones, *new_weight) = interpolate(
+                                (*jones_a, weight_a),
+                                (*jones_b, weight_b),
+                                fraction,
+                                flagged_jones,
+                            );
+                        }
+                    }
+                }
+            }
+        });
+
+    Ok((new_jones_array, new_weight_array))
+}
+
+/// Linearly interpolate between two visibilities and their (signed) weights,
+/// `fraction` of the way from `a` to `b`.
+fn interpolate(
+    a: (Jones<f32>, f32),
+    b: (Jones<f32>, f32),
+    fraction: f64,
+    flagged_jones: Jones<f32>,
+) -> (Jones<f32>, f32) {
+    let (jones_a, weight_a) = a;
+    let (jones_b, weight_b) = b;
+    let coeff_a = 1.0 - fraction;
+    let coeff_b = fraction;
+    match (weight_a > 0.0, weight_b > 0.0) {
+        (true, true) => {
+            let jones =
+                Jones::<f64>::from(jones_a) * coeff_a + Jones::<f64>::from(jones_b) * coeff_b;
+            let variance =
+                coeff_a * coeff_a / weight_a as f64 + coeff_b * coeff_b / weight_b as f64;
+            (Jones::from(jones), (1.0 / variance) as f32)
+        }
+        (true, false) => a,
+        (false, true) => b,
+        (false, false) => {
+            let jones = if flagged_jones.any_nan() {
+                flagged_jones
+            } else {
+                Jones::from(
+                    Jones::<f64>::from(jones_a) * coeff_a + Jones::<f64>::from(jones_b) * coeff_b,
+                )
+            };
+            let weight = coeff_a * weight_a.abs() as f64 + coeff_b * weight_b.abs() as f64;
+            (jones, WeightFlags::new(weight as f32, true).to_signed())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use hifitime::Unit;
+
+    use super::*;
+    use crate::Complex;
+
+    fn get_vis_ctx(num_timesteps: usize) -> VisContext {
+        VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(2., Unit::Second),
+                num_timesteps,
+            )
+            .channels(150e6, 40e3, 1)
+            .baselines(vec![(0, 1)])
+            .build()
+            .unwrap()
+    }
+
+    fn jones(re: f32) -> Jones<f32> {
+        Jones::identity() * Complex::new(re, 0.0)
+    }
+
+    #[test]
+    fn test_resample_time() {
+        let vis_ctx = get_vis_ctx(3);
+        // Timestep centroids are at 1, 3 and 5 seconds.
+        let jones_array = Array3::from_shape_fn((3, 1, 1), |(t, _, _)| jones(t as f32));
+        let mut weight_array = Array3::from_elem((3, 1, 1), 4.0);
+        weight_array[(2, 0, 0)] = -4.0;
+
+        // New centroids at 0.5, 1.5, ..., 5.5 and 6.5 seconds.
+        let start = vis_ctx.start_timestamp;
+        let int_time = Duration::from_f64(1., Unit::Second);
+        let (new_jones, new_weights) = resample_time(
+            jones_array.view(),
+            weight_array.view(),
+            &vis_ctx,
+            start,
+            int_time,
+            7,
+            TimeInterpolation::Linear,
+        )
+        .unwrap();
+        assert_eq!(new_jones.dim(), (7, 1, 1));
+        // Not extrapolated.
+        assert_abs_diff_eq!(new_jones[(0, 0, 0)], jones(0.0));
+        assert_abs_diff_eq!(new_weights[(0, 0, 0)], 4.0);
+        // A quarter of the way from the first to the second timestep; the
+        // variance is (0.75^2 + 0.25^2) / 4.
+        assert_abs_diff_eq!(new_jones[(1, 0, 0)], jones(0.25));
+        assert_abs_diff_eq!(new_weights[(1, 0, 0)], 6.4, epsilon = 1e-5);
+        assert_abs_diff_eq!(new_weights[(2, 0, 0)], 6.4, epsilon = 1e-5);
+        // The third timestep is flagged, so only the second is used.
+        assert_abs_diff_eq!(new_jones[(4, 0, 0)], jones(1.0));
+        assert_abs_diff_eq!(new_weights[(4, 0, 0)], 4.0);
+        assert_abs_diff_eq!(new_weights[(5, 0, 0)], -4.0);
+        // Outside of the existing timesteps.
+        assert_abs_diff_eq!(new_jones[(6, 0, 0)], Jones::default());
+        assert!(new_weights[(6, 0, 0)].is_sign_negative());
+
+        let (new_jones, new_weights) = resample_time(
+            jones_array.view(),
+            weight_array.view(),
+            &vis_ctx,
+            start,
+            int_time,
+            7,
+            TimeInterpolation::Nearest,
+        )
+        .unwrap();
+        for (t, expected) in [0, 0, 1, 1, 2, 2].into_iter().enumerate() {
+            assert_abs_diff_eq!(new_jones[(t, 0, 0)], jones(expected as f32));
+            assert_abs_diff_eq!(new_weights[(t, 0, 0)], weight_array[(expected, 0, 0)]);
+        }
+        assert!(new_weights[(6, 0, 0)].is_sign_negative());
+    }
+
+    #[test]
+    fn test_resample_time_flagged() {
+        let mut vis_ctx = get_vis_ctx(2);
+        vis_ctx.averaging_policy = AveragingPolicy::Nan;
+        let jones_array = Array3::from_shape_fn((2, 1, 1), |(t, _, _)| jones(t as f32));
+        let weight_array = Array3::from_elem((2, 1, 1), -2.0);
+
+        let (new_jones, new_weights) = resample_time(
+            jones_array.view(),
+            weight_array.view(),
+            &vis_ctx,
+            vis_ctx.start_timestamp + Duration::from_f64(1., Unit::Second),
+            vis_ctx.int_time,
+            1,
+            TimeInterpolation::Linear,
+        )
+        .unwrap();
+        assert!(new_jones[(0, 0, 0)].any_nan());
+        assert_abs_diff_eq!(new_weights[(0, 0, 0)], -2.0);
+
+        let result = resample_time(
+            jones_array.view(),
+            weight_array.slice(s![..1, .., ..]),
+            &vis_ctx,
+            vis_ctx.start_timestamp,
+            vis_ctx.int_time,
+            1,
+            TimeInterpolation::Linear,
+        );
+        assert!(matches!(result, Err(ResampleError::BadArrayShape(_))));
+    }
+}
@@ -0,0 +1,414 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Configure the threads used by Marlu, so that applications running several
+//! conversions at once can partition their CPUs between them.
+//!
+//! Marlu's parallel code uses rayon, which by default runs on a global pool
+//! sized by `RAYON_NUM_THREADS`. A [`Runtime`] is a separate pool, optionally
+//! with its threads pinned to CPUs, and a limit on the number of files (e.g.
+//! gpubox coarse channels) that readers access at once. Work run with
+//! [`Runtime::install`] uses these settings:
+//!
+//! ```no_run
+//! use marlu::runtime::RuntimeConfig;
+//!
+//! let runtime = RuntimeConfig {
+//!     num_threads: Some(4),
+//!     pin_cpus: Some(vec![0, 1, 2, 3]),
+//!     io_concurrency: Some(2),
+//! }
+//! .build()
+//! .unwrap();
+//! runtime.install(|| {
+//!     // e.g. VisSelection::read_mwalib and VisWrite::write_vis
+//! });
+//! ```
+//!
+//! The configuration can also be read from the environment with
+//! [`RuntimeConfig::from_env`].
+
+use std::{
+    cell::RefCell,
+    sync::{Arc, Condvar, Mutex, PoisonError},
+};
+
+use log::warn;
+use thiserror::Error;
+
+/// The environment variable of [`RuntimeConfig::num_threads`].
+pub const NUM_THREADS_ENV: &str = "MARLU_NUM_THREADS";
+
+/// The environment variable of [`RuntimeConfig::pin_cpus`], a list of CPUs
+/// and ranges of CPUs, e.g. `0-3,8,10-11`.
+pub const PIN_CPUS_ENV: &str = "MARLU_PIN_CPUS";
+
+/// The environment variable of [`RuntimeConfig::io_concurrency`].
+pub const IO_CONCURRENCY_ENV: &str = "MARLU_IO_CONCURRENCY";
+
+#[derive(Error, Debug)]
+pub enum RuntimeError {
+    #[error("bad value of environment variable {name}: {value:?}")]
+    BadEnvVar { name: &'static str, value: String },
+
+    #[error("threads can only be pinned to CPUs on Linux")]
+    PinningUnsupported,
+
+    #[error("no CPUs were given to pin threads to")]
+    NoPinCpus,
+
+    #[error(transparent)]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
+}
+
+/// The configuration of a [`Runtime`]. The default is rayon's default number
+/// of threads, without pinning or a limit on I/O concurrency.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    /// The number of threads. If this is `None`, rayon's default is used
+    /// (`RAYON_NUM_THREADS`, or the number of logical CPUs), unless
+    /// `pin_cpus` is given, in which case there's a thread for each CPU.
+    pub num_threads: Option<usize>,
+
+    /// Pin the threads to these CPUs, in turn. Only supported on Linux.
+    pub pin_cpus: Option<Vec<usize>>,
+
+    /// The maximum number of files that readers access at once, e.g. the
+    /// number of gpubox coarse channels read in parallel. If this is `None`,
+    /// there's no limit other than the number of threads.
+    pub io_concurrency: Option<usize>,
+}
+
+impl RuntimeConfig {
+    /// Read the configuration from the environment variables
+    /// [`NUM_THREADS_ENV`], [`PIN_CPUS_ENV`] and [`IO_CONCURRENCY_ENV`]. Unset
+    /// or empty variables are `None`.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`RuntimeError::BadEnvVar`] if a variable can't be parsed.
+    pub fn from_env() -> Result<Self, RuntimeError> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, RuntimeError> {
+        let get = |name: &'static str| {
+            var(name)
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let bad_value = |name: &'static str, value: String| RuntimeError::BadEnvVar { name, value };
+        let parse_count = |name: &'static str| -> Result<Option<usize>, RuntimeError> {
+            match get(name) {
+                Some(value) => match value.parse() {
+                    Ok(count) if count > 0 => Ok(Some(count)),
+                    _ => Err(bad_value(name, value)),
+                },
+                None => Ok(None),
+            }
+        };
+
+        let pin_cpus = match get(PIN_CPUS_ENV) {
+            Some(value) => match parse_cpu_list(&value) {
+                Some(cpus) => Some(cpus),
+                None => return Err(bad_value(PIN_CPUS_ENV, value)),
+            },
+            None => None,
+        };
+        Ok(Self {
+            num_threads: parse_count(NUM_THREADS_ENV)?,
+            pin_cpus,
+            io_concurrency: parse_count(IO_CONCURRENCY_ENV)?,
+        })
+    }
+
+    /// Build a [`Runtime`] with this configuration.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`RuntimeError::PinningUnsupported`] if threads are to be
+    /// pinned on a platform other than Linux, or an error if the threads can't
+    /// be spawned.
+    pub fn build(&self) -> Result<Runtime, RuntimeError> {
+        let io_limiter = self.io_concurrency.map(IoLimiter::new).map(Arc::new);
+        let pool = self.pool_builder(io_limiter.clone())?.build()?;
+        Ok(Runtime { pool, io_limiter })
+    }
+
+    /// Configure rayon's global pool, rather than building a [`Runtime`], so
+    /// that this configuration applies everywhere. This can only be done once,
+    /// before rayon is first used.
+    ///
+    /// # Errors
+    ///
+    /// See [`RuntimeConfig::build`]; an error is also returned if the global
+    /// pool has already been initialised.
+    pub fn build_global(&self) -> Result<(), RuntimeError> {
+        let io_limiter = self.io_concurrency.map(IoLimiter::new).map(Arc::new);
+        self.pool_builder(io_limiter)?.build_global()?;
+        Ok(())
+    }
+
+    fn pool_builder(
+        &self,
+        io_limiter: Option<Arc<IoLimiter>>,
+    ) -> Result<rayon::ThreadPoolBuilder, RuntimeError> {
+        let mut builder = rayon::ThreadPoolBuilder::new().thread_name(|i| format!("marlu-{}", i));
+        if let Some(cpus) = &self.pin_cpus {
+            if cpus.is_empty() {
+                return Err(RuntimeError::NoPinCpus);
+            }
+            if !cfg!(target_os = "linux") {
+                return Err(RuntimeError::PinningUnsupported);
+            }
+        }
+        match (self.num_threads, &self.pin_cpus) {
+            (Some(num_threads), _) => builder = builder.num_threads(num_threads),
+            (None, Some(cpus)) => builder = builder.num_threads(cpus.len()),
+            (None, None) => (),
+        }
+
+        let pin_cpus = self.pin_cpus.clone();
+        Ok(builder.start_handler(move |thread_idx| {
+            if let Some(cpus) = &pin_cpus {
+                let cpu = cpus[thread_idx % cpus.len()];
+                if let Err(e) = pin_current_thread(cpu) {
+                    warn!("couldn't pin thread {} to CPU {}: {}", thread_idx, cpu, e);
+                }
+            }
+            IO_LIMITER.with(|limiter| *limiter.borrow_mut() = io_limiter.clone());
+        }))
+    }
+}
+
+/// A rayon thread pool configured by a [`RuntimeConfig`].
+pub struct Runtime {
+    pool: rayon::ThreadPool,
+    io_limiter: Option<Arc<IoLimiter>>,
+}
+
+impl Runtime {
+    /// Run `op` in this runtime; any parallel work of `op` runs on the threads
+    /// of this runtime.
+    pub fn install<OP, R>(&self, op: OP) -> R
+    where
+        OP: FnOnce() -> R + Send,
+        R: Send,
+    {
+        self.pool.install(op)
+    }
+
+    /// The number of threads of this runtime.
+    pub fn num_threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// The maximum number of files that readers access at once, if limited.
+    pub fn io_concurrency(&self) -> Option<usize> {
+        self.io_limiter.as_ref().map(|limiter| limiter.max)
+    }
+}
+
+thread_local! {
+    /// The I/O limiter of the runtime that this thread belongs to.
+    static IO_LIMITER: RefCell<Option<Arc<IoLimiter>>> = const { RefCell::new(None) };
+}
+
+/// A counting semaphore of file accesses.
+#[derive(Debug)]
+struct IoLimiter {
+    max: usize,
+    in_use: Mutex<usize>,
+    released: Condvar,
+}
+
+impl IoLimiter {
+    fn new(max: usize) -> Self {
+        Self {
+            max: max.max(1),
+            in_use: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+}
+
+/// Permission to access a file, from [`io_permit`]. The permit is released
+/// when this is dropped.
+#[must_use]
+pub struct IoPermit(Option<Arc<IoLimiter>>);
+
+impl Drop for IoPermit {
+    fn drop(&mut self) {
+        if let Some(limiter) = &self.0 {
+            let mut in_use = limiter
+                .in_use
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            *in_use -= 1;
+            limiter.released.notify_one();
+        }
+    }
+}
+
+/// Wait until a file can be accessed under the I/O concurrency limit of the
+/// current [`Runtime`]. This returns immediately outside of a runtime, or if
+/// its I/O concurrency isn't limited.
+pub fn io_permit() -> IoPermit {
+    let limiter = IO_LIMITER.with(|limiter| limiter.borrow().clone());
+    if let Some(limiter) = &limiter {
+        let mut in_use = limiter
+            .in_use
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        while *in_use >= limiter.max {
+            in_use = limiter
+                .released
+                .wait(in_use)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        *in_use += 1;
+    }
+    IoPermit(limiter)
+}
+
+/// Parse a list of CPUs and ranges of CPUs, e.g. `0-3,8,10-11`.
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = vec![];
+    for item in list.split(',').map(str::trim) {
+        match item.split_once('-') {
+            Some((first, last)) => {
+                let (first, last): (usize, usize) =
+                    (first.trim().parse().ok()?, last.trim().parse().ok()?);
+                if first > last {
+                    return None;
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(item.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cpu: usize) -> std::io::Result<()> {
+    // SAFETY: `cpu_set_t` is plain data, and is only accessed through the
+    // libc macros.
+    unsafe {
+        let mut cpu_set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut cpu_set);
+        // A pid of 0 is the current thread.
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &cpu_set) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_cpu: usize) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "threads can only be pinned to CPUs on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicUsize, Ordering},
+        thread::sleep,
+        time::Duration,
+    };
+
+    use rayon::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(
+            parse_cpu_list("0-3,8, 10-11"),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_cpu_list("5"), Some(vec![5]));
+        assert_eq!(parse_cpu_list("3-1"), None);
+        assert_eq!(parse_cpu_list("a"), None);
+    }
+
+    #[test]
+    fn test_config_from_vars() {
+        let vars: HashMap<&str, &str> = [
+            (NUM_THREADS_ENV, "3"),
+            (PIN_CPUS_ENV, "0-1"),
+            (IO_CONCURRENCY_ENV, " "),
+        ]
+        .into_iter()
+        .collect();
+        let config =
+            RuntimeConfig::from_vars(|name| vars.get(name).map(ToString::to_string)).unwrap();
+        assert_eq!(
+            config,
+            RuntimeConfig {
+                num_threads: Some(3),
+                pin_cpus: Some(vec![0, 1]),
+                io_concurrency: None,
+            }
+        );
+
+        assert_eq!(
+            RuntimeConfig::from_vars(|_| None).unwrap(),
+            RuntimeConfig::default()
+        );
+        assert!(matches!(
+            RuntimeConfig::from_vars(|name| (name == IO_CONCURRENCY_ENV).then(|| "0".to_string())),
+            Err(RuntimeError::BadEnvVar {
+                name: IO_CONCURRENCY_ENV,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_runtime_limits_io_concurrency() {
+        let runtime = RuntimeConfig {
+            num_threads: Some(4),
+            pin_cpus: None,
+            io_concurrency: Some(2),
+        }
+        .build()
+        .unwrap();
+        assert_eq!(runtime.num_threads(), 4);
+        assert_eq!(runtime.io_concurrency(), Some(2));
+
+        let current = AtomicUsize::new(0);
+        let max = AtomicUsize::new(0);
+        runtime.install(|| {
+            (0..16).into_par_iter().for_each(|_| {
+                let _permit = io_permit();
+                let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+                max.fetch_max(now, Ordering::SeqCst);
+                sleep(Duration::from_millis(5));
+                current.fetch_sub(1, Ordering::SeqCst);
+            });
+        });
+        assert_eq!(max.load(Ordering::SeqCst), 2);
+
+        // A thread for each pinned CPU.
+        if cfg!(target_os = "linux") {
+            let runtime = RuntimeConfig {
+                pin_cpus: Some(vec![0]),
+                ..Default::default()
+            }
+            .build()
+            .unwrap();
+            assert_eq!(runtime.num_threads(), 1);
+            assert_eq!(runtime.io_concurrency(), None);
+        }
+
+        // Outside of a runtime, there's no limit.
+        let _permits: Vec<IoPermit> = (0..4).map(|_| io_permit()).collect();
+    }
+}
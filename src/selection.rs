@@ -50,26 +50,39 @@
 //! assert_ne!(dims, jones_array.dim());
 //! ```
 
-use std::ops::Range;
+use std::{collections::HashMap, ops::Range};
 
 use thiserror::Error;
 
-use crate::{ndarray::Array3, num_traits::Zero, Jones};
+use crate::{
+    math::baseline_to_tiles,
+    ndarray::Array3,
+    num_traits::{Float, Zero},
+    Jones,
+};
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "mwalib")] {
+        use std::{
+            cell::RefCell,
+            ops::{Deref, DerefMut},
+        };
         use itertools::izip;
         use log::warn;
         use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
         use crate::{
+            context::VisContext,
             mwalib::{CorrelatorContext, MetafitsContext},
-            ndarray::{ArrayViewMut3, Axis},
+            ndarray::{ArrayView2, ArrayViewMut3, Axis},
             rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator},
+            runtime::io_permit,
+            WeightFlags,
         };
     }
 }
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum SelectionError {
     #[error("No common timesteps found. CorrelatorContext hdu info: {hdu_info}")]
     /// Error for when gpuboxes provided have no overlapping visibilities
@@ -117,6 +130,7 @@ pub enum SelectionError {
 /// TODO: this definitely needs `fine_chans_per_coarse`
 /// TODO: what about <https://doc.rust-lang.org/std/ops/trait.RangeBounds.html> insetad of Range?
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VisSelection {
     /// selected range of mwalib timestep indices
     pub timestep_range: Range<usize>,
@@ -210,6 +224,91 @@ impl VisSelection {
             .collect()
     }
 
+    /// Split this selection into one with only the auto-correlations, and one
+    /// with only the cross-correlations.
+    #[cfg(feature = "mwalib")]
+    pub fn split_autos(&self, meta_ctx: &MetafitsContext) -> (VisSelection, VisSelection) {
+        let (auto_idxs, cross_idxs) = self.baseline_idxs.iter().partition(|&&idx| {
+            let baseline = &meta_ctx.baselines[idx];
+            baseline.ant1_index == baseline.ant2_index
+        });
+        (
+            Self {
+                baseline_idxs: auto_idxs,
+                ..self.clone()
+            },
+            Self {
+                baseline_idxs: cross_idxs,
+                ..self.clone()
+            },
+        )
+    }
+
+    /// Remove any selected baselines that involve the given antenna indices,
+    /// returning the [`AntennaRenumbering`] that maps the observation's antenna
+    /// indices to those of an output without the dropped antennas.
+    #[cfg(feature = "mwalib")]
+    pub fn drop_antennas(
+        &mut self,
+        meta_ctx: &MetafitsContext,
+        dropped_ant_idxs: &[usize],
+    ) -> AntennaRenumbering {
+        self.baseline_idxs.retain(|&idx| {
+            let baseline = &meta_ctx.baselines[idx];
+            !dropped_ant_idxs.contains(&baseline.ant1_index)
+                && !dropped_ant_idxs.contains(&baseline.ant2_index)
+        });
+        AntennaRenumbering::from_dropped(meta_ctx.num_ants, dropped_ant_idxs)
+    }
+
+    /// Remove selected timesteps at the start of the observation that are
+    /// within the metafits quack time, while the array is still settling.
+    ///
+    /// Timestep indices are matched to the timesteps of the correlator
+    /// context, as in [`VisSelection::from_mwalib`].
+    #[cfg(feature = "mwalib")]
+    pub fn trim_quack(&mut self, corr_ctx: &CorrelatorContext) {
+        let good_time_unix_ms = corr_ctx.metafits_context.good_time_unix_ms;
+        let start = self
+            .timestep_range
+            .clone()
+            .find(|&idx| {
+                corr_ctx
+                    .timesteps
+                    .get(idx)
+                    .map_or(true, |timestep| timestep.unix_time_ms >= good_time_unix_ms)
+            })
+            .unwrap_or(self.timestep_range.end);
+        self.timestep_range.start = start;
+    }
+
+    /// Remove selected timesteps at the end of the observation that aren't
+    /// complete: timesteps that extend beyond the scheduled end of the
+    /// observation, and then trailing timesteps that don't fill an averaged
+    /// timestep of `avg_time` timesteps.
+    ///
+    /// Timestep indices are matched to the timesteps of the correlator
+    /// context, as in [`VisSelection::from_mwalib`].
+    #[cfg(feature = "mwalib")]
+    pub fn trim_partial_end(&mut self, corr_ctx: &CorrelatorContext, avg_time: usize) {
+        let meta_ctx = &corr_ctx.metafits_context;
+        let sched_end_unix_time_ms = meta_ctx.sched_end_unix_time_ms;
+        let corr_int_time_ms = meta_ctx.corr_int_time_ms;
+        let end = self
+            .timestep_range
+            .clone()
+            .rev()
+            .find(|&idx| {
+                corr_ctx.timesteps.get(idx).map_or(false, |timestep| {
+                    timestep.unix_time_ms + corr_int_time_ms <= sched_end_unix_time_ms
+                })
+            })
+            .map_or(self.timestep_range.start, |idx| idx + 1);
+        let num_timesteps = end - self.timestep_range.start;
+        let avg_time = avg_time.max(1);
+        self.timestep_range.end = self.timestep_range.start + num_timesteps / avg_time * avg_time;
+    }
+
     /// Get the shape of the jones, flag or weight array for this selection
     pub fn get_shape(&self, fine_chans_per_coarse: usize) -> (usize, usize, usize) {
         let num_chans = self.coarse_chan_range.len() * fine_chans_per_coarse;
@@ -238,6 +337,26 @@ impl VisSelection {
         &self,
         fine_chans_per_coarse: usize,
     ) -> Result<Array3<Jones<f32>>, SelectionError> {
+        self.allocate_jones_generic(fine_chans_per_coarse)
+    }
+
+    /// Allocate a double-precision jones array to store visibilities for the
+    /// selection, e.g. for [`VisSelection::read_mwalib_f64`].
+    ///
+    /// # Errors
+    ///
+    /// can raise `SelectionError::InsufficientMemory` if not enough memory.
+    pub fn allocate_jones_f64(
+        &self,
+        fine_chans_per_coarse: usize,
+    ) -> Result<Array3<Jones<f64>>, SelectionError> {
+        self.allocate_jones_generic(fine_chans_per_coarse)
+    }
+
+    fn allocate_jones_generic<F: Float>(
+        &self,
+        fine_chans_per_coarse: usize,
+    ) -> Result<Array3<Jones<F>>, SelectionError> {
         let shape = self.get_shape(fine_chans_per_coarse);
         let num_elems = shape.0 * shape.1 * shape.2;
         let mut v = Vec::new();
@@ -369,14 +488,62 @@ impl VisSelection {
     pub fn read_mwalib(
         &self,
         corr_ctx: &CorrelatorContext,
-        mut jones_array: ArrayViewMut3<Jones<f32>>,
+        jones_array: ArrayViewMut3<Jones<f32>>,
+        flag_array: ArrayViewMut3<bool>,
+        draw_progress: bool,
+    ) -> Result<(), SelectionError> {
+        self.read_mwalib_with_flag_policy(
+            corr_ctx,
+            jones_array,
+            flag_array,
+            &FlagPolicy::default(),
+            draw_progress,
+        )
+    }
+
+    /// Read the visibilities for this selection into a double-precision jones
+    /// array, like [`VisSelection::read_mwalib`]. This avoids converting a
+    /// single-precision array after reading when downstream processing needs
+    /// double-precision accumulation, e.g. for long averages.
+    ///
+    /// # Errors
+    ///
+    /// See [`VisSelection::read_mwalib`].
+    #[cfg(feature = "mwalib")]
+    pub fn read_mwalib_f64(
+        &self,
+        corr_ctx: &CorrelatorContext,
+        jones_array: ArrayViewMut3<Jones<f64>>,
+        flag_array: ArrayViewMut3<bool>,
+        draw_progress: bool,
+    ) -> Result<(), SelectionError> {
+        self.read_mwalib_with_flag_policy(
+            corr_ctx,
+            jones_array,
+            flag_array,
+            &FlagPolicy::default(),
+            draw_progress,
+        )
+    }
+
+    /// Read the visibilities for this selection into the jones array, like
+    /// [`VisSelection::read_mwalib`], additionally flagging according to
+    /// `flag_policy`. The jones array may be single or double precision.
+    ///
+    /// # Errors
+    ///
+    /// See [`VisSelection::read_mwalib`].
+    #[cfg(feature = "mwalib")]
+    pub fn read_mwalib_with_flag_policy<F: Float + From<f32> + Send + Sync>(
+        &self,
+        corr_ctx: &CorrelatorContext,
+        mut jones_array: ArrayViewMut3<Jones<F>>,
         mut flag_array: ArrayViewMut3<bool>,
+        flag_policy: &FlagPolicy,
         draw_progress: bool,
     ) -> Result<(), SelectionError> {
         let fine_chans_per_coarse = corr_ctx.metafits_context.num_corr_fine_chans_per_coarse;
         let shape = self.get_shape(fine_chans_per_coarse);
-        let (num_timesteps, _, _) = shape;
-        let num_coarse_chans = self.coarse_chan_range.len();
 
         if jones_array.dim() != shape {
             return Err(SelectionError::BadArrayShape {
@@ -409,48 +576,7 @@ impl VisSelection {
         let floats_per_baseline = floats_per_chan * fine_chans_per_coarse;
         let floats_per_hdu = floats_per_baseline * corr_ctx.metafits_context.num_baselines;
 
-        // Progress bar draw target
-        let draw_target = if draw_progress {
-            ProgressDrawTarget::stderr()
-        } else {
-            ProgressDrawTarget::hidden()
-        };
-        // a progress bar containing the progress bars associated with this method
-        let multi_progress = MultiProgress::with_draw_target(draw_target);
-        // a vector of progress bars for the visibility reading progress of each channel.
-        let read_progress: Vec<ProgressBar> = self
-            .coarse_chan_range
-            .clone()
-            .map(|mwalib_coarse_chan_idx| {
-                let channel_progress = multi_progress.add(
-                    ProgressBar::new(num_timesteps as _)
-                        .with_style(
-                            ProgressStyle::default_bar()
-                                .template("{msg:16}: [{wide_bar:.blue}] {pos:4}/{len:4}")
-                                .unwrap()
-                                .progress_chars("=> "),
-                        )
-                        .with_position(0)
-                        .with_message(format!("coarse_chan {:03}", mwalib_coarse_chan_idx)),
-                );
-                channel_progress.set_position(0);
-                channel_progress
-            })
-            .collect();
-        // The total reading progress bar.
-        let total_progress = multi_progress.add(
-            ProgressBar::new((num_timesteps * num_coarse_chans) as _)
-                .with_style(
-                    ProgressStyle::default_bar()
-                        .template(
-                            "{msg:16}: [{elapsed_precise}] [{wide_bar:.cyan/blue}] {percent:3}% ({eta:5})",
-                        )
-                        .unwrap()
-                        .progress_chars("=> "),
-                )
-                .with_position(0)
-                .with_message("loading hdus"),
-        );
+        let (read_progress, total_progress) = self.read_progress_bars(draw_progress);
 
         // Load HDUs from each coarse channel. arrays: [timestep][chan][baseline]
         jones_array
@@ -464,7 +590,7 @@ impl VisSelection {
                     progress.set_position(0);
 
                     // buffer: [baseline][chan][pol][complex]
-                    let mut hdu_buffer: Vec<f32> = vec![0.0; floats_per_hdu];
+                    let mut hdu_buffer = HduBuffer::take(floats_per_hdu);
 
                     // arrays: [chan][baseline]
                     for (mut jones_array, mut flag_array, timestep_idx) in izip!(
@@ -472,11 +598,14 @@ impl VisSelection {
                         flag_array.outer_iter_mut(),
                         self.timestep_range.clone(),
                     ) {
-                        match corr_ctx.read_by_baseline_into_buffer(
+                        let permit = io_permit();
+                        let result = corr_ctx.read_by_baseline_into_buffer(
                             timestep_idx,
                             coarse_chan_idx,
                             hdu_buffer.as_mut_slice(),
-                        ) {
+                        );
+                        drop(permit);
+                        match result {
                             Ok(()) => {
                                 // arrays: [chan]
                                 for (mut jones_array, baseline_idx) in izip!(
@@ -492,14 +621,14 @@ impl VisSelection {
                                         hdu_baseline_chunk.chunks_exact(floats_per_chan)
                                     ) {
                                         *jones = Jones::from([
-                                            hdu_chan_chunk[0],
-                                            hdu_chan_chunk[1],
-                                            hdu_chan_chunk[2],
-                                            hdu_chan_chunk[3],
-                                            hdu_chan_chunk[4],
-                                            hdu_chan_chunk[5],
-                                            hdu_chan_chunk[6],
-                                            hdu_chan_chunk[7],
+                                            hdu_chan_chunk[0].into(),
+                                            hdu_chan_chunk[1].into(),
+                                            hdu_chan_chunk[2].into(),
+                                            hdu_chan_chunk[3].into(),
+                                            hdu_chan_chunk[4].into(),
+                                            hdu_chan_chunk[5].into(),
+                                            hdu_chan_chunk[6].into(),
+                                            hdu_chan_chunk[7].into(),
                                         ]);
                                     }
                                 }
@@ -525,58 +654,913 @@ impl VisSelection {
         // We're done!
         total_progress.finish();
 
+        flag_policy.apply(corr_ctx, self, flag_array);
+
         Ok(())
     }
-}
 
-#[cfg(test)]
-#[cfg(feature = "mwalib")]
-mod tests {
-    use approx::assert_abs_diff_eq;
+    /// Read the visibilities for this selection into the jones array, with
+    /// identical results to [`VisSelection::read_mwalib`], but without
+    /// converting each sample into a [`Jones`].
+    ///
+    /// HDUs are read in frequency order (`[chan][baseline]`), which matches the
+    /// layout of each timestep and coarse channel of the jones array. When all
+    /// baselines are selected and the jones array is in standard layout, each
+    /// HDU is read directly into the jones array. Otherwise, HDUs are read into
+    /// a buffer and the selected baselines are copied as whole [`Jones`]
+    /// matrices. mwalib still converts legacy data into this order.
+    ///
+    /// # Errors
+    ///
+    /// See [`VisSelection::read_mwalib`].
+    #[cfg(feature = "mwalib")]
+    pub fn read_mwalib_fast(
+        &self,
+        corr_ctx: &CorrelatorContext,
+        mut jones_array: ArrayViewMut3<Jones<f32>>,
+        mut flag_array: ArrayViewMut3<bool>,
+        draw_progress: bool,
+    ) -> Result<(), SelectionError> {
+        let meta_ctx = &corr_ctx.metafits_context;
+        let fine_chans_per_coarse = meta_ctx.num_corr_fine_chans_per_coarse;
+        let shape = self.get_shape(fine_chans_per_coarse);
 
-    use crate::Complex;
+        if jones_array.dim() != shape {
+            return Err(SelectionError::BadArrayShape {
+                argument: "jones_array".to_string(),
+                function: "VisSelection::read_mwalib_fast".to_string(),
+                expected: format!("{:?}", shape),
+                received: format!("{:?}", jones_array.dim()),
+            });
+        };
 
-    use super::*;
+        if flag_array.dim() != shape {
+            return Err(SelectionError::BadArrayShape {
+                argument: "flag_array".to_string(),
+                function: "VisSelection::read_mwalib_fast".to_string(),
+                expected: format!("{:?}", shape),
+                received: format!("{:?}", flag_array.dim()),
+            });
+        };
 
-    pub fn get_mwax_context() -> CorrelatorContext {
-        CorrelatorContext::new(
-            "tests/data/1297526432_mwax/1297526432.metafits",
-            &[
-                "tests/data/1297526432_mwax/1297526432_20210216160014_ch117_000.fits",
-                "tests/data/1297526432_mwax/1297526432_20210216160014_ch117_001.fits",
-                "tests/data/1297526432_mwax/1297526432_20210216160014_ch118_000.fits",
-                "tests/data/1297526432_mwax/1297526432_20210216160014_ch118_001.fits",
-            ],
-        )
-        .unwrap()
+        assert_eq!(meta_ctx.num_visibility_pols * 2, FLOATS_PER_JONES);
+        let num_baselines = meta_ctx.num_baselines;
+        let all_baselines = self.baseline_idxs.len() == num_baselines
+            && self
+                .baseline_idxs
+                .iter()
+                .enumerate()
+                .all(|(i, &idx)| i == idx);
+
+        let (read_progress, total_progress) = self.read_progress_bars(draw_progress);
+
+        // Load HDUs from each coarse channel. arrays: [timestep][chan][baseline]
+        jones_array
+            .axis_chunks_iter_mut(Axis(1), fine_chans_per_coarse)
+            .into_par_iter()
+            .zip(flag_array.axis_chunks_iter_mut(Axis(1), fine_chans_per_coarse))
+            .zip(self.coarse_chan_range.clone())
+            .zip(read_progress)
+            .try_for_each(
+                |(((mut jones_array, mut flag_array), coarse_chan_idx), progress)| {
+                    progress.set_position(0);
+
+                    // arrays: [chan][baseline]
+                    for (mut jones_array, mut flag_array, timestep_idx) in izip!(
+                        jones_array.outer_iter_mut(),
+                        flag_array.outer_iter_mut(),
+                        self.timestep_range.clone(),
+                    ) {
+                        let direct = all_baselines && jones_array.is_standard_layout();
+                        let permit = io_permit();
+                        let result = match jones_array.as_slice_mut() {
+                            Some(jones_slice) if direct => corr_ctx.read_by_frequency_into_buffer(
+                                timestep_idx,
+                                coarse_chan_idx,
+                                jones_as_floats_mut(jones_slice),
+                            ),
+                            _ => {
+                                // buffer: [chan][baseline][pol][complex]
+                                let mut hdu_buffer = HduBuffer::take(
+                                    fine_chans_per_coarse * num_baselines * FLOATS_PER_JONES,
+                                );
+                                corr_ctx
+                                    .read_by_frequency_into_buffer(
+                                        timestep_idx,
+                                        coarse_chan_idx,
+                                        hdu_buffer.as_mut_slice(),
+                                    )
+                                    .map(|()| {
+                                        let hdu_jones = ArrayView2::from_shape(
+                                            (fine_chans_per_coarse, num_baselines),
+                                            floats_as_jones(&hdu_buffer),
+                                        )
+                                        .expect("HDU buffer has the shape of the HDU");
+                                        for (mut jones_array, &baseline_idx) in izip!(
+                                            jones_array.axis_iter_mut(Axis(1)),
+                                            self.baseline_idxs.iter()
+                                        ) {
+                                            jones_array.assign(&hdu_jones.column(baseline_idx));
+                                        }
+                                    })
+                            }
+                        };
+                        drop(permit);
+                        match result {
+                            Ok(()) => (),
+                            Err(mwalib::GpuboxError::NoDataForTimeStepCoarseChannel { .. }) => {
+                                warn!(
+                                    "Flagging missing HDU @ ts={}, cc={}",
+                                    timestep_idx, coarse_chan_idx
+                                );
+                                flag_array.fill(true);
+                            }
+                            Err(e) => return Err(e),
+                        }
+
+                        progress.inc(1);
+                        total_progress.inc(1);
+                    }
+                    progress.finish();
+                    Ok(())
+                },
+            )?;
+
+        // We're done!
+        total_progress.finish();
+
+        Ok(())
     }
 
-    pub fn get_mwa_legacy_context() -> CorrelatorContext {
-        CorrelatorContext::new(
-            "tests/data/1196175296_mwa_ord/1196175296.metafits",
-            &[
-                "tests/data/1196175296_mwa_ord/1196175296_20171201145440_gpubox01_00.fits",
-                "tests/data/1196175296_mwa_ord/1196175296_20171201145440_gpubox02_00.fits",
-                "tests/data/1196175296_mwa_ord/1196175296_20171201145540_gpubox01_01.fits",
-                "tests/data/1196175296_mwa_ord/1196175296_20171201145540_gpubox02_01.fits",
-            ],
-        )
-        .unwrap()
+    /// The progress bars of reading this selection: one for each coarse
+    /// channel, and the total.
+    #[cfg(feature = "mwalib")]
+    fn read_progress_bars(&self, draw_progress: bool) -> (Vec<ProgressBar>, ProgressBar) {
+        let num_timesteps = self.timestep_range.len();
+        let num_coarse_chans = self.coarse_chan_range.len();
+
+        // Progress bar draw target
+        let draw_target = if draw_progress {
+            ProgressDrawTarget::stderr()
+        } else {
+            ProgressDrawTarget::hidden()
+        };
+        // a progress bar containing the progress bars associated with this method
+        let multi_progress = MultiProgress::with_draw_target(draw_target);
+        // a vector of progress bars for the visibility reading progress of each channel.
+        let read_progress: Vec<ProgressBar> = self
+            .coarse_chan_range
+            .clone()
+            .map(|mwalib_coarse_chan_idx| {
+                let channel_progress = multi_progress.add(
+                    ProgressBar::new(num_timesteps as _)
+                        .with_style(
+                            ProgressStyle::default_bar()
+                                .template("{msg:16}: [{wide_bar:.blue}] {pos:4}/{len:4}")
+                                .unwrap()
+                                .progress_chars("=> "),
+                        )
+                        .with_position(0)
+                        .with_message(format!("coarse_chan {:03}", mwalib_coarse_chan_idx)),
+                );
+                channel_progress.set_position(0);
+                channel_progress
+            })
+            .collect();
+        // The total reading progress bar.
+        let total_progress = multi_progress.add(
+            ProgressBar::new((num_timesteps * num_coarse_chans) as _)
+                .with_style(
+                    ProgressStyle::default_bar()
+                        .template(
+                            "{msg:16}: [{elapsed_precise}] [{wide_bar:.cyan/blue}] {percent:3}% ({eta:5})",
+                        )
+                        .unwrap()
+                        .progress_chars("=> "),
+                )
+                .with_position(0)
+                .with_message("loading hdus"),
+        );
+
+        (read_progress, total_progress)
     }
 
-    /// Get a dummy MWA Ord `corr_ctx` with multiple holes in the data
+    /// Iterate over chunks of at most `chunk_timesteps` timesteps of this
+    /// selection. Each chunk is only read from the gpubox files when it is
+    /// requested, so only one chunk needs to be in memory at a time.
     ///
-    /// The gpubox (batch, hdu) tuples look like this:
-    /// - ts is according to [`marlu::mwalib::correlatorContext`]
+    /// Each item is a [`VisChunk`]: the [`VisContext`] of the chunk (without
+    /// averaging), and its visibilities, weights and flags. Missing HDUs are
+    /// flagged, and flagged visibilities have a weight of -1.
     ///
-    /// |                   | ts=0   | 1      | 2      | 3      | 4      |
-    /// | ----------------- | ------ | ------ | ------ | ------ | ------ |
-    /// | gpubox=00         | (0, 0) | (0, 1) | .      | (1, 0) | .      |
-    /// | 01                | .      | (0, 0) | (0, 1) | (1, 0) | (1, 1) |
-    pub fn get_mwa_dodgy_context() -> CorrelatorContext {
-        CorrelatorContext::new(
-            "tests/data/1196175296_mwa_ord/1196175296.metafits",
-            &[
+    /// # Examples
+    ///
+    /// ```rust
+    /// use marlu::{mwalib::CorrelatorContext, VisSelection};
+    ///
+    /// let metafits_path = "tests/data/1297526432_mwax/1297526432.metafits";
+    /// let gpufits_paths = vec![
+    ///     "tests/data/1297526432_mwax/1297526432_20210216160014_ch117_000.fits",
+    ///     "tests/data/1297526432_mwax/1297526432_20210216160014_ch117_001.fits",
+    ///     "tests/data/1297526432_mwax/1297526432_20210216160014_ch118_000.fits",
+    ///     "tests/data/1297526432_mwax/1297526432_20210216160014_ch118_001.fits",
+    /// ];
+    /// let corr_ctx = CorrelatorContext::new(metafits_path, &gpufits_paths).unwrap();
+    /// let vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+    ///
+    /// for chunk in vis_sel.iter_chunks(&corr_ctx, 1) {
+    ///     let (vis_ctx, jones_array, weight_array, flag_array) = chunk.unwrap();
+    ///     assert_eq!(vis_ctx.num_sel_timesteps, 1);
+    ///     assert_eq!(jones_array.dim(), weight_array.dim());
+    ///     assert_eq!(jones_array.dim(), flag_array.dim());
+    /// }
+    /// ```
+    #[cfg(feature = "mwalib")]
+    pub fn iter_chunks<'a>(
+        &'a self,
+        corr_ctx: &'a CorrelatorContext,
+        chunk_timesteps: usize,
+    ) -> VisChunkIter<'a> {
+        VisChunkIter {
+            vis_sel: self,
+            corr_ctx,
+            chunk_timesteps: chunk_timesteps.max(1),
+            next_timestep: self.timestep_range.start,
+        }
+    }
+}
+
+/// The number of floats in a [`Jones<f32>`].
+#[cfg(feature = "mwalib")]
+const FLOATS_PER_JONES: usize = 8;
+
+// `Jones` is `repr(transparent)` over `[Complex<f32>; 4]`, and `Complex` is
+// `repr(C)` over its real and imaginary parts, so a `Jones<f32>` has the size
+// and alignment of `[f32; 8]`. This is what makes the reinterpretations below
+// sound.
+#[cfg(feature = "mwalib")]
+const _: () = assert!(
+    std::mem::size_of::<Jones<f32>>() == FLOATS_PER_JONES * std::mem::size_of::<f32>()
+        && std::mem::align_of::<Jones<f32>>() == std::mem::align_of::<f32>()
+);
+
+/// View Jones matrices as their floats, ordered `[pol][complex]`.
+#[cfg(feature = "mwalib")]
+fn jones_as_floats_mut(jones: &mut [Jones<f32>]) -> &mut [f32] {
+    // SAFETY: See the layout assertion above; any bit pattern is a valid
+    // `f32`, and the borrow of `jones` is held by the returned slice.
+    unsafe {
+        std::slice::from_raw_parts_mut(jones.as_mut_ptr().cast(), jones.len() * FLOATS_PER_JONES)
+    }
+}
+
+/// View floats, ordered `[pol][complex]`, as Jones matrices. Any floats after
+/// the last whole Jones matrix are ignored.
+#[cfg(feature = "mwalib")]
+fn floats_as_jones(floats: &[f32]) -> &[Jones<f32>] {
+    // SAFETY: See the layout assertion above; any `[f32; 8]` is a valid
+    // `Jones<f32>`, and the borrow of `floats` is held by the returned slice.
+    unsafe { std::slice::from_raw_parts(floats.as_ptr().cast(), floats.len() / FLOATS_PER_JONES) }
+}
+
+#[cfg(feature = "mwalib")]
+thread_local! {
+    /// HDU buffers that have been used by [`VisSelection::read_mwalib`] on this
+    /// thread, to be reused. At most [`max_pooled_hdu_buffers`] are kept.
+    static HDU_BUFFERS: RefCell<Vec<Vec<f32>>> = const { RefCell::new(vec![]) };
+}
+
+/// The most buffers to keep in each thread's pool. A thread only uses a few
+/// buffers at a time, but buffers taken on one thread may be dropped on
+/// another, so without a limit a pool could grow to hold every buffer.
+#[cfg(feature = "mwalib")]
+fn max_pooled_hdu_buffers() -> usize {
+    rayon::current_num_threads()
+}
+
+/// A buffer for the floats of an HDU. The buffer is taken from a thread-local
+/// pool and returned to it when dropped, so that large buffers aren't
+/// reallocated (and zeroed) for each coarse channel and each read.
+#[cfg(feature = "mwalib")]
+struct HduBuffer(Vec<f32>);
+
+#[cfg(feature = "mwalib")]
+impl HduBuffer {
+    /// Take a buffer of `len` floats from this thread's pool. The contents of
+    /// the buffer are unspecified.
+    fn take(len: usize) -> Self {
+        let mut buffer = HDU_BUFFERS
+            .with(|buffers| buffers.borrow_mut().pop())
+            .unwrap_or_default();
+        buffer.resize(len, 0.0);
+        Self(buffer)
+    }
+}
+
+#[cfg(feature = "mwalib")]
+impl Drop for HduBuffer {
+    fn drop(&mut self) {
+        let buffer = std::mem::take(&mut self.0);
+        // The pool may have already been destroyed if the thread is exiting,
+        // or be full, in which case the buffer is just freed.
+        let _ = HDU_BUFFERS.try_with(|buffers| {
+            let mut buffers = buffers.borrow_mut();
+            if buffers.len() < max_pooled_hdu_buffers() {
+                buffers.push(buffer);
+            }
+        });
+    }
+}
+
+#[cfg(feature = "mwalib")]
+impl Deref for HduBuffer {
+    type Target = Vec<f32>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(feature = "mwalib")]
+impl DerefMut for HduBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Flags derived from the metafits to apply while reading visibilities with
+/// [`VisSelection::read_mwalib_with_flag_policy`], in addition to the flags of
+/// missing HDUs. The default applies no additional flags.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlagPolicy {
+    /// Flag baselines with a tile that is flagged in the metafits (i.e. either
+    /// of its inputs are flagged).
+    pub flag_metafits_tiles: bool,
+    /// Indices of fine channels to flag within each coarse channel, e.g. the
+    /// edge and centre channels of the legacy MWA correlator.
+    pub flag_fine_chans: Vec<usize>,
+    /// Flag timesteps that start before the end of the metafits quack time,
+    /// while the array is still settling.
+    pub flag_quack_time: bool,
+}
+
+impl FlagPolicy {
+    /// Apply this policy to `flag_array`, which has dimensions
+    /// `[timestep][channel][baseline]` for `vis_sel`.
+    #[cfg(feature = "mwalib")]
+    pub(crate) fn apply(
+        &self,
+        corr_ctx: &CorrelatorContext,
+        vis_sel: &VisSelection,
+        mut flag_array: ArrayViewMut3<bool>,
+    ) {
+        let meta_ctx = &corr_ctx.metafits_context;
+
+        if self.flag_quack_time {
+            for (mut flags, timestep_idx) in flag_array
+                .outer_iter_mut()
+                .zip(vis_sel.timestep_range.clone())
+            {
+                if corr_ctx.timesteps[timestep_idx].unix_time_ms < meta_ctx.good_time_unix_ms {
+                    flags.fill(true);
+                }
+            }
+        }
+
+        if !self.flag_fine_chans.is_empty() {
+            let fine_chans_per_coarse = meta_ctx.num_corr_fine_chans_per_coarse;
+            for (chan_idx, mut flags) in flag_array.axis_iter_mut(Axis(1)).enumerate() {
+                if self
+                    .flag_fine_chans
+                    .contains(&(chan_idx % fine_chans_per_coarse))
+                {
+                    flags.fill(true);
+                }
+            }
+        }
+
+        if self.flag_metafits_tiles {
+            let flagged_tiles = TileBaselineMaps::from_mwalib(meta_ctx, true).flagged_tiles;
+            for (mut flags, &baseline_idx) in flag_array
+                .axis_iter_mut(Axis(2))
+                .zip(vis_sel.baseline_idxs.iter())
+            {
+                let (ant1, ant2) = baseline_to_tiles(meta_ctx.num_ants, baseline_idx);
+                if flagged_tiles.binary_search(&ant1).is_ok()
+                    || flagged_tiles.binary_search(&ant2).is_ok()
+                {
+                    flags.fill(true);
+                }
+            }
+        }
+    }
+}
+
+/// A chunk of visibilities read by [`VisChunkIter`]: the [`VisContext`] of the
+/// chunk, and its visibilities, weights and flags, each with dimensions
+/// `[timestep][channel][baseline]`.
+#[cfg(feature = "mwalib")]
+pub type VisChunk = (VisContext, Array3<Jones<f32>>, Array3<f32>, Array3<bool>);
+
+/// An iterator over chunks of timesteps of a [`VisSelection`], which reads each
+/// chunk lazily. See [`VisSelection::iter_chunks`].
+#[cfg(feature = "mwalib")]
+pub struct VisChunkIter<'a> {
+    vis_sel: &'a VisSelection,
+    corr_ctx: &'a CorrelatorContext,
+    chunk_timesteps: usize,
+    next_timestep: usize,
+}
+
+#[cfg(feature = "mwalib")]
+impl VisChunkIter<'_> {
+    fn read_chunk(&self, chunk_sel: &VisSelection) -> Result<VisChunk, SelectionError> {
+        let fine_chans_per_coarse = self
+            .corr_ctx
+            .metafits_context
+            .num_corr_fine_chans_per_coarse;
+        let mut jones_array = chunk_sel.allocate_jones(fine_chans_per_coarse)?;
+        let mut flag_array = chunk_sel.allocate_flags(fine_chans_per_coarse)?;
+        chunk_sel.read_mwalib(
+            self.corr_ctx,
+            jones_array.view_mut(),
+            flag_array.view_mut(),
+            false,
+        )?;
+        let weight_array = flag_array.mapv(|flag| WeightFlags::new(1.0, flag).to_signed());
+        let vis_ctx = VisContext::from_mwalib(
+            self.corr_ctx,
+            &chunk_sel.timestep_range,
+            &chunk_sel.coarse_chan_range,
+            &chunk_sel.baseline_idxs,
+            1,
+            1,
+        );
+        Ok((vis_ctx, jones_array, weight_array, flag_array))
+    }
+}
+
+#[cfg(feature = "mwalib")]
+impl Iterator for VisChunkIter<'_> {
+    type Item = Result<VisChunk, SelectionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let end = self.vis_sel.timestep_range.end;
+        if self.next_timestep >= end {
+            return None;
+        }
+        let chunk_end = (self.next_timestep + self.chunk_timesteps).min(end);
+        let chunk_sel = VisSelection {
+            timestep_range: self.next_timestep..chunk_end,
+            coarse_chan_range: self.vis_sel.coarse_chan_range.clone(),
+            baseline_idxs: self.vis_sel.baseline_idxs.clone(),
+        };
+        self.next_timestep = chunk_end;
+        Some(self.read_chunk(&chunk_sel))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self
+            .vis_sel
+            .timestep_range
+            .end
+            .saturating_sub(self.next_timestep);
+        let num_chunks = (remaining + self.chunk_timesteps - 1) / self.chunk_timesteps;
+        (num_chunks, Some(num_chunks))
+    }
+}
+
+/// A plan for processing a [`VisSelection`] in chunks of timesteps and coarse
+/// channels such that each chunk fits within a memory budget. The estimate
+/// includes the jones, weight and flag arrays of the chunk, the HDU buffers used
+/// while reading with [`VisSelection::read_mwalib`] and the buffers used by the
+/// writers while averaging.
+///
+/// Chunking in time is preferred; the selection is only chunked in frequency
+/// if a single averaged timestep of all the selected coarse channels doesn't
+/// fit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryPlan {
+    /// The number of timesteps in each chunk (the last chunk may have fewer).
+    /// This is a multiple of the time averaging factor, unless the selection
+    /// is smaller than that.
+    pub timesteps_per_chunk: usize,
+    /// The number of coarse channels in each chunk (the last chunk may have
+    /// fewer).
+    pub coarse_chans_per_chunk: usize,
+    /// The estimated peak memory use while processing a chunk \[bytes\].
+    pub bytes_per_chunk: usize,
+}
+
+impl MemoryPlan {
+    /// Find the largest chunking of `vis_sel` that fits in `budget_bytes`.
+    ///
+    /// `num_obs_baselines` is the number of baselines in the observation
+    /// (which determines the size of each HDU read), and `avg_time` is the time
+    /// averaging factor that the writers will use.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`SelectionError::InsufficientMemory`] if a single averaged
+    /// timestep of a single coarse channel doesn't fit in the budget.
+    pub fn new(
+        vis_sel: &VisSelection,
+        fine_chans_per_coarse: usize,
+        num_obs_baselines: usize,
+        avg_time: usize,
+        budget_bytes: usize,
+    ) -> Result<Self, SelectionError> {
+        let num_timesteps = vis_sel.timestep_range.len();
+        let num_coarse_chans = vis_sel.coarse_chan_range.len();
+        let avg_time = avg_time.max(1);
+        let min_timesteps = avg_time.min(num_timesteps).max(1);
+
+        let bytes_per_vis = std::mem::size_of::<Jones<f32>>()
+            + std::mem::size_of::<f32>()
+            + std::mem::size_of::<bool>();
+        // One HDU buffer of [baseline][chan][pol][re, im] floats per coarse channel.
+        let hdu_bytes = num_obs_baselines * fine_chans_per_coarse * 8 * std::mem::size_of::<f32>();
+        let bytes_for = |timesteps: usize, coarse_chans: usize| {
+            let vis_per_timestep =
+                coarse_chans * fine_chans_per_coarse * vis_sel.baseline_idxs.len();
+            // The writers hold onto an averaging window of timesteps.
+            (timesteps + avg_time) * vis_per_timestep * bytes_per_vis + coarse_chans * hdu_bytes
+        };
+
+        for coarse_chans in (1..=num_coarse_chans.max(1)).rev() {
+            let fixed_bytes = bytes_for(0, coarse_chans);
+            let bytes_per_timestep = bytes_for(1, coarse_chans) - fixed_bytes;
+            if budget_bytes < fixed_bytes {
+                continue;
+            }
+            let max_timesteps = (budget_bytes - fixed_bytes)
+                .checked_div(bytes_per_timestep)
+                .map_or(num_timesteps, |max| max.min(num_timesteps));
+            if max_timesteps < min_timesteps {
+                continue;
+            }
+            let timesteps = if max_timesteps == num_timesteps {
+                num_timesteps
+            } else {
+                max_timesteps / avg_time * avg_time
+            };
+            return Ok(Self {
+                timesteps_per_chunk: timesteps.max(min_timesteps),
+                coarse_chans_per_chunk: coarse_chans,
+                bytes_per_chunk: bytes_for(timesteps.max(min_timesteps), coarse_chans),
+            });
+        }
+
+        let need_bytes = bytes_for(min_timesteps, 1);
+        Err(SelectionError::InsufficientMemory {
+            need_gib: (need_bytes as f64 / 1024_f64.powi(3)).ceil() as usize,
+        })
+    }
+
+    /// Find the largest chunking of `vis_sel` that fits in `budget_bytes`,
+    /// using the observation details in a [`mwalib::CorrelatorContext`].
+    ///
+    /// # Errors
+    ///
+    /// See [`MemoryPlan::new`].
+    #[cfg(feature = "mwalib")]
+    pub fn from_mwalib(
+        vis_sel: &VisSelection,
+        corr_ctx: &CorrelatorContext,
+        avg_time: usize,
+        budget_bytes: usize,
+    ) -> Result<Self, SelectionError> {
+        Self::new(
+            vis_sel,
+            corr_ctx.metafits_context.num_corr_fine_chans_per_coarse,
+            corr_ctx.metafits_context.num_baselines,
+            avg_time,
+            budget_bytes,
+        )
+    }
+
+    /// Split `vis_sel` into the chunks described by this plan, ordered by
+    /// coarse channel chunk, then by timestep chunk.
+    pub fn chunks(&self, vis_sel: &VisSelection) -> Vec<VisSelection> {
+        let chunk_range = |range: &Range<usize>, size: usize| {
+            range
+                .clone()
+                .step_by(size.max(1))
+                .map(|start| start..(start + size.max(1)).min(range.end))
+                .collect::<Vec<_>>()
+        };
+        let timestep_chunks = chunk_range(&vis_sel.timestep_range, self.timesteps_per_chunk);
+        chunk_range(&vis_sel.coarse_chan_range, self.coarse_chans_per_chunk)
+            .into_iter()
+            .flat_map(|coarse_chan_range| {
+                timestep_chunks
+                    .iter()
+                    .map(move |timestep_range| VisSelection {
+                        timestep_range: timestep_range.clone(),
+                        coarse_chan_range: coarse_chan_range.clone(),
+                        baseline_idxs: vis_sel.baseline_idxs.clone(),
+                    })
+            })
+            .collect()
+    }
+}
+
+/// A mapping from the antenna indices of an observation to the antenna indices
+/// of an output from which some antennas (e.g. flagged tiles) have been
+/// dropped. Downstream tools (e.g. calibration) need this to map their results
+/// back to physical tiles.
+///
+/// # Examples
+///
+/// ```rust
+/// use marlu::selection::AntennaRenumbering;
+///
+/// let renumbering = AntennaRenumbering::from_dropped(4, &[1]);
+/// assert_eq!(renumbering.new_to_old(), vec![0, 2, 3]);
+/// assert_eq!(renumbering.map_baseline((2, 3)), Some((1, 2)));
+/// assert_eq!(renumbering.map_baseline((0, 1)), None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AntennaRenumbering {
+    /// For each original antenna index, the new antenna index, or [`None`] if
+    /// the antenna was dropped.
+    pub old_to_new: Vec<Option<usize>>,
+}
+
+impl AntennaRenumbering {
+    /// Create a renumbering for `num_ants` antennas, where the antenna indices
+    /// in `dropped` are removed and the remaining antennas keep their order.
+    pub fn from_dropped(num_ants: usize, dropped: &[usize]) -> Self {
+        let mut new_idx = 0;
+        let old_to_new = (0..num_ants)
+            .map(|old_idx| {
+                if dropped.contains(&old_idx) {
+                    None
+                } else {
+                    new_idx += 1;
+                    Some(new_idx - 1)
+                }
+            })
+            .collect();
+        Self { old_to_new }
+    }
+
+    /// The number of antennas after renumbering.
+    pub fn num_new_ants(&self) -> usize {
+        self.old_to_new.iter().flatten().count()
+    }
+
+    /// For each new antenna index, the original antenna index.
+    pub fn new_to_old(&self) -> Vec<usize> {
+        self.old_to_new
+            .iter()
+            .enumerate()
+            .filter_map(|(old_idx, new_idx)| new_idx.map(|_| old_idx))
+            .collect()
+    }
+
+    /// Map a baseline's original antenna indices to new antenna indices, or
+    /// [`None`] if either antenna was dropped.
+    pub fn map_baseline(&self, (ant1, ant2): (usize, usize)) -> Option<(usize, usize)> {
+        match (
+            self.old_to_new.get(ant1).copied().flatten(),
+            self.old_to_new.get(ant2).copied().flatten(),
+        ) {
+            (Some(new1), Some(new2)) => Some((new1, new2)),
+            _ => None,
+        }
+    }
+
+    /// Keep only the per-antenna items (e.g. antenna names or positions) of
+    /// antennas that have not been dropped.
+    pub fn filter_ants<T: Clone>(&self, items: &[T]) -> Vec<T> {
+        self.new_to_old()
+            .into_iter()
+            .map(|old_idx| items[old_idx].clone())
+            .collect()
+    }
+
+    /// Format the renumbering as a series of history messages, one per
+    /// antenna, e.g. `Antenna renumbering: 3 -> 2` or `Antenna renumbering: 4
+    /// -> dropped`.
+    pub fn as_comments(&self) -> Vec<String> {
+        self.old_to_new
+            .iter()
+            .enumerate()
+            .map(|(old_idx, new_idx)| match new_idx {
+                Some(new_idx) => format!("Antenna renumbering: {} -> {}", old_idx, new_idx),
+                None => format!("Antenna renumbering: {} -> dropped", old_idx),
+            })
+            .collect()
+    }
+
+    /// Write the renumbering as a machine-readable sidecar; a tab-separated
+    /// table with a header and an `old_index`, `new_index` row for each
+    /// original antenna. Dropped antennas have an empty `new_index`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`std::io::Error`] if the writer fails.
+    pub fn write_sidecar<W: std::io::Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(writer, "old_index\tnew_index")?;
+        for (old_idx, new_idx) in self.old_to_new.iter().enumerate() {
+            match new_idx {
+                Some(new_idx) => writeln!(writer, "{}\t{}", old_idx, new_idx)?,
+                None => writeln!(writer, "{}\t", old_idx)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Maps between the antenna (tile) pairs of baselines, mwalib baseline indices
+/// (which always include auto-correlations) and "unflagged" baseline indices,
+/// which only count the baselines between unflagged tiles.
+///
+/// # Examples
+///
+/// ```rust
+/// use marlu::selection::TileBaselineMaps;
+///
+/// let maps = TileBaselineMaps::new(4, &[1], false);
+/// assert_eq!(maps.num_unflagged_baselines(), 3);
+/// assert_eq!(maps.unflagged_baseline_to_tile_pair, vec![(0, 2), (0, 3), (2, 3)]);
+/// assert_eq!(maps.tile_pair_to_unflagged_baseline[&(2, 3)], 2);
+/// assert_eq!(maps.mwalib_baseline_idx((2, 3)), Some(8));
+/// assert_eq!(maps.mwalib_to_unflagged_baseline(8), Some(2));
+/// assert_eq!(maps.mwalib_to_unflagged_baseline(1), None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileBaselineMaps {
+    /// The total number of tiles, flagged or not.
+    pub num_tiles: usize,
+
+    /// The sorted indices of the flagged tiles.
+    pub flagged_tiles: Vec<usize>,
+
+    /// Whether auto-correlations are counted as unflagged baselines.
+    pub include_autos: bool,
+
+    /// For each unflagged baseline index, its tile pair.
+    pub unflagged_baseline_to_tile_pair: Vec<(usize, usize)>,
+
+    /// For each unflagged tile pair, its unflagged baseline index.
+    pub tile_pair_to_unflagged_baseline: HashMap<(usize, usize), usize>,
+}
+
+impl TileBaselineMaps {
+    /// Create the maps for `num_tiles` tiles, where the baselines of the tiles
+    /// in `flagged_tiles` are removed.
+    pub fn new(num_tiles: usize, flagged_tiles: &[usize], include_autos: bool) -> Self {
+        let mut flagged_tiles = flagged_tiles.to_vec();
+        flagged_tiles.sort_unstable();
+        flagged_tiles.dedup();
+
+        let unflagged_tiles: Vec<usize> = (0..num_tiles)
+            .filter(|tile| flagged_tiles.binary_search(tile).is_err())
+            .collect();
+        let offset = usize::from(!include_autos);
+        let unflagged_baseline_to_tile_pair: Vec<(usize, usize)> = unflagged_tiles
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &ant1)| {
+                unflagged_tiles[i + offset..]
+                    .iter()
+                    .map(move |&ant2| (ant1, ant2))
+            })
+            .collect();
+        let tile_pair_to_unflagged_baseline = unflagged_baseline_to_tile_pair
+            .iter()
+            .enumerate()
+            .map(|(idx, &pair)| (pair, idx))
+            .collect();
+
+        Self {
+            num_tiles,
+            flagged_tiles,
+            include_autos,
+            unflagged_baseline_to_tile_pair,
+            tile_pair_to_unflagged_baseline,
+        }
+    }
+
+    /// Create the maps for the antennas of an observation, where a tile is
+    /// flagged if either of its inputs are flagged in the metafits.
+    #[cfg(feature = "mwalib")]
+    pub fn from_mwalib(meta_ctx: &MetafitsContext, include_autos: bool) -> Self {
+        let flagged_tiles: Vec<usize> = meta_ctx
+            .antennas
+            .iter()
+            .enumerate()
+            .filter(|(_, ant)| ant.rfinput_x.flagged || ant.rfinput_y.flagged)
+            .map(|(idx, _)| idx)
+            .collect();
+        Self::new(meta_ctx.num_ants, &flagged_tiles, include_autos)
+    }
+
+    /// The number of unflagged baselines.
+    pub fn num_unflagged_baselines(&self) -> usize {
+        self.unflagged_baseline_to_tile_pair.len()
+    }
+
+    /// The indices of the unflagged tiles.
+    pub fn unflagged_tiles(&self) -> Vec<usize> {
+        (0..self.num_tiles)
+            .filter(|tile| self.flagged_tiles.binary_search(tile).is_err())
+            .collect()
+    }
+
+    /// The mwalib baseline index of a tile pair, or [`None`] if the pair isn't
+    /// ordered (`ant1 <= ant2`) or refers to a tile that doesn't exist.
+    pub fn mwalib_baseline_idx(&self, (ant1, ant2): (usize, usize)) -> Option<usize> {
+        if ant1 > ant2 || ant2 >= self.num_tiles {
+            return None;
+        }
+        Some(ant1 * (2 * self.num_tiles - ant1 + 1) / 2 + (ant2 - ant1))
+    }
+
+    /// The tile pair of an mwalib baseline index, or [`None`] if the index is
+    /// out of range.
+    pub fn mwalib_baseline_to_tile_pair(&self, baseline_idx: usize) -> Option<(usize, usize)> {
+        if baseline_idx >= self.num_tiles * (self.num_tiles + 1) / 2 {
+            return None;
+        }
+        Some(baseline_to_tiles(self.num_tiles, baseline_idx))
+    }
+
+    /// The unflagged baseline index of an mwalib baseline index, or [`None`] if
+    /// the baseline is flagged (or an excluded auto-correlation).
+    pub fn mwalib_to_unflagged_baseline(&self, baseline_idx: usize) -> Option<usize> {
+        self.mwalib_baseline_to_tile_pair(baseline_idx)
+            .and_then(|pair| self.tile_pair_to_unflagged_baseline.get(&pair).copied())
+    }
+
+    /// The mwalib baseline index of an unflagged baseline index, or [`None`] if
+    /// the index is out of range.
+    pub fn unflagged_to_mwalib_baseline(&self, unflagged_idx: usize) -> Option<usize> {
+        self.unflagged_baseline_to_tile_pair
+            .get(unflagged_idx)
+            .and_then(|&pair| self.mwalib_baseline_idx(pair))
+    }
+
+    /// The mwalib baseline indices of all unflagged baselines, in unflagged
+    /// order, e.g. for [`VisSelection::baseline_idxs`].
+    pub fn baseline_idxs(&self) -> Vec<usize> {
+        (0..self.num_unflagged_baselines())
+            .filter_map(|idx| self.unflagged_to_mwalib_baseline(idx))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "mwalib")]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use crate::{ndarray::s, weights::is_flagged, Complex};
+
+    use super::*;
+
+    pub fn get_mwax_context() -> CorrelatorContext {
+        CorrelatorContext::new(
+            "tests/data/1297526432_mwax/1297526432.metafits",
+            &[
+                "tests/data/1297526432_mwax/1297526432_20210216160014_ch117_000.fits",
+                "tests/data/1297526432_mwax/1297526432_20210216160014_ch117_001.fits",
+                "tests/data/1297526432_mwax/1297526432_20210216160014_ch118_000.fits",
+                "tests/data/1297526432_mwax/1297526432_20210216160014_ch118_001.fits",
+            ],
+        )
+        .unwrap()
+    }
+
+    pub fn get_mwa_legacy_context() -> CorrelatorContext {
+        CorrelatorContext::new(
+            "tests/data/1196175296_mwa_ord/1196175296.metafits",
+            &[
+                "tests/data/1196175296_mwa_ord/1196175296_20171201145440_gpubox01_00.fits",
+                "tests/data/1196175296_mwa_ord/1196175296_20171201145440_gpubox02_00.fits",
+                "tests/data/1196175296_mwa_ord/1196175296_20171201145540_gpubox01_01.fits",
+                "tests/data/1196175296_mwa_ord/1196175296_20171201145540_gpubox02_01.fits",
+            ],
+        )
+        .unwrap()
+    }
+
+    /// Get a dummy MWA Ord `corr_ctx` with multiple holes in the data
+    ///
+    /// The gpubox (batch, hdu) tuples look like this:
+    /// - ts is according to [`marlu::mwalib::correlatorContext`]
+    ///
+    /// |                   | ts=0   | 1      | 2      | 3      | 4      |
+    /// | ----------------- | ------ | ------ | ------ | ------ | ------ |
+    /// | gpubox=00         | (0, 0) | (0, 1) | .      | (1, 0) | .      |
+    /// | 01                | .      | (0, 0) | (0, 1) | (1, 0) | (1, 1) |
+    pub fn get_mwa_dodgy_context() -> CorrelatorContext {
+        CorrelatorContext::new(
+            "tests/data/1196175296_mwa_ord/1196175296.metafits",
+            &[
                 "tests/data/1196175296_mwa_ord/adjusted_-1/1196175296_20171201145440_gpubox01_00.fits",
                 "tests/data/1196175296_mwa_ord/limited_1/1196175296_20171201145540_gpubox01_01.fits",
                 "tests/data/1196175296_mwa_ord/1196175296_20171201145440_gpubox02_00.fits",
@@ -679,6 +1663,46 @@ mod tests {
         assert!(flag_array[(3, 2, 0)]);
     }
 
+    #[test]
+    fn test_iter_chunks_matches_read_mwalib() {
+        let corr_ctx = get_mwa_dodgy_context();
+        let vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+        let fine_chans_per_coarse = corr_ctx.metafits_context.num_corr_fine_chans_per_coarse;
+        let mut flag_array = vis_sel.allocate_flags(fine_chans_per_coarse).unwrap();
+        let mut jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+        vis_sel
+            .read_mwalib(
+                &corr_ctx,
+                jones_array.view_mut(),
+                flag_array.view_mut(),
+                false,
+            )
+            .unwrap();
+
+        let chunks = vis_sel.iter_chunks(&corr_ctx, 3);
+        let num_timesteps = vis_sel.timestep_range.len();
+        assert_eq!(chunks.size_hint().0, (num_timesteps + 2) / 3);
+
+        let mut timestep_offset = 0;
+        for chunk in chunks {
+            let (vis_ctx, chunk_jones, chunk_weights, chunk_flags) = chunk.unwrap();
+            let chunk_timesteps = vis_ctx.num_sel_timesteps;
+            assert!(chunk_timesteps <= 3);
+            assert_eq!(vis_ctx.sel_dims(), chunk_jones.dim());
+            let timesteps = timestep_offset..timestep_offset + chunk_timesteps;
+            assert_abs_diff_eq!(
+                chunk_jones,
+                jones_array.slice(s![timesteps.clone(), .., ..])
+            );
+            assert_eq!(chunk_flags, flag_array.slice(s![timesteps, .., ..]));
+            for (&weight, &flag) in chunk_weights.iter().zip(chunk_flags.iter()) {
+                assert_eq!(is_flagged(weight), flag);
+            }
+            timestep_offset += chunk_timesteps;
+        }
+        assert_eq!(timestep_offset, num_timesteps);
+    }
+
     #[test]
     fn test_read_mwalib_mwax() {
         let corr_ctx = get_mwax_context();
@@ -775,6 +1799,238 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_read_mwalib_f64_matches_f32() {
+        let corr_ctx = get_mwax_context();
+        let vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+        let fine_chans_per_coarse = corr_ctx.metafits_context.num_corr_fine_chans_per_coarse;
+        let mut flag_array = vis_sel.allocate_flags(fine_chans_per_coarse).unwrap();
+        let mut jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+        vis_sel
+            .read_mwalib(
+                &corr_ctx,
+                jones_array.view_mut(),
+                flag_array.view_mut(),
+                false,
+            )
+            .unwrap();
+
+        let mut flag_array_f64 = vis_sel.allocate_flags(fine_chans_per_coarse).unwrap();
+        let mut jones_array_f64 = vis_sel.allocate_jones_f64(fine_chans_per_coarse).unwrap();
+        vis_sel
+            .read_mwalib_f64(
+                &corr_ctx,
+                jones_array_f64.view_mut(),
+                flag_array_f64.view_mut(),
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(flag_array_f64, flag_array);
+        assert_abs_diff_eq!(jones_array_f64, jones_array.mapv(Jones::<f64>::from));
+    }
+
+    #[test]
+    fn test_read_mwalib_fast_matches_read_mwalib() {
+        for corr_ctx in [get_mwax_context(), get_mwa_legacy_context()] {
+            let all_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+            // Every other baseline, which can't be read directly into the
+            // jones array.
+            let some_sel = VisSelection {
+                baseline_idxs: all_sel.baseline_idxs.iter().copied().step_by(2).collect(),
+                ..all_sel.clone()
+            };
+            let fine_chans_per_coarse = corr_ctx.metafits_context.num_corr_fine_chans_per_coarse;
+            for vis_sel in [all_sel, some_sel] {
+                let mut flag_array = vis_sel.allocate_flags(fine_chans_per_coarse).unwrap();
+                let mut jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+                vis_sel
+                    .read_mwalib(
+                        &corr_ctx,
+                        jones_array.view_mut(),
+                        flag_array.view_mut(),
+                        false,
+                    )
+                    .unwrap();
+
+                let mut fast_flag_array = vis_sel.allocate_flags(fine_chans_per_coarse).unwrap();
+                let mut fast_jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+                vis_sel
+                    .read_mwalib_fast(
+                        &corr_ctx,
+                        fast_jones_array.view_mut(),
+                        fast_flag_array.view_mut(),
+                        false,
+                    )
+                    .unwrap();
+
+                assert_eq!(fast_flag_array, flag_array);
+                assert_eq!(fast_jones_array, jones_array);
+            }
+        }
+    }
+
+    #[test]
+    fn test_jones_float_views() {
+        let mut jones = vec![Jones::<f32>::identity(); 2];
+        let floats = jones_as_floats_mut(&mut jones);
+        assert_eq!(floats.len(), 16);
+        assert_eq!(&floats[..8], &[1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        floats[9] = 2.0;
+        assert_eq!(jones[1][0], Complex::new(1.0, 2.0));
+
+        let floats: Vec<f32> = (0..17).map(|i| i as f32).collect();
+        let jones = floats_as_jones(&floats);
+        assert_eq!(jones.len(), 2);
+        assert_eq!(jones[1][3], Complex::new(14.0, 15.0));
+    }
+
+    #[test]
+    fn test_hdu_buffers_are_reused() {
+        let buffer = HduBuffer::take(16);
+        assert_eq!(buffer.len(), 16);
+        let ptr = buffer.as_ptr();
+        drop(buffer);
+
+        // A smaller buffer on the same thread reuses the allocation.
+        let buffer = HduBuffer::take(8);
+        assert_eq!(buffer.len(), 8);
+        assert_eq!(buffer.as_ptr(), ptr);
+
+        // While it's in use, another buffer is allocated.
+        let other = HduBuffer::take(8);
+        assert_ne!(other.as_ptr(), ptr);
+        drop((buffer, other));
+
+        // Only a limited number of buffers are kept.
+        let buffers: Vec<HduBuffer> = (0..max_pooled_hdu_buffers() + 2)
+            .map(|_| HduBuffer::take(8))
+            .collect();
+        drop(buffers);
+        assert_eq!(
+            HDU_BUFFERS.with(|buffers| buffers.borrow().len()),
+            max_pooled_hdu_buffers()
+        );
+    }
+
+    #[test]
+    fn test_trim_quack() {
+        let corr_ctx = get_mwa_legacy_context();
+        let good_time_unix_ms = corr_ctx.metafits_context.good_time_unix_ms;
+        let num_timesteps = corr_ctx.timesteps.len();
+        let num_quack_timesteps = corr_ctx
+            .timesteps
+            .iter()
+            .take_while(|timestep| timestep.unix_time_ms < good_time_unix_ms)
+            .count();
+        assert!(num_quack_timesteps > 0 && num_quack_timesteps < num_timesteps);
+
+        let mut vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+        assert!(vis_sel.timestep_range.start < num_quack_timesteps);
+        vis_sel.trim_quack(&corr_ctx);
+        assert_eq!(vis_sel.timestep_range, num_quack_timesteps..num_timesteps);
+
+        // A selection after the quack time is unchanged.
+        let range = num_quack_timesteps + 1..num_timesteps;
+        vis_sel.timestep_range = range.clone();
+        vis_sel.trim_quack(&corr_ctx);
+        assert_eq!(vis_sel.timestep_range, range);
+
+        // A selection within the quack time is emptied.
+        vis_sel.timestep_range = 0..num_quack_timesteps;
+        vis_sel.trim_quack(&corr_ctx);
+        assert!(vis_sel.timestep_range.is_empty());
+    }
+
+    #[test]
+    fn test_trim_partial_end() {
+        let corr_ctx = get_mwa_legacy_context();
+        let meta_ctx = &corr_ctx.metafits_context;
+        let num_timesteps = corr_ctx.timesteps.len();
+        let num_complete_timesteps = corr_ctx
+            .timesteps
+            .iter()
+            .take_while(|timestep| {
+                timestep.unix_time_ms + meta_ctx.corr_int_time_ms <= meta_ctx.sched_end_unix_time_ms
+            })
+            .count();
+        assert!(num_complete_timesteps > 2 && num_complete_timesteps < num_timesteps);
+
+        let mut vis_sel = VisSelection {
+            timestep_range: 0..num_timesteps,
+            ..VisSelection::from_mwalib(&corr_ctx).unwrap()
+        };
+        // Timesteps beyond the scheduled end are removed.
+        vis_sel.trim_partial_end(&corr_ctx, 1);
+        assert_eq!(vis_sel.timestep_range, 0..num_complete_timesteps);
+
+        // As are trailing timesteps that don't fill an averaged timestep.
+        vis_sel.timestep_range = 1..num_timesteps;
+        vis_sel.trim_partial_end(&corr_ctx, 2);
+        assert_eq!(
+            vis_sel.timestep_range,
+            1..1 + (num_complete_timesteps - 1) / 2 * 2
+        );
+
+        // The complete timesteps are unchanged.
+        vis_sel.timestep_range = 0..num_complete_timesteps;
+        vis_sel.trim_partial_end(&corr_ctx, 1);
+        assert_eq!(vis_sel.timestep_range, 0..num_complete_timesteps);
+    }
+
+    #[test]
+    fn test_read_mwalib_with_flag_policy() {
+        let corr_ctx = get_mwa_legacy_context();
+        let meta_ctx = &corr_ctx.metafits_context;
+        let vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+        let fine_chans_per_coarse = meta_ctx.num_corr_fine_chans_per_coarse;
+        let mut flag_array = vis_sel.allocate_flags(fine_chans_per_coarse).unwrap();
+        let mut jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+        vis_sel
+            .read_mwalib(
+                &corr_ctx,
+                jones_array.view_mut(),
+                flag_array.view_mut(),
+                false,
+            )
+            .unwrap();
+
+        let flag_policy = FlagPolicy {
+            flag_metafits_tiles: true,
+            flag_fine_chans: vec![0, fine_chans_per_coarse - 1],
+            flag_quack_time: true,
+        };
+        let mut policy_flag_array = vis_sel.allocate_flags(fine_chans_per_coarse).unwrap();
+        vis_sel
+            .read_mwalib_with_flag_policy(
+                &corr_ctx,
+                jones_array.view_mut(),
+                policy_flag_array.view_mut(),
+                &flag_policy,
+                false,
+            )
+            .unwrap();
+
+        let flagged_tiles = TileBaselineMaps::from_mwalib(meta_ctx, true).flagged_tiles;
+        for ((ts, chan, bl), &flag) in policy_flag_array.indexed_iter() {
+            let timestep = &corr_ctx.timesteps[vis_sel.timestep_range.start + ts];
+            let (ant1, ant2) = baseline_to_tiles(meta_ctx.num_ants, vis_sel.baseline_idxs[bl]);
+            let expected = flag_array[(ts, chan, bl)]
+                || timestep.unix_time_ms < meta_ctx.good_time_unix_ms
+                || flag_policy
+                    .flag_fine_chans
+                    .contains(&(chan % fine_chans_per_coarse))
+                || flagged_tiles.contains(&ant1)
+                || flagged_tiles.contains(&ant2);
+            assert_eq!(flag, expected, "ts={ts}, chan={chan}, bl={bl}");
+        }
+        // The edge channels are always flagged.
+        assert!(policy_flag_array
+            .slice(s![.., 0, ..])
+            .iter()
+            .all(|&flag| flag));
+    }
+
     #[test]
     #[allow(clippy::unnecessary_cast)]
     fn test_read_mwalib_mwa_legacy() {
@@ -927,4 +2183,129 @@ mod tests {
             ])
         );
     }
+
+    #[test]
+    fn test_split_autos() {
+        let corr_ctx = get_mwax_context();
+        let meta_ctx = &corr_ctx.metafits_context;
+        let vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+        let num_ants = meta_ctx.num_ants;
+
+        let (autos, crosses) = vis_sel.split_autos(meta_ctx);
+        assert_eq!(autos.baseline_idxs.len(), num_ants);
+        assert_eq!(crosses.baseline_idxs.len(), num_ants * (num_ants - 1) / 2);
+        assert_eq!(autos.timestep_range, vis_sel.timestep_range);
+        assert!(autos
+            .get_ant_pairs(meta_ctx)
+            .into_iter()
+            .all(|(ant1, ant2)| ant1 == ant2));
+        assert!(crosses
+            .get_ant_pairs(meta_ctx)
+            .into_iter()
+            .all(|(ant1, ant2)| ant1 != ant2));
+    }
+
+    #[test]
+    fn test_drop_antennas() {
+        let corr_ctx = get_mwa_legacy_context();
+        let meta_ctx = &corr_ctx.metafits_context;
+        let mut vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+        let num_ants = meta_ctx.num_ants;
+
+        let renumbering = vis_sel.drop_antennas(meta_ctx, &[1, 3]);
+        assert_eq!(renumbering.num_new_ants(), num_ants - 2);
+        // all baselines (including autos) without antennas 1 or 3 remain.
+        assert_eq!(
+            vis_sel.baseline_idxs.len(),
+            (num_ants - 2) * (num_ants - 1) / 2
+        );
+        let new_ant_pairs: Vec<_> = vis_sel
+            .get_ant_pairs(meta_ctx)
+            .into_iter()
+            .map(|pair| renumbering.map_baseline(pair).unwrap())
+            .collect();
+        assert_eq!(new_ant_pairs[0], (0, 0));
+        assert_eq!(new_ant_pairs[1], (0, 1));
+        assert_eq!(renumbering.new_to_old()[..3], [0, 2, 4]);
+
+        let mut sidecar = vec![];
+        renumbering.write_sidecar(&mut sidecar).unwrap();
+        let sidecar = String::from_utf8(sidecar).unwrap();
+        let mut lines = sidecar.lines();
+        assert_eq!(lines.next(), Some("old_index\tnew_index"));
+        assert_eq!(lines.next(), Some("0\t0"));
+        assert_eq!(lines.next(), Some("1\t"));
+        assert_eq!(
+            renumbering.as_comments()[3],
+            "Antenna renumbering: 3 -> dropped"
+        );
+    }
+
+    #[test]
+    fn test_tile_baseline_maps_matches_mwalib() {
+        let corr_ctx = get_mwa_legacy_context();
+        let meta_ctx = &corr_ctx.metafits_context;
+        let maps = TileBaselineMaps::new(meta_ctx.num_ants, &[], true);
+        for (idx, baseline) in meta_ctx.baselines.iter().enumerate() {
+            let pair = (baseline.ant1_index, baseline.ant2_index);
+            assert_eq!(maps.mwalib_baseline_idx(pair), Some(idx));
+            assert_eq!(maps.mwalib_baseline_to_tile_pair(idx), Some(pair));
+            assert_eq!(maps.mwalib_to_unflagged_baseline(idx), Some(idx));
+        }
+
+        let maps = TileBaselineMaps::new(meta_ctx.num_ants, &[3, 1], false);
+        let vis_sel = VisSelection {
+            baseline_idxs: maps.baseline_idxs(),
+            ..VisSelection::from_mwalib(&corr_ctx).unwrap()
+        };
+        assert_eq!(
+            vis_sel.get_ant_pairs(meta_ctx),
+            maps.unflagged_baseline_to_tile_pair
+        );
+        assert_eq!(maps.flagged_tiles, vec![1, 3]);
+        assert_eq!(maps.unflagged_tiles().len(), meta_ctx.num_ants - 2);
+    }
+
+    #[test]
+    fn test_memory_plan() {
+        let vis_sel = VisSelection {
+            timestep_range: 0..10,
+            coarse_chan_range: 0..4,
+            baseline_idxs: (0..8256).collect(),
+        };
+        let fine_chans_per_coarse = 32;
+        let bytes_per_vis = 8 * 4 + 4 + 1;
+        let hdu_bytes = 8256 * fine_chans_per_coarse * 8 * 4;
+        let timestep_bytes = 4 * fine_chans_per_coarse * 8256 * bytes_per_vis;
+
+        // Everything fits.
+        let plan = MemoryPlan::new(&vis_sel, fine_chans_per_coarse, 8256, 2, usize::MAX).unwrap();
+        assert_eq!(plan.timesteps_per_chunk, 10);
+        assert_eq!(plan.coarse_chans_per_chunk, 4);
+        assert_eq!(plan.chunks(&vis_sel).len(), 1);
+
+        // Only 5 timesteps fit; chunks must be a multiple of the averaging.
+        let budget = 4 * hdu_bytes + (2 + 5) * timestep_bytes;
+        let plan = MemoryPlan::new(&vis_sel, fine_chans_per_coarse, 8256, 2, budget).unwrap();
+        assert_eq!(plan.timesteps_per_chunk, 4);
+        assert_eq!(plan.coarse_chans_per_chunk, 4);
+        assert!(plan.bytes_per_chunk <= budget);
+        let chunks = plan.chunks(&vis_sel);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[2].timestep_range, 8..10);
+
+        // Not even a single averaged timestep of all coarse channels fits.
+        let budget = 4 * hdu_bytes + 3 * timestep_bytes;
+        let plan = MemoryPlan::new(&vis_sel, fine_chans_per_coarse, 8256, 2, budget).unwrap();
+        assert!(plan.coarse_chans_per_chunk < 4);
+        assert!(plan.bytes_per_chunk <= budget);
+        let chunks = plan.chunks(&vis_sel);
+        assert_eq!(chunks.first().unwrap().coarse_chan_range.start, 0);
+        assert_eq!(chunks.last().unwrap().coarse_chan_range.end, 4);
+
+        assert!(matches!(
+            MemoryPlan::new(&vis_sel, fine_chans_per_coarse, 8256, 2, 1024),
+            Err(SelectionError::InsufficientMemory { .. })
+        ));
+    }
 }
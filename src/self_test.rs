@@ -0,0 +1,456 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A self test of the C libraries that Marlu links against (ERFA, and
+//! optionally cfitsio and casacore).
+//!
+//! [`self_test`] writes a tiny embedded "golden" dataset with each of the
+//! enabled writers into a temporary directory, reads the files back, and
+//! checks header and data invariants. This is intended to be run at install
+//! time, e.g. on HPC systems where the linked libraries may not be the ones
+//! Marlu was tested with.
+
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::{pos::ErfaError, LatLngHeight};
+
+cfg_if::cfg_if! {
+    if #[cfg(any(feature = "cfitsio", feature = "ms"))] {
+        use hifitime::{Duration, Epoch, Unit};
+        use ndarray::prelude::*;
+        use crate::{
+            errors::IOError, AveragingPolicy, Complex, Jones, RADec, VisContext, VisUnit, VisWrite, ENH,
+        };
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "cfitsio")] {
+        use crate::{errors::UvfitsWriteError, Telescope, UvfitsWriter, XyzGeodetic};
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "ms")] {
+        use rubbl_casatables::{CasacoreError, Table, TableOpenMode};
+        use crate::{
+            c32, errors::MeasurementSetWriteError, MeasurementSetWriter, ObsContext,
+        };
+    }
+}
+
+/// The version of the self test. This is incremented whenever the golden
+/// dataset or the checks change.
+pub const SELF_TEST_VERSION: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum SelfTestError {
+    /// A value read back didn't match the golden dataset.
+    #[error("self test check {check} failed: expected {expected}, received {received}")]
+    Mismatch {
+        /// The name of the check
+        check: &'static str,
+        /// The expected value
+        expected: String,
+        /// The value that was received instead
+        received: String,
+    },
+
+    #[error(transparent)]
+    Erfa(#[from] ErfaError),
+
+    #[cfg(feature = "cfitsio")]
+    #[error(transparent)]
+    Uvfits(#[from] UvfitsWriteError),
+
+    #[cfg(feature = "cfitsio")]
+    #[error(transparent)]
+    Fitsio(#[from] fitsio::errors::Error),
+
+    #[cfg(feature = "ms")]
+    #[error(transparent)]
+    MeasurementSet(#[from] MeasurementSetWriteError),
+
+    #[cfg(any(feature = "cfitsio", feature = "ms"))]
+    #[error(transparent)]
+    Writer(#[from] IOError),
+
+    /// From Rubbl Casacore
+    #[cfg(feature = "ms")]
+    #[error("Rubbl CASACore error {inner:?}")]
+    CasacoreError { inner: CasacoreError },
+
+    /// From Rubbl
+    #[cfg(feature = "ms")]
+    #[error("Rubbl error {inner:?}")]
+    RubblError { inner: failure::Error },
+
+    /// An IO error.
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+}
+
+#[cfg(feature = "ms")]
+impl From<failure::Error> for SelfTestError {
+    fn from(inner: failure::Error) -> Self {
+        Self::RubblError { inner }
+    }
+}
+
+#[cfg(feature = "ms")]
+impl From<CasacoreError> for SelfTestError {
+    fn from(inner: CasacoreError) -> Self {
+        Self::CasacoreError { inner }
+    }
+}
+
+/// The results of a successful [`self_test`].
+#[derive(Debug, Clone)]
+pub struct SelfTestReport {
+    /// See [`SELF_TEST_VERSION`].
+    pub version: u32,
+
+    /// The version of Marlu that ran the self test.
+    pub marlu_version: &'static str,
+
+    /// A description of each check that passed.
+    pub checks: Vec<String>,
+}
+
+/// Run the self test in the system's temporary directory. See
+/// [`self_test_in`].
+///
+/// # Errors
+///
+/// See [`self_test_in`].
+pub fn self_test() -> Result<SelfTestReport, SelfTestError> {
+    self_test_in(std::env::temp_dir())
+}
+
+/// Run the self test, writing temporary files into a new subdirectory of
+/// `dir`. The subdirectory is removed if all checks pass.
+///
+/// # Errors
+///
+/// Will return [`SelfTestError::Mismatch`] if a check fails, or another
+/// [`SelfTestError`] if a library call fails outright.
+pub fn self_test_in<P: AsRef<Path>>(dir: P) -> Result<SelfTestReport, SelfTestError> {
+    let test_dir = dir
+        .as_ref()
+        .join(format!("marlu_self_test_{}", std::process::id()));
+    std::fs::create_dir_all(&test_dir)?;
+
+    let mut checks = vec![];
+    check_erfa(&mut checks)?;
+    #[cfg(feature = "cfitsio")]
+    check_uvfits(&Golden::new(), &test_dir, &mut checks)?;
+    #[cfg(feature = "ms")]
+    check_ms(&Golden::new(), &test_dir, &mut checks)?;
+
+    std::fs::remove_dir_all(&test_dir)?;
+    Ok(SelfTestReport {
+        version: SELF_TEST_VERSION,
+        marlu_version: crate::built_info::PKG_VERSION,
+        checks,
+    })
+}
+
+fn mismatch<T: std::fmt::Debug>(check: &'static str, expected: T, received: T) -> SelfTestError {
+    SelfTestError::Mismatch {
+        check,
+        expected: format!("{:?}", expected),
+        received: format!("{:?}", received),
+    }
+}
+
+/// The embedded golden dataset: 3 tiles (including autos), 2 timesteps and 3
+/// channels, with visibilities that encode their own indices.
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
+struct Golden {
+    vis_ctx: VisContext,
+    array_pos: LatLngHeight,
+    phase_centre: RADec,
+    ant_names: Vec<String>,
+    ant_positions_enh: Vec<ENH>,
+    vis: Array3<Jones<f32>>,
+    weights: Array3<f32>,
+}
+
+#[cfg(any(feature = "cfitsio", feature = "ms"))]
+impl Golden {
+    fn new() -> Self {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 2,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_f64(2., Unit::Second),
+            num_sel_chans: 3,
+            start_freq_hz: 150_000_000.,
+            freq_resolution_hz: 40_000.,
+            sel_baselines: vec![(0, 0), (0, 1), (0, 2), (1, 1), (1, 2), (2, 2)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+            averaging_policy: AveragingPolicy::default(),
+            vis_unit: VisUnit::default(),
+            vis_scale: 1.0,
+        };
+        let vis = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| {
+            let value = (100 * t + 10 * c + b) as f32;
+            Jones::from([
+                Complex::new(value, 0.0),
+                Complex::new(value, 1.0),
+                Complex::new(value, 2.0),
+                Complex::new(value, 3.0),
+            ])
+        });
+        let weights = Array3::from_elem(vis_ctx.sel_dims(), 1.0);
+        Self {
+            vis_ctx,
+            array_pos: LatLngHeight::new_mwa(),
+            phase_centre: RADec::new_degrees(0.0, -27.0),
+            ant_names: vec!["Tile011".into(), "Tile012".into(), "Tile013".into()],
+            ant_positions_enh: vec![
+                ENH {
+                    e: 0.0,
+                    n: 0.0,
+                    h: 0.0,
+                },
+                ENH {
+                    e: 100.0,
+                    n: -50.0,
+                    h: 1.0,
+                },
+                ENH {
+                    e: -30.0,
+                    n: 70.0,
+                    h: -1.0,
+                },
+            ],
+            vis,
+            weights,
+        }
+    }
+
+    #[cfg(feature = "cfitsio")]
+    fn ant_positions_geodetic(&self) -> Vec<XyzGeodetic> {
+        self.ant_positions_enh
+            .iter()
+            .map(|enh| enh.to_xyz(self.array_pos.latitude_rad))
+            .collect()
+    }
+}
+
+/// Check that converting the array position to geocentric coordinates and
+/// back is lossless, and that the geocentric position is on the Earth's
+/// surface.
+fn check_erfa(checks: &mut Vec<String>) -> Result<(), SelfTestError> {
+    let array_pos = LatLngHeight::new_mwa();
+    let geocentric = array_pos.to_geocentric_wgs84()?;
+    let radius_m = (geocentric.x.powi(2) + geocentric.y.powi(2) + geocentric.z.powi(2)).sqrt();
+    if !(6.35e6..6.40e6).contains(&radius_m) {
+        return Err(mismatch("erfa geocentric radius", 6.37e6, radius_m));
+    }
+    let round_trip = geocentric.to_earth_wgs84()?;
+    for (expected, received) in [
+        (array_pos.longitude_rad, round_trip.longitude_rad),
+        (array_pos.latitude_rad, round_trip.latitude_rad),
+    ] {
+        if (expected - received).abs() > 1e-9 {
+            return Err(mismatch("erfa geodetic round trip", expected, received));
+        }
+    }
+    if (array_pos.height_metres - round_trip.height_metres).abs() > 1e-3 {
+        return Err(mismatch(
+            "erfa geodetic round trip",
+            array_pos.height_metres,
+            round_trip.height_metres,
+        ));
+    }
+    checks.push("erfa: geodetic <-> geocentric round trip".into());
+    Ok(())
+}
+
+#[cfg(feature = "cfitsio")]
+fn check_uvfits(
+    golden: &Golden,
+    test_dir: &Path,
+    checks: &mut Vec<String>,
+) -> Result<(), SelfTestError> {
+    let path = test_dir.join("golden.uvfits");
+    let vis_ctx = &golden.vis_ctx;
+    let mut writer = UvfitsWriter::from_marlu(
+        &path,
+        vis_ctx,
+        &Telescope::new(
+            "MWA",
+            golden.array_pos,
+            golden.ant_names.clone(),
+            golden.ant_positions_geodetic(),
+        ),
+        golden.phase_centre,
+        Duration::from_f64(0.0, Unit::Second),
+        Some("golden"),
+        None,
+    )?;
+    writer.write_vis(golden.vis.view(), golden.weights.view(), vis_ctx, false)?;
+    // Finalising also closes the file.
+    writer.finalise()?;
+
+    let mut fptr = fitsio::FitsFile::open(&path)?;
+    let hdu = fptr.primary_hdu()?;
+    let (num_timesteps, num_chans, num_baselines) = vis_ctx.sel_dims();
+    let gcount: i64 = hdu.read_key(&mut fptr, "GCOUNT")?;
+    if gcount != (num_timesteps * num_baselines) as i64 {
+        return Err(mismatch(
+            "uvfits GCOUNT",
+            (num_timesteps * num_baselines) as i64,
+            gcount,
+        ));
+    }
+    let naxis4: i64 = hdu.read_key(&mut fptr, "NAXIS4")?;
+    if naxis4 != num_chans as i64 {
+        return Err(mismatch("uvfits NAXIS4", num_chans as i64, naxis4));
+    }
+
+    // Read the data of the last group, with [chan][pol][re, im, weight], and
+    // uvfits pols ordered XX, YY, XY, YX.
+    let group_len = num_chans * 4 * 3;
+    let mut group = vec![0.0_f32; group_len];
+    let mut any_null = 0;
+    let mut status = 0;
+    unsafe {
+        // ffgpve = fits_read_img_flt
+        fitsio_sys::ffgpve(
+            fptr.as_raw(),
+            gcount as _,
+            1,
+            group_len as _,
+            0.0,
+            group.as_mut_ptr(),
+            &mut any_null,
+            &mut status,
+        );
+    }
+    fitsio::errors::check_status(status)?;
+    for (chan_idx, chan) in group.chunks(4 * 3).enumerate() {
+        let expected = golden.vis[(num_timesteps - 1, chan_idx, num_baselines - 1)];
+        let received = [chan[0], chan[1], chan[3], chan[4], chan[6], chan[7]];
+        let expected = [
+            expected[0].re,
+            expected[0].im,
+            expected[3].re,
+            expected[3].im,
+            expected[1].re,
+            expected[1].im,
+        ];
+        if received.iter().zip(expected).any(|(&received, expected)| {
+            (received - expected).abs() > f32::EPSILON * expected.abs()
+        }) {
+            return Err(mismatch("uvfits visibilities", expected, received));
+        }
+    }
+
+    let ant_hdu = fptr.hdu("AIPS AN")?;
+    let num_ants: i64 = ant_hdu.read_key(&mut fptr, "NAXIS2")?;
+    if num_ants != golden.ant_names.len() as i64 {
+        return Err(mismatch(
+            "uvfits antenna table rows",
+            golden.ant_names.len() as i64,
+            num_ants,
+        ));
+    }
+    checks.push("cfitsio: uvfits headers, visibilities and antenna table".into());
+    Ok(())
+}
+
+#[cfg(feature = "ms")]
+fn check_ms(
+    golden: &Golden,
+    test_dir: &Path,
+    checks: &mut Vec<String>,
+) -> Result<(), SelfTestError> {
+    let path = test_dir.join("golden.ms");
+    let vis_ctx = &golden.vis_ctx;
+    let obs_ctx = ObsContext {
+        sched_start_timestamp: vis_ctx.start_timestamp,
+        sched_duration: vis_ctx.int_time * vis_ctx.num_sel_timesteps as f64,
+        name: Some("golden".into()),
+        field_name: Some("golden".into()),
+        project_id: None,
+        observer: None,
+        phase_centre: golden.phase_centre,
+        pointing_centre: None,
+        array_pos: golden.array_pos,
+        telescope_name: "MWA".into(),
+        instrument: "MWA".into(),
+        ant_positions_enh: golden.ant_positions_enh.clone(),
+        ant_names: golden.ant_names.clone(),
+        ant_flags: vec![],
+        beams: vec![],
+    };
+    let mut writer = MeasurementSetWriter::new(
+        &path,
+        golden.phase_centre,
+        golden.array_pos,
+        obs_ctx.ant_positions_geodetic().collect(),
+        Duration::from_f64(0.0, Unit::Second),
+    );
+    writer.initialize(vis_ctx, &obs_ctx, None)?;
+    writer.write_vis(golden.vis.view(), golden.weights.view(), vis_ctx, false)?;
+    writer.finalise()?;
+
+    let mut main_table = Table::open(&path, TableOpenMode::Read)?;
+    let (num_timesteps, num_chans, num_baselines) = vis_ctx.sel_dims();
+    let num_rows = main_table.n_rows();
+    if num_rows != (num_timesteps * num_baselines) as u64 {
+        return Err(mismatch(
+            "measurement set rows",
+            (num_timesteps * num_baselines) as u64,
+            num_rows,
+        ));
+    }
+    // The last row is the last baseline of the last timestep, with DATA
+    // [chan][pol].
+    let data: Vec<c32> = main_table.get_cell_as_vec("DATA", num_rows - 1)?;
+    let expected: Vec<c32> = (0..num_chans)
+        .flat_map(|chan_idx| {
+            golden.vis[(num_timesteps - 1, chan_idx, num_baselines - 1)]
+                .iter()
+                .copied()
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    if data != expected {
+        return Err(mismatch("measurement set DATA", expected, data));
+    }
+
+    let ant_table = Table::open(&path.join("ANTENNA"), TableOpenMode::Read)?;
+    if ant_table.n_rows() != golden.ant_names.len() as u64 {
+        return Err(mismatch(
+            "measurement set ANTENNA rows",
+            golden.ant_names.len() as u64,
+            ant_table.n_rows(),
+        ));
+    }
+    checks.push("casacore: measurement set rows, DATA and ANTENNA table".into());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_test() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let report = self_test_in(tmp_dir.path()).unwrap();
+        assert_eq!(report.version, SELF_TEST_VERSION);
+        assert_eq!(
+            report.checks.len(),
+            1 + cfg!(feature = "cfitsio") as usize + cfg!(feature = "ms") as usize
+        );
+    }
+}
@@ -0,0 +1,311 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! System-equivalent flux density (SEFD) and sensitivity estimates from
+//! auto-correlations.
+//!
+//! The auto-correlations must be flux-calibrated (i.e. in Jy), in which case
+//! they are dominated by the system noise, and their power is the SEFD of the
+//! antenna. The expected noise of visibilities and images then follows from
+//! the radiometer equation.
+
+use std::cmp::Ordering;
+
+use itertools::izip;
+use ndarray::prelude::*;
+use thiserror::Error;
+
+use crate::{
+    averaging::{auto_power_spectra, AveragingError},
+    errors::BadArrayShape,
+    Jones, VisContext,
+};
+
+#[derive(Error, Debug)]
+pub enum SensitivityError {
+    #[error(transparent)]
+    BadArrayShape(#[from] BadArrayShape),
+
+    #[error(transparent)]
+    AveragingError(#[from] AveragingError),
+}
+
+/// Sensitivity estimates of a chunk of visibilities, from
+/// [`sensitivity_report`]. Polarisations are XX and YY.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensitivityReport {
+    /// The antenna index of each row of `sefd_jy`.
+    pub ants: Vec<usize>,
+
+    /// The median SEFD over channels of each antenna, with dimensions
+    /// `[antenna][pol]` \[Jy\].
+    pub sefd_jy: Array2<f64>,
+
+    /// The cross-correlation baselines of each row of `vis_noise_jy`.
+    pub baselines: Vec<(usize, usize)>,
+
+    /// The expected noise (the standard deviation of the real or imaginary
+    /// part) of a single visibility of each baseline, with dimensions
+    /// `[baseline][pol]` \[Jy\]. Baselines with an antenna without an SEFD are
+    /// NaN.
+    pub vis_noise_jy: Array2<f64>,
+
+    /// The expected noise of a naturally weighted Stokes I image of all of the
+    /// unflagged cross-correlations \[Jy\], or infinity if there aren't any.
+    pub image_noise_jy: f64,
+}
+
+/// The expected noise (the standard deviation of the real or imaginary part)
+/// of a visibility of a baseline between antennas with SEFDs `sefd1_jy` and
+/// `sefd2_jy` \[Jy\], with a bandwidth of `bandwidth_hz` and an integration time
+/// of `int_time_s`.
+pub fn visibility_noise_jy(
+    sefd1_jy: f64,
+    sefd2_jy: f64,
+    bandwidth_hz: f64,
+    int_time_s: f64,
+) -> f64 {
+    (sefd1_jy * sefd2_jy / (2.0 * bandwidth_hz.abs() * int_time_s)).sqrt()
+}
+
+/// Estimate the SEFD of each antenna and the expected noise of the
+/// visibilities from the (flux-calibrated) auto-correlations of a chunk of
+/// visibilities.
+///
+/// `jones_array` - visibilities with dimensions `[timestep][channel][baseline]`,
+///     including auto-correlations
+///
+/// `weight_array` - weights with dimensions `[timestep][channel][baseline]`.
+///     Negative weights are flagged.
+///
+/// `vis_ctx` - the timesteps, channels and baselines of the arrays. The noise
+///     of each visibility is for the channel width and integration time of
+///     `vis_ctx`.
+///
+/// The image noise is that of the inverse-variance weighted sum of the
+/// unflagged XX and YY cross-correlations, using the SEFD spectra (rather than
+/// their medians), halved for Stokes I = (XX + YY) / 2.
+///
+/// # Errors
+///
+/// Will return [`SensitivityError::BadArrayShape`] if the shapes of the arrays
+/// don't match `vis_ctx`.
+pub fn sensitivity_report(
+    jones_array: ArrayView3<Jones<f32>>,
+    weight_array: ArrayView3<f32>,
+    vis_ctx: &VisContext,
+) -> Result<SensitivityReport, SensitivityError> {
+    let sel_dims = vis_ctx.sel_dims();
+    for (argument, dim) in [
+        ("jones_array", jones_array.dim()),
+        ("weight_array", weight_array.dim()),
+    ] {
+        if dim != sel_dims {
+            return Err(SensitivityError::BadArrayShape(BadArrayShape {
+                argument,
+                function: "sensitivity_report",
+                expected: format!("{:?}", sel_dims),
+                received: format!("{:?}", dim),
+            }));
+        }
+    }
+    let bandwidth_hz = vis_ctx.freq_resolution_hz;
+    let int_time_s = vis_ctx.int_time.in_seconds();
+
+    // [antenna][channel][pol]
+    let (ants, sefd_spectra) =
+        auto_power_spectra(jones_array, weight_array, &vis_ctx.sel_baselines)?;
+    let mut sefd_jy = Array2::<f64>::from_elem((ants.len(), 2), f64::NAN);
+    for (mut sefd, ant_spectra) in sefd_jy.outer_iter_mut().zip(sefd_spectra.outer_iter()) {
+        for (sefd, pol_spectrum) in sefd.iter_mut().zip(ant_spectra.axis_iter(Axis(1))) {
+            *sefd = median(pol_spectrum.iter().copied());
+        }
+    }
+    let ant_rows: Vec<Option<usize>> = {
+        let num_ants = ants.iter().max().map_or(0, |&max| max + 1);
+        let mut ant_rows = vec![None; num_ants];
+        for (row, &ant) in ants.iter().enumerate() {
+            ant_rows[ant] = Some(row);
+        }
+        ant_rows
+    };
+    let row_of = |ant: usize| ant_rows.get(ant).copied().flatten();
+
+    let (cross_idxs, baselines): (Vec<usize>, Vec<(usize, usize)>) = vis_ctx
+        .sel_baselines
+        .iter()
+        .enumerate()
+        .filter(|(_, (ant1, ant2))| ant1 != ant2)
+        .map(|(idx, &bl)| (idx, bl))
+        .unzip();
+    let mut vis_noise_jy = Array2::<f64>::from_elem((baselines.len(), 2), f64::NAN);
+    // The sum of the inverse variances of the unflagged visibilities of each
+    // pol.
+    let mut inverse_variance_sums = [0.0_f64; 2];
+    for (&cross_idx, &(ant1, ant2), mut noise) in
+        izip!(&cross_idxs, &baselines, vis_noise_jy.outer_iter_mut())
+    {
+        let (row1, row2) = match (row_of(ant1), row_of(ant2)) {
+            (Some(row1), Some(row2)) => (row1, row2),
+            _ => continue,
+        };
+        for (pol, noise) in noise.iter_mut().enumerate() {
+            *noise = visibility_noise_jy(
+                sefd_jy[(row1, pol)],
+                sefd_jy[(row2, pol)],
+                bandwidth_hz,
+                int_time_s,
+            );
+        }
+
+        for (chan, weight_chan) in weight_array
+            .index_axis(Axis(2), cross_idx)
+            .axis_iter(Axis(1))
+            .enumerate()
+        {
+            let num_unflagged = weight_chan.iter().filter(|&&weight| weight > 0.0).count();
+            for (pol, sum) in inverse_variance_sums.iter_mut().enumerate() {
+                let variance = visibility_noise_jy(
+                    sefd_spectra[(row1, chan, pol)],
+                    sefd_spectra[(row2, chan, pol)],
+                    bandwidth_hz,
+                    int_time_s,
+                )
+                .powi(2);
+                if variance.is_finite() && variance > 0.0 {
+                    *sum += num_unflagged as f64 / variance;
+                }
+            }
+        }
+    }
+    // The noise of (XX + YY) / 2.
+    let image_noise_jy = 0.5
+        * inverse_variance_sums
+            .iter()
+            .map(|sum| 1.0 / sum)
+            .sum::<f64>()
+            .sqrt();
+
+    Ok(SensitivityReport {
+        ants,
+        sefd_jy,
+        baselines,
+        vis_noise_jy,
+        image_noise_jy,
+    })
+}
+
+/// The median of the finite `values`, or NaN if there aren't any.
+fn median(values: impl Iterator<Item = f64>) -> f64 {
+    let mut values: Vec<f64> = values.filter(|value| value.is_finite()).collect();
+    if values.is_empty() {
+        return f64::NAN;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use hifitime::{Duration, Epoch, Unit};
+
+    use super::*;
+    use crate::Complex;
+
+    #[test]
+    fn test_visibility_noise_jy() {
+        // 1000 Jy SEFDs, 40 kHz and 2 seconds.
+        assert_abs_diff_eq!(
+            visibility_noise_jy(1000.0, 1000.0, 40e3, 2.0),
+            1000.0 / (2.0 * 40e3 * 2.0_f64).sqrt(),
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(
+            visibility_noise_jy(100.0, 400.0, 1.0, 0.5),
+            200.0,
+            epsilon = 1e-12
+        );
+    }
+
+    #[test]
+    fn test_sensitivity_report() {
+        let num_ants = 4;
+        let mut baselines = vec![];
+        for ant1 in 0..num_ants {
+            for ant2 in ant1..num_ants {
+                baselines.push((ant1, ant2));
+            }
+        }
+        let vis_ctx = VisContext::builder()
+            .timesteps(
+                Epoch::from_gpst_seconds(1090008640.),
+                Duration::from_f64(2., Unit::Second),
+                3,
+            )
+            .channels(150e6, 40e3, 5)
+            .baselines(baselines.clone())
+            .build()
+            .unwrap();
+        // Every antenna has an SEFD of 1000 Jy in XX and 2000 Jy in YY, except
+        // for a channel of RFI.
+        let jones_array = Array3::from_shape_fn(vis_ctx.sel_dims(), |(_, chan, bl)| {
+            let (ant1, ant2) = baselines[bl];
+            let scale = if chan == 2 { 10.0 } else { 1.0 };
+            if ant1 == ant2 {
+                Jones::from([
+                    Complex::new(1000.0 * scale, 0.0),
+                    Complex::default(),
+                    Complex::default(),
+                    Complex::new(2000.0 * scale, 0.0),
+                ])
+            } else {
+                Jones::default()
+            }
+        });
+        let mut weight_array = Array3::from_elem(vis_ctx.sel_dims(), 1.0);
+        // Flag the RFI, and the whole of antenna 3.
+        weight_array.slice_mut(s![.., 2, ..]).fill(-1.0);
+        for (bl, &(ant1, ant2)) in baselines.iter().enumerate() {
+            if ant1 == 3 || ant2 == 3 {
+                weight_array.slice_mut(s![.., .., bl]).fill(-1.0);
+            }
+        }
+
+        let report = sensitivity_report(jones_array.view(), weight_array.view(), &vis_ctx).unwrap();
+        assert_eq!(report.ants, vec![0, 1, 2, 3]);
+        for ant in 0..3 {
+            assert_abs_diff_eq!(report.sefd_jy[(ant, 0)], 1000.0, epsilon = 1e-9);
+            assert_abs_diff_eq!(report.sefd_jy[(ant, 1)], 2000.0, epsilon = 1e-9);
+        }
+        assert!(report.sefd_jy[(3, 0)].is_nan());
+
+        assert_eq!(report.baselines.len(), 6);
+        assert_eq!(report.baselines[0], (0, 1));
+        let noise_xx = visibility_noise_jy(1000.0, 1000.0, 40e3, 2.0);
+        let noise_yy = visibility_noise_jy(2000.0, 2000.0, 40e3, 2.0);
+        assert_abs_diff_eq!(report.vis_noise_jy[(0, 0)], noise_xx, epsilon = 1e-9);
+        assert_abs_diff_eq!(report.vis_noise_jy[(0, 1)], noise_yy, epsilon = 1e-9);
+        assert!(report.vis_noise_jy[(2, 0)].is_nan());
+
+        // 3 unflagged baselines, 3 timesteps and 4 channels.
+        let num_vis = 3.0 * 3.0 * 4.0;
+        let expected = 0.5 * (noise_xx.powi(2) / num_vis + noise_yy.powi(2) / num_vis).sqrt();
+        assert_abs_diff_eq!(report.image_noise_jy, expected, epsilon = 1e-9);
+
+        assert!(matches!(
+            sensitivity_report(
+                jones_array.view(),
+                weight_array.slice(s![.., 1.., ..]),
+                &vis_ctx
+            ),
+            Err(SensitivityError::BadArrayShape(_))
+        ));
+    }
+}
@@ -133,6 +133,89 @@ pub fn sexagesimal_hms_to_float(h: f64, m: f64, s: f64) -> f64 {
     sexagesimal_dms_to_degrees(15.0 * h, 15.0 * m, 15.0 * s)
 }
 
+/// Characters that may separate the fields of any sexagesimal string.
+const SEXAGESIMAL_DELIMITERS: &[char] = &[':', ' ', '\t', 'm', 'M', 's', 'S', '\'', '"', '′', '″'];
+
+/// Parse a sexagesimal string with up to three fields into a float in the units
+/// of the first field. The fields may be separated by any of
+/// [`SEXAGESIMAL_DELIMITERS`] or `units`, and only the first field may have a
+/// sign (which also applies to the other fields, e.g. "-00:30:00" is -0.5).
+fn parse_sexagesimal(s: &str, units: &[char]) -> Result<f64, SexagesimalError> {
+    let trimmed = s.trim();
+    let (negative, unsigned) = match trimmed.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+
+    let mut fields = Vec::with_capacity(3);
+    for field in unsigned
+        .split(|c| SEXAGESIMAL_DELIMITERS.contains(&c) || units.contains(&c))
+        .filter(|field| !field.is_empty())
+    {
+        let value: f64 = field.parse()?;
+        if !value.is_finite() || value.is_sign_negative() {
+            return Err(SexagesimalError::InvalidField(s.to_string()));
+        }
+        fields.push(value);
+    }
+    if fields.is_empty() || fields.len() > 3 {
+        return Err(SexagesimalError::WrongFieldCount(s.to_string()));
+    }
+    if fields[1..].iter().any(|&field| field >= 60.0) {
+        return Err(SexagesimalError::InvalidField(s.to_string()));
+    }
+
+    let num = fields
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(field, divisor)| field / divisor)
+        .sum::<f64>();
+    Ok(if negative { -num } else { num })
+}
+
+/// Convert a sexagesimal-formatted string in "hours minutes seconds" to a
+/// float \[radians\]. The fields may be delimited by "h", "m" and "s", colons
+/// or whitespace, and the minutes and seconds may be omitted.
+///
+/// # Examples
+///
+/// ```
+/// # use marlu::{sexagesimal::{sexagesimal_hms_str_to_radians, SexagesimalError}};
+/// # use approx::assert_abs_diff_eq;
+/// # fn main() -> Result<(), SexagesimalError> {
+/// let f = sexagesimal_hms_str_to_radians("04h37m34.5s")?;
+/// assert_abs_diff_eq!(f.to_degrees(), 69.39375, epsilon = 1e-10);
+/// assert_abs_diff_eq!(f, sexagesimal_hms_str_to_radians("04:37:34.5")?);
+/// assert_abs_diff_eq!(f, sexagesimal_hms_str_to_radians("4 37 34.5")?);
+/// # Ok(())
+/// # }
+/// ```
+pub fn sexagesimal_hms_str_to_radians(hms: &str) -> Result<f64, SexagesimalError> {
+    Ok((15.0 * parse_sexagesimal(hms, &['h', 'H'])?).to_radians())
+}
+
+/// Convert a sexagesimal-formatted string in "degrees minutes seconds" to a
+/// float \[radians\]. The fields may be delimited by "d" (or "°"), "m" (or
+/// "'") and "s" (or "\""), colons or whitespace, and the minutes and seconds
+/// may be omitted.
+///
+/// # Examples
+///
+/// ```
+/// # use marlu::{sexagesimal::{sexagesimal_dms_str_to_radians, SexagesimalError}};
+/// # use approx::assert_abs_diff_eq;
+/// # fn main() -> Result<(), SexagesimalError> {
+/// let f = sexagesimal_dms_str_to_radians("-47d15m09s")?;
+/// assert_abs_diff_eq!(f.to_degrees(), -47.2525, epsilon = 1e-10);
+/// assert_abs_diff_eq!(f, sexagesimal_dms_str_to_radians("-47°15'09\"")?);
+/// assert_abs_diff_eq!(f, sexagesimal_dms_str_to_radians("-47:15:09")?);
+/// # Ok(())
+/// # }
+/// ```
+pub fn sexagesimal_dms_str_to_radians(dms: &str) -> Result<f64, SexagesimalError> {
+    Ok(parse_sexagesimal(dms, &['d', 'D', '°'])?.to_radians())
+}
+
 /// Convert a number in degrees to a sexagesimal-formatted string in "degrees
 /// minutes seconds".
 ///
@@ -208,6 +291,17 @@ pub enum SexagesimalError {
     #[error("Did not find 's' when attempting to read sexagesigmal string: {0}")]
     MissingS(String),
 
+    /// A field is negative (only the first field may have a sign), isn't
+    /// finite, or minutes or seconds aren't less than 60.
+    #[error("Invalid sexagesimal field in: {0}")]
+    InvalidField(String),
+
+    #[error("Right ascension is not in [0h, 24h): {0}")]
+    RaOutOfRange(String),
+
+    #[error("Declination is not in [-90d, 90d]: {0}")]
+    DecOutOfRange(String),
+
     #[error("{0}")]
     ParseFloat(#[from] std::num::ParseFloatError),
 }
@@ -251,6 +345,66 @@ mod tests {
         assert_abs_diff_eq!(f, -177.254425, epsilon = 1e-6);
     }
 
+    #[test]
+    fn test_sexagesimal_str_to_radians() {
+        let expected = (15.0 * (4.0 + 37.0 / 60.0 + 34.5 / 3600.0_f64)).to_radians();
+        for hms in ["04h37m34.5s", "04:37:34.5", "4 37 34.5", " +04H37M34.5S "] {
+            let f = sexagesimal_hms_str_to_radians(hms).unwrap();
+            assert_abs_diff_eq!(f, expected, epsilon = 1e-12);
+        }
+
+        let expected = -(47.0 + 15.0 / 60.0 + 9.0 / 3600.0_f64).to_radians();
+        for dms in [
+            "-47d15m09s",
+            "-47:15:09",
+            "-47 15 09",
+            "-47°15′09″",
+            "-47°15'09\"",
+        ] {
+            let f = sexagesimal_dms_str_to_radians(dms).unwrap();
+            assert_abs_diff_eq!(f, expected, epsilon = 1e-12);
+        }
+
+        // Fields may be omitted.
+        let f = sexagesimal_dms_str_to_radians("12d30m").unwrap();
+        assert_abs_diff_eq!(f, 12.5_f64.to_radians(), epsilon = 1e-12);
+        let f = sexagesimal_hms_str_to_radians("6h").unwrap();
+        assert_abs_diff_eq!(f, 90_f64.to_radians(), epsilon = 1e-12);
+
+        // The sign applies to all fields, even if the first field is 0.
+        let f = sexagesimal_dms_str_to_radians("-00:30:00").unwrap();
+        assert_abs_diff_eq!(f, -0.5_f64.to_radians(), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_sexagesimal_str_to_radians_errors() {
+        assert!(matches!(
+            sexagesimal_dms_str_to_radians(""),
+            Err(SexagesimalError::WrongFieldCount(_))
+        ));
+        assert!(matches!(
+            sexagesimal_dms_str_to_radians("1:2:3:4"),
+            Err(SexagesimalError::WrongFieldCount(_))
+        ));
+        assert!(matches!(
+            sexagesimal_dms_str_to_radians("10:-30:00"),
+            Err(SexagesimalError::InvalidField(_))
+        ));
+        assert!(matches!(
+            sexagesimal_dms_str_to_radians("10:60:00"),
+            Err(SexagesimalError::InvalidField(_))
+        ));
+        // Hours aren't degrees.
+        assert!(matches!(
+            sexagesimal_dms_str_to_radians("04h37m34.5s"),
+            Err(SexagesimalError::ParseFloat(_))
+        ));
+        assert!(matches!(
+            sexagesimal_hms_str_to_radians("ten:20:30"),
+            Err(SexagesimalError::ParseFloat(_))
+        ));
+    }
+
     #[test]
     fn tet_degrees_to_sexagesimal_dms() {
         let dms = degrees_to_sexagesimal_dms(-165.0169619);
@@ -0,0 +1,536 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Synthetic, but representative, Marlu inputs.
+//!
+//! These helpers make observation and visibility contexts, tile layouts, and
+//! visibility and weight cubes, with MWA-like dimensions without needing any
+//! data on disk. They are used by Marlu's tests and the `bench` module, and
+//! can be used by downstream projects to test or benchmark their own
+//! pipelines.
+//!
+//! [`make_vis_cube`] is quick, and suits benchmarks. [`VisSimulator`] makes
+//! visibilities of point sources with Gaussian noise and RFI, which suits
+//! tests of calibration, flagging or imaging code.
+//!
+//! All of the generated data is deterministic; the same arguments always
+//! produce the same output.
+
+use std::{f64::consts::TAU, ops::Range};
+
+use hifitime::{Duration, Epoch, Unit};
+use ndarray::{parallel::prelude::*, prelude::*, Zip};
+
+use crate::{
+    constants::VEL_C, pos::xyz::baselines_to_uvws, precession::precess_time, Complex, Jones,
+    LatLngHeight, ObsContext, RADec, VisContext, XyzGeodetic, ENH,
+};
+
+/// The GPS time of the first synthetic timestep \[seconds\].
+pub const SYNTH_START_GPS: f64 = 1_090_008_640.0;
+
+/// The radius of the synthetic array \[metres\]. This is roughly the extent of
+/// the MWA Phase I array.
+pub const SYNTH_ARRAY_RADIUS_M: f64 = 1500.0;
+
+/// The fraction of synthetic visibilities that are flagged.
+pub const SYNTH_FLAG_FRACTION: f64 = 0.01;
+
+/// A stateless hash of `seed` and `index` to a uniform value in `[0, 1)`.
+/// This is splitmix64, which is more than good enough for test data.
+pub(crate) fn uniform(seed: u64, index: u64) -> f64 {
+    let mut z = seed
+        .wrapping_add(index.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+        .wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+/// A stateless hash of `seed` and `index` to a standard normal value, with the
+/// Box-Muller transform.
+fn gaussian(seed: u64, index: u64) -> f64 {
+    // 1 - uniform is in (0, 1], so the log is finite.
+    let r = (-2.0 * (1.0 - uniform(seed, 2 * index)).ln()).sqrt();
+    r * (TAU * uniform(seed, 2 * index + 1)).cos()
+}
+
+/// Positions for `num_ants` antennas scattered within
+/// [`SYNTH_ARRAY_RADIUS_M`] of the array centre.
+pub fn make_ant_positions(num_ants: usize) -> Vec<ENH> {
+    (0..num_ants as u64)
+        .map(|i| {
+            // The square root makes the antennas uniform in area, rather than
+            // bunched up in the middle.
+            let r = SYNTH_ARRAY_RADIUS_M * uniform(1, 2 * i).sqrt();
+            let theta = std::f64::consts::TAU * uniform(1, 2 * i + 1);
+            ENH {
+                e: r * theta.cos(),
+                n: r * theta.sin(),
+                h: 0.0,
+            }
+        })
+        .collect()
+}
+
+/// An MWA-like [`ObsContext`] with `num_ants` antennas at the positions from
+/// [`make_ant_positions`], phased to RA 0°, Dec. -27°.
+pub fn make_obs_ctx(num_ants: usize) -> ObsContext {
+    let phase_centre = RADec::new_degrees(0.0, -27.0);
+    ObsContext {
+        sched_start_timestamp: Epoch::from_gpst_seconds(SYNTH_START_GPS),
+        sched_duration: Duration::from_f64(120.0, Unit::Second),
+        name: Some("synthetic".into()),
+        field_name: Some("synthetic".into()),
+        project_id: None,
+        observer: None,
+        phase_centre,
+        pointing_centre: Some(phase_centre),
+        array_pos: LatLngHeight::new_mwa(),
+        telescope_name: "MWA".into(),
+        instrument: "MWA".into(),
+        ant_positions_enh: make_ant_positions(num_ants),
+        ant_names: (0..num_ants).map(|i| format!("Tile{:03}", i + 1)).collect(),
+        ant_flags: vec![false; num_ants],
+        beams: vec![],
+    }
+}
+
+/// The [`XyzGeodetic`] positions of the antennas from [`make_ant_positions`].
+pub fn make_xyzs(num_ants: usize) -> Vec<XyzGeodetic> {
+    let latitude_rad = LatLngHeight::new_mwa().latitude_rad;
+    make_ant_positions(num_ants)
+        .into_iter()
+        .map(|enh| enh.to_xyz(latitude_rad))
+        .collect()
+}
+
+/// A [`VisContext`] for `num_timesteps` 2s timesteps and `num_chans` 40kHz
+/// channels (from 167.68MHz) of every cross-correlation baseline of
+/// `num_ants` antennas, like an MWA Phase I observation.
+pub fn make_vis_ctx(num_ants: usize, num_timesteps: usize, num_chans: usize) -> VisContext {
+    let sel_baselines = (0..num_ants)
+        .flat_map(|ant1| (ant1 + 1..num_ants).map(move |ant2| (ant1, ant2)))
+        .collect();
+    VisContext::builder()
+        .timesteps(
+            Epoch::from_gpst_seconds(SYNTH_START_GPS),
+            Duration::from_f64(2.0, Unit::Second),
+            num_timesteps,
+        )
+        .channels(167.68e6, 40e3, num_chans)
+        .baselines(sel_baselines)
+        .build()
+        .expect("synthetic visibility context is always valid")
+}
+
+/// Make visibility and weight cubes with the shape of `vis_ctx`
+/// (`[timestep][channel][baseline]`).
+///
+/// The visibilities are an unpolarised 1Jy source at the phase centre plus
+/// uniform noise, and the weights are 1, except for
+/// [`SYNTH_FLAG_FRACTION`] of them, which are flagged (-1). Different `seed`s
+/// produce different noise and flags.
+pub fn make_vis_cube(vis_ctx: &VisContext, seed: u64) -> (Array3<Jones<f32>>, Array3<f32>) {
+    let shape = vis_ctx.sel_dims();
+    let (_, num_chans, num_baselines) = shape;
+    let index = |(t, c, b): (usize, usize, usize)| ((t * num_chans + c) * num_baselines + b) as u64;
+
+    let jones_array = Array3::from_shape_fn(shape, |idx| {
+        let i = 8 * index(idx);
+        let noise = |j: u64| (uniform(seed, i + j) - 0.5) as f32;
+        Jones::from([
+            Complex::new(1.0 + noise(0), noise(1)),
+            Complex::new(noise(2), noise(3)),
+            Complex::new(noise(4), noise(5)),
+            Complex::new(1.0 + noise(6), noise(7)),
+        ])
+    });
+    let weight_array = Array3::from_shape_fn(shape, |idx| {
+        if uniform(!seed, index(idx)) < SYNTH_FLAG_FRACTION {
+            -1.0
+        } else {
+            1.0
+        }
+    });
+    (jones_array, weight_array)
+}
+
+/// The frequency at which the flux densities of [`PointSource`]s are
+/// specified \[Hz\].
+pub const SYNTH_REF_FREQ_HZ: f64 = 150e6;
+
+/// An unpolarised point source with a power-law spectrum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointSource {
+    /// The position of the source.
+    pub radec: RADec,
+    /// The flux density at [`SYNTH_REF_FREQ_HZ`] \[Jy\].
+    pub flux_density_jy: f64,
+    /// The spectral index, i.e. the flux density is proportional to
+    /// `freq^spectral_index`.
+    pub spectral_index: f64,
+}
+
+impl PointSource {
+    /// A source with a flat spectrum.
+    pub fn new(radec: RADec, flux_density_jy: f64) -> Self {
+        Self {
+            radec,
+            flux_density_jy,
+            spectral_index: 0.0,
+        }
+    }
+
+    /// The flux density of this source at `freq_hz` \[Jy\].
+    pub fn flux_density_at(&self, freq_hz: f64) -> f64 {
+        self.flux_density_jy * (freq_hz / SYNTH_REF_FREQ_HZ).powf(self.spectral_index)
+    }
+}
+
+/// Radio frequency interference, which is seen by every baseline with the
+/// same amplitude and a random phase in the XX and YY polarisations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rfi {
+    /// The affected timestep indices.
+    pub timesteps: Range<usize>,
+    /// The affected channel indices.
+    pub chans: Range<usize>,
+    /// The amplitude of the interference \[Jy\].
+    pub amplitude_jy: f32,
+}
+
+/// Simulates the visibilities of point sources with noise and RFI.
+///
+/// ```rust
+/// use marlu::{synth::*, RADec};
+///
+/// let obs_ctx = make_obs_ctx(16);
+/// let vis_ctx = make_vis_ctx(16, 2, 32);
+/// let (jones_array, weight_array) = VisSimulator::new(0)
+///     .point_source(PointSource::new(RADec::new_degrees(1.0, -26.0), 10.0))
+///     .noise(0.1)
+///     .rfi(Rfi {
+///         timesteps: 0..2,
+///         chans: 10..11,
+///         amplitude_jy: 1000.0,
+///     })
+///     .flag_rfi(true)
+///     .simulate(&vis_ctx, &obs_ctx);
+/// assert_eq!(jones_array.dim(), vis_ctx.sel_dims());
+/// assert!(weight_array[(0, 10, 0)] < 0.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct VisSimulator {
+    seed: u64,
+    sources: Vec<PointSource>,
+    noise_jy: f32,
+    rfi: Vec<Rfi>,
+    flag_rfi: bool,
+    dut1: Duration,
+}
+
+impl VisSimulator {
+    /// A simulator with no sources, noise or RFI. Different `seed`s produce
+    /// different noise and RFI phases.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            sources: vec![],
+            noise_jy: 0.0,
+            rfi: vec![],
+            flag_rfi: false,
+            dut1: Duration::from_total_nanoseconds(0),
+        }
+    }
+
+    /// Add a point source to the sky.
+    pub fn point_source(mut self, source: PointSource) -> Self {
+        self.sources.push(source);
+        self
+    }
+
+    /// Set the standard deviation of the Gaussian noise added to the real and
+    /// imaginary parts of every polarisation \[Jy\].
+    pub fn noise(mut self, sigma_jy: f32) -> Self {
+        self.noise_jy = sigma_jy;
+        self
+    }
+
+    /// Add RFI. Any part of `rfi` outside of the visibilities is ignored.
+    pub fn rfi(mut self, rfi: Rfi) -> Self {
+        self.rfi.push(rfi);
+        self
+    }
+
+    /// Set whether the visibilities affected by RFI are flagged, i.e. whether
+    /// the RFI has already been found by a flagger.
+    pub fn flag_rfi(mut self, flag_rfi: bool) -> Self {
+        self.flag_rfi = flag_rfi;
+        self
+    }
+
+    /// Set UT1 - UTC, used to get the UVWs of each timestep.
+    pub fn dut1(mut self, dut1: Duration) -> Self {
+        self.dut1 = dut1;
+        self
+    }
+
+    /// Simulate the visibilities and weights of `vis_ctx`, phase tracked to
+    /// the phase centre of `obs_ctx`, with dimensions
+    /// [`VisContext::sel_dims`].
+    ///
+    /// The UVWs are calculated like [`crate::corrections::fringe_stop`], so
+    /// fringe stopping toward a source makes its visibilities real. The
+    /// weights are 1, except for baselines with a flagged antenna (see
+    /// [`ObsContext::ant_flags`]) and RFI, if [`VisSimulator::flag_rfi`] is
+    /// set, which are flagged (-1).
+    pub fn simulate(
+        &self,
+        vis_ctx: &VisContext,
+        obs_ctx: &ObsContext,
+    ) -> (Array3<Jones<f32>>, Array3<f32>) {
+        let shape = vis_ctx.sel_dims();
+        let (num_timesteps, num_chans, num_baselines) = shape;
+        let mut jones_array = Array3::from_elem(shape, Jones::default());
+        let mut weight_array = Array3::from_elem(shape, 1.0_f32);
+
+        let frequencies_hz = vis_ctx.frequencies_hz();
+        let tile_xyzs: Vec<XyzGeodetic> = obs_ctx.ant_positions_geodetic().collect();
+        let sources: Vec<_> = self
+            .sources
+            .iter()
+            .map(|source| {
+                let fluxes: Vec<f64> = frequencies_hz
+                    .iter()
+                    .map(|&freq_hz| source.flux_density_at(freq_hz))
+                    .collect();
+                (
+                    source.radec.to_lmn(obs_ctx.phase_centre).prepare_for_rime(),
+                    fluxes,
+                )
+            })
+            .collect();
+
+        if !sources.is_empty() {
+            for (mut jones_2d, centroid_timestamp) in jones_array
+                .outer_iter_mut()
+                .zip(vis_ctx.timeseries(false, true))
+            {
+                let prec_info = precess_time(
+                    obs_ctx.array_pos.longitude_rad,
+                    obs_ctx.array_pos.latitude_rad,
+                    obs_ctx.phase_centre,
+                    centroid_timestamp,
+                    self.dut1,
+                );
+                let tiles_xyz_precessed = prec_info.precess_xyz_parallel(&tile_xyzs);
+                let uvws = baselines_to_uvws(
+                    &XyzGeodetic::to_selected_baselines(
+                        &tiles_xyz_precessed,
+                        &vis_ctx.sel_baselines,
+                    ),
+                    prec_info.hadec_j2000,
+                );
+
+                jones_2d
+                    .axis_iter_mut(Axis(1))
+                    .into_par_iter()
+                    .zip(uvws)
+                    .for_each(|(mut bl_jones, uvw)| {
+                        for (lmn, fluxes) in &sources {
+                            let phase_per_hz = lmn.dot(uvw) / VEL_C;
+                            for ((jones, &freq_hz), &flux) in
+                                bl_jones.iter_mut().zip(&frequencies_hz).zip(fluxes)
+                            {
+                                let vis = Complex::from_polar(flux, phase_per_hz * freq_hz);
+                                let vis = Complex::new(vis.re as f32, vis.im as f32);
+                                jones[0] += vis;
+                                jones[3] += vis;
+                            }
+                        }
+                    });
+            }
+        }
+
+        if self.noise_jy > 0.0 {
+            let seed = self.seed;
+            let sigma = self.noise_jy;
+            Zip::indexed(&mut jones_array).par_for_each(|(t, c, b), jones| {
+                let i = 8 * (((t * num_chans + c) * num_baselines + b) as u64);
+                let noise = |j: u64| sigma * gaussian(seed, i + j) as f32;
+                *jones += Jones::from([
+                    Complex::new(noise(0), noise(1)),
+                    Complex::new(noise(2), noise(3)),
+                    Complex::new(noise(4), noise(5)),
+                    Complex::new(noise(6), noise(7)),
+                ]);
+            });
+        }
+
+        for rfi in &self.rfi {
+            let timesteps =
+                rfi.timesteps.start.min(num_timesteps)..rfi.timesteps.end.min(num_timesteps);
+            let chans = rfi.chans.start.min(num_chans)..rfi.chans.end.min(num_chans);
+            let mut jones_rfi = jones_array.slice_mut(s![timesteps.clone(), chans.clone(), ..]);
+            for ((t, c, b), jones) in jones_rfi.indexed_iter_mut() {
+                let i = (((timesteps.start + t) * num_chans + chans.start + c) * num_baselines + b)
+                    as u64;
+                let phase = TAU * uniform(!self.seed, i);
+                let vis = Complex::from_polar(rfi.amplitude_jy, phase as f32);
+                jones[0] += vis;
+                jones[3] += vis;
+            }
+            if self.flag_rfi {
+                weight_array.slice_mut(s![timesteps, chans, ..]).fill(-1.0);
+            }
+        }
+
+        for (i_bl, &(ant1, ant2)) in vis_ctx.sel_baselines.iter().enumerate() {
+            let flagged = |ant: usize| obs_ctx.ant_flags.get(ant).copied().unwrap_or(false);
+            if flagged(ant1) || flagged(ant2) {
+                weight_array.slice_mut(s![.., .., i_bl]).fill(-1.0);
+            }
+        }
+
+        (jones_array, weight_array)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_make_vis_cube() {
+        let vis_ctx = make_vis_ctx(8, 3, 16);
+        assert_eq!(vis_ctx.sel_baselines.len(), 28);
+
+        let (jones_array, weight_array) = make_vis_cube(&vis_ctx, 0);
+        assert_eq!(jones_array.dim(), (3, 16, 28));
+        assert_eq!(weight_array.dim(), (3, 16, 28));
+        // The same seed produces the same data, a different one doesn't.
+        assert_eq!(make_vis_cube(&vis_ctx, 0).0, jones_array);
+        assert_ne!(make_vis_cube(&vis_ctx, 1).0, jones_array);
+
+        for jones in &jones_array {
+            assert!((jones[0].re - 1.0).abs() <= 0.5);
+            assert!(jones[1].norm() <= 1.0);
+        }
+        let num_flagged = weight_array.iter().filter(|w| w.is_sign_negative()).count();
+        assert!(num_flagged < weight_array.len() / 20);
+    }
+
+    #[test]
+    fn test_make_obs_ctx() {
+        let obs_ctx = make_obs_ctx(128);
+        assert_eq!(obs_ctx.ant_positions_enh.len(), 128);
+        assert_eq!(obs_ctx.ant_names[127], "Tile128");
+        for enh in &obs_ctx.ant_positions_enh {
+            assert!(enh.e.hypot(enh.n) <= SYNTH_ARRAY_RADIUS_M);
+        }
+        assert_eq!(make_xyzs(128), obs_ctx.telescope().ant_positions);
+    }
+
+    #[test]
+    fn test_simulate_point_source_at_phase_centre() {
+        let obs_ctx = make_obs_ctx(8);
+        let vis_ctx = make_vis_ctx(8, 2, 4);
+        let (jones_array, weight_array) = VisSimulator::new(0)
+            .point_source(PointSource::new(obs_ctx.phase_centre, 3.0))
+            .simulate(&vis_ctx, &obs_ctx);
+        for jones in &jones_array {
+            approx::assert_abs_diff_eq!(jones[0], Complex::new(3.0, 0.0), epsilon = 1e-5);
+            approx::assert_abs_diff_eq!(jones[1], Complex::new(0.0, 0.0));
+            approx::assert_abs_diff_eq!(jones[2], Complex::new(0.0, 0.0));
+            approx::assert_abs_diff_eq!(jones[3], Complex::new(3.0, 0.0), epsilon = 1e-5);
+        }
+        assert!(weight_array.iter().all(|&w| w > 0.0));
+    }
+
+    #[test]
+    fn test_simulate_fringe_stops_to_source() {
+        let mut obs_ctx = make_obs_ctx(8);
+        obs_ctx.ant_flags[3] = true;
+        let vis_ctx = make_vis_ctx(8, 3, 4);
+        let radec = RADec::new_degrees(2.0, -25.0);
+        let source = PointSource {
+            radec,
+            flux_density_jy: 5.0,
+            spectral_index: -0.8,
+        };
+        let (mut jones_array, weight_array) = VisSimulator::new(0)
+            .point_source(source)
+            .simulate(&vis_ctx, &obs_ctx);
+
+        // The source is away from the phase centre, so its visibilities
+        // aren't all real.
+        assert!(jones_array.iter().any(|jones| jones[0].im.abs() > 1.0));
+        crate::corrections::fringe_stop(
+            jones_array.view_mut(),
+            &vis_ctx,
+            &obs_ctx.telescope().ant_positions,
+            obs_ctx.array_pos,
+            obs_ctx.phase_centre,
+            radec,
+            Duration::from_total_nanoseconds(0),
+        )
+        .unwrap();
+        for (i_chan, freq_hz) in vis_ctx.frequencies_hz().into_iter().enumerate() {
+            let flux = source.flux_density_at(freq_hz) as f32;
+            for jones in jones_array.slice(s![.., i_chan, ..]) {
+                approx::assert_abs_diff_eq!(jones[0], Complex::new(flux, 0.0), epsilon = 1e-4);
+            }
+        }
+
+        // Baselines with antenna 3 are flagged.
+        for (i_bl, &(ant1, ant2)) in vis_ctx.sel_baselines.iter().enumerate() {
+            let flagged = ant1 == 3 || ant2 == 3;
+            for &weight in weight_array.slice(s![.., .., i_bl]) {
+                assert_eq!(weight.is_sign_negative(), flagged);
+            }
+        }
+    }
+
+    #[test]
+    fn test_simulate_noise_and_rfi() {
+        let obs_ctx = make_obs_ctx(16);
+        let vis_ctx = make_vis_ctx(16, 4, 16);
+        let simulator = VisSimulator::new(1).noise(2.0).rfi(Rfi {
+            timesteps: 1..3,
+            chans: 14..20,
+            amplitude_jy: 1000.0,
+        });
+        let (jones_array, weight_array) = simulator.simulate(&vis_ctx, &obs_ctx);
+        // Nothing is flagged unless asked.
+        assert!(weight_array.iter().all(|&w| w > 0.0));
+
+        let clean = jones_array.slice(s![.., ..14, ..]);
+        let num = (clean.len() * 8) as f64;
+        let (sum, sum_sq) = clean
+            .iter()
+            .flat_map(|jones| jones.iter().flat_map(|c| [c.re, c.im]))
+            .fold((0.0, 0.0), |(sum, sum_sq), x| {
+                (sum + f64::from(x), sum_sq + f64::from(x) * f64::from(x))
+            });
+        let mean = sum / num;
+        let std = (sum_sq / num - mean * mean).sqrt();
+        assert!(mean.abs() < 0.05, "{}", mean);
+        assert!((std - 2.0).abs() < 0.05, "{}", std);
+
+        for ((t, c, _), jones) in jones_array.indexed_iter() {
+            let rfi = (1..3).contains(&t) && c >= 14;
+            assert_eq!(jones[0].norm() > 900.0, rfi);
+            assert_eq!(jones[3].norm() > 900.0, rfi);
+            assert!(jones[1].norm() < 100.0);
+        }
+
+        let (_, weight_array) = simulator.flag_rfi(true).simulate(&vis_ctx, &obs_ctx);
+        for ((t, c, _), &weight) in weight_array.indexed_iter() {
+            let rfi = (1..3).contains(&t) && c >= 14;
+            assert_eq!(weight.is_sign_negative(), rfi);
+        }
+    }
+}
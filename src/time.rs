@@ -0,0 +1,116 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Time conversions.
+//!
+//! Times are [`Epoch`]s, which store TAI internally, so conversions between
+//! GPS, UTC, UT1 and TT account for leap seconds. These helpers centralise the
+//! time scales used by Marlu, and avoid losing precision when converting
+//! Julian dates to floats.
+
+use hifitime::{Duration, Epoch};
+
+const NANOSECONDS_PER_DAY: i128 = 86_400_000_000_000;
+
+/// Get an [`Epoch`] from a GPS time \[seconds\].
+pub fn gps_to_epoch(gps_seconds: f64) -> Epoch {
+    Epoch::from_gpst_seconds(gps_seconds)
+}
+
+/// Get the GPS time \[seconds\] of an [`Epoch`].
+pub fn epoch_to_gps(epoch: Epoch) -> f64 {
+    epoch.as_gpst_seconds()
+}
+
+/// The number of leap seconds (TAI - UTC) at `epoch`.
+pub fn leap_seconds(epoch: Epoch) -> i32 {
+    epoch.get_num_leap_seconds()
+}
+
+/// The UTC modified Julian date of `epoch` \[days\].
+pub fn mjd_utc_days(epoch: Epoch) -> f64 {
+    epoch.as_mjd_utc_days()
+}
+
+/// The UT1 modified Julian date of `epoch` \[days\], given `dut1` (i.e.
+/// UT1 - UTC).
+pub fn mjd_ut1_days(epoch: Epoch, dut1: Duration) -> f64 {
+    (epoch + dut1).as_mjd_utc_days()
+}
+
+/// The TT Julian date of `epoch` \[days\].
+pub fn jd_tt_days(epoch: Epoch) -> f64 {
+    epoch.as_jde_tt_days()
+}
+
+/// Split the UTC Julian date of `epoch` into a whole number of days and the
+/// fraction of a day in `[0, 1)`.
+///
+/// A single `f64` Julian date is only precise to ~40 microseconds; the sum of
+/// these parts is precise to the nanosecond.
+pub fn jd_utc_split(epoch: Epoch) -> (f64, f64) {
+    let ns = epoch.as_jde_utc_duration().total_nanoseconds();
+    let days = ns.div_euclid(NANOSECONDS_PER_DAY);
+    let frac = ns.rem_euclid(NANOSECONDS_PER_DAY) as f64 / NANOSECONDS_PER_DAY as f64;
+    (days as f64, frac)
+}
+
+/// The number of days between `jd_zero` (a UTC Julian date) and `epoch`,
+/// without the loss of precision of subtracting two `f64` Julian dates.
+/// `jd_zero` should have few significant figures, e.g. a whole number of days
+/// or half-days.
+pub fn jd_utc_days_since(epoch: Epoch, jd_zero: f64) -> f64 {
+    let (days, frac) = jd_utc_split(epoch);
+    (days - jd_zero) + frac
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use hifitime::Unit;
+
+    use super::*;
+
+    #[test]
+    fn test_gps_round_trip() {
+        let gps = 1065880128.25;
+        assert_abs_diff_eq!(epoch_to_gps(gps_to_epoch(gps)), gps);
+    }
+
+    #[test]
+    fn test_leap_seconds() {
+        // 2013-10-15
+        assert_eq!(leap_seconds(gps_to_epoch(1065880128.0)), 35);
+        // 2019-10-09
+        assert_eq!(leap_seconds(gps_to_epoch(1254670392.0)), 37);
+    }
+
+    #[test]
+    fn test_jd_utc_split() {
+        let epoch = Epoch::from_jde_utc(2456580.75);
+        let (days, frac) = jd_utc_split(epoch);
+        assert_abs_diff_eq!(days, 2456580.0);
+        assert_abs_diff_eq!(frac, 0.75, epsilon = 1e-12);
+
+        // The split keeps sub-millisecond offsets that a single f64 can't.
+        let later = epoch + Duration::from_f64(1e-6, Unit::Second);
+        let (days, frac) = jd_utc_split(later);
+        assert_abs_diff_eq!(days, 2456580.0);
+        assert_abs_diff_eq!((frac - 0.75) * 86400.0, 1e-6, epsilon = 1e-10);
+
+        assert_abs_diff_eq!(
+            jd_utc_days_since(later, 2456580.5) * 86400.0,
+            0.25 * 86400.0 + 1e-6,
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn test_tt() {
+        // TT = TAI + 32.184 s.
+        let epoch = gps_to_epoch(1065880128.0);
+        let tt_minus_utc = (jd_tt_days(epoch) - epoch.as_jde_utc_days()) * 86400.0;
+        assert_abs_diff_eq!(tt_minus_utc, 35.0 + 32.184, epsilon = 1e-3);
+    }
+}
@@ -0,0 +1,259 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Transforms of visibilities along the frequency axis, e.g. delay spectra.
+
+use std::f64::consts::TAU;
+
+use itertools::izip;
+use ndarray::prelude::*;
+use rayon::prelude::*;
+use rustfft::FftPlanner;
+use thiserror::Error;
+
+use crate::{weights::is_flagged, Complex, Jones};
+
+#[derive(Error, Debug)]
+pub enum TransformError {
+    #[error("bad array shape supplied to argument {argument} of function {function}. expected {expected}, received {received}")]
+    BadArrayShape {
+        argument: String,
+        function: String,
+        expected: String,
+        received: String,
+    },
+}
+
+/// A window function to taper the band with before a transform, reducing the
+/// leakage of power from low to high delays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    /// No tapering.
+    Rectangular,
+    /// The Hann window.
+    Hann,
+    /// The 4-term Blackman-Harris window, with sidelobes below -92 dB.
+    BlackmanHarris,
+}
+
+impl Window {
+    /// The (symmetric) coefficients of this window for `len` channels.
+    pub fn coefficients(self, len: usize) -> Vec<f64> {
+        if len <= 1 {
+            return vec![1.0; len];
+        }
+        let cosine_terms: &[f64] = match self {
+            Window::Rectangular => return vec![1.0; len],
+            Window::Hann => &[0.5, 0.5],
+            Window::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+        };
+        (0..len)
+            .map(|n| {
+                let phase = TAU * n as f64 / (len - 1) as f64;
+                cosine_terms
+                    .iter()
+                    .enumerate()
+                    .map(|(k, &a)| {
+                        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                        sign * a * (k as f64 * phase).cos()
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+/// The delay of each element of the spectra from [`delay_spectra`] \[seconds\],
+/// from the most negative to the most positive.
+pub fn delays_s(num_chans: usize, freq_resolution_hz: f64) -> Vec<f64> {
+    let bandwidth_hz = num_chans as f64 * freq_resolution_hz;
+    (0..num_chans)
+        .map(|i| (i as f64 - (num_chans / 2) as f64) / bandwidth_hz)
+        .collect()
+}
+
+/// Compute the delay spectrum of each timestep, baseline and pol by Fourier
+/// transforming the visibilities along the frequency axis.
+///
+/// `jones_array` - visibilities with dimensions `[timestep][channel][baseline]`
+///
+/// `weight_array` - weights with dimensions `[timestep][channel][baseline]`.
+///     Flagged (negative weight) channels are zeroed before the transform.
+///
+/// `freq_resolution_hz` - the width of each channel \[Hz\]
+///
+/// `window` - the window to taper the band with
+///
+/// Returns the spectra with dimensions `[timestep][delay][baseline]`, in the
+/// order given by [`delays_s`], i.e. with zero delay in the middle. The
+/// spectra are `Σ_ν V(ν) W(ν) exp(-2πiντ) Δν`, so their units are those of
+/// the visibilities multiplied by Hz. Baselines are transformed in parallel.
+///
+/// # Errors
+///
+/// Will return [`TransformError::BadArrayShape`] if the shapes of the arrays
+/// don't match.
+pub fn delay_spectra(
+    jones_array: ArrayView3<Jones<f32>>,
+    weight_array: ArrayView3<f32>,
+    freq_resolution_hz: f64,
+    window: Window,
+) -> Result<Array3<Jones<f32>>, TransformError> {
+    let jones_dims = jones_array.dim();
+    if weight_array.dim() != jones_dims {
+        return Err(TransformError::BadArrayShape {
+            argument: "weight_array".to_string(),
+            function: "delay_spectra".to_string(),
+            expected: format!("{:?}", jones_dims),
+            received: format!("{:?}", weight_array.dim()),
+        });
+    }
+    let num_chans = jones_dims.1;
+    let mut spectra = Array3::<Jones<f32>>::zeros(jones_dims);
+    if num_chans == 0 {
+        return Ok(spectra);
+    }
+
+    let fft = FftPlanner::<f64>::new().plan_fft_forward(num_chans);
+    let window = window.coefficients(num_chans);
+    let scale = freq_resolution_hz.abs();
+
+    spectra
+        .axis_iter_mut(Axis(2))
+        .into_par_iter()
+        .zip(jones_array.axis_iter(Axis(2)))
+        .zip(weight_array.axis_iter(Axis(2)))
+        .for_each(|((mut spectra_bl, jones_bl), weight_bl)| {
+            let mut buffer = vec![Complex::<f64>::default(); num_chans];
+            let mut scratch = vec![Complex::<f64>::default(); fft.get_inplace_scratch_len()];
+            // arrays: [channel]
+            for (mut spectrum, jones_ts, weight_ts) in izip!(
+                spectra_bl.outer_iter_mut(),
+                jones_bl.outer_iter(),
+                weight_bl.outer_iter(),
+            ) {
+                for pol in 0..4 {
+                    for (value, jones, &weight, &w) in
+                        izip!(buffer.iter_mut(), jones_ts, weight_ts, &window)
+                    {
+                        *value = if is_flagged(weight) {
+                            Complex::default()
+                        } else {
+                            let vis = jones[pol];
+                            Complex::new(vis.re as f64, vis.im as f64) * w
+                        };
+                    }
+                    fft.process_with_scratch(&mut buffer, &mut scratch);
+                    // Shift zero delay to the middle.
+                    for (i, jones) in spectrum.iter_mut().enumerate() {
+                        let value = buffer[(i + num_chans - num_chans / 2) % num_chans] * scale;
+                        jones[pol] = Complex::new(value.re as f32, value.im as f32);
+                    }
+                }
+            }
+        });
+
+    Ok(spectra)
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_window_coefficients() {
+        assert_eq!(Window::Rectangular.coefficients(3), vec![1.0; 3]);
+        let hann = Window::Hann.coefficients(5);
+        assert_abs_diff_eq!(
+            hann.as_slice(),
+            [0.0, 0.5, 1.0, 0.5, 0.0].as_slice(),
+            epsilon = 1e-12
+        );
+        let bh = Window::BlackmanHarris.coefficients(7);
+        assert_abs_diff_eq!(bh[0], 6e-5, epsilon = 1e-10);
+        assert_abs_diff_eq!(bh[3], 1.0, epsilon = 1e-12);
+        for (left, right) in bh.iter().zip(bh.iter().rev()) {
+            assert_abs_diff_eq!(left, right, epsilon = 1e-12);
+        }
+        assert_eq!(Window::BlackmanHarris.coefficients(1), vec![1.0]);
+    }
+
+    #[test]
+    fn test_delays_s() {
+        let delays = delays_s(4, 1e6);
+        assert_abs_diff_eq!(
+            delays.as_slice(),
+            [-5e-7, -2.5e-7, 0.0, 2.5e-7].as_slice(),
+            epsilon = 1e-15
+        );
+    }
+
+    #[test]
+    fn test_delay_spectra_tone() {
+        let num_chans = 16;
+        let freq_resolution_hz = 40e3;
+        let delays = delays_s(num_chans, freq_resolution_hz);
+        // A visibility of exp(2πiντ), with a delay τ of 3 bins, on the second
+        // baseline.
+        let delay_s = delays[num_chans / 2 + 3];
+        let jones_array = Array3::from_shape_fn((2, num_chans, 2), |(_, chan, bl)| {
+            let phase = TAU * chan as f64 * freq_resolution_hz * delay_s * bl as f64;
+            let vis = Complex::new(phase.cos() as f32, phase.sin() as f32);
+            Jones::from([vis, Complex::default(), Complex::default(), vis])
+        });
+        let weight_array = Array3::from_elem(jones_array.dim(), 1.0);
+
+        let spectra = delay_spectra(
+            jones_array.view(),
+            weight_array.view(),
+            freq_resolution_hz,
+            Window::Rectangular,
+        )
+        .unwrap();
+        assert_eq!(spectra.dim(), jones_array.dim());
+
+        let peak = num_chans as f32 * freq_resolution_hz as f32;
+        for (delay_idx, jones) in spectra.slice(s![1, .., 1]).iter().enumerate() {
+            let expected = if delay_idx == num_chans / 2 + 3 {
+                peak
+            } else {
+                0.0
+            };
+            assert_abs_diff_eq!(jones[0].norm(), expected, epsilon = peak * 1e-5);
+            assert_abs_diff_eq!(jones[3].norm(), expected, epsilon = peak * 1e-5);
+            assert_abs_diff_eq!(jones[1].norm(), 0.0);
+        }
+        // The first baseline has zero delay.
+        assert_abs_diff_eq!(
+            spectra[(0, num_chans / 2, 0)][0].norm(),
+            peak,
+            epsilon = peak * 1e-5
+        );
+    }
+
+    #[test]
+    fn test_delay_spectra_flags() {
+        let jones_array = Array3::from_elem((1, 8, 1), Jones::<f32>::identity());
+        let mut weight_array = Array3::from_elem(jones_array.dim(), 1.0);
+        weight_array[(0, 2, 0)] = -1.0;
+        let spectra = delay_spectra(
+            jones_array.view(),
+            weight_array.view(),
+            1.0,
+            Window::Rectangular,
+        )
+        .unwrap();
+        assert_abs_diff_eq!(spectra[(0, 4, 0)][0].re, 7.0, epsilon = 1e-5);
+
+        assert!(delay_spectra(
+            jones_array.view(),
+            weight_array.slice(s![.., 1.., ..]),
+            1.0,
+            Window::Hann
+        )
+        .is_err());
+    }
+}
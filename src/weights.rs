@@ -0,0 +1,129 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The convention for encoding flags in visibility weights.
+//!
+//! Readers and writers pass a single weight array around, where a flagged
+//! visibility is signalled by a weight with its sign bit set; the magnitude is
+//! always the weight of the visibility. Note that this means `-0.0` is a
+//! flagged zero weight, which is why flags must not be tested with
+//! `weight < 0.0`. [`WeightFlags`] converts between this representation and
+//! separate weights and flags.
+
+use ndarray::prelude::*;
+use thiserror::Error;
+
+use crate::errors::BadArrayShape;
+
+#[derive(Error, Debug)]
+pub enum WeightError {
+    #[error(transparent)]
+    BadArrayShape(#[from] BadArrayShape),
+}
+
+/// A visibility weight and its flag, decoded from a signed weight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeightFlags {
+    /// The (non-negative) weight.
+    pub weight: f32,
+
+    /// Whether the visibility is flagged.
+    pub flag: bool,
+}
+
+impl WeightFlags {
+    pub fn new(weight: f32, flag: bool) -> Self {
+        Self {
+            weight: weight.abs(),
+            flag,
+        }
+    }
+
+    /// Decode a signed weight; a weight is flagged if its sign bit is set.
+    pub fn from_signed(weight: f32) -> Self {
+        Self::new(weight, weight.is_sign_negative())
+    }
+
+    /// Encode the weight and flag as a signed weight.
+    pub fn to_signed(self) -> f32 {
+        if self.flag {
+            -self.weight
+        } else {
+            self.weight
+        }
+    }
+}
+
+impl From<f32> for WeightFlags {
+    fn from(weight: f32) -> Self {
+        Self::from_signed(weight)
+    }
+}
+
+impl From<WeightFlags> for f32 {
+    fn from(weight_flags: WeightFlags) -> Self {
+        weight_flags.to_signed()
+    }
+}
+
+/// Whether a signed weight is flagged.
+pub fn is_flagged(weight: f32) -> bool {
+    weight.is_sign_negative()
+}
+
+/// Encode `flags` into the signs of `weights`.
+///
+/// # Errors
+///
+/// Will return [`WeightError::BadArrayShape`] if `weights` and `flags` have
+/// different shapes.
+pub fn flags_to_negative_weights(
+    weights: ArrayView3<f32>,
+    flags: ArrayView3<bool>,
+) -> Result<Array3<f32>, WeightError> {
+    if weights.dim() != flags.dim() {
+        return Err(WeightError::BadArrayShape(BadArrayShape {
+            argument: "flags",
+            function: "flags_to_negative_weights",
+            expected: format!("{:?}", weights.dim()),
+            received: format!("{:?}", flags.dim()),
+        }));
+    }
+    let mut signed_weights = weights.to_owned();
+    signed_weights.zip_mut_with(&flags, |weight, &flag| {
+        *weight = WeightFlags::new(*weight, flag).to_signed();
+    });
+    Ok(signed_weights)
+}
+
+/// Decode signed `weights` into (non-negative) weights and flags.
+pub fn split_weight_sign(weights: ArrayView3<f32>) -> (Array3<f32>, Array3<bool>) {
+    (weights.mapv(f32::abs), weights.mapv(is_flagged))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weight_flags_round_trip() {
+        let weights = array![[[1.0, 0.0, 2.0], [0.0, 3.0, 4.0]]];
+        let flags = array![[[false, true, true], [false, false, true]]];
+        let signed = flags_to_negative_weights(weights.view(), flags.view()).unwrap();
+        assert_eq!(signed, array![[[1.0, -0.0, -2.0], [0.0, 3.0, -4.0]]]);
+
+        let (new_weights, new_flags) = split_weight_sign(signed.view());
+        assert_eq!(new_weights, weights);
+        assert_eq!(new_flags, flags);
+
+        // A flagged zero weight stays flagged.
+        assert!(WeightFlags::from_signed(-0.0).flag);
+        assert!(!WeightFlags::from_signed(0.0).flag);
+        assert_eq!(WeightFlags::from(-2.0), WeightFlags::new(2.0, true));
+        assert_eq!(f32::from(WeightFlags::new(-2.0, false)), 2.0);
+
+        let result = flags_to_negative_weights(weights.view(), flags.slice(s![.., .., ..2]));
+        assert!(matches!(result, Err(WeightError::BadArrayShape(_))));
+    }
+}